@@ -0,0 +1,348 @@
+//! Moving the points of each input by a rigid transform, to apply strip adjustments while merging.
+//!
+//! Strip adjustment software reports a small shift and rotation for each flight strip. Given as
+//! [`Adjustments`], by file, they are applied to the points of each input as it is read, so the
+//! adjusted deliverable comes out of a single pass. A [`RigidTransform`] rotates the points about
+//! a center, by default the middle of the bounds of the input, then shifts them. The conditions of
+//! the outputs see the points as they are in the inputs.
+//!
+//! The adjustments are read from a CSV file with a header naming its columns, of which only `file`
+//! is required, or from a JSON table with the same names grouped by what they are:
+//!
+//! ```text
+//! file,dx,dy,dz,rx,ry,rz,cx,cy,cz
+//! strip_01.laz,0.12,-0.05,0.031,0.001,-0.002,0.01,1500000,5200000,100
+//! ```
+//!
+//! ```json
+//! {"strip_01.laz": {"translation": [0.12, -0.05, 0.031], "rotation": [0.001, -0.002, 0.01]}}
+//! ```
+//!
+//! The shifts are in the units of the coordinates and the rotations in degrees, about X, Y and Z in
+//! that order.
+use crate::errors::MyError;
+use crate::source_tag::matches_input;
+use las::Point;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// The columns of the CSV adjustments, after `file`.
+const COLUMNS: [&str; 9] = ["dx", "dy", "dz", "rx", "ry", "rz", "cx", "cy", "cz"];
+
+/// A rotation about a center followed by a shift.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RigidTransform {
+    translation: [f64; 3],
+    rotation: [f64; 3],
+    center: Option<[f64; 3]>,
+    /// The rotation about X, then Y, then Z, as a matrix.
+    matrix: [[f64; 3]; 3],
+}
+
+impl Default for RigidTransform {
+    fn default() -> Self {
+        Self::translation([0.0; 3])
+    }
+}
+
+impl RigidTransform {
+    /// Shifts the points by `translation`, in X, Y and Z.
+    pub fn translation(translation: [f64; 3]) -> Self {
+        Self {
+            translation,
+            rotation: [0.0; 3],
+            center: None,
+            matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+
+    /// First rotates the points by `degrees` about X, Y and Z, in that order.
+    pub fn with_rotation(mut self, degrees: [f64; 3]) -> Self {
+        let [(sin_x, cos_x), (sin_y, cos_y), (sin_z, cos_z)] =
+            degrees.map(|angle| angle.to_radians().sin_cos());
+        self.rotation = degrees;
+        self.matrix = [
+            [
+                cos_y * cos_z,
+                sin_x * sin_y * cos_z - cos_x * sin_z,
+                cos_x * sin_y * cos_z + sin_x * sin_z,
+            ],
+            [
+                cos_y * sin_z,
+                sin_x * sin_y * sin_z + cos_x * cos_z,
+                cos_x * sin_y * sin_z - sin_x * cos_z,
+            ],
+            [-sin_y, sin_x * cos_y, cos_x * cos_y],
+        ];
+        self
+    }
+
+    /// Rotates the points about `center` rather than the middle of their input.
+    pub fn with_center(mut self, center: [f64; 3]) -> Self {
+        self.center = Some(center);
+        self
+    }
+
+    /// The shift, in X, Y and Z.
+    pub fn shift(&self) -> [f64; 3] {
+        self.translation
+    }
+
+    /// The rotation in degrees, about X, Y and Z.
+    pub fn rotation(&self) -> [f64; 3] {
+        self.rotation
+    }
+
+    /// The center of the rotation, if it isn't the middle of the input.
+    pub fn center(&self) -> Option<[f64; 3]> {
+        self.center
+    }
+
+    /// Moves `point`, rotating it about the center of the transform or else `default_center`.
+    pub fn apply(&self, point: &mut Point, default_center: [f64; 3]) {
+        let center = self.center.unwrap_or(default_center);
+        let relative = [
+            point.x - center[0],
+            point.y - center[1],
+            point.z - center[2],
+        ];
+        let [x, y, z] = [0, 1, 2].map(|axis| {
+            let row = self.matrix[axis];
+            row[0] * relative[0]
+                + row[1] * relative[1]
+                + row[2] * relative[2]
+                + center[axis]
+                + self.translation[axis]
+        });
+        point.x = x;
+        point.y = y;
+        point.z = z;
+    }
+}
+
+/// Rigid transforms applied to the points of inputs, by file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Adjustments(Vec<(String, RigidTransform)>);
+
+impl Adjustments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the points of the inputs at `path` by `transform`. The path matches inputs ending
+    /// with it, so a file name matches that file in any folder. Later adjustments win.
+    pub fn with(mut self, path: impl Into<String>, transform: RigidTransform) -> Self {
+        self.0.push((path.into(), transform));
+        self
+    }
+
+    /// Whether no input is adjusted.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The transform of `input`, if any.
+    pub fn of(&self, input: &str) -> Option<&RigidTransform> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(path, _)| matches_input(path, input))
+            .map(|(_, transform)| transform)
+    }
+
+    /// The paths of the adjustments matching none of `inputs`.
+    pub fn unmatched<'a>(&'a self, inputs: &[String]) -> Vec<&'a str> {
+        self.0
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .filter(|path| !inputs.iter().any(|input| matches_input(path, input)))
+            .collect()
+    }
+
+    /// The adjustments of a CSV table with a header row. Blank lines and lines starting with `#`
+    /// are skipped.
+    pub fn from_csv(text: &str) -> Result<Self, MyError> {
+        let mut rows = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        let header: Vec<&str> = match rows.next() {
+            Some((_, line)) => line.split(',').map(str::trim).collect(),
+            None => return Ok(Self::new()),
+        };
+        let file_column = header
+            .iter()
+            .position(|&name| name == "file")
+            .ok_or_else(|| invalid("the header has no `file` column"))?;
+        if let Some(name) = header
+            .iter()
+            .find(|&&name| name != "file" && !COLUMNS.contains(&name))
+        {
+            return Err(invalid(&format!("unknown column `{}`", name)));
+        }
+        let mut adjustments = Self::new();
+        for (number, line) in rows {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() != header.len() {
+                return Err(invalid(&format!(
+                    "line {} has {} fields, expected {}",
+                    number,
+                    fields.len(),
+                    header.len()
+                )));
+            }
+            let mut values = [None; 9];
+            for (name, field) in header.iter().zip(&fields) {
+                if let Some(column) = COLUMNS.iter().position(|column| column == name) {
+                    let value = field.parse::<f64>().ok().filter(|value| value.is_finite());
+                    values[column] = Some(value.ok_or_else(|| {
+                        invalid(&format!("`{}` on line {} isn't a number", field, number))
+                    })?);
+                }
+            }
+            let value = |column: usize| values[column].unwrap_or(0.0);
+            let mut transform = RigidTransform::translation([value(0), value(1), value(2)])
+                .with_rotation([value(3), value(4), value(5)]);
+            match values[6..] {
+                [Some(x), Some(y), Some(z)] => transform = transform.with_center([x, y, z]),
+                [None, None, None] => {}
+                _ => return Err(invalid("a center needs all of `cx`, `cy` and `cz`")),
+            }
+            adjustments = adjustments.with(fields[file_column], transform);
+        }
+        Ok(adjustments)
+    }
+
+    /// The adjustments of a JSON table from paths to their `translation`, `rotation` and
+    /// `center`, each a list of X, Y and Z and all optional.
+    pub fn from_value(value: &Value) -> Result<Self, MyError> {
+        let table = value
+            .as_object()
+            .ok_or_else(|| invalid("expected a table from files to their adjustment"))?;
+        let mut adjustments = Self::new();
+        for (path, adjustment) in table.iter() {
+            if !adjustment.is_object() {
+                return Err(invalid(&format!(
+                    "the adjustment of {} isn't a table",
+                    path
+                )));
+            }
+            let vector = |name: &str| match adjustment.get(name) {
+                Some(value) => parse_vector(value)
+                    .map(Some)
+                    .ok_or_else(|| invalid(&format!("`{}` of {} isn't [x, y, z]", name, path))),
+                None => Ok(None),
+            };
+            let mut transform =
+                RigidTransform::translation(vector("translation")?.unwrap_or([0.0; 3]))
+                    .with_rotation(vector("rotation")?.unwrap_or([0.0; 3]));
+            if let Some(center) = vector("center")? {
+                transform = transform.with_center(center);
+            }
+            adjustments = adjustments.with(path.as_str(), transform);
+        }
+        Ok(adjustments)
+    }
+
+    /// The adjustments of the file at `path`, JSON if it ends with `.json` and CSV otherwise.
+    pub fn from_path(path: &Path) -> Result<Self, MyError> {
+        let text = fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        if is_json {
+            let value: Value =
+                serde_json::from_str(&text).map_err(|err| invalid(&err.to_string()))?;
+            return Self::from_value(&value);
+        }
+        Self::from_csv(&text)
+    }
+}
+
+fn invalid(message: &str) -> MyError {
+    MyError::InvalidAdjustments(message.to_string())
+}
+
+fn parse_vector(value: &Value) -> Option<[f64; 3]> {
+    match value.as_array()?.as_slice() {
+        [x, y, z] => Some([x.as_f64()?, y.as_f64()?, z.as_f64()?]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(point: &Point, expected: [f64; 3]) {
+        for (value, expected) in [point.x, point.y, point.z].into_iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+        }
+    }
+
+    #[test]
+    fn test_adjustments() {
+        let mut point = Point {
+            x: 11.0,
+            y: 20.0,
+            z: 5.0,
+            ..Default::default()
+        };
+        // A quarter turn about Z around (10, 20, 0), then a shift
+        RigidTransform::translation([1.0, 2.0, 3.0])
+            .with_rotation([0.0, 0.0, 90.0])
+            .apply(&mut point, [10.0, 20.0, 0.0]);
+        assert_near(&point, [11.0, 23.0, 8.0]);
+        let mut point = Point {
+            y: 1.0,
+            ..Default::default()
+        };
+        RigidTransform::default()
+            .with_rotation([90.0, 0.0, 0.0])
+            .with_center([0.0; 3])
+            .apply(&mut point, [5.0; 3]);
+        assert_near(&point, [0.0, 0.0, 1.0]);
+
+        let adjustments = Adjustments::from_csv(
+            "# from the strip adjustment\nfile, dz, rz\nstrip_1.las, 0.5, 0\n\ndir/strip_2.las, -1, 0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            adjustments
+                .of("/data/strip_1.las")
+                .map(RigidTransform::shift),
+            Some([0.0, 0.0, 0.5])
+        );
+        assert_eq!(adjustments.of("other/strip_2.las"), None);
+        assert_eq!(
+            adjustments.unmatched(&["/data/strip_1.las".to_string()]),
+            ["dir/strip_2.las"]
+        );
+        for table in [
+            "dx\n1",
+            "file,dw\na.las,1",
+            "file,dx\na.las",
+            "file,dx\na.las,north",
+            "file,cx,cy\na.las,1,2",
+        ] {
+            assert!(Adjustments::from_csv(table).is_err(), "{}", table);
+        }
+    }
+
+    #[test]
+    fn test_adjustments_from_value() {
+        let value = serde_json::from_str(
+            r#"{"a.las": {"translation": [1, 2, 3], "center": [0, 0, 0]}, "b.las": {}}"#,
+        )
+        .unwrap();
+        let adjustments = Adjustments::from_value(&value).unwrap();
+        let a = adjustments.of("a.las").unwrap();
+        assert_eq!(a.shift(), [1.0, 2.0, 3.0]);
+        assert_eq!(a.center(), Some([0.0; 3]));
+        assert_eq!(adjustments.of("b.las"), Some(&RigidTransform::default()));
+        let value = serde_json::from_str(r#"{"a.las": {"rotation": [1, 2]}}"#).unwrap();
+        assert!(Adjustments::from_value(&value).is_err());
+    }
+}
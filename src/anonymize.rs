@@ -0,0 +1,188 @@
+//! Reducing datasets for public release, when the full data is sensitive.
+//!
+//! An [`Anonymization`] truncates the coordinates to a coarser precision, e.g. 1 m, so that the
+//! points no longer show the details of buildings, removes the GPS times, which tell when an area
+//! was flown, and strips the header of whatever identifies who made the file and how: the system
+//! identifier, generating software, project GUID, creation date, file source ID and every VLR
+//! except the coordinate system and the extra bytes descriptions.
+use crate::errors::MyError;
+use crate::extra_bytes::is_extra_bytes_vlr;
+use crate::output_options::is_crs_vlr;
+use las::point::Format;
+use las::{Builder, Header, Point, Vector};
+
+/// What an anonymization takes out of the outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Anonymization {
+    precision: Option<f64>,
+    drop_gps_time: bool,
+    strip_metadata: bool,
+}
+
+impl Anonymization {
+    /// An anonymization leaving the outputs alone.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Truncates the coordinates down to multiples of `precision`, in the units of the
+    /// coordinates. The scale of the outputs is raised to the precision if it is finer.
+    pub fn with_precision(mut self, precision: f64) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Removes the GPS times. The point formats that can do without them are replaced by the ones
+    /// without, and the others get times of 0.
+    pub fn with_drop_gps_time(mut self, drop: bool) -> Self {
+        self.drop_gps_time = drop;
+        self
+    }
+
+    /// Strips the identifying metadata from the header.
+    pub fn with_strip_metadata(mut self, strip: bool) -> Self {
+        self.strip_metadata = strip;
+        self
+    }
+
+    /// The precision the coordinates are truncated to, if they are.
+    pub fn precision(&self) -> Option<f64> {
+        self.precision
+    }
+
+    /// Whether the GPS times are removed.
+    pub fn drops_gps_time(&self) -> bool {
+        self.drop_gps_time
+    }
+
+    /// Whether the identifying metadata is stripped.
+    pub fn strips_metadata(&self) -> bool {
+        self.strip_metadata
+    }
+
+    /// The point format the points of `format` are written in.
+    pub fn format(&self, mut format: Format) -> Format {
+        // The formats of LAS 1.4 all have GPS times
+        if self.drop_gps_time && !format.is_extended {
+            format.has_gps_time = false;
+        }
+        format
+    }
+
+    /// The header of the outputs, made from the header the points are read with.
+    pub fn header(&self, header: Header) -> Result<Header, MyError> {
+        let mut builder = Builder::from(header);
+        builder.point_format = self.format(builder.point_format);
+        if let Some(precision) = self.precision {
+            let Vector { x, y, z } = builder.transforms;
+            builder.transforms = Vector {
+                x: coarsened(x, precision),
+                y: coarsened(y, precision),
+                z: coarsened(z, precision),
+            };
+        }
+        if self.strip_metadata {
+            builder.system_identifier = String::new();
+            builder.generating_software = String::new();
+            builder.guid = Default::default();
+            builder.date = None;
+            builder.file_source_id = 0;
+            builder
+                .vlrs
+                .retain(|vlr| is_crs_vlr(vlr) || is_extra_bytes_vlr(vlr));
+            builder.evlrs.retain(is_crs_vlr);
+            builder.padding.clear();
+            builder.vlr_padding.clear();
+        }
+        Ok(builder.into_header()?)
+    }
+
+    /// Truncates the coordinates of `point` and zeroes its GPS time, as asked. The GPS time is
+    /// dropped from the formats without it as the point is written.
+    pub fn apply(&self, point: &mut Point) {
+        if let Some(precision) = self.precision {
+            point.x = truncated(point.x, precision);
+            point.y = truncated(point.y, precision);
+            point.z = truncated(point.z, precision);
+        }
+        if self.drop_gps_time {
+            if let Some(time) = &mut point.gps_time {
+                *time = 0.0;
+            }
+        }
+    }
+}
+
+/// `value` truncated down to a multiple of `precision`.
+fn truncated(value: f64, precision: f64) -> f64 {
+    (value / precision).floor() * precision
+}
+
+/// `transform` with its scale raised to `precision` if it is finer, and its offset moved to a
+/// multiple of it so that the truncated coordinates stay exact.
+fn coarsened(transform: las::Transform, precision: f64) -> las::Transform {
+    if transform.scale >= precision {
+        return transform;
+    }
+    las::Transform {
+        scale: precision,
+        offset: truncated(transform.offset, precision),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::Vlr;
+
+    #[test]
+    fn test_anonymization() {
+        let mut builder = Builder::from((1, 2));
+        builder.point_format = Format::new(3).unwrap();
+        builder.system_identifier = "Survey rig 7".to_string();
+        builder.generating_software = "Vendor suite".to_string();
+        builder.file_source_id = 12;
+        builder.transforms.x.offset = 1500.25;
+        builder.vlrs.push(Vlr {
+            user_id: "LASF_Projection".to_string(),
+            record_id: 34735,
+            description: String::new(),
+            data: vec![0; 8],
+        });
+        builder.vlrs.push(Vlr {
+            user_id: "Vendor".to_string(),
+            record_id: 1,
+            description: "Flight log".to_string(),
+            data: vec![1; 8],
+        });
+        let anonymization = Anonymization::new()
+            .with_precision(1.0)
+            .with_drop_gps_time(true)
+            .with_strip_metadata(true);
+        let header = anonymization
+            .header(builder.into_header().unwrap())
+            .unwrap();
+        assert_eq!(header.point_format().to_u8().unwrap(), 2);
+        assert_eq!(header.system_identifier(), "");
+        assert_eq!(header.generating_software(), "");
+        assert_eq!(header.file_source_id(), 0);
+        assert_eq!(header.vlrs().len(), 1);
+        assert_eq!(header.transforms().x.scale, 1.0);
+        assert_eq!(header.transforms().x.offset, 1500.0);
+        assert_eq!(
+            anonymization.format(Format::new(6).unwrap()),
+            Format::new(6).unwrap()
+        );
+
+        let mut point = Point {
+            x: 10.7,
+            y: -0.2,
+            z: 3.0,
+            gps_time: Some(1234.5),
+            ..Default::default()
+        };
+        anonymization.apply(&mut point);
+        assert_eq!((point.x, point.y, point.z), (10.0, -1.0, 3.0));
+        assert_eq!(point.gps_time, Some(0.0));
+    }
+}
@@ -0,0 +1,385 @@
+//! Adding points to the end of an existing LAS/LAZ file, in place.
+//!
+//! An [`AppendWriter`] writes the new points after the ones already in the file rather than
+//! copying them, so appending takes as long as the new points do. In a LAS file the records go
+//! after the last one. In a LAZ file the new points are compressed into chunks of their own, which
+//! go after the last chunk, and the chunk table is written again after them. EVLRs are moved to
+//! after the new points. Only the point counts, bounds and offsets of the header are patched, so
+//! the rest of it stays as the file had it.
+//!
+//! The file is changed in place and only holds together again once the writer is finished, so
+//! outputs append to a staged copy of the file, see
+//! [`OutputWriter::append`](crate::output::OutputWriter::append).
+use crate::compression::{LazChunking, DEFAULT_CHUNK_SIZE};
+use crate::errors::MyError;
+use crate::validate::legacy_counts;
+use las::point::Format;
+use las::{Header, Point, Reader, Vlr};
+use laz::laszip::{ChunkTable, ChunkTableEntry};
+use laz::{LasZipCompressor, LazVlr};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+
+/// Where the chunk size is in the data of a laszip VLR, after the compressor, coder, version and
+/// options.
+const CHUNK_SIZE_OFFSET: usize = 12;
+
+/// The chunk size of a laszip VLR for chunks of any number of points.
+const VARIABLE_CHUNK_SIZE: u32 = u32::MAX;
+
+/// Writes points after the points of an existing file.
+pub struct AppendWriter {
+    path: String,
+    file: BufWriter<File>,
+    /// The header of the file, counting the points appended so far.
+    header: Header,
+    /// The header as it was read, to be patched.
+    raw: las::raw::Header,
+    /// The format of the point records, uncompressed.
+    format: Format,
+    /// The EVLRs of the file as they were read, to be written after the new points.
+    evlrs: Vec<u8>,
+    record: Vec<u8>,
+    laz: Option<LazAppend>,
+}
+
+/// The chunks of a LAZ file being appended to.
+struct LazAppend {
+    vlr: LazVlr,
+    table: ChunkTable,
+    /// The number of points of the new chunks.
+    chunk_size: u64,
+    /// Whether chunks end with each batch.
+    variable: bool,
+    /// Where the data of the laszip VLR is, when it has to be switched to variable-size chunks.
+    vlr_position: Option<u64>,
+    /// The records of the chunk being filled.
+    records: Vec<u8>,
+    points: u64,
+}
+
+impl AppendWriter {
+    /// Opens the file at `path` to add points to it. New LAZ chunks are `chunking` points long if
+    /// the file has variable-size chunks, and as long as the existing ones otherwise.
+    ///
+    /// Fails with `MyError::IncompatibleAppend` if the chunks of a LAZ file can't be found.
+    pub fn open(path: &str, chunking: LazChunking) -> Result<Self, MyError> {
+        let header = Reader::from_path(path)?.header().clone();
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let raw = las::raw::Header::read_from(&mut file)?;
+        let mut format = *header.point_format();
+        format.is_compressed = false;
+
+        let evlrs = match &raw.evlr {
+            Some(evlr) if evlr.number_of_evlrs > 0 => {
+                let mut evlrs = Vec::new();
+                file.seek(SeekFrom::Start(evlr.start_of_first_evlr))?;
+                file.read_to_end(&mut evlrs)?;
+                evlrs
+            }
+            _ => Vec::new(),
+        };
+
+        let start = u64::from(raw.offset_to_point_data);
+        let laz = if header.point_format().is_compressed {
+            let (laz, end) =
+                LazAppend::open(&mut file, &header, &raw, chunking).ok_or_else(|| {
+                    MyError::IncompatibleAppend(
+                        path.to_string(),
+                        "its LAZ chunk table can't be read".to_string(),
+                    )
+                })??;
+            file.seek(SeekFrom::Start(end))?;
+            Some(laz)
+        } else {
+            let records = header.number_of_points() * u64::from(format.len());
+            file.seek(SeekFrom::Start(start + records))?;
+            None
+        };
+        Ok(Self {
+            path: path.to_string(),
+            file: BufWriter::new(file),
+            header,
+            raw,
+            format,
+            evlrs,
+            record: Vec::new(),
+            laz,
+        })
+    }
+
+    /// The header of the file, with the point counts and bounds of the points appended so far.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Writes a single point after the others.
+    pub fn write_point(&mut self, point: Point) -> Result<(), MyError> {
+        self.header.add_point(&point);
+        self.record.clear();
+        point
+            .into_raw(self.header.transforms())?
+            .write_to(&mut self.record, &self.format)?;
+        match &mut self.laz {
+            None => self.file.write_all(&self.record)?,
+            Some(laz) => {
+                laz.records.extend_from_slice(&self.record);
+                laz.points += 1;
+                if laz.points >= laz.chunk_size {
+                    laz.write_chunk(&mut self.file)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends the current LAZ chunk if the file has variable-size chunks.
+    pub fn end_chunk(&mut self) -> Result<(), MyError> {
+        match &mut self.laz {
+            Some(laz) if laz.variable => laz.write_chunk(&mut self.file),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the chunk table and the EVLRs, patches the header and returns the file.
+    pub fn finish(mut self) -> Result<File, MyError> {
+        if let Some(laz) = &mut self.laz {
+            laz.write_chunk(&mut self.file)?;
+            let table_position = self.file.stream_position()?;
+            laz.table.write_to(&mut self.file, &laz.vlr)?;
+            let table_end = self.file.stream_position()?;
+            self.file
+                .seek(SeekFrom::Start(u64::from(self.raw.offset_to_point_data)))?;
+            self.file.write_all(&table_position.to_le_bytes())?;
+            if let Some(vlr_position) = laz.vlr_position {
+                self.file
+                    .seek(SeekFrom::Start(vlr_position + CHUNK_SIZE_OFFSET as u64))?;
+                self.file.write_all(&VARIABLE_CHUNK_SIZE.to_le_bytes())?;
+            }
+            self.file.seek(SeekFrom::Start(table_end))?;
+        }
+        let start_of_first_evlr = self.file.stream_position()?;
+        self.file.write_all(&self.evlrs)?;
+        let end = self.file.stream_position()?;
+        self.file.flush()?;
+        let mut file = self.file.into_inner().map_err(|err| err.into_error())?;
+        file.set_len(end)?;
+
+        let mut raw = self.raw;
+        let count = self.header.number_of_points();
+        let by_return: [u64; 15] = std::array::from_fn(|i| {
+            self.header
+                .number_of_points_by_return(i as u8 + 1)
+                .unwrap_or(0)
+        });
+        if let Some(large_file) = &mut raw.large_file {
+            // The legacy counts are only kept up if the file had them
+            if raw.number_of_point_records > 0 {
+                (raw.number_of_point_records, raw.number_of_points_by_return) =
+                    legacy_counts(raw.point_data_record_format & 0x3f, count, &by_return);
+            }
+            large_file.number_of_point_records = count;
+            large_file.number_of_points_by_return = by_return;
+        } else {
+            raw.number_of_point_records = u32::try_from(count)
+                .map_err(|_| MyError::TooManyPoints(self.path.clone(), u64::from(u32::MAX)))?;
+            raw.number_of_points_by_return =
+                std::array::from_fn(|i| u32::try_from(by_return[i]).unwrap_or(u32::MAX));
+        }
+        let bounds = self.header.bounds();
+        (raw.min_x, raw.min_y, raw.min_z) = (bounds.min.x, bounds.min.y, bounds.min.z);
+        (raw.max_x, raw.max_y, raw.max_z) = (bounds.max.x, bounds.max.y, bounds.max.z);
+        if let Some(evlr) = &mut raw.evlr {
+            if evlr.number_of_evlrs > 0 {
+                evlr.start_of_first_evlr = start_of_first_evlr;
+            }
+        }
+        file.seek(SeekFrom::Start(0))?;
+        raw.write_to(&mut file)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(file)
+    }
+}
+
+impl LazAppend {
+    /// Reads the chunk table of the LAZ `file` and returns the chunks, with where the last one
+    /// ends, or `None` if the file has no laszip VLR or chunk table. A file whose fixed-size
+    /// chunks end with a short one is switched to variable-size chunks, as the new chunks can't
+    /// follow it otherwise.
+    fn open(
+        file: &mut File,
+        header: &Header,
+        raw: &las::raw::Header,
+        chunking: LazChunking,
+    ) -> Option<Result<(Self, u64), MyError>> {
+        let laszip_vlr = header
+            .vlrs()
+            .iter()
+            .find(|vlr| las::laz::is_laszip_vlr(vlr))?;
+        let start = u64::from(raw.offset_to_point_data);
+        let mut offset = [0; 8];
+        let read_offset = file
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| file.read_exact(&mut offset));
+        if let Err(err) = read_offset {
+            return Some(Err(err.into()));
+        }
+        // Writers that couldn't seek leave the offset at -1 with the chunk table at the end
+        let end = u64::try_from(i64::from_le_bytes(offset)).ok()?;
+        Some(Self::read(file, header, raw, laszip_vlr, chunking).map(|laz| (laz, end)))
+    }
+
+    /// Reads the chunk table of the LAZ `file` compressed as `laszip_vlr` says.
+    fn read(
+        file: &mut File,
+        header: &Header,
+        raw: &las::raw::Header,
+        laszip_vlr: &Vlr,
+        chunking: LazChunking,
+    ) -> Result<Self, MyError> {
+        let mut vlr = LazVlr::from_buffer(&laszip_vlr.data)?;
+        file.seek(SeekFrom::Start(u64::from(raw.offset_to_point_data)))?;
+        let mut table = ChunkTable::read_from(&mut *file, &vlr)?;
+
+        let points = header.number_of_points();
+        let mut vlr_position = None;
+        let (chunk_size, variable) = if vlr.uses_variable_size_chunks() {
+            let chunk_size = match chunking {
+                LazChunking::Fixed(chunk_size) | LazChunking::Parallel(chunk_size) => chunk_size,
+                LazChunking::Variable => DEFAULT_CHUNK_SIZE,
+            };
+            (u64::from(chunk_size), chunking == LazChunking::Variable)
+        } else {
+            let chunk_size = u64::from(vlr.chunk_size());
+            if points % chunk_size != 0 {
+                // Every chunk but the last is full
+                let mut counts = ChunkTable::with_capacity(table.len());
+                let mut remaining = points;
+                for entry in table.iter() {
+                    let point_count = remaining.min(chunk_size);
+                    remaining -= point_count;
+                    counts.push(ChunkTableEntry {
+                        point_count,
+                        byte_count: entry.byte_count,
+                    });
+                }
+                table = counts;
+                let mut data = laszip_vlr.data.clone();
+                data[CHUNK_SIZE_OFFSET..CHUNK_SIZE_OFFSET + 4]
+                    .copy_from_slice(&VARIABLE_CHUNK_SIZE.to_le_bytes());
+                vlr = LazVlr::from_buffer(&data)?;
+                vlr_position = Some(laszip_vlr_position(file, raw)?);
+            }
+            (chunk_size, false)
+        };
+        Ok(Self {
+            vlr,
+            table,
+            chunk_size: chunk_size.max(1),
+            variable,
+            vlr_position,
+            records: Vec::new(),
+            points: 0,
+        })
+    }
+
+    /// Compresses the records of the chunk being filled into a chunk of their own and writes it.
+    fn write_chunk(&mut self, file: &mut BufWriter<File>) -> Result<(), MyError> {
+        if self.points == 0 {
+            return Ok(());
+        }
+        let mut compressor = LasZipCompressor::new(Cursor::new(Vec::new()), self.vlr.clone())?;
+        compressor.compress_many(&self.records)?;
+        compressor.done()?;
+        let data = compressor.into_inner().into_inner();
+        // The offset to the chunk table, the chunk, then a chunk table for it alone
+        let table_offset = i64::from_le_bytes(data[..8].try_into().unwrap_or_default()) as usize;
+        let chunk = &data[8..table_offset];
+        file.write_all(chunk)?;
+        self.table.push(ChunkTableEntry {
+            point_count: self.points,
+            byte_count: chunk.len() as u64,
+        });
+        self.records.clear();
+        self.points = 0;
+        Ok(())
+    }
+}
+
+/// Where the data of the laszip VLR is in `file`.
+fn laszip_vlr_position(file: &mut File, raw: &las::raw::Header) -> Result<u64, MyError> {
+    file.seek(SeekFrom::Start(u64::from(raw.header_size)))?;
+    for _ in 0..raw.number_of_variable_length_records {
+        let vlr = las::raw::Vlr::read_from(&mut *file, false)?;
+        let data_len = vlr.data.len() as u64;
+        if las::laz::is_laszip_vlr(&Vlr::new(vlr)) {
+            return Ok(file.stream_position()? - data_len);
+        }
+    }
+    Err(std::io::Error::new(std::io::ErrorKind::NotFound, "the laszip VLR is missing").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::LazWriter;
+    use las::Builder;
+    use std::path::Path;
+
+    fn write_file(path: &Path, chunking: LazChunking, xs: std::ops::Range<u32>) {
+        let mut builder = Builder::from((1, 4));
+        builder.evlrs.push(Vlr {
+            user_id: "notes".to_string(),
+            record_id: 1,
+            description: String::new(),
+            data: vec![7; 8],
+        });
+        let header = builder.into_header().unwrap();
+        let file = BufWriter::new(File::create(path).unwrap());
+        let mut writer = LazWriter::new(file, header, chunking).unwrap();
+        for x in xs {
+            writer
+                .write_point(Point {
+                    x: f64::from(x),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        writer.into_inner().unwrap().flush().unwrap();
+    }
+
+    #[test]
+    fn test_append_laz_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, chunking) in [
+            ("full.laz", LazChunking::Fixed(5)),
+            ("short.laz", LazChunking::Fixed(4)),
+            ("variable.laz", LazChunking::Variable),
+        ] {
+            let path = dir.path().join(name);
+            write_file(&path, chunking, 0..10);
+            let mut writer =
+                AppendWriter::open(path.to_str().unwrap(), LazChunking::Fixed(3)).unwrap();
+            for x in 10..17 {
+                writer
+                    .write_point(Point {
+                        x: f64::from(x),
+                        ..Default::default()
+                    })
+                    .unwrap();
+            }
+            writer.finish().unwrap();
+
+            let mut reader = Reader::from_path(&path).unwrap();
+            assert_eq!(reader.header().number_of_points(), 17, "{}", name);
+            assert_eq!(reader.header().bounds().max.x, 16.0);
+            assert_eq!(reader.header().evlrs()[0].data, [7; 8]);
+            // Seeking goes through the chunk table
+            reader.seek(12).unwrap();
+            assert_eq!(reader.read_point().unwrap().unwrap().x, 12.0);
+            reader.seek(0).unwrap();
+            let xs: Vec<f64> = reader.points().map(|point| point.unwrap().x).collect();
+            assert_eq!(xs, (0..17).map(f64::from).collect::<Vec<_>>(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Reading LAS/LAZ files straight out of ZIP archives.
+//!
+//! Entries are addressed as `archive.zip!/path/in/archive.laz`. Each entry is decompressed into
+//! memory when it is opened, so nothing is extracted to disk. This needs the `zip` feature.
+use crate::errors::MyError;
+use las::Reader;
+
+/// Separates the archive path from the entry name in an archive entry path.
+pub const ENTRY_SEPARATOR: &str = "!/";
+
+/// Returns `true` if `path` looks like a ZIP archive.
+pub fn is_zip(path: &str) -> bool {
+    path.to_lowercase().ends_with(".zip")
+}
+
+/// Splits an archive entry path into the archive path and the entry name.
+pub fn split_entry(path: &str) -> Option<(&str, &str)> {
+    path.split_once(ENTRY_SEPARATOR)
+        .filter(|(archive, _)| is_zip(archive))
+}
+
+/// Lists the LAS/LAZ files inside the archive at `path` as archive entry paths.
+#[cfg(feature = "zip")]
+pub fn list_entries(path: &str) -> Result<Vec<String>, MyError> {
+    let archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let mut entries: Vec<String> = archive
+        .file_names()
+        .filter(|name| {
+            let name = name.to_lowercase();
+            name.ends_with(".las") || name.ends_with(".laz")
+        })
+        .map(|name| format!("{}{}{}", path, ENTRY_SEPARATOR, name))
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+/// Lists the LAS/LAZ files inside the archive at `path` as archive entry paths.
+#[cfg(not(feature = "zip"))]
+pub fn list_entries(path: &str) -> Result<Vec<String>, MyError> {
+    Err(MyError::FeatureNotEnabled(path.to_string(), "zip"))
+}
+
+/// Opens a `las::Reader` over an archive entry path.
+#[cfg(feature = "zip")]
+pub fn open_reader(path: &str) -> Result<Reader, MyError> {
+    use std::io::{Cursor, Read};
+
+    let (archive_path, name) = split_entry(path).ok_or(MyError::InvalidInputPath)?;
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(archive_path)?)?;
+    let mut entry = archive.by_name(name)?;
+    let mut data = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut data)?;
+    Ok(Reader::new(Cursor::new(data))?)
+}
+
+/// Opens a `las::Reader` over an archive entry path.
+#[cfg(not(feature = "zip"))]
+pub fn open_reader(path: &str) -> Result<Reader, MyError> {
+    Err(MyError::FeatureNotEnabled(path.to_string(), "zip"))
+}
+
+#[cfg(all(test, feature = "zip"))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_zip_entries_are_listed_and_read() {
+        let dir = tempdir().unwrap();
+        let archive_path = dir.path().join("tiles.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive_path).unwrap());
+        writer
+            .start_file("tiles/input1.las", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .write_all(&std::fs::read("tests/data/input1.las").unwrap())
+            .unwrap();
+        writer
+            .start_file("readme.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"not a point cloud").unwrap();
+        writer.finish().unwrap();
+
+        let entries = list_entries(archive_path.to_str().unwrap()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].ends_with("tiles.zip!/tiles/input1.las"));
+
+        let mut reader = open_reader(&entries[0]).unwrap();
+        let expected = Reader::from_path("tests/data/input1.las")
+            .unwrap()
+            .header()
+            .number_of_points();
+        assert_eq!(reader.points().count() as u64, expected);
+    }
+}
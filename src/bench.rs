@@ -0,0 +1,129 @@
+//! Measuring how fast an input is read with different tuning settings, to pick them on new
+//! hardware.
+//!
+//! Every combination of reader threads, batch size and channel depth reads the whole input and
+//! hands the points to a callback that drops them, so the figures cover reading, decoding and
+//! batching but not writing.
+use crate::errors::MyError;
+use crate::progress::NoProgress;
+use crate::LasProcessor;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The settings of one run and how fast it went.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BenchResult {
+    pub threads: usize,
+    pub batch_size: u64,
+    pub channel_depth: usize,
+    /// The number of points read.
+    pub points: u64,
+    pub duration: Duration,
+}
+
+impl BenchResult {
+    /// The number of points read per second.
+    pub fn points_per_second(&self) -> f64 {
+        self.points as f64 / self.duration.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>7} {:>10} {:>13} {:>14.0}",
+            self.threads,
+            self.batch_size,
+            self.channel_depth,
+            self.points_per_second()
+        )
+    }
+}
+
+/// The header of the table of results, lined up with the `Display` of [`BenchResult`].
+pub const RESULTS_HEADER: &str = "threads batch size channel depth       points/s";
+
+/// Reads the input at `path` once for every combination of `threads`, `batch_sizes` and
+/// `channel_depths`, and returns the results in that order.
+pub fn bench(
+    path: &str,
+    threads: &[usize],
+    batch_sizes: &[u64],
+    channel_depths: &[usize],
+) -> Result<Vec<BenchResult>, MyError> {
+    let mut results = Vec::new();
+    for &thread_count in threads {
+        for &batch_size in batch_sizes {
+            for &channel_depth in channel_depths {
+                let report = LasProcessor::new(
+                    vec![path.to_string()],
+                    vec!["bench".to_string()],
+                    vec![Arc::new(|_point| true)],
+                    false,
+                )
+                .with_reader_threads(thread_count)
+                .with_batch_size(batch_size)
+                .with_channel_depth(channel_depth)
+                .with_observer(Arc::new(NoProgress))
+                .process_with(|_, _| {})?;
+                results.push(BenchResult {
+                    threads: thread_count,
+                    batch_size,
+                    channel_depth,
+                    points: report.points_read(),
+                    duration: report.duration,
+                });
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// The fastest of `results`.
+pub fn fastest(results: &[BenchResult]) -> Option<&BenchResult> {
+    results
+        .iter()
+        .max_by(|a, b| a.points_per_second().total_cmp(&b.points_per_second()))
+}
+
+/// Writes the settings of `result` as a pipeline file in JSON, which `trim --pipeline` reads.
+pub fn write_recommendation(result: &BenchResult, mut write: impl Write) -> io::Result<()> {
+    writeln!(
+        write,
+        "{{\n    \"threads\": {},\n    \"batch_size\": {},\n    \"channel_depth\": {}\n}}",
+        result.threads, result.batch_size, result.channel_depth
+    )?;
+    write.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{OptionValue, PipelineConfig};
+
+    #[test]
+    fn test_bench() {
+        let input = "tests/data/input1.las";
+        let results = bench(input, &[1, 2], &[1000], &[2]).unwrap();
+        let points = las::Reader::from_path(input)
+            .unwrap()
+            .header()
+            .number_of_points();
+        assert_eq!(results.len(), 2);
+        assert_eq!((results[0].threads, results[1].threads), (1, 2));
+        assert!(results.iter().all(|result| result.points == points));
+
+        let best = fastest(&results).unwrap();
+        let mut recommendation = Vec::new();
+        write_recommendation(best, &mut recommendation).unwrap();
+        let value = serde_json::from_str(&String::from_utf8(recommendation).unwrap()).unwrap();
+        let config = PipelineConfig::from_value(&value).unwrap();
+        assert!(config.options.contains(&(
+            "batch-size".to_string(),
+            OptionValue::Value("1000".to_string())
+        )));
+    }
+}
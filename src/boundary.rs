@@ -0,0 +1,414 @@
+//! Outlines of point clouds as GeoJSON polygons, for dataset footprints and tile indexes.
+//!
+//! [`Boundary`] takes the points in batches and keeps only what the outline needs: the vertices
+//! of the convex hull so far, or the grid cells holding points. The convex hull is exact. A
+//! concave outline follows the edges of the cells holding points, so it hugs the points to within
+//! a cell and leaves holes where no cell holds any; the cell size plays the part of the alpha of
+//! an alpha shape.
+//!
+//! Coordinates are written in the coordinate system of the input, not in WGS 84 as GeoJSON
+//! expects, so readers may have to be told which one it is.
+use crate::errors::MyError;
+use crate::filter::Condition;
+use crate::progress::NoProgress;
+use crate::LasProcessor;
+use las::Point;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// How the points are outlined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoundaryKind {
+    /// The convex hull of the points.
+    Convex,
+    /// The outline of the square cells of the given size that hold points.
+    Concave { cell_size: f64 },
+}
+
+pub use crate::polygon::Ring;
+
+/// The outline of the points added to it.
+#[derive(Clone, Debug)]
+pub struct Boundary {
+    kind: BoundaryKind,
+    number_of_points: u64,
+    /// The vertices of the convex hull, counter-clockwise.
+    hull: Vec<[f64; 2]>,
+    /// The cells holding points, by column and row.
+    cells: HashSet<(i64, i64)>,
+}
+
+impl Boundary {
+    pub fn new(kind: BoundaryKind) -> Self {
+        Self {
+            kind,
+            number_of_points: 0,
+            hull: Vec::new(),
+            cells: HashSet::new(),
+        }
+    }
+
+    /// Adds `points` to the outline.
+    pub fn add(&mut self, points: &[Point]) {
+        self.number_of_points += points.len() as u64;
+        match self.kind {
+            BoundaryKind::Convex => {
+                let mut candidates = std::mem::take(&mut self.hull);
+                candidates.extend(points.iter().map(|point| [point.x, point.y]));
+                self.hull = convex_hull(candidates);
+            }
+            BoundaryKind::Concave { cell_size } => {
+                self.cells.extend(points.iter().map(|point| {
+                    (
+                        (point.x / cell_size).floor() as i64,
+                        (point.y / cell_size).floor() as i64,
+                    )
+                }));
+            }
+        }
+    }
+
+    /// The number of points added.
+    pub fn number_of_points(&self) -> u64 {
+        self.number_of_points
+    }
+
+    /// The polygons of the outline, each an exterior ring followed by its holes. A convex hull is
+    /// a single polygon without holes, or none if the points don't span an area.
+    pub fn polygons(&self) -> Vec<Vec<Ring>> {
+        match self.kind {
+            BoundaryKind::Convex if self.hull.len() < 3 => Vec::new(),
+            BoundaryKind::Convex => {
+                let mut ring = self.hull.clone();
+                ring.push(ring[0]);
+                vec![vec![ring]]
+            }
+            BoundaryKind::Concave { cell_size } => trace_cells(&self.cells)
+                .into_iter()
+                .map(|polygon| {
+                    polygon
+                        .into_iter()
+                        .map(|ring| {
+                            ring.into_iter()
+                                .map(|(i, j)| [i as f64 * cell_size, j as f64 * cell_size])
+                                .collect()
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// The outline as a GeoJSON MultiPolygon.
+    pub fn to_geometry(&self) -> Value {
+        let polygons: Vec<Value> = self
+            .polygons()
+            .into_iter()
+            .map(|polygon| {
+                let rings: Vec<Value> = polygon
+                    .into_iter()
+                    .map(|ring| {
+                        let positions: Vec<Value> =
+                            ring.iter().map(|&[x, y]| json!([x, y])).collect();
+                        Value::from(positions)
+                    })
+                    .collect();
+                Value::from(rings)
+            })
+            .collect();
+        json!({"type": "MultiPolygon", "coordinates": polygons})
+    }
+}
+
+/// Outlines the points of the inputs at `paths` that meet `condition`.
+pub fn boundary(
+    paths: &[String],
+    kind: BoundaryKind,
+    condition: Condition,
+) -> Result<Boundary, MyError> {
+    let outline = Arc::new(Mutex::new(Boundary::new(kind)));
+    let shared = Arc::clone(&outline);
+    LasProcessor::new(
+        paths.to_vec(),
+        vec!["boundary".to_string()],
+        Vec::new(),
+        false,
+    )
+    .with_conditions(vec![condition])
+    .with_observer(Arc::new(NoProgress))
+    .process_with(move |_, points| {
+        if let Ok(mut outline) = shared.lock() {
+            outline.add(points);
+        }
+    })?;
+    let outline = outline.lock().map_err(|_| MyError::LockError)?;
+    Ok(outline.clone())
+}
+
+/// A GeoJSON FeatureCollection with a feature per outline, with its name and number of points as
+/// properties.
+pub fn feature_collection(outlines: &[(String, Boundary)]) -> Value {
+    let features: Vec<Value> = outlines
+        .iter()
+        .map(|(name, outline)| {
+            let properties = json!({
+                "name": name.clone(),
+                "points": outline.number_of_points(),
+            });
+            json!({
+                "type": "Feature",
+                "properties": properties,
+                "geometry": outline.to_geometry(),
+            })
+        })
+        .collect();
+    json!({"type": "FeatureCollection", "features": features})
+}
+
+/// The convex hull of `points` counter-clockwise, by Andrew's monotone chain.
+fn convex_hull(mut points: Vec<[f64; 2]>) -> Vec<[f64; 2]> {
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let turns_left = |o: [f64; 2], a: [f64; 2], b: [f64; 2]| {
+        (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0]) > 0.0
+    };
+    // The lower hull from left to right, then the upper hull from right to left
+    let half_hull = |points: &mut dyn Iterator<Item = [f64; 2]>| {
+        let mut hull: Vec<[f64; 2]> = Vec::new();
+        for point in points {
+            while hull.len() >= 2 && !turns_left(hull[hull.len() - 2], hull[hull.len() - 1], point)
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point starts the other half
+        hull.pop();
+        hull
+    };
+    let mut hull = half_hull(&mut points.iter().copied());
+    hull.extend(half_hull(&mut points.iter().rev().copied()));
+    hull
+}
+
+/// Traces the outline of `cells` into polygons of grid vertices, each an exterior ring followed
+/// by its holes.
+fn trace_cells(cells: &HashSet<(i64, i64)>) -> Vec<Vec<Vec<(i64, i64)>>> {
+    // The edges between cells holding points and empty ones, with the cell on the left
+    let mut edges: HashMap<(i64, i64), Vec<(i64, i64)>> = HashMap::new();
+    for &(i, j) in cells {
+        let sides = [
+            ((i, j - 1), (i, j), (i + 1, j)),
+            ((i + 1, j), (i + 1, j), (i + 1, j + 1)),
+            ((i, j + 1), (i + 1, j + 1), (i, j + 1)),
+            ((i - 1, j), (i, j + 1), (i, j)),
+        ];
+        for (neighbour, from, to) in sides {
+            if !cells.contains(&neighbour) {
+                edges.entry(from).or_default().push(to);
+            }
+        }
+    }
+
+    // Tracing starts at corners with a single edge leaving them, where it can't take the wrong
+    // turn. Where two cells only touch at a corner, tracing turns left so that they get rings of
+    // their own.
+    let mut starts: Vec<(i64, i64)> = edges
+        .iter()
+        .filter(|(_, to)| to.len() == 1)
+        .map(|(&from, _)| from)
+        .collect();
+    starts.sort_unstable();
+    // Rings whose every corner is shared with another ring start anywhere
+    let mut corners: Vec<(i64, i64)> = edges.keys().copied().collect();
+    corners.sort_unstable();
+    starts.extend(corners);
+    let mut rings = Vec::new();
+    for start in starts {
+        if edges.get(&start).is_none_or(Vec::is_empty) {
+            continue;
+        }
+        let mut ring = vec![start];
+        let mut current = start;
+        let mut direction = (0, 0);
+        while let Some(next) = take_edge(&mut edges, current, direction) {
+            direction = (next.0 - current.0, next.1 - current.1);
+            current = next;
+            ring.push(current);
+            if current == start {
+                break;
+            }
+        }
+        rings.push(simplify(ring));
+    }
+
+    // Exterior rings run counter-clockwise and holes clockwise. A hole belongs to the smallest
+    // exterior around the cell on the left of its first edge.
+    let (exteriors, holes): (Vec<_>, Vec<_>) =
+        rings.into_iter().partition(|ring| signed_area(ring) > 0);
+    let mut polygons: Vec<Vec<Vec<(i64, i64)>>> =
+        exteriors.into_iter().map(|ring| vec![ring]).collect();
+    for hole in holes {
+        let (from, to) = (hole[0], hole[1]);
+        // Twice the center of the cell on the left of the edge from `from` to `to`
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let inside = (from.0 + to.0 - dy, from.1 + to.1 + dx);
+        let owner = polygons
+            .iter_mut()
+            .filter(|polygon| contains(&polygon[0], inside))
+            .min_by_key(|polygon| signed_area(&polygon[0]));
+        if let Some(polygon) = owner {
+            polygon.push(hole);
+        }
+    }
+    polygons
+}
+
+/// Removes the edge leaving `from` that turns furthest left coming in along `direction`, and
+/// returns where it leads.
+fn take_edge(
+    edges: &mut HashMap<(i64, i64), Vec<(i64, i64)>>,
+    from: (i64, i64),
+    direction: (i64, i64),
+) -> Option<(i64, i64)> {
+    let to = edges.get_mut(&from)?;
+    // Left turns have a positive cross product, then come straight on and right turns
+    let turn = |to: &(i64, i64)| {
+        let out = (to.0 - from.0, to.1 - from.1);
+        let cross = direction.0 * out.1 - direction.1 * out.0;
+        -cross.signum()
+    };
+    let index = (0..to.len()).min_by_key(|&index| turn(&to[index]))?;
+    Some(to.swap_remove(index))
+}
+
+/// Drops the vertices in the middle of straight runs of a closed ring, and starts it at its
+/// lowest vertex.
+fn simplify(ring: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    let open = &ring[..ring.len() - 1];
+    let mut simplified: Vec<(i64, i64)> = (0..open.len())
+        .filter(|&index| {
+            let previous = open[(index + open.len() - 1) % open.len()];
+            let (vertex, next) = (open[index], open[(index + 1) % open.len()]);
+            let cross = (vertex.0 - previous.0) * (next.1 - vertex.1)
+                - (vertex.1 - previous.1) * (next.0 - vertex.0);
+            cross != 0
+        })
+        .map(|index| open[index])
+        .collect();
+    if let Some(lowest) = (0..simplified.len()).min_by_key(|&index| simplified[index]) {
+        simplified.rotate_left(lowest);
+        simplified.push(simplified[0]);
+    }
+    simplified
+}
+
+/// Twice the area of a closed ring, positive when it runs counter-clockwise.
+fn signed_area(ring: &[(i64, i64)]) -> i64 {
+    ring.windows(2)
+        .map(|edge| edge[0].0 * edge[1].1 - edge[1].0 * edge[0].1)
+        .sum()
+}
+
+/// Returns `true` if the closed ring of grid vertices contains `point`, given in half cells.
+fn contains(ring: &[(i64, i64)], point: (i64, i64)) -> bool {
+    let mut inside = false;
+    for edge in ring.windows(2) {
+        let (a, b) = (
+            (edge[0].0 * 2, edge[0].1 * 2),
+            (edge[1].0 * 2, edge[1].1 * 2),
+        );
+        if (a.1 > point.1) != (b.1 > point.1) {
+            // Where the edge crosses the row of the point, compared without dividing
+            let lhs = (point.0 - a.0) * (b.1 - a.1);
+            let rhs = (b.0 - a.0) * (point.1 - a.1);
+            if (b.1 > a.1 && lhs < rhs) || (b.1 < a.1 && lhs > rhs) {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(coordinates: &[(f64, f64)]) -> Vec<Point> {
+        coordinates
+            .iter()
+            .map(|&(x, y)| Point {
+                x,
+                y,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_boundary() {
+        let mut convex = Boundary::new(BoundaryKind::Convex);
+        convex.add(&points(&[(0.0, 0.0), (4.0, 0.0), (2.0, 1.0)]));
+        convex.add(&points(&[(4.0, 4.0), (0.0, 4.0), (2.0, 2.0), (2.0, 4.0)]));
+        assert_eq!(convex.number_of_points(), 7);
+        assert_eq!(
+            convex.polygons(),
+            [[[[0.0, 0.0], [4.0, 0.0], [4.0, 4.0], [0.0, 4.0], [0.0, 0.0]]]]
+        );
+        let mut line = Boundary::new(BoundaryKind::Convex);
+        line.add(&points(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]));
+        assert!(line.polygons().is_empty());
+
+        // A ring of eight cells around an empty one, and a cell touching it at a corner
+        let mut concave = Boundary::new(BoundaryKind::Concave { cell_size: 2.0 });
+        let mut ring = Vec::new();
+        for i in 0..3 {
+            for j in 0..3 {
+                if (i, j) != (1, 1) {
+                    ring.push((f64::from(i) * 2.0 + 0.5, f64::from(j) * 2.0 + 0.5));
+                }
+            }
+        }
+        ring.push((7.0, 7.0));
+        concave.add(&points(&ring));
+        let polygons = concave.polygons();
+        assert_eq!(polygons.len(), 2);
+        let square = polygons.iter().find(|polygon| polygon.len() == 2).unwrap();
+        assert_eq!(
+            square[0],
+            [[0.0, 0.0], [6.0, 0.0], [6.0, 6.0], [0.0, 6.0], [0.0, 0.0]]
+        );
+        assert_eq!(
+            square[1],
+            [[2.0, 2.0], [2.0, 4.0], [4.0, 4.0], [4.0, 2.0], [2.0, 2.0]]
+        );
+        let corner = polygons.iter().find(|polygon| polygon.len() == 1).unwrap();
+        assert_eq!(
+            corner[0],
+            [[6.0, 6.0], [8.0, 6.0], [8.0, 8.0], [6.0, 8.0], [6.0, 6.0]]
+        );
+
+        let collection = feature_collection(&[("test".to_string(), concave)]);
+        assert_eq!(collection["features"][0]["properties"]["points"], 9);
+        let geometry = &collection["features"][0]["geometry"];
+        assert_eq!(geometry["type"], "MultiPolygon");
+        assert_eq!(geometry["coordinates"][0][0][0][0], 0.0);
+
+        let input = "tests/data/input1.las";
+        let keep_all = Condition::on_view(crate::Dimensions::XYZ, Arc::new(|_| true));
+        let outline = boundary(&[input.to_string()], BoundaryKind::Convex, keep_all).unwrap();
+        let header = las::Reader::from_path(input).unwrap().header().clone();
+        assert_eq!(outline.number_of_points(), header.number_of_points());
+        let bounds = header.bounds();
+        let ring = &outline.polygons()[0][0];
+        let tolerance = 0.01;
+        for &[x, y] in ring {
+            assert!(x >= bounds.min.x - tolerance && x <= bounds.max.x + tolerance);
+            assert!(y >= bounds.min.y - tolerance && y <= bounds.max.y + tolerance);
+        }
+    }
+}
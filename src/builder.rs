@@ -0,0 +1,211 @@
+//! Building a [`LasProcessor`] one setting at a time.
+//!
+//! [`LasProcessor::new`] takes the inputs, outputs, conditions and extra bytes flag as positional
+//! arguments, so every new setting would otherwise change its signature. The builder pairs each
+//! output with its condition and leaves everything else at its default unless it is set.
+//!
+//! ```rust
+//! use las_trimmer::{Condition, LasProcessor, NumericFilter};
+//! use std::sync::Arc;
+//!
+//! let processor = LasProcessor::builder()
+//!     .input("tests/data/input1.las")
+//!     .output(
+//!         "ground.las",
+//!         Condition::numeric(NumericFilter::Classes(las_trimmer::ClassMask::new(&[2]))),
+//!     )
+//!     .output(
+//!         "bright.laz",
+//!         Condition::on_point(Arc::new(|point| point.intensity > 60_000)),
+//!     )
+//!     .batch_size(10_000)
+//!     .reader_threads(2)
+//!     .build();
+//! # let _ = processor;
+//! ```
+use crate::adjustment::Adjustments;
+use crate::anonymize::Anonymization;
+use crate::cancel::CancellationToken;
+use crate::colormap::{ColorSource, Colormap};
+use crate::compression::LazChunking;
+use crate::filter::Condition;
+use crate::gps_time::GpsTimeConversion;
+use crate::neighborhood::NeighborFilter;
+use crate::output_options::OutputOptions;
+use crate::overlap::OverlapKind;
+use crate::palette::ClassPalette;
+use crate::progress::ProgressObserver;
+use crate::sink::PointSink;
+use crate::source_tag::{SourceIds, SourceTag};
+use crate::stages::SortKey;
+use crate::stamp::HeaderStamp;
+use crate::stream::InputStream;
+use crate::transform::ConditionalTransform;
+use crate::vlr_merge::VlrMerge;
+use crate::{Backend, ErrorPolicy, LasProcessor};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builds a [`LasProcessor`]. Created by [`LasProcessor::builder`].
+pub struct LasProcessorBuilder {
+    processor: LasProcessor,
+}
+
+impl LasProcessor {
+    /// Starts building a processor with no inputs or outputs and the default settings.
+    pub fn builder() -> LasProcessorBuilder {
+        LasProcessorBuilder {
+            processor: LasProcessor::new(Vec::new(), Vec::new(), Vec::new(), false),
+        }
+    }
+}
+
+impl LasProcessorBuilder {
+    /// Adds an input file.
+    pub fn input(mut self, path: impl Into<String>) -> Self {
+        self.processor.paths.push(path.into());
+        self
+    }
+
+    /// Adds several input files.
+    pub fn inputs<I>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.processor
+            .paths
+            .extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    /// Adds a stream as an input. See [`LasProcessor::with_stream`].
+    pub fn stream(mut self, name: &str, stream: impl InputStream + 'static) -> Self {
+        self.processor = self.processor.with_stream(name, stream);
+        self
+    }
+
+    /// Adds an output file receiving the points that meet `condition`.
+    pub fn output(mut self, path: impl Into<String>, condition: Condition) -> Self {
+        self.processor.output_paths.push(path.into());
+        self.processor.conditions.push(condition);
+        self
+    }
+
+    /// Adds an output handing its points to `sink`. See [`LasProcessor::with_sink`].
+    pub fn sink(
+        mut self,
+        name: &str,
+        sink: impl PointSink + 'static,
+        condition: Condition,
+    ) -> Self {
+        self.processor = self.processor.with_sink(name, sink, condition);
+        self
+    }
+
+    /// Drops the extra bytes of the points. See [`LasProcessor::new`].
+    pub fn strip_extra_bytes(mut self, strip: bool) -> Self {
+        self.processor.strip_extra_bytes = strip;
+        self
+    }
+
+    /// Returns the processor.
+    pub fn build(self) -> LasProcessor {
+        self.processor
+    }
+}
+
+/// Adds a method to [`LasProcessorBuilder`] for each `with_*` method of [`LasProcessor`] listed,
+/// named without the prefix, which passes its arguments on to the processor.
+macro_rules! forward {
+    ($($name:ident => $with:ident($($arg:ident: $ty:ty),*);)*) => {
+        impl LasProcessorBuilder {
+            $(
+                #[doc = concat!("See [`LasProcessor::", stringify!($with), "`].")]
+                pub fn $name(mut self, $($arg: $ty),*) -> Self {
+                    self.processor = self.processor.$with($($arg),*);
+                    self
+                }
+            )*
+        }
+    };
+}
+
+forward! {
+    gps_time => with_gps_time(conversion: GpsTimeConversion);
+    source_tag => with_source_tag(tag: SourceTag);
+    source_ids => with_source_ids(ids: SourceIds);
+    adjustments => with_adjustments(adjustments: Adjustments);
+    transform => with_transform(transform: ConditionalTransform);
+    anonymization => with_anonymization(anonymization: Anonymization);
+    stamp => with_stamp(stamp: HeaderStamp);
+    vlr_merge => with_vlr_merge(merge: VlrMerge);
+    drop_waveforms => with_drop_waveforms(drop_waveforms: bool);
+    neighbor_filter => with_neighbor_filter(filter: NeighborFilter);
+    output_options => with_output_options(path: &str, options: OutputOptions);
+    minimize_format => with_minimize_format(minimize_format: bool);
+    intensity_stretch => with_intensity_stretch(low: f64, high: f64);
+    colorize => with_colorize(source: ColorSource, colormap: Colormap);
+    class_colors => with_class_colors(palette: ClassPalette);
+    crop_to_overlap => with_crop_to_overlap(kind: OverlapKind);
+    flight_line_ids => with_flight_line_ids(gap: f64);
+    overwrite => with_overwrite(overwrite: bool);
+    append => with_append(append: bool);
+    legacy_compatible => with_legacy_compatible(legacy_compatible: bool);
+    cancellation => with_cancellation(token: CancellationToken);
+    file_timeout => with_file_timeout(timeout: Duration);
+    error_policy => with_error_policy(policy: ErrorPolicy);
+    max_point_errors => with_max_point_errors(limit: u64);
+    observer => with_observer(observer: Arc<dyn ProgressObserver>);
+    reader_threads => with_reader_threads(threads: usize);
+    writer_threads => with_writer_threads(threads: usize);
+    batch_size => with_batch_size(batch_size: u64);
+    channel_depth => with_channel_depth(depth: usize);
+    auto_tune => with_auto_tune(auto_tune: bool);
+    max_memory => with_max_memory(bytes: u64);
+    low_memory => with_low_memory(low_memory: bool);
+    spill_dir => with_spill_dir(dir: impl Into<PathBuf>);
+    sort => with_sort(key: SortKey);
+    compressed_spill => with_compressed_spill(compressed: bool);
+    max_output_points => with_max_output_points(points: u64);
+    split_size => with_split_size(points: u64);
+    mmap => with_mmap(mmap: bool);
+    verify => with_verify(verify: bool);
+    space_check => with_space_check(space_check: bool);
+    laz_chunking => with_laz_chunking(chunking: LazChunking);
+    backend => with_backend(backend: Backend);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::NumericFilter;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_builder() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("output.las");
+        let processor = LasProcessor::builder()
+            .inputs(["tests/data/input1.las"])
+            .output(
+                output.to_str().unwrap(),
+                Condition::numeric(NumericFilter::Intensity(0..=39_999)),
+            )
+            .strip_extra_bytes(true)
+            .batch_size(0)
+            .reader_threads(2)
+            .backend(Backend::Rayon)
+            .build();
+        assert_eq!(processor.paths, ["tests/data/input1.las"]);
+        assert_eq!(processor.conditions.len(), 1);
+        assert!(processor.strip_extra_bytes);
+        assert_eq!(processor.vec_size, 1);
+        assert_eq!(processor.reader_threads, Some(2));
+        assert_eq!(processor.backend, Backend::Rayon);
+
+        let report = processor.process_lidar_files().unwrap();
+        assert!(report.outputs[0].points_written > 0);
+    }
+}
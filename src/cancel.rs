@@ -0,0 +1,34 @@
+//! Cooperative cancellation of a running `LasProcessor`.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A handle that lets an embedding application stop a running `LasProcessor`.
+///
+/// Clones share the same flag, so one clone can be handed to the processor while another is kept
+/// by the GUI or server that wants to cancel. The processor checks the token between batches:
+/// reading stops, the points read so far are written, and the outputs are finalized normally.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that hasn't been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps an existing flag, for applications that already track cancellation with an
+    /// `Arc<AtomicBool>`.
+    pub fn from_flag(flag: Arc<AtomicBool>) -> Self {
+        Self(flag)
+    }
+
+    /// Requests cancellation.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` once cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
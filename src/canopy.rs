@@ -0,0 +1,207 @@
+//! Forestry metrics gridded from the points while they stream past.
+//!
+//! For each cell of a grid, [`CanopyGrid`] computes the canopy cover, the share of the first
+//! returns that aren't classified as ground, and the maximum and percentile heights above the
+//! ground. The ground is the lowest ground point of the cell, or its lowest point when none is
+//! classified as ground. The percentiles are of the points that aren't ground, and are 0 in cells
+//! with none. The height of every point is kept until the end, as 4 bytes per point.
+//!
+//! A [`RasterOutput`](crate::grid::RasterOutput) of a [`CanopyGrid`] writes the metrics of an
+//! output as a GeoTIFF with a band per metric, or as CSV with a line per cell.
+use crate::errors::MyError;
+use crate::grid::RasterGrid;
+use crate::raster::{cell_of, Raster, NODATA};
+use las::point::Classification;
+use las::Point;
+use std::collections::HashMap;
+
+/// The percentile heights computed unless others are asked for.
+pub const DEFAULT_PERCENTILES: [f64; 4] = [25.0, 50.0, 75.0, 95.0];
+
+/// What is kept of the points of a cell.
+#[derive(Clone, Debug)]
+struct Cell {
+    first_returns: u64,
+    canopy_first_returns: u64,
+    lowest_ground: f64,
+    lowest: f64,
+    highest: f64,
+    /// The heights of the points that aren't ground, above sea level until the ground is known.
+    canopy: Vec<f32>,
+}
+
+/// The points of a grid, reduced to what the metrics need.
+#[derive(Clone, Debug)]
+pub struct CanopyGrid {
+    cell_size: f64,
+    percentiles: Vec<f64>,
+    cells: HashMap<(i64, i64), Cell>,
+}
+
+impl CanopyGrid {
+    pub fn new(cell_size: f64) -> Self {
+        Self {
+            cell_size,
+            percentiles: DEFAULT_PERCENTILES.to_vec(),
+            cells: HashMap::new(),
+        }
+    }
+
+    /// Sets the percentile heights computed, from 0 to 100.
+    pub fn with_percentiles(mut self, percentiles: &[f64]) -> Self {
+        self.percentiles = percentiles.to_vec();
+        self
+    }
+
+    /// The names of the metrics, in the order of the bands.
+    pub fn metric_names(&self) -> Vec<String> {
+        let mut names = vec!["cover".to_string(), "max_height".to_string()];
+        names.extend(
+            self.percentiles
+                .iter()
+                .map(|percentile| format!("p{}", percentile)),
+        );
+        names
+    }
+
+    /// The metrics of the cell holding `(x, y)`, in the order of [`CanopyGrid::metric_names`],
+    /// if it holds points.
+    pub fn metrics_at(&self, x: f64, y: f64) -> Option<Vec<f32>> {
+        self.cells
+            .get(&cell_of(x, y, self.cell_size))
+            .map(|cell| self.metrics(cell))
+    }
+
+    fn metrics(&self, cell: &Cell) -> Vec<f32> {
+        let ground = if cell.lowest_ground.is_finite() {
+            cell.lowest_ground
+        } else {
+            cell.lowest
+        };
+        let cover = match cell.first_returns {
+            0 => 0.0,
+            first_returns => cell.canopy_first_returns as f64 / first_returns as f64,
+        };
+        let mut heights: Vec<f64> = cell.canopy.iter().map(|&z| f64::from(z) - ground).collect();
+        heights.sort_unstable_by(f64::total_cmp);
+        let mut metrics = vec![cover as f32, (cell.highest - ground) as f32];
+        metrics.extend(
+            self.percentiles
+                .iter()
+                .map(|&percentile| percentile_of(&heights, percentile) as f32),
+        );
+        metrics
+    }
+}
+
+impl RasterGrid for CanopyGrid {
+    fn add(&mut self, points: &[Point]) {
+        for point in points {
+            let cell = self
+                .cells
+                .entry(cell_of(point.x, point.y, self.cell_size))
+                .or_insert(Cell {
+                    first_returns: 0,
+                    canopy_first_returns: 0,
+                    lowest_ground: f64::INFINITY,
+                    lowest: f64::INFINITY,
+                    highest: f64::NEG_INFINITY,
+                    canopy: Vec::new(),
+                });
+            let ground = point.classification == Classification::Ground;
+            if point.return_number <= 1 {
+                cell.first_returns += 1;
+                cell.canopy_first_returns += u64::from(!ground);
+            }
+            if ground {
+                cell.lowest_ground = cell.lowest_ground.min(point.z);
+            } else {
+                cell.canopy.push(point.z as f32);
+            }
+            cell.lowest = cell.lowest.min(point.z);
+            cell.highest = cell.highest.max(point.z);
+        }
+    }
+
+    /// The metrics as a raster with a band per metric.
+    fn to_raster(&self) -> Result<Raster, MyError> {
+        let metrics: Vec<((i64, i64), Vec<f32>)> = self
+            .cells
+            .iter()
+            .map(|(&key, cell)| (key, self.metrics(cell)))
+            .collect();
+        let cells = metrics
+            .iter()
+            .map(|(key, values)| (*key, values.as_slice()));
+        Ok(
+            Raster::from_band_cells(self.cell_size, &self.metric_names(), cells, NODATA)?
+                .with_nodata(NODATA),
+        )
+    }
+}
+
+/// The `percentile` of the sorted `values`, interpolated between the closest ones, or 0 if there
+/// are none.
+fn percentile_of(values: &[f64], percentile: f64) -> f64 {
+    let Some(&last) = values.last() else {
+        return 0.0;
+    };
+    let rank = (percentile / 100.0).clamp(0.0, 1.0) * (values.len() - 1) as f64;
+    let below = rank.floor() as usize;
+    match values.get(below + 1) {
+        Some(&above) => values[below] + (above - values[below]) * (rank - below as f64),
+        None => last,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canopy() {
+        let point = |x: f64, z: f64, return_number: u8, classification: Classification| Point {
+            x,
+            y: 5.0,
+            z,
+            return_number,
+            classification,
+            ..Default::default()
+        };
+        let points = [
+            // Ground at 100 under trees up to 20 high, with 2 of 3 first returns on the trees
+            point(1.0, 100.0, 1, Classification::Ground),
+            point(2.0, 100.5, 2, Classification::Ground),
+            point(3.0, 110.0, 1, Classification::HighVegetation),
+            point(4.0, 120.0, 1, Classification::HighVegetation),
+            point(5.0, 105.0, 2, Classification::MediumVegetation),
+            // A cell without ground points, whose lowest point stands for the ground
+            point(15.0, 50.0, 1, Classification::Unclassified),
+            point(16.0, 52.0, 1, Classification::Unclassified),
+        ];
+        let mut grid = CanopyGrid::new(10.0).with_percentiles(&[0.0, 50.0, 100.0]);
+        grid.add(&points);
+        assert_eq!(
+            grid.metric_names(),
+            ["cover", "max_height", "p0", "p50", "p100"]
+        );
+        let metrics = grid.metrics_at(0.0, 0.0).unwrap();
+        assert!((metrics[0] - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(metrics[1..], [20.0, 5.0, 10.0, 20.0]);
+        assert_eq!(
+            grid.metrics_at(10.0, 0.0).unwrap(),
+            [1.0, 2.0, 0.0, 1.0, 2.0]
+        );
+        assert_eq!(grid.metrics_at(20.0, 0.0), None);
+
+        let raster = grid.to_raster().unwrap();
+        assert_eq!((raster.columns, raster.rows, raster.bands.len()), (2, 1, 5));
+        assert_eq!(
+            raster.values_at(15.0, 5.0),
+            Some(&[1.0, 2.0, 0.0, 1.0, 2.0][..])
+        );
+
+        assert_eq!(percentile_of(&[], 50.0), 0.0);
+        assert_eq!(percentile_of(&[1.0, 2.0, 3.0, 4.0], 50.0), 2.5);
+    }
+}
@@ -0,0 +1,195 @@
+//! Coloring points from their intensity or elevation, for scans without color.
+//!
+//! Many viewers show the color of the points and ignore their intensity, so scans without color
+//! show up black or in a single flat tone. A [`Colorizer`] gives every point the color a
+//! [`Colormap`] has at its intensity or elevation, as a fraction of a range: the whole range of
+//! intensities, or the elevations between the lowest and the highest point of the inputs. The
+//! outputs are written in the point format with the fields of the inputs and color, see
+//! [`with_color`].
+use crate::errors::MyError;
+use las::point::Format;
+use las::{Color, Point};
+
+/// A ramp of colors, from the lowest value to the highest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    /// From black to white.
+    Gray,
+    /// From dark purple through blue and green to yellow, evenly bright to the eye.
+    Viridis,
+    /// From deep blue water through green lowlands and brown hills to white peaks.
+    Terrain,
+    /// From blue through cyan, green and yellow to red.
+    Rainbow,
+}
+
+impl Colormap {
+    /// The 8-bit colors of the ramp and where they are on it, from 0 to 1.
+    fn stops(self) -> &'static [(f64, [u8; 3])] {
+        match self {
+            Colormap::Gray => &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])],
+            Colormap::Viridis => &[
+                (0.0, [68, 1, 84]),
+                (0.25, [59, 82, 139]),
+                (0.5, [33, 145, 140]),
+                (0.75, [94, 201, 98]),
+                (1.0, [253, 231, 37]),
+            ],
+            Colormap::Terrain => &[
+                (0.0, [51, 51, 153]),
+                (0.15, [0, 153, 255]),
+                (0.25, [0, 204, 102]),
+                (0.5, [255, 255, 153]),
+                (0.75, [128, 92, 84]),
+                (1.0, [255, 255, 255]),
+            ],
+            Colormap::Rainbow => &[
+                (0.0, [0, 0, 255]),
+                (0.25, [0, 255, 255]),
+                (0.5, [0, 255, 0]),
+                (0.75, [255, 255, 0]),
+                (1.0, [255, 0, 0]),
+            ],
+        }
+    }
+
+    /// The color at `fraction` of the ramp, clamped to it, blended between the nearest stops.
+    pub fn color(self, fraction: f64) -> Color {
+        let fraction = if fraction.is_nan() {
+            0.0
+        } else {
+            fraction.clamp(0.0, 1.0)
+        };
+        let stops = self.stops();
+        let upper = stops
+            .iter()
+            .position(|&(at, _)| at >= fraction)
+            .unwrap_or(stops.len() - 1)
+            .max(1);
+        let (from, low) = stops[upper - 1];
+        let (to, high) = stops[upper];
+        let weight = ((fraction - from) / (to - from)).clamp(0.0, 1.0);
+        let channel = |index: usize| {
+            let value =
+                f64::from(low[index]) + (f64::from(high[index]) - f64::from(low[index])) * weight;
+            // Scaled so that 255 is the top of the 16-bit range
+            (value * 257.0).round() as u16
+        };
+        Color::new(channel(0), channel(1), channel(2))
+    }
+}
+
+/// The value of the points the colors are taken from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSource {
+    /// The intensity, over the whole range of intensities.
+    Intensity,
+    /// The Z coordinate, between the lowest and the highest of the inputs.
+    Elevation,
+}
+
+/// Colors points from a colormap, over a range of their intensities or elevations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Colorizer {
+    source: ColorSource,
+    colormap: Colormap,
+    low: f64,
+    high: f64,
+}
+
+impl Colorizer {
+    /// Colors the points from `colormap` by their `source`, the values from `low` to `high`
+    /// spanning the whole colormap.
+    pub fn new(source: ColorSource, colormap: Colormap, low: f64, high: f64) -> Self {
+        Self {
+            source,
+            colormap,
+            low: low.min(high),
+            high: high.max(low),
+        }
+    }
+
+    /// Colors the points by their intensity, from 0 to the highest intensity.
+    pub fn by_intensity(colormap: Colormap) -> Self {
+        Self::new(ColorSource::Intensity, colormap, 0.0, f64::from(u16::MAX))
+    }
+
+    /// The color of `point`. If the range is empty, every point gets the middle of the colormap.
+    pub fn color(&self, point: &Point) -> Color {
+        let value = match self.source {
+            ColorSource::Intensity => f64::from(point.intensity),
+            ColorSource::Elevation => point.z,
+        };
+        let fraction = if self.high > self.low {
+            (value - self.low) / (self.high - self.low)
+        } else {
+            0.5
+        };
+        self.colormap.color(fraction)
+    }
+
+    /// Replaces the color of `point`.
+    pub fn apply(&self, point: &mut Point) {
+        point.color = Some(self.color(point));
+    }
+}
+
+/// The point format with the fields of `format` and color, keeping its extra bytes.
+pub fn with_color(format: Format) -> Result<Format, MyError> {
+    if format.has_color {
+        return Ok(format);
+    }
+    let number = match format.to_u8()? {
+        0 => 2,
+        1 => 3,
+        4 => 5,
+        6 => 7,
+        _ => 10,
+    };
+    let mut colored = Format::new(number)?;
+    colored.extra_bytes = format.extra_bytes;
+    colored.is_compressed = format.is_compressed;
+    Ok(colored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colorizer() {
+        assert_eq!(Colormap::Gray.color(0.0), Color::new(0, 0, 0));
+        assert_eq!(Colormap::Gray.color(2.0), Color::new(65535, 65535, 65535));
+        assert_eq!(Colormap::Gray.color(0.5), Color::new(32768, 32768, 32768));
+        assert_eq!(Colormap::Rainbow.color(0.25), Color::new(0, 65535, 65535));
+        assert_eq!(Colormap::Viridis.color(1.0), Color::new(65021, 59367, 9509));
+        assert_eq!(
+            Colormap::Terrain.color(-1.0),
+            Color::new(13107, 13107, 39321)
+        );
+
+        let colorizer = Colorizer::new(ColorSource::Elevation, Colormap::Gray, 110.0, 100.0);
+        let mut point = Point {
+            z: 105.0,
+            ..Default::default()
+        };
+        colorizer.apply(&mut point);
+        assert_eq!(point.color, Some(Color::new(32768, 32768, 32768)));
+        let point = Point {
+            intensity: u16::MAX,
+            ..Default::default()
+        };
+        assert_eq!(
+            Colorizer::by_intensity(Colormap::Gray).color(&point),
+            Color::new(65535, 65535, 65535)
+        );
+
+        for (number, expected) in [(0, 2), (1, 3), (4, 5), (6, 7), (9, 10), (3, 3), (8, 8)] {
+            let mut format = Format::new(number).unwrap();
+            format.extra_bytes = 2;
+            let colored = with_color(format).unwrap();
+            assert_eq!(colored.to_u8().unwrap(), expected);
+            assert_eq!(colored.extra_bytes, 2);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Writing LAZ output with control over how the points are split into chunks.
+//!
+//! `las::Writer` always compresses with the default LAZ settings, so LAZ outputs go through
+//! [`LazWriter`] instead, which drives the `laz` compressor directly and lets the chunk size be
+//! chosen. LAS output still uses `las::Writer`.
+//!
+//! Compression is usually what limits how fast a single LAZ output is written. With
+//! [`LazChunking::Parallel`] the points are held back until there is a chunk for every thread, and
+//! the chunks are compressed at the same time, each on its own, then written one after the other
+//! with a chunk table rebuilt for them all.
+use crate::errors::MyError;
+use las::{Builder, Header, Point, Vlr};
+#[cfg(feature = "native")]
+use laz::ParLasZipCompressor;
+use laz::{LasZipCompressor, LazItemRecordBuilder, LazVlr, LazVlrBuilder};
+use std::io::{Seek, SeekFrom, Write};
+
+/// The number of points per chunk used by LAZ writers unless told otherwise.
+pub const DEFAULT_CHUNK_SIZE: u32 = 50_000;
+
+/// How the points of a LAZ output are split into independently compressed chunks.
+///
+/// Larger chunks compress a little better, while smaller ones let readers seek to a point, or to
+/// an area when the chunks are spatially coherent, with less decompression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LazChunking {
+    /// Chunks of a fixed number of points.
+    Fixed(u32),
+    /// A chunk per batch of points, so the chunks follow the order the points were read in.
+    Variable,
+    /// Chunks of a fixed number of points, compressed on every core at once. The points of a
+    /// chunk per core are held in memory until they are compressed. Without the `native` feature
+    /// the chunks are compressed one at a time.
+    Parallel(u32),
+}
+
+impl Default for LazChunking {
+    fn default() -> Self {
+        LazChunking::Fixed(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+/// Writes LAZ compressed points to a seekable stream.
+pub struct LazWriter<W: Write + Seek + Send + 'static> {
+    compressor: Compressor<W>,
+    header: Header,
+    start: u64,
+    buffer: Vec<u8>,
+    chunking: LazChunking,
+    points_in_chunk: u64,
+}
+
+/// Compresses the points as they come, or a chunk per core at a time.
+enum Compressor<W: Write + Seek + Send + 'static> {
+    Sequential(LasZipCompressor<'static, W>),
+    #[cfg(feature = "native")]
+    Parallel {
+        compressor: ParLasZipCompressor<W>,
+        /// The records waiting to be compressed.
+        records: Vec<u8>,
+        /// The number of bytes of records compressed at once.
+        batch_size: usize,
+    },
+}
+
+impl<W: Write + Seek + Send + 'static> LazWriter<W> {
+    /// Writes `header`, with the point counts cleared and a laszip VLR for `chunking`, and
+    /// returns a writer for the points.
+    pub fn new(mut write: W, header: Header, chunking: LazChunking) -> Result<Self, MyError> {
+        let start = write.stream_position()?;
+        let mut builder = Builder::from(header);
+        builder.point_format.is_compressed = true;
+        builder.vlrs.retain(|vlr| !las::laz::is_laszip_vlr(vlr));
+        builder.evlrs.retain(|vlr| !las::laz::is_laszip_vlr(vlr));
+
+        let items = LazItemRecordBuilder::default_for_point_format_id(
+            builder.point_format.to_u8()?,
+            builder.point_format.extra_bytes,
+        )?;
+        let laz_vlr = match chunking {
+            LazChunking::Fixed(chunk_size) | LazChunking::Parallel(chunk_size) => {
+                LazVlrBuilder::new(items).with_fixed_chunk_size(chunk_size.max(1))
+            }
+            LazChunking::Variable => LazVlrBuilder::new(items).with_variable_chunk_size(),
+        }
+        .build();
+        let mut data = Vec::new();
+        laz_vlr.write_to(&mut data)?;
+        builder.vlrs.push(Vlr {
+            user_id: LazVlr::USER_ID.to_string(),
+            record_id: LazVlr::RECORD_ID,
+            description: LazVlr::DESCRIPTION.to_string(),
+            data,
+        });
+
+        let mut header = builder.into_header()?;
+        header.clear();
+        header.write_to(&mut write)?;
+        let compressor = match chunking {
+            #[cfg(feature = "native")]
+            LazChunking::Parallel(chunk_size) => Compressor::Parallel {
+                batch_size: chunk_size.max(1) as usize
+                    * usize::from(header.point_format().len())
+                    * rayon::current_num_threads(),
+                compressor: ParLasZipCompressor::new(write, laz_vlr)?,
+                records: Vec::new(),
+            },
+            // Without the `native` feature there is no thread pool, so parallel chunks are
+            // compressed here one at a time, with the same chunk size
+            _ => Compressor::Sequential(LasZipCompressor::new(write, laz_vlr)?),
+        };
+        Ok(Self {
+            compressor,
+            header,
+            start,
+            buffer: Vec::new(),
+            chunking,
+            points_in_chunk: 0,
+        })
+    }
+
+    /// The header, with the point counts and bounds of the points written so far.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Compresses a single point.
+    pub fn write_point(&mut self, point: Point) -> Result<(), MyError> {
+        self.header.add_point(&point);
+        self.buffer.clear();
+        point
+            .into_raw(self.header.transforms())?
+            .write_to(&mut self.buffer, self.header.point_format())?;
+        match &mut self.compressor {
+            Compressor::Sequential(compressor) => compressor.compress_one(&self.buffer)?,
+            #[cfg(feature = "native")]
+            Compressor::Parallel {
+                compressor,
+                records,
+                batch_size,
+            } => {
+                records.extend_from_slice(&self.buffer);
+                if records.len() >= *batch_size {
+                    compressor.compress_many(records)?;
+                    records.clear();
+                }
+            }
+        }
+        self.points_in_chunk += 1;
+        Ok(())
+    }
+
+    /// Ends the current chunk when writing variable-size chunks. Does nothing for fixed-size
+    /// chunks, or if no point has been written since the last chunk ended.
+    pub fn end_chunk(&mut self) -> Result<(), MyError> {
+        if let (LazChunking::Variable, Compressor::Sequential(compressor)) =
+            (self.chunking, &mut self.compressor)
+        {
+            if self.points_in_chunk > 0 {
+                compressor.finish_current_chunk()?;
+                self.points_in_chunk = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the chunk table and the EVLRs, updates the header and returns the stream.
+    pub fn into_inner(self) -> Result<W, MyError> {
+        let mut write = match self.compressor {
+            Compressor::Sequential(mut compressor) => {
+                compressor.done()?;
+                compressor.into_inner()
+            }
+            #[cfg(feature = "native")]
+            Compressor::Parallel {
+                mut compressor,
+                records,
+                ..
+            } => {
+                if !records.is_empty() {
+                    compressor.compress_many(&records)?;
+                }
+                compressor.done()?;
+                compressor.into_inner()
+            }
+        };
+        write.write_all(self.header.point_padding())?;
+        let start_of_first_evlr = write.stream_position()?;
+        for evlr in self.header.evlrs() {
+            evlr.clone().into_raw(true)?.write_to(&mut write)?;
+        }
+        let mut raw_header = self.header.clone().into_raw()?;
+        if let Some(evlr) = raw_header.evlr.as_mut() {
+            evlr.start_of_first_evlr = start_of_first_evlr;
+        }
+        write.seek(SeekFrom::Start(self.start))?;
+        raw_header.write_to(&mut write)?;
+        write.seek(SeekFrom::End(0))?;
+        Ok(write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_laz_chunking() {
+        let write = |chunking| {
+            let header = Builder::from((1, 4)).into_header().unwrap();
+            let mut writer = LazWriter::new(Cursor::new(Vec::new()), header, chunking).unwrap();
+            for i in 0..10 {
+                writer
+                    .write_point(Point {
+                        x: i as f64,
+                        ..Default::default()
+                    })
+                    .unwrap();
+                if i % 5 == 4 {
+                    writer.end_chunk().unwrap();
+                }
+            }
+            let mut data = writer.into_inner().unwrap();
+            data.set_position(0);
+            las::Reader::new(data).unwrap()
+        };
+
+        let mut reader = write(LazChunking::Fixed(3));
+        assert_eq!(reader.header().laz_vlr().unwrap().chunk_size(), 3);
+        assert_eq!(reader.header().number_of_points(), 10);
+        // Seeking goes through the chunk table
+        reader.seek(7).unwrap();
+        assert_eq!(reader.read_point().unwrap().unwrap().x, 7.0);
+
+        // Several chunks at once, the last one short
+        let mut reader = write(LazChunking::Parallel(3));
+        assert_eq!(reader.header().laz_vlr().unwrap().chunk_size(), 3);
+        assert_eq!(reader.header().number_of_points(), 10);
+        reader.seek(7).unwrap();
+        assert_eq!(reader.read_point().unwrap().unwrap().x, 7.0);
+        let xs: Vec<f64> = reader.points().map(|point| point.unwrap().x).collect();
+        assert_eq!(xs, [8.0, 9.0]);
+
+        let mut reader = write(LazChunking::Variable);
+        assert!(reader
+            .header()
+            .laz_vlr()
+            .unwrap()
+            .uses_variable_size_chunks());
+        let xs: Vec<f64> = reader.points().map(|point| point.unwrap().x).collect();
+        assert_eq!(xs, (0..10).map(|i| i as f64).collect::<Vec<_>>());
+    }
+}
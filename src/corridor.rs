@@ -0,0 +1,149 @@
+//! Keeping the points near a position or along a polyline.
+//!
+//! A [`Corridor`] holds the points within a distance of a polyline, e.g. the surroundings of a
+//! pipeline, or of a single position, e.g. a survey monument. The distance is measured in 3D, or
+//! only horizontally when the corridor is made flat, in which case the Z of the vertices is
+//! ignored. The vertices must be in the coordinate system of the points.
+use crate::errors::MyError;
+
+/// The positions within a distance of a polyline, or of a single position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Corridor {
+    vertices: Vec<[f64; 3]>,
+    distance: f64,
+    horizontal: bool,
+    /// The lowest and highest X and Y of the corridor, to turn away far points quickly.
+    min: [f64; 2],
+    max: [f64; 2],
+}
+
+impl Corridor {
+    /// The positions within `distance` of the polyline through `vertices`, measured only in X
+    /// and Y if `horizontal`. A single vertex is a position on its own.
+    pub fn new(vertices: Vec<[f64; 3]>, distance: f64, horizontal: bool) -> Result<Self, MyError> {
+        if vertices.is_empty() {
+            return Err(MyError::InvalidCorridor("no positions".to_string()));
+        }
+        if distance < 0.0 || !distance.is_finite() {
+            return Err(MyError::InvalidCorridor(format!(
+                "the distance {} isn't a finite, non-negative number",
+                distance
+            )));
+        }
+        let mut min = [f64::INFINITY; 2];
+        let mut max = [f64::NEG_INFINITY; 2];
+        for [x, y, _] in &vertices {
+            min = [min[0].min(x - distance), min[1].min(y - distance)];
+            max = [max[0].max(x + distance), max[1].max(y + distance)];
+        }
+        Ok(Self {
+            vertices,
+            distance,
+            horizontal,
+            min,
+            max,
+        })
+    }
+
+    /// The vertices of the polyline.
+    pub fn vertices(&self) -> &[[f64; 3]] {
+        &self.vertices
+    }
+
+    /// The distance from the polyline within which points are kept.
+    pub fn distance(&self) -> f64 {
+        self.distance
+    }
+
+    /// Whether the distance is only measured in X and Y.
+    pub fn is_horizontal(&self) -> bool {
+        self.horizontal
+    }
+
+    /// Whether the position `(x, y, z)` is within the distance of the polyline.
+    pub fn contains(&self, x: f64, y: f64, z: f64) -> bool {
+        if x < self.min[0] || x > self.max[0] || y < self.min[1] || y > self.max[1] {
+            return false;
+        }
+        let limit = self.distance * self.distance;
+        let flatten = |[x, y, z]: [f64; 3]| [x, y, if self.horizontal { 0.0 } else { z }];
+        let position = flatten([x, y, z]);
+        if let [vertex] = self.vertices[..] {
+            return squared_distance(position, flatten(vertex)) <= limit;
+        }
+        self.vertices.windows(2).any(|segment| {
+            let (start, end) = (flatten(segment[0]), flatten(segment[1]));
+            squared_distance(position, closest_on_segment(position, start, end)) <= limit
+        })
+    }
+
+    /// The corridor with each of its vertices moved by `map` in X and Y, e.g. to project them to
+    /// the coordinate system of the points. Segments stay straight between the moved vertices.
+    pub fn map(&self, map: impl Fn(f64, f64) -> (f64, f64)) -> Result<Self, MyError> {
+        let vertices = self
+            .vertices
+            .iter()
+            .map(|&[x, y, z]| {
+                let (x, y) = map(x, y);
+                [x, y, z]
+            })
+            .collect();
+        Self::new(vertices, self.distance, self.horizontal)
+    }
+}
+
+/// The point of the segment from `start` to `end` closest to `position`.
+fn closest_on_segment(position: [f64; 3], start: [f64; 3], end: [f64; 3]) -> [f64; 3] {
+    let along = [end[0] - start[0], end[1] - start[1], end[2] - start[2]];
+    let length = along[0] * along[0] + along[1] * along[1] + along[2] * along[2];
+    if length == 0.0 {
+        return start;
+    }
+    let t = ((0..3)
+        .map(|i| (position[i] - start[i]) * along[i])
+        .sum::<f64>()
+        / length)
+        .clamp(0.0, 1.0);
+    [
+        start[0] + t * along[0],
+        start[1] + t * along[1],
+        start[2] + t * along[2],
+    ]
+}
+
+fn squared_distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corridor() {
+        let monument = Corridor::new(vec![[10.0, 10.0, 5.0]], 2.0, false).unwrap();
+        assert!(monument.contains(11.0, 11.0, 6.0));
+        assert!(!monument.contains(10.0, 10.0, 8.0));
+        let flat = Corridor::new(vec![[10.0, 10.0, 5.0]], 2.0, true).unwrap();
+        assert!(flat.contains(10.0, 10.0, 800.0));
+        assert!(!flat.contains(12.0, 12.0, 5.0));
+
+        // An L-shaped pipeline along the X axis then up the Y axis
+        let pipeline = Corridor::new(
+            vec![[0.0, 0.0, 0.0], [100.0, 0.0, 0.0], [100.0, 50.0, 0.0]],
+            1.0,
+            true,
+        )
+        .unwrap();
+        assert!(pipeline.contains(50.0, 0.9, 0.0));
+        assert!(pipeline.contains(100.5, 25.0, 0.0));
+        assert!(!pipeline.contains(50.0, 1.1, 0.0));
+        assert!(!pipeline.contains(-1.5, 0.0, 0.0));
+        assert!(!pipeline.contains(100.0, 51.5, 0.0));
+
+        assert!(matches!(
+            Corridor::new(Vec::new(), 1.0, false),
+            Err(MyError::InvalidCorridor(_))
+        ));
+    }
+}
@@ -0,0 +1,677 @@
+//! Reading and converting the coordinate system of LAS files.
+//!
+//! LAS files describe their coordinate system in `LASF_Projection` VLRs, either as GeoTIFF keys,
+//! which files up to LAS 1.3 must use, or as WKT, which LAS 1.4 requires of point formats 6 and
+//! above. Writing the points in another version or format can then call for the other
+//! representation, which [`fit_crs`] converts to.
+//!
+//! Any coordinate system with an EPSG code converts to GeoTIFF keys, which only need the code.
+//! WKT spells out the whole definition, and without a database of coordinate systems only the
+//! common ones in a small table convert to it: geographic WGS 84, NAD83, ETRS89, GDA94, GDA2020
+//! and NZGD2000, with their UTM, MGA and NZTM projections. Compound systems keep their vertical
+//! code as a key, but not in WKT.
+use crate::input::{is_stdin, open_reader};
+use crate::raster::GeoKeys;
+use las::{Builder, Header, Version, Vlr};
+use log::warn;
+use std::fmt;
+
+/// The user id of the VLRs describing the coordinate system.
+const USER_ID: &str = "LASF_Projection";
+
+/// The record id of the VLR holding the coordinate system as WKT.
+pub const WKT_RECORD_ID: u16 = 2112;
+
+/// The record ids of the VLRs holding the GeoTIFF keys: the directory, doubles and ASCII.
+const GEO_KEY_RECORD_IDS: [u16; 3] = [34735, 34736, 34737];
+
+/// The GeoTIFF key ids read and written.
+const MODEL_TYPE_KEY: u16 = 1024;
+const RASTER_TYPE_KEY: u16 = 1025;
+const CITATION_KEY: u16 = 1026;
+const GEOGRAPHIC_TYPE_KEY: u16 = 2048;
+const GEOGRAPHIC_CITATION_KEY: u16 = 2049;
+const PROJECTED_TYPE_KEY: u16 = 3072;
+const PROJECTED_CITATION_KEY: u16 = 3073;
+const LINEAR_UNITS_KEY: u16 = 3076;
+const VERTICAL_TYPE_KEY: u16 = 4096;
+
+/// The GeoTIFF code of a coordinate system defined by the user rather than by EPSG.
+const USER_DEFINED: u16 = 32767;
+
+/// Returns `true` if `vlr` holds the coordinate system as WKT.
+pub fn is_wkt_vlr(vlr: &Vlr) -> bool {
+    vlr.user_id == USER_ID && vlr.record_id == WKT_RECORD_ID
+}
+
+/// Returns `true` if `vlr` holds some of the GeoTIFF keys.
+pub fn is_geo_key_vlr(vlr: &Vlr) -> bool {
+    vlr.user_id == USER_ID && GEO_KEY_RECORD_IDS.contains(&vlr.record_id)
+}
+
+/// Whether a coordinate system is projected or geographic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrsKind {
+    Projected,
+    Geographic,
+}
+
+/// A coordinate system, as far as it can be told from the VLRs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Crs {
+    /// The name of the system, e.g. `WGS 84 / UTM zone 33N`.
+    pub name: String,
+    pub kind: CrsKind,
+    /// The EPSG code of the horizontal system, if it has one.
+    pub epsg: Option<u32>,
+    /// The EPSG code of the vertical system, if there is one and it has one.
+    pub vertical_epsg: Option<u32>,
+}
+
+impl Crs {
+    /// The coordinate system in the VLRs of `header`, if it has one that can be read.
+    pub fn from_header(header: &Header) -> Option<Self> {
+        Self::from_vlrs(header.all_vlrs())
+    }
+
+    /// The coordinate system of the first of `paths`, if it can be opened and has one. Stdin
+    /// isn't read, as its points would be lost.
+    pub fn from_inputs(paths: &[String]) -> Option<Self> {
+        let path = paths.first().filter(|path| !is_stdin(path))?;
+        Self::from_header(open_reader(path).ok()?.header())
+    }
+
+    /// The coordinate system in `vlrs`, from the WKT if there is any and from the GeoTIFF keys
+    /// otherwise.
+    pub fn from_vlrs<'a>(vlrs: impl IntoIterator<Item = &'a Vlr>) -> Option<Self> {
+        let vlrs: Vec<&Vlr> = vlrs.into_iter().collect();
+        match vlrs.iter().find(|vlr| is_wkt_vlr(vlr)) {
+            Some(vlr) => Self::from_wkt(String::from_utf8_lossy(&vlr.data).trim_end_matches('\0')),
+            None => Self::from_geo_keys(&GeoKeys::from_vlrs(vlrs)?),
+        }
+    }
+
+    /// The coordinate system described by `keys`, if they say whether it is projected or
+    /// geographic.
+    pub fn from_geo_keys(keys: &GeoKeys) -> Option<Self> {
+        let key = |id: u16| {
+            keys.directory
+                .get(4..)?
+                .chunks_exact(4)
+                .find(|entry| entry[0] == id)
+                .map(|entry| (entry[1], entry[2], entry[3]))
+        };
+        // Inline values are in the directory, the others in the ASCII params
+        let short = |id: u16| {
+            key(id)
+                .filter(|(location, ..)| *location == 0)
+                .map(|key| key.2)
+        };
+        let citation = |id: u16| {
+            let (_, count, offset) = key(id).filter(|key| key.0 == GEO_KEY_RECORD_IDS[2])?;
+            let text = keys
+                .ascii
+                .get(usize::from(offset)..usize::from(offset) + usize::from(count))?;
+            Some(text.trim_end_matches(['|', '\0']).to_string())
+        };
+        let (kind, type_key, citation_keys) = match short(MODEL_TYPE_KEY)? {
+            1 => (
+                CrsKind::Projected,
+                PROJECTED_TYPE_KEY,
+                [PROJECTED_CITATION_KEY, CITATION_KEY],
+            ),
+            2 => (
+                CrsKind::Geographic,
+                GEOGRAPHIC_TYPE_KEY,
+                [GEOGRAPHIC_CITATION_KEY, CITATION_KEY],
+            ),
+            _ => return None,
+        };
+        let code = |id: u16| {
+            short(id)
+                .filter(|&code| code != USER_DEFINED)
+                .map(u32::from)
+        };
+        let epsg = code(type_key);
+        let name = epsg
+            .and_then(name_of)
+            .or_else(|| citation_keys.into_iter().find_map(citation))
+            .or_else(|| epsg.map(|epsg| format!("EPSG:{}", epsg)))
+            .unwrap_or_else(|| "unnamed".to_string());
+        Some(Self {
+            name,
+            kind,
+            epsg,
+            vertical_epsg: code(VERTICAL_TYPE_KEY),
+        })
+    }
+
+    /// The coordinate system described by `wkt`, in WKT 1 or 2, if it is projected or
+    /// geographic, or a compound of one of those.
+    pub fn from_wkt(wkt: &str) -> Option<Self> {
+        let root = Parser::new(wkt).node()?;
+        let (horizontal, vertical) = match root.keyword.as_str() {
+            "COMPD_CS" | "COMPOUNDCRS" => (
+                root.children()
+                    .find(|node| kind_of(&node.keyword).is_some())?,
+                root.children().find(|node| {
+                    matches!(node.keyword.as_str(), "VERT_CS" | "VERTCRS" | "VERTICALCRS")
+                }),
+            ),
+            _ => (&root, None),
+        };
+        Some(Self {
+            name: root.name()?.to_string(),
+            kind: kind_of(&horizontal.keyword)?,
+            epsg: horizontal.epsg(),
+            vertical_epsg: vertical.and_then(Node::epsg),
+        })
+    }
+
+    /// The coordinate system as WKT 1, if it is one of the systems this module knows.
+    pub fn to_wkt(&self) -> Option<String> {
+        let epsg = self.epsg?;
+        match self.kind {
+            CrsKind::Geographic => geographic_wkt(epsg),
+            CrsKind::Projected => {
+                let projection = projection(epsg)?;
+                Some(format!(
+                    "PROJCS[\"{}\",{},PROJECTION[\"Transverse_Mercator\"],\
+                     PARAMETER[\"latitude_of_origin\",0],PARAMETER[\"central_meridian\",{}],\
+                     PARAMETER[\"scale_factor\",{}],PARAMETER[\"false_easting\",{}],\
+                     PARAMETER[\"false_northing\",{}],\
+                     UNIT[\"metre\",1,AUTHORITY[\"EPSG\",\"9001\"]],\
+                     AXIS[\"Easting\",EAST],AXIS[\"Northing\",NORTH],AUTHORITY[\"EPSG\",\"{}\"]]",
+                    projection.name,
+                    geographic_wkt(projection.base)?,
+                    projection.central_meridian,
+                    projection.scale_factor,
+                    projection.false_easting,
+                    projection.false_northing,
+                    epsg
+                ))
+            }
+        }
+    }
+
+    /// The coordinate system as GeoTIFF keys, if it has an EPSG code that fits in them.
+    pub fn to_geo_keys(&self) -> Option<GeoKeys> {
+        let code = u16::try_from(self.epsg?).ok()?;
+        let mut keys = match self.kind {
+            CrsKind::Projected => vec![
+                [MODEL_TYPE_KEY, 0, 1, 1],
+                [RASTER_TYPE_KEY, 0, 1, 1],
+                [PROJECTED_TYPE_KEY, 0, 1, code],
+                [LINEAR_UNITS_KEY, 0, 1, 9001],
+            ],
+            CrsKind::Geographic => vec![
+                [MODEL_TYPE_KEY, 0, 1, 2],
+                [RASTER_TYPE_KEY, 0, 1, 1],
+                [GEOGRAPHIC_TYPE_KEY, 0, 1, code],
+            ],
+        };
+        if let Some(vertical) = self.vertical_epsg.and_then(|code| u16::try_from(code).ok()) {
+            keys.push([VERTICAL_TYPE_KEY, 0, 1, vertical]);
+        }
+        let mut directory = vec![1, 1, 0, keys.len() as u16];
+        directory.extend(keys.into_iter().flatten());
+        Some(GeoKeys {
+            directory,
+            ..Default::default()
+        })
+    }
+}
+
+impl fmt::Display for Crs {
+    /// Writes the name with the EPSG codes, e.g. `WGS 84 / UTM zone 33N (EPSG:32633)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        match (self.epsg, self.vertical_epsg) {
+            (Some(epsg), Some(vertical)) => write!(f, " (EPSG:{} + EPSG:{})", epsg, vertical),
+            (Some(epsg), None) => write!(f, " (EPSG:{})", epsg),
+            (None, Some(vertical)) => write!(f, " (vertical EPSG:{})", vertical),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Rewrites the coordinate system in the VLRs of `builder` in the representation its version
+/// and point format call for: WKT for point formats 6 and above, and GeoTIFF keys before LAS 1.4,
+/// which has no WKT. A system that can't be converted is left as it is, with a warning.
+pub fn fit_crs(builder: &mut Builder) {
+    let has_wkt = builder.vlrs.iter().chain(&builder.evlrs).any(is_wkt_vlr);
+    let has_keys = builder
+        .vlrs
+        .iter()
+        .chain(&builder.evlrs)
+        .any(|vlr| is_geo_key_vlr(vlr) && vlr.record_id == GEO_KEY_RECORD_IDS[0]);
+    let crs = || Crs::from_vlrs(builder.vlrs.iter().chain(&builder.evlrs));
+    if builder.point_format.is_extended && has_keys && !has_wkt {
+        let Some(wkt) = crs().and_then(|crs| crs.to_wkt()) else {
+            warn!(
+                "The GeoTIFF keys of the CRS can't be converted to the WKT that point formats 6 \
+                 and above need, so they are kept"
+            );
+            return;
+        };
+        builder.vlrs.retain(|vlr| !is_geo_key_vlr(vlr));
+        builder.evlrs.retain(|vlr| !is_geo_key_vlr(vlr));
+        let mut data = wkt.into_bytes();
+        data.push(0);
+        builder.vlrs.push(Vlr {
+            user_id: USER_ID.to_string(),
+            record_id: WKT_RECORD_ID,
+            description: "OGC Coordinate System WKT".to_string(),
+            data,
+        });
+        builder.has_wkt_crs = true;
+    } else if builder.version < Version::new(1, 4) && has_wkt {
+        use_geo_keys(builder);
+    }
+}
+
+/// Replaces a coordinate system given as WKT in the VLRs of `builder` with GeoTIFF keys, which
+/// LAS versions before 1.4 and the software written for them need. A system that can't be
+/// converted is left as it is, with a warning.
+pub fn use_geo_keys(builder: &mut Builder) {
+    if !builder.vlrs.iter().chain(&builder.evlrs).any(is_wkt_vlr) {
+        return;
+    }
+    let crs = Crs::from_vlrs(builder.vlrs.iter().chain(&builder.evlrs));
+    let Some(keys) = crs.and_then(|crs| crs.to_geo_keys()) else {
+        warn!("The WKT of the CRS can't be converted to GeoTIFF keys, so it is kept");
+        return;
+    };
+    builder
+        .vlrs
+        .retain(|vlr| !is_wkt_vlr(vlr) && !is_geo_key_vlr(vlr));
+    builder
+        .evlrs
+        .retain(|vlr| !is_wkt_vlr(vlr) && !is_geo_key_vlr(vlr));
+    builder.vlrs.extend(keys.vlrs());
+    builder.has_wkt_crs = false;
+}
+
+/// Whether the WKT keyword `keyword` is a projected or geographic system.
+fn kind_of(keyword: &str) -> Option<CrsKind> {
+    match keyword {
+        "PROJCS" | "PROJCRS" | "PROJECTEDCRS" => Some(CrsKind::Projected),
+        "GEOGCS" | "GEOGCRS" | "GEODCRS" | "GEOGRAPHICCRS" | "GEODETICCRS" => {
+            Some(CrsKind::Geographic)
+        }
+        _ => None,
+    }
+}
+
+/// A geographic system this module knows, with its datum and ellipsoid. Every one of them has a
+/// semi-major axis of 6378137 m.
+struct Geographic {
+    code: u32,
+    name: &'static str,
+    datum: &'static str,
+    datum_code: u32,
+    /// The ellipsoid: WGS 84 or GRS 1980.
+    wgs84: bool,
+}
+
+const GEOGRAPHIC: [Geographic; 6] = [
+    Geographic {
+        code: 4326,
+        name: "WGS 84",
+        datum: "WGS_1984",
+        datum_code: 6326,
+        wgs84: true,
+    },
+    Geographic {
+        code: 4269,
+        name: "NAD83",
+        datum: "North_American_Datum_1983",
+        datum_code: 6269,
+        wgs84: false,
+    },
+    Geographic {
+        code: 4258,
+        name: "ETRS89",
+        datum: "European_Terrestrial_Reference_System_1989",
+        datum_code: 6258,
+        wgs84: false,
+    },
+    Geographic {
+        code: 4283,
+        name: "GDA94",
+        datum: "Geocentric_Datum_of_Australia_1994",
+        datum_code: 6283,
+        wgs84: false,
+    },
+    Geographic {
+        code: 7844,
+        name: "GDA2020",
+        datum: "Geocentric_Datum_of_Australia_2020",
+        datum_code: 1168,
+        wgs84: false,
+    },
+    Geographic {
+        code: 4167,
+        name: "NZGD2000",
+        datum: "New_Zealand_Geodetic_Datum_2000",
+        datum_code: 6167,
+        wgs84: false,
+    },
+];
+
+/// A transverse Mercator projection this module knows.
+pub(crate) struct Projection {
+    pub(crate) name: String,
+    /// The EPSG code of the geographic system it projects.
+    pub(crate) base: u32,
+    pub(crate) central_meridian: f64,
+    pub(crate) scale_factor: f64,
+    pub(crate) false_easting: f64,
+    pub(crate) false_northing: f64,
+}
+
+/// The projection of EPSG code `code`, if this module knows it.
+pub(crate) fn projection(code: u32) -> Option<Projection> {
+    let utm = |name: String, base: u32, zone: u32, south: bool| Projection {
+        name,
+        base,
+        central_meridian: -183.0 + 6.0 * f64::from(zone),
+        scale_factor: 0.9996,
+        false_easting: 500_000.0,
+        false_northing: if south { 10_000_000.0 } else { 0.0 },
+    };
+    Some(match code {
+        32601..=32660 => utm(
+            format!("WGS 84 / UTM zone {}N", code - 32600),
+            4326,
+            code - 32600,
+            false,
+        ),
+        32701..=32760 => utm(
+            format!("WGS 84 / UTM zone {}S", code - 32700),
+            4326,
+            code - 32700,
+            true,
+        ),
+        26901..=26923 => utm(
+            format!("NAD83 / UTM zone {}N", code - 26900),
+            4269,
+            code - 26900,
+            false,
+        ),
+        25828..=25838 => utm(
+            format!("ETRS89 / UTM zone {}N", code - 25800),
+            4258,
+            code - 25800,
+            false,
+        ),
+        28348..=28358 => utm(
+            format!("GDA94 / MGA zone {}", code - 28300),
+            4283,
+            code - 28300,
+            true,
+        ),
+        7846..=7859 => utm(
+            format!("GDA2020 / MGA zone {}", code - 7800),
+            7844,
+            code - 7800,
+            true,
+        ),
+        2193 => Projection {
+            name: "NZGD2000 / New Zealand Transverse Mercator 2000".to_string(),
+            base: 4167,
+            central_meridian: 173.0,
+            scale_factor: 0.9996,
+            false_easting: 1_600_000.0,
+            false_northing: 10_000_000.0,
+        },
+        _ => return None,
+    })
+}
+
+/// The name of the system with EPSG code `code`, if this module knows it.
+pub fn name_of(code: u32) -> Option<String> {
+    match GEOGRAPHIC.iter().find(|geographic| geographic.code == code) {
+        Some(geographic) => Some(geographic.name.to_string()),
+        None => projection(code).map(|projection| projection.name),
+    }
+}
+
+/// The inverse flattening of the ellipsoid of the geographic system with EPSG code `code`, if
+/// this module knows it.
+pub(crate) fn inverse_flattening(code: u32) -> Option<f64> {
+    let geographic = GEOGRAPHIC
+        .iter()
+        .find(|geographic| geographic.code == code)?;
+    Some(match geographic.wgs84 {
+        true => 298.257223563,
+        false => 298.257222101,
+    })
+}
+
+/// The WKT 1 of the geographic system with EPSG code `code`, if this module knows it.
+fn geographic_wkt(code: u32) -> Option<String> {
+    let geographic = GEOGRAPHIC
+        .iter()
+        .find(|geographic| geographic.code == code)?;
+    let (ellipsoid, inverse_flattening, ellipsoid_code) = match geographic.wgs84 {
+        true => ("WGS 84", "298.257223563", 7030),
+        false => ("GRS 1980", "298.257222101", 7019),
+    };
+    Some(format!(
+        "GEOGCS[\"{}\",DATUM[\"{}\",SPHEROID[\"{}\",6378137,{},AUTHORITY[\"EPSG\",\"{}\"]],\
+         AUTHORITY[\"EPSG\",\"{}\"]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",\"8901\"]],\
+         UNIT[\"degree\",0.0174532925199433,AUTHORITY[\"EPSG\",\"9122\"]],\
+         AUTHORITY[\"EPSG\",\"{}\"]]",
+        geographic.name,
+        geographic.datum,
+        ellipsoid,
+        inverse_flattening,
+        ellipsoid_code,
+        geographic.datum_code,
+        code
+    ))
+}
+
+/// An element of WKT: a keyword followed by its items in brackets.
+#[derive(Debug)]
+struct Node {
+    keyword: String,
+    items: Vec<Item>,
+}
+
+#[derive(Debug)]
+enum Item {
+    /// A quoted string.
+    Text(String),
+    /// A number or an unquoted word, such as an axis direction.
+    Word(String),
+    Node(Node),
+}
+
+impl Node {
+    /// The elements among the items.
+    fn children(&self) -> impl Iterator<Item = &Node> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Node(node) => Some(node),
+            _ => None,
+        })
+    }
+
+    /// The first quoted string, which is the name of the systems.
+    fn name(&self) -> Option<&str> {
+        self.items.iter().find_map(|item| match item {
+            Item::Text(text) => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The code of the last EPSG authority of the element itself, as WKT 1 `AUTHORITY` or
+    /// WKT 2 `ID`.
+    fn epsg(&self) -> Option<u32> {
+        self.children()
+            .filter(|node| matches!(node.keyword.as_str(), "AUTHORITY" | "ID"))
+            .filter(|node| {
+                node.name()
+                    .is_some_and(|name| name.eq_ignore_ascii_case("EPSG"))
+            })
+            .filter_map(|node| match node.items.get(1)? {
+                Item::Text(code) | Item::Word(code) => code.parse().ok(),
+                Item::Node(_) => None,
+            })
+            .last()
+    }
+}
+
+/// Reads WKT into [`Node`]s.
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(wkt: &str) -> Self {
+        Self {
+            chars: wkt.chars().collect(),
+            position: 0,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|c| c.is_whitespace())
+        {
+            self.position += 1;
+        }
+        self.chars.get(self.position).copied()
+    }
+
+    /// Reads an unquoted word.
+    fn word(&mut self) -> String {
+        self.peek();
+        let start = self.position;
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|&c| c.is_alphanumeric() || "_.+-".contains(c))
+        {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    /// Reads an element, if the WKT has one at the current position.
+    fn node(&mut self) -> Option<Node> {
+        let keyword = self.word();
+        if keyword.is_empty() {
+            return None;
+        }
+        self.items(keyword)
+    }
+
+    /// Reads the bracketed items of the element `keyword`.
+    fn items(&mut self, keyword: String) -> Option<Node> {
+        if !matches!(self.peek()?, '[' | '(') {
+            return None;
+        }
+        self.position += 1;
+        let mut items = Vec::new();
+        loop {
+            match self.peek()? {
+                '"' => {
+                    self.position += 1;
+                    let mut text = String::new();
+                    loop {
+                        let c = *self.chars.get(self.position)?;
+                        self.position += 1;
+                        match c {
+                            // A doubled quote is a quote in the text
+                            '"' if self.chars.get(self.position) == Some(&'"') => {
+                                self.position += 1;
+                                text.push('"');
+                            }
+                            '"' => break,
+                            c => text.push(c),
+                        }
+                    }
+                    items.push(Item::Text(text));
+                }
+                _ => {
+                    let word = self.word();
+                    if word.is_empty() {
+                        return None;
+                    }
+                    match self.peek()? {
+                        '[' | '(' => items.push(Item::Node(self.items(word)?)),
+                        _ => items.push(Item::Word(word)),
+                    }
+                }
+            }
+            match self.peek()? {
+                ',' => self.position += 1,
+                ']' | ')' => {
+                    self.position += 1;
+                    return Some(Node { keyword, items });
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::point::Format;
+
+    #[test]
+    fn test_crs_conversion() {
+        let utm = Crs {
+            name: "WGS 84 / UTM zone 33N".to_string(),
+            kind: CrsKind::Projected,
+            epsg: Some(32633),
+            vertical_epsg: None,
+        };
+        let keys = utm.to_geo_keys().unwrap();
+        assert_eq!(Crs::from_geo_keys(&keys), Some(utm.clone()));
+        let wkt = utm.to_wkt().unwrap();
+        assert!(wkt.contains("PARAMETER[\"central_meridian\",15]"));
+        assert_eq!(Crs::from_wkt(&wkt), Some(utm.clone()));
+        assert_eq!(utm.to_string(), "WGS 84 / UTM zone 33N (EPSG:32633)");
+
+        let compound = "COMPOUNDCRS[\"NZGD2000 / NZTM 2000 + NZVD2016 height\",\
+            PROJCRS[\"NZGD2000 / New Zealand Transverse Mercator 2000\",BASEGEOGCRS[\"NZGD2000\",\
+            ID[\"EPSG\",4167]],ID[\"EPSG\",2193]],VERTCRS[\"NZVD2016 height\",ID[\"EPSG\",7839]],\
+            ID[\"EPSG\",9194]]";
+        let crs = Crs::from_wkt(compound).unwrap();
+        assert_eq!(crs.name, "NZGD2000 / NZTM 2000 + NZVD2016 height");
+        assert_eq!(crs.kind, CrsKind::Projected);
+        assert_eq!((crs.epsg, crs.vertical_epsg), (Some(2193), Some(7839)));
+        assert!(crs.to_string().ends_with("(EPSG:2193 + EPSG:7839)"));
+        assert!(Crs::from_wkt("LOCAL_CS[\"site grid\"]").is_none());
+        assert!(Crs::from_wkt("PROJCS[\"broken\"").is_none());
+    }
+
+    #[test]
+    fn test_fit_crs() {
+        let mut builder = Builder::from((1, 4));
+        builder.vlrs = Crs::from_wkt(&geographic_wkt(4326).unwrap())
+            .and_then(|crs| crs.to_geo_keys())
+            .unwrap()
+            .vlrs();
+        builder.point_format = Format::new(6).unwrap();
+        fit_crs(&mut builder);
+        assert!(builder.has_wkt_crs);
+        assert_eq!(builder.vlrs.len(), 1);
+        assert!(is_wkt_vlr(&builder.vlrs[0]));
+
+        builder.version = Version::new(1, 2);
+        builder.point_format = Format::new(1).unwrap();
+        fit_crs(&mut builder);
+        assert!(!builder.has_wkt_crs);
+        let crs = Crs::from_vlrs(&builder.vlrs).unwrap();
+        assert_eq!((crs.name.as_str(), crs.epsg), ("WGS 84", Some(4326)));
+    }
+}
@@ -0,0 +1,392 @@
+//! Comparing two LAS/LAZ files point by point.
+//!
+//! The headers are compared on what describes the points: version, point format, point count,
+//! scales and offsets, and bounds. Points are compared in order, dimension by dimension, and two
+//! values count as different when they are further apart than the tolerance set for the dimension,
+//! which is 0 unless set with [`DiffOptions::with_tolerance`].
+use crate::errors::MyError;
+use crate::input::open_reader;
+use las::{Header, Point};
+
+/// A dimension of a point that can be compared, as a number.
+pub struct Dimension {
+    /// The name tolerances are set under.
+    pub name: &'static str,
+    /// The value of the dimension, `NaN` when the point format doesn't have it.
+    pub value: fn(&Point) -> f64,
+}
+
+/// The dimensions compared, in the order they are reported.
+pub const DIMENSIONS: &[Dimension] = &[
+    Dimension {
+        name: "x",
+        value: |point| point.x,
+    },
+    Dimension {
+        name: "y",
+        value: |point| point.y,
+    },
+    Dimension {
+        name: "z",
+        value: |point| point.z,
+    },
+    Dimension {
+        name: "intensity",
+        value: |point| f64::from(point.intensity),
+    },
+    Dimension {
+        name: "return_number",
+        value: |point| f64::from(point.return_number),
+    },
+    Dimension {
+        name: "number_of_returns",
+        value: |point| f64::from(point.number_of_returns),
+    },
+    Dimension {
+        name: "classification",
+        value: |point| f64::from(u8::from(point.classification)),
+    },
+    Dimension {
+        name: "scan_angle",
+        value: |point| f64::from(point.scan_angle),
+    },
+    Dimension {
+        name: "user_data",
+        value: |point| f64::from(point.user_data),
+    },
+    Dimension {
+        name: "point_source_id",
+        value: |point| f64::from(point.point_source_id),
+    },
+    Dimension {
+        name: "gps_time",
+        value: |point| point.gps_time.unwrap_or(f64::NAN),
+    },
+    Dimension {
+        name: "red",
+        value: |point| point.color.map_or(f64::NAN, |color| f64::from(color.red)),
+    },
+    Dimension {
+        name: "green",
+        value: |point| point.color.map_or(f64::NAN, |color| f64::from(color.green)),
+    },
+    Dimension {
+        name: "blue",
+        value: |point| point.color.map_or(f64::NAN, |color| f64::from(color.blue)),
+    },
+    Dimension {
+        name: "nir",
+        value: |point| point.nir.map_or(f64::NAN, f64::from),
+    },
+    Dimension {
+        name: "scanner_channel",
+        value: |point| f64::from(point.scanner_channel),
+    },
+    Dimension {
+        name: "synthetic",
+        value: |point| f64::from(u8::from(point.is_synthetic)),
+    },
+    Dimension {
+        name: "key_point",
+        value: |point| f64::from(u8::from(point.is_key_point)),
+    },
+    Dimension {
+        name: "withheld",
+        value: |point| f64::from(u8::from(point.is_withheld)),
+    },
+    Dimension {
+        name: "overlap",
+        value: |point| f64::from(u8::from(point.is_overlap)),
+    },
+    Dimension {
+        name: "scan_direction",
+        value: |point| f64::from(point.scan_direction as u8),
+    },
+    Dimension {
+        name: "edge_of_flight_line",
+        value: |point| f64::from(u8::from(point.is_edge_of_flight_line)),
+    },
+];
+
+/// How two files are compared.
+#[derive(Clone, Debug)]
+pub struct DiffOptions {
+    tolerances: Vec<(String, f64)>,
+    points_shown: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            tolerances: Vec::new(),
+            points_shown: 1,
+        }
+    }
+}
+
+impl DiffOptions {
+    /// Lets the values of the dimension `name`, one of [`DIMENSIONS`], differ by up to
+    /// `tolerance`. For `x`, `y` and `z` it applies to the header bounds too.
+    pub fn with_tolerance(mut self, name: &str, tolerance: f64) -> Self {
+        self.tolerances.retain(|(dimension, _)| dimension != name);
+        self.tolerances.push((name.to_string(), tolerance));
+        self
+    }
+
+    /// Sets how many of the differing points are described. Defaults to 1.
+    pub fn with_points_shown(mut self, points: usize) -> Self {
+        self.points_shown = points;
+        self
+    }
+
+    fn tolerance(&self, name: &str) -> f64 {
+        self.tolerances
+            .iter()
+            .find(|(dimension, _)| dimension == name)
+            .map_or(0.0, |(_, tolerance)| *tolerance)
+    }
+}
+
+/// Returns `true` if `a` and `b` are further apart than `tolerance`. Two missing values are equal.
+fn differs(a: f64, b: f64, tolerance: f64) -> bool {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => false,
+        (false, false) => (a - b).abs() > tolerance,
+        _ => true,
+    }
+}
+
+/// How the values of a dimension differ over all the points.
+#[derive(Clone, Copy, Default)]
+struct DimensionDelta {
+    points: u64,
+    max_delta: f64,
+}
+
+/// Compares the files at `first` and `second` and returns their differences, or nothing if they
+/// hold the same points in the same order within the tolerances. The first differing points are
+/// described, along with how many points differ in each dimension and by how much at most.
+pub fn diff(first: &str, second: &str, options: &DiffOptions) -> Result<Vec<String>, MyError> {
+    if let Some((name, _)) = options
+        .tolerances
+        .iter()
+        .find(|(name, _)| !DIMENSIONS.iter().any(|dimension| dimension.name == name))
+    {
+        return Err(MyError::UnknownDimension(name.clone()));
+    }
+    let mut first_reader = open_reader(first)?;
+    let mut second_reader = open_reader(second)?;
+    let mut differences = diff_headers(first_reader.header(), second_reader.header(), options)?;
+
+    let mut deltas = vec![DimensionDelta::default(); DIMENSIONS.len()];
+    let mut differing = 0u64;
+    let points = first_reader.points().zip(second_reader.points());
+    for (index, (first_point, second_point)) in points.enumerate() {
+        let (first_point, second_point) = (first_point?, second_point?);
+        if first_point == second_point {
+            continue;
+        }
+        let mut described = Vec::new();
+        for (dimension, delta) in DIMENSIONS.iter().zip(&mut deltas) {
+            let (a, b) = (
+                (dimension.value)(&first_point),
+                (dimension.value)(&second_point),
+            );
+            if !differs(a, b, options.tolerance(dimension.name)) {
+                continue;
+            }
+            delta.points += 1;
+            delta.max_delta = delta.max_delta.max((a - b).abs());
+            described.push(format!("{} {} and {}", dimension.name, a, b));
+        }
+        if first_point.extra_bytes != second_point.extra_bytes {
+            described.push("extra bytes".to_string());
+        }
+        if described.is_empty() {
+            continue;
+        }
+        if differing < options.points_shown as u64 {
+            differences.push(format!("point {} differs: {}", index, described.join(", ")));
+        }
+        differing += 1;
+    }
+    if differing > 0 {
+        differences.push(format!("{} points differ in all", differing));
+    }
+    for (dimension, delta) in DIMENSIONS.iter().zip(&deltas) {
+        if delta.points == 0 {
+            continue;
+        }
+        let tolerance = options.tolerance(dimension.name);
+        let mut line = format!("{}: {} points differ", dimension.name, delta.points);
+        if tolerance > 0.0 {
+            line.push_str(&format!(" by more than {}", tolerance));
+        }
+        if delta.max_delta.is_finite() {
+            line.push_str(&format!(", by up to {}", delta.max_delta));
+        }
+        differences.push(line);
+    }
+    Ok(differences)
+}
+
+/// Compares what the headers say about the points.
+fn diff_headers(
+    first: &Header,
+    second: &Header,
+    options: &DiffOptions,
+) -> Result<Vec<String>, MyError> {
+    let mut differences = Vec::new();
+    if first.version() != second.version() {
+        differences.push(format!(
+            "versions differ: {} and {}",
+            first.version(),
+            second.version()
+        ));
+    }
+    let formats = (
+        first.point_format().to_u8()?,
+        second.point_format().to_u8()?,
+    );
+    if formats.0 != formats.1 {
+        differences.push(format!(
+            "point formats differ: {} and {}",
+            formats.0, formats.1
+        ));
+    }
+    if first.point_format().extra_bytes != second.point_format().extra_bytes {
+        differences.push(format!(
+            "extra bytes per point differ: {} and {}",
+            first.point_format().extra_bytes,
+            second.point_format().extra_bytes
+        ));
+    }
+    if first.number_of_points() != second.number_of_points() {
+        differences.push(format!(
+            "point counts differ: {} and {}",
+            first.number_of_points(),
+            second.number_of_points()
+        ));
+    }
+    let (first_transforms, second_transforms) = (first.transforms(), second.transforms());
+    let axes = [
+        ("x", first_transforms.x, second_transforms.x),
+        ("y", first_transforms.y, second_transforms.y),
+        ("z", first_transforms.z, second_transforms.z),
+    ];
+    for (axis, first_transform, second_transform) in axes {
+        if first_transform != second_transform {
+            differences.push(format!(
+                "{} scale and offset differ: {} {} and {} {}",
+                axis,
+                first_transform.scale,
+                first_transform.offset,
+                second_transform.scale,
+                second_transform.offset
+            ));
+        }
+    }
+    let (first_bounds, second_bounds) = (first.bounds(), second.bounds());
+    let bounds = [
+        (
+            "x",
+            first_bounds.min.x,
+            first_bounds.max.x,
+            second_bounds.min.x,
+            second_bounds.max.x,
+        ),
+        (
+            "y",
+            first_bounds.min.y,
+            first_bounds.max.y,
+            second_bounds.min.y,
+            second_bounds.max.y,
+        ),
+        (
+            "z",
+            first_bounds.min.z,
+            first_bounds.max.z,
+            second_bounds.min.z,
+            second_bounds.max.z,
+        ),
+    ];
+    for (axis, first_min, first_max, second_min, second_max) in bounds {
+        let tolerance = options.tolerance(axis);
+        if differs(first_min, second_min, tolerance) || differs(first_max, second_max, tolerance) {
+            differences.push(format!(
+                "{} bounds differ: {} to {} and {} to {}",
+                axis, first_min, first_max, second_min, second_max
+            ));
+        }
+    }
+    Ok(differences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::{Builder, Writer};
+
+    fn write_points(path: &std::path::Path, points: &[(f64, u16)]) {
+        let header = Builder::from((1, 4)).into_header().unwrap();
+        let mut writer = Writer::from_path(path, header).unwrap();
+        for &(z, intensity) in points {
+            let point = Point {
+                z,
+                intensity,
+                ..Default::default()
+            };
+            writer.write_point(point).unwrap();
+        }
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn test_diff() {
+        let input = "tests/data/input1.las";
+        let options = DiffOptions::default();
+        assert!(diff(input, input, &options).unwrap().is_empty());
+
+        let differences = diff(input, "tests/data/input2.las", &options).unwrap();
+        assert!(!differences.is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = (dir.path().join("first.las"), dir.path().join("second.las"));
+        write_points(&first, &[(1.0, 10), (2.0, 20), (3.0, 30)]);
+        write_points(&second, &[(1.0, 10), (2.005, 20), (3.5, 31)]);
+        let (first, second) = (first.to_str().unwrap(), second.to_str().unwrap());
+
+        let differences = diff(first, second, &options).unwrap();
+        assert_eq!(
+            differences,
+            [
+                "z bounds differ: 1 to 3 and 1 to 3.5",
+                "point 1 differs: z 2 and 2.005",
+                "2 points differ in all",
+                "z: 2 points differ, by up to 0.5",
+                "intensity: 1 points differ, by up to 1",
+            ]
+        );
+
+        let options = DiffOptions::default()
+            .with_tolerance("z", 0.01)
+            .with_tolerance("intensity", 1.0)
+            .with_points_shown(5);
+        let differences = diff(first, second, &options).unwrap();
+        assert_eq!(
+            differences,
+            [
+                "z bounds differ: 1 to 3 and 1 to 3.5",
+                "point 2 differs: z 3 and 3.5",
+                "1 points differ in all",
+                "z: 1 points differ by more than 0.01, by up to 0.5",
+            ]
+        );
+
+        let options = DiffOptions::default().with_tolerance("height", 1.0);
+        assert!(matches!(
+            diff(first, second, &options),
+            Err(MyError::UnknownDimension(_))
+        ));
+    }
+}
@@ -7,6 +7,8 @@ pub enum MyError {
     ReadError(#[from] las::Error),
     #[error("failed to read from reader: {0}")]
     InputOutputError(#[from] std::io::Error),
+    #[error("LAZ compression failed: {0}")]
+    LazError(#[from] laz::LasZipError),
     #[error("failed to lock mutex.")]
     LockError,
     #[error("An error occurred in a thread.")]
@@ -21,6 +23,146 @@ pub enum MyError {
     InvalidFilterFunction,
     #[error("Output paths number must match the number of filter arguments")]
     MismatchedFiltersAndOutputs,
+    #[error("Output file {0} already exists. Use --force to overwrite it.")]
+    OutputExists(String),
+    #[error("Output file {0} is also one of the inputs.")]
+    OutputOverlapsInput(String),
+    #[error("Output file {0} is written by more than one output.")]
+    DuplicateOutput(String),
+    #[error("Can't append to {0}: {1}.")]
+    IncompatibleAppend(String, String),
+    #[error("{0} can't count more than {1} points, and can't be spread over several files.")]
+    TooManyPoints(String, u64),
+    #[error("The header counts {0} points, more than the data can hold.")]
+    PointDataOutOfRange(u64),
+    #[error("{0} needs the `{1}` feature to be enabled.")]
+    FeatureNotEnabled(String, &'static str),
+    #[error("{0} can't be used with the rayon backend, whose threads write the points they read themselves.")]
+    NotForBackend(String),
+    #[error("Invalid remote URL: {0}")]
+    InvalidRemoteUrl(String),
+    #[cfg(feature = "object-store")]
+    #[error("object store request failed: {0}")]
+    ObjectStoreError(#[from] object_store::Error),
+    #[cfg(feature = "watch")]
+    #[error("failed to watch directory: {0}")]
+    WatchError(#[from] notify::Error),
+    #[error("Output path {0} must contain a {{stem}} placeholder to be processed per input.")]
+    OutputTemplateRequired(String),
+    #[error("failed to open {path}: {source}")]
+    OpenError { path: String, source: Box<MyError> },
+    #[error("failed to read point {point_index} of {path}: {source}")]
+    PointReadError {
+        path: String,
+        point_index: u64,
+        source: las::Error,
+    },
+    #[error(
+        "more than {limit} corrupt points in {path}, the last one at point {point_index}: {source}"
+    )]
+    TooManyCorruptPoints {
+        path: String,
+        point_index: u64,
+        limit: u64,
+        source: las::Error,
+    },
+    #[error("failed to write {path}: {source}")]
+    WriteError { path: String, source: Box<MyError> },
+    #[error("{} input file(s) could not be read and were skipped: {}", .0.len(), .0.join(", "))]
+    PartialFailure(Vec<String>),
+    #[error("Output path {0} must contain {{x}} and {{y}} placeholders to name the tiles.")]
+    TileTemplateRequired(String),
+    #[error(
+        "Output path {0} must contain a {{name}} placeholder to name the outputs of the features."
+    )]
+    FeatureTemplateRequired(String),
+    #[error("Output path {0} must contain a {{line}} placeholder to name the outputs of the flight lines.")]
+    LineTemplateRequired(String),
+    #[error("{} input file(s) failed validation: {}", .0.len(), .0.join(", "))]
+    InvalidFiles(Vec<String>),
+    #[error(
+        "The outputs in {path} may need up to {needed} bytes but only {available} bytes are free. Leave out --space-check to run anyway."
+    )]
+    InsufficientSpace {
+        path: String,
+        needed: u64,
+        available: u64,
+    },
+    #[error("verification failed: {0}")]
+    VerificationFailed(String),
+    #[error("{0} can't be repaired: {1}")]
+    CannotRepair(String, String),
+    #[error("A raster of {0} by {1} cells is too large to write. Use a larger cell size.")]
+    RasterTooLarge(u64, u64),
+    #[error("Raster output {0} must have a .tif or .tiff extension.")]
+    InvalidRasterExtension(String),
+    #[error("Canopy metrics output {0} must have a .tif, .tiff or .csv extension.")]
+    InvalidCanopyExtension(String),
+    #[error("Converting GPS week time to standard GPS time needs the GPS week of the inputs.")]
+    GpsWeekRequired,
+    #[error("{0} has no GPS times to merge by.")]
+    NoGpsTime(String),
+    #[error("{0} isn't sorted by GPS time, point {1} is earlier than the one before it. Merging by GPS time needs every input sorted.")]
+    UnsortedInput(String, u64),
+    #[error("{0} has GPS times of another type than the first input, week or standard time, so they can't be merged by time.")]
+    GpsTimeTypeMismatch(String),
+    #[error(
+        "Can't tag the points of {0} inputs with point source IDs. Use the extra byte instead."
+    )]
+    TooManySources(usize),
+    #[error("--assign-source-id was given for {0}, which isn't one of the inputs.")]
+    UnmatchedSourceId(String),
+    #[error("--adjust has an adjustment for {0}, which isn't one of the inputs.")]
+    UnmatchedAdjustment(String),
+    #[error(
+        "{0} has full-waveform points, whose waveform data can't be copied. Use --drop-waveforms to write them without it."
+    )]
+    WaveformsNotSupported(String),
+    #[error("Invalid neighbourhood filter: {0}")]
+    InvalidNeighborhood(String),
+    #[error("Invalid profile: {0}")]
+    InvalidProfile(String),
+    #[error("Point format {0} can't be written: {1}")]
+    InvalidPointFormat(u8, String),
+    #[error("The {0} `{1}` is longer than the 32 bytes a LAS header holds.")]
+    HeaderFieldTooLong(String, String),
+    #[error("{0} has a {1} record {2} that differs from the one of an earlier input. Use --merge-vlrs first or last to keep one of them.")]
+    VlrConflict(String, String, u16),
+    #[error("{0} describes its extra bytes differently from the first input, so its records can't be merged.")]
+    ExtraBytesConflict(String),
+    #[error("EPSG:{0} isn't one of the coordinate systems filters can be projected from or to.")]
+    UnsupportedCrs(u32),
+    #[error("The points are in {0}, which has no EPSG code to project the filters to. Give one with --data-crs.")]
+    UnknownDataCrs(String),
+    #[error("The inputs don't say which coordinate system they are in, so the filters can't be projected to it. Give it with --data-crs.")]
+    DataCrsRequired,
+    #[error("{0} and {1} differ")]
+    FilesDiffer(String, String),
+    #[error("Unknown dimension {0}.")]
+    UnknownDimension(String),
+    #[error("Invalid pipeline file: {0}")]
+    InvalidPipeline(String),
+    #[error("Invalid job {0}: {1}")]
+    InvalidJob(String, String),
+    #[error("{} of {1} job(s) failed: {}", .0.len(), .0.join(", "))]
+    FailedJobs(Vec<String>, usize),
+    #[error("Unknown preset {0}. Use --list-presets to see the available presets.")]
+    UnknownPreset(String),
+    #[error("Invalid preset {0}: {1}")]
+    InvalidPreset(String, String),
+    #[error("Invalid class palette: {0}")]
+    InvalidPalette(String),
+    #[error("Invalid clip polygons: {0}")]
+    InvalidPolygon(String),
+    #[error("Invalid distance filter: {0}")]
+    InvalidCorridor(String),
+    #[error("Invalid adjustments: {0}")]
+    InvalidAdjustments(String),
+    #[error("failed to set up logging: {0}")]
+    LoggerError(#[from] log::SetLoggerError),
+    #[cfg(feature = "zip")]
+    #[error("failed to read from zip archive: {0}")]
+    ZipError(#[from] zip::result::ZipError),
 }
 
 impl Debug for MyError {
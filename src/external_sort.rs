@@ -0,0 +1,390 @@
+//! Sorting more points than fit in memory.
+//!
+//! An [`ExternalSort`] holds the points it is given up to the buffer of its [`SpillConfig`], then
+//! sorts them and writes them out as a run in a temporary folder, as raw point records or, when
+//! the config says so, as LAZ. Once every point is in, the runs are merged: the next point of
+//! every run is kept and the first by the order is passed on, so only a point per run and a batch
+//! are held however many points there are. At most [`MAX_FAN_IN`] runs are merged at once, so
+//! with more runs than that, groups of them are first merged into longer runs, in passes, to
+//! bound the files held open. Points in no run are sorted in memory.
+//!
+//! The order is any [`PointOrder`]: the [`SortKey`]s of pipeline sorts and
+//! [`LasProcessor::with_sort`](crate::LasProcessor::with_sort) make one, and callers can bring
+//! their own to [`Pipeline::sort_by`](crate::Pipeline::sort_by). Points the order finds equal come
+//! out in the order they were added.
+//!
+//! [`SortKey`]: crate::SortKey
+use crate::errors::MyError;
+use crate::spill::SpillConfig;
+use las::point::Format;
+use las::{Builder, Header, Point, Reader, Transform, Vector, Writer};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// The most runs merged at once.
+pub const MAX_FAN_IN: usize = 64;
+
+/// How two points compare in a sort.
+pub type PointOrder = Arc<dyn Fn(&Point, &Point) -> Ordering + Send + Sync>;
+
+/// The memory a point takes while it is held.
+pub(crate) fn held_size(point: &Point) -> usize {
+    std::mem::size_of::<Point>() + point.extra_bytes.len()
+}
+
+/// Points being sorted, spilled as sorted runs when they take more than the buffer.
+pub struct ExternalSort {
+    order: PointOrder,
+    config: SpillConfig,
+    /// The format the runs are written in, uncompressed.
+    format: Format,
+    transforms: Vector<Transform>,
+    held: Vec<Point>,
+    held_bytes: usize,
+    /// The folder of the runs, made with the first one.
+    dir: Option<TempDir>,
+    runs: Vec<PathBuf>,
+    /// The number of runs written so far, merged or not, which names the next one.
+    written: usize,
+    /// The most runs merged at once.
+    fan_in: usize,
+}
+
+impl ExternalSort {
+    /// A sort by `order` of points in the format of `header`, which is also what they are
+    /// spilled in.
+    pub fn new(order: PointOrder, header: &Header, config: SpillConfig) -> Self {
+        let mut format = *header.point_format();
+        format.is_compressed = false;
+        Self {
+            order,
+            config,
+            format,
+            transforms: *header.transforms(),
+            held: Vec::new(),
+            held_bytes: 0,
+            dir: None,
+            runs: Vec::new(),
+            written: 0,
+            fan_in: MAX_FAN_IN,
+        }
+    }
+
+    /// The number of runs spilled so far.
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Takes the points out of `points`, spilling a run once the buffer is full.
+    pub fn add(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        self.held_bytes += points.iter().map(held_size).sum::<usize>();
+        self.held.append(points);
+        if self.held_bytes > self.config.buffer_bytes() {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sorts the held points and writes them out as a run.
+    fn spill(&mut self) -> Result<(), MyError> {
+        let order = Arc::clone(&self.order);
+        self.held.sort_by(|a, b| order(a, b));
+        let path = self.run_path()?;
+        let mut run = RunWriter::create(
+            &path,
+            self.config.compressed_runs(),
+            &self.format,
+            &self.transforms,
+        )?;
+        for point in std::mem::take(&mut self.held) {
+            run.write(point, &self.format, &self.transforms)?;
+        }
+        run.finish()?;
+        self.runs.push(path);
+        self.held_bytes = 0;
+        Ok(())
+    }
+
+    /// The path of a new run, in the folder of the runs, which is made with the first one.
+    fn run_path(&mut self) -> Result<PathBuf, MyError> {
+        let dir = match self.dir.take() {
+            Some(dir) => dir,
+            None => self.config.tempdir()?,
+        };
+        let extension = if self.config.compressed_runs() {
+            "laz"
+        } else {
+            "bin"
+        };
+        let path = dir
+            .path()
+            .join(format!("run_{}.{}", self.written, extension));
+        self.dir = Some(dir);
+        self.written += 1;
+        Ok(path)
+    }
+
+    /// Hands every point to `write` in order, in batches that fit in the buffer.
+    pub fn finish(
+        mut self,
+        write: &mut dyn FnMut(Vec<Point>) -> Result<(), MyError>,
+    ) -> Result<(), MyError> {
+        if self.runs.is_empty() {
+            let order = Arc::clone(&self.order);
+            self.held.sort_by(|a, b| order(a, b));
+            return write(std::mem::take(&mut self.held));
+        }
+        if !self.held.is_empty() {
+            self.spill()?;
+        }
+        while self.runs.len() > self.fan_in {
+            self.merge_pass()?;
+        }
+        let buffer_bytes = self.config.buffer_bytes();
+        let mut batch = Vec::new();
+        let mut batch_bytes = 0;
+        self.merge_runs(&self.runs, &mut |point| {
+            batch_bytes += held_size(&point);
+            batch.push(point);
+            if batch_bytes > buffer_bytes {
+                write(std::mem::take(&mut batch))?;
+                batch_bytes = 0;
+            }
+            Ok(())
+        })?;
+        if !batch.is_empty() {
+            write(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Merges the runs in groups of the fan-in, each into a run of its own, in the order of the
+    /// runs so that equal points keep the order they were added in.
+    fn merge_pass(&mut self) -> Result<(), MyError> {
+        let runs = std::mem::take(&mut self.runs);
+        for group in runs.chunks(self.fan_in) {
+            if let [run] = group {
+                self.runs.push(run.clone());
+                continue;
+            }
+            let path = self.run_path()?;
+            let mut merged = RunWriter::create(
+                &path,
+                self.config.compressed_runs(),
+                &self.format,
+                &self.transforms,
+            )?;
+            self.merge_runs(group, &mut |point| {
+                merged.write(point, &self.format, &self.transforms)
+            })?;
+            merged.finish()?;
+            for run in group {
+                fs::remove_file(run)?;
+            }
+            self.runs.push(path);
+        }
+        Ok(())
+    }
+
+    /// Hands every point of the runs at `paths` to `write`, in order.
+    fn merge_runs(
+        &self,
+        paths: &[PathBuf],
+        write: &mut dyn FnMut(Point) -> Result<(), MyError>,
+    ) -> Result<(), MyError> {
+        let mut runs = Vec::with_capacity(paths.len());
+        let mut heads = BinaryHeap::with_capacity(paths.len());
+        for (index, path) in paths.iter().enumerate() {
+            let mut run = Run::open(path, self.config.compressed_runs())?;
+            if let Some(point) = run.next(&self.format, &self.transforms)? {
+                heads.push(Head {
+                    order: Arc::clone(&self.order),
+                    index,
+                    point,
+                });
+            }
+            runs.push(run);
+        }
+        while let Some(head) = heads.pop() {
+            let Head {
+                order,
+                index,
+                point,
+            } = head;
+            if let Some(next) = runs[index].next(&self.format, &self.transforms)? {
+                heads.push(Head {
+                    order,
+                    index,
+                    point: next,
+                });
+            }
+            write(point)?;
+        }
+        Ok(())
+    }
+}
+
+/// A run being written.
+enum RunWriter {
+    Raw(BufWriter<File>),
+    Laz(Box<Writer<BufWriter<File>>>),
+}
+
+impl RunWriter {
+    fn create(
+        path: &Path,
+        compressed: bool,
+        format: &Format,
+        transforms: &Vector<Transform>,
+    ) -> Result<Self, MyError> {
+        Ok(if compressed {
+            let mut builder = Builder::from((1, 4));
+            builder.point_format = *format;
+            builder.point_format.is_compressed = true;
+            builder.transforms = *transforms;
+            RunWriter::Laz(Box::new(Writer::from_path(path, builder.into_header()?)?))
+        } else {
+            RunWriter::Raw(BufWriter::new(File::create(path)?))
+        })
+    }
+
+    fn write(
+        &mut self,
+        point: Point,
+        format: &Format,
+        transforms: &Vector<Transform>,
+    ) -> Result<(), MyError> {
+        match self {
+            RunWriter::Raw(file) => point.into_raw(transforms)?.write_to(file, format)?,
+            RunWriter::Laz(writer) => writer.write_point(point)?,
+        }
+        Ok(())
+    }
+
+    /// Writes out what is left of the run.
+    fn finish(self) -> Result<(), MyError> {
+        match self {
+            RunWriter::Raw(mut file) => file.flush()?,
+            RunWriter::Laz(mut writer) => writer.close()?,
+        }
+        Ok(())
+    }
+}
+
+/// A run being merged.
+enum Run {
+    Raw(BufReader<File>),
+    Laz(Box<Reader>),
+}
+
+impl Run {
+    fn open(path: &Path, compressed: bool) -> Result<Self, MyError> {
+        Ok(if compressed {
+            Run::Laz(Box::new(Reader::from_path(path)?))
+        } else {
+            Run::Raw(BufReader::new(File::open(path)?))
+        })
+    }
+
+    /// The next point of the run, or `None` at its end.
+    fn next(
+        &mut self,
+        format: &Format,
+        transforms: &Vector<Transform>,
+    ) -> Result<Option<Point>, MyError> {
+        match self {
+            Run::Raw(file) => {
+                if file.fill_buf()?.is_empty() {
+                    return Ok(None);
+                }
+                let raw = las::raw::Point::read_from(file, format)?;
+                Ok(Some(Point::new(raw, transforms)))
+            }
+            Run::Laz(reader) => Ok(reader.read_point()?),
+        }
+    }
+}
+
+/// The next point of a run, ordered so that the first by the order is the greatest, then by run
+/// so that equal points keep the order they were added in.
+struct Head {
+    order: PointOrder,
+    index: usize,
+    point: Point,
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.order)(&other.point, &self.point).then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort_runs(config: SpillConfig, fan_in: usize) {
+        let order: PointOrder = Arc::new(|a: &Point, b: &Point| a.z.total_cmp(&b.z));
+        let mut builder = Builder::from((1, 4));
+        builder.point_format = Format::new(1).unwrap();
+        let header = builder.into_header().unwrap();
+        let mut sort = ExternalSort::new(order, &header, config);
+        sort.fan_in = fan_in;
+        let mut expected = Vec::new();
+        for batch in 0..5 {
+            let mut points: Vec<Point> = (0..7)
+                .map(|i| Point {
+                    z: f64::from((i * 13 + batch * 5) % 31),
+                    gps_time: Some(f64::from(batch * 7 + i)),
+                    ..Default::default()
+                })
+                .collect();
+            expected.extend(points.iter().map(|point| (point.z, point.gps_time)));
+            sort.add(&mut points).unwrap();
+            assert!(points.is_empty());
+        }
+        assert!(sort.runs() > 1);
+
+        let mut sorted = Vec::new();
+        sort.finish(&mut |points| {
+            sorted.extend(points.iter().map(|point| (point.z, point.gps_time)));
+            Ok(())
+        })
+        .unwrap();
+        // A stable sort, like the merge
+        expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn test_external_sort() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SpillConfig::new()
+            .with_dir(dir.path())
+            .with_buffer_bytes(4 * std::mem::size_of::<Point>());
+        sort_runs(config.clone(), MAX_FAN_IN);
+        // Five runs merged two at a time, in passes
+        sort_runs(config.clone(), 2);
+        sort_runs(config.with_compressed_runs(true), 2);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,149 @@
+//! Describing the extra bytes of point records, so that other software can read them.
+//!
+//! The extra bytes follow the standard fields of each point record. LAS 1.4 describes them with an
+//! extra bytes VLR holding a descriptor of 192 bytes per attribute, in the order the attributes
+//! are stored in. Extra bytes without a VLR are described as undocumented when attributes are
+//! added after them.
+use las::{Builder, Vlr};
+
+/// The user id of the extra bytes VLR.
+pub const USER_ID: &str = "LASF_Spec";
+
+/// The record id of the extra bytes VLR.
+pub const RECORD_ID: u16 = 4;
+
+/// The length of the descriptor of an attribute.
+const DESCRIPTOR_LEN: usize = 192;
+
+/// The types of attribute written here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttributeType {
+    /// A little-endian `u32`.
+    UnsignedLong,
+    /// A little-endian `f64`.
+    Double,
+}
+
+impl AttributeType {
+    /// The data type code in the descriptor.
+    fn code(self) -> u8 {
+        match self {
+            AttributeType::UnsignedLong => 5,
+            AttributeType::Double => 10,
+        }
+    }
+
+    /// The number of bytes the attribute takes in each point.
+    pub fn size(self) -> u16 {
+        match self {
+            AttributeType::UnsignedLong => 4,
+            AttributeType::Double => 8,
+        }
+    }
+}
+
+/// An attribute stored in the extra bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Attribute<'a> {
+    /// The name, up to 32 bytes.
+    pub name: &'a str,
+    pub attribute_type: AttributeType,
+    /// What the attribute holds, up to 32 bytes.
+    pub description: &'a str,
+}
+
+impl Attribute<'_> {
+    fn descriptor(&self) -> [u8; DESCRIPTOR_LEN] {
+        let mut descriptor = [0u8; DESCRIPTOR_LEN];
+        descriptor[2] = self.attribute_type.code();
+        copy_truncated(&mut descriptor[4..36], self.name);
+        copy_truncated(&mut descriptor[160..192], self.description);
+        descriptor
+    }
+}
+
+fn copy_truncated(field: &mut [u8], text: &str) {
+    let len = text.len().min(field.len());
+    field[..len].copy_from_slice(&text.as_bytes()[..len]);
+}
+
+/// Returns `true` if `vlr` is an extra bytes VLR.
+pub fn is_extra_bytes_vlr(vlr: &Vlr) -> bool {
+    vlr.user_id == USER_ID && vlr.record_id == RECORD_ID
+}
+
+/// Makes `attributes` the only extra bytes of the points of `builder`.
+pub fn replace_attributes(builder: &mut Builder, attributes: &[Attribute]) {
+    builder.vlrs.retain(|vlr| !is_extra_bytes_vlr(vlr));
+    builder.evlrs.retain(|vlr| !is_extra_bytes_vlr(vlr));
+    builder.point_format.extra_bytes = 0;
+    append_attributes(builder, attributes);
+}
+
+/// Adds `attributes` after the extra bytes the points of `builder` already have.
+pub fn append_attributes(builder: &mut Builder, attributes: &[Attribute]) {
+    let existing = builder
+        .vlrs
+        .iter_mut()
+        .chain(builder.evlrs.iter_mut())
+        .find(|vlr| is_extra_bytes_vlr(vlr));
+    let vlr = match existing {
+        Some(vlr) => vlr,
+        None => {
+            let mut data = Vec::new();
+            if builder.point_format.extra_bytes > 0 {
+                // Undocumented extra bytes, with their number in the options
+                let mut descriptor = [0u8; DESCRIPTOR_LEN];
+                descriptor[3] = builder.point_format.extra_bytes.min(255) as u8;
+                data.extend_from_slice(&descriptor);
+            }
+            builder.vlrs.push(Vlr {
+                user_id: USER_ID.to_string(),
+                record_id: RECORD_ID,
+                description: "Extra bytes".to_string(),
+                data,
+            });
+            builder.vlrs.last_mut().expect("pushed above")
+        }
+    };
+    for attribute in attributes {
+        vlr.data.extend_from_slice(&attribute.descriptor());
+        builder.point_format.extra_bytes += attribute.attribute_type.size();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extra_bytes() {
+        let source = Attribute {
+            name: "source",
+            attribute_type: AttributeType::UnsignedLong,
+            description: "Index of the input file",
+        };
+        let mut builder = Builder::from((1, 4));
+        builder.point_format.extra_bytes = 3;
+        append_attributes(&mut builder, &[source]);
+        assert_eq!(builder.point_format.extra_bytes, 7);
+        let vlr = builder.vlrs.iter().find(|vlr| is_extra_bytes_vlr(vlr));
+        let data = &vlr.unwrap().data;
+        // The undocumented bytes, then the attribute
+        assert_eq!(data.len(), 384);
+        assert_eq!((data[2], data[3]), (0, 3));
+        assert_eq!(data[192 + 2], 5);
+        assert_eq!(&data[192 + 4..192 + 10], b"source");
+
+        replace_attributes(&mut builder, &[source]);
+        assert_eq!(builder.point_format.extra_bytes, 4);
+        let vlrs: Vec<_> = builder
+            .vlrs
+            .iter()
+            .filter(|vlr| is_extra_bytes_vlr(vlr))
+            .collect();
+        assert_eq!(vlrs.len(), 1);
+        assert_eq!(vlrs[0].data.len(), 192);
+        builder.into_header().unwrap();
+    }
+}
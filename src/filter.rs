@@ -0,0 +1,572 @@
+//! Conditions deciding which points go to each output, and lazy access to the fields they test.
+//!
+//! A [`Condition`] either looks at a fully decoded `las::Point`, or at a [`PointView`] after
+//! declaring the [`Dimensions`] it needs. When every condition of a run uses a view, uncompressed
+//! local inputs are read as raw records and only the fields the conditions ask for are decoded;
+//! the rest of a point is only decoded when the point is kept.
+//!
+//! The built-in [`NumericFilter`]s go further: on raw records they are evaluated a block of
+//! records at a time by the kernels in [`crate::simd`], while closures are called point by point.
+use crate::corridor::Corridor;
+use crate::polygon::Polygons;
+use crate::SharedFunction;
+use las::point::ScanDirection;
+use las::{Bounds, Header, Point};
+use std::ops::{BitOr, Not, RangeInclusive};
+use std::sync::Arc;
+
+/// A closure deciding whether a point is kept, given a view of it.
+pub type ViewFunction = Arc<dyn Fn(&PointView) -> bool + Send + Sync>;
+
+/// The fields of a point a condition reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dimensions(u8);
+
+impl Dimensions {
+    /// The coordinates.
+    pub const XYZ: Dimensions = Dimensions(1);
+    /// The intensity.
+    pub const INTENSITY: Dimensions = Dimensions(1 << 1);
+    /// The classification code.
+    pub const CLASSIFICATION: Dimensions = Dimensions(1 << 2);
+    /// The return number and number of returns.
+    pub const RETURNS: Dimensions = Dimensions(1 << 3);
+    /// The scanner channel.
+    pub const SCANNER_CHANNEL: Dimensions = Dimensions(1 << 4);
+    /// The scan direction and edge of flight line flags.
+    pub const SCAN_FLAGS: Dimensions = Dimensions(1 << 5);
+    /// Every field, which means decoding the whole point.
+    pub const ALL: Dimensions = Dimensions(u8::MAX);
+
+    /// Returns `true` if all of `other` is part of these dimensions.
+    pub fn contains(self, other: Dimensions) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Dimensions {
+    type Output = Dimensions;
+
+    fn bitor(self, other: Dimensions) -> Dimensions {
+        Dimensions(self.0 | other.0)
+    }
+}
+
+/// Read access to the fields of a point, decoding them from the raw record only when asked for.
+pub struct PointView<'a> {
+    inner: ViewInner<'a>,
+}
+
+enum ViewInner<'a> {
+    Record {
+        record: &'a [u8],
+        header: &'a Header,
+        point: Option<Point>,
+    },
+    Point(Point),
+}
+
+impl<'a> PointView<'a> {
+    /// A view of a point record in the format of `header`, as stored in a LAS file. `record`
+    /// must hold a whole record.
+    pub fn from_record(record: &'a [u8], header: &'a Header) -> Self {
+        Self {
+            inner: ViewInner::Record {
+                record,
+                header,
+                point: None,
+            },
+        }
+    }
+
+    /// A view of a point that has been decoded already.
+    pub fn from_point(point: Point) -> Self {
+        Self {
+            inner: ViewInner::Point(point),
+        }
+    }
+
+    /// The x coordinate.
+    pub fn x(&self) -> f64 {
+        match &self.inner {
+            ViewInner::Record { record, header, .. } => {
+                header.transforms().x.direct(read_i32(record, 0))
+            }
+            ViewInner::Point(point) => point.x,
+        }
+    }
+
+    /// The y coordinate.
+    pub fn y(&self) -> f64 {
+        match &self.inner {
+            ViewInner::Record { record, header, .. } => {
+                header.transforms().y.direct(read_i32(record, 4))
+            }
+            ViewInner::Point(point) => point.y,
+        }
+    }
+
+    /// The z coordinate.
+    pub fn z(&self) -> f64 {
+        match &self.inner {
+            ViewInner::Record { record, header, .. } => {
+                header.transforms().z.direct(read_i32(record, 8))
+            }
+            ViewInner::Point(point) => point.z,
+        }
+    }
+
+    /// The intensity.
+    pub fn intensity(&self) -> u16 {
+        match &self.inner {
+            ViewInner::Record { record, .. } => u16::from_le_bytes([record[12], record[13]]),
+            ViewInner::Point(point) => point.intensity,
+        }
+    }
+
+    /// The ASPRS classification code.
+    pub fn classification(&self) -> u8 {
+        match &self.inner {
+            ViewInner::Record { record, header, .. } if header.point_format().is_extended => {
+                record[16]
+            }
+            ViewInner::Record { record, .. } => record[15] & 0x1f,
+            ViewInner::Point(point) => point.classification.into(),
+        }
+    }
+
+    /// The return number of the point within its pulse.
+    pub fn return_number(&self) -> u8 {
+        match &self.inner {
+            ViewInner::Record { record, header, .. } if header.point_format().is_extended => {
+                record[14] & 0x0f
+            }
+            ViewInner::Record { record, .. } => record[14] & 0x07,
+            ViewInner::Point(point) => point.return_number,
+        }
+    }
+
+    /// The number of returns of the pulse.
+    pub fn number_of_returns(&self) -> u8 {
+        match &self.inner {
+            ViewInner::Record { record, header, .. } if header.point_format().is_extended => {
+                record[14] >> 4
+            }
+            ViewInner::Record { record, .. } => (record[14] >> 3) & 0x07,
+            ViewInner::Point(point) => point.number_of_returns,
+        }
+    }
+
+    /// The channel of the scanner that recorded the point, which is always 0 in the formats
+    /// before LAS 1.4.
+    pub fn scanner_channel(&self) -> u8 {
+        match &self.inner {
+            ViewInner::Record { record, header, .. } if header.point_format().is_extended => {
+                (record[15] >> 4) & 0x03
+            }
+            ViewInner::Record { .. } => 0,
+            ViewInner::Point(point) => point.scanner_channel,
+        }
+    }
+
+    /// The direction the scanner mirror was moving in.
+    pub fn scan_direction(&self) -> ScanDirection {
+        match &self.inner {
+            ViewInner::Record { .. } if self.scan_flags() & 0x40 != 0 => ScanDirection::LeftToRight,
+            ViewInner::Record { .. } => ScanDirection::RightToLeft,
+            ViewInner::Point(point) => point.scan_direction,
+        }
+    }
+
+    /// Returns `true` if the point is the last one of a scan line before the mirror turns.
+    pub fn is_edge_of_flight_line(&self) -> bool {
+        match &self.inner {
+            ViewInner::Record { .. } => self.scan_flags() & 0x80 != 0,
+            ViewInner::Point(point) => point.is_edge_of_flight_line,
+        }
+    }
+
+    /// The byte of a record holding the scan direction and edge of flight line flags in its top
+    /// two bits.
+    fn scan_flags(&self) -> u8 {
+        match &self.inner {
+            ViewInner::Record { record, header, .. } => record[scan_flags_offset(header)],
+            ViewInner::Point(_) => 0,
+        }
+    }
+
+    /// The whole point, decoded the first time it is asked for.
+    pub fn point(&mut self) -> Result<&Point, las::Error> {
+        match &mut self.inner {
+            ViewInner::Record {
+                record,
+                header,
+                point,
+            } => {
+                if point.is_none() {
+                    *point = Some(decode(record, header)?);
+                }
+                Ok(point.as_ref().expect("decoded above"))
+            }
+            ViewInner::Point(point) => Ok(point),
+        }
+    }
+
+    /// Turns the view into the whole point, decoding it if needed.
+    pub fn into_point(self) -> Result<Point, las::Error> {
+        match self.inner {
+            ViewInner::Record {
+                point: Some(point), ..
+            } => Ok(point),
+            ViewInner::Record { record, header, .. } => decode(record, header),
+            ViewInner::Point(point) => Ok(point),
+        }
+    }
+}
+
+/// The offset of the byte holding the scan direction and edge of flight line flags in the records
+/// of `header`.
+pub(crate) fn scan_flags_offset(header: &Header) -> usize {
+    if header.point_format().is_extended {
+        15
+    } else {
+        14
+    }
+}
+
+fn read_i32(record: &[u8], offset: usize) -> i32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&record[offset..offset + 4]);
+    i32::from_le_bytes(bytes)
+}
+
+fn decode(record: &[u8], header: &Header) -> Result<Point, las::Error> {
+    let raw = las::raw::Point::read_from(record, header.point_format())?;
+    Ok(Point::new(raw, header.transforms()))
+}
+
+/// A set of classification codes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClassMask([u64; 4]);
+
+impl ClassMask {
+    /// A mask holding `classes`.
+    pub fn new(classes: &[u8]) -> Self {
+        let mut mask = ClassMask::default();
+        for &class in classes {
+            mask.0[usize::from(class >> 6)] |= 1 << (class & 63);
+        }
+        mask
+    }
+
+    /// Returns `true` if `class` is in the mask.
+    #[inline(always)]
+    pub fn contains(&self, class: u8) -> bool {
+        (self.0[usize::from(class >> 6)] >> (class & 63)) & 1 == 1
+    }
+
+    /// The classes in either mask.
+    pub fn union(&self, other: &ClassMask) -> Self {
+        ClassMask(std::array::from_fn(|i| self.0[i] | other.0[i]))
+    }
+
+    /// The classes in both masks.
+    pub fn intersection(&self, other: &ClassMask) -> Self {
+        ClassMask(std::array::from_fn(|i| self.0[i] & other.0[i]))
+    }
+}
+
+/// Which returns of each pulse are kept. Points without a return number count as the first and
+/// last return of a single return pulse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReturnSelection {
+    /// The first return of each pulse.
+    First,
+    /// The last return of each pulse.
+    Last,
+    /// The points of pulses with a single return.
+    Single,
+}
+
+impl ReturnSelection {
+    /// Returns `true` if the return `return_number` of `number_of_returns` is kept.
+    #[inline(always)]
+    pub fn contains(self, return_number: u8, number_of_returns: u8) -> bool {
+        match self {
+            ReturnSelection::First => return_number <= 1,
+            ReturnSelection::Last => return_number >= number_of_returns,
+            ReturnSelection::Single => number_of_returns <= 1,
+        }
+    }
+}
+
+/// A built-in test on the numeric fields of a point. Bounds are inclusive.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NumericFilter {
+    /// Keeps the points inside a box.
+    Bounds(Bounds),
+    /// Keeps the points with an intensity in the range.
+    Intensity(RangeInclusive<u16>),
+    /// Keeps the points with one of the classification codes.
+    Classes(ClassMask),
+    /// Keeps the selected returns of each pulse.
+    Returns(ReturnSelection),
+    /// Keeps the points recorded by a channel of a multi-channel scanner.
+    ScannerChannel(u8),
+    /// Keeps the points whose edge of flight line flag is set, or with `false` the points where
+    /// it isn't.
+    EdgeOfFlightLine(bool),
+    /// Keeps the points scanned in a direction.
+    ScanDirection(ScanDirection),
+    /// Keeps the points inside the polygons, or with `false` the points outside them.
+    Polygons(Arc<Polygons>, bool),
+    /// Keeps the points within the distance of a position or polyline.
+    Corridor(Arc<Corridor>),
+}
+
+impl NumericFilter {
+    /// The fields the filter reads.
+    pub fn dimensions(&self) -> Dimensions {
+        match self {
+            NumericFilter::Bounds(_) | NumericFilter::Polygons(..) | NumericFilter::Corridor(_) => {
+                Dimensions::XYZ
+            }
+            NumericFilter::Intensity(_) => Dimensions::INTENSITY,
+            NumericFilter::Classes(_) => Dimensions::CLASSIFICATION,
+            NumericFilter::Returns(_) => Dimensions::RETURNS,
+            NumericFilter::ScannerChannel(_) => Dimensions::SCANNER_CHANNEL,
+            NumericFilter::EdgeOfFlightLine(_) | NumericFilter::ScanDirection(_) => {
+                Dimensions::SCAN_FLAGS
+            }
+        }
+    }
+
+    /// Tests a single point.
+    pub fn matches(&self, view: &PointView) -> bool {
+        match self {
+            NumericFilter::Bounds(bounds) => {
+                let (x, y, z) = (view.x(), view.y(), view.z());
+                bounds.min.x <= x
+                    && x <= bounds.max.x
+                    && bounds.min.y <= y
+                    && y <= bounds.max.y
+                    && bounds.min.z <= z
+                    && z <= bounds.max.z
+            }
+            NumericFilter::Intensity(range) => range.contains(&view.intensity()),
+            NumericFilter::Classes(mask) => mask.contains(view.classification()),
+            NumericFilter::Returns(selection) => {
+                selection.contains(view.return_number(), view.number_of_returns())
+            }
+            NumericFilter::ScannerChannel(channel) => view.scanner_channel() == *channel,
+            NumericFilter::EdgeOfFlightLine(edge) => view.is_edge_of_flight_line() == *edge,
+            NumericFilter::ScanDirection(direction) => view.scan_direction() == *direction,
+            NumericFilter::Polygons(polygons, inside) => {
+                polygons.contains(view.x(), view.y()) == *inside
+            }
+            NumericFilter::Corridor(corridor) => corridor.contains(view.x(), view.y(), view.z()),
+        }
+    }
+}
+
+/// Decides whether a point goes to an output.
+#[derive(Clone)]
+pub struct Condition {
+    dimensions: Dimensions,
+    test: Test,
+}
+
+#[derive(Clone)]
+enum Test {
+    Point(SharedFunction),
+    View(ViewFunction),
+    Numeric(NumericFilter),
+}
+
+impl Condition {
+    /// A condition on the whole decoded point.
+    pub fn on_point(function: SharedFunction) -> Self {
+        Self {
+            dimensions: Dimensions::ALL,
+            test: Test::Point(function),
+        }
+    }
+
+    /// A condition that only reads `dimensions` of the point through a [`PointView`], so the
+    /// other fields don't need decoding for the points it rejects.
+    pub fn on_view(dimensions: Dimensions, function: ViewFunction) -> Self {
+        Self {
+            dimensions,
+            test: Test::View(function),
+        }
+    }
+
+    /// A built-in condition, which on raw records is evaluated for a block of records at once.
+    pub fn numeric(filter: NumericFilter) -> Self {
+        Self {
+            dimensions: filter.dimensions(),
+            test: Test::Numeric(filter),
+        }
+    }
+
+    /// A condition passing the points that pass all of `filters`, or `None` if there are none.
+    pub fn all_numeric(filters: &[NumericFilter]) -> Option<Self> {
+        match filters {
+            [] => None,
+            [filter] => Some(Condition::numeric(filter.clone())),
+            filters => {
+                let dimensions = filters
+                    .iter()
+                    .map(NumericFilter::dimensions)
+                    .fold(filters[0].dimensions(), |all, dimensions| all | dimensions);
+                let filters = filters.to_vec();
+                Some(Condition::on_view(
+                    dimensions,
+                    Arc::new(move |view| filters.iter().all(|filter| filter.matches(view))),
+                ))
+            }
+        }
+    }
+
+    /// The built-in filter of the condition, if it is one.
+    pub(crate) fn numeric_filter(&self) -> Option<&NumericFilter> {
+        match &self.test {
+            Test::Numeric(filter) => Some(filter),
+            _ => None,
+        }
+    }
+
+    /// The fields the condition reads.
+    pub fn dimensions(&self) -> Dimensions {
+        self.dimensions
+    }
+
+    /// Tests the point seen through `view`, decoding it first if the condition needs the whole
+    /// point.
+    pub fn matches(&self, view: &mut PointView) -> Result<bool, las::Error> {
+        match &self.test {
+            Test::Point(function) => Ok(function(view.point()?)),
+            Test::View(function) => Ok(function(view)),
+            Test::Numeric(filter) => Ok(filter.matches(view)),
+        }
+    }
+}
+
+impl Not for Condition {
+    type Output = Condition;
+
+    /// A condition passing the points this one rejects, reading the same fields.
+    fn not(self) -> Condition {
+        let dimensions = self.dimensions;
+        match self.test {
+            Test::Point(function) => Condition::on_point(Arc::new(move |point| !function(point))),
+            Test::View(function) => {
+                Condition::on_view(dimensions, Arc::new(move |view| !function(view)))
+            }
+            Test::Numeric(filter) => {
+                Condition::on_view(dimensions, Arc::new(move |view| !filter.matches(view)))
+            }
+        }
+    }
+}
+
+impl From<SharedFunction> for Condition {
+    fn from(function: SharedFunction) -> Self {
+        Condition::on_point(function)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::point::Classification;
+    use las::Builder;
+
+    #[test]
+    fn test_point_view() {
+        for format in [1, 6] {
+            let mut builder = Builder::from((1, 4));
+            builder.point_format = las::point::Format::new(format).unwrap();
+            let header = builder.into_header().unwrap();
+            let point = Point {
+                x: 1.5,
+                y: -2.25,
+                z: 300.0,
+                intensity: 1234,
+                classification: Classification::Ground,
+                return_number: 2,
+                number_of_returns: 3,
+                scanner_channel: if format == 6 { 2 } else { 0 },
+                scan_direction: ScanDirection::LeftToRight,
+                is_edge_of_flight_line: format == 6,
+                gps_time: Some(42.0),
+                ..Default::default()
+            };
+            let mut record = Vec::new();
+            point
+                .clone()
+                .into_raw(header.transforms())
+                .unwrap()
+                .write_to(&mut record, header.point_format())
+                .unwrap();
+
+            let mut view = PointView::from_record(&record, &header);
+            assert_eq!(view.x(), 1.5);
+            assert_eq!(view.y(), -2.25);
+            assert_eq!(view.z(), 300.0);
+            assert_eq!(view.intensity(), 1234);
+            assert_eq!(view.classification(), 2);
+            assert_eq!(view.return_number(), 2);
+            assert_eq!(view.number_of_returns(), 3);
+            assert_eq!(view.scanner_channel(), if format == 6 { 2 } else { 0 });
+            assert_eq!(view.scan_direction(), ScanDirection::LeftToRight);
+            assert_eq!(view.is_edge_of_flight_line(), format == 6);
+            assert_eq!(view.point().unwrap(), &point);
+            assert_eq!(view.into_point().unwrap(), point);
+        }
+
+        let condition = Condition::on_view(
+            Dimensions::XYZ | Dimensions::INTENSITY,
+            Arc::new(|view| view.intensity() > 100),
+        );
+        assert!(condition.dimensions().contains(Dimensions::INTENSITY));
+        assert!(!condition.dimensions().contains(Dimensions::CLASSIFICATION));
+        let mut view = PointView::from_point(Point {
+            intensity: 200,
+            ..Default::default()
+        });
+        assert!(condition.matches(&mut view).unwrap());
+
+        let mask = ClassMask::new(&[2, 6, 200]);
+        assert!(mask.contains(2) && mask.contains(6) && mask.contains(200));
+        assert!(!mask.contains(3) && !mask.contains(66));
+        let condition = Condition::numeric(NumericFilter::Classes(mask));
+        assert_eq!(condition.dimensions(), Dimensions::CLASSIFICATION);
+        assert!(!condition.matches(&mut view).unwrap());
+
+        let condition = Condition::all_numeric(&[
+            NumericFilter::Intensity(100..=300),
+            NumericFilter::Classes(ClassMask::new(&[0]).union(&ClassMask::new(&[2]))),
+        ])
+        .unwrap();
+        assert_eq!(
+            condition.dimensions(),
+            Dimensions::INTENSITY | Dimensions::CLASSIFICATION
+        );
+        assert!(condition.matches(&mut view).unwrap());
+        assert!(Condition::all_numeric(&[]).is_none());
+
+        let returns = |return_number, number_of_returns| {
+            [
+                ReturnSelection::First,
+                ReturnSelection::Last,
+                ReturnSelection::Single,
+            ]
+            .map(|selection| selection.contains(return_number, number_of_returns))
+        };
+        assert_eq!(returns(1, 3), [true, false, false]);
+        assert_eq!(returns(3, 3), [false, true, false]);
+        assert_eq!(returns(1, 1), [true, true, true]);
+        assert_eq!(returns(0, 0), [true, true, true]);
+    }
+}
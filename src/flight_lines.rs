@@ -0,0 +1,152 @@
+//! Finding the flight lines of a survey from the gaps in the GPS times of its points.
+//!
+//! Vendors don't always fill in the point source IDs, which should tell the flight lines apart.
+//! The scanner is off while the aircraft turns between lines, so a line ends wherever no point
+//! was recorded for longer than a gap. A [`FlightLineDetector`] finds those gaps in the GPS times
+//! it is given in any order, keeping only the earliest and latest time of each span of a gap's
+//! length, and hands over the [`FlightLines`] found. Points without a GPS time aren't on any line.
+use crate::filter::Condition;
+use las::Point;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The placeholder in an output path that stands for the number of a flight line.
+pub const LINE_PLACEHOLDER: &str = "{line}";
+
+/// Fills in the `{line}` placeholder of an output path template with the number of line `line`,
+/// counted from 1.
+pub fn render_line_path(template: &str, line: usize) -> String {
+    template.replace(LINE_PLACEHOLDER, &(line + 1).to_string())
+}
+
+/// Finds the flight lines in the GPS times added to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlightLineDetector {
+    gap: f64,
+    /// The earliest and latest time in each span of `gap` seconds, by span.
+    spans: BTreeMap<i64, (f64, f64)>,
+}
+
+impl FlightLineDetector {
+    /// A detector starting a new line after more than `gap` seconds without a point.
+    pub fn new(gap: f64) -> Self {
+        Self {
+            gap,
+            spans: BTreeMap::new(),
+        }
+    }
+
+    /// Records the GPS time of `point`, if it has one.
+    pub fn add(&mut self, point: &Point) {
+        if let Some(time) = point.gps_time.filter(|time| time.is_finite()) {
+            let span = (time / self.gap).floor() as i64;
+            let (first, last) = self.spans.entry(span).or_insert((time, time));
+            *first = first.min(time);
+            *last = last.max(time);
+        }
+    }
+
+    /// The flight lines of the times added. No span holds a gap longer than its length, so the
+    /// gaps are all between spans.
+    pub fn lines(&self) -> FlightLines {
+        let mut starts = Vec::new();
+        let mut previous_last: Option<f64> = None;
+        for &(first, last) in self.spans.values() {
+            if previous_last.is_none_or(|previous| first - previous > self.gap) {
+                starts.push(first);
+            }
+            previous_last = Some(last);
+        }
+        FlightLines { starts }
+    }
+}
+
+/// The flight lines of a survey, by the time each one starts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FlightLines {
+    starts: Vec<f64>,
+}
+
+impl FlightLines {
+    /// The flight lines starting at `starts`, in order.
+    pub fn new(starts: Vec<f64>) -> Self {
+        Self { starts }
+    }
+
+    /// The number of lines.
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Whether there are no lines, because no point had a GPS time.
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// The times the lines start at, in order.
+    pub fn starts(&self) -> &[f64] {
+        &self.starts
+    }
+
+    /// The index of the line the GPS time `time` is on, the last line starting at or before it.
+    /// Times before the first line are put on it.
+    pub fn line_of(&self, time: f64) -> usize {
+        self.starts
+            .partition_point(|&start| start <= time)
+            .saturating_sub(1)
+    }
+
+    /// The point source ID of the line of `point`, its index counted from 1 and capped at the
+    /// largest ID, or `None` if the point has no GPS time.
+    pub fn source_id_of(&self, point: &Point) -> Option<u16> {
+        point
+            .gps_time
+            .map(|time| u16::try_from(self.line_of(time) + 1).unwrap_or(u16::MAX))
+    }
+
+    /// A condition keeping the points of line `line`.
+    pub fn condition(&self, line: usize) -> Condition {
+        let lines = Arc::new(self.clone());
+        Condition::on_point(Arc::new(move |point| {
+            point
+                .gps_time
+                .is_some_and(|time| lines.line_of(time) == line)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::PointView;
+
+    #[test]
+    fn test_flight_lines() {
+        let mut detector = FlightLineDetector::new(10.0);
+        // Two lines, the first one with a gap shorter than the threshold, added out of order
+        for time in [112.0, 100.0, 105.0, 119.9, 140.0, 141.0, 135.0] {
+            detector.add(&Point {
+                gps_time: Some(time),
+                ..Default::default()
+            });
+        }
+        detector.add(&Point::default());
+        let lines = detector.lines();
+        assert_eq!(lines.starts(), [100.0, 135.0]);
+        assert_eq!(lines.line_of(119.9), 0);
+        assert_eq!(lines.line_of(135.0), 1);
+        assert_eq!(lines.line_of(0.0), 0);
+
+        let point = Point {
+            gps_time: Some(140.0),
+            ..Default::default()
+        };
+        assert_eq!(lines.source_id_of(&point), Some(2));
+        assert_eq!(lines.source_id_of(&Point::default()), None);
+        let mut view = PointView::from_point(point);
+        assert!(lines.condition(1).matches(&mut view).unwrap());
+        assert!(!lines.condition(0).matches(&mut view).unwrap());
+        assert!(FlightLineDetector::new(1.0).lines().is_empty());
+        assert_eq!(render_line_path("line_{line}.laz", 1), "line_2.laz");
+    }
+}
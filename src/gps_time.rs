@@ -0,0 +1,116 @@
+//! Converting GPS times between the two representations LAS files use.
+//!
+//! The global encoding of a LAS header says whether the GPS time of the points is GPS week time,
+//! the seconds since the start of the GPS week, or adjusted standard GPS time, the seconds since
+//! the GPS epoch minus 10⁹. Files from different surveys mix the two, so merging them gives
+//! timestamps that can't be compared. [`GpsTimeConversion`] brings every input to one of them,
+//! and can shift the times by a constant as well.
+//!
+//! Week time doesn't say which week it is in, so converting it to standard time needs the week
+//! number. Converting standard time to week time drops the week.
+use crate::errors::MyError;
+use las::GpsTimeType;
+
+/// The seconds in a GPS week.
+pub const SECONDS_PER_WEEK: f64 = 604_800.0;
+
+/// What is taken from standard GPS time to make it adjusted standard GPS time.
+pub const STANDARD_TIME_ADJUSTMENT: f64 = 1e9;
+
+/// Converts GPS times to one representation and adds an offset to them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GpsTimeConversion {
+    target: GpsTimeType,
+    week: Option<u32>,
+    offset: f64,
+}
+
+impl GpsTimeConversion {
+    /// A conversion to `target`, which leaves the times already in it as they are.
+    pub fn new(target: GpsTimeType) -> Self {
+        Self {
+            target,
+            week: None,
+            offset: 0.0,
+        }
+    }
+
+    /// Sets the GPS week of inputs in week time, needed to convert them to standard time.
+    pub fn with_week(mut self, week: u32) -> Self {
+        self.week = Some(week);
+        self
+    }
+
+    /// Sets the seconds added to every time once it is converted.
+    pub fn with_offset(mut self, offset: f64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// The representation the times are converted to.
+    pub fn target(&self) -> GpsTimeType {
+        self.target
+    }
+
+    /// Checks that the times of a file in the `source` representation can be converted.
+    pub fn check(&self, source: GpsTimeType) -> Result<(), MyError> {
+        if source == GpsTimeType::Week
+            && self.target == GpsTimeType::Standard
+            && self.week.is_none()
+        {
+            return Err(MyError::GpsWeekRequired);
+        }
+        Ok(())
+    }
+
+    /// Converts `time` from the `source` representation. Times in week time are taken to be in
+    /// week 0 if no week was set, which [`GpsTimeConversion::check`] catches.
+    pub fn convert(&self, time: f64, source: GpsTimeType) -> f64 {
+        let converted = match (source, self.target) {
+            (GpsTimeType::Week, GpsTimeType::Standard) => {
+                f64::from(self.week.unwrap_or(0)) * SECONDS_PER_WEEK + time
+                    - STANDARD_TIME_ADJUSTMENT
+            }
+            (GpsTimeType::Standard, GpsTimeType::Week) => {
+                (time + STANDARD_TIME_ADJUSTMENT).rem_euclid(SECONDS_PER_WEEK)
+            }
+            _ => time,
+        };
+        converted + self.offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gps_time_conversion() {
+        // Tuesday 12:00 in GPS week 2000
+        let week_time = 2.0 * 86_400.0 + 43_200.0;
+        let standard_time = 2000.0 * SECONDS_PER_WEEK + week_time - STANDARD_TIME_ADJUSTMENT;
+
+        let to_standard = GpsTimeConversion::new(GpsTimeType::Standard).with_week(2000);
+        assert!(to_standard.check(GpsTimeType::Week).is_ok());
+        assert_eq!(
+            to_standard.convert(week_time, GpsTimeType::Week),
+            standard_time
+        );
+        assert_eq!(
+            to_standard.convert(standard_time, GpsTimeType::Standard),
+            standard_time
+        );
+        assert!(matches!(
+            GpsTimeConversion::new(GpsTimeType::Standard).check(GpsTimeType::Week),
+            Err(MyError::GpsWeekRequired)
+        ));
+
+        let to_week = GpsTimeConversion::new(GpsTimeType::Week).with_offset(-0.5);
+        assert!(to_week.check(GpsTimeType::Standard).is_ok());
+        assert!(
+            (to_week.convert(standard_time, GpsTimeType::Standard) - (week_time - 0.5)).abs()
+                < 1e-6
+        );
+        assert_eq!(to_week.convert(10.0, GpsTimeType::Week), 9.5);
+    }
+}
@@ -0,0 +1,224 @@
+//! Rasters gridded from the points of an output while they stream past.
+//!
+//! A [`RasterGrid`] reduces the points falling in each cell of a grid to what its raster needs,
+//! and [`RasterOutput`] is a sink doing so for an output of a
+//! [`LasProcessor`](crate::LasProcessor) and writing the raster once every point has been
+//! received. [`CellGrid`] keeps a count or a mean per cell: the point density, for checking that
+//! a survey meets its density specification, or the mean intensity, a cheap stand-in for an
+//! orthoimage. The canopy metrics and the elevation models are grids of their own.
+use crate::errors::MyError;
+use crate::raster::{cell_of, is_csv_path, GeoKeys, Raster, NODATA};
+use crate::sink::PointSink;
+use las::Point;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Points reduced to the cells of a grid they fall in, to be turned into a raster.
+pub trait RasterGrid: Send {
+    /// Adds `points` to the cells they fall in.
+    fn add(&mut self, points: &[Point]);
+
+    /// The raster of the grid, covering every cell holding points.
+    fn to_raster(&self) -> Result<Raster, MyError>;
+}
+
+/// What a [`CellGrid`] writes for each cell.
+#[derive(Clone, Copy, Debug)]
+pub enum CellValue {
+    /// The number of points. Cells without points are written as 0, so gaps in the coverage
+    /// show. With a cell size of 1 the counts are points per square unit.
+    Count,
+    /// The mean of a value of the points. Cells without points are written as [`NODATA`].
+    Mean(fn(&Point) -> f64),
+}
+
+/// The number of points in each cell of a grid, and the sum of a value of them for a mean.
+#[derive(Clone, Debug)]
+pub struct CellGrid {
+    cell_size: f64,
+    value: CellValue,
+    cells: HashMap<(i64, i64), (f64, u64)>,
+}
+
+impl CellGrid {
+    pub fn new(cell_size: f64, value: CellValue) -> Self {
+        Self {
+            cell_size,
+            value,
+            cells: HashMap::new(),
+        }
+    }
+
+    /// A grid of the number of points in each cell.
+    pub fn density(cell_size: f64) -> Self {
+        Self::new(cell_size, CellValue::Count)
+    }
+
+    /// A grid of the mean intensity of the points in each cell.
+    pub fn mean_intensity(cell_size: f64) -> Self {
+        Self::new(
+            cell_size,
+            CellValue::Mean(|point| f64::from(point.intensity)),
+        )
+    }
+
+    /// The value of the cell holding `(x, y)`: its number of points, or their mean if it holds
+    /// any.
+    pub fn value_at(&self, x: f64, y: f64) -> Option<f64> {
+        let cell = self.cells.get(&cell_of(x, y, self.cell_size));
+        match self.value {
+            CellValue::Count => Some(cell.map_or(0.0, |&(_, count)| count as f64)),
+            CellValue::Mean(_) => cell.map(|&(sum, count)| sum / count as f64),
+        }
+    }
+}
+
+impl RasterGrid for CellGrid {
+    fn add(&mut self, points: &[Point]) {
+        for point in points {
+            let (sum, count) = self
+                .cells
+                .entry(cell_of(point.x, point.y, self.cell_size))
+                .or_default();
+            if let CellValue::Mean(value) = self.value {
+                *sum += value(point);
+            }
+            *count += 1;
+        }
+    }
+
+    fn to_raster(&self) -> Result<Raster, MyError> {
+        let cells = self
+            .cells
+            .iter()
+            .map(|(&cell, &(sum, count))| match self.value {
+                CellValue::Count => (cell, count as f32),
+                CellValue::Mean(_) => (cell, (sum / count as f64) as f32),
+            })
+            .collect();
+        match self.value {
+            CellValue::Count => Raster::from_cells(self.cell_size, &cells, 0.0),
+            CellValue::Mean(_) => {
+                Ok(Raster::from_cells(self.cell_size, &cells, NODATA)?.with_nodata(NODATA))
+            }
+        }
+    }
+}
+
+/// A sink adding the points of an output to a grid and writing its raster as a GeoTIFF, or as
+/// CSV when the path ends with .csv.
+pub struct RasterOutput<G> {
+    path: PathBuf,
+    grid: G,
+    geo_keys: Option<GeoKeys>,
+}
+
+impl<G: RasterGrid> RasterOutput<G> {
+    pub fn new(path: impl Into<PathBuf>, grid: G) -> Self {
+        Self {
+            path: path.into(),
+            grid,
+            geo_keys: None,
+        }
+    }
+
+    /// Places the raster in the coordinate system of the inputs, as read with
+    /// [`GeoKeys::from_header`].
+    pub fn with_geo_keys(mut self, geo_keys: Option<GeoKeys>) -> Self {
+        self.geo_keys = geo_keys;
+        self
+    }
+}
+
+impl<G: RasterGrid> PointSink for RasterOutput<G> {
+    fn write_points(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        self.grid.add(points);
+        points.clear();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), MyError> {
+        let raster = self.grid.to_raster()?;
+        if is_csv_path(&self.path.to_string_lossy()) {
+            raster.write_csv(&self.path)
+        } else {
+            raster
+                .with_geo_keys(self.geo_keys.clone())
+                .write_geotiff(&self.path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{Condition, Dimensions};
+    use crate::LasProcessor;
+    use std::sync::Arc;
+
+    fn points(points: &[(f64, f64, u16)]) -> Vec<Point> {
+        points
+            .iter()
+            .map(|&(x, y, intensity)| Point {
+                x,
+                y,
+                intensity,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_density() {
+        let mut grid = CellGrid::density(1.0);
+        grid.add(&points(&[
+            (0.5, 0.5, 0),
+            (0.9, 0.1, 0),
+            (2.5, 0.5, 0),
+            (-0.5, 1.5, 0),
+        ]));
+        assert_eq!(grid.value_at(0.0, 0.0), Some(2.0));
+        assert_eq!(grid.value_at(-0.1, 1.9), Some(1.0));
+        assert_eq!(grid.value_at(5.0, 5.0), Some(0.0));
+        let raster = grid.to_raster().unwrap();
+        assert_eq!((raster.columns, raster.rows), (4, 2));
+        assert_eq!(raster.nodata, None);
+        assert_eq!(raster.values, [1.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 1.0]);
+
+        let input = "tests/data/input1.las";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("density.tif");
+        let keep_all = Condition::on_view(Dimensions::XYZ, Arc::new(|_| true));
+        let sink = RasterOutput::new(&path, CellGrid::density(10.0));
+        LasProcessor::new(vec![input.to_string()], Vec::new(), Vec::new(), false)
+            .with_sink("density", sink, keep_all)
+            .with_observer(Arc::new(crate::NoProgress))
+            .process_lidar_files()
+            .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..4], b"II*\0");
+        // The pixels follow the TIFF header, and the width and height are the first two entries
+        // of the image file directory
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let directory = u32_at(4) as usize;
+        let cells = (u32_at(directory + 10) * u32_at(directory + 22)) as usize;
+        let total: f32 = bytes[8..8 + cells * 4]
+            .chunks_exact(4)
+            .map(|pixel| f32::from_le_bytes(pixel.try_into().unwrap()))
+            .sum();
+        let header = las::Reader::from_path(input).unwrap().header().clone();
+        assert_eq!(total as u64, header.number_of_points());
+    }
+
+    #[test]
+    fn test_mean_intensity() {
+        let mut grid = CellGrid::mean_intensity(1.0);
+        grid.add(&points(&[(0.5, 0.5, 100), (0.9, 0.1, 301), (1.5, 1.5, 7)]));
+        assert_eq!(grid.value_at(0.0, 0.0), Some(200.5));
+        assert_eq!(grid.value_at(2.0, 0.0), None);
+        let raster = grid.to_raster().unwrap();
+        assert_eq!(raster.nodata, Some(NODATA));
+        assert_eq!(raster.values, [NODATA, 7.0, 200.5, NODATA]);
+    }
+}
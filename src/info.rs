@@ -0,0 +1,153 @@
+//! Summaries of LAS/LAZ files taken from their headers, without reading the points.
+use crate::crs::Crs;
+use crate::errors::MyError;
+use crate::input::open_reader;
+use las::{Bounds, Header, Vector, Version};
+use std::fmt;
+use std::io::{self, Write};
+
+/// What the header of a file says about it.
+#[derive(Clone, Debug)]
+pub struct FileInfo {
+    pub path: String,
+    pub version: Version,
+    pub point_format: u8,
+    pub compressed: bool,
+    pub extra_bytes: u16,
+    pub number_of_points: u64,
+    pub bounds: Bounds,
+    pub scale: Vector<f64>,
+    pub offset: Vector<f64>,
+    pub system_identifier: String,
+    pub generating_software: String,
+    pub vlrs: usize,
+    pub evlrs: usize,
+    /// The coordinate system decoded from the VLRs, if there is one that can be read.
+    pub crs: Option<Crs>,
+}
+
+impl FileInfo {
+    /// Reads the header of the input at `path`.
+    pub fn read(path: &str) -> Result<Self, MyError> {
+        let reader = open_reader(path)?;
+        Self::from_header(path, reader.header())
+    }
+
+    /// Summarizes `header`, read from the file at `path`.
+    pub fn from_header(path: &str, header: &Header) -> Result<Self, MyError> {
+        let transforms = header.transforms();
+        Ok(Self {
+            path: path.to_string(),
+            version: header.version(),
+            point_format: header.point_format().to_u8()?,
+            compressed: header.point_format().is_compressed,
+            extra_bytes: header.point_format().extra_bytes,
+            number_of_points: header.number_of_points(),
+            bounds: header.bounds(),
+            scale: Vector {
+                x: transforms.x.scale,
+                y: transforms.y.scale,
+                z: transforms.z.scale,
+            },
+            offset: Vector {
+                x: transforms.x.offset,
+                y: transforms.y.offset,
+                z: transforms.z.offset,
+            },
+            system_identifier: header.system_identifier().to_string(),
+            generating_software: header.generating_software().to_string(),
+            vlrs: header.vlrs().len(),
+            evlrs: header.evlrs().len(),
+            crs: Crs::from_header(header),
+        })
+    }
+}
+
+impl fmt::Display for FileInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (min, max) = (&self.bounds.min, &self.bounds.max);
+        writeln!(f, "{}", self.path)?;
+        writeln!(f, "  version:             {}", self.version)?;
+        write!(f, "  point format:        {}", self.point_format)?;
+        if self.compressed {
+            write!(f, " (compressed)")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "  extra bytes:         {}", self.extra_bytes)?;
+        writeln!(f, "  points:              {}", self.number_of_points)?;
+        writeln!(f, "  min x y z:           {} {} {}", min.x, min.y, min.z)?;
+        writeln!(f, "  max x y z:           {} {} {}", max.x, max.y, max.z)?;
+        let (scale, offset) = (&self.scale, &self.offset);
+        writeln!(
+            f,
+            "  scale x y z:         {} {} {}",
+            scale.x, scale.y, scale.z
+        )?;
+        writeln!(
+            f,
+            "  offset x y z:        {} {} {}",
+            offset.x, offset.y, offset.z
+        )?;
+        writeln!(f, "  system identifier:   {}", self.system_identifier)?;
+        writeln!(f, "  generating software: {}", self.generating_software)?;
+        writeln!(f, "  VLRs / EVLRs:        {} / {}", self.vlrs, self.evlrs)?;
+        match &self.crs {
+            Some(crs) => write!(f, "  CRS:                 {}", crs),
+            None => write!(f, "  CRS:                 none"),
+        }
+    }
+}
+
+/// Writes an index of `files` as CSV, a line per file with its point count and bounds.
+pub fn write_index(files: &[FileInfo], mut write: impl Write) -> io::Result<()> {
+    writeln!(write, "path,points,min_x,min_y,min_z,max_x,max_y,max_z")?;
+    for file in files {
+        let (min, max) = (&file.bounds.min, &file.bounds.max);
+        writeln!(
+            write,
+            "{},{},{},{},{},{},{},{}",
+            csv_field(&file.path),
+            file.number_of_points,
+            min.x,
+            min.y,
+            min.z,
+            max.x,
+            max.y,
+            max.z
+        )?;
+    }
+    write.flush()
+}
+
+/// Quotes a CSV field if it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_info() {
+        let info = FileInfo::read("tests/data/input1.las").unwrap();
+        let reader = las::Reader::from_path("tests/data/input1.las").unwrap();
+        assert_eq!(info.number_of_points, reader.header().number_of_points());
+        assert_eq!(info.bounds, reader.header().bounds());
+        assert!(info.to_string().starts_with("tests/data/input1.las\n"));
+
+        let mut index = Vec::new();
+        let mut quoted = info.clone();
+        quoted.path = "a,b.las".to_string();
+        write_index(&[info.clone(), quoted], &mut index).unwrap();
+        let index = String::from_utf8(index).unwrap();
+        let lines: Vec<_> = index.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with(&format!("tests/data/input1.las,{},", info.number_of_points)));
+        assert!(lines[2].starts_with("\"a,b.las\","));
+    }
+}
@@ -0,0 +1,159 @@
+//! Opening LAS/LAZ readers for the different kinds of input the processor accepts.
+//!
+//! Inputs are passed around as plain strings. Most of them are file paths, but the special path
+//! `-` means the point data is piped in on stdin, and URLs like `s3://bucket/key.laz` or
+//! `https://example.com/tile.laz` are read remotely (see [`crate::remote`]). Files inside ZIP
+//! archives are addressed as `archive.zip!/tile.laz` (see [`crate::archive`]). Streams handed over
+//! by the caller have no path and are opened by the processor itself (see [`crate::stream`]).
+use crate::archive;
+use crate::errors::MyError;
+use crate::remote;
+use las::Reader;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The input path that stands for stdin.
+pub const STDIN_PATH: &str = "-";
+
+/// The bytes read from stdin, kept around because the processor opens each input more than once.
+/// The whole stream is held in memory, so a point cloud larger than the available memory has to
+/// be written to a file and read from there instead.
+static STDIN_DATA: Mutex<Option<Arc<[u8]>>> = Mutex::new(None);
+
+/// Returns `true` if `path` refers to stdin rather than a file.
+pub fn is_stdin(path: &str) -> bool {
+    path == STDIN_PATH
+}
+
+/// Opens a `las::Reader` for an input path.
+///
+/// Stdin is not seekable, which `las::Reader` needs to jump between the header, the point data
+/// and the EVLRs, so the whole stream is buffered in memory the first time it is opened. Later
+/// calls share the same buffer.
+///
+/// Errors are wrapped in `MyError::OpenError` so that they name the input.
+pub fn open_reader(path: &str) -> Result<Reader, MyError> {
+    open_any_reader(path).map_err(|err| MyError::OpenError {
+        path: path.to_string(),
+        source: Box::new(err),
+    })
+}
+
+fn open_any_reader(path: &str) -> Result<Reader, MyError> {
+    if is_stdin(path) {
+        let data = stdin_data()?;
+        Ok(Reader::new(Cursor::new(data))?)
+    } else if remote::is_remote(path) || remote::is_http(path) {
+        remote::open_reader(path)
+    } else if archive::split_entry(path).is_some() {
+        archive::open_reader(path)
+    } else {
+        Ok(Reader::from_path(path)?)
+    }
+}
+
+/// The file extensions picked up from directory inputs by default.
+pub const DEFAULT_EXTENSIONS: [&str; 2] = ["las", "laz"];
+
+/// Finds the files in `dir` whose extension is one of `extensions` (compared case-insensitively),
+/// descending into subdirectories if `recursive` is set. The paths are returned sorted.
+pub fn find_files(
+    dir: &Path,
+    extensions: &[String],
+    recursive: bool,
+) -> Result<Vec<String>, MyError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                paths.extend(find_files(&path, extensions, recursive)?);
+            }
+        } else if has_extension(&path, extensions) {
+            paths.push(path.to_string_lossy().to_string());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Returns `true` if the extension of `path` is one of `extensions`, ignoring case.
+pub fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension().is_some_and(|ext| {
+        extensions
+            .iter()
+            .any(|wanted| ext.eq_ignore_ascii_case(wanted.as_str()))
+    })
+}
+
+/// Reads input paths from a text file with one path per line.
+///
+/// Leading and trailing whitespace is ignored, as are blank lines and lines starting with `#`.
+pub fn read_input_list(list_path: &Path) -> Result<Vec<String>, MyError> {
+    Ok(fs::read_to_string(list_path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+fn stdin_data() -> Result<Arc<[u8]>, MyError> {
+    let mut cached = STDIN_DATA.lock().map_err(|_| MyError::LockError)?;
+    if let Some(data) = cached.as_ref() {
+        return Ok(Arc::clone(data));
+    }
+    let mut buffer = Vec::new();
+    std::io::stdin().lock().read_to_end(&mut buffer)?;
+    let data: Arc<[u8]> = buffer.into();
+    *cached = Some(Arc::clone(&data));
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_files_recursive_and_extensions() {
+        let dir = tempdir().unwrap();
+        let nested = dir.path().join("nested");
+        fs::create_dir(&nested).unwrap();
+        for path in [
+            dir.path().join("a.las"),
+            dir.path().join("b.LAZ"),
+            dir.path().join("notes.txt"),
+            nested.join("c.laz"),
+        ] {
+            fs::write(path, b"").unwrap();
+        }
+        let defaults: Vec<String> = DEFAULT_EXTENSIONS.iter().map(|e| e.to_string()).collect();
+
+        let top_level = find_files(dir.path(), &defaults, false).unwrap();
+        assert_eq!(top_level.len(), 2);
+
+        let all = find_files(dir.path(), &defaults, true).unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(all.iter().any(|path| path.ends_with("c.laz")));
+
+        let only_las = find_files(dir.path(), &["las".to_string()], true).unwrap();
+        assert_eq!(only_las.len(), 1);
+    }
+
+    #[test]
+    fn test_read_input_list_skips_comments_and_blanks() {
+        let dir = tempdir().unwrap();
+        let list_path = dir.path().join("files.txt");
+        fs::write(
+            &list_path,
+            "# tiles for block A\ntiles/a.las\n\n  tiles/b.laz  \n#tiles/c.laz\n",
+        )
+        .unwrap();
+
+        let paths = read_input_list(&list_path).unwrap();
+        assert_eq!(paths, vec!["tiles/a.las", "tiles/b.laz"]);
+    }
+}
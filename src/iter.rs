@@ -0,0 +1,125 @@
+//! Iterating over the points meeting a condition instead of writing them to an output.
+//!
+//! [`LasProcessor::iter_points`] runs the processor on a background thread with a single sink
+//! output that sends each batch through a bounded channel, so the readers wait while the caller
+//! isn't consuming points.
+use crate::errors::MyError;
+use crate::filter::Condition;
+use crate::sink::PointSink;
+use crate::{Backend, LasProcessor};
+use crossbeam::channel;
+use las::Point;
+use std::thread::{self, JoinHandle};
+
+/// Sends the batches of points to a [`PointIter`].
+struct ChannelSink(channel::Sender<Vec<Point>>);
+
+impl PointSink for ChannelSink {
+    fn write_points(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        self.0
+            .send(std::mem::take(points))
+            .map_err(|_| MyError::SendError)
+    }
+}
+
+/// An iterator over the points meeting a condition, created by [`LasProcessor::iter_points`].
+///
+/// If processing fails, the error is the last item. Dropping the iterator early makes the run
+/// fail on its next batch, which stops it.
+pub struct PointIter {
+    batches: channel::Receiver<Vec<Point>>,
+    batch: std::vec::IntoIter<Point>,
+    processing: Option<JoinHandle<Result<(), MyError>>>,
+}
+
+impl Iterator for PointIter {
+    type Item = Result<Point, MyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(point) = self.batch.next() {
+                return Some(Ok(point));
+            }
+            match self.batches.recv() {
+                Ok(batch) => self.batch = batch.into_iter(),
+                Err(_) => {
+                    let result = self.processing.take()?.join();
+                    return match result {
+                        Ok(Ok(())) => None,
+                        Ok(Err(err)) => Some(Err(err)),
+                        Err(_) => Some(Err(MyError::ThreadError)),
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl LasProcessor {
+    /// Reads the inputs and returns an iterator over the points meeting `condition`, from all
+    /// inputs, instead of writing outputs. The outputs of the processor are ignored.
+    ///
+    /// Points come in no particular order, unless `ordered` is set: then the inputs are read one
+    /// after the other on a single thread and the points come in the order of the inputs and of
+    /// the points in each input.
+    pub fn iter_points(mut self, condition: Condition, ordered: bool) -> PointIter {
+        let (sender, batches) = channel::bounded(self.channel_depth);
+        self.output_paths.clear();
+        self.conditions.clear();
+        self.sinks.clear();
+        if ordered {
+            self.reader_threads = Some(1);
+            self.backend = Backend::Threads;
+        }
+        let processor = self.with_sink("points", ChannelSink(sender), condition);
+        let processing = thread::spawn(move || processor.process_lidar_files().map(|_| ()));
+        PointIter {
+            batches,
+            batch: Vec::new().into_iter(),
+            processing: Some(processing),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::NumericFilter;
+    use crate::NoProgress;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_iter_points() {
+        let processor = || {
+            LasProcessor::builder()
+                .input("tests/data/input1.las")
+                .observer(Arc::new(NoProgress))
+                .batch_size(100)
+                .split_size(1_000)
+                .reader_threads(3)
+                .build()
+        };
+        let condition = || Condition::numeric(NumericFilter::Intensity(0..=39_999));
+        let expected: Vec<Point> = las::Reader::from_path("tests/data/input1.las")
+            .unwrap()
+            .points()
+            .map(|point| point.unwrap())
+            .filter(|point| point.intensity < 40_000)
+            .collect();
+
+        let points: Vec<Point> = processor()
+            .iter_points(condition(), true)
+            .map(|point| point.unwrap())
+            .collect();
+        assert_eq!(points, expected);
+
+        let points: Result<Vec<Point>, MyError> =
+            processor().iter_points(condition(), false).collect();
+        assert_eq!(points.unwrap().len(), expected.len());
+
+        // Stopping early is fine
+        let mut points = processor().iter_points(condition(), false);
+        assert!(points.next().unwrap().is_ok());
+        drop(points);
+    }
+}
@@ -0,0 +1,77 @@
+//! A small on-disk journal of completed inputs, so an interrupted batch run can be resumed.
+//!
+//! The journal is a text file with one completed input path per line. Each line is flushed to
+//! disk as soon as its input is done, so a crash or kill loses at most the input being processed.
+use crate::errors::MyError;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Records which inputs of a batch run have been processed completely.
+pub struct Journal {
+    file: File,
+    completed: HashSet<String>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, creating it if it doesn't exist yet. Inputs recorded by
+    /// earlier runs are reported as completed.
+    pub fn open(path: &Path) -> Result<Self, MyError> {
+        let completed = match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashSet::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file, completed })
+    }
+
+    /// Returns `true` if `input` was recorded as completed.
+    pub fn is_completed(&self, input: &str) -> bool {
+        self.completed.contains(input)
+    }
+
+    /// Returns the number of inputs recorded as completed.
+    pub fn completed_count(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// Records `input` as completed and makes sure the record is on disk.
+    pub fn mark_completed(&mut self, input: &str) -> Result<(), MyError> {
+        writeln!(self.file, "{}", input)?;
+        self.file.sync_data()?;
+        self.completed.insert(input.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_journal_survives_reopening() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("run.journal");
+
+        let mut journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.completed_count(), 0);
+        journal.mark_completed("tiles/a.las").unwrap();
+        drop(journal);
+
+        let mut journal = Journal::open(&path).unwrap();
+        assert!(journal.is_completed("tiles/a.las"));
+        assert!(!journal.is_completed("tiles/b.las"));
+        journal.mark_completed("tiles/b.las").unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.completed_count(), 2);
+    }
+}
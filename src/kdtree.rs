@@ -0,0 +1,141 @@
+//! A static k-d tree over points in three dimensions, for radius queries.
+//!
+//! The tree is stored implicitly: the points are reordered so that the median of each range, by
+//! the axis of its depth, sits in the middle with the smaller points before it and the larger
+//! after. Building it takes O(n log n) and needs no memory beyond the points and their indices.
+
+/// A k-d tree over points given as X, Y and Z.
+#[derive(Clone, Debug)]
+pub struct KdTree {
+    points: Vec<[f64; 3]>,
+    /// The index each point had when the tree was built.
+    indices: Vec<usize>,
+}
+
+impl KdTree {
+    /// Builds a tree over `points`.
+    pub fn new(points: Vec<[f64; 3]>) -> Self {
+        let mut entries: Vec<([f64; 3], usize)> = points.into_iter().zip(0..).collect();
+        build(&mut entries, 0);
+        let (points, indices) = entries.into_iter().unzip();
+        Self { points, indices }
+    }
+
+    /// The number of points in the tree.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Returns `true` if the tree holds no points.
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The number of points within `radius` of `centre`, counting no further than `limit`.
+    pub fn count_within(&self, centre: [f64; 3], radius: f64, limit: usize) -> usize {
+        let mut count = 0;
+        self.visit_within(0, self.points.len(), 0, centre, radius, &mut |_| {
+            count += 1;
+            count < limit
+        });
+        count
+    }
+
+    /// The indices, as given to [`KdTree::new`], of the points within `radius` of `centre`.
+    pub fn within(&self, centre: [f64; 3], radius: f64) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.visit_within(0, self.points.len(), 0, centre, radius, &mut |index| {
+            found.push(index);
+            true
+        });
+        found
+    }
+
+    /// Calls `visit` with the index of every point of `start..end` within `radius` of `centre`,
+    /// until it returns `false`. Returns `false` if it was stopped.
+    fn visit_within(
+        &self,
+        start: usize,
+        end: usize,
+        depth: usize,
+        centre: [f64; 3],
+        radius: f64,
+        visit: &mut dyn FnMut(usize) -> bool,
+    ) -> bool {
+        if start >= end {
+            return true;
+        }
+        let middle = start + (end - start) / 2;
+        let point = self.points[middle];
+        let distance_squared: f64 = (0..3)
+            .map(|axis| (point[axis] - centre[axis]).powi(2))
+            .sum();
+        if distance_squared <= radius * radius && !visit(self.indices[middle]) {
+            return false;
+        }
+        let axis = depth % 3;
+        let difference = centre[axis] - point[axis];
+        if difference <= radius
+            && !self.visit_within(start, middle, depth + 1, centre, radius, visit)
+        {
+            return false;
+        }
+        if difference >= -radius
+            && !self.visit_within(middle + 1, end, depth + 1, centre, radius, visit)
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// Orders `entries` as the tree of a range at `depth`.
+fn build(entries: &mut [([f64; 3], usize)], depth: usize) {
+    if entries.len() < 2 {
+        return;
+    }
+    let axis = depth % 3;
+    let middle = entries.len() / 2;
+    entries.select_nth_unstable_by(middle, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+    let (before, rest) = entries.split_at_mut(middle);
+    build(before, depth + 1);
+    build(&mut rest[1..], depth + 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kdtree() {
+        // A 10 x 10 x 10 lattice with a spacing of 1
+        let points: Vec<[f64; 3]> = (0..1000)
+            .map(|i| [(i % 10) as f64, (i / 10 % 10) as f64, (i / 100) as f64])
+            .collect();
+        let tree = KdTree::new(points.clone());
+        assert_eq!(tree.len(), 1000);
+        for (centre, radius) in [
+            ([5.0, 5.0, 5.0], 1.0),
+            ([0.0, 0.0, 0.0], 1.5),
+            ([4.5, 4.5, 9.0], 2.0),
+        ] {
+            let mut found = tree.within(centre, radius);
+            found.sort();
+            let expected: Vec<usize> = (0..points.len())
+                .filter(|&i| {
+                    let d: f64 = (0..3)
+                        .map(|axis| (points[i][axis] - centre[axis]).powi(2))
+                        .sum();
+                    d <= radius * radius
+                })
+                .collect();
+            assert_eq!(found, expected);
+            assert_eq!(
+                tree.count_within(centre, radius, usize::MAX),
+                expected.len()
+            );
+        }
+        assert_eq!(tree.count_within([5.0, 5.0, 5.0], 1.0, 3), 3);
+        assert!(KdTree::new(Vec::new()).within([0.0; 3], 1.0).is_empty());
+    }
+}
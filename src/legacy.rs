@@ -0,0 +1,142 @@
+//! Writing LAS 1.4 outputs that software older than LAS 1.4 reads correctly.
+//!
+//! Older readers take the point count and the points by return from the legacy fields of the
+//! header, and only know point formats 0 to 5 and coordinate systems given as GeoTIFF keys. In
+//! legacy-compatible outputs:
+//!
+//! * The LAS 1.4 point formats become the older format with the same dimensions, without the near
+//!   infrared, and the points are fitted to it by [`fit_legacy_point`].
+//! * EVLRs small enough to be VLRs are moved before the points.
+//! * A WKT coordinate system is replaced with GeoTIFF keys, when it can be converted.
+//! * The legacy counts are set by [`fill_legacy_counts`] once the file is written, unless there
+//!   are too many points for them.
+use crate::crs::use_geo_keys;
+use crate::errors::MyError;
+use crate::validate::legacy_counts;
+use las::point::{Classification, Format};
+use las::{Builder, Header, Point, Vlr};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// The highest class, return number and number of returns the legacy point formats hold.
+const MAX_LEGACY_CLASS: u8 = 31;
+const MAX_LEGACY_RETURNS: u8 = 7;
+
+/// The legacy point format holding the dimensions of `format` that older readers know.
+pub fn legacy_format(format: Format) -> Result<Format, MyError> {
+    if !format.is_extended {
+        return Ok(format);
+    }
+    let number = match (format.has_color, format.has_waveform) {
+        (false, false) => 1,
+        (true, false) => 3,
+        (false, true) => 4,
+        (true, true) => 5,
+    };
+    let mut legacy = Format::new(number)?;
+    legacy.extra_bytes = format.extra_bytes;
+    legacy.is_compressed = format.is_compressed;
+    Ok(legacy)
+}
+
+/// The header of an output for older readers, made from `header`. The version is kept, so LAS
+/// 1.4 outputs stay LAS 1.4 for the readers that know it.
+pub fn legacy_header(header: Header) -> Result<Header, MyError> {
+    let mut builder = Builder::from(header);
+    builder.point_format = legacy_format(builder.point_format)?;
+    let (vlrs, evlrs): (Vec<Vlr>, Vec<Vlr>) = std::mem::take(&mut builder.evlrs)
+        .into_iter()
+        .partition(|evlr| evlr.data.len() <= usize::from(u16::MAX));
+    builder.vlrs.extend(vlrs);
+    builder.evlrs = evlrs;
+    use_geo_keys(&mut builder);
+    Ok(builder.into_header()?)
+}
+
+/// Makes `point` fit a legacy point format: classes above 31 become unclassified, return numbers
+/// and numbers of returns stop at 7, the scanner channel is dropped and the scan angle is rounded
+/// to a whole degree from -90 to 90.
+pub fn fit_legacy_point(point: &mut Point) {
+    if u8::from(point.classification) > MAX_LEGACY_CLASS {
+        point.classification = Classification::Unclassified;
+    }
+    point.return_number = point.return_number.min(MAX_LEGACY_RETURNS);
+    point.number_of_returns = point.number_of_returns.min(MAX_LEGACY_RETURNS);
+    point.scanner_channel = 0;
+    point.scan_angle = point.scan_angle.clamp(-90.0, 90.0).round();
+}
+
+/// Sets the legacy point count and points by return of the LAS 1.4 file written to `file` from
+/// its LAS 1.4 counts. They stay at 0 for the LAS 1.4 point formats, and when there are too many
+/// points. Files older than LAS 1.4 only have the legacy counts and are left alone.
+pub fn fill_legacy_counts<F: Read + Write + Seek>(file: &mut F) -> Result<(), MyError> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut raw = las::raw::Header::read_from(&mut *file)?;
+    if let Some(large_file) = &raw.large_file {
+        let (count, by_return) = legacy_counts(
+            raw.point_data_record_format & 0x3f,
+            large_file.number_of_point_records,
+            &large_file.number_of_points_by_return,
+        );
+        raw.number_of_point_records = count;
+        raw.number_of_points_by_return = by_return;
+        file.seek(SeekFrom::Start(0))?;
+        raw.write_to(&mut *file)?;
+    }
+    file.seek(SeekFrom::End(0))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::Writer;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_legacy_header_and_counts() {
+        let mut builder = Builder::from((1, 4));
+        builder.point_format = Format::new(8).unwrap();
+        builder.evlrs.push(Vlr {
+            user_id: "notes".to_string(),
+            record_id: 1,
+            description: String::new(),
+            data: vec![1; 8],
+        });
+        let header = legacy_header(builder.into_header().unwrap()).unwrap();
+        assert_eq!(header.point_format().to_u8().unwrap(), 3);
+        assert_eq!(header.version(), las::Version::new(1, 4));
+        assert_eq!(header.vlrs().len(), 1);
+        assert!(header.evlrs().is_empty());
+
+        let mut point = Point {
+            classification: Classification::new(40).unwrap(),
+            return_number: 9,
+            number_of_returns: 9,
+            scan_angle: 120.4,
+            ..Default::default()
+        };
+        fit_legacy_point(&mut point);
+        assert_eq!(point.classification, Classification::Unclassified);
+        assert_eq!((point.return_number, point.number_of_returns), (7, 7));
+        assert_eq!(point.scan_angle, 90.0);
+
+        let mut writer = Writer::new(Cursor::new(Vec::new()), header).unwrap();
+        for return_number in [1, 1, 2] {
+            let mut point = Point {
+                return_number,
+                number_of_returns: 2,
+                gps_time: Some(0.0),
+                color: Some(las::Color::default()),
+                ..Default::default()
+            };
+            fit_legacy_point(&mut point);
+            writer.write_point(point).unwrap();
+        }
+        let mut file = writer.into_inner().unwrap();
+        fill_legacy_counts(&mut file).unwrap();
+        file.set_position(0);
+        let raw = las::raw::Header::read_from(&mut file).unwrap();
+        assert_eq!(raw.number_of_point_records, 3);
+        assert_eq!(raw.number_of_points_by_return, [2, 1, 0, 0, 0]);
+    }
+}
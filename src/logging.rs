@@ -0,0 +1,278 @@
+//! A minimal `log` backend for the command line tool.
+//!
+//! The library only emits records through the `log` macros, so embedders decide where its
+//! diagnostics go, if anywhere. The CLI installs [`CliLogger`], which prints info messages to
+//! stderr as they are and prefixes the other levels with their name, or writes them as JSON events
+//! next to the JSON progress events. It can also append every record down to debug level to a log
+//! file, whatever the console level.
+//!
+//! Records logged while an input is read are about that input: the processor names it with
+//! [`file_context`] on the reading thread, and [`CliLogger`] puts the name in front of them.
+//! Other loggers can find it with [`current_file`].
+use crate::errors::MyError;
+use chrono::Local;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The level written to the log file.
+const LOG_FILE_LEVEL: LevelFilter = LevelFilter::Debug;
+
+thread_local! {
+    /// The file the records logged on this thread are about.
+    static FILE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Names `path` as the file the records logged on this thread are about, until the returned
+/// guard is dropped.
+pub fn file_context(path: &str) -> FileContext {
+    let previous = FILE.with(|file| file.replace(Some(path.to_string())));
+    FileContext { previous }
+}
+
+/// The file the records logged on this thread are about, if any.
+pub fn current_file() -> Option<String> {
+    FILE.with(|file| file.borrow().clone())
+}
+
+/// Names a file for the records logged on a thread, and names the one before when dropped.
+pub struct FileContext {
+    previous: Option<String>,
+}
+
+impl Drop for FileContext {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        FILE.with(|file| file.replace(previous));
+    }
+}
+
+/// The levels of `RUST_LOG`: a level for every module, and levels for some modules and the
+/// modules in them, as in `warn,las_trimmer=debug,las_trimmer::output=trace`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvFilter {
+    default: Option<LevelFilter>,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl EnvFilter {
+    /// Parses comma-separated directives, each a level, a module, or a module and a level joined
+    /// by `=`. A module alone gets every record. Directives that aren't understood are left out.
+    pub fn parse(spec: &str) -> Self {
+        let mut filter = Self::default();
+        for directive in spec.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    if let Ok(level) = level.trim().parse() {
+                        filter.modules.push((module.trim().to_string(), level));
+                    }
+                }
+                None if directive.is_empty() => {}
+                None => match directive.parse() {
+                    Ok(level) => filter.default = Some(level),
+                    Err(_) => filter
+                        .modules
+                        .push((directive.to_string(), LevelFilter::Trace)),
+                },
+            }
+        }
+        filter
+    }
+
+    /// The level of the records of `target`: that of the longest module holding it, or the level
+    /// for every module, if one was given.
+    pub fn level(&self, target: &str) -> Option<LevelFilter> {
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target == module
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .or(self.default)
+    }
+
+    /// The most verbose level of the filter.
+    fn max_level(&self) -> Option<LevelFilter> {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .chain(self.default)
+            .max()
+    }
+}
+
+/// Writes log records to stderr and optionally to a log file, each with its own level.
+pub struct CliLogger {
+    console_level: LevelFilter,
+    filter: Option<EnvFilter>,
+    json: bool,
+    file: Option<Mutex<File>>,
+}
+
+impl CliLogger {
+    /// Creates a logger that writes records at or above `console_level` to stderr.
+    pub fn new(console_level: LevelFilter) -> Self {
+        Self {
+            console_level,
+            filter: None,
+            json: false,
+            file: None,
+        }
+    }
+
+    /// Picks the level of the records written to stderr by module with `filter`. Records of
+    /// modules it has no level for are written at or above the console level.
+    pub fn with_filter(mut self, filter: EnvFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Writes the records to stderr as JSON objects, a line each, like
+    /// `{"event":"log","level":"warn","message":"..."}`, so that stderr only holds JSON when the
+    /// progress is reported as JSON too.
+    pub fn with_json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    /// Also appends all records at debug level and above to the file at `path`, with a timestamp.
+    pub fn with_log_file(mut self, path: &Path) -> Result<Self, MyError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.file = Some(Mutex::new(file));
+        Ok(self)
+    }
+
+    /// The level of the records of `target` written to stderr.
+    fn console_level(&self, target: &str) -> LevelFilter {
+        self.filter
+            .as_ref()
+            .and_then(|filter| filter.level(target))
+            .unwrap_or(self.console_level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        let console_level = match &self.filter {
+            Some(filter) => filter
+                .max_level()
+                .map_or(self.console_level, |level| level.max(self.console_level)),
+            None => self.console_level,
+        };
+        match self.file {
+            Some(_) => console_level.max(LOG_FILE_LEVEL),
+            None => console_level,
+        }
+    }
+}
+
+impl Log for CliLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        let input = current_file();
+        let message = match &input {
+            Some(input) if !self.json => format!("{}: {}", input, record.args()),
+            _ => record.args().to_string(),
+        };
+        if record.level() <= self.console_level(record.target()) {
+            match record.level() {
+                level if self.json => eprintln!(
+                    "{}",
+                    json!({
+                        "event": "log",
+                        "level": level.as_str().to_lowercase(),
+                        "file": input,
+                        "message": message,
+                    })
+                ),
+                Level::Info => eprintln!("{}", message),
+                level => eprintln!("{}: {}", level.as_str().to_lowercase(), message),
+            }
+        }
+        if let Some(file) = &self.file {
+            if record.level() <= LOG_FILE_LEVEL {
+                if let Ok(mut file) = file.lock() {
+                    let _ = writeln!(
+                        file,
+                        "{} {:<5} {}",
+                        Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+                        record.level(),
+                        message
+                    );
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(Ok(mut file)) = self.file.as_ref().map(Mutex::lock) {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Reads the levels from `RUST_LOG`, if it is set. See [`EnvFilter::parse`].
+pub fn filter_from_env() -> Option<EnvFilter> {
+    Some(EnvFilter::parse(&std::env::var("RUST_LOG").ok()?))
+}
+
+/// Installs `logger` as the global logger.
+pub fn init(logger: CliLogger) -> Result<(), MyError> {
+    let max_level = logger.max_level();
+    log::set_boxed_logger(Box::new(logger))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_filter() {
+        let filter =
+            EnvFilter::parse("warn, las_trimmer=debug,las_trimmer::output=trace,bogus=loud");
+        assert_eq!(filter.level("las_trimmer"), Some(LevelFilter::Debug));
+        assert_eq!(
+            filter.level("las_trimmer::processor"),
+            Some(LevelFilter::Debug)
+        );
+        assert_eq!(
+            filter.level("las_trimmer::output"),
+            Some(LevelFilter::Trace)
+        );
+        assert_eq!(filter.level("las_trimmer_extra"), Some(LevelFilter::Warn));
+        assert_eq!(filter.level("bogus"), Some(LevelFilter::Warn));
+        assert_eq!(filter.max_level(), Some(LevelFilter::Trace));
+
+        let filter = EnvFilter::parse("las_trimmer=debug");
+        assert_eq!(filter.level("laz"), None);
+        assert_eq!(
+            EnvFilter::parse("info").level("laz"),
+            Some(LevelFilter::Info)
+        );
+    }
+
+    #[test]
+    fn test_file_context() {
+        assert_eq!(current_file(), None);
+        {
+            let _outer = file_context("a.las");
+            {
+                let _inner = file_context("b.las");
+                assert_eq!(current_file().as_deref(), Some("b.las"));
+            }
+            assert_eq!(current_file().as_deref(), Some("a.las"));
+        }
+        assert_eq!(current_file(), None);
+    }
+}
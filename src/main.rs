@@ -1,10 +1,71 @@
-use clap::{Parser, ValueEnum};
-use las::Point;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use las::point::ScanDirection;
+use las::{GpsTimeType, Point};
+use las_trimmer::adjustment::Adjustments;
+use las_trimmer::anonymize::Anonymization;
+use las_trimmer::archive;
+use las_trimmer::bench;
+use las_trimmer::boundary::{boundary, feature_collection, BoundaryKind};
+use las_trimmer::canopy::{CanopyGrid, DEFAULT_PERCENTILES};
+use las_trimmer::colormap::{ColorSource, Colormap};
+use las_trimmer::compression::DEFAULT_CHUNK_SIZE;
+use las_trimmer::corridor::Corridor;
+use las_trimmer::crs::Crs;
+use las_trimmer::diff::{diff, DiffOptions, DIMENSIONS};
 use las_trimmer::errors::MyError;
-use las_trimmer::{LasProcessor, SharedFunction};
-use std::fs;
-use std::path::PathBuf;
-use std::sync::Arc;
+use las_trimmer::flight_lines::{render_line_path, LINE_PLACEHOLDER};
+use las_trimmer::gps_time::GpsTimeConversion;
+use las_trimmer::grid::{CellGrid, RasterOutput};
+use las_trimmer::info::{write_index, FileInfo};
+use las_trimmer::input::{find_files, is_stdin, open_reader, read_input_list, DEFAULT_EXTENSIONS};
+use las_trimmer::journal::Journal;
+use las_trimmer::logging::{self, CliLogger, EnvFilter};
+use las_trimmer::metrics::{serve_metrics, Metrics};
+use las_trimmer::neighborhood::NeighborFilter;
+use las_trimmer::output::{
+    check_output_paths, is_stdout, is_template, outputs_up_to_date, render_output_path,
+    SkipExisting,
+};
+use las_trimmer::output_options::OutputOptions;
+use las_trimmer::overlap::OverlapKind;
+use las_trimmer::palette::ClassPalette;
+use las_trimmer::pipeline::{OptionValue, PipelineConfig};
+use las_trimmer::polygon::{is_feature_template, read_features, Polygons};
+use las_trimmer::preset::{self, find_preset};
+use las_trimmer::profile::{ProfileLine, ProfileOutput};
+use las_trimmer::progress::PROGRESS_TARGET;
+use las_trimmer::queue::{read_job_dir, read_job_lines, run_queue, JobSummary, QueuedJob};
+use las_trimmer::raster::{is_csv_path, is_raster_path, GeoKeys};
+use las_trimmer::remote::{is_http, is_remote};
+use las_trimmer::repair::{repair, RepairOptions};
+use las_trimmer::reproject::Reprojection;
+use las_trimmer::source_tag::{SourceIds, SourceTag};
+use las_trimmer::stamp::{HeaderStamp, FIELD_LENGTH};
+use las_trimmer::stats::StatsOutput;
+use las_trimmer::status::{serve_status, JobStatus};
+use las_trimmer::surface::{GridMethod, SurfaceGrid, SurfaceKind};
+use las_trimmer::tile::{is_tile_template, tiles};
+use las_trimmer::time_merge::TimeMerge;
+use las_trimmer::validate::validate;
+use las_trimmer::vlr_merge::{VlrMerge, VlrPrecedence};
+use las_trimmer::watch::watch_directory;
+use las_trimmer::{
+    Backend, Condition, ConditionalTransform, ConsoleProgress, Dimensions, ErrorPolicy,
+    JsonProgress, LasProcessor, LazChunking, NoProgress, NumericFilter, Observers, PointFlag,
+    ProcessingReport, ProgressBars, ProgressObserver, ReturnSelection, SharedFunction, SortKey,
+    Transform,
+};
+use log::{error, info, LevelFilter};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufWriter, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The command run when the options are given without one.
+const DEFAULT_COMMAND: &str = "trim";
 
 /// Las file trimmer
 ///
@@ -17,21 +78,1224 @@ use std::sync::Arc;
     long_about = "This tool reads LAS and LAZ files and optionally trims some points based on specified criteria. Using the excellent las-rs crate (https://docs.rs/las/latest/las/) that does most of the heavy lifting."
 )]
 struct Cli {
-    /// Sets the input file or folder
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    logging: LoggingArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Writes the points meeting each filter to the output paired with it. The default command,
+    /// run when the options are given without a command
+    Trim(TrimArgs),
+    /// Writes the points of all the inputs to a single output
+    Merge(MergeArgs),
+    /// Spreads the points over output files of at most a given number of points
+    Split(SplitArgs),
+    /// Cuts the points into square tiles on a grid
+    Tile(TileArgs),
+    /// Splits the points into an output per flight line, found by gaps in their GPS times
+    Lines(LinesArgs),
+    /// Writes the points inside each feature of a GeoJSON polygon layer to an output of its own,
+    /// named from a property of the feature
+    Clip(ClipArgs),
+    /// Prints what the header of each input says about it
+    Info(InputArgs),
+    /// Reads every point of each input and checks the file against the LAS specification and the
+    /// points against the header. Exits with code 1 if any input has errors
+    Validate(ValidateArgs),
+    /// Copies a file with the point counts, bounds and points by return of its header fixed to
+    /// match its points, which are copied unchanged
+    Repair(RepairArgs),
+    /// Compares two files point by point. Exits with code 1 if they differ
+    Diff(DiffArgs),
+    /// Writes a GeoTIFF of the number of points in each cell of a grid, to check the density of
+    /// a survey
+    Density(DensityArgs),
+    /// Writes a terrain model gridded from the ground points, or a surface model gridded from the
+    /// first returns, to a GeoTIFF
+    Surface(SurfaceArgs),
+    /// Writes the outline of the points of each input as GeoJSON, as a convex hull or following
+    /// the grid cells holding points
+    Boundary(BoundaryArgs),
+    /// Writes the points in a thin vertical slice along a line, with their distance along and
+    /// from the line, to CSV or to LAS/LAZ
+    Profile(ProfileArgs),
+    /// Writes a CSV index of the inputs with their point counts and bounds
+    Index(IndexArgs),
+    /// Reads a file with different thread counts, batch sizes and channel depths and reports the
+    /// throughput of each combination
+    Bench(BenchArgs),
+    /// Runs a queue of jobs, each a command line of this tool without the program name, one after
+    /// the other or several at once, and prints a summary of each. Exits with an error if any job
+    /// failed
+    Queue(QueueArgs),
+}
+
+impl Command {
+    /// The processing options of the commands that read and write points.
+    fn processing(&self) -> Option<&ProcessingArgs> {
+        match self {
+            Command::Trim(args) => Some(&args.processing),
+            Command::Merge(args) => Some(&args.processing),
+            Command::Split(args) => Some(&args.processing),
+            Command::Tile(args) => Some(&args.processing),
+            Command::Lines(args) => Some(&args.processing),
+            Command::Clip(args) => Some(&args.processing),
+            _ => None,
+        }
+    }
+}
+
+/// Options on diagnostic output, accepted by every command.
+#[derive(Args)]
+struct LoggingArgs {
+    /// Sets how much diagnostic output is printed. Defaults to the levels in `RUST_LOG`, which
+    /// may be set by module as in `warn,las_trimmer=debug`, or `info`
+    #[arg(long, value_name = "LEVEL", global = true)]
+    log_level: Option<LogLevel>,
+
+    /// Appends the full diagnostic output, including per-file statistics, to this file whatever
+    /// the console verbosity
+    #[arg(long, value_name = "FILE", global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Only prints errors, and no progress unless `--progress` is given. Suitable for cron jobs
+    #[arg(short, long, conflicts_with = "verbose", global = true)]
+    quiet: bool,
+
+    /// Prints more detail. Use `-vv` for even more
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+}
+
+/// Where the points are read from.
+#[derive(Args)]
+struct InputArgs {
+    /// Sets the input file or folder. Use `-` to read a LAS/LAZ stream from stdin, which is
+    /// held in memory as a whole, an `s3://`, `gs://` or `az://` URL to read from object storage, an `https://` URL,
+    /// or a .zip archive of LAS/LAZ files
     #[arg(short, long, value_name = "INPUT")]
     input: Vec<PathBuf>,
 
-    /// Sets the output files. File types must be either .las or .laz
-    #[arg(short, long, value_name = "OUTPUTS")]
-    output: Vec<PathBuf>,
+    /// Reads additional inputs from a text file with one path per line. Blank lines and lines
+    /// starting with `#` are ignored
+    #[arg(long, value_name = "FILE")]
+    input_list: Option<PathBuf>,
+
+    /// Descends into subdirectories of folder inputs
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// File extensions picked up from folder inputs
+    #[arg(long, value_name = "EXTENSIONS", value_delimiter = ',', default_values_t = DEFAULT_EXTENSIONS.map(String::from))]
+    extensions: Vec<String>,
+}
+
+/// How the points are read and written by the commands writing outputs.
+#[derive(Args)]
+struct ProcessingArgs {
+    /// Serves Prometheus metrics on `http://<ADDR>/metrics`, e.g. `127.0.0.1:9898`. Mostly
+    /// useful with `trim --watch`
+    #[arg(long, value_name = "ADDR")]
+    metrics_addr: Option<String>,
+
+    /// Serves the state of the current job as JSON on `http://<ADDR>/status` and as a page on
+    /// `http://<ADDR>/`: the files being read, percentage complete and estimated time left
+    #[arg(long, value_name = "ADDR")]
+    status_addr: Option<String>,
+
+    /// Number of threads reading the inputs. Defaults to the number of cores minus the ones
+    /// used for writing
+    #[arg(long, value_name = "N")]
+    threads: Option<usize>,
+
+    /// Number of points sent from the readers to the writers at a time
+    #[arg(long, value_name = "POINTS")]
+    batch_size: Option<u64>,
+
+    /// Number of batches that may wait to be written before the readers are held back
+    #[arg(long, value_name = "BATCHES")]
+    channel_depth: Option<usize>,
+
+    /// Adjusts the batch size and channel depth while running, starting from the values above.
+    /// The values picked are printed in the summary
+    #[arg(long)]
+    auto_tune: bool,
+
+    /// Limits the memory taken by points waiting to be written, e.g. `4G` or `512M`. Readers are
+    /// held back when it is reached
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_memory: Option<u64>,
+
+    /// Holds as little in memory as possible, at the cost of speed: small batches, a shallow
+    /// channel and sorts and neighbourhood filters spilling to disk early
+    #[arg(long, conflicts_with = "auto_tune")]
+    low_memory: bool,
+
+    /// Folder for the temporary files of sorts and neighbourhood filters. Defaults to the
+    /// system's temporary folder
+    #[arg(long, value_name = "DIR")]
+    spill_dir: Option<PathBuf>,
+
+    /// Writes the points of each output sorted by this key. Points that don't fit in memory are
+    /// sorted in runs on disk and merged
+    #[arg(long, value_name = "KEY")]
+    sort_by: Option<SortMode>,
+
+    /// Writes the runs of `--sort-by` to disk as LAZ, taking less room at some cost in speed
+    #[arg(long, requires = "sort_by")]
+    compress_spill: bool,
+
+    /// Number of threads writing the outputs, each taking a share of them. Defaults to one per
+    /// output
+    #[arg(long, value_name = "N")]
+    writer_threads: Option<usize>,
+
+    /// Number of points per chunk in LAZ outputs. Larger chunks compress a little better, smaller
+    /// ones make seeking cheaper
+    #[arg(long, value_name = "POINTS", conflicts_with = "laz_variable_chunks")]
+    laz_chunk_size: Option<u32>,
+
+    /// Ends a chunk of a LAZ output after every batch instead of after a fixed number of points
+    #[arg(long)]
+    laz_variable_chunks: bool,
+
+    /// Compresses the chunks of each LAZ output on every core at once rather than on its writer
+    /// thread, for runs with few outputs where compression is the bottleneck. A chunk per core is
+    /// held in memory for each output
+    #[arg(long, conflicts_with = "laz_variable_chunks")]
+    parallel_compression: bool,
+
+    /// How the work is spread over threads
+    #[arg(long, value_name = "BACKEND", default_value = "threads")]
+    backend: BackendMode,
+
+    /// Memory-maps uncompressed local LAS inputs instead of reading them through a buffer. Needs
+    /// the `mmap` feature
+    #[arg(long)]
+    mmap: bool,
+
+    /// Abandons an input file when reading it makes no progress for this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    file_timeout: Option<u64>,
+
+    /// What to do when an input file can't be read. With `skip` the file is left out, the other
+    /// files are processed and the run exits with code 2
+    #[arg(long, value_name = "POLICY", default_value = "abort")]
+    on_error: OnErrorMode,
+
+    /// Skips up to this many points per file that can't be decoded before the file counts as
+    /// unreadable
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    max_point_errors: u64,
+
+    /// How progress is shown. Defaults to `bars` when stderr is a terminal and `plain` otherwise
+    #[arg(long, value_name = "MODE")]
+    progress: Option<ProgressMode>,
+
+    /// Overwrites output files that already exist
+    #[arg(long)]
+    force: bool,
+
+    /// Adds the points to the end of output files that already exist, which must have the point
+    /// format, scales and offsets of the output, and updates their headers. The points are added
+    /// to a copy of each file, which only replaces it once the run has succeeded
+    #[arg(long, conflicts_with_all = ["force", "skip_existing"])]
+    append: bool,
+
+    /// Checks that the outputs fit in the free disk space before reading any point, sizing them
+    /// as if every point was kept, so runs whose filters keep few points may be refused
+    #[arg(long)]
+    space_check: bool,
+
+    /// Reads every output back once it is written and fails if its header doesn't count the
+    /// points written or its last point can't be decoded
+    #[arg(long)]
+    verify: bool,
+
+    /// Also writes a GeoTIFF of the number of points read in each cell of a grid. `{stem}` is
+    /// replaced like in the outputs
+    #[arg(long, value_name = "FILE")]
+    density: Option<PathBuf>,
+
+    /// The side of the cells of the density raster, in the units of the coordinates
+    #[arg(long, value_name = "SIZE", default_value_t = 1.0, value_parser = parse_cell_size)]
+    density_cell_size: f64,
+
+    /// Also writes a GeoTIFF of the mean intensity of the points read in each cell of a grid, for
+    /// a quick look at the survey. `{stem}` is replaced like in the outputs
+    #[arg(long, value_name = "FILE")]
+    intensity: Option<PathBuf>,
+
+    /// The side of the cells of the intensity raster, in the units of the coordinates
+    #[arg(long, value_name = "SIZE", default_value_t = 1.0, value_parser = parse_cell_size)]
+    intensity_cell_size: f64,
+
+    /// Also writes forestry metrics for each cell of a grid: the canopy cover, the share of first
+    /// returns that aren't ground, then the maximum and percentile heights above the ground. To a
+    /// GeoTIFF with a band per metric, or to CSV with a .csv extension. `{stem}` is replaced like
+    /// in the outputs
+    #[arg(long, value_name = "FILE")]
+    canopy: Option<PathBuf>,
+
+    /// The side of the cells of the canopy metrics, in the units of the coordinates
+    #[arg(long, value_name = "SIZE", default_value_t = 20.0, value_parser = parse_cell_size)]
+    canopy_cell_size: f64,
+
+    /// The percentile heights in the canopy metrics, from 0 to 100. Defaults to 25, 50, 75 and 95
+    #[arg(
+        long,
+        value_name = "PERCENTILE,...",
+        value_delimiter = ',',
+        value_parser = parse_percentile
+    )]
+    canopy_percentiles: Vec<f64>,
+
+    /// Also writes a terrain model gridded from the points classified as ground to this GeoTIFF.
+    /// `{stem}` is replaced like in the outputs
+    #[arg(long, value_name = "FILE")]
+    dtm: Option<PathBuf>,
+
+    /// Also writes a surface model gridded from the first returns to this GeoTIFF. `{stem}` is
+    /// replaced like in the outputs
+    #[arg(long, value_name = "FILE")]
+    dsm: Option<PathBuf>,
+
+    /// How the height of each cell of the terrain and surface models is found
+    #[arg(long, value_name = "METHOD", default_value = "tin")]
+    surface_method: SurfaceMethod,
+
+    /// The side of the cells of the terrain and surface models, in the units of the coordinates
+    #[arg(long, value_name = "SIZE", default_value_t = 1.0, value_parser = parse_cell_size)]
+    surface_cell_size: f64,
+
+    /// Skips processing when the outputs already exist, for resuming interrupted batch jobs
+    #[arg(long, value_name = "MODE", num_args = 0..=1, default_missing_value = "exists")]
+    skip_existing: Option<SkipExistingMode>,
 
     /// Strips extra bytes from the LAS/LAZ file. Can dramatically decrease resulting size
     #[arg(short, long, value_name = "Strip extra bytes")]
     strip_extra_bytes: bool,
 
-    /// Specifies the filtering function to apply to points.
+    /// Writes the points of full-waveform formats (4, 5, 9 and 10) in the same format without the
+    /// waveform fields, and leaves out the waveform descriptors and data, which can't be copied.
+    /// Inputs with waveforms fail without it
+    #[arg(long)]
+    drop_waveforms: bool,
+
+    /// Writes the outputs in the smallest point format holding what the points use, dropping
+    /// black color, a GPS time shared by every point and all-zero extra bytes. Reads the inputs
+    /// an extra time to find out
+    #[arg(long)]
+    minimize_format: bool,
+
+    /// Writes outputs that software older than LAS 1.4 reads correctly: in point formats 0 to 5,
+    /// with the coordinate system as GeoTIFF keys and the legacy point counts set. Classes above
+    /// 31 become unclassified, and return numbers stop at 7
+    #[arg(long)]
+    legacy_compatible: bool,
+
+    /// Stretches the intensities between two percentiles of every intensity, 2 and 98 unless they
+    /// are given, onto the whole range, clipping the others, for viewers showing intensities as
+    /// they are. Reads the inputs an extra time to find the percentiles
+    #[arg(
+        long,
+        value_name = "LOW,HIGH",
+        num_args = 0..=1,
+        default_missing_value = "2,98",
+        value_parser = parse_percentile_range
+    )]
+    stretch_intensity: Option<(f64, f64)>,
+
+    /// Colors the points by their intensity or elevation, writing the outputs in a point format
+    /// with color, so that scans without color look reasonable in viewers that ignore intensity.
+    /// Elevations span the colormap from the lowest to the highest point of the input headers.
+    /// Applied after `--stretch-intensity` and before the other color options
+    #[arg(long, value_name = "VALUE")]
+    color_from: Option<ColorFromMode>,
+
+    /// The colormap of `--color-from`, gray for intensities and terrain for elevations unless
+    /// it is given
+    #[arg(long, value_name = "NAME", requires = "color_from")]
+    colormap: Option<ColormapMode>,
+
+    /// Colors the points by their class, for ready-to-view QA copies of classified datasets,
+    /// writing the outputs in a point format with color. The standard ASPRS classes have their
+    /// usual colors, which a JSON table from classes to colors, such as `{"2": "#aa5500"}`, can
+    /// change. Applied before the other color options
+    #[arg(
+        long,
+        value_name = "PALETTE",
+        num_args = 0..=1,
+        conflicts_with = "color_from"
+    )]
+    color_by_class: Option<Option<PathBuf>>,
+
+    /// Only keeps the points in the area every input covers, for strip-to-strip comparisons and
+    /// calibration checks. Without a cell size, the area is where the bounds in the headers of the
+    /// inputs intersect. With one, it is made of the cells of a grid of that size that every input
+    /// has points in, which follows strips crossing at an angle but reads the inputs an extra time
+    #[arg(
+        long,
+        value_name = "CELL_SIZE",
+        num_args = 0..=1,
+        value_parser = parse_cell_size
+    )]
+    crop_to_overlap: Option<Option<f64>>,
+
+    /// Converts the GPS times of the points to this representation and marks the outputs as
+    /// holding it, so inputs of both kinds can be merged with consistent timestamps
+    #[arg(long, value_name = "TYPE")]
+    gps_time: Option<GpsTimeMode>,
+
+    /// The GPS week of the inputs in GPS week time, needed to convert them to standard time
+    #[arg(long, value_name = "WEEK", requires = "gps_time")]
+    gps_week: Option<u32>,
+
+    /// Seconds added to every GPS time once it is converted
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        allow_hyphen_values = true,
+        requires = "gps_time"
+    )]
+    gps_time_offset: Option<f64>,
+
+    /// Records which input each point came from, by its index in the order of the inputs, and
+    /// lists the inputs in a VLR of the outputs
+    #[arg(long, value_name = "WHERE")]
+    tag_source: Option<TagSourceMode>,
+
+    /// Sets the point source ID of the points of an input, e.g. `strip_03.las=17`, for strips
+    /// delivered without them. The file matches the inputs whose path ends with it. Can be
+    /// repeated
+    #[arg(long, value_name = "FILE=ID", value_parser = parse_source_id)]
+    assign_source_id: Vec<(String, u16)>,
+
+    /// Sets the point source ID of each point to the number of its flight line, counted from 1 in
+    /// time order, for datasets delivered without IDs. A line ends wherever no point was recorded
+    /// for more than this many seconds. Replaces the IDs of `--assign-source-id`, and reads the
+    /// inputs an extra time to find the lines
+    #[arg(long, value_name = "SECONDS", value_parser = parse_gap)]
+    flight_line_ids: Option<f64>,
+
+    /// Moves the points of each input listed in FILE by its shift and rotation, e.g. the results
+    /// of a strip adjustment. FILE is a CSV with a header naming its columns: `file` and any of
+    /// `dx`, `dy`, `dz` for the shift, `rx`, `ry`, `rz` for the rotation in degrees and `cx`,
+    /// `cy`, `cz` for its center, by default the middle of the input. A .json FILE maps each file
+    /// to its `translation`, `rotation` and `center`. The filters see the points unmoved
+    #[arg(long, value_name = "FILE")]
+    adjust: Option<PathBuf>,
+
+    /// Prepares the outputs for a public release of a sensitive dataset in one step: truncates
+    /// the coordinates to 1 m, or to `--truncate-coordinates`, removes the GPS times and strips
+    /// the identifying metadata
+    #[arg(long)]
+    anonymize: bool,
+
+    /// Truncates the coordinates of the points written down to multiples of this many units, e.g.
+    /// 1 for whole meters, coarsening the scale of the outputs to match
+    #[arg(long, value_name = "PRECISION", value_parser = parse_cell_size)]
+    truncate_coordinates: Option<f64>,
+
+    /// Removes the GPS times of the points written, leaving them out of the point formats that
+    /// can do without and zeroing them in the others
+    #[arg(long)]
+    drop_gps_time: bool,
+
+    /// Clears the system identifier, generating software, project GUID, creation date and file
+    /// source ID of the outputs, and drops every VLR but the CRS and extra bytes descriptions
+    #[arg(long)]
+    strip_metadata: bool,
+
+    /// Sets the system identifier of the outputs, or clears it if empty
+    #[arg(long, value_name = "TEXT", value_parser = parse_header_field)]
+    system_identifier: Option<String>,
+
+    /// Sets the generating software of the outputs, or clears it if empty. By default it is
+    /// `las-trimmer vX.Y`
+    #[arg(long, value_name = "TEXT", value_parser = parse_header_field)]
+    generating_software: Option<String>,
+
+    /// Sets the project GUID of the outputs, e.g. `6f1c2a9e-3b4d-4e5f-8a7b-9c0d1e2f3a4b`, or
+    /// clears it if empty
+    #[arg(long, value_name = "GUID", value_parser = parse_guid)]
+    guid: Option<uuid::Uuid>,
+
+    /// Sets the creation date of the outputs, as YYYY-MM-DD or `today`, or clears it if empty
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    creation_date: Option<DateStamp>,
+
+    /// Copies the VLRs and EVLRs of every input to the outputs, not only those of the first,
+    /// keeping one of each set of identical records. Records of the same kind that differ fail
+    /// the run, or the record of the first or last input having it is kept
+    #[arg(
+        long,
+        value_name = "CONFLICTS",
+        num_args = 0..=1,
+        default_missing_value = "fail"
+    )]
+    merge_vlrs: Option<VlrConflictMode>,
+
+    /// Sets a flag, `withheld`, `synthetic`, `key-point` or `overlap`, on the points going to the
+    /// outputs. `FLAG=PRESET` only sets it on the points a preset keeps and `FLAG=!PRESET` on the
+    /// ones it drops, e.g. `withheld=!survey-area` to withhold the points outside an area instead
+    /// of deleting them. Can be repeated
+    #[arg(long, value_name = "FLAG[=[!]PRESET]", value_parser = parse_flag_change)]
+    set_flag: Vec<FlagChange>,
+
+    /// Clears a flag on the points going to the outputs, like `--set-flag` sets it. Flags are
+    /// cleared after they are set
+    #[arg(long, value_name = "FLAG[=[!]PRESET]", value_parser = parse_flag_change)]
+    clear_flag: Vec<FlagChange>,
+
+    /// Multiplies the red, green and blue channels of the points by these gains, to balance
+    /// the colors of clouds captured under different light. Applied before the gamma
+    #[arg(long, value_name = "R,G,B", value_parser = parse_gains)]
+    color_gain: Option<[f64; 3]>,
+
+    /// Applies a gamma to the colors of the points, above 1 to brighten the mid-tones and below 1
+    /// to darken them. Applied before the brightness
+    #[arg(long, value_name = "GAMMA", value_parser = parse_gamma)]
+    color_gamma: Option<f64>,
+
+    /// Adds a fraction of the full range, from -1 to 1, to each color channel of the points,
+    /// negative to darken
+    #[arg(
+        long,
+        value_name = "FRACTION",
+        allow_hyphen_values = true,
+        value_parser = parse_brightness
+    )]
+    color_brightness: Option<f64>,
+
+    /// Drops the points with fewer than this many other points within `--neighbor-radius`, such
+    /// as isolated noise. The points are spilled to temporary files by tile and only filtered
+    /// and written once every input has been read
+    #[arg(long, value_name = "K", requires = "neighbor_radius")]
+    min_neighbors: Option<usize>,
+
+    /// The 3D radius that `--min-neighbors` counts the neighbours of a point in
+    #[arg(
+        long,
+        value_name = "RADIUS",
+        value_parser = parse_radius,
+        requires = "min_neighbors"
+    )]
+    neighbor_radius: Option<f64>,
+
+    /// Checks the inputs and outputs and prints the planned outputs with their largest possible
+    /// size, from the headers of the inputs and without reading any points
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct TrimArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+
+    /// Sets the output files. File types must be either .las or .laz. If every output contains
+    /// `{stem}`, each input is processed on its own and `{stem}` is replaced by its file name
+    /// without the extension. Use `-` to write uncompressed LAS to stdout.
+    /// `s3://`, `gs://` and `az://` URLs are uploaded to object storage
+    #[arg(short, long, value_name = "OUTPUTS")]
+    output: Vec<PathBuf>,
+
+    /// Specifies the filtering function to apply to points, one per output. May be left out when
+    /// presets or flags such as `--keep-first` are given, to keep the points they keep in every
+    /// output
     #[arg(short, long, value_name = "FILTER")]
     filter: Vec<FilterType>,
+
+    /// Narrows every output to the points a named preset keeps, such as `ground-only`,
+    /// `buildings` or `denoise-aerial`. Can be repeated to apply several presets
+    #[arg(long, value_name = "NAME")]
+    preset: Vec<String>,
+
+    #[command(flatten)]
+    stages: StageArgs,
+
+    /// Lists the built-in presets and the ones in the `presets` folder of the configuration
+    /// directory, `$LAS_TRIMMER_CONFIG_DIR` or `~/.config/las_trimmer`, then exits
+    #[arg(long)]
+    list_presets: bool,
+
+    /// Watches a folder and processes new LAS/LAZ files as they appear. Output paths must contain
+    /// `{stem}`, which is replaced by the name of each incoming file without its extension
+    #[arg(long, value_name = "DIR", conflicts_with = "dry_run")]
+    watch: Option<PathBuf>,
+
+    /// Records completed inputs in this file and skips the inputs it already lists, so an
+    /// interrupted run can be resumed. Needs per-input outputs using `{stem}`
+    #[arg(long, value_name = "FILE")]
+    journal: Option<PathBuf>,
+
+    /// Reads the inputs, outputs and other options from a pipeline file in TOML, JSON or YAML, or
+    /// from a PDAL pipeline in JSON. Options given on the command line replace the ones in the file
+    #[arg(long, value_name = "FILE")]
+    pipeline: Option<PathBuf>,
+
+    /// Writes statistics of the points of each output to this JSON file, or to stdout with `-`:
+    /// their count, bounds, classes, returns, and intensity and GPS time ranges
+    #[arg(long, value_name = "FILE")]
+    stats: Option<PathBuf>,
+
+    /// Writes no output, only gathers the `--stats`, printed to stdout without a file, and the
+    /// rasters such as `--density`. Outputs then only name the sets of points statistics are
+    /// gathered for, and can be left out to gather them for every point kept
+    #[arg(long, conflicts_with_all = ["watch", "journal", "dry_run", "skip_existing"])]
+    stats_only: bool,
+}
+
+/// Shortcuts for common filters, which narrow every output like presets do.
+#[derive(Args)]
+struct StageArgs {
+    /// Keeps only the first return of each pulse
+    #[arg(long, conflicts_with_all = ["keep_last", "keep_single"])]
+    keep_first: bool,
+
+    /// Keeps only the last return of each pulse
+    #[arg(long, conflicts_with = "keep_single")]
+    keep_last: bool,
+
+    /// Keeps only the points of pulses with a single return
+    #[arg(long)]
+    keep_single: bool,
+
+    /// Keeps only the points recorded by this channel, from 0 to 3, of a multi-channel scanner.
+    /// Points in the formats before LAS 1.4 are all on channel 0
+    #[arg(long, value_name = "N", value_parser = parse_scanner_channel)]
+    keep_scanner_channel: Option<u8>,
+
+    /// Drops the points flagged as the edge of a flight line, the last point of each scan line,
+    /// which tend to be noisy at the boundaries between strips
+    #[arg(long)]
+    drop_edge_of_flight_line: bool,
+
+    /// Keeps only the points scanned while the mirror moved in this direction
+    #[arg(long, value_name = "DIRECTION")]
+    keep_scan_direction: Option<ScanDirectionMode>,
+
+    /// Keeps only the points inside the polygons of this GeoJSON file, in the coordinates of the
+    /// points or of `--filter-crs`
+    #[arg(long, value_name = "GEOJSON")]
+    clip: Option<PathBuf>,
+
+    /// Drops the points inside the polygons of this GeoJSON file and keeps everything else, e.g.
+    /// to redact private properties from a published dataset
+    #[arg(long, value_name = "GEOJSON")]
+    clip_outside: Option<PathBuf>,
+
+    /// Keeps only the points within `--within` of this position or polyline, e.g. around a survey
+    /// monument or along a pipeline, given as `X,Y[,Z]` vertices separated by `;`. The distance is
+    /// 3D when the vertices have a Z and horizontal when they don't
+    #[arg(
+        long,
+        value_name = "X,Y[,Z];...",
+        allow_hyphen_values = true,
+        value_parser = parse_polyline,
+        requires = "within"
+    )]
+    near: Option<Polyline>,
+
+    /// The distance from `--near` within which points are kept
+    #[arg(long, value_name = "DISTANCE", value_parser = parse_radius, requires = "near")]
+    within: Option<f64>,
+
+    /// Keeps only the points inside this box, in the coordinates of the points or of
+    /// `--filter-crs`
+    #[arg(
+        long,
+        value_name = "MINX,MINY,MAXX,MAXY",
+        allow_hyphen_values = true,
+        value_parser = parse_bbox
+    )]
+    bbox: Option<[f64; 4]>,
+
+    /// The coordinate system `--bbox`, `--near`, `--clip` and `--clip-outside` are given in when
+    /// it isn't the one of the points, e.g. `EPSG:4326` for WGS 84 longitudes and latitudes. They
+    /// are projected to the coordinate system of the first input, which must be one of the common
+    /// geographic, UTM, MGA or NZTM systems
+    #[arg(long, value_name = "EPSG:CODE", value_parser = parse_epsg)]
+    filter_crs: Option<u32>,
+
+    /// The coordinate system of the points, for inputs that don't say or say it differently
+    #[arg(
+        long,
+        value_name = "EPSG:CODE",
+        value_parser = parse_epsg,
+        requires = "filter_crs"
+    )]
+    data_crs: Option<u32>,
+}
+
+impl StageArgs {
+    /// The filter stages the flags stand for, with the spatial ones projected to the coordinate
+    /// system of the first of `paths`. Fails if a polygon file can't be read.
+    fn stages(&self, paths: &[String]) -> Result<Vec<NumericFilter>, MyError> {
+        let mut stages = Vec::new();
+        for (flag, selection) in [
+            (self.keep_first, ReturnSelection::First),
+            (self.keep_last, ReturnSelection::Last),
+            (self.keep_single, ReturnSelection::Single),
+        ] {
+            if flag {
+                stages.push(NumericFilter::Returns(selection));
+            }
+        }
+        if let Some(channel) = self.keep_scanner_channel {
+            stages.push(NumericFilter::ScannerChannel(channel));
+        }
+        if self.drop_edge_of_flight_line {
+            stages.push(NumericFilter::EdgeOfFlightLine(false));
+        }
+        if let Some(direction) = self.keep_scan_direction {
+            stages.push(NumericFilter::ScanDirection(direction.into()));
+        }
+        let reprojection = match (self.filter_crs, self.data_crs) {
+            (Some(from), Some(to)) => Some(Reprojection::between(from, to)?),
+            (Some(from), None) => {
+                let crs = Crs::from_inputs(paths).ok_or(MyError::DataCrsRequired)?;
+                Some(Reprojection::new(from, &crs)?)
+            }
+            (None, _) => None,
+        };
+        if let Some([min_x, min_y, max_x, max_y]) = self.bbox {
+            stages.push(match &reprojection {
+                // The edges of the box curve once projected, so they are followed closely
+                Some(reprojection) => {
+                    let steps = 16;
+                    let corners = [
+                        [min_x, min_y],
+                        [max_x, min_y],
+                        [max_x, max_y],
+                        [min_x, max_y],
+                    ];
+                    let ring = (0..4)
+                        .flat_map(|side| {
+                            let ([x0, y0], [x1, y1]) = (corners[side], corners[(side + 1) % 4]);
+                            (0..steps).map(move |step| {
+                                let t = f64::from(step) / f64::from(steps);
+                                [x0 + (x1 - x0) * t, y0 + (y1 - y0) * t]
+                            })
+                        })
+                        .collect();
+                    let polygons =
+                        Polygons::new(vec![vec![ring]])?.map(|x, y| reprojection.apply(x, y))?;
+                    NumericFilter::Polygons(Arc::new(polygons), true)
+                }
+                None => NumericFilter::Bounds(las::Bounds {
+                    min: las::Vector {
+                        x: min_x,
+                        y: min_y,
+                        z: f64::NEG_INFINITY,
+                    },
+                    max: las::Vector {
+                        x: max_x,
+                        y: max_y,
+                        z: f64::INFINITY,
+                    },
+                }),
+            });
+        }
+        if let (Some(polyline), Some(distance)) = (&self.near, self.within) {
+            let corridor = Corridor::new(polyline.vertices.clone(), distance, polyline.horizontal)?;
+            let corridor = match &reprojection {
+                Some(reprojection) => corridor.map(|x, y| reprojection.apply(x, y))?,
+                None => corridor,
+            };
+            stages.push(NumericFilter::Corridor(Arc::new(corridor)));
+        }
+        for (path, inside) in [(&self.clip, true), (&self.clip_outside, false)] {
+            if let Some(path) = path {
+                let polygons = Polygons::from_path(path)?;
+                let polygons = match &reprojection {
+                    Some(reprojection) => polygons.map(|x, y| reprojection.apply(x, y))?,
+                    None => polygons,
+                };
+                stages.push(NumericFilter::Polygons(Arc::new(polygons), inside));
+            }
+        }
+        Ok(stages)
+    }
+}
+
+#[derive(Args)]
+struct MergeArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+
+    /// Sets the output file, either .las or .laz. Use `-` to write uncompressed LAS to stdout
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: PathBuf,
+
+    /// Merges inputs that are each sorted by GPS time into an output sorted by GPS time, reading
+    /// them in step rather than in parallel. Fails on an input found out of order, or with GPS
+    /// times of another type than the first input. Of the processing options, only the LAZ
+    /// chunking, `--force` and `--verify` are accepted. Use `--sort-by gps-time` for inputs that
+    /// aren't sorted
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "metrics_addr", "status_addr", "threads", "batch_size", "channel_depth", "auto_tune",
+            "max_memory", "low_memory", "spill_dir", "sort_by", "compress_spill", "writer_threads",
+            "backend", "mmap", "file_timeout", "on_error", "max_point_errors", "progress", "append",
+            "space_check", "density", "density_cell_size", "intensity", "intensity_cell_size",
+            "canopy", "canopy_cell_size", "canopy_percentiles", "dtm", "dsm", "surface_method",
+            "surface_cell_size", "skip_existing", "strip_extra_bytes", "drop_waveforms",
+            "minimize_format", "legacy_compatible", "stretch_intensity", "color_from", "colormap",
+            "color_by_class", "crop_to_overlap", "gps_time", "gps_week", "gps_time_offset",
+            "tag_source", "assign_source_id", "flight_line_ids", "adjust", "anonymize",
+            "truncate_coordinates", "drop_gps_time", "strip_metadata", "system_identifier",
+            "generating_software", "guid", "creation_date", "merge_vlrs", "set_flag", "clear_flag",
+            "color_gain", "color_gamma", "color_brightness", "min_neighbors", "neighbor_radius",
+            "dry_run",
+        ]
+    )]
+    by_gps_time: bool,
+}
+
+#[derive(Args)]
+struct SplitArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+
+    /// Sets the output files, either .las or .laz. `{part}` is replaced by the number of each
+    /// file, which is otherwise added before the extension. If the path contains `{stem}`, each
+    /// input is split on its own
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: PathBuf,
+
+    /// The largest number of points in an output file
+    #[arg(long, value_name = "POINTS")]
+    points: u64,
+}
+
+#[derive(Args)]
+struct TileArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+
+    /// Sets the output files, either .las or .laz. `{x}` and `{y}` are replaced by the lowest
+    /// coordinates of each tile. Tiles no point falls in aren't written
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: PathBuf,
+
+    /// The side of the tiles, in the units of the coordinates
+    #[arg(long, value_name = "SIZE", value_parser = parse_tile_size)]
+    size: f64,
+}
+
+#[derive(Args)]
+struct LinesArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+
+    /// Sets the output files, either .las or .laz. `{line}` is replaced by the number of each
+    /// flight line, counted from 1 in time order. Points without a GPS time aren't written
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: PathBuf,
+
+    /// The time without points, in seconds, that ends a flight line
+    #[arg(long, value_name = "SECONDS", value_parser = parse_gap)]
+    gap: f64,
+}
+
+#[derive(Args)]
+struct ClipArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs,
+
+    /// Sets the output files, either .las or .laz. `{name}` is replaced by the name of each
+    /// feature, with the characters that don't belong in a file name replaced by `_`
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: PathBuf,
+
+    /// The GeoJSON FeatureCollection of the polygons, in the coordinate system of the points.
+    /// Features without polygons are left out
+    #[arg(long, value_name = "GEOJSON")]
+    polygons: PathBuf,
+
+    /// The property naming the output of each feature, e.g. a parcel ID. Features with the same
+    /// name share an output. Without it, the features are named by their index in the collection
+    #[arg(long, value_name = "PROPERTY")]
+    name_field: Option<String>,
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    /// Prints the reports as a JSON array, with the severity and message of every issue
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct RepairArgs {
+    /// The local LAS/LAZ file to repair
+    input: PathBuf,
+
+    /// The repaired copy
+    output: PathBuf,
+
+    /// Leaves out VLRs with the same user id, record id and data as an earlier VLR
+    #[arg(long)]
+    drop_duplicate_vlrs: bool,
+
+    /// Overwrites the output if it already exists
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    /// The first file
+    first: PathBuf,
+
+    /// The file compared to the first
+    second: PathBuf,
+
+    /// Lets the values of a dimension differ by up to this much, e.g. `z=0.01`. Can be repeated
+    #[arg(long, value_name = "DIMENSION=DELTA", value_parser = parse_tolerance)]
+    tolerance: Vec<(String, f64)>,
+
+    /// The number of differing points to describe
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    points: usize,
+}
+
+#[derive(Args)]
+struct DensityArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    /// The GeoTIFF to write, with a .tif or .tiff extension
+    #[arg(short, long, value_name = "FILE")]
+    output: PathBuf,
+
+    /// The side of the cells, in the units of the coordinates. With 1, the raster holds the
+    /// points per square unit
+    #[arg(long, value_name = "SIZE", default_value_t = 1.0, value_parser = parse_cell_size)]
+    cell_size: f64,
+
+    /// Only counts the points a named preset keeps. Can be repeated
+    #[arg(long, value_name = "NAME")]
+    preset: Vec<String>,
+}
+
+#[derive(Args)]
+struct SurfaceArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    /// The GeoTIFF to write, with a .tif or .tiff extension
+    #[arg(short, long, value_name = "FILE")]
+    output: PathBuf,
+
+    /// The elevation model to make
+    #[arg(long, value_name = "KIND", default_value = "dtm")]
+    kind: SurfaceKindMode,
+
+    /// How the height of each cell is found
+    #[arg(long, value_name = "METHOD", default_value = "tin")]
+    method: SurfaceMethod,
+
+    /// The side of the cells, in the units of the coordinates
+    #[arg(long, value_name = "SIZE", default_value_t = 1.0, value_parser = parse_cell_size)]
+    cell_size: f64,
+}
+
+#[derive(Args)]
+struct BoundaryArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    /// Writes the GeoJSON to this file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Follows the edges of the grid cells of this size holding points instead of taking the
+    /// convex hull, leaving out the gaps and holes between the points
+    #[arg(long, value_name = "CELL_SIZE", value_parser = parse_cell_size)]
+    concave: Option<f64>,
+
+    /// Writes a single outline of the points of all the inputs
+    #[arg(long)]
+    merge: bool,
+
+    /// Only outlines the points a named preset keeps. Can be repeated
+    #[arg(long, value_name = "NAME")]
+    preset: Vec<String>,
+}
+
+#[derive(Args)]
+struct ProfileArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    /// The file to write. A .csv file gets a line per point, sorted along the line. A .las or
+    /// .laz file keeps the points, with the station and offset as extra bytes
+    #[arg(short, long, value_name = "FILE")]
+    output: PathBuf,
+
+    /// The vertices of the line, in the units of the coordinates
+    #[arg(
+        long,
+        value_name = "X1,Y1,X2,Y2,...",
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        required = true
+    )]
+    line: Vec<f64>,
+
+    /// The width of the slice, centred on the line
+    #[arg(long, value_name = "WIDTH", value_parser = parse_cell_size)]
+    width: f64,
+
+    /// Overwrites the output if it exists
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+struct IndexArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    /// Writes the index to this file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct QueueArgs {
+    /// A folder of .job files, each holding a job, run in name order. Use `-` to read a job per
+    /// line from stdin, run as the lines come in until stdin is closed
+    queue: PathBuf,
+
+    /// Number of jobs run at once
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    jobs: usize,
+
+    /// Starts no more jobs once one has failed
+    #[arg(long)]
+    stop_on_error: bool,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// The file to read
+    input: PathBuf,
+
+    /// The reader thread counts to try. Defaults to powers of two up to the number of cores
+    #[arg(long, value_name = "N,...", value_delimiter = ',')]
+    threads: Vec<usize>,
+
+    /// The batch sizes to try
+    #[arg(
+        long,
+        value_name = "POINTS,...",
+        value_delimiter = ',',
+        default_value = "10000,100000,1000000"
+    )]
+    batch_sizes: Vec<u64>,
+
+    /// The channel depths to try
+    #[arg(
+        long,
+        value_name = "BATCHES,...",
+        value_delimiter = ',',
+        default_value = "4,20,64"
+    )]
+    channel_depths: Vec<usize>,
+
+    /// Writes the fastest settings to this file, as a pipeline file for `trim --pipeline`
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum SkipExistingMode {
+    /// Skip when the outputs exist
+    Exists,
+    /// Skip when the outputs exist and are newer than the inputs
+    Newer,
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum OnErrorMode {
+    /// Stop at the first input that can't be read
+    Abort,
+    /// Leave unreadable inputs out and process the rest
+    Skip,
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum BackendMode {
+    /// Reader threads feeding a writer thread per output
+    Threads,
+    /// A rayon pool whose threads both read and write, for inputs of very different sizes
+    Rayon,
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ProgressMode {
+    /// Progress bars with an ETA
+    Bars,
+    /// A progress line about once a second
+    Plain,
+    /// A JSON object per line about once a second, for orchestrators
+    Json,
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum SurfaceKindMode {
+    /// A terrain model, from the points classified as ground
+    Dtm,
+    /// A surface model, from the first returns
+    Dsm,
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum TagSourceMode {
+    /// In place of the point source ID
+    PointSourceId,
+    /// In a `source` attribute added to the extra bytes
+    ExtraByte,
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum VlrConflictMode {
+    /// Fail the run
+    Fail,
+    /// Keep the record of the first input having it
+    First,
+    /// Keep the record of the last input having it
+    Last,
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum GpsTimeMode {
+    /// Seconds since the start of the GPS week
+    Week,
+    /// Adjusted standard GPS time, the seconds since the GPS epoch minus 10^9
+    Standard,
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ColorFromMode {
+    /// The intensities, as a grayscale ramp unless another colormap is given
+    Intensity,
+    /// The Z coordinates, as an elevation ramp unless another colormap is given
+    Elevation,
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ColormapMode {
+    /// From black to white
+    Gray,
+    /// From dark purple through blue and green to yellow
+    Viridis,
+    /// From blue water through green lowlands and brown hills to white peaks
+    Terrain,
+    /// From blue through cyan, green and yellow to red
+    Rainbow,
+}
+
+impl From<ColormapMode> for Colormap {
+    fn from(colormap: ColormapMode) -> Self {
+        match colormap {
+            ColormapMode::Gray => Colormap::Gray,
+            ColormapMode::Viridis => Colormap::Viridis,
+            ColormapMode::Terrain => Colormap::Terrain,
+            ColormapMode::Rainbow => Colormap::Rainbow,
+        }
+    }
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum SortMode {
+    /// The GPS time, the order the points were scanned in
+    GpsTime,
+    /// The point source ID, then the GPS time, grouping the points by flight line
+    PointSourceId,
+    /// The elevation, lowest first
+    Z,
+    /// A Z-order curve over X and Y, keeping points near each other together in the file
+    Morton,
+}
+
+impl From<SortMode> for SortKey {
+    fn from(mode: SortMode) -> Self {
+        match mode {
+            SortMode::GpsTime => SortKey::GpsTime,
+            SortMode::PointSourceId => SortKey::PointSourceId,
+            SortMode::Z => SortKey::Z,
+            SortMode::Morton => SortKey::Morton,
+        }
+    }
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ScanDirectionMode {
+    /// The positive scan direction
+    LeftToRight,
+    /// The negative scan direction
+    RightToLeft,
+}
+
+impl From<ScanDirectionMode> for ScanDirection {
+    fn from(direction: ScanDirectionMode) -> Self {
+        match direction {
+            ScanDirectionMode::LeftToRight => ScanDirection::LeftToRight,
+            ScanDirectionMode::RightToLeft => ScanDirection::RightToLeft,
+        }
+    }
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum SurfaceMethod {
+    /// The height of the lowest point of the cell
+    Min,
+    /// The height of the highest point of the cell
+    Max,
+    /// The mean height of the points of the cell
+    Mean,
+    /// The height at the centre of the cell of a triangulation of the lowest point of each cell,
+    /// or the highest for a surface model. Fills the cells without points between the others
+    Tin,
+}
+
+impl From<SurfaceMethod> for GridMethod {
+    fn from(method: SurfaceMethod) -> Self {
+        match method {
+            SurfaceMethod::Min => GridMethod::Min,
+            SurfaceMethod::Max => GridMethod::Max,
+            SurfaceMethod::Mean => GridMethod::Mean,
+            SurfaceMethod::Tin => GridMethod::Tin,
+        }
+    }
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
 }
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum FilterType {
@@ -46,64 +1310,1725 @@ fn return_false(_point: &Point) -> bool {
     false
 }
 
-fn main() -> Result<(), MyError> {
-    let cli = Cli::parse();
+/// A condition keeping every point, for the commands that don't filter.
+fn keep_all() -> Condition {
+    Condition::on_view(Dimensions::XYZ, Arc::new(|_| true))
+}
 
-    let input_paths = cli.input;
-    let output_paths: Vec<String> = cli
-        .output
-        .iter()
-        .map(|p| p.to_string_lossy().to_string())
-        .collect();
-    let strip_extra_bytes = cli.strip_extra_bytes;
+/// The exit code used when some inputs were skipped because they couldn't be read.
+const PARTIAL_FAILURE_EXIT_CODE: i32 = 2;
 
-    // Check if the output files have valid extensions
-    for output_path in &output_paths {
-        let path_buf = PathBuf::from(output_path);
-        let output_extension = path_buf
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .unwrap_or("");
-        if output_extension != "las" && output_extension != "laz" {
-            return Err(MyError::InvalidOutputExtension);
+/// The name the statistics of `--stats-only` are gathered under when no output is given.
+const ALL_POINTS: &str = "all";
+
+fn main() -> Result<(), MyError> {
+    match parse_cli().and_then(run) {
+        Err(err @ MyError::PartialFailure(_)) => {
+            eprintln!("{:?}", err);
+            std::process::exit(PARTIAL_FAILURE_EXIT_CODE);
         }
+        result => result,
     }
+}
 
-    let mut paths = Vec::new();
-    for input_path in input_paths {
-        if input_path.is_file() {
-            paths.push(input_path.to_string_lossy().to_string());
-        } else if input_path.is_dir() {
-            let dir_paths: Vec<String> = fs::read_dir(input_path)?
-                .filter_map(Result::ok)
-                .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "las"))
-                .map(|entry| entry.path().to_string_lossy().to_string())
+fn run(cli: Cli) -> Result<(), MyError> {
+    let progress = cli.command.processing().and_then(|args| args.progress);
+    // Logs go out as JSON events too, so that every line on stderr parses
+    let json = progress == Some(ProgressMode::Json);
+    init_logging(&cli.logging, json, progress.is_some())?;
+    run_command(cli.command, cli.logging.quiet)
+}
+
+fn run_command(command: Command, quiet: bool) -> Result<(), MyError> {
+    match command {
+        Command::Trim(args) => trim(args, quiet),
+        Command::Merge(args) if args.by_gps_time => time_merge(args),
+        Command::Merge(args) => {
+            let outputs = vec![args.output.to_string_lossy().to_string()];
+            check_output_extensions(&outputs)?;
+            let paths = resolve_inputs(&args.inputs)?;
+            let job = Job::new(&args.processing, quiet, outputs, vec![keep_all()])?;
+            job.run(paths, None)
+        }
+        Command::Split(args) => {
+            let outputs = vec![args.output.to_string_lossy().to_string()];
+            check_output_extensions(&outputs)?;
+            let paths = resolve_inputs(&args.inputs)?;
+            let mut job = Job::new(&args.processing, quiet, outputs, vec![keep_all()])?;
+            job.max_output_points = Some(args.points);
+            job.run(paths, None)
+        }
+        Command::Tile(args) => tile(args, quiet),
+        Command::Lines(args) => lines(args, quiet),
+        Command::Clip(args) => clip(args, quiet),
+        Command::Info(args) => {
+            for path in resolve_inputs(&args)? {
+                println!("{}", FileInfo::read(&path)?);
+            }
+            Ok(())
+        }
+        Command::Validate(args) => {
+            let mut reports = Vec::new();
+            for path in resolve_inputs(&args.inputs)? {
+                let report = validate(&path)?;
+                if !args.json {
+                    if report.issues.is_empty() {
+                        println!("{}: ok", path);
+                    }
+                    for issue in &report.issues {
+                        println!("{}: {}", path, issue);
+                    }
+                }
+                reports.push(report);
+            }
+            if args.json {
+                let reports: Vec<_> = reports.iter().map(|report| report.to_json()).collect();
+                println!("{}", serde_json::Value::from(reports));
+            }
+            let invalid: Vec<_> = reports
+                .into_iter()
+                .filter(|report| !report.is_valid())
+                .map(|report| report.path)
                 .collect();
-            paths.extend(dir_paths);
+            if invalid.is_empty() {
+                Ok(())
+            } else {
+                Err(MyError::InvalidFiles(invalid))
+            }
+        }
+        Command::Repair(args) => {
+            let input = args.input.to_string_lossy().to_string();
+            let output = args.output.to_string_lossy().to_string();
+            let options = RepairOptions::default()
+                .with_drop_duplicate_vlrs(args.drop_duplicate_vlrs)
+                .with_overwrite(args.force);
+            let fixes = repair(&input, &output, &options)?;
+            if fixes.is_empty() {
+                println!("{}: nothing to repair", input);
+            }
+            for fix in &fixes {
+                println!("{}: {}", input, fix);
+            }
+            info!("Wrote {}", output);
+            Ok(())
+        }
+        Command::Diff(args) => {
+            let first = args.first.to_string_lossy().to_string();
+            let second = args.second.to_string_lossy().to_string();
+            let options = args
+                .tolerance
+                .iter()
+                .fold(DiffOptions::default(), |options, (name, tolerance)| {
+                    options.with_tolerance(name, *tolerance)
+                })
+                .with_points_shown(args.points);
+            let differences = diff(&first, &second, &options)?;
+            for difference in &differences {
+                println!("{}", difference);
+            }
+            if differences.is_empty() {
+                println!("{} and {} hold the same points", first, second);
+                Ok(())
+            } else {
+                Err(MyError::FilesDiffer(first, second))
+            }
+        }
+        Command::Density(args) => density(args),
+        Command::Surface(args) => surface(args),
+        Command::Boundary(args) => outline(args),
+        Command::Profile(args) => profile(args),
+        Command::Bench(args) => bench(args),
+        Command::Queue(args) => queue(args, quiet),
+        Command::Index(args) => {
+            let files = resolve_inputs(&args.inputs)?
+                .iter()
+                .map(|path| FileInfo::read(path))
+                .collect::<Result<Vec<_>, _>>()?;
+            match &args.output {
+                Some(output) => write_index(&files, BufWriter::new(File::create(output)?))?,
+                None => write_index(&files, std::io::stdout().lock())?,
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Parses the command line, exiting with clap's message if it is invalid.
+fn parse_cli() -> Result<Cli, MyError> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    parse_args(&args)?.map_err(|err| err.exit())
+}
+
+/// Parses `args`, starting with the program name. Options given without a command are those of
+/// `trim`, the default command, as they were before there were commands. With a pipeline file, the
+/// options in the file become the defaults of the `trim` command and the arguments are parsed
+/// again. Fails with the outer error if the pipeline file can't be read.
+fn parse_args(args: &[OsString]) -> Result<Result<Cli, clap::Error>, MyError> {
+    let (args, cli) = match Cli::try_parse_from(args) {
+        Ok(cli) => (args.to_vec(), cli),
+        Err(err)
+            if err.kind() == clap::error::ErrorKind::UnknownArgument && !names_command(args) =>
+        {
+            let mut trim_args = args.to_vec();
+            trim_args.insert(1.min(trim_args.len()), OsString::from(DEFAULT_COMMAND));
+            match Cli::try_parse_from(&trim_args) {
+                Ok(cli) => (trim_args, cli),
+                Err(err) => return Ok(Err(err)),
+            }
+        }
+        Err(err) => return Ok(Err(err)),
+    };
+    let Command::Trim(TrimArgs {
+        pipeline: Some(path),
+        ..
+    }) = &cli.command
+    else {
+        return Ok(Ok(cli));
+    };
+    let config = PipelineConfig::read(path)?;
+    let command = with_pipeline_defaults(Cli::command(), &config)?;
+    Ok(command
+        .try_get_matches_from(&args)
+        .and_then(|matches| Cli::from_arg_matches(&matches)))
+}
+
+/// Whether the first argument of `args` that isn't an option or the value of one names a command,
+/// so that an unknown option is a mistake in that command rather than an option of `trim`.
+fn names_command(args: &[OsString]) -> bool {
+    let command = Cli::command();
+    let takes_value = |matches: &dyn Fn(&clap::Arg) -> bool| {
+        command
+            .get_arguments()
+            .any(|arg| matches(arg) && arg.get_action().takes_values())
+    };
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let Some(arg) = arg.to_str() else {
+            return false;
+        };
+        if let Some(long) = arg.strip_prefix("--") {
+            if !long.contains('=') && takes_value(&|option| option.get_long() == Some(long)) {
+                args.next();
+            }
+        } else if let Some(short) = arg.strip_prefix('-').filter(|short| !short.is_empty()) {
+            let mut flags = short.chars();
+            if let (Some(flag), None) = (flags.next(), flags.next()) {
+                if takes_value(&|option| option.get_short() == Some(flag)) {
+                    args.next();
+                }
+            }
         } else {
-            return Err(MyError::InvalidInputPath);
+            return command.find_subcommand(arg).is_some();
+        }
+    }
+    false
+}
+
+/// Sets the options of a pipeline file as the defaults of the `trim` command, or of the command
+/// line as a whole for the logging options.
+fn with_pipeline_defaults(
+    mut command: clap::Command,
+    config: &PipelineConfig,
+) -> Result<clap::Command, MyError> {
+    let mut defaults = Vec::new();
+    if !config.inputs.is_empty() {
+        defaults.push(("input".to_string(), config.inputs.clone()));
+    }
+    if !config.outputs.is_empty() {
+        let (paths, filters) = config
+            .outputs
+            .iter()
+            .map(|output| (output.path.clone(), output.filter.clone()))
+            .unzip();
+        defaults.push(("output".to_string(), paths));
+        defaults.push(("filter".to_string(), filters));
+    }
+    for (name, value) in &config.options {
+        let values = match value {
+            OptionValue::Flag(flag) => vec![flag.to_string()],
+            OptionValue::Value(value) => vec![value.clone()],
+            OptionValue::List(values) => values.clone(),
+        };
+        defaults.push((name.clone(), values));
+    }
+
+    for (name, values) in defaults {
+        let id = name.replace('-', "_");
+        let has_arg = |command: &clap::Command| {
+            command
+                .get_arguments()
+                .any(|arg| arg.get_id() == id.as_str())
+        };
+        let on_trim = command.find_subcommand("trim").is_some_and(has_arg);
+        if on_trim {
+            command = command.mut_subcommand("trim", |trim| {
+                trim.mut_arg(&id, |arg| arg.default_values(values))
+            });
+        } else if has_arg(&command) {
+            command = command.mut_arg(&id, |arg| arg.default_values(values));
+        } else {
+            return Err(MyError::InvalidPipeline(format!(
+                "unknown option `{}`",
+                name
+            )));
+        }
+    }
+    Ok(command)
+}
+
+/// Sets up the logger for `args`. `progress` says whether `--progress` was given, which plain
+/// progress is still printed for with `--quiet`, as it is logged at info level.
+fn init_logging(args: &LoggingArgs, json: bool, progress: bool) -> Result<(), MyError> {
+    let log_level = match args.log_level {
+        Some(LogLevel::Off) => LevelFilter::Off,
+        Some(LogLevel::Error) => LevelFilter::Error,
+        Some(LogLevel::Warn) => LevelFilter::Warn,
+        Some(LogLevel::Info) => LevelFilter::Info,
+        Some(LogLevel::Debug) => LevelFilter::Debug,
+        Some(LogLevel::Trace) => LevelFilter::Trace,
+        None if args.quiet => LevelFilter::Error,
+        None => match args.verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        },
+    };
+    let mut logger = CliLogger::new(log_level).with_json(json);
+    if args.log_level.is_none() && !args.quiet && args.verbose == 0 {
+        if let Some(filter) = logging::filter_from_env() {
+            logger = logger.with_filter(filter);
+        }
+    }
+    if args.log_level.is_none() && args.quiet && progress {
+        logger = logger.with_filter(EnvFilter::parse(&format!("{}=info", PROGRESS_TARGET)));
+    }
+    if let Some(log_file) = &args.log_file {
+        logger = logger.with_log_file(log_file)?;
+    }
+    logging::init(logger)
+}
+
+fn trim(args: TrimArgs, quiet: bool) -> Result<(), MyError> {
+    if args.list_presets {
+        for preset in preset::presets()? {
+            match &preset.path {
+                Some(path) => println!("{:<16} {} ({:?})", preset.name, preset.description, path),
+                None => println!("{:<16} {}", preset.name, preset.description),
+            }
         }
+        return Ok(());
     }
+    // Watched folders have no inputs yet
+    let paths = match &args.watch {
+        Some(_) => Vec::new(),
+        None => resolve_inputs(&args.inputs)?,
+    };
+    let mut preset_stages = preset_stages(&args.preset)?;
+    let flag_stages = args.stages.stages(&paths)?;
+    preset_stages.extend(flag_stages.iter().cloned());
 
-    println!("{:?} files were found", paths.len());
+    let mut output_paths: Vec<String> = args
+        .output
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    // Without outputs, the statistics are of every point kept
+    let all_points = args.stats_only && output_paths.is_empty() && args.filter.is_empty();
+    if all_points {
+        output_paths.push(ALL_POINTS.to_string());
+    } else if !args.stats_only {
+        check_output_extensions(&output_paths)?;
+    }
 
-    let filter_functions: Vec<SharedFunction> = cli
-        .filter
+    // The outputs taken from a PDAL pipeline keep the filter stages in front of them
+    let pipeline_outputs = match &args.pipeline {
+        Some(path) => PipelineConfig::read(path)?.outputs,
+        None => Vec::new(),
+    };
+    let filters = if args.filter.is_empty()
+        && (all_points || !(args.preset.is_empty() && flag_stages.is_empty()))
+    {
+        vec![FilterType::AlwaysTrue; output_paths.len()]
+    } else {
+        args.filter.clone()
+    };
+    let conditions: Vec<Condition> = filters
         .iter()
-        .map(|filter| match filter {
-            FilterType::AlwaysTrue => Arc::new(return_true) as SharedFunction,
-            FilterType::AlwaysFalse => Arc::new(return_false) as SharedFunction,
+        .enumerate()
+        .map(|(index, filter)| {
+            let mut stages = pipeline_outputs
+                .iter()
+                .find(|output| output_paths.get(index) == Some(&output.path))
+                .map_or_else(Vec::new, |output| output.stages.clone());
+            stages.extend(preset_stages.iter().cloned());
+            match (filter, Condition::all_numeric(&stages)) {
+                (FilterType::AlwaysTrue, Some(stages)) => stages,
+                (FilterType::AlwaysTrue, None) => {
+                    Condition::from(Arc::new(return_true) as SharedFunction)
+                }
+                (FilterType::AlwaysFalse, _) => {
+                    Condition::from(Arc::new(return_false) as SharedFunction)
+                }
+            }
         })
         .collect();
 
     // Check that the number of filter functions matches the number of output files
-    if filter_functions.len() != output_paths.len() {
+    if conditions.len() != output_paths.len() {
         return Err(MyError::MismatchedFiltersAndOutputs);
     }
 
-    let processor = LasProcessor::new(paths, output_paths, filter_functions, strip_extra_bytes);
+    let output_options = output_paths
+        .iter()
+        .map(|path| {
+            pipeline_outputs
+                .iter()
+                .find(|output| &output.path == path)
+                .map(|output| output.options.clone())
+        })
+        .collect();
+    let mut job = Job::new(&args.processing, quiet, output_paths, conditions)?;
+    job.output_options = output_options;
+    job.stats_only = args.stats_only;
+    job.gather_stats = args.stats.is_some() || args.stats_only;
+
+    if let Some(watch_dir) = &args.watch {
+        if let Some(output_path) = job.outputs.iter().find(|path| !is_template(path)) {
+            return Err(MyError::OutputTemplateRequired(output_path.clone()));
+        }
+        info!("Watching {:?} for new files", watch_dir);
+        return watch_directory(watch_dir, &args.inputs.extensions, |input_path| {
+            if let Err(err) = job.process_input(input_path) {
+                error!("Failed to process {}: {}", input_path, err);
+                job.metrics.record_error();
+            }
+        });
+    }
+
+    let result = job.run(paths, args.journal.as_deref());
+    // The statistics of the inputs that could be read are written even if others were skipped
+    if job.gather_stats && matches!(result, Ok(()) | Err(MyError::PartialFailure(_))) {
+        job.write_stats(args.stats.as_deref().unwrap_or(Path::new("-")))?;
+    }
+    result
+}
+
+/// Runs the jobs of a queue, printing a summary of each as it finishes. The logging set up for the
+/// queue is kept, so the logging options of the jobs are ignored, except for `--quiet`.
+fn queue(args: QueueArgs, quiet: bool) -> Result<(), MyError> {
+    // The addresses served by the running jobs, which two jobs can't bind at once
+    let served = Mutex::new(HashSet::new());
+    let run_job = |job: &QueuedJob| {
+        let args: Vec<OsString> = std::iter::once("las_trimmer".to_string())
+            .chain(job.args()?)
+            .map(OsString::from)
+            .collect();
+        let invalid = |reason: &str| MyError::InvalidJob(job.name.clone(), reason.to_string());
+        // The first line of clap's message, without the usage and help that follow
+        let cli = parse_args(&args)?.map_err(|err| {
+            let message = err.to_string();
+            let reason = message.lines().next().unwrap_or_default();
+            invalid(reason.trim_start_matches("error: "))
+        })?;
+        if let Command::Queue(_) = cli.command {
+            return Err(invalid("queues can't be nested"));
+        }
+        let logging = &cli.logging;
+        if logging.log_level.is_some()
+            || logging.log_file.is_some()
+            || logging.quiet
+            || logging.verbose > 0
+        {
+            return Err(invalid(
+                "logging options apply to the whole queue and can't be given to a job",
+            ));
+        }
+        let addresses: Vec<String> = cli
+            .command
+            .processing()
+            .into_iter()
+            .flat_map(|processing| {
+                processing
+                    .metrics_addr
+                    .iter()
+                    .chain(&processing.status_addr)
+            })
+            .cloned()
+            .collect();
+        {
+            let mut served = served.lock().map_err(|_| MyError::LockError)?;
+            if let Some(address) = addresses.iter().find(|address| served.contains(*address)) {
+                return Err(invalid(&format!(
+                    "{} is already served by another job",
+                    address
+                )));
+            }
+            served.extend(addresses.iter().cloned());
+        }
+        let result = run_command(cli.command, quiet);
+        if let Ok(mut served) = served.lock() {
+            for address in &addresses {
+                served.remove(address);
+            }
+        }
+        result
+    };
+    let print = |summary: &JobSummary| println!("{}", summary);
+    let summaries = if is_stdin(&args.queue.to_string_lossy()) {
+        let lines = read_job_lines(std::io::BufReader::new(std::io::stdin()));
+        run_queue(lines, args.jobs, args.stop_on_error, run_job, print)?
+    } else {
+        let jobs = read_job_dir(&args.queue)?.into_iter().map(Ok);
+        run_queue(jobs, args.jobs, args.stop_on_error, run_job, print)?
+    };
+    let failed: Vec<String> = summaries
+        .iter()
+        .filter(|summary| !summary.succeeded())
+        .map(|summary| summary.name.clone())
+        .collect();
+    info!("Ran {} job(s), {} failed", summaries.len(), failed.len());
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(MyError::FailedJobs(failed, summaries.len()))
+    }
+}
+
+fn time_merge(args: MergeArgs) -> Result<(), MyError> {
+    let output = args.output.to_string_lossy().to_string();
+    check_output_extensions(std::slice::from_ref(&output))?;
+    let paths = resolve_inputs(&args.inputs)?;
+    let merge = TimeMerge::new(paths)
+        .with_overwrite(args.processing.force)
+        .with_verify(args.processing.verify);
+    let merge = match laz_chunking(&args.processing) {
+        Some(chunking) => merge.with_laz_chunking(chunking),
+        None => merge,
+    };
+    let points: u64 = merge
+        .write(&output)?
+        .iter()
+        .map(|report| report.points_written)
+        .sum();
+    info!("Merged {} points by GPS time into {}", points, output);
+    Ok(())
+}
+
+fn tile(args: TileArgs, quiet: bool) -> Result<(), MyError> {
+    let template = args.output.to_string_lossy().to_string();
+    if !is_tile_template(&template) {
+        return Err(MyError::TileTemplateRequired(template));
+    }
+    check_output_extensions(std::slice::from_ref(&template))?;
+    let paths = resolve_inputs(&args.inputs)?;
+
+    // The grid covers the bounds of all the inputs
+    let mut bounds: Option<las::Bounds> = None;
+    for path in &paths {
+        let file = FileInfo::read(path)?;
+        if file.number_of_points == 0 {
+            continue;
+        }
+        let (min, max) = (file.bounds.min, file.bounds.max);
+        bounds = Some(match bounds {
+            Some(bounds) => las::Bounds {
+                min: las::Vector {
+                    x: bounds.min.x.min(min.x),
+                    y: bounds.min.y.min(min.y),
+                    z: bounds.min.z.min(min.z),
+                },
+                max: las::Vector {
+                    x: bounds.max.x.max(max.x),
+                    y: bounds.max.y.max(max.y),
+                    z: bounds.max.z.max(max.z),
+                },
+            },
+            None => file.bounds,
+        });
+    }
+    let tiles = bounds.map_or_else(Vec::new, |bounds| tiles(&bounds, args.size));
+    info!("Cutting into {} tile(s)", tiles.len());
+    let outputs = tiles
+        .iter()
+        .map(|tile| tile.render_path(&template))
+        .collect();
+    let conditions = tiles.iter().map(|tile| tile.condition()).collect();
+
+    let job = Job::new(&args.processing, quiet, outputs, conditions)?;
+    if args.processing.dry_run {
+        return job.print_plan(paths);
+    }
+    let report = job
+        .processor(paths, job.outputs.clone())
+        .process_lidar_files()?;
+    let empty = report
+        .outputs
+        .iter()
+        .filter(|output| output.points_written == 0 && !is_remote(&output.path));
+    for output in empty {
+        std::fs::remove_file(&output.path)?;
+    }
+    partial_failure(report)
+}
+
+fn lines(args: LinesArgs, quiet: bool) -> Result<(), MyError> {
+    let template = args.output.to_string_lossy().to_string();
+    if !template.contains(LINE_PLACEHOLDER) {
+        return Err(MyError::LineTemplateRequired(template));
+    }
+    check_output_extensions(std::slice::from_ref(&template))?;
+    let paths = resolve_inputs(&args.inputs)?;
+    let lines = LasProcessor::new(paths.clone(), Vec::new(), Vec::new(), false)
+        .with_observer(Arc::new(NoProgress))
+        .flight_lines(args.gap)?;
+    if lines.is_empty() {
+        info!("No point has a GPS time, so there are no flight lines to write");
+        return Ok(());
+    }
+    info!("Found {} flight line(s)", lines.len());
+    let outputs = (0..lines.len())
+        .map(|line| render_line_path(&template, line))
+        .collect();
+    let conditions = (0..lines.len()).map(|line| lines.condition(line)).collect();
+    let job = Job::new(&args.processing, quiet, outputs, conditions)?;
+    job.run(paths, None)
+}
+
+fn clip(args: ClipArgs, quiet: bool) -> Result<(), MyError> {
+    let template = args.output.to_string_lossy().to_string();
+    if !is_feature_template(&template) {
+        return Err(MyError::FeatureTemplateRequired(template));
+    }
+    check_output_extensions(std::slice::from_ref(&template))?;
+    let features = read_features(&args.polygons, args.name_field.as_deref())?;
+    info!("Clipping to {} feature(s)", features.len());
+    let outputs = features
+        .iter()
+        .map(|feature| feature.render_path(&template))
+        .collect();
+    let conditions = features.iter().map(|feature| feature.condition()).collect();
+    let paths = resolve_inputs(&args.inputs)?;
+    let job = Job::new(&args.processing, quiet, outputs, conditions)?;
+    job.run(paths, None)
+}
+
+fn density(args: DensityArgs) -> Result<(), MyError> {
+    let output = args.output.to_string_lossy().to_string();
+    check_raster_extension(&output)?;
+    let condition = Condition::all_numeric(&preset_stages(&args.preset)?).unwrap_or_else(keep_all);
+    let paths = resolve_inputs(&args.inputs)?;
+    let sink = RasterOutput::new(&output, CellGrid::density(args.cell_size))
+        .with_geo_keys(GeoKeys::from_inputs(&paths));
+    let report = LasProcessor::new(paths, Vec::new(), Vec::new(), false)
+        .with_sink("density", sink, condition)
+        .with_observer(Arc::new(NoProgress))
+        .process_lidar_files()?;
+    info!(
+        "Wrote the density of {} points to {}",
+        report.points_read(),
+        output
+    );
+    Ok(())
+}
+
+fn surface(args: SurfaceArgs) -> Result<(), MyError> {
+    let output = args.output.to_string_lossy().to_string();
+    check_raster_extension(&output)?;
+    let kind = match args.kind {
+        SurfaceKindMode::Dtm => SurfaceKind::Terrain,
+        SurfaceKindMode::Dsm => SurfaceKind::Surface,
+    };
+    let paths = resolve_inputs(&args.inputs)?;
+    let grid = SurfaceGrid::new(kind, args.method.into(), args.cell_size);
+    let sink = RasterOutput::new(&output, grid).with_geo_keys(GeoKeys::from_inputs(&paths));
+    let report = LasProcessor::new(paths, Vec::new(), Vec::new(), false)
+        .with_sink("surface", sink, kind.condition())
+        .with_observer(Arc::new(NoProgress))
+        .process_lidar_files()?;
+    info!(
+        "Wrote the model of {} points to {}",
+        report.points_written(),
+        output
+    );
+    Ok(())
+}
+
+fn outline(args: BoundaryArgs) -> Result<(), MyError> {
+    let kind = match args.concave {
+        Some(cell_size) => BoundaryKind::Concave { cell_size },
+        None => BoundaryKind::Convex,
+    };
+    let condition = Condition::all_numeric(&preset_stages(&args.preset)?).unwrap_or_else(keep_all);
+    let paths = resolve_inputs(&args.inputs)?;
+    let mut outlines = Vec::new();
+    if args.merge {
+        let outline = boundary(&paths, kind, condition)?;
+        outlines.push(("merged".to_string(), outline));
+    } else {
+        for path in paths {
+            let outline = boundary(std::slice::from_ref(&path), kind, condition.clone())?;
+            outlines.push((path, outline));
+        }
+    }
+    let collection = feature_collection(&outlines).to_string();
+    match &args.output {
+        Some(output) => {
+            std::fs::write(output, collection)?;
+            info!("Wrote the outlines to {:?}", output);
+        }
+        None => println!("{}", collection),
+    }
+    Ok(())
+}
+
+fn profile(args: ProfileArgs) -> Result<(), MyError> {
+    if args.line.len() < 4 || !args.line.len().is_multiple_of(2) {
+        return Err(MyError::InvalidProfile(
+            "--line takes the X and Y of at least two vertices".to_string(),
+        ));
+    }
+    let vertices: Vec<[f64; 2]> = args
+        .line
+        .chunks_exact(2)
+        .map(|vertex| [vertex[0], vertex[1]])
+        .collect();
+    let line = Arc::new(ProfileLine::new(&vertices, args.width)?);
+    let output = args.output.to_string_lossy().to_string();
+    let paths = resolve_inputs(&args.inputs)?;
+    check_output_paths(std::slice::from_ref(&output), &paths, args.force)?;
+    let sink = if is_csv_path(&output) {
+        ProfileOutput::csv(Arc::clone(&line), &output)
+    } else {
+        check_output_extensions(std::slice::from_ref(&output))?;
+        let input = paths
+            .first()
+            .filter(|path| !is_stdin(path))
+            .ok_or_else(|| {
+                MyError::InvalidProfile("a LAS/LAZ profile needs an input file".to_string())
+            })?;
+        ProfileOutput::las(Arc::clone(&line), &output, open_reader(input)?.header())?
+    };
+    let report = LasProcessor::new(paths, Vec::new(), Vec::new(), false)
+        .with_sink("profile", sink, line.condition())
+        .with_observer(Arc::new(NoProgress))
+        .process_lidar_files()?;
+    info!(
+        "Wrote {} points along {} units of line to {}",
+        report.points_written(),
+        line.length(),
+        output
+    );
+    Ok(())
+}
+
+fn bench(args: BenchArgs) -> Result<(), MyError> {
+    let input = args.input.to_string_lossy().to_string();
+    let threads = if args.threads.is_empty() {
+        let cores = num_cpus::get();
+        let mut threads: Vec<usize> = std::iter::successors(Some(1), |threads| Some(threads * 2))
+            .take_while(|&threads| threads < cores)
+            .collect();
+        threads.push(cores);
+        threads
+    } else {
+        args.threads
+    };
+    let results = bench::bench(&input, &threads, &args.batch_sizes, &args.channel_depths)?;
+    println!("{}", bench::RESULTS_HEADER);
+    for result in &results {
+        println!("{}", result);
+    }
+    let Some(best) = bench::fastest(&results) else {
+        return Ok(());
+    };
+    println!(
+        "Recommended: --threads {} --batch-size {} --channel-depth {}",
+        best.threads, best.batch_size, best.channel_depth
+    );
+    if let Some(output) = &args.output {
+        bench::write_recommendation(best, BufWriter::new(File::create(output)?))?;
+        info!("Wrote the recommended settings to {:?}", output);
+    }
+    Ok(())
+}
+
+/// The point source IDs given to inputs with `--assign-source-id`.
+fn source_ids(args: &ProcessingArgs) -> SourceIds {
+    args.assign_source_id
+        .iter()
+        .fold(SourceIds::new(), |ids, (path, id)| ids.with(path, *id))
+}
+
+/// A creation date given with `--creation-date`, which may be cleared.
+#[derive(Clone, Copy, Debug)]
+struct DateStamp(Option<chrono::NaiveDate>);
+
+/// The header fields set with `--system-identifier`, `--generating-software`, `--guid` and
+/// `--creation-date`.
+fn stamp(args: &ProcessingArgs) -> HeaderStamp {
+    let mut stamp = HeaderStamp::new();
+    if let Some(identifier) = &args.system_identifier {
+        stamp = stamp.with_system_identifier(identifier);
+    }
+    if let Some(software) = &args.generating_software {
+        stamp = stamp.with_generating_software(software);
+    }
+    if let Some(guid) = args.guid {
+        stamp = stamp.with_guid(guid);
+    }
+    if let Some(DateStamp(date)) = args.creation_date {
+        stamp = stamp.with_date(date);
+    }
+    stamp
+}
+
+/// What `--anonymize`, `--truncate-coordinates`, `--drop-gps-time` and `--strip-metadata` take out
+/// of the outputs, if anything.
+fn anonymization(args: &ProcessingArgs) -> Option<Anonymization> {
+    let precision = match args.anonymize {
+        true => Some(args.truncate_coordinates.unwrap_or(1.0)),
+        false => args.truncate_coordinates,
+    };
+    let anonymization = Anonymization::new()
+        .with_drop_gps_time(args.anonymize || args.drop_gps_time)
+        .with_strip_metadata(args.anonymize || args.strip_metadata);
+    let anonymization = match precision {
+        Some(precision) => anonymization.with_precision(precision),
+        None => anonymization,
+    };
+    (anonymization != Anonymization::new()).then_some(anonymization)
+}
+
+/// A flag to set or clear with `--set-flag` or `--clear-flag`, on the points a preset keeps or
+/// drops, or on every point.
+#[derive(Clone, Debug)]
+struct FlagChange {
+    flag: PointFlag,
+    preset: Option<String>,
+    /// Whether the change is made to the points the preset drops.
+    inverted: bool,
+}
+
+/// The transforms of `--set-flag` and `--clear-flag`, the clearing ones last.
+fn flag_transforms(args: &ProcessingArgs) -> Result<Vec<ConditionalTransform>, MyError> {
+    let changes = args
+        .set_flag
+        .iter()
+        .map(|change| (change, true))
+        .chain(args.clear_flag.iter().map(|change| (change, false)));
+    let mut transforms = Vec::new();
+    for (change, value) in changes {
+        let transform = ConditionalTransform::new(Transform::SetFlag(change.flag, value));
+        let transform = match &change.preset {
+            Some(name) => {
+                let stages = preset_stages(std::slice::from_ref(name))?;
+                let condition = Condition::all_numeric(&stages).unwrap_or_else(keep_all);
+                transform.when(if change.inverted {
+                    !condition
+                } else {
+                    condition
+                })
+            }
+            None => transform,
+        };
+        transforms.push(transform);
+    }
+    Ok(transforms)
+}
+
+/// The color adjustments of the command, in the order they are applied.
+fn color_transforms(args: &ProcessingArgs) -> Vec<ConditionalTransform> {
+    [
+        args.color_gain.map(Transform::ColorGain),
+        args.color_gamma.map(Transform::Gamma),
+        args.color_brightness.map(Transform::Brightness),
+    ]
+    .into_iter()
+    .flatten()
+    .map(ConditionalTransform::new)
+    .collect()
+}
+
+/// The filter stages of the named presets, in order.
+fn preset_stages(names: &[String]) -> Result<Vec<NumericFilter>, MyError> {
+    let mut stages = Vec::new();
+    if !names.is_empty() {
+        let presets = preset::presets()?;
+        for name in names {
+            stages.extend(find_preset(&presets, name)?.stages.iter().cloned());
+        }
+    }
+    Ok(stages)
+}
+
+/// Lists the inputs, looking into folders and ZIP archives.
+fn resolve_inputs(args: &InputArgs) -> Result<Vec<String>, MyError> {
+    let mut input_paths = args.input.clone();
+    if let Some(input_list) = &args.input_list {
+        input_paths.extend(read_input_list(input_list)?.into_iter().map(PathBuf::from));
+    }
+    let mut paths = Vec::new();
+    for input_path in &input_paths {
+        let input_str = input_path.to_string_lossy();
+        if archive::is_zip(&input_str) && input_path.is_file() {
+            paths.extend(archive::list_entries(&input_str)?);
+        } else if is_stdin(&input_str)
+            || is_remote(&input_str)
+            || is_http(&input_str)
+            || input_path.is_file()
+        {
+            paths.push(input_path.to_string_lossy().to_string());
+        } else if input_path.is_dir() {
+            paths.extend(find_files(input_path, &args.extensions, args.recursive)?);
+        } else {
+            return Err(MyError::InvalidInputPath);
+        }
+    }
+    info!("{:?} files were found", paths.len());
+    Ok(paths)
+}
 
-    processor.process_lidar_files()?;
+/// Checks that the outputs have valid extensions.
+fn check_raster_extension(output_path: &str) -> Result<(), MyError> {
+    if is_raster_path(output_path) {
+        Ok(())
+    } else {
+        Err(MyError::InvalidRasterExtension(output_path.to_string()))
+    }
+}
 
+fn check_output_extensions(output_paths: &[String]) -> Result<(), MyError> {
+    for output_path in output_paths.iter().filter(|path| !is_stdout(path)) {
+        let path_buf = PathBuf::from(output_path);
+        let output_extension = path_buf
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+        if output_extension != "las" && output_extension != "laz" {
+            return Err(MyError::InvalidOutputExtension);
+        }
+    }
     Ok(())
 }
+
+/// The outputs and conditions of a command, with the processing options applied to every run.
+struct Job<'a> {
+    args: &'a ProcessingArgs,
+    outputs: Vec<String>,
+    conditions: Vec<Condition>,
+    observer: Arc<dyn ProgressObserver>,
+    metrics: Arc<Metrics>,
+    skip_existing: Option<SkipExisting>,
+    max_output_points: Option<u64>,
+    /// The flag changes of `--set-flag` and `--clear-flag`.
+    transforms: Vec<ConditionalTransform>,
+    /// The filter of `--min-neighbors`.
+    neighbor_filter: Option<NeighborFilter>,
+    /// The palette of `--color-by-class`.
+    class_palette: Option<ClassPalette>,
+    /// The adjustments of `--adjust`.
+    adjustments: Adjustments,
+    /// The settings of the outputs from a pipeline file, by output.
+    output_options: Vec<Option<OutputOptions>>,
+    /// Whether statistics of the outputs are gathered, for `--stats`.
+    gather_stats: bool,
+    /// Whether the statistics are gathered instead of writing the outputs, for `--stats-only`.
+    stats_only: bool,
+    /// The statistics gathered, by output.
+    stats: Mutex<Vec<(String, StatsOutput)>>,
+}
+
+impl<'a> Job<'a> {
+    /// Sets up progress reporting, and the metrics and status servers if they were asked for.
+    fn new(
+        args: &'a ProcessingArgs,
+        quiet: bool,
+        outputs: Vec<String>,
+        conditions: Vec<Condition>,
+    ) -> Result<Self, MyError> {
+        for raster in [&args.density, &args.intensity, &args.dtm, &args.dsm]
+            .into_iter()
+            .flatten()
+        {
+            check_raster_extension(&raster.to_string_lossy())?;
+        }
+        if let Some(canopy) = &args.canopy {
+            let canopy = canopy.to_string_lossy();
+            if !is_raster_path(&canopy) && !is_csv_path(&canopy) {
+                return Err(MyError::InvalidCanopyExtension(canopy.to_string()));
+            }
+        }
+        if args.backend == BackendMode::Rayon {
+            let threads_only = [
+                ("--channel-depth", args.channel_depth.is_some()),
+                ("--auto-tune", args.auto_tune),
+                ("--writer-threads", args.writer_threads.is_some()),
+            ];
+            if let Some((option, _)) = threads_only.into_iter().find(|(_, given)| *given) {
+                return Err(MyError::NotForBackend(option.to_string()));
+            }
+        }
+        let progress_mode = args.progress.unwrap_or(if std::io::stderr().is_terminal() {
+            ProgressMode::Bars
+        } else {
+            ProgressMode::Plain
+        });
+        let observer: Arc<dyn ProgressObserver> = match progress_mode {
+            _ if quiet && args.progress.is_none() => Arc::new(NoProgress),
+            ProgressMode::Bars => Arc::new(ProgressBars::new()),
+            ProgressMode::Plain => Arc::new(ConsoleProgress::new()),
+            ProgressMode::Json => Arc::new(JsonProgress::new()),
+        };
+        let metrics = Arc::new(Metrics::new());
+        let observer: Arc<dyn ProgressObserver> = match &args.metrics_addr {
+            Some(addr) => {
+                let addr = serve_metrics(addr, Arc::clone(&metrics))?;
+                info!("Serving metrics on http://{}/metrics", addr);
+                Arc::new(Observers(vec![observer, metrics.clone()]))
+            }
+            None => observer,
+        };
+        let observer: Arc<dyn ProgressObserver> = match &args.status_addr {
+            Some(addr) => {
+                let status = Arc::new(JobStatus::new());
+                let addr = serve_status(addr, Arc::clone(&status))?;
+                info!("Serving job status on http://{}/", addr);
+                Arc::new(Observers(vec![observer, status]))
+            }
+            None => observer,
+        };
+        Ok(Self {
+            args,
+            outputs,
+            conditions,
+            observer,
+            metrics,
+            skip_existing: args.skip_existing.map(|mode| match mode {
+                SkipExistingMode::Exists => SkipExisting::Exists,
+                SkipExistingMode::Newer => SkipExisting::Newer,
+            }),
+            max_output_points: None,
+            transforms: flag_transforms(args)?
+                .into_iter()
+                .chain(color_transforms(args))
+                .collect(),
+            neighbor_filter: match (args.neighbor_radius, args.min_neighbors) {
+                (Some(radius), Some(min_neighbors)) => {
+                    Some(NeighborFilter::new(radius, min_neighbors)?)
+                }
+                _ => None,
+            },
+            class_palette: match &args.color_by_class {
+                Some(Some(path)) => Some(ClassPalette::from_path(path)?),
+                Some(None) => Some(ClassPalette::new()),
+                None => None,
+            },
+            adjustments: match &args.adjust {
+                Some(path) => Adjustments::from_path(path)?,
+                None => Adjustments::new(),
+            },
+            output_options: Vec::new(),
+            gather_stats: false,
+            stats_only: false,
+            stats: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// A processor reading `paths` into `outputs`, configured with the options of the command.
+    fn processor(&self, paths: Vec<String>, outputs: Vec<String>) -> LasProcessor {
+        let args = self.args;
+        let stats_names = if self.gather_stats {
+            outputs.clone()
+        } else {
+            Vec::new()
+        };
+        // With --stats-only the points only go to the statistics
+        let (outputs, conditions) = if self.stats_only {
+            (Vec::new(), Vec::new())
+        } else {
+            (outputs, self.conditions.clone())
+        };
+        let output_options: Vec<(String, OutputOptions)> = outputs
+            .iter()
+            .zip(&self.output_options)
+            .filter_map(|(path, options)| Some((path.clone(), options.clone()?)))
+            .collect();
+        // The rasters written alongside the outputs, in the coordinate system of the inputs
+        let geo_keys = [
+            &args.density,
+            &args.intensity,
+            &args.canopy,
+            &args.dtm,
+            &args.dsm,
+        ]
+        .iter()
+        .any(|raster| raster.is_some())
+        .then(|| GeoKeys::from_inputs(&paths))
+        .flatten();
+        let raster_path = |template: &PathBuf| {
+            let template = template.to_string_lossy();
+            match paths.as_slice() {
+                [input_path] if self.per_input_outputs() => {
+                    render_output_path(&template, input_path)
+                }
+                _ => template.to_string(),
+            }
+        };
+        let density = args.density.as_ref().map(|template| {
+            let grid = CellGrid::density(args.density_cell_size);
+            RasterOutput::new(raster_path(template), grid).with_geo_keys(geo_keys.clone())
+        });
+        let intensity = args.intensity.as_ref().map(|template| {
+            let grid = CellGrid::mean_intensity(args.intensity_cell_size);
+            RasterOutput::new(raster_path(template), grid).with_geo_keys(geo_keys.clone())
+        });
+        let canopy = args.canopy.as_ref().map(|template| {
+            let percentiles = match args.canopy_percentiles.as_slice() {
+                [] => &DEFAULT_PERCENTILES[..],
+                percentiles => percentiles,
+            };
+            let grid = CanopyGrid::new(args.canopy_cell_size).with_percentiles(percentiles);
+            RasterOutput::new(raster_path(template), grid).with_geo_keys(geo_keys.clone())
+        });
+        let surfaces: Vec<(SurfaceKind, RasterOutput<SurfaceGrid>)> = [
+            (SurfaceKind::Terrain, &args.dtm),
+            (SurfaceKind::Surface, &args.dsm),
+        ]
+        .into_iter()
+        .filter_map(|(kind, template)| {
+            let grid = SurfaceGrid::new(kind, args.surface_method.into(), args.surface_cell_size);
+            let output = RasterOutput::new(raster_path(template.as_ref()?), grid)
+                .with_geo_keys(geo_keys.clone());
+            Some((kind, output))
+        })
+        .collect();
+        let builder = outputs.into_iter().zip(conditions).fold(
+            LasProcessor::builder()
+                .inputs(paths)
+                .strip_extra_bytes(args.strip_extra_bytes),
+            |builder, (path, condition)| builder.output(path, condition),
+        );
+        let builder = builder
+            .drop_waveforms(args.drop_waveforms)
+            .minimize_format(args.minimize_format)
+            .legacy_compatible(args.legacy_compatible)
+            .overwrite(args.force)
+            .append(args.append)
+            .error_policy(match args.on_error {
+                OnErrorMode::Abort => ErrorPolicy::Abort,
+                OnErrorMode::Skip => ErrorPolicy::Skip,
+            })
+            .backend(match args.backend {
+                BackendMode::Threads => Backend::Threads,
+                BackendMode::Rayon => Backend::Rayon,
+            })
+            .mmap(args.mmap)
+            .verify(args.verify)
+            .space_check(args.space_check)
+            .max_point_errors(args.max_point_errors)
+            .auto_tune(args.auto_tune)
+            .low_memory(args.low_memory)
+            .compressed_spill(args.compress_spill)
+            .observer(Arc::clone(&self.observer));
+        let builder = match density {
+            Some(sink) => builder.sink("density", sink, keep_all()),
+            None => builder,
+        };
+        let builder = match intensity {
+            Some(sink) => builder.sink("intensity", sink, keep_all()),
+            None => builder,
+        };
+        let builder = match canopy {
+            Some(sink) => builder.sink("canopy", sink, keep_all()),
+            None => builder,
+        };
+        let builder = surfaces.into_iter().fold(builder, |builder, (kind, sink)| {
+            builder.sink("surface", sink, kind.condition())
+        });
+        let builder = match args.gps_time {
+            Some(mode) => {
+                let mut conversion = GpsTimeConversion::new(match mode {
+                    GpsTimeMode::Week => GpsTimeType::Week,
+                    GpsTimeMode::Standard => GpsTimeType::Standard,
+                })
+                .with_offset(args.gps_time_offset.unwrap_or(0.0));
+                if let Some(week) = args.gps_week {
+                    conversion = conversion.with_week(week);
+                }
+                builder.gps_time(conversion)
+            }
+            None => builder,
+        };
+        let builder = builder
+            .source_ids(source_ids(args))
+            .adjustments(self.adjustments.clone());
+        let builder = builder.stamp(stamp(args));
+        let builder = match args.merge_vlrs {
+            Some(mode) => builder.vlr_merge(VlrMerge::new(match mode {
+                VlrConflictMode::Fail => VlrPrecedence::Fail,
+                VlrConflictMode::First => VlrPrecedence::First,
+                VlrConflictMode::Last => VlrPrecedence::Last,
+            })),
+            None => builder,
+        };
+        let builder = match anonymization(args) {
+            Some(anonymization) => builder.anonymization(anonymization),
+            None => builder,
+        };
+        let builder = match args.flight_line_ids {
+            Some(gap) => builder.flight_line_ids(gap),
+            None => builder,
+        };
+        let builder = match args.tag_source {
+            Some(TagSourceMode::PointSourceId) => builder.source_tag(SourceTag::PointSourceId),
+            Some(TagSourceMode::ExtraByte) => builder.source_tag(SourceTag::ExtraByte),
+            None => builder,
+        };
+        let builder = self.transforms.iter().fold(builder, |builder, transform| {
+            builder.transform(transform.clone())
+        });
+        let builder = match self.neighbor_filter {
+            Some(filter) => builder.neighbor_filter(filter),
+            None => builder,
+        };
+        let builder = match args.threads {
+            Some(threads) => builder.reader_threads(threads),
+            None => builder,
+        };
+        let builder = match args.writer_threads {
+            Some(threads) => builder.writer_threads(threads),
+            None => builder,
+        };
+        let builder = match args.batch_size {
+            Some(batch_size) => builder.batch_size(batch_size),
+            None => builder,
+        };
+        let builder = match args.channel_depth {
+            Some(depth) => builder.channel_depth(depth),
+            None => builder,
+        };
+        let builder = match args.max_memory {
+            Some(bytes) => builder.max_memory(bytes),
+            None => builder,
+        };
+        let builder = match &args.spill_dir {
+            Some(dir) => builder.spill_dir(dir.clone()),
+            None => builder,
+        };
+        let builder = match args.sort_by {
+            Some(mode) => builder.sort(mode.into()),
+            None => builder,
+        };
+        let builder = match args.stretch_intensity {
+            Some((low, high)) => builder.intensity_stretch(low, high),
+            None => builder,
+        };
+        let builder = match args.color_from {
+            Some(ColorFromMode::Intensity) => builder.colorize(
+                ColorSource::Intensity,
+                args.colormap.map_or(Colormap::Gray, Colormap::from),
+            ),
+            Some(ColorFromMode::Elevation) => builder.colorize(
+                ColorSource::Elevation,
+                args.colormap.map_or(Colormap::Terrain, Colormap::from),
+            ),
+            None => builder,
+        };
+        let builder = match &self.class_palette {
+            Some(palette) => builder.class_colors(palette.clone()),
+            None => builder,
+        };
+        let builder = match args.crop_to_overlap {
+            Some(Some(cell_size)) => builder.crop_to_overlap(OverlapKind::Cells { cell_size }),
+            Some(None) => builder.crop_to_overlap(OverlapKind::Bounds),
+            None => builder,
+        };
+        let builder = match self.max_output_points {
+            Some(points) => builder.max_output_points(points),
+            None => builder,
+        };
+        let builder = match laz_chunking(args) {
+            Some(chunking) => builder.laz_chunking(chunking),
+            None => builder,
+        };
+        let builder = output_options
+            .into_iter()
+            .fold(builder, |builder, (path, options)| {
+                builder.output_options(&path, options)
+            });
+        let builder = stats_names.into_iter().zip(&self.conditions).fold(
+            builder,
+            |builder, (name, condition)| {
+                let sink = StatsOutput::new();
+                if let Ok(mut stats) = self.stats.lock() {
+                    stats.push((name, sink.clone()));
+                }
+                builder.sink("stats", sink, condition.clone())
+            },
+        );
+        let builder = match args.file_timeout {
+            Some(seconds) => builder.file_timeout(Duration::from_secs(seconds)),
+            None => builder,
+        };
+        builder.build()
+    }
+
+    /// Processes one input on its own, with the output templates filled in for it.
+    fn process_input(&self, input_path: &str) -> Result<(), MyError> {
+        let outputs: Vec<String> = self
+            .outputs
+            .iter()
+            .map(|template| render_output_path(template, input_path))
+            .collect();
+        if let Some(policy) = self.skip_existing {
+            if outputs_up_to_date(&outputs, &[input_path.to_string()], policy) {
+                info!("Skipping {}, outputs are up to date", input_path);
+                return Ok(());
+            }
+        }
+        let report = self
+            .processor(vec![input_path.to_string()], outputs)
+            .process_lidar_files()?;
+        partial_failure(report)
+    }
+
+    /// Writes the statistics gathered for each output as JSON to `path`, or to stdout if it is
+    /// `-`.
+    fn write_stats(&self, path: &Path) -> Result<(), MyError> {
+        let stats: Vec<serde_json::Value> = self
+            .stats
+            .lock()
+            .map_err(|_| MyError::LockError)?
+            .iter()
+            .map(|(name, output)| {
+                let mut stats = output.stats().to_json();
+                stats["output"] = name.clone().into();
+                stats
+            })
+            .collect();
+        let json = serde_json::Value::from(stats);
+        if path == Path::new("-") {
+            println!("{}", json);
+        } else {
+            std::fs::write(path, format!("{}\n", json))?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if every output contains `{stem}`, so each input is processed on its own.
+    fn per_input_outputs(&self) -> bool {
+        !self.outputs.is_empty() && self.outputs.iter().all(|path| is_template(path))
+    }
+
+    /// Prints what processing `paths` would read and write, without reading any points.
+    fn print_plan(&self, paths: Vec<String>) -> Result<(), MyError> {
+        if !self.per_input_outputs() {
+            println!("{}", self.processor(paths, self.outputs.clone()).plan()?);
+            return Ok(());
+        }
+        for input_path in paths {
+            let outputs = self
+                .outputs
+                .iter()
+                .map(|template| render_output_path(template, &input_path))
+                .collect();
+            println!("{}", self.processor(vec![input_path], outputs).plan()?);
+        }
+        Ok(())
+    }
+
+    /// Processes `paths` into the outputs, or each input on its own if every output contains
+    /// `{stem}`. Inputs listed in the journal are skipped, and the ones completed are added to
+    /// it. With `--dry-run`, only prints the plan.
+    fn run(&self, paths: Vec<String>, journal: Option<&Path>) -> Result<(), MyError> {
+        if let Some(path) = source_ids(self.args).unmatched(&paths).first() {
+            return Err(MyError::UnmatchedSourceId(path.to_string()));
+        }
+        if let Some(path) = self.adjustments.unmatched(&paths).first() {
+            return Err(MyError::UnmatchedAdjustment(path.to_string()));
+        }
+        if self.args.dry_run {
+            return self.print_plan(paths);
+        }
+        if self.per_input_outputs() {
+            let mut journal = journal.map(Journal::open).transpose()?;
+            if let Some(journal) = &journal {
+                info!(
+                    "Resuming, {} inputs were already completed",
+                    journal.completed_count()
+                );
+            }
+            let mut skipped = Vec::new();
+            for input_path in &paths {
+                if journal
+                    .as_ref()
+                    .is_some_and(|journal| journal.is_completed(input_path))
+                {
+                    continue;
+                }
+                match self.process_input(input_path) {
+                    Ok(()) => {}
+                    Err(MyError::PartialFailure(paths)) => {
+                        skipped.extend(paths);
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                }
+                if let Some(journal) = &mut journal {
+                    journal.mark_completed(input_path)?;
+                }
+            }
+            if !skipped.is_empty() {
+                return Err(MyError::PartialFailure(skipped));
+            }
+            return Ok(());
+        }
+        if let (Some(_), Some(output_path)) = (journal, self.outputs.first()) {
+            return Err(MyError::OutputTemplateRequired(output_path.clone()));
+        }
+
+        if let Some(policy) = self.skip_existing {
+            if outputs_up_to_date(&self.outputs, &paths, policy) {
+                info!("Skipping, outputs are up to date");
+                return Ok(());
+            }
+        }
+
+        let report = self
+            .processor(paths, self.outputs.clone())
+            .process_lidar_files()?;
+
+        partial_failure(report)
+    }
+}
+
+/// How LAZ outputs are split into chunks, if the options say.
+fn laz_chunking(args: &ProcessingArgs) -> Option<LazChunking> {
+    match (args.laz_variable_chunks, args.laz_chunk_size) {
+        (true, _) => Some(LazChunking::Variable),
+        (false, size) if args.parallel_compression => {
+            Some(LazChunking::Parallel(size.unwrap_or(DEFAULT_CHUNK_SIZE)))
+        }
+        (false, Some(size)) => Some(LazChunking::Fixed(size)),
+        (false, None) => None,
+    }
+}
+
+/// Turns inputs that were skipped because they couldn't be read into a `PartialFailure`.
+fn partial_failure(report: ProcessingReport) -> Result<(), MyError> {
+    if report.skipped.is_empty() {
+        Ok(())
+    } else {
+        Err(MyError::PartialFailure(report.skipped))
+    }
+}
+
+/// Parses a size in bytes with an optional `K`, `M`, `G` or `T` suffix (powers of 1024).
+fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let upper = size.to_ascii_uppercase();
+    let digits = upper.strip_suffix('B').unwrap_or(&upper);
+    let (digits, multiplier) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 1u64 << 10),
+        Some('M') => (&digits[..digits.len() - 1], 1 << 20),
+        Some('G') => (&digits[..digits.len() - 1], 1 << 30),
+        Some('T') => (&digits[..digits.len() - 1], 1 << 40),
+        _ => (digits, 1),
+    };
+    digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size `{}`, expected e.g. 512M or 4G", size))
+}
+
+/// Parses the tolerance of a dimension, as `<dimension>=<delta>`.
+fn parse_tolerance(tolerance: &str) -> Result<(String, f64), String> {
+    let error = || {
+        format!(
+            "invalid tolerance `{}`, expected e.g. z=0.01 with a dimension among {}",
+            tolerance,
+            DIMENSIONS
+                .iter()
+                .map(|dimension| dimension.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    };
+    let (name, delta) = tolerance.split_once('=').ok_or_else(error)?;
+    let name = name.trim();
+    if !DIMENSIONS.iter().any(|dimension| dimension.name == name) {
+        return Err(error());
+    }
+    match delta.trim().parse::<f64>() {
+        Ok(delta) if delta >= 0.0 => Ok((name.to_string(), delta)),
+        _ => Err(error()),
+    }
+}
+
+fn parse_source_id(assignment: &str) -> Result<(String, u16), String> {
+    let error = || {
+        format!(
+            "invalid source ID assignment `{}`, expected e.g. strip.las=17 with an ID up to 65535",
+            assignment
+        )
+    };
+    let (path, id) = assignment.rsplit_once('=').ok_or_else(error)?;
+    match id.trim().parse::<u16>() {
+        Ok(id) if !path.is_empty() => Ok((path.to_string(), id)),
+        _ => Err(error()),
+    }
+}
+
+/// Parses a flag change, the name of the flag with an optional preset after `=`, negated by `!`.
+fn parse_flag_change(change: &str) -> Result<FlagChange, String> {
+    let (flag, preset) = match change.split_once('=') {
+        Some((flag, preset)) => (flag, Some(preset.trim())),
+        None => (change, None),
+    };
+    let flag = match flag.trim() {
+        "withheld" => PointFlag::Withheld,
+        "synthetic" => PointFlag::Synthetic,
+        "key-point" => PointFlag::KeyPoint,
+        "overlap" => PointFlag::Overlap,
+        flag => {
+            return Err(format!(
+                "unknown flag `{}`, expected withheld, synthetic, key-point or overlap",
+                flag
+            ))
+        }
+    };
+    let (preset, inverted) = match preset.map(|preset| preset.strip_prefix('!')) {
+        Some(Some(preset)) => (Some(preset), true),
+        Some(None) => (preset, false),
+        None => (None, false),
+    };
+    if preset == Some("") {
+        return Err(format!("missing preset name in `{}`", change));
+    }
+    Ok(FlagChange {
+        flag,
+        preset: preset.map(String::from),
+        inverted,
+    })
+}
+
+/// Parses the side of a tile, which must be a positive number.
+fn parse_tile_size(size: &str) -> Result<f64, String> {
+    match size.trim().parse::<f64>() {
+        Ok(size) if size > 0.0 && size.is_finite() => Ok(size),
+        _ => Err(format!(
+            "invalid tile size `{}`, expected a positive number",
+            size
+        )),
+    }
+}
+
+/// Parses the side of the cells of a concave outline, which must be a positive number.
+fn parse_cell_size(size: &str) -> Result<f64, String> {
+    match size.trim().parse::<f64>() {
+        Ok(size) if size > 0.0 && size.is_finite() => Ok(size),
+        _ => Err(format!(
+            "invalid cell size `{}`, expected a positive number",
+            size
+        )),
+    }
+}
+
+/// Parses a text header field, which must fit in the header.
+fn parse_header_field(text: &str) -> Result<String, String> {
+    match text.len() <= FIELD_LENGTH {
+        true => Ok(text.to_string()),
+        false => Err(format!(
+            "`{}` is longer than the {} bytes a LAS header holds",
+            text, FIELD_LENGTH
+        )),
+    }
+}
+
+/// Parses a project GUID, where an empty one is the nil GUID that clears it.
+fn parse_guid(guid: &str) -> Result<uuid::Uuid, String> {
+    if guid.trim().is_empty() {
+        return Ok(uuid::Uuid::nil());
+    }
+    uuid::Uuid::parse_str(guid.trim()).map_err(|err| format!("invalid GUID `{}`: {}", guid, err))
+}
+
+/// Parses a creation date, as YYYY-MM-DD or `today`, where an empty one clears it.
+fn parse_date(date: &str) -> Result<DateStamp, String> {
+    match date.trim() {
+        "" => Ok(DateStamp(None)),
+        "today" => Ok(DateStamp(Some(chrono::Utc::now().date_naive()))),
+        date => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(|date| DateStamp(Some(date)))
+            .map_err(|_| format!("invalid date `{}`, expected YYYY-MM-DD or today", date)),
+    }
+}
+
+/// Parses a box given as `MINX,MINY,MAXX,MAXY`.
+fn parse_bbox(bbox: &str) -> Result<[f64; 4], String> {
+    let invalid = || format!("invalid box `{}`, expected MINX,MINY,MAXX,MAXY", bbox);
+    let values: Vec<f64> = bbox
+        .split(',')
+        .map(|value| value.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    match values[..] {
+        [min_x, min_y, max_x, max_y] if min_x <= max_x && min_y <= max_y => {
+            Ok([min_x, min_y, max_x, max_y])
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// The vertices of `--near`, and whether distances from them are horizontal.
+#[derive(Clone, Debug)]
+struct Polyline {
+    vertices: Vec<[f64; 3]>,
+    horizontal: bool,
+}
+
+/// Parses a polyline given as `X,Y[,Z]` vertices separated by `;`, all with a Z or none.
+fn parse_polyline(polyline: &str) -> Result<Polyline, String> {
+    let invalid = || {
+        format!(
+            "invalid positions `{}`, expected X,Y[,Z] separated by `;`, all with a Z or none",
+            polyline
+        )
+    };
+    let vertices: Vec<Vec<f64>> = polyline
+        .split(';')
+        .filter(|vertex| !vertex.trim().is_empty())
+        .map(|vertex| {
+            vertex
+                .split(',')
+                .map(|value| value.trim().parse::<f64>())
+                .collect::<Result<_, _>>()
+        })
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    let horizontal = vertices.iter().all(|vertex| vertex.len() == 2);
+    let with_z = vertices.iter().all(|vertex| vertex.len() == 3);
+    if vertices.is_empty() || !(horizontal || with_z) {
+        return Err(invalid());
+    }
+    Ok(Polyline {
+        vertices: vertices
+            .iter()
+            .map(|vertex| [vertex[0], vertex[1], vertex.get(2).copied().unwrap_or(0.0)])
+            .collect(),
+        horizontal,
+    })
+}
+
+/// Parses an EPSG code, given as `EPSG:CODE` or just the code.
+fn parse_epsg(code: &str) -> Result<u32, String> {
+    let trimmed = code.trim();
+    let number = match trimmed.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("EPSG:") => &trimmed[5..],
+        _ => trimmed,
+    };
+    number
+        .parse()
+        .map_err(|_| format!("invalid coordinate system `{}`, expected EPSG:CODE", code))
+}
+
+/// Parses the gap in GPS time ending a flight line, which must be a positive number of seconds.
+fn parse_gap(gap: &str) -> Result<f64, String> {
+    match gap.trim().parse::<f64>() {
+        Ok(gap) if gap > 0.0 && gap.is_finite() => Ok(gap),
+        _ => Err(format!(
+            "invalid gap `{}`, expected a positive number of seconds",
+            gap
+        )),
+    }
+}
+
+/// Parses a radius, which must be a positive number.
+fn parse_radius(radius: &str) -> Result<f64, String> {
+    match radius.trim().parse::<f64>() {
+        Ok(radius) if radius > 0.0 && radius.is_finite() => Ok(radius),
+        _ => Err(format!(
+            "invalid radius `{}`, expected a positive number",
+            radius
+        )),
+    }
+}
+
+/// Parses a scanner channel, which must be from 0 to 3.
+fn parse_scanner_channel(channel: &str) -> Result<u8, String> {
+    match channel.trim().parse::<u8>() {
+        Ok(channel) if channel <= 3 => Ok(channel),
+        _ => Err(format!(
+            "invalid scanner channel `{}`, expected 0, 1, 2 or 3",
+            channel
+        )),
+    }
+}
+
+/// Parses a percentile, which must be from 0 to 100.
+fn parse_percentile(percentile: &str) -> Result<f64, String> {
+    match percentile.trim().parse::<f64>() {
+        Ok(percentile) if (0.0..=100.0).contains(&percentile) => Ok(percentile),
+        _ => Err(format!(
+            "invalid percentile `{}`, expected a number from 0 to 100",
+            percentile
+        )),
+    }
+}
+
+/// Parses two percentiles separated by a comma, the first below the second.
+fn parse_percentile_range(range: &str) -> Result<(f64, f64), String> {
+    let (low, high) = range
+        .split_once(',')
+        .ok_or_else(|| format!("invalid range `{}`, expected LOW,HIGH", range))?;
+    let (low, high) = (parse_percentile(low)?, parse_percentile(high)?);
+    if low >= high {
+        return Err(format!(
+            "invalid range `{}`, the low percentile should be below the high one",
+            range
+        ));
+    }
+    Ok((low, high))
+}
+
+/// Parses the gains of the red, green and blue channels, which must not be negative.
+fn parse_gains(gains: &str) -> Result<[f64; 3], String> {
+    let error = || {
+        format!(
+            "invalid gains `{}`, expected three numbers of 0 or more like 1.1,1,0.9",
+            gains
+        )
+    };
+    let values = gains
+        .split(',')
+        .map(|gain| match gain.trim().parse::<f64>() {
+            Ok(gain) if gain >= 0.0 && gain.is_finite() => Ok(gain),
+            _ => Err(error()),
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+    values.try_into().map_err(|_| error())
+}
+
+/// Parses a gamma, which must be a positive number.
+fn parse_gamma(gamma: &str) -> Result<f64, String> {
+    match gamma.trim().parse::<f64>() {
+        Ok(gamma) if gamma > 0.0 && gamma.is_finite() => Ok(gamma),
+        _ => Err(format!(
+            "invalid gamma `{}`, expected a positive number",
+            gamma
+        )),
+    }
+}
+
+/// Parses a brightness change, which must be from -1 to 1.
+fn parse_brightness(brightness: &str) -> Result<f64, String> {
+    match brightness.trim().parse::<f64>() {
+        Ok(brightness) if (-1.0..=1.0).contains(&brightness) => Ok(brightness),
+        _ => Err(format!(
+            "invalid brightness `{}`, expected a number from -1 to 1",
+            brightness
+        )),
+    }
+}
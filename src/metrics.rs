@@ -0,0 +1,223 @@
+//! Prometheus metrics for long-running modes such as `--watch`.
+//!
+//! [`Metrics`] is a `ProgressObserver` that accumulates counters over every run it observes, and
+//! [`serve_metrics`] exposes them in the Prometheus text format on `/metrics`.
+use crate::errors::MyError;
+use crate::progress::{Progress, ProgressObserver};
+use crate::report::{FileReport, FileState, ProcessingReport};
+use crate::server::{self, Response};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Counters and gauges collected across processing runs.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+#[derive(Debug, Default)]
+struct MetricsState {
+    /// Totals of the runs that have finished.
+    points_read: u64,
+    points_written: u64,
+    /// The latest progress of the run in flight.
+    current: Progress,
+    files_processed: u64,
+    errors: u64,
+    /// Whether a file of the latest run was counted as an error.
+    file_failed: bool,
+    throughput: f64,
+}
+
+impl Metrics {
+    /// Creates metrics with all counters at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts an error that stopped a whole run, e.g. an input that couldn't be opened. A run
+    /// that failed with one of its files is already counted with the file.
+    pub fn record_error(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            if !state.file_failed {
+                state.errors += 1;
+            }
+            state.file_failed = false;
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let Ok(state) = self.state.lock() else {
+            return String::new();
+        };
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(out, "# HELP las_trimmer_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE las_trimmer_{} {}", name, kind);
+            let _ = writeln!(out, "las_trimmer_{} {}", name, value);
+        };
+        metric(
+            "points_read_total",
+            "counter",
+            "Points read from inputs.",
+            (state.points_read + state.current.points_read).to_string(),
+        );
+        metric(
+            "points_written_total",
+            "counter",
+            "Points written to outputs.",
+            (state.points_written + state.current.points_written).to_string(),
+        );
+        metric(
+            "files_processed_total",
+            "counter",
+            "Input files read completely.",
+            state.files_processed.to_string(),
+        );
+        metric(
+            "errors_total",
+            "counter",
+            "Input files that failed or timed out, and runs that failed otherwise.",
+            state.errors.to_string(),
+        );
+        metric(
+            "queue_depth",
+            "gauge",
+            "Batches of points read but not written yet.",
+            state.current.batches_queued.to_string(),
+        );
+        metric(
+            "throughput_points_per_second",
+            "gauge",
+            "Points read per second over the last progress interval.",
+            format!("{:.1}", state.throughput),
+        );
+        out
+    }
+}
+
+impl ProgressObserver for Metrics {
+    fn on_started(&self, _paths: &[String], _output_paths: &[String]) {
+        if let Ok(mut state) = self.state.lock() {
+            state.current = Progress::default();
+            state.file_failed = false;
+        }
+    }
+
+    fn on_file_finished(&self, _index: usize, file: &FileReport) {
+        if let Ok(mut state) = self.state.lock() {
+            match file.state {
+                FileState::Done => state.files_processed += 1,
+                FileState::Failed | FileState::TimedOut => {
+                    state.errors += 1;
+                    state.file_failed = true;
+                }
+                FileState::Queued | FileState::Reading => {}
+            }
+        }
+    }
+
+    fn on_batch_written(&self, _output_index: usize, _batch_size: usize, progress: &Progress) {
+        self.on_progress(progress);
+    }
+
+    fn on_progress(&self, progress: &Progress) {
+        if let Ok(mut state) = self.state.lock() {
+            let interval = progress.elapsed.saturating_sub(state.current.elapsed);
+            if interval >= Duration::from_millis(500) {
+                let points = progress
+                    .points_read
+                    .saturating_sub(state.current.points_read);
+                state.throughput = points as f64 / interval.as_secs_f64();
+                state.current = *progress;
+            } else {
+                state.current = Progress {
+                    elapsed: state.current.elapsed,
+                    ..*progress
+                };
+            }
+        }
+    }
+
+    fn on_finished(&self, report: &ProcessingReport) {
+        if let Ok(mut state) = self.state.lock() {
+            state.points_read += report.points_read();
+            state.points_written += report.points_written();
+            state.current = Progress::default();
+            state.file_failed = false;
+            state.throughput = 0.0;
+        }
+    }
+}
+
+/// Serves `metrics` on `http://<addr>/metrics` from a background thread. Returns the bound
+/// address.
+pub fn serve_metrics(addr: &str, metrics: Arc<Metrics>) -> Result<SocketAddr, MyError> {
+    server::serve(addr, move |path| match path {
+        "/metrics" => Some(Response {
+            content_type: "text/plain; version=0.0.4",
+            body: metrics.render(),
+        }),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_metrics_endpoint() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.on_progress(&Progress {
+            points_read: 1_000,
+            points_written: 400,
+            batches_queued: 3,
+            elapsed: Duration::from_secs(1),
+            ..Progress::default()
+        });
+        metrics.record_error();
+
+        let addr = serve_metrics("127.0.0.1:0", Arc::clone(&metrics)).unwrap();
+        // A client that never sends its request doesn't hold up the others
+        let _idle = TcpStream::connect(addr).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("las_trimmer_points_read_total 1000\n"));
+        assert!(response.contains("las_trimmer_points_written_total 400\n"));
+        assert!(response.contains("las_trimmer_errors_total 1\n"));
+        assert!(response.contains("las_trimmer_queue_depth 3\n"));
+        assert!(response.contains("las_trimmer_throughput_points_per_second 1000.0\n"));
+    }
+
+    #[test]
+    fn test_metrics_errors() {
+        let metrics = Metrics::new();
+        let failed = FileReport {
+            path: "broken.las".to_string(),
+            state: FileState::Failed,
+            points_read: 0,
+            corrupt_points: 0,
+            duration: Duration::ZERO,
+        };
+        // A run stopped by a failed file counts once
+        metrics.on_started(&[], &[]);
+        metrics.on_file_finished(0, &failed);
+        metrics.record_error();
+        assert!(metrics.render().contains("las_trimmer_errors_total 1\n"));
+        // A run failing before any file does too
+        metrics.record_error();
+        assert!(metrics.render().contains("las_trimmer_errors_total 2\n"));
+    }
+}
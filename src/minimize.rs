@@ -0,0 +1,150 @@
+//! Picking the smallest point format that holds what the points really use.
+//!
+//! Files are often written in a generous point format: color that is black everywhere, a GPS time
+//! shared by every point, or extra bytes that are all zero. [`DimensionUsage`] records which of
+//! those dimensions carry information across the points it sees, and picks the smallest format
+//! keeping them. The LAS 1.4 formats are only kept when a point needs them, for a class above 31,
+//! more than 7 returns, a scanner channel, the overlap flag or a scan angle the older formats would
+//! round, or when the points use the near infrared, which only they hold.
+use crate::errors::MyError;
+use las::point::Format;
+use las::Point;
+
+/// Which of the optional dimensions the points use.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DimensionUsage {
+    /// The GPS time of the first point, once one has been seen.
+    first_gps_time: Option<f64>,
+    varying_gps_time: bool,
+    color: bool,
+    nir: bool,
+    extra_bytes: bool,
+    extended: bool,
+}
+
+impl DimensionUsage {
+    /// Usage with no point seen.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records what `point` uses.
+    pub fn add(&mut self, point: &Point) {
+        if let Some(time) = point.gps_time {
+            match self.first_gps_time {
+                Some(first) => self.varying_gps_time |= time != first,
+                None => self.first_gps_time = Some(time),
+            }
+        }
+        self.color |= point
+            .color
+            .is_some_and(|color| (color.red, color.green, color.blue) != (0, 0, 0));
+        self.nir |= point.nir.is_some_and(|nir| nir != 0);
+        self.extra_bytes |= point.extra_bytes.iter().any(|&byte| byte != 0);
+        self.extended |= u8::from(point.classification) > 31
+            || point.return_number > 7
+            || point.number_of_returns > 7
+            || point.scanner_channel != 0
+            || point.is_overlap
+            || point.scan_angle.fract() != 0.0
+            || point.scan_angle.abs() > 90.0;
+    }
+
+    /// Whether the extra bytes hold anything but zeros.
+    pub fn uses_extra_bytes(&self) -> bool {
+        self.extra_bytes
+    }
+
+    /// The smallest format holding the dimensions used of points in `format`, keeping its extra
+    /// bytes unless they are all zero.
+    pub fn format(&self, format: &Format) -> Result<Format, MyError> {
+        let gps_time = format.has_gps_time && self.varying_gps_time;
+        let color = format.has_color && self.color;
+        let nir = format.has_nir && self.nir;
+        let number = if format.is_extended && (self.extended || nir) {
+            match (color, nir) {
+                (_, true) => 8,
+                (true, false) => 7,
+                (false, false) => 6,
+            }
+        } else {
+            match (gps_time, color) {
+                (false, false) => 0,
+                (true, false) => 1,
+                (false, true) => 2,
+                (true, true) => 3,
+            }
+        };
+        let mut minimal = Format::new(number)?;
+        minimal.extra_bytes = if self.extra_bytes {
+            format.extra_bytes
+        } else {
+            0
+        };
+        minimal.is_compressed = format.is_compressed;
+        Ok(minimal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::point::Classification;
+    use las::Color;
+
+    #[test]
+    fn test_dimension_usage() {
+        let mut format = Format::new(8).unwrap();
+        format.extra_bytes = 2;
+        let point = Point {
+            gps_time: Some(5.0),
+            color: Some(Color::new(0, 0, 0)),
+            nir: Some(0),
+            extra_bytes: vec![0, 0],
+            ..Default::default()
+        };
+        let mut usage = DimensionUsage::new();
+        usage.add(&point);
+        usage.add(&point);
+        let minimal = usage.format(&format).unwrap();
+        assert_eq!(minimal.to_u8().unwrap(), 0);
+        assert_eq!(minimal.extra_bytes, 0);
+
+        usage.add(&Point {
+            gps_time: Some(6.0),
+            color: Some(Color::new(1, 0, 0)),
+            ..point.clone()
+        });
+        assert_eq!(usage.format(&format).unwrap().to_u8().unwrap(), 3);
+
+        usage.add(&Point {
+            classification: Classification::new(40).unwrap(),
+            extra_bytes: vec![0, 1],
+            ..point
+        });
+        let minimal = usage.format(&format).unwrap();
+        assert_eq!(minimal.to_u8().unwrap(), 7);
+        assert_eq!(minimal.extra_bytes, 2);
+        assert!(usage.uses_extra_bytes());
+        // Dimensions the format doesn't have aren't added
+        let legacy = usage.format(&Format::new(1).unwrap()).unwrap();
+        assert_eq!(legacy.to_u8().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_dimension_usage_keeps_nir() {
+        // Nothing but the near infrared needs the LAS 1.4 formats
+        let mut usage = DimensionUsage::new();
+        usage.add(&Point {
+            gps_time: Some(5.0),
+            color: Some(Color::new(0, 0, 0)),
+            nir: Some(12),
+            ..Default::default()
+        });
+        let minimal = usage.format(&Format::new(10).unwrap()).unwrap();
+        assert_eq!(minimal.to_u8().unwrap(), 8);
+        // Formats without it stay as small as they were
+        let legacy = usage.format(&Format::new(3).unwrap()).unwrap();
+        assert_eq!(legacy.to_u8().unwrap(), 0);
+    }
+}
@@ -0,0 +1,259 @@
+//! Filters deciding on a point from the points around it, such as dropping isolated noise.
+//!
+//! A [`NeighborFilter`] keeps the points with at least a number of other points within a radius.
+//! A point's neighbours can come from anywhere in the inputs, so the points of an output are
+//! first spilled to temporary files by square tile, in a [`TileSpill`]. Once every point is in,
+//! the tiles are filtered one at a time: the points of a tile, and the points of the tiles
+//! around it within the radius of its edges, go into a [`KdTree`] that answers the radius
+//! queries. Only those nine tiles are in memory at once, so the memory needed depends on the
+//! tile size and the density of the points rather than on the size of the inputs.
+use crate::errors::MyError;
+use crate::kdtree::KdTree;
+use crate::spill::SpillConfig;
+use las::point::Format;
+use las::{Header, Point, Transform, Vector};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// The side of the tiles, in radii, unless it is set.
+pub const DEFAULT_TILE_SIZE_IN_RADII: f64 = 100.0;
+
+/// Keeps the points with at least `min_neighbors` other points within `radius`, in 3D.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NeighborFilter {
+    radius: f64,
+    min_neighbors: usize,
+    tile_size: f64,
+}
+
+impl NeighborFilter {
+    /// A filter keeping the points with `min_neighbors` within `radius`, which must be positive.
+    pub fn new(radius: f64, min_neighbors: usize) -> Result<Self, MyError> {
+        if !(radius > 0.0 && radius.is_finite()) {
+            return Err(MyError::InvalidNeighborhood(format!(
+                "the radius must be positive, not {}",
+                radius
+            )));
+        }
+        Ok(Self {
+            radius,
+            min_neighbors,
+            tile_size: radius * DEFAULT_TILE_SIZE_IN_RADII,
+        })
+    }
+
+    /// Sets the side of the tiles the points are spilled to, which is at least the radius.
+    /// Smaller tiles need less memory but read the points near their edges more often.
+    pub fn with_tile_size(mut self, tile_size: f64) -> Self {
+        self.tile_size = tile_size.max(self.radius);
+        self
+    }
+
+    /// The radius the neighbours are counted in.
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// The number of neighbours a point needs to be kept.
+    pub fn min_neighbors(&self) -> usize {
+        self.min_neighbors
+    }
+
+    fn tile_of(&self, point: &Point) -> (i64, i64) {
+        (
+            (point.x / self.tile_size).floor() as i64,
+            (point.y / self.tile_size).floor() as i64,
+        )
+    }
+}
+
+/// The points of a tile waiting to be written to its file.
+#[derive(Default)]
+struct Tile {
+    buffer: Vec<u8>,
+    points: u64,
+}
+
+/// Points spilled to temporary files by tile, to be filtered by a [`NeighborFilter`].
+pub struct TileSpill {
+    filter: NeighborFilter,
+    format: Format,
+    transforms: Vector<Transform>,
+    dir: TempDir,
+    tiles: BTreeMap<(i64, i64), Tile>,
+    buffered: usize,
+    /// The bytes of points held in memory before they are written to the tile files.
+    buffer_bytes: usize,
+}
+
+impl TileSpill {
+    /// A spill for points in the format of `header`, in a new temporary folder made as `spill`
+    /// says.
+    pub fn new(
+        filter: NeighborFilter,
+        header: &Header,
+        spill: &SpillConfig,
+    ) -> Result<Self, MyError> {
+        let mut format = *header.point_format();
+        format.is_compressed = false;
+        Ok(Self {
+            filter,
+            format,
+            transforms: *header.transforms(),
+            dir: spill.tempdir()?,
+            tiles: BTreeMap::new(),
+            buffered: 0,
+            buffer_bytes: spill.buffer_bytes(),
+        })
+    }
+
+    /// Takes the points out of `points` and spills them.
+    pub fn add(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        for point in points.drain(..) {
+            let tile = self.tiles.entry(self.filter.tile_of(&point)).or_default();
+            let before = tile.buffer.len();
+            point
+                .into_raw(&self.transforms)?
+                .write_to(&mut tile.buffer, &self.format)?;
+            tile.points += 1;
+            self.buffered += tile.buffer.len() - before;
+        }
+        if self.buffered > self.buffer_bytes {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered points to their tile files.
+    fn flush(&mut self) -> Result<(), MyError> {
+        for (key, tile) in &mut self.tiles {
+            if tile.buffer.is_empty() {
+                continue;
+            }
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(tile_path(&self.dir, *key))?;
+            // Taken rather than cleared, so that the memory of spilled tiles is given back
+            file.write_all(&std::mem::take(&mut tile.buffer))?;
+        }
+        self.buffered = 0;
+        Ok(())
+    }
+
+    /// Reads back the points of the tile at `key`.
+    fn read_tile(&self, key: (i64, i64)) -> Result<Vec<Point>, MyError> {
+        let Some(tile) = self.tiles.get(&key) else {
+            return Ok(Vec::new());
+        };
+        let mut file = BufReader::new(File::open(tile_path(&self.dir, key))?);
+        (0..tile.points)
+            .map(|_| {
+                let raw = las::raw::Point::read_from(&mut file, &self.format)?;
+                Ok(Point::new(raw, &self.transforms))
+            })
+            .collect()
+    }
+
+    /// Filters the spilled points, a tile at a time, handing the points kept to `write`.
+    pub fn finish(
+        mut self,
+        write: &mut dyn FnMut(&mut Vec<Point>) -> Result<(), MyError>,
+    ) -> Result<(), MyError> {
+        self.flush()?;
+        let radius = self.filter.radius;
+        let size = self.filter.tile_size;
+        let keys: Vec<(i64, i64)> = self.tiles.keys().copied().collect();
+        for (tx, ty) in keys {
+            let mut points = self.read_tile((tx, ty))?;
+            // The points of the tiles around within the radius of the edges
+            let (min_x, min_y) = (tx as f64 * size - radius, ty as f64 * size - radius);
+            let (max_x, max_y) = (min_x + size + 2.0 * radius, min_y + size + 2.0 * radius);
+            let mut positions: Vec<[f64; 3]> = points
+                .iter()
+                .map(|point| [point.x, point.y, point.z])
+                .collect();
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    if (dx, dy) == (0, 0) {
+                        continue;
+                    }
+                    positions.extend(
+                        self.read_tile((tx + dx, ty + dy))?
+                            .iter()
+                            .filter(|point| {
+                                (min_x..=max_x).contains(&point.x)
+                                    && (min_y..=max_y).contains(&point.y)
+                            })
+                            .map(|point| [point.x, point.y, point.z]),
+                    );
+                }
+            }
+            // Each point finds itself as well
+            let needed = self.filter.min_neighbors.saturating_add(1);
+            let tree = KdTree::new(positions);
+            points.retain(|point| {
+                tree.count_within([point.x, point.y, point.z], radius, needed) >= needed
+            });
+            write(&mut points)?;
+        }
+        Ok(())
+    }
+}
+
+fn tile_path(dir: &TempDir, (tx, ty): (i64, i64)) -> PathBuf {
+    dir.path().join(format!("{}_{}.bin", tx, ty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_neighbor_filter() {
+        assert!(NeighborFilter::new(0.0, 1).is_err());
+        // Tiles of 2 m, so clusters straddle the tile edges
+        let filter = NeighborFilter::new(0.5, 2).unwrap().with_tile_size(2.0);
+        let header = las::Builder::from((1, 4)).into_header().unwrap();
+        let mut spill = TileSpill::new(filter, &header, &SpillConfig::new()).unwrap();
+
+        // Rows of three points 0.3 apart, where only the middle one has two neighbours, and
+        // isolated points
+        let mut points = Vec::new();
+        for i in 0..10 {
+            let x = i as f64 * 1.9;
+            for offset in [0.0, 0.3, 0.6] {
+                points.push(Point {
+                    x: x + offset,
+                    y: 5.0,
+                    z: 1.0,
+                    ..Default::default()
+                });
+            }
+            points.push(Point {
+                x,
+                y: 20.0,
+                z: 1.0,
+                ..Default::default()
+            });
+        }
+        spill.add(&mut points).unwrap();
+        assert!(points.is_empty());
+
+        let mut kept = Vec::new();
+        spill
+            .finish(&mut |points| {
+                kept.append(points);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(kept.len(), 10);
+        for point in kept {
+            assert_eq!(point.y, 5.0);
+            assert!(((point.x - 0.3) / 1.9 - ((point.x - 0.3) / 1.9).round()).abs() < 1e-6);
+        }
+    }
+}
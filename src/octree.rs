@@ -0,0 +1,321 @@
+//! An in-memory octree over points, for box and radius queries once the inputs have been read.
+//!
+//! The points are reordered so that the points of every node are contiguous: a node covers a
+//! cube and either holds its points as a leaf, or splits them between the eight octants of its
+//! cube. [`LasProcessor::build_octree`] collects the points meeting a condition during a run,
+//! alongside the outputs, so an application can extract regions interactively afterwards
+//! without reading the inputs again.
+use crate::errors::MyError;
+use crate::filter::Condition;
+use crate::report::ProcessingReport;
+use crate::sink::MemoryOutput;
+use crate::LasProcessor;
+use las::{Bounds, Point, Vector};
+use std::ops::Range;
+
+/// The most points a leaf holds, unless it is set.
+pub const DEFAULT_LEAF_SIZE: usize = 64;
+
+/// The deepest a node can be, which stops the splitting of points sharing a position.
+const MAX_DEPTH: usize = 21;
+
+/// A node of the tree: a cube and the range of the points in it.
+#[derive(Clone, Debug)]
+struct Node {
+    min: [f64; 3],
+    size: f64,
+    range: Range<usize>,
+    /// The indices of the nodes of the non-empty octants, none for a leaf.
+    children: Vec<usize>,
+}
+
+impl Node {
+    fn max(&self) -> [f64; 3] {
+        self.min.map(|min| min + self.size)
+    }
+}
+
+/// An octree over points.
+#[derive(Clone, Debug, Default)]
+pub struct Octree {
+    points: Vec<Point>,
+    /// The nodes, the root first.
+    nodes: Vec<Node>,
+}
+
+impl Octree {
+    /// Builds a tree over `points` with at most [`DEFAULT_LEAF_SIZE`] points in a leaf.
+    pub fn new(points: Vec<Point>) -> Self {
+        Self::with_leaf_size(points, DEFAULT_LEAF_SIZE)
+    }
+
+    /// Builds a tree over `points` with at most `leaf_size` points in a leaf, unless they share a
+    /// position.
+    pub fn with_leaf_size(mut points: Vec<Point>, leaf_size: usize) -> Self {
+        let mut nodes = Vec::new();
+        if let Some(first) = points.first() {
+            let mut min = [first.x, first.y, first.z];
+            let mut max = min;
+            for point in &points {
+                for (axis, value) in [point.x, point.y, point.z].into_iter().enumerate() {
+                    min[axis] = min[axis].min(value);
+                    max[axis] = max[axis].max(value);
+                }
+            }
+            let size = (0..3)
+                .map(|axis| max[axis] - min[axis])
+                .fold(f64::MIN_POSITIVE, f64::max);
+            let count = points.len();
+            build(
+                &mut points,
+                0..count,
+                min,
+                size,
+                0,
+                leaf_size.max(1),
+                &mut nodes,
+            );
+        }
+        Self { points, nodes }
+    }
+
+    /// The number of points in the tree.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Returns `true` if the tree holds no points.
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The points, in the order of the tree.
+    pub fn points(&self) -> &[Point] {
+        &self.points
+    }
+
+    /// Takes the points out of the tree.
+    pub fn into_points(self) -> Vec<Point> {
+        self.points
+    }
+
+    /// The cube the root covers, which holds every point.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.nodes.first().map(|root| {
+            let [x, y, z] = root.min;
+            let [max_x, max_y, max_z] = root.max();
+            Bounds {
+                min: Vector { x, y, z },
+                max: Vector {
+                    x: max_x,
+                    y: max_y,
+                    z: max_z,
+                },
+            }
+        })
+    }
+
+    /// The points inside `bounds`, edges included.
+    pub fn in_bounds(&self, bounds: &Bounds) -> Vec<&Point> {
+        let min = [bounds.min.x, bounds.min.y, bounds.min.z];
+        let max = [bounds.max.x, bounds.max.y, bounds.max.z];
+        let contains = |point: &Point| {
+            [point.x, point.y, point.z]
+                .iter()
+                .enumerate()
+                .all(|(axis, value)| (min[axis]..=max[axis]).contains(value))
+        };
+        let mut found = Vec::new();
+        let mut stack: Vec<usize> = (!self.nodes.is_empty()).then_some(0).into_iter().collect();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let node_max = node.max();
+            if (0..3).any(|axis| node_max[axis] < min[axis] || node.min[axis] > max[axis]) {
+                continue;
+            }
+            let points = &self.points[node.range.clone()];
+            if (0..3).all(|axis| node.min[axis] >= min[axis] && node_max[axis] <= max[axis]) {
+                found.extend(points);
+            } else if node.children.is_empty() {
+                found.extend(points.iter().filter(|point| contains(point)));
+            } else {
+                stack.extend(&node.children);
+            }
+        }
+        found
+    }
+
+    /// The points within `radius` of `centre`, in 3D.
+    pub fn within(&self, centre: [f64; 3], radius: f64) -> Vec<&Point> {
+        let radius_squared = radius * radius;
+        let distance_squared = |position: [f64; 3]| -> f64 {
+            (0..3)
+                .map(|axis| (position[axis] - centre[axis]).powi(2))
+                .sum()
+        };
+        let mut found = Vec::new();
+        let mut stack: Vec<usize> = (!self.nodes.is_empty()).then_some(0).into_iter().collect();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let node_max = node.max();
+            let closest: [f64; 3] =
+                std::array::from_fn(|axis| centre[axis].clamp(node.min[axis], node_max[axis]));
+            if distance_squared(closest) > radius_squared {
+                continue;
+            }
+            if node.children.is_empty() {
+                found.extend(self.points[node.range.clone()].iter().filter(|point| {
+                    distance_squared([point.x, point.y, point.z]) <= radius_squared
+                }));
+            } else {
+                stack.extend(&node.children);
+            }
+        }
+        found
+    }
+}
+
+/// Adds the node of the cube at `min` with side `size` holding `points[range]` to `nodes`, and
+/// its children after it, and returns its index.
+fn build(
+    points: &mut [Point],
+    range: Range<usize>,
+    min: [f64; 3],
+    size: f64,
+    depth: usize,
+    leaf_size: usize,
+    nodes: &mut Vec<Node>,
+) -> usize {
+    let index = nodes.len();
+    nodes.push(Node {
+        min,
+        size,
+        range: range.clone(),
+        children: Vec::new(),
+    });
+    if range.len() <= leaf_size || depth >= MAX_DEPTH {
+        return index;
+    }
+    let half = size / 2.0;
+    let octant = |point: &Point| {
+        [point.x, point.y, point.z]
+            .iter()
+            .enumerate()
+            .map(|(axis, value)| usize::from(*value >= min[axis] + half) << axis)
+            .sum::<usize>()
+    };
+    points[range.clone()].sort_unstable_by_key(octant);
+    let mut children = Vec::new();
+    let mut start = range.start;
+    for child in 0..8 {
+        let end = start
+            + points[start..range.end]
+                .iter()
+                .take_while(|point| octant(point) == child)
+                .count();
+        if end > start {
+            let child_min: [f64; 3] =
+                std::array::from_fn(|axis| min[axis] + half * ((child >> axis) & 1) as f64);
+            children.push(build(
+                points,
+                start..end,
+                child_min,
+                half,
+                depth + 1,
+                leaf_size,
+                nodes,
+            ));
+        }
+        start = end;
+    }
+    nodes[index].children = children;
+    index
+}
+
+impl LasProcessor {
+    /// Processes the inputs like [`LasProcessor::process_lidar_files`], writing the outputs as
+    /// usual, and builds an octree over the points meeting `condition` in the same pass.
+    pub fn build_octree(self, condition: Condition) -> Result<(Octree, ProcessingReport), MyError> {
+        let points = MemoryOutput::new();
+        let report = self
+            .with_sink("octree", points.clone(), condition)
+            .process_lidar_files()?;
+        Ok((Octree::new(points.take()), report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_octree() {
+        let input = "tests/data/input1.las";
+        let (tree, report) =
+            LasProcessor::new(vec![input.to_string()], Vec::new(), Vec::new(), false)
+                .with_observer(Arc::new(crate::NoProgress))
+                .build_octree(Condition::on_point(Arc::new(|_| true)))
+                .unwrap();
+        let points: Vec<Point> = las::Reader::from_path(input)
+            .unwrap()
+            .points()
+            .map(|point| point.unwrap())
+            .collect();
+        assert_eq!(tree.len(), points.len());
+        assert_eq!(report.outputs[0].points_written, points.len() as u64);
+        let tree = Octree::with_leaf_size(tree.into_points(), 8);
+
+        let mut extent = Bounds::default();
+        for point in &points {
+            extent.grow(point);
+        }
+        let bounds = tree.bounds().unwrap();
+        assert!(bounds.min.x <= extent.min.x && bounds.max.z >= extent.max.z);
+        let centre = [
+            (extent.min.x + extent.max.x) / 2.0,
+            (extent.min.y + extent.max.y) / 2.0,
+            (extent.min.z + extent.max.z) / 2.0,
+        ];
+        // The half of the points west of the centre
+        let query = Bounds {
+            min: extent.min,
+            max: Vector {
+                x: centre[0],
+                ..extent.max
+            },
+        };
+        let mut found: Vec<(f64, f64, f64)> = tree
+            .in_bounds(&query)
+            .iter()
+            .map(|point| (point.x, point.y, point.z))
+            .collect();
+        let mut expected: Vec<(f64, f64, f64)> = points
+            .iter()
+            .filter(|point| {
+                (query.min.x..=query.max.x).contains(&point.x)
+                    && (query.min.y..=query.max.y).contains(&point.y)
+                    && (query.min.z..=query.max.z).contains(&point.z)
+            })
+            .map(|point| (point.x, point.y, point.z))
+            .collect();
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!(!expected.is_empty());
+        assert_eq!(found, expected);
+
+        let radius = (extent.max.x - extent.min.x) / 2.0;
+        let expected = points
+            .iter()
+            .filter(|point| {
+                (point.x - centre[0]).powi(2)
+                    + (point.y - centre[1]).powi(2)
+                    + (point.z - centre[2]).powi(2)
+                    <= radius * radius
+            })
+            .count();
+        assert!(expected > 0);
+        assert_eq!(tree.within(centre, radius).len(), expected);
+        assert!(Octree::new(Vec::new()).within(centre, radius).is_empty());
+    }
+}
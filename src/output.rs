@@ -0,0 +1,1042 @@
+//! Creating LAS/LAZ writers for the different kinds of output the processor accepts.
+//!
+//! Outputs are passed around as plain strings. Most of them are file paths, but the special path
+//! `-` means the point data is streamed to stdout, and URLs like `s3://bucket/key.laz` are
+//! uploaded to object storage (see [`crate::remote`]). Sinks handing their points to the caller
+//! have no path and are written to through [`OutputWriter::sink`] (see [`crate::sink`]).
+use crate::append::AppendWriter;
+use crate::compression::{LazChunking, LazWriter};
+use crate::errors::MyError;
+use crate::external_sort::{ExternalSort, PointOrder};
+use crate::legacy::fill_legacy_counts;
+use crate::neighborhood::{NeighborFilter, TileSpill};
+use crate::output_options::fit_point;
+use crate::remote;
+use crate::report::OutputReport;
+use crate::sink::SharedSink;
+use crate::spill::SpillConfig;
+use las::{Builder, Header, Point, Reader, Transform, Vector, Version, Writer};
+use log::warn;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// The output path that stands for stdout.
+pub const STDOUT_PATH: &str = "-";
+
+/// Returns `true` if `path` refers to stdout rather than a file.
+pub fn is_stdout(path: &str) -> bool {
+    path == STDOUT_PATH
+}
+
+/// The placeholder in an output path template that stands for the input file name without its
+/// extension.
+pub const STEM_PLACEHOLDER: &str = "{stem}";
+
+/// Returns `true` if `template` contains a placeholder filled in by [`render_output_path`].
+pub fn is_template(template: &str) -> bool {
+    template.contains(STEM_PLACEHOLDER)
+}
+
+/// Fills in the placeholders of an output path template for the input at `input_path`.
+pub fn render_output_path(template: &str, input_path: &str) -> String {
+    let stem = Path::new(input_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    template.replace(STEM_PLACEHOLDER, &stem)
+}
+
+/// The placeholder in an output path that stands for the number of the file, when the output is
+/// spread over several files of a limited number of points.
+pub const PART_PLACEHOLDER: &str = "{part}";
+
+/// The path of file number `part` of an output spread over several files, counting from 1. The
+/// number replaces `{part}` if the path has it, and is otherwise added to the file name before its
+/// extension.
+pub fn render_part_path(path: &str, part: usize) -> String {
+    if path.contains(PART_PLACEHOLDER) {
+        return path.replace(PART_PLACEHOLDER, &part.to_string());
+    }
+    let file_name_start = path.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    match path[file_name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = file_name_start + dot;
+            format!("{}_{}{}", &path[..dot], part, &path[dot..])
+        }
+        _ => format!("{}_{}", path, part),
+    }
+}
+
+/// Checks that no two outputs are the same file, that no output would overwrite one of the
+/// inputs, and unless `overwrite` is set, that no output file exists yet.
+pub fn check_output_paths(
+    output_paths: &[String],
+    input_paths: &[String],
+    overwrite: bool,
+) -> Result<(), MyError> {
+    let local_inputs: Vec<_> = input_paths
+        .iter()
+        .filter_map(|path| fs::canonicalize(path).ok())
+        .collect();
+    let mut resolved = Vec::with_capacity(output_paths.len());
+    for output_path in output_paths {
+        if is_stdout(output_path) {
+            continue;
+        }
+        let path = resolve_output_path(output_path);
+        if resolved.contains(&path) {
+            return Err(MyError::DuplicateOutput(output_path.clone()));
+        }
+        resolved.push(path);
+    }
+    for output_path in output_paths {
+        if is_stdout(output_path) || remote::is_remote(output_path) {
+            continue;
+        }
+        let Ok(output) = fs::canonicalize(output_path) else {
+            // The output doesn't exist yet.
+            continue;
+        };
+        if local_inputs.contains(&output) {
+            return Err(MyError::OutputOverlapsInput(output_path.clone()));
+        }
+        if !overwrite {
+            return Err(MyError::OutputExists(output_path.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// The file `path` stands for, with the folder it is in resolved if it exists, so that different
+/// ways of writing the same path compare equal.
+fn resolve_output_path(path: &str) -> PathBuf {
+    if remote::is_remote(path) {
+        return PathBuf::from(path);
+    }
+    let path = Path::new(path);
+    if let Ok(resolved) = fs::canonicalize(path) {
+        return resolved;
+    }
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    match (fs::canonicalize(dir), path.file_name()) {
+        (Ok(dir), Some(file_name)) => dir.join(file_name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Decides when an existing output counts as up to date, so that processing can be skipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipExisting {
+    /// The output file exists.
+    Exists,
+    /// The output file exists and was modified after every input.
+    Newer,
+}
+
+/// Returns `true` if every output in `output_paths` is up to date with `input_paths` according
+/// to `policy`. Outputs that aren't local files are never considered up to date.
+pub fn outputs_up_to_date(
+    output_paths: &[String],
+    input_paths: &[String],
+    policy: SkipExisting,
+) -> bool {
+    let modified = |path: &str| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    let newest_input = match policy {
+        SkipExisting::Exists => None,
+        SkipExisting::Newer => {
+            let input_times: Option<Vec<_>> =
+                input_paths.iter().map(|path| modified(path)).collect();
+            let Some(newest) = input_times.and_then(|times| times.into_iter().max()) else {
+                return false;
+            };
+            Some(newest)
+        }
+    };
+    !output_paths.is_empty()
+        && output_paths.iter().all(|path| {
+            if is_stdout(path) || remote::is_remote(path) {
+                return false;
+            }
+            match (modified(path), newest_input) {
+                (Some(output_time), Some(newest)) => output_time >= newest,
+                (Some(_), None) => true,
+                (None, _) => false,
+            }
+        })
+}
+
+/// A LAS/LAZ writer for one output path.
+///
+/// Points are always written to a temporary file first, and [`OutputWriter::finish`] moves the
+/// file to its destination once the header has been finalized:
+///
+/// * Local files are staged next to the destination and renamed into place, so a crash never
+///   leaves a truncated output with a plausible looking header behind.
+/// * Stdout and object stores aren't seekable, so the finished file is copied or uploaded.
+///   Data written to stdout is always uncompressed LAS.
+///
+/// Sinks get the points directly, and only the header is kept to report on them.
+///
+/// An output created with [`OutputWriter::create_parts`] moves on to a new file whenever the
+/// current one is full, and reports on every file it wrote. Any other output does the same once a
+/// file counts as many points as its LAS version can, rather than writing a header with a wrapped
+/// count: versions before 1.4 count up to `u32::MAX` points. The first file keeps its path and the
+/// next ones are named by [`render_part_path`], from 2.
+///
+/// With a neighbourhood filter, the points are spilled by tile until the output is finished, and
+/// only then filtered and written. A sorted output holds its points in an [`ExternalSort`] until it
+/// is finished in the same way, after the neighbourhood filter when it has one.
+///
+/// An output opened with [`OutputWriter::append`] adds its points to the end of a staged copy of
+/// the existing file, with an [`AppendWriter`].
+pub struct OutputWriter {
+    path: String,
+    target: Target,
+    parts: Option<Parts>,
+    verify: bool,
+    neighborhood: Option<TileSpill>,
+    sort: Option<ExternalSort>,
+    /// Whether the points are fitted to the point format of the output as they are written.
+    fit: bool,
+    /// The number of points already in the file appended to, which aren't reported as written.
+    existing: u64,
+    /// Whether the legacy point counts of LAS 1.4 files are set.
+    legacy: bool,
+    /// The most points the current file can count.
+    limit: u64,
+    /// How LAZ files are split into chunks, for the files the output rolls over to.
+    chunking: LazChunking,
+}
+
+/// What it takes to start the next file of an output spread over several files.
+struct Parts {
+    path: String,
+    header: Header,
+    chunking: LazChunking,
+    max_points: u64,
+    /// The reports on the files that are already finished.
+    finished: Vec<OutputReport>,
+}
+
+/// What the points are written to.
+enum Target {
+    File {
+        writer: PointWriter,
+        spill: NamedTempFile,
+        destination: Destination,
+    },
+    Sink {
+        sink: SharedSink,
+        header: Box<Header>,
+    },
+    Append {
+        writer: Box<AppendWriter>,
+        /// The copy of the file appended to, which replaces it once finished.
+        spill: NamedTempFile,
+    },
+}
+
+/// The writer for the points, depending on whether the output is compressed.
+enum PointWriter {
+    Las(Writer<BufWriter<File>>),
+    Laz(Box<LazWriter<BufWriter<File>>>),
+}
+
+impl PointWriter {
+    fn header(&self) -> &Header {
+        match self {
+            PointWriter::Las(writer) => writer.header(),
+            PointWriter::Laz(writer) => writer.header(),
+        }
+    }
+
+    fn into_inner(self) -> Result<BufWriter<File>, MyError> {
+        match self {
+            PointWriter::Las(writer) => Ok(writer.into_inner()?),
+            PointWriter::Laz(writer) => writer.into_inner(),
+        }
+    }
+}
+
+/// Where the finished file ends up.
+enum Destination {
+    File(String),
+    Stdout,
+    Remote(String),
+}
+
+impl OutputWriter {
+    /// Creates a writer for `path` using `header` as the template for the output header.
+    /// `chunking` is used if the output is LAZ.
+    ///
+    /// Errors from the writer are wrapped in `MyError::WriteError` so that they name the output.
+    pub fn create(path: &str, header: Header, chunking: LazChunking) -> Result<Self, MyError> {
+        Self::create_unwrapped(path, header, chunking).map_err(|err| write_error(path, err))
+    }
+
+    /// Creates a writer spreading the points over files of at most `max_points` points each,
+    /// named after `path` by [`render_part_path`]. Stdout can't be split and gets all the points.
+    pub fn create_parts(
+        path: &str,
+        header: Header,
+        chunking: LazChunking,
+        max_points: u64,
+    ) -> Result<Self, MyError> {
+        if is_stdout(path) {
+            return Self::create(path, header, chunking);
+        }
+        let mut writer = Self::create(&render_part_path(path, 1), header.clone(), chunking)?;
+        writer.parts = Some(Parts {
+            path: path.to_string(),
+            header,
+            chunking,
+            max_points: max_points.max(1),
+            finished: Vec::new(),
+        });
+        Ok(writer)
+    }
+
+    /// The number of points written to the current file.
+    fn points_in_file(&self) -> u64 {
+        match &self.target {
+            Target::File { writer, .. } => writer.header().number_of_points(),
+            Target::Sink { header, .. } => header.number_of_points(),
+            Target::Append { writer, .. } => writer.header().number_of_points(),
+        }
+    }
+
+    /// Finishes the current file and starts the next one if the current one is full.
+    fn next_part_if_full(&mut self) -> Result<(), MyError> {
+        let max_points = match &self.parts {
+            Some(parts) => parts.max_points.min(self.limit),
+            None => self.limit,
+        };
+        if self.points_in_file() < max_points {
+            return Ok(());
+        }
+        let mut parts = match self.parts.take() {
+            Some(parts) => parts,
+            None => self.overflow_parts()?,
+        };
+        let path = render_part_path(&parts.path, parts.finished.len() + 2);
+        let next = Self::create(&path, parts.header.clone(), parts.chunking)?
+            .with_verify(self.verify)
+            .with_fitted_points(self.fit)
+            .with_legacy_counts(self.legacy);
+        parts
+            .finished
+            .extend(std::mem::replace(self, next).finish()?);
+        self.parts = Some(parts);
+        Ok(())
+    }
+
+    /// Spreads an output over several files once its first file counts as many points as it can.
+    /// Fails with `MyError::TooManyPoints` for stdout, which can't be spread.
+    fn overflow_parts(&self) -> Result<Parts, MyError> {
+        if is_stdout(&self.path) {
+            return Err(MyError::TooManyPoints(self.path.clone(), self.limit));
+        }
+        let next_path = render_part_path(&self.path, 2);
+        warn!(
+            "{} can't count more than {} points, the next points go to {}",
+            self.path, self.limit, next_path
+        );
+        let mut header = self.header().clone();
+        header.clear();
+        Ok(Parts {
+            path: self.path.clone(),
+            header,
+            chunking: self.chunking,
+            max_points: self.limit,
+            finished: Vec::new(),
+        })
+    }
+
+    /// Creates a writer handing the points to `sink`, reported under `name`, with `header` as
+    /// the template for the header kept to report on it.
+    pub(crate) fn sink(
+        name: &str,
+        sink: SharedSink,
+        mut header: Header,
+        chunking: LazChunking,
+    ) -> Self {
+        header.clear();
+        Self {
+            path: name.to_string(),
+            target: Target::Sink {
+                sink,
+                header: Box::new(header),
+            },
+            parts: None,
+            verify: false,
+            neighborhood: None,
+            sort: None,
+            fit: false,
+            existing: 0,
+            legacy: false,
+            limit: u64::MAX,
+            chunking,
+        }
+    }
+
+    fn create_unwrapped(
+        path: &str,
+        header: Header,
+        chunking: LazChunking,
+    ) -> Result<Self, MyError> {
+        let compressed = path.to_lowercase().ends_with(".laz");
+        let (spill, destination) = if is_stdout(path) {
+            (NamedTempFile::new()?, Destination::Stdout)
+        } else if remote::is_remote(path) {
+            (NamedTempFile::new()?, Destination::Remote(path.to_string()))
+        } else {
+            (staging_file(path)?, Destination::File(path.to_string()))
+        };
+        let compressed = compressed && !matches!(destination, Destination::Stdout);
+        let writer = spill_writer(&spill, header, compressed, chunking)?;
+        let limit = point_limit(writer.header());
+        Ok(Self {
+            path: path.to_string(),
+            target: Target::File {
+                writer,
+                spill,
+                destination,
+            },
+            parts: None,
+            verify: false,
+            neighborhood: None,
+            sort: None,
+            fit: false,
+            existing: 0,
+            legacy: false,
+            limit,
+            chunking,
+        })
+    }
+
+    /// Creates a writer adding points to the end of the existing file at `path`, or a new file
+    /// like [`OutputWriter::create`] if there is none yet. The bytes of the existing file are
+    /// copied to the staged file, whose header and compression are kept, and the new points are
+    /// written after them. The existing file is only replaced once the output is finished, so it
+    /// is left as it was if the run fails or is stopped. Stdout is written as usual.
+    ///
+    /// Fails with `MyError::IncompatibleAppend` if the existing file has another point format,
+    /// scales or offsets than `header`, or is in an object store. The reports count the new
+    /// points only, while their bounds are those of the whole file.
+    pub fn append(path: &str, header: Header, chunking: LazChunking) -> Result<Self, MyError> {
+        if remote::is_remote(path) {
+            return Err(MyError::IncompatibleAppend(
+                path.to_string(),
+                "only local files can be appended to".to_string(),
+            ));
+        }
+        if is_stdout(path) || !Path::new(path).exists() {
+            return Self::create(path, header, chunking);
+        }
+        let spill = staging_file(path)?;
+        fs::copy(path, spill.path()).map_err(|err| write_error(path, err.into()))?;
+        let staged = spill.path().to_string_lossy();
+        let writer = AppendWriter::open(&staged, chunking).map_err(|err| match err {
+            MyError::IncompatibleAppend(_, reason) => {
+                MyError::IncompatibleAppend(path.to_string(), reason)
+            }
+            err => write_error(path, err),
+        })?;
+        check_appendable(path, writer.header(), &header)?;
+        let existing = writer.header().number_of_points();
+        Ok(Self {
+            path: path.to_string(),
+            limit: point_limit(writer.header()),
+            target: Target::Append {
+                writer: Box::new(writer),
+                spill,
+            },
+            parts: None,
+            verify: false,
+            neighborhood: None,
+            sort: None,
+            fit: false,
+            existing,
+            legacy: false,
+            chunking,
+        })
+    }
+
+    /// Re-opens every finished file before it is moved into place, and fails with
+    /// `MyError::VerificationFailed` unless its header counts the points written and its last
+    /// point, and so its last LAZ chunk, can be decoded. Catches files cut short by a full disk or
+    /// a flaky network filesystem. Sinks aren't verified.
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Fits the points to the point format of the output as they are written, for outputs whose
+    /// header was changed by [`OutputOptions`](crate::output_options::OutputOptions).
+    pub fn with_fitted_points(mut self, fit: bool) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// Sets the legacy point count and points by return of LAS 1.4 files once they are written,
+    /// for software older than LAS 1.4. See [`fill_legacy_counts`].
+    pub fn with_legacy_counts(mut self, legacy: bool) -> Self {
+        self.legacy = legacy;
+        self
+    }
+
+    /// The header of the current file.
+    fn header(&self) -> &Header {
+        match &self.target {
+            Target::File { writer, .. } => writer.header(),
+            Target::Sink { header, .. } => header,
+            Target::Append { writer, .. } => writer.header(),
+        }
+    }
+
+    /// Only writes the points `filter` keeps, once the output is finished. The points are spilled
+    /// as `spill` says until then.
+    pub fn with_neighbor_filter(
+        mut self,
+        filter: NeighborFilter,
+        spill: &SpillConfig,
+    ) -> Result<Self, MyError> {
+        self.neighborhood = Some(TileSpill::new(filter, self.header(), spill)?);
+        Ok(self)
+    }
+
+    /// Writes the points in `order` once the output is finished. The points are spilled as
+    /// `spill` says until then.
+    pub fn with_sort(mut self, order: PointOrder, spill: &SpillConfig) -> Self {
+        self.sort = Some(ExternalSort::new(order, self.header(), spill.clone()));
+        self
+    }
+
+    /// Writes a single point.
+    pub fn write_point(&mut self, mut point: Point) -> Result<(), MyError> {
+        if self.fit {
+            fit_point(&mut point, self.header().point_format());
+        }
+        if let Some(spill) = &mut self.neighborhood {
+            return spill
+                .add(&mut vec![point])
+                .map_err(|err| write_error(&self.path, err));
+        }
+        if let Some(sort) = &mut self.sort {
+            return sort
+                .add(&mut vec![point])
+                .map_err(|err| write_error(&self.path, err));
+        }
+        self.next_part_if_full()?;
+        match &mut self.target {
+            Target::File {
+                writer: PointWriter::Las(writer),
+                ..
+            } => writer.write_point(point).map_err(MyError::from),
+            Target::File {
+                writer: PointWriter::Laz(writer),
+                ..
+            } => writer.write_point(point),
+            Target::Append { writer, .. } => writer.write_point(point),
+            Target::Sink { sink, header } => {
+                header.add_point(&point);
+                let mut sink = sink.lock().map_err(|_| MyError::LockError)?;
+                sink.write_points(&mut vec![point])
+            }
+        }
+        .map_err(|err| write_error(&self.path, err))
+    }
+
+    /// Writes a batch of points, taking them out of `points`, and ends the batch like
+    /// [`OutputWriter::end_batch`]. Sinks get the whole batch at once.
+    pub fn write_batch(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        if self.fit {
+            let format = *self.header().point_format();
+            for point in points.iter_mut() {
+                fit_point(point, &format);
+            }
+        }
+        if let Some(spill) = &mut self.neighborhood {
+            return spill
+                .add(points)
+                .map_err(|err| write_error(&self.path, err));
+        }
+        if let Some(sort) = &mut self.sort {
+            return sort.add(points).map_err(|err| write_error(&self.path, err));
+        }
+        if let Target::Sink { sink, header } = &mut self.target {
+            for point in points.iter() {
+                header.add_point(point);
+            }
+            let mut sink = sink.lock().map_err(|_| MyError::LockError)?;
+            let result = sink.write_points(points);
+            points.clear();
+            return result.map_err(|err| write_error(&self.path, err));
+        }
+        for point in points.drain(..) {
+            self.write_point(point)?;
+        }
+        self.end_batch()
+    }
+
+    /// Marks the end of a batch of points. With `LazChunking::Variable` this ends the current
+    /// LAZ chunk.
+    pub fn end_batch(&mut self) -> Result<(), MyError> {
+        match &mut self.target {
+            Target::File {
+                writer: PointWriter::Laz(writer),
+                ..
+            } => writer.end_chunk(),
+            Target::Append { writer, .. } => writer.end_chunk(),
+            _ => Ok(()),
+        }
+        .map_err(|err| write_error(&self.path, err))
+    }
+
+    /// Finalizes the header and moves the finished file to its destination. Returns what was
+    /// written to each file of the output.
+    pub fn finish(mut self) -> Result<Vec<OutputReport>, MyError> {
+        if let Some(spill) = self.neighborhood.take() {
+            spill.finish(&mut |points| self.write_batch(points))?;
+        }
+        if let Some(sort) = self.sort.take() {
+            sort.finish(&mut |mut points| self.write_batch(&mut points))?;
+        }
+        let mut reports = self
+            .parts
+            .take()
+            .map(|parts| parts.finished)
+            .unwrap_or_default();
+        let header = self.header();
+        let report = OutputReport {
+            path: self.path.clone(),
+            points_written: header.number_of_points() - self.existing,
+            bounds: (header.number_of_points() > 0).then(|| header.bounds()),
+        };
+        self.finish_unwrapped()
+            .map_err(|err| write_error(&report.path, err))?;
+        reports.push(report);
+        Ok(reports)
+    }
+
+    fn finish_unwrapped(self) -> Result<(), MyError> {
+        let (writer, spill, destination) = match self.target {
+            Target::File {
+                writer,
+                spill,
+                destination,
+            } => (writer, spill, destination),
+            Target::Sink { sink, .. } => {
+                return sink.lock().map_err(|_| MyError::LockError)?.finish();
+            }
+            Target::Append { writer, spill } => {
+                let points = writer.header().number_of_points();
+                let mut file = writer.finish()?;
+                if self.legacy {
+                    fill_legacy_counts(&mut file)?;
+                }
+                if self.verify {
+                    file.sync_all()?;
+                    verify_file(spill.path(), points)?;
+                }
+                spill.persist(&self.path).map_err(|err| err.error)?;
+                return Ok(());
+            }
+        };
+        let points = writer.header().number_of_points();
+        let mut file = writer.into_inner()?;
+        file.flush()?;
+        if self.legacy {
+            fill_legacy_counts(file.get_mut())?;
+        }
+        if self.verify {
+            // Writes the file out, for errors the filesystem only reports then
+            file.get_ref().sync_all()?;
+            verify_file(spill.path(), points)?;
+        }
+        match destination {
+            Destination::File(path) => {
+                spill.persist(path).map_err(|err| err.error)?;
+            }
+            Destination::Stdout => {
+                let mut spilled = spill.reopen()?;
+                let mut stdout = std::io::stdout().lock();
+                std::io::copy(&mut spilled, &mut stdout)?;
+                stdout.flush()?;
+            }
+            Destination::Remote(path) => remote::upload(spill.path(), &path)?,
+        }
+        Ok(())
+    }
+}
+
+fn write_error(path: &str, err: MyError) -> MyError {
+    MyError::WriteError {
+        path: path.to_string(),
+        source: Box::new(err),
+    }
+}
+
+/// The most points a file with `header` can count: LAS versions before 1.4 count them in 32 bits.
+fn point_limit(header: &Header) -> u64 {
+    if header.version() < Version::new(1, 4) {
+        u64::from(u32::MAX)
+    } else {
+        u64::MAX
+    }
+}
+
+/// Checks that the points of an output with `header` can be added to the file at `path`, whose
+/// header is `existing`. Only the compression of the point formats may differ: points with other
+/// scales or offsets would be stored at another precision than the points around them.
+fn check_appendable(path: &str, existing: &Header, header: &Header) -> Result<(), MyError> {
+    let mut existing_format = *existing.point_format();
+    let mut format = *header.point_format();
+    existing_format.is_compressed = false;
+    format.is_compressed = false;
+    if existing_format != format {
+        return Err(MyError::IncompatibleAppend(
+            path.to_string(),
+            format!(
+                "it holds points of format {} with {} extra bytes, not format {} with {}",
+                existing_format.to_u8()?,
+                existing_format.extra_bytes,
+                format.to_u8()?,
+                format.extra_bytes
+            ),
+        ));
+    }
+    if existing.transforms() != header.transforms() {
+        return Err(MyError::IncompatibleAppend(
+            path.to_string(),
+            format!(
+                "its coordinates are stored with {}, not {}",
+                describe_transforms(existing.transforms()),
+                describe_transforms(header.transforms())
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The scales and offsets of `transforms`, for messages.
+fn describe_transforms(transforms: &Vector<Transform>) -> String {
+    format!(
+        "scales {} {} {} and offsets {} {} {}",
+        transforms.x.scale,
+        transforms.y.scale,
+        transforms.z.scale,
+        transforms.x.offset,
+        transforms.y.offset,
+        transforms.z.offset
+    )
+}
+
+/// Checks that the file at `path` holds `points` points and that the last one can be read.
+fn verify_file(path: &Path, points: u64) -> Result<(), MyError> {
+    let mut reader = Reader::from_path(path).map_err(|err| {
+        MyError::VerificationFailed(format!("the written file can't be opened: {}", err))
+    })?;
+    let header_points = reader.header().number_of_points();
+    if header_points != points {
+        return Err(MyError::VerificationFailed(format!(
+            "the header counts {} points but {} were written",
+            header_points, points
+        )));
+    }
+    if points == 0 {
+        return Ok(());
+    }
+    let last = reader.seek(points - 1).and_then(|()| reader.read_point());
+    match last {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(MyError::VerificationFailed(format!(
+            "point {} is missing",
+            points - 1
+        ))),
+        Err(err) => Err(MyError::VerificationFailed(format!(
+            "point {} can't be read: {}",
+            points - 1,
+            err
+        ))),
+    }
+}
+
+/// Creates the hidden temporary file that a local output is written to before it is renamed
+/// into place. It lives in the destination directory so that the rename can't cross filesystems.
+fn staging_file(path: &str) -> std::io::Result<NamedTempFile> {
+    let path = Path::new(path);
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let prefix = format!(".{}.", file_name);
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(&prefix).suffix(".tmp");
+    // Temporary files are private by default, but the output should get the usual permissions.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    builder.tempfile_in(dir)
+}
+
+/// Creates a writer backed by a temporary file.
+fn spill_writer(
+    spill: &NamedTempFile,
+    header: Header,
+    compressed: bool,
+    chunking: LazChunking,
+) -> Result<PointWriter, MyError> {
+    let write = BufWriter::new(spill.reopen()?);
+    if compressed {
+        let writer = LazWriter::new(write, header, chunking)?;
+        return Ok(PointWriter::Laz(Box::new(writer)));
+    }
+    let mut builder = Builder::from(header);
+    builder.point_format.is_compressed = false;
+    // A laszip VLR copied from a LAZ input would be stale
+    builder.vlrs.retain(|vlr| !las::laz::is_laszip_vlr(vlr));
+    builder.evlrs.retain(|vlr| !las::laz::is_laszip_vlr(vlr));
+    let header = builder.into_header()?;
+    Ok(PointWriter::Las(Writer::new(write, header)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_output_path() {
+        assert!(is_template("out/{stem}_ground.laz"));
+        assert!(!is_template("out/ground.laz"));
+        assert_eq!(
+            render_output_path("out/{stem}_ground.laz", "/drop/flight_03.las"),
+            "out/flight_03_ground.laz"
+        );
+    }
+
+    #[test]
+    fn test_output_is_renamed_into_place_when_finished() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("output.laz");
+        let header = Builder::from((1, 4)).into_header().unwrap();
+
+        let mut writer =
+            OutputWriter::create(path.to_str().unwrap(), header, LazChunking::default()).unwrap();
+        writer.write_point(Point::default()).unwrap();
+        assert!(!path.exists());
+
+        writer.finish().unwrap();
+        let reader = las::Reader::from_path(&path).unwrap();
+        assert_eq!(reader.header().number_of_points(), 1);
+        assert!(reader.header().point_format().is_compressed);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_output_verification() {
+        let dir = tempfile::tempdir().unwrap();
+        let header = Builder::from((1, 4)).into_header().unwrap();
+        for name in ["output.las", "output.laz"] {
+            let path = dir.path().join(name);
+            let mut writer = OutputWriter::create(
+                path.to_str().unwrap(),
+                header.clone(),
+                LazChunking::Fixed(4),
+            )
+            .unwrap()
+            .with_verify(true);
+            for _ in 0..10 {
+                writer.write_point(Point::default()).unwrap();
+            }
+            writer.finish().unwrap();
+            verify_file(&path, 10).unwrap();
+
+            // Lose the end of the file, as a full disk would
+            let data = fs::read(&path).unwrap();
+            fs::write(&path, &data[..data.len() - 40]).unwrap();
+            assert!(matches!(
+                verify_file(&path, 10),
+                Err(MyError::VerificationFailed(_))
+            ));
+        }
+        assert!(matches!(
+            verify_file(&dir.path().join("output.las"), 11),
+            Err(MyError::VerificationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_output_parts() {
+        assert_eq!(render_part_path("out/tile_{part}.laz", 3), "out/tile_3.laz");
+        assert_eq!(render_part_path("out.d/tile.laz", 2), "out.d/tile_2.laz");
+        assert_eq!(render_part_path("tile", 1), "tile_1");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("part_{part}.las");
+        let header = Builder::from((1, 4)).into_header().unwrap();
+        let mut writer =
+            OutputWriter::create_parts(path.to_str().unwrap(), header, LazChunking::default(), 4)
+                .unwrap();
+        for _ in 0..10 {
+            writer.write_point(Point::default()).unwrap();
+        }
+        let reports = writer.finish().unwrap();
+        let counts: Vec<_> = reports.iter().map(|report| report.points_written).collect();
+        assert_eq!(counts, [4, 4, 2]);
+        for (part, report) in reports.iter().enumerate() {
+            let path = dir.path().join(format!("part_{}.las", part + 1));
+            assert_eq!(report.path, path.to_str().unwrap());
+            let reader = las::Reader::from_path(&path).unwrap();
+            assert_eq!(reader.header().number_of_points(), report.points_written);
+        }
+    }
+
+    #[test]
+    fn test_output_overflow() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("output.las");
+        let header = Builder::from((1, 2)).into_header().unwrap();
+        let mut writer =
+            OutputWriter::create(path.to_str().unwrap(), header, LazChunking::default()).unwrap();
+        assert_eq!(writer.limit, u64::from(u32::MAX));
+        // As if the file was as full as LAS 1.2 allows
+        writer.limit = 3;
+        for _ in 0..7 {
+            writer.write_point(Point::default()).unwrap();
+        }
+        let reports = writer.finish().unwrap();
+        let counts: Vec<_> = reports.iter().map(|report| report.points_written).collect();
+        assert_eq!(counts, [3, 3, 1]);
+        for (name, report) in ["output.las", "output_2.las", "output_3.las"]
+            .iter()
+            .zip(&reports)
+        {
+            let path = dir.path().join(name);
+            assert_eq!(report.path, path.to_str().unwrap());
+            let reader = las::Reader::from_path(&path).unwrap();
+            assert_eq!(reader.header().number_of_points(), report.points_written);
+            assert_eq!(reader.header().version(), Version::new(1, 2));
+        }
+    }
+
+    #[test]
+    fn test_output_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let header = Builder::from((1, 4)).into_header().unwrap();
+        for name in ["output.las", "output.laz"] {
+            let path = dir.path().join(name);
+            let path = path.to_str().unwrap();
+            for x in [1.0, 2.0, 3.0] {
+                let mut writer = OutputWriter::append(path, header.clone(), LazChunking::Fixed(2))
+                    .unwrap()
+                    .with_verify(true);
+                writer
+                    .write_point(Point {
+                        x,
+                        ..Default::default()
+                    })
+                    .unwrap();
+                let reports = writer.finish().unwrap();
+                assert_eq!(reports[0].points_written, 1);
+                assert_eq!(reports[0].bounds.unwrap().min.x, 1.0);
+                assert_eq!(reports[0].bounds.unwrap().max.x, x);
+            }
+            let mut reader = las::Reader::from_path(path).unwrap();
+            assert_eq!(reader.header().number_of_points(), 3);
+            let xs: Vec<f64> = reader.points().map(|point| point.unwrap().x).collect();
+            assert_eq!(xs, [1.0, 2.0, 3.0]);
+
+            let mut builder = Builder::from((1, 4));
+            builder.point_format = las::point::Format::new(1).unwrap();
+            assert!(matches!(
+                OutputWriter::append(path, builder.into_header().unwrap(), LazChunking::default()),
+                Err(MyError::IncompatibleAppend(..))
+            ));
+            let mut builder = Builder::from((1, 4));
+            builder.transforms.x.scale = 0.01;
+            assert!(matches!(
+                OutputWriter::append(path, builder.into_header().unwrap(), LazChunking::default()),
+                Err(MyError::IncompatibleAppend(..))
+            ));
+
+            // An output that isn't finished leaves the file as it was
+            let before = fs::read(path).unwrap();
+            let mut writer =
+                OutputWriter::append(path, header.clone(), LazChunking::Fixed(2)).unwrap();
+            for x in [4.0, 5.0, 6.0] {
+                writer
+                    .write_point(Point {
+                        x,
+                        ..Default::default()
+                    })
+                    .unwrap();
+            }
+            drop(writer);
+            assert_eq!(fs::read(path).unwrap(), before);
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_check_output_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("a.las").to_string_lossy().to_string();
+        let same = dir
+            .path()
+            .join(".")
+            .join("a.las")
+            .to_string_lossy()
+            .to_string();
+        let other = dir.path().join("b.las").to_string_lossy().to_string();
+        assert!(check_output_paths(&[output.clone(), other], &[], false).is_ok());
+        assert!(matches!(
+            check_output_paths(&[output, same], &[], false),
+            Err(MyError::DuplicateOutput(..))
+        ));
+        let stdout = STDOUT_PATH.to_string();
+        assert!(check_output_paths(&[stdout.clone(), stdout], &[], false).is_ok());
+    }
+
+    #[test]
+    fn test_outputs_up_to_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.las").to_string_lossy().to_string();
+        let output = dir.path().join("output.las").to_string_lossy().to_string();
+        fs::write(&input, b"").unwrap();
+
+        let outputs = [output.clone()];
+        let inputs = [input.clone()];
+        assert!(!outputs_up_to_date(&outputs, &inputs, SkipExisting::Exists));
+
+        fs::write(&output, b"").unwrap();
+        assert!(outputs_up_to_date(&outputs, &inputs, SkipExisting::Exists));
+
+        // Touch the input so that it is newer than the output.
+        let later =
+            fs::metadata(&output).unwrap().modified().unwrap() + std::time::Duration::from_secs(10);
+        fs::File::options()
+            .write(true)
+            .open(&input)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(!outputs_up_to_date(&outputs, &inputs, SkipExisting::Newer));
+        assert!(outputs_up_to_date(&outputs, &inputs, SkipExisting::Exists));
+    }
+}
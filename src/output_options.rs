@@ -0,0 +1,191 @@
+//! Settings that differ from one output to the next, such as an archive copy keeping every
+//! dimension written next to a slimmed copy for the web.
+//!
+//! [`OutputOptions`] change the header the points of an output are written with, and the points
+//! are fitted to its point format as they are written: the dimensions the format lacks are
+//! dropped, and the ones it adds are zero. Compression follows the extension of each output, so
+//! it is already set per output.
+use crate::crs::fit_crs;
+use crate::errors::MyError;
+use crate::extra_bytes::is_extra_bytes_vlr;
+use crate::legacy::fit_legacy_point;
+use las::point::Format;
+use las::{Builder, Color, Header, Point, Version, Vlr};
+
+/// The user id of the VLRs describing the coordinate system, as GeoTIFF keys or WKT.
+const CRS_USER_ID: &str = "LASF_Projection";
+
+/// The settings of an output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OutputOptions {
+    strip_extra_bytes: Option<bool>,
+    point_format: Option<u8>,
+    drop_crs: bool,
+}
+
+impl OutputOptions {
+    /// Options leaving the output like the others.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the extra bytes are left out of the output, whatever the processor does for the
+    /// other outputs.
+    pub fn with_strip_extra_bytes(mut self, strip: bool) -> Self {
+        self.strip_extra_bytes = Some(strip);
+        self
+    }
+
+    /// Writes the output in point format `format`, from 0 to 10 without the waveform formats 4,
+    /// 5, 9 and 10. Formats 6 and above need LAS 1.4, which the output is then written as.
+    pub fn with_point_format(mut self, format: u8) -> Self {
+        self.point_format = Some(format);
+        self
+    }
+
+    /// Leaves the coordinate system VLRs out of the output.
+    pub fn with_drop_crs(mut self, drop: bool) -> Self {
+        self.drop_crs = drop;
+        self
+    }
+
+    /// Whether the extra bytes are left out, or `None` to do as the processor does.
+    pub fn strip_extra_bytes(&self) -> Option<bool> {
+        self.strip_extra_bytes
+    }
+
+    /// The point format written, or `None` to keep the format of the inputs.
+    pub fn point_format(&self) -> Option<u8> {
+        self.point_format
+    }
+
+    /// Whether the coordinate system VLRs are left out.
+    pub fn drop_crs(&self) -> bool {
+        self.drop_crs
+    }
+
+    /// The point format of the output, made from the format the points are read in.
+    pub fn output_format(&self, format: Format) -> Result<Format, MyError> {
+        let mut output_format = match self.point_format {
+            Some(number) => {
+                let invalid =
+                    |reason: &str| MyError::InvalidPointFormat(number, reason.to_string());
+                let mut output_format =
+                    Format::new(number).map_err(|err| invalid(&err.to_string()))?;
+                if output_format.has_waveform {
+                    return Err(invalid("waveforms aren't written"));
+                }
+                output_format.extra_bytes = format.extra_bytes;
+                output_format.is_compressed = format.is_compressed;
+                output_format
+            }
+            None => format,
+        };
+        if self.strip_extra_bytes == Some(true) {
+            output_format.extra_bytes = 0;
+        }
+        Ok(output_format)
+    }
+
+    /// The header of the output, made from the header the points are read with.
+    pub fn header(&self, header: Header) -> Result<Header, MyError> {
+        let mut builder = Builder::from(header);
+        builder.point_format = self.output_format(builder.point_format)?;
+        if builder.point_format.is_extended && builder.version < Version::new(1, 4) {
+            builder.version = Version::new(1, 4);
+        }
+        if builder.point_format.extra_bytes == 0 {
+            builder.vlrs.retain(|vlr| !is_extra_bytes_vlr(vlr));
+        }
+        fit_crs(&mut builder);
+        if self.drop_crs {
+            builder.vlrs.retain(|vlr| !is_crs_vlr(vlr));
+            builder.evlrs.retain(|vlr| !is_crs_vlr(vlr));
+            builder.has_wkt_crs = false;
+        }
+        Ok(builder.into_header()?)
+    }
+}
+
+/// Returns `true` if `vlr` describes the coordinate system.
+pub fn is_crs_vlr(vlr: &Vlr) -> bool {
+    vlr.user_id == CRS_USER_ID
+}
+
+/// Makes `point` hold the dimensions of `format`, dropping the others and zeroing the missing
+/// ones. Points going to the formats older than LAS 1.4 are also fitted by [`fit_legacy_point`].
+pub fn fit_point(point: &mut Point, format: &Format) {
+    if !format.is_extended {
+        fit_legacy_point(point);
+    }
+    if point.matches(format) {
+        return;
+    }
+    point.gps_time = format
+        .has_gps_time
+        .then(|| point.gps_time.unwrap_or_default());
+    point.color = format
+        .has_color
+        .then(|| point.color.unwrap_or(Color::new(0, 0, 0)));
+    point.nir = format.has_nir.then(|| point.nir.unwrap_or_default());
+    if !format.has_waveform {
+        point.waveform = None;
+    }
+    point.extra_bytes.resize(usize::from(format.extra_bytes), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_options() {
+        let mut builder = Builder::from((1, 2));
+        builder.point_format = Format::new(3).unwrap();
+        builder.point_format.extra_bytes = 4;
+        builder.vlrs.push(Vlr {
+            user_id: CRS_USER_ID.to_string(),
+            record_id: 34735,
+            description: String::new(),
+            data: vec![0; 8],
+        });
+        let header = builder.into_header().unwrap();
+
+        let slim = OutputOptions::new()
+            .with_strip_extra_bytes(true)
+            .with_point_format(6)
+            .with_drop_crs(true)
+            .header(header.clone())
+            .unwrap();
+        assert_eq!(slim.point_format().to_u8().unwrap(), 6);
+        assert_eq!(slim.point_format().extra_bytes, 0);
+        assert_eq!(slim.version(), Version::new(1, 4));
+        assert!(slim.vlrs().is_empty());
+
+        let same = OutputOptions::new().header(header.clone()).unwrap();
+        assert_eq!(same.point_format(), header.point_format());
+        assert_eq!(same.vlrs().len(), 1);
+        assert!(OutputOptions::new()
+            .with_point_format(4)
+            .header(header.clone())
+            .is_err());
+        assert!(OutputOptions::new()
+            .with_point_format(11)
+            .header(header)
+            .is_err());
+
+        let mut point = Point {
+            gps_time: Some(2.0),
+            color: Some(Color::new(1, 2, 3)),
+            extra_bytes: vec![1, 2, 3, 4],
+            ..Default::default()
+        };
+        fit_point(&mut point, slim.point_format());
+        assert!(point.matches(slim.point_format()));
+        assert_eq!(point.gps_time, Some(2.0));
+        assert!(point.color.is_none() && point.extra_bytes.is_empty());
+        fit_point(&mut point, &Format::new(8).unwrap());
+        assert_eq!(point.color, Some(Color::new(0, 0, 0)));
+        assert_eq!(point.nir, Some(0));
+    }
+}
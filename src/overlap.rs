@@ -0,0 +1,135 @@
+//! Keeping only the area every input covers, for strip-to-strip comparisons and calibration
+//! checks.
+//!
+//! The quick [`Overlap`] is the intersection of the bounds in the headers of the inputs, which is
+//! exact for inputs filling rectangles but too generous for flight strips crossing at an angle.
+//! The exact one is made of the cells of a coarse grid that every input has points in, which
+//! takes a pass over the points of the inputs to find. Either way only X and Y are compared.
+use las::Bounds;
+use std::collections::HashSet;
+
+/// How the overlap of the inputs is found.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverlapKind {
+    /// From the bounds in the headers of the inputs.
+    Bounds,
+    /// From the square cells of the given size that every input has points in.
+    Cells { cell_size: f64 },
+}
+
+/// The area where all the inputs are.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Overlap {
+    /// A rectangle, from its lowest to its highest X and Y. Empty if the lowest is above the
+    /// highest.
+    Bounds { min: [f64; 2], max: [f64; 2] },
+    /// The cells of the grid, by column and row.
+    Cells {
+        cell_size: f64,
+        cells: HashSet<(i64, i64)>,
+    },
+}
+
+impl Overlap {
+    /// The intersection of `bounds`, or everything if there are none.
+    pub fn of_bounds<'a>(bounds: impl IntoIterator<Item = &'a Bounds>) -> Self {
+        bounds.into_iter().fold(
+            Overlap::Bounds {
+                min: [f64::NEG_INFINITY; 2],
+                max: [f64::INFINITY; 2],
+            },
+            |overlap, bounds| match overlap {
+                Overlap::Bounds { min, max } => Overlap::Bounds {
+                    min: [min[0].max(bounds.min.x), min[1].max(bounds.min.y)],
+                    max: [max[0].min(bounds.max.x), max[1].min(bounds.max.y)],
+                },
+                cells => cells,
+            },
+        )
+    }
+
+    /// The cells of side `cell_size` in every one of `inputs`, each the cells holding the points
+    /// of an input, as found by [`cell_of`].
+    pub fn of_cells(cell_size: f64, inputs: Vec<HashSet<(i64, i64)>>) -> Self {
+        let mut inputs = inputs.into_iter();
+        let first = inputs.next().unwrap_or_default();
+        let cells = inputs.fold(first, |cells, input| {
+            cells.intersection(&input).copied().collect()
+        });
+        Overlap::Cells { cell_size, cells }
+    }
+
+    /// Whether the point at `x` and `y` is in the overlap.
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        match self {
+            Overlap::Bounds { min, max } => {
+                min[0] <= x && x <= max[0] && min[1] <= y && y <= max[1]
+            }
+            Overlap::Cells { cell_size, cells } => cells.contains(&cell_of(*cell_size, x, y)),
+        }
+    }
+
+    /// Whether no point can be in the overlap.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Overlap::Bounds { min, max } => min[0] > max[0] || min[1] > max[1],
+            Overlap::Cells { cells, .. } => cells.is_empty(),
+        }
+    }
+}
+
+/// The column and row of the cell of side `cell_size` holding the point at `x` and `y`.
+pub fn cell_of(cell_size: f64, x: f64, y: f64) -> (i64, i64) {
+    (
+        (x / cell_size).floor() as i64,
+        (y / cell_size).floor() as i64,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::Vector;
+
+    #[test]
+    fn test_overlap() {
+        let bounds = |min_x, min_y, max_x, max_y| Bounds {
+            min: Vector {
+                x: min_x,
+                y: min_y,
+                z: 0.0,
+            },
+            max: Vector {
+                x: max_x,
+                y: max_y,
+                z: 0.0,
+            },
+        };
+        let overlap =
+            Overlap::of_bounds(&[bounds(0.0, 0.0, 10.0, 10.0), bounds(5.0, -5.0, 20.0, 8.0)]);
+        assert_eq!(
+            overlap,
+            Overlap::Bounds {
+                min: [5.0, 0.0],
+                max: [10.0, 8.0]
+            }
+        );
+        assert!(overlap.contains(5.0, 8.0));
+        assert!(!overlap.contains(4.0, 5.0));
+        assert!(
+            Overlap::of_bounds(&[bounds(0.0, 0.0, 1.0, 1.0), bounds(2.0, 0.0, 3.0, 1.0)])
+                .is_empty()
+        );
+        assert!(Overlap::of_bounds(&[]).contains(1e9, -1e9));
+
+        let strip = |cells: &[(i64, i64)]| cells.iter().copied().collect::<HashSet<_>>();
+        let overlap = Overlap::of_cells(
+            10.0,
+            vec![strip(&[(0, 0), (1, 0), (2, 0)]), strip(&[(1, 0), (1, 1)])],
+        );
+        assert!(overlap.contains(15.0, 5.0));
+        assert!(!overlap.contains(5.0, 5.0));
+        assert!(!overlap.contains(15.0, 15.0));
+        assert_eq!(cell_of(10.0, -0.5, 19.0), (-1, 1));
+    }
+}
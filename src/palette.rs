@@ -0,0 +1,181 @@
+//! Coloring points by their class, for ready-to-view QA copies of classified datasets.
+//!
+//! A [`ClassPalette`] gives each class a color, by default after the classes of the ASPRS
+//! standard: brown ground, green vegetation from light to dark, red buildings and so on. The
+//! colors of some classes can be changed by a JSON table from class numbers to colors, given as
+//! `#rrggbb` or as red, green and blue from 0 to 255:
+//!
+//! ```json
+//! {"2": "#c8a064", "6": [255, 128, 0]}
+//! ```
+use crate::errors::MyError;
+use las::{Color, Point};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// The colors of the standard classes, as 8-bit red, green and blue.
+const STANDARD: [(u8, [u8; 3]); 19] = [
+    (0, [170, 170, 170]),
+    (1, [170, 170, 170]),
+    (2, [170, 85, 0]),
+    (3, [0, 170, 170]),
+    (4, [85, 255, 85]),
+    (5, [0, 170, 0]),
+    (6, [255, 85, 85]),
+    (7, [170, 0, 0]),
+    (8, [85, 85, 85]),
+    (9, [85, 255, 255]),
+    (10, [170, 0, 170]),
+    (11, [0, 0, 0]),
+    (12, [255, 255, 85]),
+    (13, [255, 85, 255]),
+    (14, [255, 170, 0]),
+    (15, [170, 85, 85]),
+    (16, [255, 170, 255]),
+    (17, [85, 85, 255]),
+    (18, [255, 0, 0]),
+];
+
+/// The color of the classes the palette doesn't name.
+const OTHER: [u8; 3] = [255, 255, 255];
+
+/// The color of each class.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassPalette {
+    colors: Vec<Color>,
+}
+
+impl Default for ClassPalette {
+    /// The ASPRS standard classes in their usual colors, and the others in white.
+    fn default() -> Self {
+        let mut colors = vec![to_color(OTHER); 256];
+        for (class, color) in STANDARD {
+            colors[usize::from(class)] = to_color(color);
+        }
+        Self { colors }
+    }
+}
+
+impl ClassPalette {
+    /// The standard palette.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gives `class` the color `color`.
+    pub fn with_color(mut self, class: u8, color: Color) -> Self {
+        self.colors[usize::from(class)] = color;
+        self
+    }
+
+    /// The standard palette with the colors of a table from class numbers to colors.
+    pub fn from_value(value: &Value) -> Result<Self, MyError> {
+        let table = value
+            .as_object()
+            .ok_or_else(|| invalid("expected a table from classes to colors"))?;
+        let mut palette = Self::new();
+        for (class, color) in table.iter() {
+            let class: u8 = class
+                .parse()
+                .map_err(|_| invalid(&format!("{} isn't a class from 0 to 255", class)))?;
+            palette = palette.with_color(class, parse_color(color)?);
+        }
+        Ok(palette)
+    }
+
+    /// The standard palette with the colors of the JSON table at `path`.
+    pub fn from_path(path: &Path) -> Result<Self, MyError> {
+        let value: Value = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|err| invalid(&err.to_string()))?;
+        Self::from_value(&value)
+    }
+
+    /// The color of `class`.
+    pub fn color(&self, class: u8) -> Color {
+        self.colors[usize::from(class)]
+    }
+
+    /// Replaces the color of `point` by the color of its class.
+    pub fn apply(&self, point: &mut Point) {
+        point.color = Some(self.color(u8::from(point.classification)));
+    }
+}
+
+fn invalid(message: &str) -> MyError {
+    MyError::InvalidPalette(message.to_string())
+}
+
+fn to_color([red, green, blue]: [u8; 3]) -> Color {
+    // Scaled so that 255 is the top of the 16-bit range
+    Color::new(
+        u16::from(red) * 257,
+        u16::from(green) * 257,
+        u16::from(blue) * 257,
+    )
+}
+
+/// Reads a color given as `#rrggbb` or as a list of red, green and blue from 0 to 255.
+fn parse_color(value: &Value) -> Result<Color, MyError> {
+    let channels: Option<Vec<u8>> = match value {
+        Value::String(hex) => hex
+            .strip_prefix('#')
+            .filter(|digits| digits.len() == 6 && digits.is_ascii())
+            .and_then(|digits| {
+                (0..6)
+                    .step_by(2)
+                    .map(|start| u8::from_str_radix(&digits[start..start + 2], 16).ok())
+                    .collect()
+            }),
+        Value::Array(channels) => channels
+            .iter()
+            .map(|channel| {
+                channel
+                    .as_u64()
+                    .and_then(|channel| u8::try_from(channel).ok())
+            })
+            .collect(),
+        _ => None,
+    };
+    match channels.as_deref() {
+        Some(&[red, green, blue]) => Ok(to_color([red, green, blue])),
+        _ => Err(invalid(&format!(
+            "{} isn't a color, expected \"#rrggbb\" or [red, green, blue]",
+            value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::point::Classification;
+
+    #[test]
+    fn test_class_palette() {
+        let palette = ClassPalette::new();
+        assert_eq!(palette.color(2), Color::new(43690, 21845, 0));
+        assert_eq!(palette.color(100), Color::new(65535, 65535, 65535));
+
+        let value = serde_json::from_str(r##"{"2": "#ff0080", "6": [0, 255, 1]}"##).unwrap();
+        let palette = ClassPalette::from_value(&value).unwrap();
+        let mut point = Point {
+            classification: Classification::Ground,
+            ..Default::default()
+        };
+        palette.apply(&mut point);
+        assert_eq!(point.color, Some(Color::new(65535, 0, 32896)));
+        assert_eq!(palette.color(6), Color::new(0, 65535, 257));
+        assert_eq!(palette.color(5), ClassPalette::new().color(5));
+
+        for table in [
+            r#"[]"#,
+            r##"{"256": "#000000"}"##,
+            r##"{"2": "#00"}"##,
+            r#"{"2": [0, 0]}"#,
+        ] {
+            let value = serde_json::from_str(table).unwrap();
+            assert!(ClassPalette::from_value(&value).is_err(), "{}", table);
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! Reading PDAL pipeline JSON, so jobs written for PDAL can be run without rewriting them.
+//!
+//! Only the stages with a counterpart here are understood:
+//!
+//! - `readers.las`, whose `filename` becomes an input
+//! - `filters.range`, with `limits` on `X`, `Y`, `Z`, `Intensity` and `Classification`
+//! - `filters.crop`, with 2D or 3D `bounds`
+//! - `writers.las`, whose `filename` becomes an output, written in the point format of its
+//!   `dataformat_id` if it has one
+//!
+//! As in PDAL, a bare file name is a reader, or a writer when it is the last stage, and the
+//! filters apply to the writers that follow them. Other options of the readers and writers are
+//! ignored; any other stage or filter option is an error rather than being silently dropped.
+use crate::errors::MyError;
+use crate::filter::{ClassMask, NumericFilter};
+use crate::output_options::OutputOptions;
+use crate::pipeline::{OutputConfig, PipelineConfig};
+use las::{Bounds, Vector};
+use serde_json::Value;
+use std::ops::RangeInclusive;
+
+/// Returns `true` if `value` looks like a PDAL pipeline: a list of stages, or a table holding
+/// only a `pipeline` list.
+pub fn is_pdal_pipeline(value: &Value) -> bool {
+    match value {
+        Value::Array(_) => true,
+        Value::Object(_) => value.get("pipeline").is_some_and(Value::is_array),
+        _ => false,
+    }
+}
+
+/// Maps the stages of a PDAL pipeline to the inputs and outputs of a pipeline.
+pub fn from_pdal(value: &Value) -> Result<PipelineConfig, MyError> {
+    let stages = match value {
+        Value::Array(stages) => stages,
+        _ => value
+            .get("pipeline")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("expected a `pipeline` list of stages"))?,
+    };
+
+    let mut config = PipelineConfig::default();
+    let mut filters = Filters::default();
+    for (index, stage) in stages.iter().enumerate() {
+        let last = index + 1 == stages.len() && index > 0;
+        let (kind, filename) = match stage {
+            Value::String(filename) => (inferred_kind(filename, last)?, Some(filename.as_str())),
+            Value::Object(_) => {
+                let filename = stage.get("filename").and_then(Value::as_str);
+                let kind = match (stage.get("type").and_then(Value::as_str), filename) {
+                    (Some(kind), _) => kind,
+                    (None, Some(filename)) => inferred_kind(filename, last)?,
+                    (None, None) => return Err(invalid("every stage needs a `type`")),
+                };
+                (kind, filename)
+            }
+            _ => return Err(invalid("stages should be tables or file names")),
+        };
+        match kind {
+            "readers.las" => config.inputs.push(required_filename(kind, filename)?),
+            "writers.las" => config.outputs.push(OutputConfig {
+                path: required_filename(kind, filename)?,
+                filter: "always-true".to_string(),
+                stages: filters.to_numeric(),
+                options: match stage.get("dataformat_id").and_then(Value::as_u64) {
+                    Some(format) => OutputOptions::new().with_point_format(
+                        u8::try_from(format).map_err(|_| invalid("unknown `dataformat_id`"))?,
+                    ),
+                    None => OutputOptions::new(),
+                },
+            }),
+            _ => filters.apply(kind, stage)?,
+        }
+    }
+    Ok(config)
+}
+
+/// Reads a list of PDAL filter stages into the built-in filters passing the same points.
+pub fn filter_stages(stages: &[Value]) -> Result<Vec<NumericFilter>, MyError> {
+    let mut filters = Filters::default();
+    for stage in stages {
+        let kind = stage
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("every stage needs a `type`"))?;
+        filters.apply(kind, stage)?;
+    }
+    Ok(filters.to_numeric())
+}
+
+/// The filters met so far, narrowed by every stage.
+#[derive(Default)]
+struct Filters {
+    bounds: Option<Bounds>,
+    intensity: Option<RangeInclusive<u16>>,
+    classes: Option<ClassMask>,
+}
+
+impl Filters {
+    /// Applies the filter stage `stage` of type `kind`.
+    fn apply(&mut self, kind: &str, stage: &Value) -> Result<(), MyError> {
+        match kind {
+            "filters.range" => {
+                check_options(stage, &["limits"])?;
+                let limits = stage
+                    .get("limits")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| invalid("`filters.range` needs `limits`"))?;
+                self.add_limits(limits)
+            }
+            "filters.crop" => {
+                check_options(stage, &["bounds"])?;
+                let bounds = stage
+                    .get("bounds")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| invalid("`filters.crop` needs `bounds`"))?;
+                self.crop(parse_bounds(bounds)?);
+                Ok(())
+            }
+            _ => Err(invalid(&format!(
+                "the PDAL stage `{}` isn't supported",
+                kind
+            ))),
+        }
+    }
+
+    /// Applies the comma-separated `limits` of a `filters.range` stage. As in PDAL, ranges on the
+    /// same dimension keep the points in any of them, which is only supported for
+    /// classifications.
+    fn add_limits(&mut self, limits: &str) -> Result<(), MyError> {
+        let mut classes: Option<ClassMask> = None;
+        let mut seen = Vec::new();
+        for limit in limits.split(',').map(str::trim) {
+            let (dimension, range) = parse_limit(limit)?;
+            let dimension = dimension.to_ascii_lowercase();
+            if dimension == "classification" {
+                let codes: Vec<u8> = whole_range(range, 255.0).map(|code| code as u8).collect();
+                let mask = classes.get_or_insert_with(ClassMask::default);
+                *mask = mask.union(&ClassMask::new(&codes));
+                continue;
+            }
+            if seen.contains(&dimension) {
+                return Err(invalid(&format!(
+                    "several ranges on `{}` in `filters.range` aren't supported",
+                    dimension
+                )));
+            }
+            match dimension.as_str() {
+                "x" | "y" | "z" => self.crop(axis_bounds(&dimension, range)),
+                "intensity" => {
+                    let range = whole_range(range, 65535.0);
+                    self.intensity = Some(match self.intensity.take() {
+                        Some(current) => {
+                            *current.start().max(range.start())..=*current.end().min(range.end())
+                        }
+                        None => range,
+                    });
+                }
+                _ => {
+                    return Err(invalid(&format!(
+                        "ranges on `{}` in `filters.range` aren't supported",
+                        dimension
+                    )))
+                }
+            }
+            seen.push(dimension);
+        }
+        if let Some(classes) = classes {
+            self.classes = Some(match self.classes {
+                Some(current) => current.intersection(&classes),
+                None => classes,
+            });
+        }
+        Ok(())
+    }
+
+    /// Keeps only the points inside `bounds`.
+    fn crop(&mut self, bounds: Bounds) {
+        self.bounds = Some(match self.bounds {
+            Some(current) => Bounds {
+                min: Vector {
+                    x: current.min.x.max(bounds.min.x),
+                    y: current.min.y.max(bounds.min.y),
+                    z: current.min.z.max(bounds.min.z),
+                },
+                max: Vector {
+                    x: current.max.x.min(bounds.max.x),
+                    y: current.max.y.min(bounds.max.y),
+                    z: current.max.z.min(bounds.max.z),
+                },
+            },
+            None => bounds,
+        });
+    }
+
+    fn to_numeric(&self) -> Vec<NumericFilter> {
+        let mut filters = Vec::new();
+        if let Some(bounds) = self.bounds {
+            filters.push(NumericFilter::Bounds(bounds));
+        }
+        if let Some(intensity) = &self.intensity {
+            filters.push(NumericFilter::Intensity(intensity.clone()));
+        }
+        if let Some(classes) = self.classes {
+            filters.push(NumericFilter::Classes(classes));
+        }
+        filters
+    }
+}
+
+fn invalid(message: &str) -> MyError {
+    MyError::InvalidPipeline(message.to_string())
+}
+
+/// The stage a file name stands for: a writer if it is the last stage and a reader otherwise.
+fn inferred_kind(filename: &str, last: bool) -> Result<&'static str, MyError> {
+    let lowercase = filename.to_lowercase();
+    if !lowercase.ends_with(".las") && !lowercase.ends_with(".laz") {
+        return Err(invalid(&format!(
+            "only LAS/LAZ files are supported, not `{}`",
+            filename
+        )));
+    }
+    Ok(if last { "writers.las" } else { "readers.las" })
+}
+
+/// The whole numbers of `range` from 0 to `max`, empty if there are none.
+fn whole_range((min, max): (f64, f64), limit: f64) -> RangeInclusive<u16> {
+    let (min, max) = (min.max(0.0).ceil(), max.min(limit).floor());
+    if min > max {
+        #[allow(clippy::reversed_empty_ranges)]
+        return 1..=0;
+    }
+    min as u16..=max as u16
+}
+
+fn required_filename(kind: &str, filename: Option<&str>) -> Result<String, MyError> {
+    filename
+        .map(String::from)
+        .ok_or_else(|| invalid(&format!("`{}` needs a `filename`", kind)))
+}
+
+/// Checks that a filter stage has no options besides `type`, `tag`, `inputs` and `allowed`.
+fn check_options(stage: &Value, allowed: &[&str]) -> Result<(), MyError> {
+    let Some(options) = stage.as_object() else {
+        return Ok(());
+    };
+    for key in options.keys() {
+        if !["type", "tag", "inputs"].contains(&key.as_str()) && !allowed.contains(&key.as_str()) {
+            return Err(invalid(&format!(
+                "the option `{}` of `{}` isn't supported",
+                key,
+                stage.get("type").and_then(Value::as_str).unwrap_or("")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a range like `Z[0:50]`, where either end may be left out. Exclusive and negated ranges
+/// aren't supported.
+fn parse_limit(limit: &str) -> Result<(&str, (f64, f64)), MyError> {
+    let error = || invalid(&format!("unsupported range `{}`", limit));
+    let (dimension, range) = limit.split_once('[').ok_or_else(error)?;
+    let (min, max) = range
+        .strip_suffix(']')
+        .and_then(|range| range.split_once(':'))
+        .ok_or_else(error)?;
+    let bound = |text: &str, unbounded: f64| match text.trim() {
+        "" => Ok(unbounded),
+        text => text.parse::<f64>().map_err(|_| error()),
+    };
+    let dimension = dimension.trim();
+    if dimension.is_empty() || dimension.starts_with('!') {
+        return Err(error());
+    }
+    Ok((
+        dimension,
+        (bound(min, f64::NEG_INFINITY)?, bound(max, f64::INFINITY)?),
+    ))
+}
+
+/// Parses crop bounds like `([0, 10], [0, 10])`, with an optional third range for Z.
+fn parse_bounds(text: &str) -> Result<Bounds, MyError> {
+    let error = || invalid(&format!("unsupported bounds `{}`", text));
+    let inner = text
+        .trim()
+        .strip_prefix('(')
+        .and_then(|text| text.strip_suffix(')'))
+        .ok_or_else(error)?;
+    let mut axes = Vec::new();
+    for part in inner.split(']') {
+        let part = part.trim().trim_start_matches(',').trim();
+        if part.is_empty() {
+            continue;
+        }
+        let (min, max) = part
+            .strip_prefix('[')
+            .and_then(|part| part.split_once(','))
+            .ok_or_else(error)?;
+        let min = min.trim().parse::<f64>().map_err(|_| error())?;
+        let max = max.trim().parse::<f64>().map_err(|_| error())?;
+        axes.push((min, max));
+    }
+    let (x, y, z) = match axes[..] {
+        [x, y] => (x, y, (f64::NEG_INFINITY, f64::INFINITY)),
+        [x, y, z] => (x, y, z),
+        _ => return Err(error()),
+    };
+    Ok(Bounds {
+        min: Vector {
+            x: x.0,
+            y: y.0,
+            z: z.0,
+        },
+        max: Vector {
+            x: x.1,
+            y: y.1,
+            z: z.1,
+        },
+    })
+}
+
+/// Bounds limiting only the `axis` among `x`, `y` and `z`.
+fn axis_bounds(axis: &str, (min, max): (f64, f64)) -> Bounds {
+    let mut bounds = Bounds {
+        min: Vector {
+            x: f64::NEG_INFINITY,
+            y: f64::NEG_INFINITY,
+            z: f64::NEG_INFINITY,
+        },
+        max: Vector {
+            x: f64::INFINITY,
+            y: f64::INFINITY,
+            z: f64::INFINITY,
+        },
+    };
+    match axis {
+        "x" => (bounds.min.x, bounds.max.x) = (min, max),
+        "y" => (bounds.min.y, bounds.max.y) = (min, max),
+        _ => (bounds.min.z, bounds.max.z) = (min, max),
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_pdal() {
+        let value: Value = serde_json::from_str(
+            r#"{"pipeline": [
+                "input.las",
+                {"type": "readers.las", "filename": "other.laz"},
+                {"type": "filters.range", "limits": "Classification[2:2],Classification[6:6],Z[:50]"},
+                {"type": "filters.crop", "bounds": "([0, 10], [5, 20])"},
+                {"type": "filters.range", "limits": "Intensity[100:]"},
+                {"type": "writers.las", "filename": "ground.laz", "compression": "laszip", "dataformat_id": 1}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(is_pdal_pipeline(&value));
+        let config = from_pdal(&value).unwrap();
+        assert_eq!(config.inputs, ["input.las", "other.laz"]);
+        assert_eq!(config.outputs.len(), 1);
+        let output = &config.outputs[0];
+        assert_eq!(output.path, "ground.laz");
+        assert_eq!(output.filter, "always-true");
+        assert_eq!(output.options.point_format(), Some(1));
+        let [NumericFilter::Bounds(bounds), NumericFilter::Intensity(intensity), NumericFilter::Classes(classes)] =
+            &output.stages[..]
+        else {
+            panic!("unexpected stages {:?}", output.stages);
+        };
+        assert_eq!((bounds.min.x, bounds.max.x), (0.0, 10.0));
+        assert_eq!((bounds.min.y, bounds.max.y), (5.0, 20.0));
+        assert_eq!((bounds.min.z, bounds.max.z), (f64::NEG_INFINITY, 50.0));
+        assert_eq!(intensity, &(100..=65535));
+        assert!(classes.contains(2) && classes.contains(6) && !classes.contains(3));
+
+        // A bare file name at the end is a writer
+        let value: Value = serde_json::from_str(r#"["in.las", "out.las"]"#).unwrap();
+        let config = from_pdal(&value).unwrap();
+        assert_eq!(config.inputs, ["in.las"]);
+        assert_eq!(config.outputs[0].path, "out.las");
+        assert!(config.outputs[0].stages.is_empty());
+        assert_eq!(config.outputs[0].options, OutputOptions::new());
+
+        for pipeline in [
+            r#"["in.las", {"type": "filters.outlier"}, "out.las"]"#,
+            r#"["in.las", {"type": "filters.crop", "polygon": "POLYGON(...)"}, "out.las"]"#,
+            r#"["in.las", {"type": "filters.range", "limits": "Z(0:10]"}, "out.las"]"#,
+        ] {
+            let value: Value = serde_json::from_str(pipeline).unwrap();
+            assert!(matches!(
+                from_pdal(&value),
+                Err(MyError::InvalidPipeline(_))
+            ));
+        }
+    }
+}
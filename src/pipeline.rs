@@ -0,0 +1,280 @@
+//! Job descriptions read from pipeline files.
+//!
+//! A pipeline file describes the inputs of a `trim` run, its outputs with the filter deciding
+//! which points go to each, and any other option, so that a batch job can be versioned next to
+//! the data rather than living in shell history. Options are named like the long command-line
+//! options, with dashes or underscores:
+//!
+//! ```toml
+//! inputs = ["tiles/"]
+//! recursive = true
+//! threads = 8
+//! batch-size = 50000
+//!
+//! [[outputs]]
+//! path = "out/{stem}_ground.laz"
+//! filter = "always-true"
+//! point-format = 6
+//! ```
+//!
+//! An output can also set `strip-extra-bytes`, `point-format` and `drop-crs` for itself alone,
+//! see [`OutputOptions`].
+//!
+//! The format is picked from the extension: `.json` files are always read, `.toml` files need the
+//! `toml` feature and `.yaml`/`.yml` files the `yaml` feature. PDAL pipelines in JSON are read too,
+//! see [`crate::pdal`].
+use crate::errors::MyError;
+use crate::filter::{Condition, NumericFilter};
+use crate::output_options::OutputOptions;
+use crate::pdal;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// The contents of a pipeline file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PipelineConfig {
+    /// The input files or folders.
+    pub inputs: Vec<String>,
+    /// The outputs, in order.
+    pub outputs: Vec<OutputConfig>,
+    /// The other options, named like the long command-line options without the leading dashes.
+    pub options: Vec<(String, OptionValue)>,
+}
+
+/// An output of a pipeline file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputConfig {
+    pub path: String,
+    /// The name of the filter deciding which points go to the output.
+    pub filter: String,
+    /// Built-in filters the points must also pass, from the stages of a PDAL pipeline.
+    pub stages: Vec<NumericFilter>,
+    /// The settings of the output that differ from the others.
+    pub options: OutputOptions,
+}
+
+impl OutputConfig {
+    /// A condition passing the points that pass every stage, or `None` without stages.
+    pub fn stages_condition(&self) -> Option<Condition> {
+        Condition::all_numeric(&self.stages)
+    }
+}
+
+/// The value of an option in a pipeline file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionValue {
+    /// A switch like `force`, on or off.
+    Flag(bool),
+    Value(String),
+    List(Vec<String>),
+}
+
+impl PipelineConfig {
+    /// Reads the pipeline file at `path`.
+    pub fn read(path: &Path) -> Result<Self, MyError> {
+        let text = fs::read_to_string(path)?;
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let value = match extension.as_str() {
+            "json" => serde_json::from_str(&text).map_err(invalid)?,
+            "toml" => parse_toml(&text)?,
+            "yaml" | "yml" => parse_yaml(&text)?,
+            _ => {
+                return Err(MyError::InvalidPipeline(format!(
+                    "{:?} should end in .toml, .json, .yaml or .yml",
+                    path
+                )))
+            }
+        };
+        Self::from_value(&value)
+    }
+
+    /// Reads a pipeline from its parsed contents, which may be a PDAL pipeline.
+    pub fn from_value(value: &Value) -> Result<Self, MyError> {
+        if pdal::is_pdal_pipeline(value) {
+            return pdal::from_pdal(value);
+        }
+        let object = value
+            .as_object()
+            .ok_or_else(|| MyError::InvalidPipeline("expected a table of settings".to_string()))?;
+        let mut config = PipelineConfig::default();
+        for (key, value) in object.iter() {
+            match key.as_str() {
+                "inputs" => config.inputs = strings(key, value)?,
+                "outputs" => {
+                    let outputs = value.as_array().ok_or_else(|| {
+                        MyError::InvalidPipeline("`outputs` should be a list".to_string())
+                    })?;
+                    for output in outputs {
+                        config.outputs.push(OutputConfig {
+                            path: string(output, "path")?,
+                            filter: string(output, "filter")?,
+                            stages: Vec::new(),
+                            options: output_options(output)?,
+                        });
+                    }
+                }
+                _ => {
+                    let value = match value {
+                        Value::Bool(flag) => OptionValue::Flag(*flag),
+                        Value::String(text) => OptionValue::Value(text.clone()),
+                        Value::Number(_) => OptionValue::Value(value.to_string()),
+                        Value::Array(_) => OptionValue::List(strings(key, value)?),
+                        _ => {
+                            return Err(MyError::InvalidPipeline(format!(
+                                "unexpected value for `{}`",
+                                key
+                            )))
+                        }
+                    };
+                    config.options.push((key.replace('_', "-"), value));
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn invalid(err: impl std::fmt::Display) -> MyError {
+    MyError::InvalidPipeline(err.to_string())
+}
+
+/// Reads a list of strings or numbers as strings.
+fn strings(key: &str, value: &Value) -> Result<Vec<String>, MyError> {
+    let error = || MyError::InvalidPipeline(format!("`{}` should be a list of values", key));
+    value
+        .as_array()
+        .ok_or_else(error)?
+        .iter()
+        .map(|item| match item {
+            Value::String(text) => Ok(text.clone()),
+            Value::Number(_) => Ok(item.to_string()),
+            _ => Err(error()),
+        })
+        .collect()
+}
+
+/// The settings of an output table, named like the options with dashes or underscores:
+/// `strip-extra-bytes`, `point-format` and `drop-crs`.
+fn output_options(output: &Value) -> Result<OutputOptions, MyError> {
+    let mut options = OutputOptions::new();
+    let Some(table) = output.as_object() else {
+        return Ok(options);
+    };
+    for (key, value) in table.iter() {
+        let error = |expected: &str| {
+            MyError::InvalidPipeline(format!("`{}` of an output should be {}", key, expected))
+        };
+        options = match key.replace('_', "-").as_str() {
+            "strip-extra-bytes" => {
+                options.with_strip_extra_bytes(value.as_bool().ok_or_else(|| error("a switch"))?)
+            }
+            "point-format" => options.with_point_format(
+                value
+                    .as_u64()
+                    .and_then(|format| u8::try_from(format).ok())
+                    .ok_or_else(|| error("a point format number"))?,
+            ),
+            "drop-crs" => options.with_drop_crs(value.as_bool().ok_or_else(|| error("a switch"))?),
+            _ => options,
+        };
+    }
+    Ok(options)
+}
+
+/// The string under `key` in a table.
+fn string(table: &Value, key: &str) -> Result<String, MyError> {
+    table
+        .get(key)
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or_else(|| MyError::InvalidPipeline(format!("every output needs a `{}`", key)))
+}
+
+#[cfg(feature = "toml")]
+fn parse_toml(text: &str) -> Result<Value, MyError> {
+    toml::from_str(text).map_err(invalid)
+}
+
+#[cfg(not(feature = "toml"))]
+fn parse_toml(_text: &str) -> Result<Value, MyError> {
+    Err(MyError::FeatureNotEnabled(
+        "Reading TOML pipelines".to_string(),
+        "toml",
+    ))
+}
+
+#[cfg(feature = "yaml")]
+fn parse_yaml(text: &str) -> Result<Value, MyError> {
+    serde_yaml::from_str(text).map_err(invalid)
+}
+
+#[cfg(not(feature = "yaml"))]
+fn parse_yaml(_text: &str) -> Result<Value, MyError> {
+    Err(MyError::FeatureNotEnabled(
+        "Reading YAML pipelines".to_string(),
+        "yaml",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_pipeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("job.json");
+        fs::write(
+            &path,
+            r#"{
+                "inputs": ["tiles/", "extra.laz"],
+                "recursive": true,
+                "batch_size": 50000,
+                "extensions": ["las"],
+                "outputs": [{
+                    "path": "out/{stem}.laz",
+                    "filter": "always-true",
+                    "strip_extra_bytes": true,
+                    "point-format": 6
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        let config = PipelineConfig::read(&path).unwrap();
+        assert_eq!(config.inputs, ["tiles/", "extra.laz"]);
+        assert_eq!(
+            config.outputs,
+            [OutputConfig {
+                path: "out/{stem}.laz".to_string(),
+                filter: "always-true".to_string(),
+                stages: Vec::new(),
+                options: OutputOptions::new()
+                    .with_strip_extra_bytes(true)
+                    .with_point_format(6),
+            }]
+        );
+        assert!(config
+            .options
+            .contains(&("recursive".to_string(), OptionValue::Flag(true))));
+        assert!(config.options.contains(&(
+            "batch-size".to_string(),
+            OptionValue::Value("50000".to_string())
+        )));
+        assert!(config.options.contains(&(
+            "extensions".to_string(),
+            OptionValue::List(vec!["las".to_string()])
+        )));
+
+        fs::write(&path, r#"{"outputs": [{"path": "out.laz"}]}"#).unwrap();
+        assert!(matches!(
+            PipelineConfig::read(&path),
+            Err(MyError::InvalidPipeline(_))
+        ));
+    }
+}
@@ -0,0 +1,116 @@
+//! What a run would read and write, worked out from the headers of the inputs without reading
+//! any points. Returned by `LasProcessor::plan`.
+use crate::info::FileInfo;
+use std::fmt;
+
+/// How much smaller than uncompressed LAS a LAZ output is assumed to be. Real ratios are usually
+/// between 5 and 10, so this errs on the large side.
+pub const ASSUMED_LAZ_RATIO: u64 = 5;
+
+/// The inputs and outputs of a run.
+#[derive(Clone, Debug)]
+pub struct Plan {
+    /// The headers of the inputs that could be read, in the order the inputs were given.
+    pub inputs: Vec<FileInfo>,
+    /// The inputs that couldn't be read and would be left out under `ErrorPolicy::Skip`.
+    pub skipped: Vec<String>,
+    /// One entry per output, in the order the outputs were given.
+    pub outputs: Vec<PlannedOutput>,
+}
+
+/// An output of a run, sized as if every point went to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedOutput {
+    pub path: String,
+    pub compressed: bool,
+    /// The number of points in all inputs, which the output holds if no point is filtered out.
+    pub max_points: u64,
+    /// The number of files the output is spread over when their number of points is limited.
+    pub parts: u64,
+    /// The size of the output holding `max_points` points, exact for LAS apart from the VLRs and
+    /// estimated with [`ASSUMED_LAZ_RATIO`] for LAZ.
+    pub estimated_bytes: u64,
+    /// Whether a local file is already at the path.
+    pub exists: bool,
+}
+
+impl Plan {
+    /// The number of points in all readable inputs.
+    pub fn points(&self) -> u64 {
+        self.inputs.iter().map(|input| input.number_of_points).sum()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} input(s), {} points:",
+            self.inputs.len(),
+            self.points()
+        )?;
+        for input in &self.inputs {
+            writeln!(
+                f,
+                "  {}: {} points, point format {}, LAS {}",
+                input.path, input.number_of_points, input.point_format, input.version
+            )?;
+        }
+        for path in &self.skipped {
+            writeln!(f, "  {}: can't be read, would be skipped", path)?;
+        }
+        write!(f, "{} output(s):", self.outputs.len())?;
+        for output in &self.outputs {
+            write!(f, "\n  {}", output)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PlannedOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}, at most {} points, about {}",
+            self.path,
+            if self.compressed { "LAZ" } else { "LAS" },
+            self.max_points,
+            format_size(self.estimated_bytes)
+        )?;
+        if self.parts > 1 {
+            write!(f, " in {} files", self.parts)?;
+        }
+        if self.exists {
+            write!(f, " (exists)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a number of bytes with the largest binary unit that keeps it at least 1.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 << 30), "3.0 GiB");
+        assert_eq!(format_size(1 << 50), "1024.0 TiB");
+    }
+}
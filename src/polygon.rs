@@ -0,0 +1,415 @@
+//! Polygons to clip point clouds to, or to cut out of them.
+//!
+//! [`Polygons`] tests whether points fall inside any of a set of polygons, each an exterior ring
+//! with optional holes, only looking at X and Y. The polygons are read from GeoJSON: a Polygon or
+//! MultiPolygon geometry, a Feature holding one, or a FeatureCollection or GeometryCollection of
+//! them. The coordinates must be in the coordinate system of the points. Keeping the points inside
+//! the polygons clips the cloud to an area, and keeping the ones outside redacts the area, e.g.
+//! private properties in a published dataset.
+//!
+//! The features of a layer can also each be given an output of their own, named from one of their
+//! properties, e.g. to clip a survey to hundreds of parcels in one pass. See [`Feature`].
+use crate::errors::MyError;
+use crate::filter::{Condition, NumericFilter};
+#[cfg(feature = "native")]
+use serde_json::Value;
+use std::sync::Arc;
+
+/// The placeholder in an output path that stands for the name of a feature.
+pub const NAME_PLACEHOLDER: &str = "{name}";
+
+/// Returns `true` if `template` can name the outputs of features, i.e. contains `{name}`.
+pub fn is_feature_template(template: &str) -> bool {
+    template.contains(NAME_PLACEHOLDER)
+}
+
+/// A ring of a polygon, closed, with the exterior counter-clockwise and holes clockwise.
+pub type Ring = Vec<[f64; 2]>;
+
+/// A set of polygons, each an exterior ring followed by its holes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polygons {
+    polygons: Vec<Vec<Ring>>,
+    /// The lowest and highest X and Y of the exterior rings, to turn away far points quickly.
+    min: [f64; 2],
+    max: [f64; 2],
+}
+
+impl Polygons {
+    /// The polygons `polygons`, each an exterior ring followed by its holes. The rings may be
+    /// open or closed, and wound either way.
+    pub fn new(polygons: Vec<Vec<Ring>>) -> Result<Self, MyError> {
+        let mut min = [f64::INFINITY; 2];
+        let mut max = [f64::NEG_INFINITY; 2];
+        for polygon in &polygons {
+            let Some(exterior) = polygon.first() else {
+                return Err(invalid("a polygon has no rings"));
+            };
+            for ring in polygon {
+                if ring.len() < 3 {
+                    return Err(invalid("a ring has fewer than 3 positions"));
+                }
+            }
+            for [x, y] in exterior {
+                min = [min[0].min(*x), min[1].min(*y)];
+                max = [max[0].max(*x), max[1].max(*y)];
+            }
+        }
+        Ok(Self { polygons, min, max })
+    }
+
+    /// The polygons of a GeoJSON geometry, feature or collection.
+    #[cfg(feature = "native")]
+    pub fn from_geojson(value: &Value) -> Result<Self, MyError> {
+        let mut polygons = Vec::new();
+        collect_polygons(value, &mut polygons)?;
+        if polygons.is_empty() {
+            return Err(invalid("no Polygon or MultiPolygon found"));
+        }
+        Self::new(polygons)
+    }
+
+    /// The polygons of the GeoJSON file at `path`.
+    #[cfg(feature = "native")]
+    pub fn from_path(path: &std::path::Path) -> Result<Self, MyError> {
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|err| invalid(&err.to_string()))?;
+        Self::from_geojson(&value)
+    }
+
+    /// The polygons with each of their vertices moved by `map`, e.g. to project them to the
+    /// coordinate system of the points. Edges stay straight between the moved vertices.
+    pub fn map(&self, map: impl Fn(f64, f64) -> (f64, f64)) -> Result<Self, MyError> {
+        let polygons = self
+            .polygons
+            .iter()
+            .map(|polygon| {
+                polygon
+                    .iter()
+                    .map(|ring| {
+                        ring.iter()
+                            .map(|&[x, y]| {
+                                let (x, y) = map(x, y);
+                                [x, y]
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        Self::new(polygons)
+    }
+
+    /// The polygons, each an exterior ring followed by its holes.
+    pub fn polygons(&self) -> &[Vec<Ring>] {
+        &self.polygons
+    }
+
+    /// Whether the point at `x` and `y` is inside one of the polygons and outside its holes.
+    /// Points on an edge may fall either way.
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        if x < self.min[0] || x > self.max[0] || y < self.min[1] || y > self.max[1] {
+            return false;
+        }
+        self.polygons.iter().any(|polygon| {
+            ring_contains(&polygon[0], x, y)
+                && !polygon[1..].iter().any(|hole| ring_contains(hole, x, y))
+        })
+    }
+}
+
+/// A feature of a polygon layer, with the name of its output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Feature {
+    pub name: String,
+    pub polygons: Polygons,
+}
+
+impl Feature {
+    /// A condition keeping the points inside the polygons of the feature.
+    pub fn condition(&self) -> Condition {
+        Condition::numeric(NumericFilter::Polygons(
+            Arc::new(self.polygons.clone()),
+            true,
+        ))
+    }
+
+    /// Fills in the `{name}` placeholder of an output path template with the name of the
+    /// feature, with the characters that don't belong in a file name replaced by `_`.
+    pub fn render_path(&self, template: &str) -> String {
+        let name: String = self
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' ') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let name = if name.chars().all(|c| c == '.') {
+            name.replace('.', "_")
+        } else {
+            name
+        };
+        template.replace(NAME_PLACEHOLDER, &name)
+    }
+}
+
+/// The features of a GeoJSON FeatureCollection or Feature that hold polygons, in order, named by
+/// their `name_field` property, or by their index in the collection without one. The polygons of
+/// features with the same name are put together. Fails on names that are blank or only dots, like
+/// `..`, which can't name a file.
+#[cfg(feature = "native")]
+pub fn features(value: &Value, name_field: Option<&str>) -> Result<Vec<Feature>, MyError> {
+    let members = match value.get("type").and_then(Value::as_str) {
+        Some("FeatureCollection") => value
+            .get("features")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("the FeatureCollection has no features"))?
+            .iter()
+            .collect(),
+        Some("Feature") => vec![value],
+        _ => return Err(invalid("expected a Feature or a FeatureCollection")),
+    };
+    let mut features: Vec<(String, Vec<Vec<Ring>>)> = Vec::new();
+    for (index, feature) in members.into_iter().enumerate() {
+        let mut polygons = Vec::new();
+        collect_polygons(feature, &mut polygons)?;
+        if polygons.is_empty() {
+            continue;
+        }
+        let name = match name_field {
+            Some(field) => match feature
+                .get("properties")
+                .and_then(|properties| properties.get(field))
+            {
+                Some(Value::String(name)) => name.clone(),
+                Some(Value::Number(number)) => number.to_string(),
+                _ => {
+                    return Err(invalid(&format!(
+                        "feature {} has no {} property to name it",
+                        index, field
+                    )))
+                }
+            },
+            None => index.to_string(),
+        };
+        if name.trim().chars().all(|c| c == '.') {
+            return Err(invalid(&format!(
+                "feature {} is named {:?}, which can't name a file",
+                index, name
+            )));
+        }
+        match features.iter_mut().find(|(other, _)| *other == name) {
+            Some((_, other)) => other.extend(polygons),
+            None => features.push((name, polygons)),
+        }
+    }
+    features
+        .into_iter()
+        .map(|(name, polygons)| {
+            Ok(Feature {
+                name,
+                polygons: Polygons::new(polygons)?,
+            })
+        })
+        .collect()
+}
+
+/// The features of the GeoJSON file at `path`, see [`features`].
+#[cfg(feature = "native")]
+pub fn read_features(
+    path: &std::path::Path,
+    name_field: Option<&str>,
+) -> Result<Vec<Feature>, MyError> {
+    let value: Value = serde_json::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|err| invalid(&err.to_string()))?;
+    features(&value, name_field)
+}
+
+fn invalid(message: &str) -> MyError {
+    MyError::InvalidPolygon(message.to_string())
+}
+
+/// Whether `ring` contains the point at `x` and `y`, by the even-odd rule.
+fn ring_contains(ring: &[[f64; 2]], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    let mut previous = ring[ring.len() - 1];
+    for &vertex in ring {
+        let ([x1, y1], [x2, y2]) = (previous, vertex);
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+            inside = !inside;
+        }
+        previous = vertex;
+    }
+    inside
+}
+
+/// Adds the polygons of the GeoJSON object `value` to `polygons`, ignoring other geometries.
+#[cfg(feature = "native")]
+fn collect_polygons(value: &Value, polygons: &mut Vec<Vec<Ring>>) -> Result<(), MyError> {
+    let kind = value
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("expected a GeoJSON object with a type"))?;
+    let coordinates = || {
+        value
+            .get("coordinates")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid(&format!("the {} has no coordinates", kind)))
+    };
+    let members = |key: &str| {
+        value
+            .get(key)
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid(&format!("the {} has no {}", kind, key)))
+    };
+    match kind {
+        "Polygon" => polygons.push(parse_polygon(coordinates()?)?),
+        "MultiPolygon" => {
+            for polygon in coordinates()? {
+                let rings = polygon
+                    .as_array()
+                    .ok_or_else(|| invalid("expected a list of rings"))?;
+                polygons.push(parse_polygon(rings)?);
+            }
+        }
+        "Feature" => {
+            if let Some(geometry) = value
+                .get("geometry")
+                .filter(|geometry| geometry.is_object())
+            {
+                collect_polygons(geometry, polygons)?;
+            }
+        }
+        "FeatureCollection" => {
+            for feature in members("features")? {
+                collect_polygons(feature, polygons)?;
+            }
+        }
+        "GeometryCollection" => {
+            for geometry in members("geometries")? {
+                collect_polygons(geometry, polygons)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Reads the rings of a GeoJSON polygon, each a list of positions.
+#[cfg(feature = "native")]
+fn parse_polygon(rings: &[Value]) -> Result<Vec<Ring>, MyError> {
+    rings
+        .iter()
+        .map(|ring| {
+            ring.as_array()
+                .ok_or_else(|| invalid("expected a ring of positions"))?
+                .iter()
+                .map(|position| {
+                    let position = position.as_array().map(Vec::as_slice);
+                    match position {
+                        Some([x, y, ..]) => x
+                            .as_f64()
+                            .zip(y.as_f64())
+                            .map(|(x, y)| [x, y])
+                            .ok_or_else(|| invalid("expected numeric coordinates")),
+                        _ => Err(invalid("expected positions of at least X and Y")),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_polygons() {
+        // A 10 by 10 square with a 2 by 2 hole, and a triangle off to the side
+        let square = vec![
+            vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]],
+            vec![[4.0, 4.0], [4.0, 6.0], [6.0, 6.0], [6.0, 4.0], [4.0, 4.0]],
+        ];
+        let triangle = vec![vec![[20.0, 0.0], [30.0, 0.0], [20.0, 10.0]]];
+        let polygons = Polygons::new(vec![square, triangle]).unwrap();
+        assert!(polygons.contains(1.0, 1.0));
+        assert!(!polygons.contains(5.0, 5.0));
+        assert!(polygons.contains(21.0, 1.0));
+        assert!(!polygons.contains(29.0, 9.0));
+        assert!(!polygons.contains(15.0, 5.0));
+        assert!(!polygons.contains(-1.0, 5.0));
+        assert!(Polygons::new(vec![vec![vec![[0.0, 0.0], [1.0, 1.0]]]]).is_err());
+        assert!(Polygons::new(vec![vec![]]).is_err());
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn test_polygons_from_geojson() {
+        let value = serde_json::from_str(
+            r#"{"type": "FeatureCollection", "features": [
+                {"type": "Feature", "properties": {}, "geometry":
+                    {"type": "Polygon", "coordinates": [[[0, 0], [2, 0], [2, 2], [0, 2], [0, 0]]]}},
+                {"type": "Feature", "properties": {}, "geometry":
+                    {"type": "MultiPolygon", "coordinates": [[[[5, 5, 1], [6, 5, 1], [6, 6, 1]]]]}},
+                {"type": "Feature", "properties": {}, "geometry":
+                    {"type": "Point", "coordinates": [9, 9]}}
+            ]}"#,
+        )
+        .unwrap();
+        let polygons = Polygons::from_geojson(&value).unwrap();
+        assert_eq!(polygons.polygons().len(), 2);
+        assert!(polygons.contains(1.0, 1.0));
+        assert!(polygons.contains(5.9, 5.5));
+        assert!(!polygons.contains(9.0, 9.0));
+
+        for geojson in [
+            r#"{"type": "Point", "coordinates": [0, 0]}"#,
+            r#"{"type": "Polygon", "coordinates": [[[0, 0], [1, "a"], [1, 1]]]}"#,
+            r#"[]"#,
+        ] {
+            let value = serde_json::from_str(geojson).unwrap();
+            assert!(Polygons::from_geojson(&value).is_err(), "{}", geojson);
+        }
+
+        // Features are named by a property, and the unnamed point feature is left out
+        let features = features(&value_with_ids(), Some("parcel")).unwrap();
+        let names: Vec<&str> = features
+            .iter()
+            .map(|feature| feature.name.as_str())
+            .collect();
+        assert_eq!(names, ["A/1", "7"]);
+        assert_eq!(features[1].polygons.polygons().len(), 2);
+        assert_eq!(features[0].render_path("out/{name}.laz"), "out/A_1.laz");
+        let unnamed = super::features(&value_with_ids(), None).unwrap();
+        let names: Vec<&str> = unnamed
+            .iter()
+            .map(|feature| feature.name.as_str())
+            .collect();
+        assert_eq!(names, ["0", "1", "2"]);
+        assert!(super::features(&value_with_ids(), Some("owner")).is_err());
+        // Names that would leave the output folder
+        let mut value = value_with_ids();
+        value["features"][0]["properties"]["parcel"] = Value::String("..".to_string());
+        assert!(super::features(&value, Some("parcel")).is_err());
+    }
+
+    #[cfg(feature = "native")]
+    fn value_with_ids() -> Value {
+        let square = r#"{"type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1]]]}"#;
+        serde_json::from_str(&format!(
+            r#"{{"type": "FeatureCollection", "features": [
+                {{"type": "Feature", "properties": {{"parcel": "A/1"}}, "geometry": {square}}},
+                {{"type": "Feature", "properties": {{"parcel": 7}}, "geometry": {square}}},
+                {{"type": "Feature", "properties": {{"parcel": 7}}, "geometry": {square}}},
+                {{"type": "Feature", "properties": {{}},
+                    "geometry": {{"type": "Point", "coordinates": [0, 0]}}}}
+            ]}}"#
+        ))
+        .unwrap()
+    }
+}
@@ -0,0 +1,70 @@
+//! Recycling of the buffers batches of points are sent in.
+//!
+//! Writers give a batch's buffer back once its points are written, and readers take their next
+//! buffer from the pool, so once a run has warmed up no batch needs a fresh allocation. The
+//! buffers are cleared but keep their capacity.
+use crossbeam::queue::ArrayQueue;
+use las::Point;
+
+/// A bounded pool of empty batch buffers, shared between the readers and the writers.
+#[derive(Debug)]
+pub(crate) struct BatchPool {
+    buffers: ArrayQueue<Vec<Point>>,
+}
+
+impl BatchPool {
+    /// Creates a pool holding up to `capacity` idle buffers. It should be about the number of
+    /// batches alive at once, the ones being filled plus the ones waiting to be written.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            buffers: ArrayQueue::new(capacity.max(1)),
+        }
+    }
+
+    /// Takes an empty buffer with room for at least `batch_size` points, reusing an idle one if
+    /// there is any.
+    pub(crate) fn take(&self, batch_size: usize) -> Vec<Point> {
+        match self.buffers.pop() {
+            Some(mut buffer) => {
+                buffer.reserve(batch_size);
+                buffer
+            }
+            None => Vec::with_capacity(batch_size),
+        }
+    }
+
+    /// Clears `buffer` and keeps it for reuse. It is dropped instead if the pool is full.
+    pub(crate) fn give_back(&self, mut buffer: Vec<Point>) {
+        buffer.clear();
+        let _ = self.buffers.push(buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_pool() {
+        let pool = BatchPool::new(1);
+        let mut buffer = pool.take(100);
+        assert!(buffer.capacity() >= 100);
+        buffer.push(Point::default());
+        let address = buffer.as_ptr();
+        pool.give_back(buffer);
+
+        // The same allocation comes back, empty
+        let buffer = pool.take(50);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_ptr(), address);
+        // and grows when a larger batch is asked for
+        pool.give_back(buffer);
+        assert!(pool.take(1_000).capacity() >= 1_000);
+
+        // Buffers beyond the capacity are dropped
+        pool.give_back(Vec::with_capacity(10));
+        pool.give_back(Vec::with_capacity(20));
+        assert_eq!(pool.take(0).capacity(), 10);
+        assert_eq!(pool.take(0).capacity(), 0);
+    }
+}
@@ -0,0 +1,215 @@
+//! Named stacks of filters, so common selections don't have to be spelled out on every run.
+//!
+//! Presets are lists of PDAL filter stages, as read by [`crate::pdal::filter_stages`]. Besides the
+//! built-in ones, every `<name>.json` file in the `presets` folder of the configuration directory
+//! is a preset named after the file, replacing any built-in preset of the same name. The file
+//! holds either the list of stages, or a table with the `stages` and a `description`:
+//!
+//! ```json
+//! {
+//!     "description": "Ground points in the survey area",
+//!     "stages": [
+//!         {"type": "filters.range", "limits": "Classification[2:2]"},
+//!         {"type": "filters.crop", "bounds": "([1000, 2000], [5000, 6000])"}
+//!     ]
+//! }
+//! ```
+use crate::errors::MyError;
+use crate::filter::NumericFilter;
+use crate::pdal::filter_stages;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The environment variable overriding the configuration directory.
+pub const CONFIG_DIR_VAR: &str = "LAS_TRIMMER_CONFIG_DIR";
+
+/// The built-in presets, as names, descriptions and stages.
+const BUILT_IN: [(&str, &str, &str); 4] = [
+    (
+        "ground-only",
+        "Ground points (class 2)",
+        r#"[{"type": "filters.range", "limits": "Classification[2:2]"}]"#,
+    ),
+    (
+        "buildings",
+        "Building points (class 6)",
+        r#"[{"type": "filters.range", "limits": "Classification[6:6]"}]"#,
+    ),
+    (
+        "vegetation",
+        "Low, medium and high vegetation (classes 3 to 5)",
+        r#"[{"type": "filters.range", "limits": "Classification[3:5]"}]"#,
+    ),
+    (
+        "denoise-aerial",
+        "Everything but low and high noise (classes 7 and 18)",
+        r#"[{"type": "filters.range",
+             "limits": "Classification[0:6],Classification[8:17],Classification[19:255]"}]"#,
+    ),
+];
+
+/// A named stack of filters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    pub description: String,
+    /// The filters a point must all pass.
+    pub stages: Vec<NumericFilter>,
+    /// The file the preset was read from, or `None` for a built-in preset.
+    pub path: Option<PathBuf>,
+}
+
+impl Preset {
+    /// Reads a preset from a list of stages, or a table with the stages and a description.
+    fn from_value(name: &str, value: &Value, path: Option<PathBuf>) -> Result<Self, MyError> {
+        let invalid = |message: &str| MyError::InvalidPreset(name.to_string(), message.to_string());
+        let (description, stages) = match value {
+            Value::Array(stages) => ("", stages),
+            _ => (
+                value
+                    .get("description")
+                    .and_then(Value::as_str)
+                    .unwrap_or(""),
+                value
+                    .get("stages")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| invalid("expected a list of `stages`"))?,
+            ),
+        };
+        let stages = filter_stages(stages).map_err(|err| match err {
+            MyError::InvalidPipeline(message) => invalid(&message),
+            err => err,
+        })?;
+        Ok(Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            stages,
+            path,
+        })
+    }
+}
+
+/// The configuration directory: `$LAS_TRIMMER_CONFIG_DIR`, or `las_trimmer` in
+/// `$XDG_CONFIG_HOME` or `~/.config`.
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(CONFIG_DIR_VAR) {
+        return Some(PathBuf::from(dir));
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("las_trimmer"))
+}
+
+/// The built-in presets followed by the ones in the configuration directory.
+pub fn presets() -> Result<Vec<Preset>, MyError> {
+    load_presets(config_dir().as_deref())
+}
+
+/// The built-in presets followed by the ones in the `presets` folder of `config_dir`, in file name
+/// order. User presets replace the built-in ones of the same name.
+pub fn load_presets(config_dir: Option<&Path>) -> Result<Vec<Preset>, MyError> {
+    let mut presets = BUILT_IN
+        .iter()
+        .map(|(name, description, stages)| {
+            let stages: Value = serde_json::from_str(stages)
+                .map_err(|err| MyError::InvalidPreset(name.to_string(), format!("{}", err)))?;
+            let mut preset = Preset::from_value(name, &stages, None)?;
+            preset.description = description.to_string();
+            Ok(preset)
+        })
+        .collect::<Result<Vec<_>, MyError>>()?;
+
+    let dir = match config_dir {
+        Some(config_dir) if config_dir.join("presets").is_dir() => config_dir.join("presets"),
+        _ => return Ok(presets),
+    };
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == "json")
+    });
+    paths.sort();
+    let mut user_presets = Vec::new();
+    for path in paths {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let value: Value = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|err| MyError::InvalidPreset(name.clone(), format!("{}", err)))?;
+        presets.retain(|preset: &Preset| preset.name != name);
+        user_presets.push(Preset::from_value(&name, &value, Some(path))?);
+    }
+    presets.extend(user_presets);
+    Ok(presets)
+}
+
+/// Finds the preset named `name` among `presets`.
+pub fn find_preset<'a>(presets: &'a [Preset], name: &str) -> Result<&'a Preset, MyError> {
+    presets
+        .iter()
+        .find(|preset| preset.name == name)
+        .ok_or_else(|| MyError::UnknownPreset(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::ClassMask;
+
+    #[test]
+    fn test_presets() {
+        let presets = load_presets(None).unwrap();
+        let ground = find_preset(&presets, "ground-only").unwrap();
+        assert_eq!(
+            ground.stages,
+            [NumericFilter::Classes(ClassMask::new(&[2]))]
+        );
+        let denoise = find_preset(&presets, "denoise-aerial").unwrap();
+        let [NumericFilter::Classes(classes)] = &denoise.stages[..] else {
+            panic!("unexpected stages {:?}", denoise.stages);
+        };
+        assert!(classes.contains(2) && classes.contains(19));
+        assert!(!classes.contains(7) && !classes.contains(18));
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("presets")).unwrap();
+        fs::write(
+            dir.path().join("presets/buildings.json"),
+            r#"{"description": "Tall buildings", "stages": [
+                {"type": "filters.range", "limits": "Classification[6:6],Z[50:]"}
+            ]}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("presets/water.json"),
+            r#"[{"type": "filters.range", "limits": "Classification[9:9]"}]"#,
+        )
+        .unwrap();
+        let presets = load_presets(Some(dir.path())).unwrap();
+        assert_eq!(presets.len(), BUILT_IN.len() + 1);
+        let buildings = find_preset(&presets, "buildings").unwrap();
+        assert_eq!(buildings.description, "Tall buildings");
+        assert_eq!(buildings.stages.len(), 2);
+        assert!(find_preset(&presets, "water").unwrap().path.is_some());
+        assert!(matches!(
+            find_preset(&presets, "roads"),
+            Err(MyError::UnknownPreset(_))
+        ));
+
+        fs::write(
+            dir.path().join("presets/bad.json"),
+            r#"[{"type": "filters.smrf"}]"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            load_presets(Some(dir.path())),
+            Err(MyError::InvalidPreset(name, _)) if name == "bad"
+        ));
+    }
+}
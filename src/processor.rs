@@ -0,0 +1,3256 @@
+//! The multithreaded pipeline behind [`LasProcessor`]: reader threads filter the points of the
+//! inputs into batches, and writer threads write the batches to the outputs. It needs the
+//! `native` feature.
+use crate::adjustment::Adjustments;
+use crate::anonymize::Anonymization;
+use crate::cancel::CancellationToken;
+use crate::colormap::{with_color, ColorSource, Colorizer, Colormap};
+use crate::compression::LazChunking;
+use crate::crs::Crs;
+use crate::errors::MyError;
+use crate::filter::{Condition, Dimensions};
+use crate::flight_lines::{FlightLineDetector, FlightLines};
+use crate::gps_time::GpsTimeConversion;
+use crate::info::FileInfo;
+use crate::input::is_stdin;
+use crate::legacy::legacy_header;
+use crate::logging;
+use crate::minimize::DimensionUsage;
+use crate::neighborhood::NeighborFilter;
+use crate::output::{check_output_paths, render_part_path, OutputWriter};
+use crate::output_options::OutputOptions;
+use crate::overlap::{cell_of, Overlap, OverlapKind};
+use crate::palette::ClassPalette;
+use crate::plan::{Plan, PlannedOutput, ASSUMED_LAZ_RATIO};
+use crate::pool::BatchPool;
+use crate::progress::{ConsoleProgress, Progress, ProgressObserver};
+use crate::records::PointSource;
+use crate::report::{FileReport, FileState, ProcessingReport};
+use crate::sink::{OutputTarget, PointSink, SharedSink};
+use crate::source_tag::{SourceIds, SourceTag};
+use crate::space::check_space;
+use crate::spill::{
+    SpillConfig, LOW_MEMORY_BATCH_SIZE, LOW_MEMORY_CHANNEL_DEPTH, LOW_MEMORY_SPILL_BYTES,
+};
+use crate::split::{can_split, chunk_alignment, split_ranges, DEFAULT_SPLIT_SIZE};
+use crate::stages::SortKey;
+use crate::stamp::HeaderStamp;
+use crate::stream::{InputSource, InputStream, Stream};
+use crate::stretch::{IntensityHistogram, IntensityStretch};
+use crate::transform::ConditionalTransform;
+use crate::tuning::Tuning;
+use crate::tuning::{AutoTuner, PointBudget, Throttle, DEFAULT_BATCH_SIZE, DEFAULT_CHANNEL_DEPTH};
+use crate::vlr_merge::VlrMerge;
+use crate::waveform::{drop_waveforms, without_waveform};
+use crate::SharedFunction;
+use crossbeam::channel;
+use las::{Header, Point, Reader};
+use log::{debug, warn};
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use threadpool::ThreadPool;
+
+/// How often the writer loop checks on the reader threads while it waits for points.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What to do when an input file can't be read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop processing and return the error. The outputs are not written.
+    #[default]
+    Abort,
+    /// Log the error, leave the file out and carry on with the others. The skipped files are
+    /// listed in `ProcessingReport::skipped`.
+    Skip,
+}
+
+/// How the reading and writing are spread over threads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// A pool of reader threads, one job per input or range of an input, sending batches through
+    /// a bounded queue to writer threads that each own a share of the outputs.
+    #[default]
+    Threads,
+    /// A rayon pool whose threads both read and write. The inputs are cut into shards that idle
+    /// threads pick up, which keeps every core busy when the input sizes are skewed, and each batch
+    /// is written to its output by the thread that filled it.
+    Rayon,
+}
+
+/// Cancels a token when dropped.
+struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// The state of one input file, shared between its reader threads and the writer loop.
+struct FileProgress {
+    state: FileState,
+    last_progress: Instant,
+    error: Option<MyError>,
+    points_read: u64,
+    corrupt_points: u64,
+    duration: Duration,
+    /// When the first range of the file started being read.
+    started: Option<Instant>,
+    /// The number of ranges of the file that haven't been read yet.
+    ranges_left: usize,
+}
+
+impl FileProgress {
+    fn is_finished(&self) -> bool {
+        matches!(
+            self.state,
+            FileState::Done | FileState::TimedOut | FileState::Failed
+        )
+    }
+}
+
+/// Records that the reader of file `index` is still making progress. Returns `false` if the file
+/// has been abandoned or has failed in the meantime and reading should stop.
+fn record_progress(progress: &Mutex<Vec<FileProgress>>, index: usize, state: FileState) -> bool {
+    let Ok(mut progress) = progress.lock() else {
+        return false;
+    };
+    let file = &mut progress[index];
+    if matches!(file.state, FileState::TimedOut | FileState::Failed) {
+        return false;
+    }
+    file.state = state;
+    file.last_progress = Instant::now();
+    true
+}
+
+/// Records that a range of file `index` has started being read. Returns `true` for the first
+/// range of the file.
+fn record_start(progress: &Mutex<Vec<FileProgress>>, index: usize) -> bool {
+    let Ok(mut progress) = progress.lock() else {
+        return false;
+    };
+    let file = &mut progress[index];
+    let first = file.started.is_none();
+    file.started.get_or_insert_with(Instant::now);
+    first
+}
+
+/// Counts an undecodable point of file `index`. Returns the number counted for the file so far.
+fn record_corrupt_point(progress: &Mutex<Vec<FileProgress>>, index: usize) -> u64 {
+    let Ok(mut progress) = progress.lock() else {
+        return u64::MAX;
+    };
+    progress[index].corrupt_points += 1;
+    progress[index].corrupt_points
+}
+
+/// Records that file `index` could not be read, unless it has been abandoned already.
+fn record_failure(progress: &Mutex<Vec<FileProgress>>, index: usize, error: MyError) {
+    if let Ok(mut progress) = progress.lock() {
+        let file = &mut progress[index];
+        if file.state != FileState::TimedOut {
+            file.state = FileState::Failed;
+            file.error = Some(error);
+        }
+    }
+}
+
+/// Records that a range of file `index` is finished, with `points_read` points read from it.
+/// Returns `true` for the last range of the file, which marks the file as done unless it failed or
+/// timed out.
+fn record_range_done(progress: &Mutex<Vec<FileProgress>>, index: usize, points_read: u64) -> bool {
+    let Ok(mut progress) = progress.lock() else {
+        return false;
+    };
+    let file = &mut progress[index];
+    file.points_read += points_read;
+    if let Some(started) = file.started {
+        file.duration = started.elapsed();
+    }
+    file.ranges_left = file.ranges_left.saturating_sub(1);
+    if file.ranges_left > 0 {
+        return false;
+    }
+    if matches!(file.state, FileState::Queued | FileState::Reading) {
+        file.state = FileState::Done;
+    }
+    true
+}
+
+/// Builds the report for input `path` from its shared state.
+fn file_report(path: &str, file: &FileProgress) -> FileReport {
+    FileReport {
+        path: path.to_string(),
+        state: file.state,
+        points_read: file.points_read,
+        corrupt_points: file.corrupt_points,
+        duration: file.duration,
+    }
+}
+
+/// The state of a run shared between the readers, the writers and the loop watching over them.
+struct Run {
+    start: Instant,
+    file_progress: Mutex<Vec<FileProgress>>,
+    points_to_read: Mutex<u64>,
+    points_read: Mutex<u64>,
+    points_to_write: Mutex<u64>,
+    points_written: Mutex<u64>,
+    /// The buffers of written batches, for the readers to fill again.
+    batches: BatchPool,
+    /// Stops the remaining readers when a failed file aborts the run, or when the run ends early
+    /// for any other reason.
+    abort: CancellationToken,
+    /// The stretch of the intensities, once the percentiles are known.
+    intensity_stretch: Option<IntensityStretch>,
+    /// The coloring of the points, once the range of values is known.
+    colorizer: Option<Colorizer>,
+    /// The area all the inputs cover, outside of which points are dropped, if they are.
+    overlap: Option<Overlap>,
+    /// The flight lines whose numbers the points are given as point source IDs, once found.
+    flight_lines: Option<FlightLines>,
+}
+
+impl Run {
+    /// The progress so far, with `batches_queued` batches waiting to be written.
+    fn snapshot(&self, batches_queued: usize) -> Result<Progress, MyError> {
+        let (files_total, files_finished) = {
+            let progress = self.file_progress.lock().map_err(|_| MyError::LockError)?;
+            let finished = progress.iter().filter(|file| file.is_finished()).count();
+            (progress.len(), finished)
+        };
+        Ok(Progress {
+            files_total,
+            files_finished,
+            points_to_read: *self.points_to_read.lock().map_err(|_| MyError::LockError)?,
+            points_read: *self.points_read.lock().map_err(|_| MyError::LockError)?,
+            points_to_write: *self
+                .points_to_write
+                .lock()
+                .map_err(|_| MyError::LockError)?,
+            points_written: *self.points_written.lock().map_err(|_| MyError::LockError)?,
+            batches_queued,
+            elapsed: self.start.elapsed(),
+        })
+    }
+}
+
+/// What the reader jobs need from the processor, cloned into each job.
+#[derive(Clone)]
+struct ReadSettings {
+    conditions: Vec<Condition>,
+    cancellation: CancellationToken,
+    observer: Arc<dyn ProgressObserver>,
+    max_point_errors: u64,
+    /// Whether the extra bytes of the inputs are dropped before the points are batched, so they
+    /// are never copied.
+    strip_extra_bytes: bool,
+    /// How the GPS times of the points are converted as they are read.
+    gps_time: Option<GpsTimeConversion>,
+    /// Where the index of the input is written in each point, if it is.
+    source_tag: Option<SourceTag>,
+    /// The point source IDs given to the points of some inputs.
+    source_ids: SourceIds,
+    /// The rigid transforms moving the points of some inputs.
+    adjustments: Adjustments,
+    /// The transforms applied to the points kept, in order.
+    transforms: Vec<ConditionalTransform>,
+    /// The colors the points are given by class, if they are.
+    class_palette: Option<ClassPalette>,
+    /// What is taken out of the points before they are written, if anything.
+    anonymization: Option<Anonymization>,
+    /// Whether the waveforms of full-waveform points are dropped rather than refused.
+    drop_waveforms: bool,
+    /// Whether uncompressed local inputs are read as raw records, which pays off when no
+    /// condition needs the whole point.
+    read_records: bool,
+    /// Whether uncompressed local inputs are memory-mapped.
+    mmap: bool,
+    /// How many points are read between checks for cancellation.
+    check_interval: u64,
+}
+
+/// An input to read, or a range of the points of a split one.
+struct ReadJob {
+    index: usize,
+    path: String,
+    source: InputSource,
+    range: Option<Range<u64>>,
+}
+
+/// Lists the jobs for the `inputs`, one per input or per range of a split input.
+fn read_jobs(
+    inputs: Vec<(String, InputSource)>,
+    ranges: Vec<Option<Vec<Range<u64>>>>,
+) -> Vec<ReadJob> {
+    inputs
+        .into_iter()
+        .zip(ranges)
+        .enumerate()
+        .flat_map(|(index, ((path, source), ranges))| {
+            let ranges: Vec<Option<Range<u64>>> = match ranges {
+                Some(ranges) => ranges.into_iter().map(Some).collect(),
+                None => vec![None],
+            };
+            ranges.into_iter().map(move |range| ReadJob {
+                index,
+                path: path.clone(),
+                source: source.clone(),
+                range,
+            })
+        })
+        .collect()
+}
+
+/// Reads the points of `job` and hands the ones meeting each condition to `send` in batches of
+/// `batch_size()` points, along with the index of the condition. `send` returns `false` when
+/// reading should stop. The outcome is recorded in `run`, and the observer is told when the last
+/// range of the file is done.
+fn read_job(
+    settings: &ReadSettings,
+    run: &Run,
+    job: &ReadJob,
+    batch_size: &dyn Fn() -> u64,
+    send: &mut dyn FnMut(usize, Vec<Point>) -> Result<bool, MyError>,
+) {
+    let i = job.index;
+    let path = &job.path;
+    let _context = logging::file_context(path);
+    let conditions = &settings.conditions;
+    let max_point_errors = settings.max_point_errors;
+    let mut total_points_read = 0;
+    let result = (|| -> Result<(), MyError> {
+        if settings.cancellation.is_cancelled() || run.abort.is_cancelled() {
+            return Ok(());
+        }
+        record_progress(&run.file_progress, i, FileState::Reading);
+        let first_range = record_start(&run.file_progress, i);
+        let mut source = PointSource::open(&job.source, settings.read_records, settings.mmap)?;
+        let number_of_points = source.header().number_of_points();
+        let gps_time_type = source.header().gps_time_type();
+        // A LAZ decompressor loses its place after a point it can't decode
+        let skip_corrupt = max_point_errors > 0 && !source.header().point_format().is_compressed;
+        let input_extra_bytes = usize::from(source.header().point_format().extra_bytes);
+        if source.header().point_format().has_waveform && !settings.drop_waveforms {
+            return Err(MyError::WaveformsNotSupported(path.clone()));
+        }
+        let source_id = settings.source_ids.id_of(path);
+        let adjustment = settings.adjustments.of(path);
+        // Rotations without a center of their own turn about the middle of the input
+        let bounds = source.header().bounds();
+        let middle = [
+            (bounds.min.x + bounds.max.x) / 2.0,
+            (bounds.min.y + bounds.max.y) / 2.0,
+            (bounds.min.z + bounds.max.z) / 2.0,
+        ];
+        if let Some(conversion) = &settings.gps_time {
+            conversion.check(gps_time_type)?;
+        }
+        if first_range {
+            *run.points_to_read.lock().map_err(|_| MyError::LockError)? += number_of_points;
+            settings.observer.on_file_started(i, path, number_of_points);
+        }
+
+        let (first_point, range_points) = match &job.range {
+            Some(range) => {
+                source.seek(range.start)?;
+                (range.start, range.end - range.start)
+            }
+            None => (0, number_of_points),
+        };
+        let mut batch_size_now = batch_size();
+        let mut points_vecs: Vec<Vec<Point>> = (0..conditions.len())
+            .map(|_| run.batches.take(batch_size_now as usize))
+            .collect();
+        let mut range_corrupt_points = 0;
+        let mut matches = vec![false; conditions.len()];
+
+        while total_points_read + range_corrupt_points < range_points {
+            if total_points_read % settings.check_interval == 0 {
+                if settings.cancellation.is_cancelled()
+                    || run.abort.is_cancelled()
+                    || !record_progress(&run.file_progress, i, FileState::Reading)
+                {
+                    break;
+                }
+                batch_size_now = batch_size();
+            }
+            let Some(view) = source.read(conditions, &mut matches) else {
+                break;
+            };
+            let point_index = first_point + total_points_read + range_corrupt_points;
+            // The whole point is only decoded if a condition needs it or keeps it
+            let point = view.and_then(|view| {
+                if matches.contains(&true) {
+                    view.into_point().map(Some)
+                } else {
+                    Ok(None)
+                }
+            });
+            let point = match point {
+                Ok(point) => point,
+                Err(err) if !skip_corrupt => {
+                    return Err(MyError::PointReadError {
+                        path: path.clone(),
+                        point_index,
+                        source: err,
+                    });
+                }
+                Err(err) => {
+                    range_corrupt_points += 1;
+                    if record_corrupt_point(&run.file_progress, i) > max_point_errors {
+                        return Err(MyError::TooManyCorruptPoints {
+                            path: path.clone(),
+                            point_index,
+                            limit: max_point_errors,
+                            source: err,
+                        });
+                    }
+                    continue;
+                }
+            };
+            total_points_read += 1;
+
+            {
+                let mut points = run.points_read.lock().map_err(|_| MyError::LockError)?;
+                *points += 1;
+            }
+
+            let Some(mut point) = point else {
+                continue;
+            };
+            if let Some(overlap) = &run.overlap {
+                if !overlap.contains(point.x, point.y) {
+                    continue;
+                }
+            }
+            if settings.drop_waveforms {
+                point.waveform = None;
+            }
+            if let Some(adjustment) = adjustment {
+                adjustment.apply(&mut point, middle);
+            }
+            // The lines were found from the GPS times as they are in the inputs
+            let line_id = run
+                .flight_lines
+                .as_ref()
+                .and_then(|lines| lines.source_id_of(&point));
+            if let Some(conversion) = &settings.gps_time {
+                point.gps_time = point
+                    .gps_time
+                    .map(|time| conversion.convert(time, gps_time_type));
+            }
+            if let Some(source_id) = line_id.or(source_id) {
+                point.point_source_id = source_id;
+            }
+            if let Some(tag) = settings.source_tag {
+                tag.tag(&mut point, i);
+            }
+            if let Some(stretch) = &run.intensity_stretch {
+                stretch.apply(&mut point);
+            }
+            if let Some(colorizer) = &run.colorizer {
+                colorizer.apply(&mut point);
+            }
+            if let Some(palette) = &settings.class_palette {
+                palette.apply(&mut point);
+            }
+            for transform in &settings.transforms {
+                point = transform.apply(point)?;
+            }
+            if let Some(anonymization) = &settings.anonymization {
+                anonymization.apply(&mut point);
+            }
+            // Stripped once the transforms have seen them, keeping the source tag that follows
+            if settings.strip_extra_bytes {
+                point
+                    .extra_bytes
+                    .drain(..input_extra_bytes.min(point.extra_bytes.len()));
+            }
+            // Only points matching more than one condition are cloned, the last match takes it
+            let mut last_match = None;
+            for (j, _) in matches.iter().enumerate().filter(|(_, matched)| **matched) {
+                if let Some(previous) = last_match.replace(j) {
+                    points_vecs[previous].push(point.clone());
+                }
+            }
+            if let Some(last_match) = last_match {
+                points_vecs[last_match].push(point);
+            }
+            for (j, points_vec) in points_vecs.iter_mut().enumerate() {
+                if points_vec.len() as u64 >= batch_size_now {
+                    let buffer = run.batches.take(batch_size_now as usize);
+                    let batch = std::mem::replace(points_vec, buffer);
+                    if !send(j, batch)? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        if !record_progress(&run.file_progress, i, FileState::Reading) {
+            return Ok(());
+        }
+        for (j, points_vec) in points_vecs.into_iter().enumerate() {
+            if points_vec.is_empty() {
+                run.batches.give_back(points_vec);
+            } else if !send(j, points_vec)? {
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    })();
+    if let Err(err) = result {
+        record_failure(&run.file_progress, i, err);
+    }
+    if !record_range_done(&run.file_progress, i, total_points_read) {
+        return;
+    }
+    if let Ok(progress) = run.file_progress.lock() {
+        let file = file_report(path, &progress[i]);
+        debug!(
+            "Read {} points from {:?} in {:.2?} ({}, {} corrupt points skipped)",
+            file.points_read,
+            file.path,
+            file.duration,
+            file.state.as_str(),
+            file.corrupt_points
+        );
+        settings.observer.on_file_finished(i, &file);
+    }
+}
+
+/// `LasProcessor` is a struct that represents a processor for LiDAR files.
+pub struct LasProcessor {
+    /// A vector of strings representing the paths to the input LiDAR files.
+    pub(crate) paths: Vec<String>,
+    /// A vector of strings representing the paths to the output LiDAR files.
+    pub(crate) output_paths: Vec<String>,
+    /// A vector of `Arc` containing closures that take a `Point` as input and return a boolean.
+    /// Each closure is applied to each point read from the input files. Only points for which the closure returns `true` are written to the corresponding output file.
+    pub(crate) conditions: Vec<Condition>,
+    pub(crate) vec_size: u64,
+    pub(crate) strip_extra_bytes: bool,
+    /// How the GPS times of the points are converted, if they are.
+    pub(crate) gps_time: Option<GpsTimeConversion>,
+    /// Where the index of the input is written in each point, if it is.
+    pub(crate) source_tag: Option<SourceTag>,
+    /// The point source IDs given to the points of some inputs.
+    pub(crate) source_ids: SourceIds,
+    /// The rigid transforms moving the points of some inputs.
+    pub(crate) adjustments: Adjustments,
+    /// The transforms applied to the points kept, in order.
+    pub(crate) transforms: Vec<ConditionalTransform>,
+    /// What is taken out of the outputs for a public release, if anything.
+    pub(crate) anonymization: Option<Anonymization>,
+    /// The provenance fields set in the headers of the outputs.
+    pub(crate) stamp: HeaderStamp,
+    /// How the records of the inputs after the first are merged into the outputs, if they are.
+    pub(crate) vlr_merge: Option<VlrMerge>,
+    /// Whether full-waveform points are written without their waveforms.
+    pub(crate) drop_waveforms: bool,
+    /// The filter on the neighbourhood of the points applied to every output, if any.
+    pub(crate) neighbor_filter: Option<NeighborFilter>,
+    /// The settings of the outputs that differ from the others, by output path.
+    pub(crate) output_options: Vec<(String, OutputOptions)>,
+    /// Whether the outputs are written in the smallest point format holding what the points use.
+    pub(crate) minimize_format: bool,
+    /// The percentiles the intensities are stretched between, if they are.
+    pub(crate) intensity_stretch: Option<(f64, f64)>,
+    /// What the points are colored by and from which colormap, if they are.
+    pub(crate) colorize: Option<(ColorSource, Colormap)>,
+    /// The colors the points are given by class, if they are.
+    pub(crate) class_palette: Option<ClassPalette>,
+    /// How the overlap of the inputs the points are cropped to is found, if they are.
+    pub(crate) crop_to_overlap: Option<OverlapKind>,
+    /// The gap in GPS time ending a flight line, if the points are given the numbers of their
+    /// lines as point source IDs.
+    pub(crate) flight_line_gap: Option<f64>,
+    /// Whether existing output files may be replaced.
+    pub(crate) overwrite: bool,
+    /// Whether the points are added to existing output files.
+    pub(crate) append: bool,
+    /// Whether the outputs are written for software older than LAS 1.4.
+    pub(crate) legacy_compatible: bool,
+    /// Checked between batches to stop processing early.
+    pub(crate) cancellation: CancellationToken,
+    /// How long a reader may go without progress before its file is abandoned.
+    pub(crate) file_timeout: Option<Duration>,
+    /// What to do when an input file can't be read.
+    pub(crate) on_error: ErrorPolicy,
+    /// How many undecodable points are skipped per file before the file counts as failed.
+    pub(crate) max_point_errors: u64,
+    /// Receives progress updates.
+    pub(crate) observer: Arc<dyn ProgressObserver>,
+    /// How many threads read the inputs, or `None` to pick one from the number of cores.
+    pub(crate) reader_threads: Option<usize>,
+    /// How many threads write the outputs, or `None` for one per output.
+    pub(crate) writer_threads: Option<usize>,
+    /// How many batches may be waiting to be written.
+    pub(crate) channel_depth: usize,
+    /// Whether the batch size and channel depth are adjusted while running.
+    pub(crate) auto_tune: bool,
+    /// How many bytes the batches of points may take up, if limited.
+    pub(crate) max_memory: Option<u64>,
+    /// Where the points held by neighbourhood filters and sorts are spilled, and how many are
+    /// held first.
+    pub(crate) spill: SpillConfig,
+    pub(crate) sort: Option<SortKey>,
+    /// How many points an output file may hold before the output moves on to a new file.
+    pub(crate) max_output_points: Option<u64>,
+    /// The smallest number of points an input is split into for parallel reading.
+    pub(crate) split_size: u64,
+    /// How LAZ outputs are split into chunks.
+    pub(crate) laz_chunking: LazChunking,
+    /// How the work is spread over threads.
+    pub(crate) backend: Backend,
+    /// Whether uncompressed local inputs are memory-mapped.
+    pub(crate) mmap: bool,
+    /// Whether finished output files are read back and checked.
+    pub(crate) verify: bool,
+    /// Whether processing fails early when the outputs may not fit on disk.
+    pub(crate) space_check: bool,
+    /// The streams added as inputs, by the index of the input.
+    pub(crate) streams: BTreeMap<usize, Stream>,
+    /// The sinks added as outputs, by the index of the output.
+    pub(crate) sinks: BTreeMap<usize, SharedSink>,
+}
+
+impl LasProcessor {
+    /// This method creates a new `LasProcessor`. It takes as input a vector of strings representing the paths to the input LiDAR files,
+    /// a vector of strings representing the paths to the output LiDAR files, and a vector of closures that take a `las::Point` as input and return a boolean.
+    /// It returns a `LasProcessor`.
+    pub fn new(
+        paths: Vec<String>,
+        output_paths: Vec<String>,
+        conditions: Vec<SharedFunction>,
+        strip_extra_bytes: bool,
+    ) -> Self
+where {
+        Self {
+            paths,
+            output_paths,
+            vec_size: DEFAULT_BATCH_SIZE,
+            conditions: conditions.into_iter().map(Condition::from).collect(),
+            strip_extra_bytes,
+            gps_time: None,
+            source_tag: None,
+            source_ids: SourceIds::default(),
+            adjustments: Adjustments::default(),
+            transforms: Vec::new(),
+            anonymization: None,
+            stamp: HeaderStamp::new(),
+            vlr_merge: None,
+            drop_waveforms: false,
+            neighbor_filter: None,
+            output_options: Vec::new(),
+            minimize_format: false,
+            intensity_stretch: None,
+            colorize: None,
+            class_palette: None,
+            crop_to_overlap: None,
+            flight_line_gap: None,
+            overwrite: false,
+            append: false,
+            legacy_compatible: false,
+            cancellation: CancellationToken::new(),
+            file_timeout: None,
+            on_error: ErrorPolicy::Abort,
+            max_point_errors: 0,
+            observer: Arc::new(ConsoleProgress::new()),
+            reader_threads: None,
+            writer_threads: None,
+            channel_depth: DEFAULT_CHANNEL_DEPTH,
+            auto_tune: false,
+            max_memory: None,
+            spill: SpillConfig::default(),
+            sort: None,
+            max_output_points: None,
+            split_size: DEFAULT_SPLIT_SIZE,
+            laz_chunking: LazChunking::default(),
+            backend: Backend::default(),
+            mmap: false,
+            verify: false,
+            space_check: false,
+            streams: BTreeMap::new(),
+            sinks: BTreeMap::new(),
+        }
+    }
+
+    /// Converts the GPS times of the points with `conversion`, and marks the outputs as holding
+    /// the times it converts to. Inputs in GPS week time fail when converting to standard time
+    /// without the week.
+    pub fn with_gps_time(mut self, conversion: GpsTimeConversion) -> Self {
+        self.gps_time = Some(conversion);
+        self
+    }
+
+    /// Records which input each point came from as `tag` says, and lists the inputs in a VLR of
+    /// the outputs.
+    pub fn with_source_tag(mut self, tag: SourceTag) -> Self {
+        self.source_tag = Some(tag);
+        self
+    }
+
+    /// Sets the point source ID of the points of the inputs in `ids`. Tagging the points with
+    /// their input index in the point source ID takes precedence.
+    pub fn with_source_ids(mut self, ids: SourceIds) -> Self {
+        self.source_ids = ids;
+        self
+    }
+
+    /// Moves the points of the inputs in `adjustments` by their rigid transform, e.g. to apply
+    /// the results of a strip adjustment while merging. The conditions see the points as they are
+    /// in the inputs, and the transforms see them moved.
+    pub fn with_adjustments(mut self, adjustments: Adjustments) -> Self {
+        self.adjustments = adjustments;
+        self
+    }
+
+    /// Applies `transform` to the points going to the outputs, after the transforms added
+    /// before it.
+    pub fn with_transform(mut self, transform: ConditionalTransform) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    /// Takes out of the outputs what `anonymization` asks for, to release a sensitive dataset:
+    /// the coordinates are truncated and the GPS times zeroed after the transforms, and the
+    /// header of each output is stripped of its identifying metadata. The conditions see the
+    /// points as they are in the inputs.
+    pub fn with_anonymization(mut self, anonymization: Anonymization) -> Self {
+        self.anonymization = Some(anonymization);
+        self
+    }
+
+    /// Sets the provenance fields of the headers of the outputs with `stamp`. By default the
+    /// outputs name las-trimmer as their generating software. An anonymization stripping the
+    /// metadata clears the fields again.
+    pub fn with_stamp(mut self, stamp: HeaderStamp) -> Self {
+        self.stamp = stamp;
+        self
+    }
+
+    /// Copies the VLRs and EVLRs of every input to the outputs rather than those of the first
+    /// input only, keeping one of each set of identical records. See [`VlrMerge`].
+    pub fn with_vlr_merge(mut self, merge: VlrMerge) -> Self {
+        self.vlr_merge = Some(merge);
+        self
+    }
+
+    /// Writes the points of full-waveform formats in the same format without the waveform fields,
+    /// and leaves out the waveform packet descriptors and data. Otherwise inputs with waveforms
+    /// fail with `MyError::WaveformsNotSupported`, as their waveform data can't be copied.
+    pub fn with_drop_waveforms(mut self, drop_waveforms: bool) -> Self {
+        self.drop_waveforms = drop_waveforms;
+        self
+    }
+
+    /// Only writes the points of each output that `filter` keeps. The points are spilled to
+    /// temporary files by tile as they are read, and filtered and written once every input has
+    /// been read, so the points written are only counted then.
+    pub fn with_neighbor_filter(mut self, filter: NeighborFilter) -> Self {
+        self.neighbor_filter = Some(filter);
+        self
+    }
+
+    /// Writes the output at `path` with `options`, e.g. in another point format or without the
+    /// extra bytes. Unless the options say otherwise, the extra bytes are stripped as set by
+    /// [`LasProcessor::new`].
+    pub fn with_output_options(mut self, path: &str, options: OutputOptions) -> Self {
+        self.output_options.retain(|(output, _)| output != path);
+        self.output_options.push((path.to_string(), options));
+        self
+    }
+
+    /// Writes each output in the smallest point format holding the dimensions the points use,
+    /// dropping color that is black everywhere, a GPS time shared by every point, and extra bytes
+    /// that are all zero. See [`DimensionUsage`]. The inputs are read an extra time beforehand to
+    /// find out. Outputs given a point format by their [`OutputOptions`] keep it.
+    pub fn with_minimize_format(mut self, minimize_format: bool) -> Self {
+        self.minimize_format = minimize_format;
+        self
+    }
+
+    /// Stretches the intensities of the points between the `low` and `high` percentiles of every
+    /// intensity onto the whole range, clipping the others. See [`IntensityStretch`]. The inputs
+    /// are read an extra time beforehand to find the percentiles.
+    pub fn with_intensity_stretch(mut self, low: f64, high: f64) -> Self {
+        self.intensity_stretch = Some((low, high));
+        self
+    }
+
+    /// Colors the points from `colormap` by their `source`, so that scans without color look
+    /// reasonable in viewers that ignore intensity. See [`Colorizer`]. Intensities span the
+    /// colormap from 0 to 65535, elevations from the lowest to the highest Z of the input
+    /// headers. The outputs are written in a point format with color, and the transforms see the
+    /// new colors.
+    pub fn with_colorize(mut self, source: ColorSource, colormap: Colormap) -> Self {
+        self.colorize = Some((source, colormap));
+        self
+    }
+
+    /// Colors the points by their class from `palette`, for ready-to-view copies of classified
+    /// datasets. The outputs are written in a point format with color, and the transforms see the
+    /// new colors. Replaces the colors of [`LasProcessor::with_colorize`].
+    pub fn with_class_colors(mut self, palette: ClassPalette) -> Self {
+        self.class_palette = Some(palette);
+        self
+    }
+
+    /// Only keeps the points in the area every input covers, found as `kind` says. See
+    /// [`Overlap`]. The outputs of a single input are left whole, and inputs that don't overlap
+    /// leave the outputs empty.
+    pub fn with_crop_to_overlap(mut self, kind: OverlapKind) -> Self {
+        self.crop_to_overlap = Some(kind);
+        self
+    }
+
+    /// Gives each point the number of its flight line, counted from 1, as point source ID. A line
+    /// ends wherever no point was recorded for more than `gap` seconds, see
+    /// [`FlightLineDetector`]. The inputs are read an extra time beforehand to find the lines, and
+    /// points without a GPS time keep their IDs. Replaces the IDs of
+    /// [`LasProcessor::with_source_ids`].
+    pub fn with_flight_line_ids(mut self, gap: f64) -> Self {
+        self.flight_line_gap = Some(gap);
+        self
+    }
+
+    /// Finds the flight lines of the inputs, a line ending wherever no point was recorded for
+    /// more than `gap` seconds, by reading every point. See [`FlightLineDetector`].
+    pub fn flight_lines(&self, gap: f64) -> Result<FlightLines, MyError> {
+        let mut detector = FlightLineDetector::new(gap);
+        self.scan_inputs(&mut |point| detector.add(point))?;
+        Ok(detector.lines())
+    }
+
+    /// Whether the points are given new colors, so the outputs need a point format with color.
+    fn colors_points(&self) -> bool {
+        self.colorize.is_some() || self.class_palette.is_some()
+    }
+
+    /// Hands every point of the inputs to `visit`, for what has to be known before the points are
+    /// copied.
+    fn scan_inputs(&self, visit: &mut dyn FnMut(&Point)) -> Result<(), MyError> {
+        for index in 0..self.paths.len() {
+            let mut reader = match self.open_input(index) {
+                Ok(reader) => reader,
+                Err(_) if self.on_error == ErrorPolicy::Skip => continue,
+                Err(err) => return Err(err),
+            };
+            let skip_corrupt =
+                self.max_point_errors > 0 && !reader.header().point_format().is_compressed;
+            for point in reader.points() {
+                match point {
+                    Ok(point) => visit(&point),
+                    // Corrupt points are counted when the points are copied
+                    Err(_) if skip_corrupt => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The headers of the inputs after the one at `index`, with their paths, leaving out the
+    /// inputs skipped on errors.
+    fn headers_after(&self, index: usize) -> Result<Vec<(String, Header)>, MyError> {
+        let mut headers = Vec::new();
+        for (other, path) in self.paths.iter().enumerate().skip(index + 1) {
+            match self.open_input(other) {
+                Ok(reader) => headers.push((path.clone(), reader.header().clone())),
+                Err(_) if self.on_error == ErrorPolicy::Skip => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(headers)
+    }
+
+    /// The colorizer of the points, if they are colored. Elevations span the Z bounds of `header`
+    /// and of the other inputs, but stdin, which can't be read again.
+    fn colorizer(&self, header: &Header) -> Option<Colorizer> {
+        let (source, colormap) = self.colorize?;
+        let (low, high) = match source {
+            ColorSource::Intensity => return Some(Colorizer::by_intensity(colormap)),
+            ColorSource::Elevation => self
+                .paths
+                .iter()
+                .enumerate()
+                .filter(|(_, path)| !is_stdin(path))
+                .filter_map(|(index, _)| self.open_input(index).ok())
+                .map(|reader| reader.header().bounds())
+                .fold(
+                    (header.bounds().min.z, header.bounds().max.z),
+                    |(low, high), bounds| (low.min(bounds.min.z), high.max(bounds.max.z)),
+                ),
+        };
+        Some(Colorizer::new(source, colormap, low, high))
+    }
+
+    /// The overlap of the inputs the points are cropped to, if they are. Inputs that can't be
+    /// opened are left out if they are skipped, and stdin, which can't be read again, is too.
+    fn overlap(&self) -> Result<Option<Overlap>, MyError> {
+        let Some(kind) = self.crop_to_overlap else {
+            return Ok(None);
+        };
+        let mut readers = Vec::new();
+        for (index, path) in self.paths.iter().enumerate() {
+            if is_stdin(path) {
+                continue;
+            }
+            match self.open_input(index) {
+                Ok(reader) => readers.push(reader),
+                Err(_) if self.on_error == ErrorPolicy::Skip => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let overlap = match kind {
+            OverlapKind::Bounds => {
+                let bounds: Vec<_> = readers
+                    .iter()
+                    .map(|reader| reader.header().bounds())
+                    .collect();
+                Overlap::of_bounds(&bounds)
+            }
+            OverlapKind::Cells { cell_size } => {
+                let mut inputs = Vec::new();
+                for mut reader in readers {
+                    let mut cells = HashSet::new();
+                    let skip_corrupt =
+                        self.max_point_errors > 0 && !reader.header().point_format().is_compressed;
+                    for point in reader.points() {
+                        match point {
+                            Ok(point) => {
+                                cells.insert(cell_of(cell_size, point.x, point.y));
+                            }
+                            // Corrupt points are counted when the points are copied
+                            Err(_) if skip_corrupt => {}
+                            Err(err) => return Err(err.into()),
+                        }
+                    }
+                    inputs.push(cells);
+                }
+                Overlap::of_cells(cell_size, inputs)
+            }
+        };
+        if overlap.is_empty() {
+            warn!("The inputs don't overlap, so no point is kept");
+        }
+        Ok(Some(overlap))
+    }
+
+    /// The options of the output at `path`, saying whether its extra bytes are stripped.
+    fn options_of(&self, path: &str) -> OutputOptions {
+        let options = self
+            .output_options
+            .iter()
+            .find(|(output, _)| output == path)
+            .map(|(_, options)| options.clone())
+            .unwrap_or_default();
+        match options.strip_extra_bytes() {
+            Some(_) => options,
+            None => options.with_strip_extra_bytes(self.strip_extra_bytes),
+        }
+    }
+
+    /// Whether every output strips the extra bytes, so that they can be dropped before the points
+    /// are batched.
+    fn strips_every_output(&self) -> bool {
+        self.output_paths
+            .iter()
+            .all(|path| self.options_of(path).strip_extra_bytes() == Some(true))
+    }
+
+    /// Allows existing output files to be overwritten. By default processing fails with
+    /// `MyError::OutputExists` instead.
+    pub fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Adds the points to output files that already exist rather than failing, keeping their
+    /// headers. Fails with `MyError::IncompatibleAppend` if the point format, scales or offsets of
+    /// an existing file aren't those of its output. Outputs spread over several files by
+    /// [`LasProcessor::with_max_output_points`] are created as usual. See
+    /// [`OutputWriter::append`].
+    pub fn with_append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Writes the outputs so that software older than LAS 1.4 reads them correctly: in the older
+    /// point formats, with the EVLRs that fit moved to VLRs, the coordinate system as GeoTIFF keys
+    /// and the legacy point counts set. See [`crate::legacy`].
+    pub fn with_legacy_compatible(mut self, legacy_compatible: bool) -> Self {
+        self.legacy_compatible = legacy_compatible;
+        self
+    }
+
+    /// Lets processing be stopped early through `token`. When the token is cancelled the readers
+    /// stop at the next batch boundary and the outputs are finalized with the points read so far.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Abandons an input file when its reader makes no progress for `timeout`, e.g. because a
+    /// corrupted LAZ chunk makes decompression hang. The points read from it before that are
+    /// kept, and nothing the reader sends afterwards is written. The remaining files are processed
+    /// as usual, on a new thread if the abandoned reader still holds its own.
+    pub fn with_file_timeout(mut self, timeout: Duration) -> Self {
+        self.file_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets what happens when an input file can't be read. See [`ErrorPolicy`].
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.on_error = policy;
+        self
+    }
+
+    /// Skips up to `limit` points per file that can't be decoded instead of failing the file on
+    /// the first one. The skipped points are counted and reported. Only uncompressed inputs skip
+    /// points: a LAZ decompressor can't pick up again after a point it failed on, so LAZ inputs
+    /// still fail on the first.
+    pub fn with_max_point_errors(mut self, limit: u64) -> Self {
+        self.max_point_errors = limit;
+        self
+    }
+
+    /// Sends progress updates to `observer` instead of printing them to stderr with
+    /// `ConsoleProgress`.
+    pub fn with_observer(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Reads the inputs on `threads` threads. By default one thread per core is used, minus the
+    /// ones taken by the writers. A value of 0 is treated as 1.
+    pub fn with_reader_threads(mut self, threads: usize) -> Self {
+        self.reader_threads = Some(threads.max(1));
+        self
+    }
+
+    /// Writes the outputs on `threads` threads, each owning a share of the outputs. By default
+    /// every output gets its own thread, so LAZ compression for different outputs runs in
+    /// parallel. Use fewer on machines with few cores. A value of 0 is treated as 1.
+    pub fn with_writer_threads(mut self, threads: usize) -> Self {
+        self.writer_threads = Some(threads.max(1));
+        self
+    }
+
+    /// Sends points from the readers to the writers in batches of `batch_size`. Larger batches
+    /// cost less synchronization but more memory. A value of 0 is treated as 1.
+    pub fn with_batch_size(mut self, batch_size: u64) -> Self {
+        self.vec_size = batch_size.max(1);
+        self
+    }
+
+    /// Lets up to `depth` batches wait to be written before the readers are held back. A value
+    /// of 0 is treated as 1.
+    pub fn with_channel_depth(mut self, depth: usize) -> Self {
+        self.channel_depth = depth.max(1);
+        self
+    }
+
+    /// Adjusts the batch size and channel depth while running, starting from the configured
+    /// values, to keep the writers busy without holding more points in memory than needed. The
+    /// values used at the end are reported in `ProcessingReport::tuning`.
+    pub fn with_auto_tune(mut self, auto_tune: bool) -> Self {
+        self.auto_tune = auto_tune;
+        self
+    }
+
+    /// Keeps the points held in batches, both the ones the readers are filling and the ones
+    /// waiting to be written, within about `bytes` bytes. The channel depth is lowered first and
+    /// then the batch size; when the limit is reached the readers wait for the writers to catch
+    /// up. Buffers inside the LAS/LAZ readers and writers aren't counted.
+    pub fn with_max_memory(mut self, bytes: u64) -> Self {
+        self.max_memory = Some(bytes);
+        self
+    }
+
+    /// Holds as little in memory as it can, for machines with little of it, at the cost of speed:
+    /// batches of [`LOW_MEMORY_BATCH_SIZE`] points, a channel of [`LOW_MEMORY_CHANNEL_DEPTH`]
+    /// batch, no auto-tuning, and neighbourhood filters and pipeline sorts spilling their points
+    /// to disk a megabyte at a time. Later calls to [`LasProcessor::with_batch_size`] and
+    /// [`LasProcessor::with_channel_depth`] still change those. Does nothing when `low_memory` is
+    /// false.
+    pub fn with_low_memory(mut self, low_memory: bool) -> Self {
+        if low_memory {
+            self.vec_size = LOW_MEMORY_BATCH_SIZE;
+            self.channel_depth = LOW_MEMORY_CHANNEL_DEPTH;
+            self.auto_tune = false;
+            self.spill = self.spill.with_buffer_bytes(LOW_MEMORY_SPILL_BYTES);
+        }
+        self
+    }
+
+    /// Spills the points held by neighbourhood filters and pipeline sorts to a temporary folder
+    /// made in `dir` rather than in the system's temporary folder, e.g. on a disk with more room.
+    pub fn with_spill_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.spill = self.spill.with_dir(dir);
+        self
+    }
+
+    /// Writes the points of each output sorted by `key`, holding them until every input has been
+    /// read, so the points written are only counted then. The points past the spill buffer are
+    /// sorted in runs on disk and merged, so outputs larger than memory can be sorted. Morton
+    /// order is within the bounds of the output's header, from the first input.
+    pub fn with_sort(mut self, key: SortKey) -> Self {
+        self.sort = Some(key);
+        self
+    }
+
+    /// Writes the runs of sorts as LAZ, taking less disk. See
+    /// [`SpillConfig::with_compressed_runs`].
+    pub fn with_compressed_spill(mut self, compressed: bool) -> Self {
+        self.spill = self.spill.with_compressed_runs(compressed);
+        self
+    }
+
+    /// Spreads each output over files of at most `points` points, named by
+    /// [`render_part_path`]: the file number replaces `{part}` in the output path, or is added
+    /// before the extension. Each file is reported as an output of its own. Stdout and sinks
+    /// aren't split.
+    pub fn with_max_output_points(mut self, points: u64) -> Self {
+        self.max_output_points = Some(points.max(1));
+        self
+    }
+
+    /// When there are more reader threads than inputs, splits the inputs into ranges of at least
+    /// `points` points that are read by separate threads, so that a single large input still
+    /// keeps every thread busy. Inputs that are remote or inside ZIP archives are never split.
+    pub fn with_split_size(mut self, points: u64) -> Self {
+        self.split_size = points.max(1);
+        self
+    }
+
+    /// Replaces the conditions passed to `new`, one per output, with ones that may test points
+    /// through a [`PointView`]. When none of them needs the whole point, uncompressed local inputs
+    /// are read as raw records and only the points that are kept are fully decoded.
+    pub fn with_conditions(mut self, conditions: Vec<Condition>) -> Self {
+        self.conditions = conditions;
+        self
+    }
+
+    /// Memory-maps uncompressed local LAS inputs and reads the point records straight from the
+    /// map instead of copying them through a buffer. LAZ, remote and archived inputs are read as
+    /// usual. Needs the `mmap` feature, processing fails with `MyError::FeatureNotEnabled`
+    /// without it.
+    pub fn with_mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Reads every output file back once it is written, failing the output with
+    /// `MyError::VerificationFailed` if its header doesn't count the points written or its last
+    /// point can't be decoded. See [`OutputWriter::with_verify`].
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Checks before reading any point that the outputs fit in the free space of the filesystems
+    /// they are written to, sized as if every point was kept, and fails with
+    /// `MyError::InsufficientSpace` if they may not. Disabled by default, as outputs of filtered
+    /// runs are far smaller than that. See [`crate::space::check_space`].
+    pub fn with_space_check(mut self, space_check: bool) -> Self {
+        self.space_check = space_check;
+        self
+    }
+
+    /// Sets how LAZ outputs are split into chunks. See [`LazChunking`].
+    pub fn with_laz_chunking(mut self, chunking: LazChunking) -> Self {
+        self.laz_chunking = chunking;
+        self
+    }
+
+    /// Adds `stream` as an input, for data that isn't in a file: an in-memory buffer, a
+    /// decrypting reader and so on. The input is reported under `name`. Several readers may use
+    /// the stream when the input is split, taking turns and seeking it to their own position
+    /// before each read.
+    pub fn with_stream(mut self, name: &str, stream: impl InputStream + 'static) -> Self {
+        let stream: Box<dyn InputStream> = Box::new(stream);
+        self.streams
+            .insert(self.paths.len(), Arc::new(Mutex::new(stream)));
+        self.paths.push(name.to_string());
+        self
+    }
+
+    /// Adds an output that hands the points meeting `condition` to `sink` instead of writing a
+    /// file, e.g. a [`MemoryOutput`] collecting them. The output is reported under `name`. The
+    /// sink gets the points a batch at a time, in no particular order.
+    pub fn with_sink(
+        mut self,
+        name: &str,
+        sink: impl PointSink + 'static,
+        condition: Condition,
+    ) -> Self {
+        let sink: Box<dyn PointSink> = Box::new(sink);
+        self.sinks
+            .insert(self.output_paths.len(), Arc::new(Mutex::new(sink)));
+        self.output_paths.push(name.to_string());
+        self.conditions.push(condition);
+        self
+    }
+
+    /// Picks how the work is spread over threads. See [`Backend`]. With `Backend::Rayon` the
+    /// reader thread count sets the size of the pool, which defaults to one thread per core, and
+    /// the writer thread count, channel depth and auto-tuning don't apply: a warning is logged
+    /// when auto-tuning is asked for. Inputs are split into shards of the split size whatever the
+    /// number of threads.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Splits the inputs into at most `parts` ranges of points each. Returns the ranges of each
+    /// input, or `None` for an input that is read in one go.
+    fn split_inputs(&self, parts: usize) -> Vec<Option<Vec<Range<u64>>>> {
+        (0..self.paths.len())
+            .map(|index| {
+                let source = self.source(index);
+                if parts < 2 || matches!(&source, InputSource::Path(path) if !can_split(path)) {
+                    return None;
+                }
+                // Unreadable inputs are left to fail when they are read
+                let reader = source.open_reader().ok()?;
+                let header = reader.header();
+                let ranges = split_ranges(
+                    header.number_of_points(),
+                    parts,
+                    self.split_size,
+                    chunk_alignment(header)?,
+                );
+                (ranges.len() > 1).then_some(ranges)
+            })
+            .collect()
+    }
+
+    /// Handles the files that failed since the last call according to the error policy: skipped
+    /// files are logged and added to `skipped`, and with `ErrorPolicy::Abort` the first failure is
+    /// returned.
+    fn collect_failures(
+        &self,
+        progress: &mut [FileProgress],
+        skipped: &mut Vec<String>,
+    ) -> Result<(), MyError> {
+        for (path, file) in self.paths.iter().zip(progress.iter_mut()) {
+            let Some(err) = file.error.take() else {
+                continue;
+            };
+            if self.on_error == ErrorPolicy::Abort {
+                return Err(err);
+            }
+            if !skipped.contains(path) {
+                warn!("Skipping {:?}: {}", path, err);
+                skipped.push(path.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the input at `index` is read from.
+    fn source(&self, index: usize) -> InputSource {
+        match self.streams.get(&index) {
+            Some(stream) => InputSource::Reader(Arc::clone(stream)),
+            None => InputSource::Path(self.paths[index].clone()),
+        }
+    }
+
+    /// Opens the input at `index` for its header, failing if it has waveforms that aren't
+    /// dropped.
+    fn open_input(&self, index: usize) -> Result<Reader, MyError> {
+        let reader = self.source(index).open_reader()?;
+        if reader.header().point_format().has_waveform && !self.drop_waveforms {
+            return Err(MyError::WaveformsNotSupported(self.paths[index].clone()));
+        }
+        Ok(reader)
+    }
+
+    /// The paths of the outputs written to by path, leaving out the sinks.
+    fn output_files(&self) -> impl Iterator<Item = &String> {
+        self.output_paths
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !self.sinks.contains_key(index))
+            .map(|(_, path)| path)
+    }
+
+    /// Where the output at `index` goes.
+    fn target(&self, index: usize) -> OutputTarget {
+        match self.sinks.get(&index) {
+            Some(sink) => OutputTarget::Sink(Arc::clone(sink)),
+            None => OutputTarget::Path(self.output_paths[index].clone()),
+        }
+    }
+
+    /// Checks the outputs, or the first file of each when they are spread over several files.
+    /// Sinks and streams have no file to check.
+    fn check_output_paths(&self) -> Result<(), MyError> {
+        let first_files: Vec<String> = match self.max_output_points {
+            Some(_) => self
+                .output_files()
+                .map(|path| render_part_path(path, 1))
+                .collect(),
+            None => self.output_files().cloned().collect(),
+        };
+        let input_files: Vec<String> = self
+            .paths
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !self.streams.contains_key(index))
+            .map(|(_, path)| path.clone())
+            .collect();
+        let append = self.append && self.max_output_points.is_none();
+        check_output_paths(&first_files, &input_files, self.overwrite || append)
+    }
+
+    /// Works out what `process_lidar_files` would read and write from the headers of the inputs,
+    /// without reading any points. Fails like `process_lidar_files` would on unreadable inputs
+    /// and outputs that are in the way.
+    pub fn plan(&self) -> Result<Plan, MyError> {
+        if self.mmap && !cfg!(feature = "mmap") {
+            return Err(MyError::FeatureNotEnabled(
+                "Memory-mapping inputs".to_string(),
+                "mmap",
+            ));
+        }
+        self.check_output_paths()?;
+
+        let mut inputs = Vec::new();
+        let mut skipped = Vec::new();
+        let mut sizes = None;
+        for (index, path) in self.paths.iter().enumerate() {
+            match self.open_input(index) {
+                Ok(reader) => {
+                    let header = reader.header();
+                    let mut format = *header.point_format();
+                    format = without_waveform(format)?;
+                    if self.strips_every_output() {
+                        format.extra_bytes = 0;
+                    }
+                    if self.source_tag == Some(SourceTag::ExtraByte) {
+                        format.extra_bytes += 4;
+                    }
+                    if self.colors_points() {
+                        format = with_color(format)?;
+                    }
+                    // The outputs take the header of the first readable input
+                    sizes.get_or_insert((u64::from(header.version().header_size()), format));
+                    inputs.push(FileInfo::from_header(path, header)?);
+                }
+                Err(_) if self.on_error == ErrorPolicy::Skip => skipped.push(path.clone()),
+                Err(err) => return Err(err),
+            }
+        }
+        let Some((header_size, format)) = sizes else {
+            return Err(MyError::PartialFailure(skipped));
+        };
+
+        let max_points: u64 = inputs.iter().map(|input| input.number_of_points).sum();
+        let outputs = self
+            .output_paths
+            .iter()
+            .map(|path| {
+                let compressed = path.to_lowercase().ends_with(".laz");
+                let record_length = u64::from(self.options_of(path).output_format(format)?.len());
+                let bytes = header_size + max_points * record_length;
+                Ok(PlannedOutput {
+                    path: path.clone(),
+                    compressed,
+                    max_points,
+                    parts: self
+                        .max_output_points
+                        .map_or(1, |points| max_points.div_ceil(points.max(1)).max(1)),
+                    estimated_bytes: if compressed {
+                        bytes / ASSUMED_LAZ_RATIO
+                    } else {
+                        bytes
+                    },
+                    exists: Path::new(path).is_file(),
+                })
+            })
+            .collect::<Result<_, MyError>>()?;
+        Ok(Plan {
+            inputs,
+            skipped,
+            outputs,
+        })
+    }
+
+    /// This method processes the LiDAR files. It reads points from the input files, applies the condition to each point, and writes the points that meet the condition to the output file. It returns a `Result<ProcessingReport, MyError>`. If the method completes successfully, it returns a `ProcessingReport` describing the run. If an error occurs, it returns `Err(MyError)`.
+    pub fn process_lidar_files(&self) -> Result<ProcessingReport, MyError> {
+        if self.mmap && !cfg!(feature = "mmap") {
+            return Err(MyError::FeatureNotEnabled(
+                "Memory-mapping inputs".to_string(),
+                "mmap",
+            ));
+        }
+        self.check_output_paths()?;
+        if self.space_check && self.sinks.len() < self.output_paths.len() {
+            let outputs: Vec<PlannedOutput> = self
+                .plan()?
+                .outputs
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _)| !self.sinks.contains_key(index))
+                .map(|(_, output)| output)
+                .collect();
+            check_space(&outputs)?;
+        }
+        self.observer.on_started(&self.paths, &self.output_paths);
+        let start = Instant::now();
+
+        let num_threads = num_cpus::get();
+        debug!("Number of logical cores is {}", num_threads);
+        let writer_threads = self
+            .writer_threads
+            .unwrap_or(self.output_paths.len())
+            .min(self.output_paths.len())
+            .max(1);
+        let reader_threads = self.reader_threads.unwrap_or_else(|| match self.backend {
+            // Leave a core for the loop that hands batches to the writers
+            Backend::Threads => num_threads.saturating_sub(writer_threads + 1).max(1),
+            Backend::Rayon => num_threads,
+        });
+        match self.backend {
+            Backend::Threads => debug!(
+                "Using {} reader thread(s) and {} writer thread(s)",
+                reader_threads, writer_threads
+            ),
+            Backend::Rayon => debug!("Using a rayon pool of {} thread(s)", reader_threads),
+        }
+
+        let header;
+        let extra_bytes;
+        use las::point::Format;
+        use las::Builder;
+        let mut skipped = Vec::new();
+        {
+            let mut readable = None;
+            for (index, path) in self.paths.iter().enumerate() {
+                match self.open_input(index) {
+                    Ok(reader) => {
+                        readable = Some(reader);
+                        break;
+                    }
+                    Err(err) if self.on_error == ErrorPolicy::Skip => {
+                        warn!("Skipping {:?}: {}", path, err);
+                        skipped.push(path.clone());
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            let Some(reader1) = readable else {
+                return Err(MyError::PartialFailure(skipped));
+            };
+            let old_header = reader1.header().clone();
+            // The inputs that couldn't be opened came before the first one that could
+            let old_header = match &self.vlr_merge {
+                Some(merge) => merge.header(old_header, &self.headers_after(skipped.len())?)?,
+                None => old_header,
+            };
+            extra_bytes = old_header.point_format().extra_bytes;
+            if self.strips_every_output() {
+                let format_u8 = old_header.point_format().to_u8()?;
+                debug!("Old header format : {}", format_u8);
+
+                let mut new_format = Format::new(format_u8).unwrap();
+                let mut builder = Builder::new(old_header.into_raw()?)?;
+                new_format.extra_bytes = 0;
+                builder.point_format = new_format;
+
+                header = builder.into_header().unwrap();
+            } else {
+                header = old_header;
+            }
+        }
+        let header = if self.drop_waveforms {
+            drop_waveforms(header)?
+        } else {
+            header
+        };
+        let header = match &self.gps_time {
+            Some(conversion) => {
+                let mut builder = Builder::from(header);
+                builder.gps_time_type = conversion.target();
+                builder.into_header()?
+            }
+            None => header,
+        };
+        let header = self.stamp.header(header)?;
+        let header = match self.source_tag {
+            Some(tag) => tag.header(header, &self.paths)?,
+            None => header,
+        };
+        let colorizer = self.colorizer(&header);
+        let header = if self.colors_points() {
+            let mut builder = Builder::from(header);
+            builder.point_format = with_color(builder.point_format)?;
+            builder.into_header()?
+        } else {
+            header
+        };
+
+        let parts = match self.backend {
+            Backend::Threads => reader_threads / self.paths.len().max(1),
+            // Idle threads steal the shards left, so there is no point in fewer than possible
+            Backend::Rayon => usize::MAX,
+        };
+        let ranges = self.split_inputs(parts);
+        let budget = self.max_memory.map(|max_memory| {
+            let point_size = std::mem::size_of::<Point>() as u64 + u64::from(extra_bytes);
+            let jobs: usize = ranges
+                .iter()
+                .map(|file_ranges| file_ranges.as_ref().map_or(1, Vec::len))
+                .sum();
+            let buffers = (reader_threads.min(jobs) * self.conditions.len()) as u64;
+            PointBudget::from_memory(max_memory, point_size, buffers)
+        });
+
+        // The passes over the inputs needed before the points are copied, made together
+        let mut usage = self.minimize_format.then(DimensionUsage::new);
+        let mut histogram = self.intensity_stretch.map(|_| IntensityHistogram::new());
+        let mut detector = self.flight_line_gap.map(FlightLineDetector::new);
+        if usage.is_some() || histogram.is_some() || detector.is_some() {
+            self.scan_inputs(&mut |point| {
+                if let Some(usage) = &mut usage {
+                    if self.colors_points() {
+                        // The color the point will be given counts, not the one it has
+                        let mut point = point.clone();
+                        if let Some(colorizer) = &colorizer {
+                            colorizer.apply(&mut point);
+                        }
+                        if let Some(palette) = &self.class_palette {
+                            palette.apply(&mut point);
+                        }
+                        usage.add(&point);
+                    } else {
+                        usage.add(point);
+                    }
+                }
+                if let Some(histogram) = &mut histogram {
+                    histogram.add(point.intensity);
+                }
+                if let Some(detector) = &mut detector {
+                    detector.add(point);
+                }
+            })?;
+        }
+        let intensity_stretch =
+            histogram
+                .zip(self.intensity_stretch)
+                .and_then(|(histogram, (low, high))| {
+                    IntensityStretch::from_percentiles(&histogram, low, high)
+                });
+
+        let run = Arc::new(Run {
+            start,
+            file_progress: Mutex::new(
+                ranges
+                    .iter()
+                    .map(|file_ranges| FileProgress {
+                        state: FileState::Queued,
+                        last_progress: Instant::now(),
+                        error: None,
+                        points_read: 0,
+                        corrupt_points: 0,
+                        duration: Duration::ZERO,
+                        started: None,
+                        ranges_left: file_ranges.as_ref().map_or(1, Vec::len),
+                    })
+                    .collect(),
+            ),
+            points_to_read: Mutex::new(0),
+            points_read: Mutex::new(0),
+            points_to_write: Mutex::new(0),
+            points_written: Mutex::new(0),
+            // Enough for the batches being filled and the ones waiting to be written
+            batches: BatchPool::new(reader_threads * self.conditions.len() + self.channel_depth),
+            abort: CancellationToken::new(),
+            intensity_stretch,
+            colorizer,
+            overlap: self.overlap()?,
+            flight_lines: detector.map(|detector| detector.lines()),
+        });
+        let _abort_on_exit = CancelOnDrop(run.abort.clone());
+        let inputs = (0..self.paths.len())
+            .map(|index| (self.paths[index].clone(), self.source(index)))
+            .collect();
+        let jobs = read_jobs(inputs, ranges);
+
+        let crs = Crs::from_header(&header);
+        let mut writers = Vec::new();
+        for (index, output_path) in self.output_paths.iter().enumerate() {
+            let mut options = self.options_of(output_path);
+            if let Some(usage) = &usage {
+                if options.point_format().is_none() {
+                    let format = usage.format(header.point_format())?;
+                    options = options.with_point_format(format.to_u8()?);
+                }
+                // The source attribute comes after the extra bytes of the inputs
+                if !usage.uses_extra_bytes() && self.source_tag != Some(SourceTag::ExtraByte) {
+                    options = options.with_strip_extra_bytes(true);
+                }
+            }
+            let output_header = options.header(header.clone())?;
+            let output_header = match &self.anonymization {
+                Some(anonymization) => anonymization.header(output_header)?,
+                None => output_header,
+            };
+            let output_header = if self.legacy_compatible {
+                legacy_header(output_header)?
+            } else {
+                output_header
+            };
+            let fit = output_header.point_format() != header.point_format();
+            let order = self.sort.map(|key| key.order(&output_header));
+            let writer = match (self.target(index), self.max_output_points) {
+                (OutputTarget::Sink(sink), _) => {
+                    OutputWriter::sink(output_path, sink, output_header, self.laz_chunking)
+                }
+                (OutputTarget::Path(path), Some(points)) => {
+                    OutputWriter::create_parts(&path, output_header, self.laz_chunking, points)?
+                }
+                (OutputTarget::Path(path), None) if self.append => {
+                    OutputWriter::append(&path, output_header, self.laz_chunking)?
+                }
+                (OutputTarget::Path(path), None) => {
+                    OutputWriter::create(&path, output_header, self.laz_chunking)?
+                }
+            }
+            .with_verify(self.verify)
+            .with_fitted_points(fit)
+            .with_legacy_counts(self.legacy_compatible);
+            let writer = match self.neighbor_filter {
+                Some(filter) => writer.with_neighbor_filter(filter, &self.spill)?,
+                None => writer,
+            };
+            writers.push(match order {
+                Some(order) => writer.with_sort(order, &self.spill),
+                None => writer,
+            });
+        }
+        let (writers, tuning) = match self.backend {
+            Backend::Threads => {
+                let threads = (reader_threads, writer_threads);
+                self.run_threads(&run, jobs, writers, budget, threads, &mut skipped)?
+            }
+            Backend::Rayon => {
+                self.run_rayon(&run, jobs, writers, budget, reader_threads, &mut skipped)?
+            }
+        };
+
+        {
+            let mut progress = run.file_progress.lock().map_err(|_| MyError::LockError)?;
+            self.collect_failures(&mut progress, &mut skipped)?;
+        }
+
+        let mut outputs = Vec::new();
+        for writer in writers {
+            for output in writer.finish()? {
+                debug!(
+                    "Wrote {} points to {:?}",
+                    output.points_written, output.path
+                );
+                outputs.push(output);
+            }
+        }
+        let duration = start.elapsed();
+        let files = self
+            .paths
+            .iter()
+            .zip(
+                run.file_progress
+                    .lock()
+                    .map_err(|_| MyError::LockError)?
+                    .iter(),
+            )
+            .map(|(path, file)| file_report(path, file))
+            .collect();
+        let report = ProcessingReport {
+            files,
+            outputs,
+            skipped,
+            cancelled: self.cancellation.is_cancelled(),
+            duration,
+            tuning,
+            crs,
+        };
+        self.observer.on_finished(&report);
+        Ok(report)
+    }
+
+    fn read_settings(&self) -> ReadSettings {
+        ReadSettings {
+            conditions: self.conditions.clone(),
+            cancellation: self.cancellation.clone(),
+            observer: Arc::clone(&self.observer),
+            max_point_errors: self.max_point_errors,
+            strip_extra_bytes: self.strips_every_output(),
+            gps_time: self.gps_time,
+            source_tag: self.source_tag,
+            source_ids: self.source_ids.clone(),
+            adjustments: self.adjustments.clone(),
+            transforms: self.transforms.clone(),
+            class_palette: self.class_palette.clone(),
+            anonymization: self.anonymization,
+            drop_waveforms: self.drop_waveforms,
+            read_records: self
+                .conditions
+                .iter()
+                .all(|condition| condition.dimensions() != Dimensions::ALL),
+            mmap: self.mmap,
+            check_interval: self.vec_size.max(1),
+        }
+    }
+
+    /// Abandons the files whose readers have made no progress for longer than the file timeout,
+    /// calling `on_timed_out` with the index of each, and handles the failed ones according to the
+    /// error policy. Returns `true` once every file is finished.
+    fn check_files(
+        &self,
+        run: &Run,
+        skipped: &mut Vec<String>,
+        on_timed_out: &mut dyn FnMut(usize),
+    ) -> Result<bool, MyError> {
+        let mut progress = run.file_progress.lock().map_err(|_| MyError::LockError)?;
+        if let Some(timeout) = self.file_timeout {
+            for (index, (path, file)) in self.paths.iter().zip(progress.iter_mut()).enumerate() {
+                if file.state == FileState::Reading && file.last_progress.elapsed() > timeout {
+                    warn!(
+                        "No progress reading {:?} for {:?}, abandoning it",
+                        path, timeout
+                    );
+                    file.state = FileState::TimedOut;
+                    on_timed_out(index);
+                }
+            }
+        }
+        self.collect_failures(&mut progress, skipped)?;
+        Ok(progress.iter().all(FileProgress::is_finished))
+    }
+
+    /// Runs `jobs` on a pool of `threads.0` reader threads that send their batches through a
+    /// throttled queue to `threads.1` writer threads, each owning a share of `writers`. Returns
+    /// the writers, to be finished once the run has succeeded, and the tuning used.
+    fn run_threads(
+        &self,
+        run: &Arc<Run>,
+        jobs: Vec<ReadJob>,
+        writers: Vec<OutputWriter>,
+        budget: Option<PointBudget>,
+        (reader_threads, writer_threads): (usize, usize),
+        skipped: &mut Vec<String>,
+    ) -> Result<(Vec<OutputWriter>, Tuning), MyError> {
+        let (batch_size, channel_depth) = match budget {
+            Some(budget) => budget.fit(self.vec_size, self.channel_depth),
+            None => (self.vec_size, self.channel_depth),
+        };
+        if (batch_size, channel_depth) != (self.vec_size, self.channel_depth) {
+            debug!(
+                "Using batches of {} points and a channel depth of {} to stay within the memory limit",
+                batch_size, channel_depth
+            );
+        }
+        let throttle = Arc::new(Throttle::new(batch_size, channel_depth));
+        let mut tuner = self.auto_tune.then(|| AutoTuner::new(budget));
+        let pool = ThreadPool::new(reader_threads);
+
+        // The throttle bounds the batches in flight, so the channels themselves don't need to
+        let (tx, rx) = channel::unbounded();
+        // Each job sends through a sender of its own, dropped when its file times out so that an
+        // abandoned reader can't send any more batches
+        let mut job_senders = Vec::with_capacity(jobs.len());
+        let settings = self.read_settings();
+        for job in jobs {
+            let settings = settings.clone();
+            let run = Arc::clone(run);
+            let sender = Arc::new(Mutex::new(Some(tx.clone())));
+            job_senders.push((job.index, Arc::clone(&sender)));
+            let throttle = Arc::clone(&throttle);
+
+            match &job.range {
+                Some(range) => debug!(
+                    "Starting read thread {} for {:?} {:?}",
+                    job.index, job.path, range
+                ),
+                None => debug!("Starting read thread {} for {:?}", job.index, job.path),
+            }
+            pool.execute(move || {
+                // Waits for room in the queue, giving up when the run is aborted or the file has
+                // timed out
+                let mut send = |index: usize, points_vec: Vec<Point>| -> Result<bool, MyError> {
+                    let keep_waiting = || {
+                        !run.abort.is_cancelled()
+                            && record_progress(&run.file_progress, job.index, FileState::Reading)
+                    };
+                    if !throttle.acquire(WATCHDOG_INTERVAL, keep_waiting)? {
+                        return Ok(false);
+                    }
+                    let sender = sender.lock().map_err(|_| MyError::LockError)?;
+                    let Some(tx) = sender.as_ref() else {
+                        throttle.release();
+                        return Ok(false);
+                    };
+                    *run.points_to_write.lock().map_err(|_| MyError::LockError)? +=
+                        points_vec.len() as u64;
+                    tx.send((index, points_vec))
+                        .map_err(|_| MyError::SendError)?;
+                    Ok(true)
+                };
+                read_job(&settings, &run, &job, &|| throttle.batch_size(), &mut send);
+                // The channel closes once every job has dropped its sender
+                if let Ok(mut sender) = sender.lock() {
+                    sender.take();
+                }
+            });
+        }
+        // A reader that hangs keeps its thread, which is replaced so that the files queued behind
+        // it still get read
+        let mut on_timed_out = |file_index: usize| {
+            for (index, sender) in &job_senders {
+                if *index == file_index {
+                    if let Ok(mut sender) = sender.lock() {
+                        sender.take();
+                    }
+                }
+            }
+            pool.set_num_threads(pool.max_count() + 1);
+        };
+
+        drop(tx);
+
+        // Writer threads, each owning every `writer_threads`-th output
+        let mut shares: Vec<Vec<(usize, OutputWriter)>> =
+            (0..writer_threads).map(|_| Vec::new()).collect();
+        for (index, writer) in writers.into_iter().enumerate() {
+            shares[index % writer_threads].push((index, writer));
+        }
+        // Writes the batches for one share of the outputs
+        let write_batches = |share: &mut Vec<(usize, OutputWriter)>,
+                             batches: channel::Receiver<(usize, Vec<Point>)>|
+         -> Result<(), MyError> {
+            for (index, mut points_vec) in batches {
+                let no_of_points = points_vec.len();
+                let (_, writer) = share
+                    .iter_mut()
+                    .find(|(i, _)| *i == index)
+                    .ok_or(MyError::ThreadError)?;
+                writer.write_batch(&mut points_vec)?;
+                run.batches.give_back(points_vec);
+                throttle.release();
+                *run.points_written.lock().map_err(|_| MyError::LockError)? += no_of_points as u64;
+                self.observer.on_batch_written(
+                    index,
+                    no_of_points,
+                    &run.snapshot(throttle.in_flight())?,
+                );
+            }
+            Ok(())
+        };
+        // Hands the writers back, so that they are only finished once the run has succeeded. A
+        // writer that fails stops the run, as readers waiting for the slots of the batches it
+        // will never write would otherwise wait forever
+        let write_share = |mut share: Vec<(usize, OutputWriter)>,
+                           batches: channel::Receiver<(usize, Vec<Point>)>|
+         -> Result<Vec<(usize, OutputWriter)>, MyError> {
+            let written = write_batches(&mut share, batches);
+            if written.is_err() {
+                run.abort.cancel();
+                throttle.release();
+            }
+            written.map(|()| share)
+        };
+
+        let writers = thread::scope(|scope| -> Result<Vec<OutputWriter>, MyError> {
+            let mut senders = Vec::new();
+            let mut handles = Vec::new();
+            for share in shares {
+                let (share_tx, share_rx) = channel::unbounded();
+                senders.push(share_tx);
+                let write_share = &write_share;
+                handles.push(scope.spawn(move || write_share(share, share_rx)));
+            }
+            // Fails once a writer thread has stopped, which join reports below
+            let dispatch = |index: usize, points_vec: Vec<Point>| {
+                senders[index % writer_threads]
+                    .send((index, points_vec))
+                    .is_ok()
+            };
+
+            let mut last_check = Instant::now();
+            loop {
+                match rx.recv_timeout(WATCHDOG_INTERVAL) {
+                    Ok((index, points_vec)) => {
+                        if !dispatch(index, points_vec) {
+                            run.abort.cancel();
+                            break;
+                        }
+                    }
+                    Err(channel::RecvTimeoutError::Disconnected) => break,
+                    Err(channel::RecvTimeoutError::Timeout) => {}
+                }
+                if last_check.elapsed() < WATCHDOG_INTERVAL {
+                    continue;
+                }
+                last_check = Instant::now();
+
+                // A hung reader only drops its sender once it has timed out, so don't rely on the
+                // channel closing
+                let finished = match self.check_files(run, skipped, &mut on_timed_out) {
+                    Ok(finished) => finished,
+                    Err(err) => {
+                        run.abort.cancel();
+                        return Err(err);
+                    }
+                };
+                if let Some(tuner) = tuner.as_mut() {
+                    tuner.tune(&throttle)?;
+                }
+                self.observer
+                    .on_progress(&run.snapshot(throttle.in_flight())?);
+                if finished {
+                    for (index, points_vec) in rx.try_iter() {
+                        if !dispatch(index, points_vec) {
+                            run.abort.cancel();
+                            break;
+                        }
+                    }
+                    break;
+                }
+            }
+            drop(senders);
+
+            let mut writers = Vec::new();
+            for handle in handles {
+                writers.extend(handle.join().map_err(|_| MyError::ThreadError)??);
+            }
+            writers.sort_by_key(|(index, _)| *index);
+            Ok(writers.into_iter().map(|(_, writer)| writer).collect())
+        })?;
+        Ok((writers, throttle.tuning(self.auto_tune)))
+    }
+
+    /// Runs `jobs` as tasks on a rayon pool of `threads` threads. Each thread writes the batches
+    /// it fills to `writers` itself, taking the output's lock for the length of a batch, so there
+    /// is no queue in between. Returns the writers, to be finished once the run has succeeded,
+    /// and the tuning used.
+    fn run_rayon(
+        &self,
+        run: &Arc<Run>,
+        jobs: Vec<ReadJob>,
+        writers: Vec<OutputWriter>,
+        budget: Option<PointBudget>,
+        threads: usize,
+        skipped: &mut Vec<String>,
+    ) -> Result<(Vec<OutputWriter>, Tuning), MyError> {
+        if self.auto_tune {
+            warn!(
+                "Auto-tuning doesn't apply to the rayon backend, the batch size is left as it is"
+            );
+        }
+        let batch_size = match budget {
+            Some(budget) => budget.fit(self.vec_size, 0).0,
+            None => self.vec_size,
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|_| MyError::ThreadError)?;
+        // Emptied when the run is over, so that a reader that was abandoned can't write any more
+        let sinks: Arc<Vec<Mutex<Option<OutputWriter>>>> = Arc::new(
+            writers
+                .into_iter()
+                .map(|writer| Mutex::new(Some(writer)))
+                .collect(),
+        );
+        // A failed write fails the run rather than the file being read
+        let write_error = Arc::new(Mutex::new(None));
+
+        let (done_tx, done_rx) = channel::unbounded();
+        let settings = self.read_settings();
+        // Jobs are claimed by the first thread to pick them up, so that the ones still queued
+        // behind readers that hang can be handed to a new pool
+        let jobs: Vec<(Arc<ReadJob>, Arc<AtomicBool>)> = jobs
+            .into_iter()
+            .map(|job| (Arc::new(job), Arc::new(AtomicBool::new(false))))
+            .collect();
+        let spawn = |pool: &rayon::ThreadPool, job: &Arc<ReadJob>, claimed: &Arc<AtomicBool>| {
+            let settings = settings.clone();
+            let run = Arc::clone(run);
+            let sinks = Arc::clone(&sinks);
+            let write_error = Arc::clone(&write_error);
+            let done_tx = done_tx.clone();
+            let job = Arc::clone(job);
+            let claimed = Arc::clone(claimed);
+            pool.spawn(move || {
+                if claimed.swap(true, Ordering::SeqCst) {
+                    return;
+                }
+                let write = |index: usize, mut points_vec: Vec<Point>| -> Result<bool, MyError> {
+                    let no_of_points = points_vec.len() as u64;
+                    *run.points_to_write.lock().map_err(|_| MyError::LockError)? += no_of_points;
+                    {
+                        let mut sink = sinks[index].lock().map_err(|_| MyError::LockError)?;
+                        let Some(writer) = sink.as_mut() else {
+                            return Ok(false);
+                        };
+                        // An abandoned reader can't write any more, checked under the lock of
+                        // the output so that the run can't end in between
+                        if !record_progress(&run.file_progress, job.index, FileState::Reading) {
+                            return Ok(false);
+                        }
+                        writer.write_batch(&mut points_vec)?;
+                    }
+                    run.batches.give_back(points_vec);
+                    *run.points_written.lock().map_err(|_| MyError::LockError)? += no_of_points;
+                    settings.observer.on_batch_written(
+                        index,
+                        no_of_points as usize,
+                        &run.snapshot(0)?,
+                    );
+                    Ok(true)
+                };
+                let mut send = |index: usize, points_vec: Vec<Point>| {
+                    write(index, points_vec).or_else(|err| {
+                        if let Ok(mut write_error) = write_error.lock() {
+                            write_error.get_or_insert(err);
+                        }
+                        run.abort.cancel();
+                        Ok(false)
+                    })
+                };
+                read_job(&settings, &run, &job, &|| batch_size, &mut send);
+                let _ = done_tx.send(());
+            });
+        };
+        for (job, claimed) in &jobs {
+            spawn(&pool, job, claimed);
+        }
+
+        // Pools started for the jobs queued behind readers that hang, which keep their threads
+        let mut replacements = Vec::new();
+        loop {
+            let _ = done_rx.recv_timeout(WATCHDOG_INTERVAL);
+            if let Some(err) = write_error.lock().map_err(|_| MyError::LockError)?.take() {
+                return Err(err);
+            }
+            let mut timed_out = 0;
+            let finished = self.check_files(run, skipped, &mut |_| timed_out += 1)?;
+            if timed_out > 0
+                && jobs
+                    .iter()
+                    .any(|(_, claimed)| !claimed.load(Ordering::SeqCst))
+            {
+                let replacement = rayon::ThreadPoolBuilder::new()
+                    .num_threads(timed_out)
+                    .build()
+                    .map_err(|_| MyError::ThreadError)?;
+                for (job, claimed) in &jobs {
+                    if !claimed.load(Ordering::SeqCst) {
+                        spawn(&replacement, job, claimed);
+                    }
+                }
+                replacements.push(replacement);
+            }
+            self.observer.on_progress(&run.snapshot(0)?);
+            if finished {
+                break;
+            }
+        }
+
+        let mut writers = Vec::new();
+        for sink in sinks.iter() {
+            let writer = sink.lock().map_err(|_| MyError::LockError)?.take();
+            writers.push(writer.ok_or(MyError::ThreadError)?);
+        }
+        let tuning = Tuning {
+            batch_size,
+            channel_depth: 0,
+            auto_tuned: false,
+        };
+        Ok((writers, tuning))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::NumericFilter;
+    use crate::transform::{PointFlag, Transform};
+    use las::{Builder, Point, Writer};
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    fn create_test_las_file(file_path: &str) {
+        let builder = Builder::from((1, 4)); // LAS version 1.4
+        let header = builder.into_header().unwrap();
+        let mut writer = Writer::from_path(file_path, header).unwrap();
+
+        // Create some dummy points
+        for i in 0..10 {
+            let point = las::Point {
+                x: i as f64,
+                y: i as f64,
+                z: i as f64,
+                ..Default::default()
+            };
+            writer.write_point(point).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_process_lidar_files_success() {
+        // Setup: Create a temporary directory and test files
+        let dir = tempdir().unwrap();
+        let input_file_path = dir.path().join("test.las");
+        let output_file_path = dir.path().join("output.las");
+
+        // Create a test .las file with some dummy data
+        create_test_las_file(input_file_path.to_str().unwrap());
+
+        // Initialize your struct with the test file paths and a simple condition
+        let processor = LasProcessor {
+            paths: vec![input_file_path.to_str().unwrap().to_string()],
+            output_paths: vec![output_file_path.to_str().unwrap().to_string()],
+            conditions: vec![Condition::on_point(Arc::new(|_point| true))], // Simple condition that always returns true
+            vec_size: 100000,
+            strip_extra_bytes: false,
+            gps_time: None,
+            source_tag: None,
+            source_ids: SourceIds::default(),
+            adjustments: Adjustments::default(),
+            transforms: Vec::new(),
+            anonymization: None,
+            stamp: HeaderStamp::new(),
+            vlr_merge: None,
+            drop_waveforms: false,
+            neighbor_filter: None,
+            output_options: Vec::new(),
+            minimize_format: false,
+            intensity_stretch: None,
+            colorize: None,
+            class_palette: None,
+            crop_to_overlap: None,
+            flight_line_gap: None,
+            overwrite: false,
+            append: false,
+            legacy_compatible: false,
+            cancellation: CancellationToken::new(),
+            file_timeout: None,
+            on_error: ErrorPolicy::Abort,
+            max_point_errors: 0,
+            observer: Arc::new(ConsoleProgress::new()),
+            reader_threads: None,
+            writer_threads: None,
+            channel_depth: DEFAULT_CHANNEL_DEPTH,
+            auto_tune: false,
+            max_memory: None,
+            spill: SpillConfig::default(),
+            sort: None,
+            max_output_points: None,
+            split_size: DEFAULT_SPLIT_SIZE,
+            laz_chunking: LazChunking::default(),
+            backend: Backend::default(),
+            mmap: false,
+            verify: false,
+            space_check: false,
+            streams: BTreeMap::new(),
+            sinks: BTreeMap::new(),
+        };
+
+        // Call the method and assert the result
+        let result = processor.process_lidar_files();
+        assert!(result.is_ok());
+
+        // Additional assertions to verify the output file content can be added here
+    }
+
+    #[test]
+    fn test_process_lidar_files_refuses_to_overwrite() {
+        let dir = tempdir().unwrap();
+        let input_file_path = dir.path().join("test.las");
+        let output_file_path = dir.path().join("output.las");
+        create_test_las_file(input_file_path.to_str().unwrap());
+        create_test_las_file(output_file_path.to_str().unwrap());
+        let input = input_file_path.to_str().unwrap().to_string();
+        let output = output_file_path.to_str().unwrap().to_string();
+
+        let processor = LasProcessor::new(
+            vec![input.clone()],
+            vec![output.clone()],
+            vec![Arc::new(|_point| true)],
+            false,
+        );
+        let result = processor.process_lidar_files();
+        assert!(matches!(result, Err(MyError::OutputExists(_))));
+
+        // Writing over an input is refused even when overwriting is allowed
+        let processor = LasProcessor::new(
+            vec![input.clone()],
+            vec![input],
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_overwrite(true);
+        let result = processor.process_lidar_files();
+        assert!(matches!(result, Err(MyError::OutputOverlapsInput(_))));
+    }
+
+    #[test]
+    fn test_plan() {
+        let dir = tempdir().unwrap();
+        let input_file_path = dir.path().join("test.las");
+        let output_file_path = dir.path().join("output.laz");
+        create_test_las_file(input_file_path.to_str().unwrap());
+        let input = input_file_path.to_str().unwrap().to_string();
+        let missing = dir.path().join("missing.las").to_str().unwrap().to_string();
+
+        let processor = LasProcessor::new(
+            vec![input.clone(), input.clone(), missing],
+            vec![output_file_path.to_str().unwrap().to_string()],
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_error_policy(ErrorPolicy::Skip)
+        .with_max_output_points(8);
+        let plan = processor.plan().unwrap();
+        assert_eq!(plan.inputs.len(), 2);
+        assert_eq!(plan.skipped.len(), 1);
+        assert_eq!(plan.points(), 20);
+        let output = &plan.outputs[0];
+        assert!(output.compressed && !output.exists);
+        assert_eq!((output.max_points, output.parts), (20, 3));
+        // A LAS 1.4 header, and 20 bytes for each point of format 0
+        assert_eq!(output.estimated_bytes, (375 + 20 * 20) / ASSUMED_LAZ_RATIO);
+        assert!(!output_file_path.exists());
+
+        let processor = processor.with_error_policy(ErrorPolicy::Abort);
+        assert!(processor.plan().is_err());
+    }
+
+    #[test]
+    fn test_process_lidar_files_cancelled() {
+        let dir = tempdir().unwrap();
+        let output_file_path = dir.path().join("output.las");
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let processor = LasProcessor::new(
+            vec!["tests/data/input1.las".to_string()],
+            vec![output_file_path.to_str().unwrap().to_string()],
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_cancellation(token.clone());
+        let result = processor.process_lidar_files();
+        assert!(result.is_ok());
+
+        // Nothing was read, but the output is still a valid, finalized file
+        let reader = las::Reader::from_path(&output_file_path).unwrap();
+        assert_eq!(reader.header().number_of_points(), 0);
+    }
+
+    #[test]
+    fn test_process_lidar_files_with_file_timeout() {
+        let dir = tempdir().unwrap();
+        let output_file_path = dir.path().join("output.las");
+
+        // A generous timeout doesn't get in the way of a healthy file
+        let processor = LasProcessor::new(
+            vec!["tests/data/input1.las".to_string()],
+            vec![output_file_path.to_str().unwrap().to_string()],
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_file_timeout(Duration::from_secs(60));
+        assert!(processor.process_lidar_files().is_ok());
+
+        let expected = las::Reader::from_path("tests/data/input1.las")
+            .unwrap()
+            .header()
+            .number_of_points();
+        let reader = las::Reader::from_path(&output_file_path).unwrap();
+        assert_eq!(reader.header().number_of_points(), expected);
+    }
+
+    /// Blocks the reader of the first file until it is released, like a reader stuck in a
+    /// corrupt LAZ chunk.
+    struct BlockFirstFile {
+        release: Mutex<Option<channel::Receiver<()>>>,
+    }
+
+    impl ProgressObserver for BlockFirstFile {
+        fn on_file_started(&self, index: usize, _path: &str, _number_of_points: u64) {
+            let release = match self.release.lock() {
+                Ok(mut release) if index == 0 => release.take(),
+                _ => None,
+            };
+            if let Some(release) = release {
+                let _ = release.recv();
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_lidar_files_abandons_blocked_reader() {
+        let expected = las::Reader::from_path("tests/data/input2.las")
+            .unwrap()
+            .header()
+            .number_of_points();
+        for backend in [Backend::Threads, Backend::Rayon] {
+            let dir = tempdir().unwrap();
+            let output_file_path = dir.path().join("output.las");
+            let (release_tx, release_rx) = channel::bounded(1);
+            // The second file waits behind the blocked one for the only reader thread
+            let processor = LasProcessor::new(
+                vec![
+                    "tests/data/input1.las".to_string(),
+                    "tests/data/input2.las".to_string(),
+                ],
+                vec![output_file_path.to_str().unwrap().to_string()],
+                vec![Arc::new(|_point| true)],
+                false,
+            )
+            .with_backend(backend)
+            .with_reader_threads(1)
+            .with_observer(Arc::new(BlockFirstFile {
+                release: Mutex::new(Some(release_rx)),
+            }))
+            .with_file_timeout(Duration::from_millis(300));
+            let report = processor.process_lidar_files().unwrap();
+            // The abandoned reader goes on once released, without sending anything
+            release_tx.send(()).unwrap();
+
+            assert_eq!(report.files[0].state, FileState::TimedOut, "{:?}", backend);
+            assert_eq!(report.files[1].state, FileState::Done, "{:?}", backend);
+            let reader = las::Reader::from_path(&output_file_path).unwrap();
+            assert_eq!(reader.header().number_of_points(), expected);
+        }
+    }
+
+    #[test]
+    fn test_process_lidar_files_error_policy() {
+        let dir = tempdir().unwrap();
+        let broken = dir.path().join("broken.las");
+        std::fs::write(&broken, b"not a las file").unwrap();
+        let inputs = vec![
+            "tests/data/input1.las".to_string(),
+            broken.to_str().unwrap().to_string(),
+        ];
+        let output_file_path = dir.path().join("output.las");
+        let output = output_file_path.to_str().unwrap().to_string();
+
+        let processor = LasProcessor::new(
+            inputs.clone(),
+            vec![output.clone()],
+            vec![Arc::new(|_point| true)],
+            false,
+        );
+        let result = processor.process_lidar_files();
+        assert!(matches!(result, Err(MyError::OpenError { path, .. }) if path == inputs[1]));
+        assert!(!output_file_path.exists());
+
+        let processor = LasProcessor::new(
+            inputs.clone(),
+            vec![output],
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_error_policy(ErrorPolicy::Skip);
+        let report = processor.process_lidar_files().unwrap();
+        assert_eq!(report.skipped, [inputs[1].clone()]);
+        assert_eq!(report.files[1].state, FileState::Failed);
+
+        let expected = las::Reader::from_path("tests/data/input1.las")
+            .unwrap()
+            .header()
+            .number_of_points();
+        let reader = las::Reader::from_path(&output_file_path).unwrap();
+        assert_eq!(reader.header().number_of_points(), expected);
+    }
+
+    #[test]
+    fn test_process_lidar_files_max_point_errors() {
+        let dir = tempdir().unwrap();
+        let truncated = dir.path().join("truncated.las");
+        let output_file_path = dir.path().join("output.las");
+
+        // Cut the file off halfway through its third to last point
+        let expected = {
+            let reader = las::Reader::from_path("tests/data/input1.las").unwrap();
+            let header = reader.header();
+            let record_length = u64::from(header.point_format().len());
+            let data = std::fs::read("tests/data/input1.las").unwrap();
+            let end = data.len() as u64 - 2 * record_length - record_length / 2;
+            std::fs::write(&truncated, &data[..end as usize]).unwrap();
+            header.number_of_points() - 3
+        };
+        let inputs = vec![truncated.to_str().unwrap().to_string()];
+        let outputs = vec![output_file_path.to_str().unwrap().to_string()];
+
+        let processor = LasProcessor::new(
+            inputs.clone(),
+            outputs.clone(),
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_max_point_errors(2);
+        assert!(processor.process_lidar_files().is_err());
+
+        let processor = LasProcessor::new(inputs, outputs, vec![Arc::new(|_point| true)], false)
+            .with_max_point_errors(3);
+        assert!(processor.process_lidar_files().is_ok());
+        let reader = las::Reader::from_path(&output_file_path).unwrap();
+        assert_eq!(reader.header().number_of_points(), expected);
+
+        // A LAZ input fails on its first corrupt point whatever the limit
+        let laz = dir.path().join("input.laz");
+        {
+            let mut reader = las::Reader::from_path("tests/data/input1.las").unwrap();
+            let mut header = las::Builder::from(reader.header().clone());
+            header.point_format.is_compressed = true;
+            let mut writer = las::Writer::from_path(&laz, header.into_header().unwrap()).unwrap();
+            for point in reader.points() {
+                writer.write_point(point.unwrap()).unwrap();
+            }
+            writer.close().unwrap();
+        }
+        let data = std::fs::read(&laz).unwrap();
+        let truncated = dir.path().join("truncated.laz");
+        std::fs::write(&truncated, &data[..data.len() * 3 / 4]).unwrap();
+        let processor = LasProcessor::new(
+            vec![truncated.to_str().unwrap().to_string()],
+            vec![dir.path().join("laz.las").to_str().unwrap().to_string()],
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_max_point_errors(1_000_000);
+        assert!(processor.process_lidar_files().is_err());
+    }
+
+    #[test]
+    fn test_process_lidar_files_report() {
+        let dir = tempdir().unwrap();
+        let all_path = dir.path().join("all.las");
+        let none_path = dir.path().join("none.las");
+        let processor = LasProcessor::new(
+            vec![
+                "tests/data/input1.las".to_string(),
+                "tests/data/input2.las".to_string(),
+            ],
+            vec![
+                all_path.to_str().unwrap().to_string(),
+                none_path.to_str().unwrap().to_string(),
+            ],
+            vec![Arc::new(|_point| true), Arc::new(|_point| false)],
+            false,
+        );
+
+        let report = processor.process_lidar_files().unwrap();
+
+        let reader = las::Reader::from_path(&all_path).unwrap();
+        let header = reader.header();
+        assert_eq!(report.points_read(), header.number_of_points());
+        assert_eq!(report.points_written(), header.number_of_points());
+        assert_eq!(report.outputs[0].points_written, header.number_of_points());
+        assert_eq!(report.outputs[1].points_written, 0);
+        assert!(report.outputs[1].bounds.is_none());
+        // The header stores the bounds rounded to the coordinate scale
+        let bounds = report.bounds().unwrap();
+        assert!((bounds.min.x - header.bounds().min.x).abs() < 0.01);
+        assert!((bounds.max.z - header.bounds().max.z).abs() < 0.01);
+        assert!(report
+            .files
+            .iter()
+            .all(|file| file.state == FileState::Done && file.points_read > 0));
+        assert!(report.skipped.is_empty());
+        assert!(!report.cancelled);
+    }
+
+    #[test]
+    fn test_process_lidar_files_thread_counts() {
+        let dir = tempdir().unwrap();
+        let output_paths: Vec<String> = (0..3)
+            .map(|i| {
+                let path = dir.path().join(format!("output{}.las", i));
+                path.to_str().unwrap().to_string()
+            })
+            .collect();
+        let processor = LasProcessor::new(
+            vec![
+                "tests/data/input1.las".to_string(),
+                "tests/data/input2.las".to_string(),
+            ],
+            output_paths,
+            vec![
+                Arc::new(|_point| true),
+                Arc::new(|point: &Point| point.intensity > 100),
+                Arc::new(|_point| false),
+            ],
+            false,
+        )
+        .with_reader_threads(1)
+        .with_writer_threads(2);
+
+        let report = processor.process_lidar_files().unwrap();
+
+        assert_eq!(report.outputs.len(), 3);
+        assert_eq!(report.outputs[0].points_written, report.points_read());
+        assert!(report.outputs[1].points_written <= report.points_read());
+        assert_eq!(report.outputs[2].points_written, 0);
+        for output in &report.outputs {
+            let reader = las::Reader::from_path(&output.path).unwrap();
+            assert_eq!(reader.header().number_of_points(), output.points_written);
+        }
+    }
+
+    #[test]
+    fn test_process_lidar_files_auto_tune() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.las");
+        let processor = LasProcessor::new(
+            vec![
+                "tests/data/input1.las".to_string(),
+                "tests/data/input2.las".to_string(),
+            ],
+            vec![output_path.to_str().unwrap().to_string()],
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_batch_size(1_000)
+        .with_channel_depth(1)
+        .with_auto_tune(true);
+
+        let report = processor.process_lidar_files().unwrap();
+
+        // Tiny batches through a queue of one still get every point across
+        assert_eq!(report.points_written(), report.points_read());
+        assert!(report.tuning.auto_tuned);
+        assert!(report.tuning.batch_size > 0);
+        assert!(report.tuning.channel_depth > 0);
+    }
+
+    /// A sink on a full disk.
+    struct FailingSink;
+
+    impl PointSink for FailingSink {
+        fn write_points(&mut self, _points: &mut Vec<Point>) -> Result<(), MyError> {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "no space left on device").into())
+        }
+    }
+
+    #[test]
+    fn test_process_lidar_files_writer_fails() {
+        // The readers waiting for the only slot give up once the writer has failed, and the run
+        // reports why
+        let processor = LasProcessor::new(
+            vec![
+                "tests/data/input1.las".to_string(),
+                "tests/data/input2.las".to_string(),
+            ],
+            Vec::new(),
+            Vec::new(),
+            false,
+        )
+        .with_sink("full", FailingSink, Arc::new(|_point| true))
+        .with_batch_size(1)
+        .with_channel_depth(1)
+        .with_file_timeout(Duration::from_secs(60));
+        assert!(matches!(
+            processor.process_lidar_files(),
+            Err(MyError::WriteError { path, .. }) if path == "full"
+        ));
+    }
+
+    #[test]
+    fn test_process_lidar_files_max_memory() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.las");
+        let processor = LasProcessor::new(
+            vec!["tests/data/input1.las".to_string()],
+            vec![output_path.to_str().unwrap().to_string()],
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_reader_threads(1)
+        .with_max_memory(1_000 * std::mem::size_of::<Point>() as u64);
+
+        let report = processor.process_lidar_files().unwrap();
+
+        // One batch being filled and one waiting, of at most 500 points each
+        assert_eq!(report.tuning.channel_depth, 1);
+        assert!(report.tuning.batch_size <= 500);
+        assert_eq!(report.points_written(), report.points_read());
+    }
+
+    #[test]
+    fn test_process_lidar_files_writer_thread_per_output() {
+        // Observers are told about written batches from the thread that wrote them
+        #[derive(Default)]
+        struct WriterThreads(Mutex<Vec<(usize, thread::ThreadId)>>);
+        impl ProgressObserver for WriterThreads {
+            fn on_batch_written(&self, output_index: usize, _size: usize, _progress: &Progress) {
+                let mut writers = self.0.lock().unwrap();
+                let id = thread::current().id();
+                if !writers.contains(&(output_index, id)) {
+                    writers.push((output_index, id));
+                }
+            }
+        }
+
+        let dir = tempdir().unwrap();
+        let run = |processor: LasProcessor| {
+            let writers = Arc::new(WriterThreads::default());
+            processor
+                .with_overwrite(true)
+                .with_observer(writers.clone())
+                .process_lidar_files()
+                .unwrap();
+            let mut writers = writers.0.lock().unwrap().clone();
+            writers.sort_by_key(|(index, _)| *index);
+            writers
+        };
+        let processor = || {
+            LasProcessor::new(
+                vec!["tests/data/input1.las".to_string()],
+                (0..2)
+                    .map(|i| {
+                        let path = dir.path().join(format!("output{}.las", i));
+                        path.to_str().unwrap().to_string()
+                    })
+                    .collect(),
+                vec![Arc::new(|_point| true), Arc::new(|_point| true)],
+                false,
+            )
+        };
+
+        let writers = run(processor());
+        assert_eq!(writers.len(), 2);
+        assert_ne!(writers[0].1, writers[1].1);
+
+        let writers = run(processor().with_writer_threads(1));
+        assert_eq!(writers.len(), 2);
+        assert_eq!(writers[0].1, writers[1].1);
+    }
+
+    #[test]
+    fn test_process_lidar_files_split_input() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.las");
+        let processor = LasProcessor::new(
+            vec!["tests/data/input1.las".to_string()],
+            vec![output_path.to_str().unwrap().to_string()],
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_reader_threads(4)
+        .with_split_size(100);
+
+        let report = processor.process_lidar_files().unwrap();
+
+        // Every point is read exactly once across the ranges
+        let input = las::Reader::from_path("tests/data/input1.las").unwrap();
+        let number_of_points = input.header().number_of_points();
+        assert!(number_of_points >= 400);
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].state, FileState::Done);
+        assert_eq!(report.files[0].points_read, number_of_points);
+        let mut output = las::Reader::from_path(&output_path).unwrap();
+        let mut points: Vec<_> = output
+            .points()
+            .map(|point| {
+                let point = point.unwrap();
+                (point.x.to_bits(), point.y.to_bits(), point.z.to_bits())
+            })
+            .collect();
+        let mut expected: Vec<_> = las::Reader::from_path("tests/data/input1.las")
+            .unwrap()
+            .points()
+            .map(|point| {
+                let point = point.unwrap();
+                (point.x.to_bits(), point.y.to_bits(), point.z.to_bits())
+            })
+            .collect();
+        points.sort();
+        expected.sort();
+        assert_eq!(points, expected);
+    }
+
+    #[test]
+    fn test_process_lidar_files_rayon_backend() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.las");
+        let low_path = dir.path().join("low.las");
+        let processor = LasProcessor::new(
+            vec!["tests/data/input1.las".to_string()],
+            vec![
+                output_path.to_str().unwrap().to_string(),
+                low_path.to_str().unwrap().to_string(),
+            ],
+            vec![
+                Arc::new(|_point| true),
+                Arc::new(|point| point.intensity < 100),
+            ],
+            false,
+        )
+        .with_backend(Backend::Rayon)
+        .with_reader_threads(3)
+        .with_batch_size(50)
+        .with_split_size(100);
+
+        let report = processor.process_lidar_files().unwrap();
+
+        let count = |path: &str, condition: fn(&Point) -> bool| {
+            las::Reader::from_path(path)
+                .unwrap()
+                .points()
+                .filter(|point| condition(point.as_ref().unwrap()))
+                .count() as u64
+        };
+        // The input is read in shards, every point exactly once
+        let input = "tests/data/input1.las";
+        assert_eq!(report.files[0].state, FileState::Done);
+        assert_eq!(report.files[0].points_read, count(input, |_| true));
+        let low = count(input, |point| point.intensity < 100);
+        assert_eq!(report.outputs[0].points_written, report.points_read());
+        assert_eq!(report.outputs[1].points_written, low);
+        assert_eq!(count(low_path.to_str().unwrap(), |_| true), low);
+        assert_eq!(report.tuning.channel_depth, 0);
+    }
+
+    #[test]
+    fn test_process_lidar_files_view_conditions() {
+        let dir = tempdir().unwrap();
+        let view_path = dir.path().join("view.las");
+        let point_path = dir.path().join("point.las");
+        let numeric_path = dir.path().join("numeric.las");
+        let run = |output_path: &std::path::Path, condition: Condition| {
+            LasProcessor::new(
+                vec!["tests/data/input1.las".to_string()],
+                vec![output_path.to_str().unwrap().to_string()],
+                vec![],
+                false,
+            )
+            .with_conditions(vec![condition])
+            .process_lidar_files()
+            .unwrap()
+        };
+
+        // Raw records are only decoded for the points that are kept
+        let report = run(
+            &view_path,
+            Condition::on_view(
+                Dimensions::INTENSITY,
+                Arc::new(|view| view.intensity() < 40_000),
+            ),
+        );
+        run(
+            &point_path,
+            Condition::on_point(Arc::new(|point| point.intensity < 40_000)),
+        );
+        // Built-in filters are evaluated a block of records at a time
+        run(
+            &numeric_path,
+            Condition::numeric(NumericFilter::Intensity(0..=39_999)),
+        );
+
+        let points = |path: &std::path::Path| -> Vec<Point> {
+            las::Reader::from_path(path)
+                .unwrap()
+                .points()
+                .map(|point| point.unwrap())
+                .collect()
+        };
+        let input = las::Reader::from_path("tests/data/input1.las").unwrap();
+        assert_eq!(
+            report.files[0].points_read,
+            input.header().number_of_points()
+        );
+        assert!(!points(&view_path).is_empty());
+        assert_eq!(points(&view_path), points(&point_path));
+        assert_eq!(points(&numeric_path), points(&point_path));
+    }
+
+    #[test]
+    fn test_process_lidar_files_notifies_observer() {
+        #[derive(Default)]
+        struct Recorder {
+            files_started: Mutex<Vec<usize>>,
+            files_finished: Mutex<Vec<FileState>>,
+            points_written: Mutex<u64>,
+            finished: Mutex<bool>,
+        }
+        impl ProgressObserver for Recorder {
+            fn on_file_started(&self, index: usize, _path: &str, _number_of_points: u64) {
+                self.files_started.lock().unwrap().push(index);
+            }
+            fn on_file_finished(&self, _index: usize, file: &FileReport) {
+                self.files_finished.lock().unwrap().push(file.state);
+            }
+            fn on_batch_written(&self, _output_index: usize, _size: usize, progress: &Progress) {
+                *self.points_written.lock().unwrap() = progress.points_written;
+            }
+            fn on_finished(&self, _report: &ProcessingReport) {
+                *self.finished.lock().unwrap() = true;
+            }
+        }
+
+        let dir = tempdir().unwrap();
+        let output_file_path = dir.path().join("output.las");
+        let recorder = Arc::new(Recorder::default());
+        let processor = LasProcessor::new(
+            vec![
+                "tests/data/input1.las".to_string(),
+                "tests/data/input2.las".to_string(),
+            ],
+            vec![output_file_path.to_str().unwrap().to_string()],
+            vec![Arc::new(|_point| true)],
+            false,
+        )
+        .with_observer(recorder.clone());
+
+        let report = processor.process_lidar_files().unwrap();
+
+        let mut started = recorder.files_started.lock().unwrap().clone();
+        started.sort();
+        assert_eq!(started, [0, 1]);
+        assert_eq!(
+            *recorder.files_finished.lock().unwrap(),
+            [FileState::Done, FileState::Done]
+        );
+        assert_eq!(
+            *recorder.points_written.lock().unwrap(),
+            report.points_written()
+        );
+        assert!(*recorder.finished.lock().unwrap());
+    }
+
+    #[test]
+    fn test_process_lidar_files_file_not_found() {
+        // Setup: Use a non-existent file path
+        let processor = LasProcessor {
+            paths: vec!["non_existent_file.las".to_string()],
+            output_paths: vec!["output.las".to_string()],
+            conditions: vec![Condition::on_point(Arc::new(|_point| true))],
+            vec_size: 100000,
+            strip_extra_bytes: false,
+            gps_time: None,
+            source_tag: None,
+            source_ids: SourceIds::default(),
+            adjustments: Adjustments::default(),
+            transforms: Vec::new(),
+            anonymization: None,
+            stamp: HeaderStamp::new(),
+            vlr_merge: None,
+            drop_waveforms: false,
+            neighbor_filter: None,
+            output_options: Vec::new(),
+            minimize_format: false,
+            intensity_stretch: None,
+            colorize: None,
+            class_palette: None,
+            crop_to_overlap: None,
+            flight_line_gap: None,
+            overwrite: false,
+            append: false,
+            legacy_compatible: false,
+            cancellation: CancellationToken::new(),
+            file_timeout: None,
+            on_error: ErrorPolicy::Abort,
+            max_point_errors: 0,
+            observer: Arc::new(ConsoleProgress::new()),
+            reader_threads: None,
+            writer_threads: None,
+            channel_depth: DEFAULT_CHANNEL_DEPTH,
+            auto_tune: false,
+            max_memory: None,
+            spill: SpillConfig::default(),
+            sort: None,
+            max_output_points: None,
+            split_size: DEFAULT_SPLIT_SIZE,
+            laz_chunking: LazChunking::default(),
+            backend: Backend::default(),
+            mmap: false,
+            verify: false,
+            space_check: false,
+            streams: BTreeMap::new(),
+            sinks: BTreeMap::new(),
+        };
+
+        // Call the method and assert the result
+        let result = processor.process_lidar_files();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_process_lidar_files_condition_filtering() {
+        // Setup: Create a temporary directory and test files
+        let dir = tempdir().unwrap();
+        let input_file_path = "tests/data/input1.las";
+        let output_file_path = dir.path().join("output.las");
+
+        // Create a test .las file with some dummy data
+
+        // Initialize your struct with the test file paths and a condition that filters points
+        let processor = LasProcessor {
+            paths: vec![input_file_path.to_string()],
+            output_paths: vec![output_file_path.to_str().unwrap().to_string()],
+            conditions: vec![Condition::on_point(Arc::new(|point| point.x < 5.0))], // Condition that filters points
+            vec_size: 100000,
+            strip_extra_bytes: false,
+            gps_time: None,
+            source_tag: None,
+            source_ids: SourceIds::default(),
+            adjustments: Adjustments::default(),
+            transforms: Vec::new(),
+            anonymization: None,
+            stamp: HeaderStamp::new(),
+            vlr_merge: None,
+            drop_waveforms: false,
+            neighbor_filter: None,
+            output_options: Vec::new(),
+            minimize_format: false,
+            intensity_stretch: None,
+            colorize: None,
+            class_palette: None,
+            crop_to_overlap: None,
+            flight_line_gap: None,
+            overwrite: false,
+            append: false,
+            legacy_compatible: false,
+            cancellation: CancellationToken::new(),
+            file_timeout: None,
+            on_error: ErrorPolicy::Abort,
+            max_point_errors: 0,
+            observer: Arc::new(ConsoleProgress::new()),
+            reader_threads: None,
+            writer_threads: None,
+            channel_depth: DEFAULT_CHANNEL_DEPTH,
+            auto_tune: false,
+            max_memory: None,
+            spill: SpillConfig::default(),
+            sort: None,
+            max_output_points: None,
+            split_size: DEFAULT_SPLIT_SIZE,
+            laz_chunking: LazChunking::default(),
+            backend: Backend::default(),
+            mmap: false,
+            verify: false,
+            space_check: false,
+            streams: BTreeMap::new(),
+            sinks: BTreeMap::new(),
+        };
+
+        // Call the method and assert the result
+        let result = processor.process_lidar_files();
+        assert!(result.is_ok());
+
+        // Verify that only points meeting the condition were written to the output file
+        let output_file = File::open(output_file_path).unwrap();
+        let mut reader = las::Reader::new(output_file).unwrap();
+
+        for point in reader.points() {
+            let point = point.unwrap();
+            assert!(point.x < 5.0);
+        }
+    }
+
+    #[test]
+    fn test_process_lidar_files_multiple_conditions() {
+        // Setup: Create a temporary directory and test files
+        let dir = tempdir().unwrap();
+        let input_file_path = dir.path().join("test.las");
+        let output_file_path1 = dir.path().join("output1.las");
+        let output_file_path2 = dir.path().join("output2.las");
+
+        // Create a test .las file with some dummy data
+        create_test_las_file(input_file_path.to_str().unwrap());
+
+        // Initialize your struct with the test file paths and multiple conditions
+        let processor = LasProcessor {
+            paths: vec![input_file_path.to_str().unwrap().to_string()],
+            output_paths: vec![
+                output_file_path1.to_str().unwrap().to_string(),
+                output_file_path2.to_str().unwrap().to_string(),
+            ],
+            conditions: vec![
+                Condition::on_point(Arc::new(|point: &Point| point.x < 5.0)), // Condition for output1
+                Condition::on_point(Arc::new(|point: &Point| point.x >= 5.0)), // Condition for output2
+            ],
+            vec_size: 100000,
+            strip_extra_bytes: false,
+            gps_time: None,
+            source_tag: None,
+            source_ids: SourceIds::default(),
+            adjustments: Adjustments::default(),
+            transforms: Vec::new(),
+            anonymization: None,
+            stamp: HeaderStamp::new(),
+            vlr_merge: None,
+            drop_waveforms: false,
+            neighbor_filter: None,
+            output_options: Vec::new(),
+            minimize_format: false,
+            intensity_stretch: None,
+            colorize: None,
+            class_palette: None,
+            crop_to_overlap: None,
+            flight_line_gap: None,
+            overwrite: false,
+            append: false,
+            legacy_compatible: false,
+            cancellation: CancellationToken::new(),
+            file_timeout: None,
+            on_error: ErrorPolicy::Abort,
+            max_point_errors: 0,
+            observer: Arc::new(ConsoleProgress::new()),
+            reader_threads: None,
+            writer_threads: None,
+            channel_depth: DEFAULT_CHANNEL_DEPTH,
+            auto_tune: false,
+            max_memory: None,
+            spill: SpillConfig::default(),
+            sort: None,
+            max_output_points: None,
+            split_size: DEFAULT_SPLIT_SIZE,
+            laz_chunking: LazChunking::default(),
+            backend: Backend::default(),
+            mmap: false,
+            verify: false,
+            space_check: false,
+            streams: BTreeMap::new(),
+            sinks: BTreeMap::new(),
+        };
+
+        // Call the method and assert the result
+        let result = processor.process_lidar_files();
+        assert!(result.is_ok());
+
+        // Verify that points meeting the first condition were written to the first output file
+        let output_file1 = File::open(output_file_path1).unwrap();
+        let mut reader1 = las::Reader::new(output_file1).unwrap();
+        for point in reader1.points() {
+            let point = point.unwrap();
+            assert!(point.x < 5.0);
+        }
+
+        // Verify that points meeting the second condition were written to the second output file
+        let output_file2 = File::open(output_file_path2).unwrap();
+        let mut reader2 = las::Reader::new(output_file2).unwrap();
+        for point in reader2.points() {
+            let point = point.unwrap();
+            assert!(point.x >= 5.0);
+        }
+    }
+
+    #[test]
+    fn test_process_lidar_files_empty_input() {
+        // Setup: Create a temporary directory and test files
+        let dir = tempdir().unwrap();
+        let input_file_path = dir.path().join("empty.las");
+        let output_file_path = dir.path().join("output.las");
+        // Create an empty test .las file
+        let builder = Builder::from((1, 4)); // LAS version 1.4
+        let header = builder.into_header().unwrap();
+        println!("{}", input_file_path.to_str().unwrap());
+        {
+            let _writer = Writer::from_path(input_file_path.to_str().unwrap(), header).unwrap();
+        }
+
+        // Initialize your struct with the test file paths and a simple condition
+        let processor = LasProcessor {
+            paths: vec![input_file_path.to_str().unwrap().to_string()],
+            output_paths: vec![output_file_path.to_str().unwrap().to_string()],
+            conditions: vec![Condition::on_point(Arc::new(|_point| true))], // Simple condition that always returns true
+            vec_size: 100000,
+            strip_extra_bytes: false,
+            gps_time: None,
+            source_tag: None,
+            source_ids: SourceIds::default(),
+            adjustments: Adjustments::default(),
+            transforms: Vec::new(),
+            anonymization: None,
+            stamp: HeaderStamp::new(),
+            vlr_merge: None,
+            drop_waveforms: false,
+            neighbor_filter: None,
+            output_options: Vec::new(),
+            minimize_format: false,
+            intensity_stretch: None,
+            colorize: None,
+            class_palette: None,
+            crop_to_overlap: None,
+            flight_line_gap: None,
+            overwrite: false,
+            append: false,
+            legacy_compatible: false,
+            cancellation: CancellationToken::new(),
+            file_timeout: None,
+            on_error: ErrorPolicy::Abort,
+            max_point_errors: 0,
+            observer: Arc::new(ConsoleProgress::new()),
+            reader_threads: None,
+            writer_threads: None,
+            channel_depth: DEFAULT_CHANNEL_DEPTH,
+            auto_tune: false,
+            max_memory: None,
+            spill: SpillConfig::default(),
+            sort: None,
+            max_output_points: None,
+            split_size: DEFAULT_SPLIT_SIZE,
+            laz_chunking: LazChunking::default(),
+            backend: Backend::default(),
+            mmap: false,
+            verify: false,
+            space_check: false,
+            streams: BTreeMap::new(),
+            sinks: BTreeMap::new(),
+        };
+
+        // Call the method and assert the result
+        let result = processor.process_lidar_files();
+        assert!(result.is_ok());
+
+        // Verify that the output file is also empty
+        let output_file = File::open(output_file_path).unwrap();
+        let mut reader = las::Reader::new(output_file).unwrap();
+        assert!(reader.points().next().is_none());
+    }
+
+    #[test]
+    fn test_process_lidar_files_strip_extra_bytes_after_filtering() {
+        let dir = tempdir().unwrap();
+        let input_path = dir.path().join("extra.las");
+        let output_path = dir.path().join("output.las");
+        let mut builder = Builder::from((1, 4));
+        builder.point_format.extra_bytes = 1;
+        let mut writer = Writer::from_path(&input_path, builder.into_header().unwrap()).unwrap();
+        for i in 0..10 {
+            writer
+                .write_point(Point {
+                    x: f64::from(i),
+                    extra_bytes: vec![i],
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        drop(writer);
+
+        // The conditions and transforms still see the extra bytes that are stripped
+        let processor = LasProcessor::new(
+            vec![input_path.to_str().unwrap().to_string()],
+            vec![output_path.to_str().unwrap().to_string()],
+            vec![Arc::new(|point| point.extra_bytes[0] >= 5)],
+            true,
+        )
+        .with_transform(
+            ConditionalTransform::new(Transform::SetFlag(PointFlag::Withheld, true)).when(
+                Condition::on_point(Arc::new(|point| point.extra_bytes[0] % 2 == 0)),
+            ),
+        );
+        processor.process_lidar_files().unwrap();
+
+        let mut reader = las::Reader::from_path(&output_path).unwrap();
+        assert_eq!(reader.header().point_format().extra_bytes, 0);
+        let points: Vec<(f64, bool)> = reader
+            .points()
+            .map(|point| point.unwrap())
+            .map(|point| (point.x, point.is_withheld))
+            .collect();
+        assert_eq!(
+            points,
+            [
+                (5.0, false),
+                (6.0, true),
+                (7.0, false),
+                (8.0, true),
+                (9.0, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_lidar_files_strip_extra_bytes() {
+        // Setup: Create a temporary directory and test files
+        let dir = tempdir().unwrap();
+        let input_file_path = dir.path().join("test.las");
+        let output_file_path = dir.path().join("output.las");
+
+        // Create a test .las file with some dummy data
+        create_test_las_file(input_file_path.to_str().unwrap());
+
+        // Initialize your struct with the test file paths and a simple condition
+        let processor = LasProcessor {
+            paths: vec![input_file_path.to_str().unwrap().to_string()],
+            output_paths: vec![output_file_path.to_str().unwrap().to_string()],
+            conditions: vec![Condition::on_point(Arc::new(|_point| true))], // Simple condition that always returns true
+            vec_size: 100000,
+            strip_extra_bytes: true, // Enable strip_extra_bytes
+            gps_time: None,
+            source_tag: None,
+            source_ids: SourceIds::default(),
+            adjustments: Adjustments::default(),
+            transforms: Vec::new(),
+            anonymization: None,
+            stamp: HeaderStamp::new(),
+            vlr_merge: None,
+            drop_waveforms: false,
+            neighbor_filter: None,
+            output_options: Vec::new(),
+            minimize_format: false,
+            intensity_stretch: None,
+            colorize: None,
+            class_palette: None,
+            crop_to_overlap: None,
+            flight_line_gap: None,
+            overwrite: false,
+            append: false,
+            legacy_compatible: false,
+            cancellation: CancellationToken::new(),
+            file_timeout: None,
+            on_error: ErrorPolicy::Abort,
+            max_point_errors: 0,
+            observer: Arc::new(ConsoleProgress::new()),
+            reader_threads: None,
+            writer_threads: None,
+            channel_depth: DEFAULT_CHANNEL_DEPTH,
+            auto_tune: false,
+            max_memory: None,
+            spill: SpillConfig::default(),
+            sort: None,
+            max_output_points: None,
+            split_size: DEFAULT_SPLIT_SIZE,
+            laz_chunking: LazChunking::default(),
+            backend: Backend::default(),
+            mmap: false,
+            verify: false,
+            space_check: false,
+            streams: BTreeMap::new(),
+            sinks: BTreeMap::new(),
+        };
+
+        // Call the method and assert the result
+        let result = processor.process_lidar_files();
+        assert!(result.is_ok());
+
+        // Verify that the output file has points with empty extra_bytes
+        let output_file = File::open(output_file_path).unwrap();
+        let mut reader = las::Reader::new(output_file).unwrap();
+        for point in reader.points() {
+            let point = point.unwrap();
+            assert!(point.extra_bytes.is_empty());
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Cross-sections of point clouds along a line, for engineering profiles.
+//!
+//! A [`ProfileLine`] is a polyline with a width, and keeps the points in the vertical slice of
+//! that width centred on it. Each point gets a station, the distance along the line to the foot
+//! of the perpendicular from the point, and an offset, the distance from the line, positive to
+//! its left. A point is placed on the segment it is closest to, and points beyond the ends of
+//! the line, or off the outside of a bend, fall outside the slice.
+//!
+//! [`ProfileOutput`] is a sink writing the points of the slice to CSV, sorted by station, or to a
+//! LAS/LAZ file where the station and offset replace any extra bytes of the points, described
+//! by an extra bytes VLR so that other software can read them.
+use crate::compression::LazChunking;
+use crate::errors::MyError;
+use crate::extra_bytes::{replace_attributes, Attribute, AttributeType};
+use crate::filter::{Condition, Dimensions};
+use crate::output::OutputWriter;
+use crate::sink::PointSink;
+use las::{Builder, Header, Point};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A polyline along which points are kept within half the width on either side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileLine {
+    vertices: Vec<[f64; 2]>,
+    /// The station of each vertex.
+    stations: Vec<f64>,
+    half_width: f64,
+}
+
+impl ProfileLine {
+    /// A line through `vertices` keeping the points less than `width / 2` away from it. Fails
+    /// unless there are at least two vertices, consecutive vertices differ and the width is
+    /// positive.
+    pub fn new(vertices: &[[f64; 2]], width: f64) -> Result<Self, MyError> {
+        if vertices.len() < 2 {
+            return Err(MyError::InvalidProfile(
+                "the line needs at least two vertices".to_string(),
+            ));
+        }
+        if !(width > 0.0 && width.is_finite()) {
+            return Err(MyError::InvalidProfile(format!(
+                "the width must be positive, not {}",
+                width
+            )));
+        }
+        let mut stations = vec![0.0];
+        for pair in vertices.windows(2) {
+            let length = distance(pair[0], pair[1]);
+            if length == 0.0 {
+                return Err(MyError::InvalidProfile(format!(
+                    "the vertex {},{} is repeated",
+                    pair[0][0], pair[0][1]
+                )));
+            }
+            stations.push(stations[stations.len() - 1] + length);
+        }
+        Ok(Self {
+            vertices: vertices.to_vec(),
+            stations,
+            half_width: width / 2.0,
+        })
+    }
+
+    /// The length of the line.
+    pub fn length(&self) -> f64 {
+        self.stations[self.stations.len() - 1]
+    }
+
+    /// The station and offset of `(x, y)`, if it is in the slice.
+    pub fn locate(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let mut closest: Option<(f64, f64)> = None;
+        for (index, pair) in self.vertices.windows(2).enumerate() {
+            let [start, end] = [pair[0], pair[1]];
+            let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+            let length = self.stations[index + 1] - self.stations[index];
+            let along = ((x - start[0]) * dx + (y - start[1]) * dy) / length;
+            if along < 0.0 || along > length {
+                continue;
+            }
+            let offset = (dx * (y - start[1]) - dy * (x - start[0])) / length;
+            if offset.abs() > self.half_width
+                || closest.is_some_and(|(_, closest)| closest.abs() <= offset.abs())
+            {
+                continue;
+            }
+            closest = Some((self.stations[index] + along, offset));
+        }
+        closest
+    }
+
+    /// A condition keeping the points in the slice.
+    pub fn condition(self: &Arc<Self>) -> Condition {
+        let line = Arc::clone(self);
+        Condition::on_view(
+            Dimensions::XYZ,
+            Arc::new(move |view| line.locate(view.x(), view.y()).is_some()),
+        )
+    }
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    (b[0] - a[0]).hypot(b[1] - a[1])
+}
+
+/// The header of a profile cut from files with `header`: the same, but with the station and
+/// offset as the only extra bytes.
+pub fn profile_header(header: &Header) -> Result<Header, MyError> {
+    let mut builder = Builder::from(header.clone());
+    replace_attributes(
+        &mut builder,
+        &[
+            Attribute {
+                name: "station",
+                attribute_type: AttributeType::Double,
+                description: "Distance along the line",
+            },
+            Attribute {
+                name: "offset",
+                attribute_type: AttributeType::Double,
+                description: "Distance left of the line",
+            },
+        ],
+    );
+    Ok(builder.into_header()?)
+}
+
+/// Where the points of a profile are written.
+enum Target {
+    Csv {
+        path: PathBuf,
+        rows: Vec<(f64, f64, Point)>,
+    },
+    Las(Option<Box<OutputWriter>>),
+}
+
+/// A sink writing the points of a profile with their station and offset.
+pub struct ProfileOutput {
+    line: Arc<ProfileLine>,
+    target: Target,
+}
+
+impl ProfileOutput {
+    /// A sink writing CSV to `path`, with a line per point.
+    pub fn csv(line: Arc<ProfileLine>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            line,
+            target: Target::Csv {
+                path: path.into(),
+                rows: Vec::new(),
+            },
+        }
+    }
+
+    /// A sink writing a LAS/LAZ file to `path`, with the header of the inputs, `header`, changed
+    /// by [`profile_header`].
+    pub fn las(line: Arc<ProfileLine>, path: &str, header: &Header) -> Result<Self, MyError> {
+        let writer = OutputWriter::create(path, profile_header(header)?, LazChunking::default())?;
+        Ok(Self {
+            line,
+            target: Target::Las(Some(Box::new(writer))),
+        })
+    }
+}
+
+impl PointSink for ProfileOutput {
+    fn write_points(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        match &mut self.target {
+            Target::Csv { rows, .. } => {
+                for point in points.drain(..) {
+                    if let Some((station, offset)) = self.line.locate(point.x, point.y) {
+                        rows.push((station, offset, point));
+                    }
+                }
+                Ok(())
+            }
+            Target::Las(writer) => {
+                points.retain_mut(|point| {
+                    let Some((station, offset)) = self.line.locate(point.x, point.y) else {
+                        return false;
+                    };
+                    point.extra_bytes = [station.to_le_bytes(), offset.to_le_bytes()].concat();
+                    true
+                });
+                match writer {
+                    Some(writer) => writer.write_batch(points),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), MyError> {
+        match &mut self.target {
+            Target::Csv { path, rows } => {
+                rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+                let mut writer = BufWriter::new(File::create(&*path)?);
+                writeln!(
+                    writer,
+                    "station,offset,x,y,z,intensity,classification,return_number"
+                )?;
+                for (station, offset, point) in rows.iter() {
+                    writeln!(
+                        writer,
+                        "{},{},{},{},{},{},{},{}",
+                        station,
+                        offset,
+                        point.x,
+                        point.y,
+                        point.z,
+                        point.intensity,
+                        u8::from(point.classification),
+                        point.return_number
+                    )?;
+                }
+                writer.flush()?;
+                Ok(())
+            }
+            Target::Las(writer) => match writer.take() {
+                Some(writer) => writer.finish().map(|_| ()),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extra_bytes::is_extra_bytes_vlr;
+    use crate::LasProcessor;
+
+    #[test]
+    fn test_profile() {
+        // An L: east along y = 0, then north along x = 10
+        let line = ProfileLine::new(&[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]], 2.0).unwrap();
+        assert_eq!(line.length(), 20.0);
+        assert_eq!(line.locate(4.0, 0.5), Some((4.0, 0.5)));
+        assert_eq!(line.locate(4.0, -0.5), Some((4.0, -0.5)));
+        assert_eq!(line.locate(10.5, 6.0), Some((16.0, -0.5)));
+        // Inside the bend, closer to the second segment
+        let (station, offset) = line.locate(9.8, 0.9).unwrap();
+        assert!((station - 10.9).abs() < 1e-9 && (offset - 0.2).abs() < 1e-9);
+        assert_eq!(line.locate(4.0, 1.5), None);
+        assert_eq!(line.locate(-0.5, 0.0), None);
+        assert!(ProfileLine::new(&[[0.0, 0.0]], 1.0).is_err());
+        assert!(ProfileLine::new(&[[0.0, 0.0], [0.0, 0.0]], 1.0).is_err());
+        assert!(ProfileLine::new(&[[0.0, 0.0], [1.0, 0.0]], 0.0).is_err());
+
+        // A slice across the test file, written to LAS with the station and offset
+        let input = "tests/data/input1.las";
+        let header = las::Reader::from_path(input).unwrap().header().clone();
+        let bounds = header.bounds();
+        let y = (bounds.min.y + bounds.max.y) / 2.0;
+        let line =
+            Arc::new(ProfileLine::new(&[[bounds.min.x, y], [bounds.max.x, y]], 1.0).unwrap());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profile.las");
+        let path = path.to_str().unwrap();
+        let sink = ProfileOutput::las(Arc::clone(&line), path, &header).unwrap();
+        let report = LasProcessor::new(vec![input.to_string()], Vec::new(), Vec::new(), false)
+            .with_sink("profile", sink, line.condition())
+            .with_observer(Arc::new(crate::NoProgress))
+            .process_lidar_files()
+            .unwrap();
+        let written = report.points_written();
+        assert!(written > 0 && written < header.number_of_points());
+
+        let mut reader = las::Reader::from_path(path).unwrap();
+        assert_eq!(reader.header().number_of_points(), written);
+        assert_eq!(reader.header().point_format().extra_bytes, 16);
+        assert!(reader
+            .header()
+            .vlrs()
+            .iter()
+            .any(|vlr| is_extra_bytes_vlr(vlr) && vlr.data.len() == 384));
+        for point in reader.points() {
+            let point = point.unwrap();
+            let station = f64::from_le_bytes(point.extra_bytes[..8].try_into().unwrap());
+            let offset = f64::from_le_bytes(point.extra_bytes[8..].try_into().unwrap());
+            assert!((station - (point.x - bounds.min.x)).abs() < 1e-6);
+            assert!((offset - (point.y - y)).abs() < 1e-6);
+            assert!(offset.abs() <= 0.5);
+        }
+    }
+}
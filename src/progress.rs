@@ -0,0 +1,573 @@
+//! Progress reporting for a running `LasProcessor`.
+//!
+//! The processor calls a [`ProgressObserver`] as files are started and finished, batches are
+//! written and periodically in between. [`ConsoleProgress`] is the default and logs progress
+//! lines, [`ProgressBars`] draws terminal progress bars instead and [`JsonProgress`]
+//! emits JSON lines for orchestrators. GUIs and servers can implement the trait to get structured
+//! progress.
+use crate::report::{FileReport, FileState, ProcessingReport};
+use chrono::{DateTime, Local};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::info;
+use num_format::{Locale, ToFormattedString};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A snapshot of how far processing has got.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of input files.
+    pub files_total: usize,
+    /// The number of input files that are done, failed or timed out.
+    pub files_finished: usize,
+    /// The number of points in the input files that have been started so far.
+    pub points_to_read: u64,
+    /// The number of points read so far.
+    pub points_read: u64,
+    /// The number of points that passed a filter so far.
+    pub points_to_write: u64,
+    /// The number of points written so far.
+    pub points_written: u64,
+    /// The number of batches read but not written yet.
+    pub batches_queued: usize,
+    /// The time since processing started.
+    pub elapsed: Duration,
+}
+
+/// How far back [`EtaEstimator`] looks when working out the current read rate.
+const ETA_WINDOW: Duration = Duration::from_secs(30);
+
+/// An estimate of when processing will be done.
+#[derive(Clone, Copy, Debug)]
+pub struct Eta {
+    /// The estimated time left.
+    pub remaining: Duration,
+    /// The projected completion time.
+    pub completion: DateTime<Local>,
+}
+
+/// Estimates the time left from the read rate over the last 30 seconds, so that the estimate
+/// follows changes in speed, e.g. when slow remote inputs are reached.
+///
+/// The number of points to read only grows as files are started, so with more inputs than
+/// reader threads the estimate is optimistic until the last files have been opened.
+#[derive(Debug, Default)]
+pub struct EtaEstimator {
+    samples: VecDeque<(Duration, u64)>,
+}
+
+impl EtaEstimator {
+    /// Creates an estimator without any samples.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a sample and returns the current estimate, or `None` while there isn't enough
+    /// progress to estimate the rate.
+    pub fn update(&mut self, progress: &Progress) -> Option<Eta> {
+        self.samples
+            .push_back((progress.elapsed, progress.points_read));
+        while self.samples.len() > 2
+            && progress.elapsed.saturating_sub(self.samples[0].0) > ETA_WINDOW
+        {
+            self.samples.pop_front();
+        }
+        let (first_time, first_points) = *self.samples.front()?;
+        let (last_time, last_points) = *self.samples.back()?;
+        let seconds = (last_time - first_time).as_secs_f64();
+        if last_points <= first_points || seconds <= 0.0 {
+            return None;
+        }
+        let rate = (last_points - first_points) as f64 / seconds;
+        let points_left = progress.points_to_read.saturating_sub(progress.points_read);
+        let remaining = Duration::from_secs_f64(points_left as f64 / rate);
+        Some(Eta {
+            remaining,
+            completion: Local::now() + remaining,
+        })
+    }
+}
+
+/// Receives progress updates from a running `LasProcessor`.
+///
+/// The methods may be called from the reader threads as well as the writer, so implementations
+/// need to be thread-safe and should return quickly. All methods do nothing by default.
+pub trait ProgressObserver: Send + Sync {
+    /// Called when processing starts, before any input is opened.
+    fn on_started(&self, _paths: &[String], _output_paths: &[String]) {}
+
+    /// Called when a reader starts on input `index`, which holds `number_of_points` points.
+    fn on_file_started(&self, _index: usize, _path: &str, _number_of_points: u64) {}
+
+    /// Called when a reader is done with an input, whether it succeeded or not.
+    fn on_file_finished(&self, _index: usize, _file: &FileReport) {}
+
+    /// Called after a batch of `batch_size` points was written to output `output_index`.
+    fn on_batch_written(&self, _output_index: usize, _batch_size: usize, _progress: &Progress) {}
+
+    /// Called periodically while processing, even when no batches are being written.
+    fn on_progress(&self, _progress: &Progress) {}
+
+    /// Called once the outputs have been finalized.
+    fn on_finished(&self, _report: &ProcessingReport) {}
+}
+
+/// A `ProgressObserver` that forwards every update to several others, e.g. to show progress on
+/// the console while also collecting metrics.
+#[derive(Clone, Default)]
+pub struct Observers(pub Vec<Arc<dyn ProgressObserver>>);
+
+impl ProgressObserver for Observers {
+    fn on_started(&self, paths: &[String], output_paths: &[String]) {
+        for observer in &self.0 {
+            observer.on_started(paths, output_paths);
+        }
+    }
+
+    fn on_file_started(&self, index: usize, path: &str, number_of_points: u64) {
+        for observer in &self.0 {
+            observer.on_file_started(index, path, number_of_points);
+        }
+    }
+
+    fn on_file_finished(&self, index: usize, file: &FileReport) {
+        for observer in &self.0 {
+            observer.on_file_finished(index, file);
+        }
+    }
+
+    fn on_batch_written(&self, output_index: usize, batch_size: usize, progress: &Progress) {
+        for observer in &self.0 {
+            observer.on_batch_written(output_index, batch_size, progress);
+        }
+    }
+
+    fn on_progress(&self, progress: &Progress) {
+        for observer in &self.0 {
+            observer.on_progress(progress);
+        }
+    }
+
+    fn on_finished(&self, report: &ProcessingReport) {
+        for observer in &self.0 {
+            observer.on_finished(report);
+        }
+    }
+}
+
+/// A `ProgressObserver` that does nothing, for embedders that don't want any output.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {}
+
+/// The target of the records a [`ConsoleProgress`] logs, so that their level can be set apart
+/// from that of the other records.
+pub const PROGRESS_TARGET: &str = module_path!();
+
+/// The default `ProgressObserver`, which logs per-file statistics and a progress line about once
+/// a second at info level, under [`PROGRESS_TARGET`].
+#[derive(Debug)]
+pub struct ConsoleProgress {
+    interval: Duration,
+    last: Mutex<(Instant, Progress)>,
+    eta: Mutex<EtaEstimator>,
+}
+
+impl ConsoleProgress {
+    /// Creates an observer that prints a progress line about once a second.
+    pub fn new() -> Self {
+        Self::with_interval(Duration::from_secs(1))
+    }
+
+    /// Creates an observer that prints a progress line about once every `interval`.
+    pub fn with_interval(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: Mutex::new((Instant::now(), Progress::default())),
+            eta: Mutex::new(EtaEstimator::new()),
+        }
+    }
+}
+
+impl Default for ConsoleProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressObserver for ConsoleProgress {
+    fn on_started(&self, _paths: &[String], _output_paths: &[String]) {
+        if let Ok(mut last) = self.last.lock() {
+            *last = (Instant::now(), Progress::default());
+        }
+        if let Ok(mut eta) = self.eta.lock() {
+            *eta = EtaEstimator::new();
+        }
+    }
+
+    fn on_file_started(&self, index: usize, path: &str, number_of_points: u64) {
+        info!(
+            "{}|| Started {:?} with {} points",
+            index,
+            path,
+            number_of_points.to_formatted_string(&Locale::en)
+        );
+    }
+
+    fn on_file_finished(&self, index: usize, file: &FileReport) {
+        let number_locale = &Locale::en;
+        let points_per_second = file.points_read as f64 / file.duration.as_secs_f64();
+        info!("Done : {:?} ({}, {:?})", file.path, index, file.state);
+        info!(
+            "Total points read: {}",
+            file.points_read.to_formatted_string(number_locale)
+        );
+        if file.corrupt_points > 0 {
+            info!("Corrupt points skipped: {}", file.corrupt_points);
+        }
+        info!("Time taken: {:.2?}", file.duration);
+        info!("Read speed: {:.2} points/second", points_per_second);
+    }
+
+    fn on_progress(&self, progress: &Progress) {
+        let Ok(mut last) = self.last.lock() else {
+            return;
+        };
+        let (last_time, previous) = *last;
+        let time_elapsed = last_time.elapsed();
+        if time_elapsed < self.interval {
+            return;
+        }
+        *last = (Instant::now(), *progress);
+        drop(last);
+        let eta = self
+            .eta
+            .lock()
+            .ok()
+            .and_then(|mut estimator| estimator.update(progress));
+
+        let number_locale = &Locale::en;
+        let percentage = if progress.points_to_read == 0 {
+            0.0
+        } else {
+            (progress.points_read as f64 / progress.points_to_read as f64) * 100.0
+        };
+        info!(
+            "Points read/written in the last {} second(s) and left to read/write : {} / {} / {} / {} / {:.2}%",
+            time_elapsed.as_secs(),
+            (progress.points_read - previous.points_read).to_formatted_string(number_locale),
+            (progress.points_written - previous.points_written).to_formatted_string(number_locale),
+            (progress.points_to_read - progress.points_read).to_formatted_string(number_locale),
+            (progress.points_to_write - progress.points_written).to_formatted_string(number_locale),
+            percentage
+        );
+        if let Some(eta) = eta {
+            info!(
+                "Estimated time left: {:.0?}, done at {}",
+                eta.remaining,
+                eta.completion.format("%H:%M:%S")
+            );
+        }
+    }
+
+    fn on_finished(&self, report: &ProcessingReport) {
+        let number_locale = &Locale::en;
+        if report.cancelled {
+            info!("Processing was cancelled, the outputs contain the points read so far.");
+        }
+        info!(
+            "Total points read/written: {}/{}",
+            report.points_read().to_formatted_string(number_locale),
+            report.points_written().to_formatted_string(number_locale)
+        );
+        if report.corrupt_points() > 0 {
+            info!(
+                "Corrupt points skipped: {}",
+                report.corrupt_points().to_formatted_string(number_locale)
+            );
+        }
+        if let Some(crs) = &report.crs {
+            info!("Coordinate system: {}", crs);
+        }
+        info!("Time taken: {:?}", report.duration);
+        if report.tuning.auto_tuned {
+            info!(
+                "Auto-tuned batch size: {}, channel depth: {}",
+                report.tuning.batch_size, report.tuning.channel_depth
+            );
+        }
+    }
+}
+
+/// A `ProgressObserver` that draws an overall progress bar with an ETA and a line per output with
+/// the number of points written to it.
+///
+/// The same observer can be used for several runs in a row; the bars are reset when a run starts.
+pub struct ProgressBars {
+    multi: MultiProgress,
+    overall: ProgressBar,
+    outputs: Mutex<Vec<ProgressBar>>,
+}
+
+impl ProgressBars {
+    /// Creates the bars. They are drawn on stderr once the first run starts.
+    pub fn new() -> Self {
+        let multi = MultiProgress::new();
+        let overall = multi.add(ProgressBar::new(0));
+        overall.set_style(
+            ProgressStyle::with_template(
+                "{elapsed_precise} [{wide_bar}] {percent:>3}% {human_pos}/{human_len} points read, ETA {eta}",
+            )
+            .unwrap()
+            .progress_chars("=> "),
+        );
+        Self {
+            multi,
+            overall,
+            outputs: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Default for ProgressBars {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressObserver for ProgressBars {
+    fn on_started(&self, _paths: &[String], output_paths: &[String]) {
+        self.overall.reset();
+        self.overall.set_length(0);
+        let Ok(mut outputs) = self.outputs.lock() else {
+            return;
+        };
+        for bar in outputs.drain(..) {
+            self.multi.remove(&bar);
+        }
+        for output_path in output_paths {
+            let bar = self.multi.add(ProgressBar::new_spinner());
+            bar.set_style(
+                ProgressStyle::with_template("  {msg}: {human_pos} points written").unwrap(),
+            );
+            bar.set_message(output_path.clone());
+            outputs.push(bar);
+        }
+    }
+
+    fn on_file_finished(&self, _index: usize, file: &FileReport) {
+        let _ = self.multi.println(format!(
+            "{:?}: {} points read in {:.2?} ({:?})",
+            file.path,
+            file.points_read.to_formatted_string(&Locale::en),
+            file.duration,
+            file.state
+        ));
+    }
+
+    fn on_batch_written(&self, output_index: usize, batch_size: usize, progress: &Progress) {
+        if let Some(bar) = self
+            .outputs
+            .lock()
+            .ok()
+            .and_then(|outputs| outputs.get(output_index).cloned())
+        {
+            bar.inc(batch_size as u64);
+        }
+        self.on_progress(progress);
+    }
+
+    fn on_progress(&self, progress: &Progress) {
+        self.overall.set_length(progress.points_to_read);
+        self.overall.set_position(progress.points_read);
+    }
+
+    fn on_finished(&self, report: &ProcessingReport) {
+        self.overall.finish();
+        if let Ok(outputs) = self.outputs.lock() {
+            for bar in outputs.iter() {
+                bar.finish();
+            }
+        }
+        let _ = self.multi.println(format!(
+            "Total points read/written: {}/{} in {:.2?}{}",
+            report.points_read().to_formatted_string(&Locale::en),
+            report.points_written().to_formatted_string(&Locale::en),
+            report.duration,
+            if report.cancelled { " (cancelled)" } else { "" }
+        ));
+        if let Some(crs) = &report.crs {
+            let _ = self.multi.println(format!("Coordinate system: {}", crs));
+        }
+        if report.tuning.auto_tuned {
+            let _ = self.multi.println(format!(
+                "Auto-tuned batch size: {}, channel depth: {}",
+                report.tuning.batch_size, report.tuning.channel_depth
+            ));
+        }
+    }
+}
+
+/// A `ProgressObserver` that writes one JSON object per line to stderr: a `progress` event about
+/// once a second and a `finished` event with the summary at the end, e.g.
+///
+/// ```text
+/// {"event":"progress","elapsed_secs":3.0,"eta_secs":9.1,"completion":"2024-10-01T14:03:12+02:00","points_read":1200000,...,"files":[{"path":"a.las","state":"done"}]}
+/// ```
+#[derive(Debug)]
+pub struct JsonProgress {
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+    files: Mutex<Vec<(String, FileState)>>,
+    eta: Mutex<EtaEstimator>,
+}
+
+impl JsonProgress {
+    /// Creates an observer that emits a progress event about once a second.
+    pub fn new() -> Self {
+        Self::with_interval(Duration::from_secs(1))
+    }
+
+    /// Creates an observer that emits a progress event about once every `interval`.
+    pub fn with_interval(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: Mutex::new(None),
+            files: Mutex::new(Vec::new()),
+            eta: Mutex::new(EtaEstimator::new()),
+        }
+    }
+
+    fn set_state(&self, index: usize, state: FileState) {
+        if let Ok(mut files) = self.files.lock() {
+            if let Some(file) = files.get_mut(index) {
+                file.1 = state;
+            }
+        }
+    }
+}
+
+impl Default for JsonProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressObserver for JsonProgress {
+    fn on_started(&self, paths: &[String], _output_paths: &[String]) {
+        if let Ok(mut eta) = self.eta.lock() {
+            *eta = EtaEstimator::new();
+        }
+        if let Ok(mut files) = self.files.lock() {
+            *files = paths
+                .iter()
+                .map(|path| (path.clone(), FileState::Queued))
+                .collect();
+        }
+        if let Ok(mut last) = self.last.lock() {
+            *last = Some(Instant::now());
+        }
+    }
+
+    fn on_file_started(&self, index: usize, _path: &str, _number_of_points: u64) {
+        self.set_state(index, FileState::Reading);
+    }
+
+    fn on_file_finished(&self, index: usize, file: &FileReport) {
+        self.set_state(index, file.state);
+    }
+
+    fn on_progress(&self, progress: &Progress) {
+        {
+            let Ok(mut last) = self.last.lock() else {
+                return;
+            };
+            if last.is_some_and(|last| last.elapsed() < self.interval) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+        let files: Vec<_> = self
+            .files
+            .lock()
+            .map(|files| {
+                files
+                    .iter()
+                    .map(|(path, state)| json!({"path": path, "state": state.as_str()}))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let eta = self
+            .eta
+            .lock()
+            .ok()
+            .and_then(|mut estimator| estimator.update(progress));
+        let event = json!({
+            "event": "progress",
+            "elapsed_secs": progress.elapsed.as_secs_f64(),
+            "eta_secs": eta.map(|eta| eta.remaining.as_secs_f64()),
+            "completion": eta.map(|eta| eta.completion.to_rfc3339()),
+            "files_total": progress.files_total,
+            "files_finished": progress.files_finished,
+            "points_to_read": progress.points_to_read,
+            "points_read": progress.points_read,
+            "points_to_write": progress.points_to_write,
+            "points_written": progress.points_written,
+            "files": files,
+        });
+        eprintln!("{}", event);
+    }
+
+    fn on_finished(&self, report: &ProcessingReport) {
+        let event = json!({
+            "event": "finished",
+            "elapsed_secs": report.duration.as_secs_f64(),
+            "cancelled": report.cancelled,
+            "points_read": report.points_read(),
+            "points_written": report.points_written(),
+            "corrupt_points": report.corrupt_points(),
+            "batch_size": report.tuning.batch_size,
+            "channel_depth": report.tuning.channel_depth,
+            "auto_tuned": report.tuning.auto_tuned,
+            "crs": report.crs.as_ref().map(|crs| crs.name.clone()),
+            "epsg": report.crs.as_ref().and_then(|crs| crs.epsg),
+            "skipped": report.skipped.clone(),
+            "files": report
+                .files
+                .iter()
+                .map(|file| json!({"path": file.path.clone(), "state": file.state.as_str()}))
+                .collect::<Vec<_>>(),
+        });
+        eprintln!("{}", event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eta_follows_recent_rate() {
+        let mut estimator = EtaEstimator::new();
+        let progress = |seconds: u64, points_read: u64| Progress {
+            points_to_read: 10_000,
+            points_read,
+            elapsed: Duration::from_secs(seconds),
+            ..Progress::default()
+        };
+        assert!(estimator.update(&progress(0, 0)).is_none());
+
+        // 100 points per second
+        let eta = estimator.update(&progress(10, 1_000)).unwrap();
+        assert_eq!(eta.remaining, Duration::from_secs(90));
+
+        // Once the early samples fall out of the window only the new rate of 200 points per
+        // second counts
+        estimator.update(&progress(40, 7_000));
+        let eta = estimator.update(&progress(50, 9_000)).unwrap();
+        assert_eq!(eta.remaining.as_secs(), 5);
+    }
+}
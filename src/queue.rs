@@ -0,0 +1,325 @@
+//! Running a queue of jobs, each a command line of its own, like a small batch scheduler.
+//!
+//! A queue is a folder of `.job` files, taken in name order, or lines of text, such as stdin,
+//! taken as they come so that a scheduler can keep feeding them. A job file holds one command
+//! line, which may run over several lines, and a line of text holds one job. Blank lines and lines
+//! starting with `#` are left out either way. The command lines are split into arguments the way
+//! a shell splits them, with single and double quotes and backslash escapes, but nothing is
+//! expanded.
+//!
+//! [`run_queue`] runs the jobs on up to a given number of threads, starting the next job as soon
+//! as one finishes, and returns a [`JobSummary`] for each. What running a job means is up to the
+//! caller: the CLI parses the command lines as its own and runs them.
+use crate::errors::MyError;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The extension of the job files in a queue folder.
+pub const JOB_EXTENSION: &str = "job";
+
+/// A job of a queue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueuedJob {
+    /// The file name of the job, or its line number in the queue.
+    pub name: String,
+    /// The command line of the job.
+    pub command: String,
+}
+
+impl QueuedJob {
+    /// The arguments of the command line.
+    pub fn args(&self) -> Result<Vec<String>, MyError> {
+        split_args(&self.command).map_err(|reason| MyError::InvalidJob(self.name.clone(), reason))
+    }
+}
+
+/// Splits `command` into arguments like a shell, without expanding anything. Fails on unclosed
+/// quotes and on a backslash at the end.
+pub fn split_args(command: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    // The argument being read, if one has started, which an empty pair of quotes does
+    let mut arg: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(arg.take()),
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err("unclosed single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => arg.push(c),
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => return Err("unclosed double quote".to_string()),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err("unclosed double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => arg.get_or_insert_with(String::new).push(c),
+                None => return Err("backslash at the end".to_string()),
+            },
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// The lines of a job definition that aren't blank or comments, trimmed.
+fn job_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// The jobs of the `.job` files in `dir`, in name order, each named after its file.
+pub fn read_job_dir(dir: &Path) -> Result<Vec<QueuedJob>, MyError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_job = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case(JOB_EXTENSION));
+        if is_job && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let text = fs::read_to_string(&path)?;
+            Ok(QueuedJob {
+                name: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                command: job_lines(&text).collect::<Vec<_>>().join(" "),
+            })
+        })
+        .collect()
+}
+
+/// The jobs on the lines of `reader`, a job per line, named by line number. The lines are read as
+/// the jobs are taken, so a queue on stdin runs until stdin is closed.
+pub fn read_job_lines(reader: impl BufRead) -> impl Iterator<Item = Result<QueuedJob, MyError>> {
+    reader
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| match line {
+            Ok(line) => job_lines(&line).next().map(|command| {
+                Ok(QueuedJob {
+                    name: format!("line {}", index + 1),
+                    command: command.to_string(),
+                })
+            }),
+            Err(err) => Some(Err(err.into())),
+        })
+}
+
+/// How a job of a queue went.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JobSummary {
+    /// The name of the job.
+    pub name: String,
+    /// How long the job ran for.
+    pub elapsed: Duration,
+    /// Why the job failed, or `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+impl JobSummary {
+    /// Whether the job succeeded.
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl Display for JobSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            None => write!(f, "{}: done in {:.1?}", self.name, self.elapsed),
+            Some(error) => write!(
+                f,
+                "{}: failed after {:.1?}: {}",
+                self.name,
+                self.elapsed,
+                error.trim_end()
+            ),
+        }
+    }
+}
+
+/// Runs the `jobs` with `run`, on up to `concurrency` threads at once, calling `on_finished` with
+/// the summary of each job as it finishes. With `stop_on_error`, no job is started once one has
+/// failed. Returns the summaries of the jobs run, in the order they were queued, or the error
+/// reading the queue, once the jobs started have finished.
+pub fn run_queue<I, F, R>(
+    jobs: I,
+    concurrency: usize,
+    stop_on_error: bool,
+    run: F,
+    on_finished: R,
+) -> Result<Vec<JobSummary>, MyError>
+where
+    I: Iterator<Item = Result<QueuedJob, MyError>> + Send,
+    F: Fn(&QueuedJob) -> Result<(), MyError> + Sync,
+    R: Fn(&JobSummary) + Sync,
+{
+    let queue = Mutex::new(jobs.enumerate());
+    let summaries = Mutex::new(Vec::new());
+    let read_error = Mutex::new(None);
+    let stop = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency.max(1) {
+            scope.spawn(|| {
+                while !stop.load(Ordering::Relaxed) {
+                    let next = match queue.lock() {
+                        Ok(mut queue) => queue.next(),
+                        Err(_) => None,
+                    };
+                    let (index, job) = match next {
+                        Some((index, Ok(job))) => (index, job),
+                        Some((_, Err(err))) => {
+                            stop.store(true, Ordering::Relaxed);
+                            if let Ok(mut read_error) = read_error.lock() {
+                                read_error.get_or_insert(err);
+                            }
+                            break;
+                        }
+                        None => break,
+                    };
+                    let start = Instant::now();
+                    let result = run(&job);
+                    let summary = JobSummary {
+                        name: job.name,
+                        elapsed: start.elapsed(),
+                        error: result.err().map(|err| err.to_string()),
+                    };
+                    if stop_on_error && !summary.succeeded() {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    on_finished(&summary);
+                    if let Ok(mut summaries) = summaries.lock() {
+                        summaries.push((index, summary));
+                    }
+                }
+            });
+        }
+    });
+    if let Some(err) = read_error.into_inner().map_err(|_| MyError::LockError)? {
+        return Err(err);
+    }
+    let mut summaries = summaries.into_inner().map_err(|_| MyError::LockError)?;
+    summaries.sort_by_key(|(index, _)| *index);
+    Ok(summaries.into_iter().map(|(_, summary)| summary).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args(r#"trim --input "a b.las" --output 'c d.laz'  --filter x\ y "" e"\"f""#)
+                .unwrap(),
+            ["trim", "--input", "a b.las", "--output", "c d.laz", "--filter", "x y", "", "e\"f"]
+        );
+        assert!(split_args("trim 'open").is_err());
+        assert!(split_args("trim \\").is_err());
+    }
+
+    #[test]
+    fn test_read_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("b.job"),
+            "# second\ninfo\n  --input b.las\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("a.job"), "info --input a.las").unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a job").unwrap();
+        let jobs = read_job_dir(dir.path()).unwrap();
+        let commands: Vec<(&str, &str)> = jobs
+            .iter()
+            .map(|job| (job.name.as_str(), job.command.as_str()))
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                ("a.job", "info --input a.las"),
+                ("b.job", "info --input b.las")
+            ]
+        );
+
+        let jobs: Vec<QueuedJob> = read_job_lines(Cursor::new("info a\n\n# skipped\ninfo b\n"))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[1].name, "line 4");
+        assert_eq!(jobs[1].args().unwrap(), ["info", "b"]);
+    }
+
+    #[test]
+    fn test_run_queue() {
+        let jobs: Vec<QueuedJob> = (0..8)
+            .map(|index| QueuedJob {
+                name: index.to_string(),
+                command: if index == 5 { "fail" } else { "ok" }.to_string(),
+            })
+            .collect();
+        let run = |job: &QueuedJob| match job.command.as_str() {
+            "ok" => Ok(()),
+            _ => Err(MyError::InvalidJob(job.name.clone(), "failed".to_string())),
+        };
+        let finished = AtomicUsize::new(0);
+        let summaries = run_queue(jobs.clone().into_iter().map(Ok), 3, false, run, |_| {
+            finished.fetch_add(1, Ordering::Relaxed);
+        })
+        .unwrap();
+        assert_eq!(finished.into_inner(), 8);
+        let names: Vec<&str> = summaries
+            .iter()
+            .map(|summary| summary.name.as_str())
+            .collect();
+        assert_eq!(names, ["0", "1", "2", "3", "4", "5", "6", "7"]);
+        assert_eq!(
+            summaries
+                .iter()
+                .filter(|summary| !summary.succeeded())
+                .count(),
+            1
+        );
+
+        // Run one at a time, nothing is started after the failure
+        let summaries = run_queue(jobs.into_iter().map(Ok), 1, true, run, |_| {}).unwrap();
+        assert_eq!(summaries.len(), 6);
+        assert!(summaries[5].to_string().starts_with("5: failed after"));
+    }
+}
@@ -0,0 +1,518 @@
+//! Grids of values over the XY plane, written as GeoTIFFs or CSV.
+//!
+//! Cells are squares aligned to multiples of the cell size, like tiles, and a point falls in the
+//! cell whose lower edges it is on or above. Rasters are written as uncompressed 32-bit float
+//! TIFFs in a single strip, with the bands of a pixel next to each other, and placed with a tie
+//! point and pixel scale. When the input has a GeoTIFF key directory, as LAS files up to 1.3 do,
+//! the keys are copied so the raster is in the same coordinate system. Otherwise the raster only
+//! says that it is projected, and readers may have to be told which coordinate system it is in.
+use crate::errors::MyError;
+use crate::input::{is_stdin, open_reader};
+use las::{Header, Vlr};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// The value of the cells left without one, in the rasters that mark such cells as no data.
+pub const NODATA: f32 = -9999.0;
+
+/// Returns `true` if `path` has a .tif or .tiff extension.
+pub fn is_raster_path(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    path.ends_with(".tif") || path.ends_with(".tiff")
+}
+
+/// Returns `true` if `path` has a .csv extension.
+pub fn is_csv_path(path: &str) -> bool {
+    path.to_ascii_lowercase().ends_with(".csv")
+}
+
+/// The column and row of the cell holding `(x, y)`, counting up from the origin.
+pub fn cell_of(x: f64, y: f64, cell_size: f64) -> (i64, i64) {
+    (
+        (x / cell_size).floor() as i64,
+        (y / cell_size).floor() as i64,
+    )
+}
+
+/// The GeoTIFF keys describing a coordinate system, as stored in the `LASF_Projection` VLRs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GeoKeys {
+    /// The GeoKeyDirectory, record 34735.
+    pub directory: Vec<u16>,
+    /// The GeoDoubleParams, record 34736.
+    pub doubles: Vec<f64>,
+    /// The GeoAsciiParams, record 34737.
+    pub ascii: String,
+}
+
+impl GeoKeys {
+    /// The keys in the VLRs of `header`, if it has a key directory.
+    pub fn from_header(header: &Header) -> Option<Self> {
+        Self::from_vlrs(header.all_vlrs())
+    }
+
+    /// The keys in `vlrs`, if they have a key directory.
+    pub fn from_vlrs<'a>(vlrs: impl IntoIterator<Item = &'a Vlr>) -> Option<Self> {
+        let vlrs: Vec<&Vlr> = vlrs.into_iter().collect();
+        let record = |id: u16| {
+            vlrs.iter()
+                .find(|vlr| vlr.user_id == "LASF_Projection" && vlr.record_id == id)
+                .map(|vlr| vlr.data.as_slice())
+        };
+        let directory: Vec<u16> = record(34735)?
+            .chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        if directory.len() < 4 {
+            return None;
+        }
+        let doubles = record(34736)
+            .unwrap_or_default()
+            .chunks_exact(8)
+            .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+            .collect();
+        let ascii = record(34737)
+            .map(|bytes| {
+                String::from_utf8_lossy(bytes)
+                    .trim_end_matches('\0')
+                    .to_string()
+            })
+            .unwrap_or_default();
+        Some(Self {
+            directory,
+            doubles,
+            ascii,
+        })
+    }
+
+    /// The `LASF_Projection` VLRs holding the keys, leaving out the empty ones.
+    pub fn vlrs(&self) -> Vec<Vlr> {
+        let vlr = |record_id: u16, description: &str, data: Vec<u8>| Vlr {
+            user_id: "LASF_Projection".to_string(),
+            record_id,
+            description: description.to_string(),
+            data,
+        };
+        let mut vlrs = vec![vlr(
+            34735,
+            "GeoKeyDirectoryTag",
+            self.directory
+                .iter()
+                .flat_map(|key| key.to_le_bytes())
+                .collect(),
+        )];
+        if !self.doubles.is_empty() {
+            vlrs.push(vlr(
+                34736,
+                "GeoDoubleParamsTag",
+                self.doubles
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect(),
+            ));
+        }
+        if !self.ascii.is_empty() {
+            let mut ascii = self.ascii.clone().into_bytes();
+            ascii.push(0);
+            vlrs.push(vlr(34737, "GeoAsciiParamsTag", ascii));
+        }
+        vlrs
+    }
+
+    /// The keys of the first of `paths`, if it can be opened and has a key directory. Stdin isn't
+    /// read, as its points would be lost.
+    pub fn from_inputs(paths: &[String]) -> Option<Self> {
+        let path = paths.first().filter(|path| !is_stdin(path))?;
+        Self::from_header(open_reader(path).ok()?.header())
+    }
+}
+
+/// A grid of values, row by row from the top, with the values of the bands of each cell next to
+/// each other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Raster {
+    /// The X coordinate of the left edge of the grid.
+    pub min_x: f64,
+    /// The Y coordinate of the top edge of the grid.
+    pub max_y: f64,
+    pub cell_size: f64,
+    pub columns: usize,
+    pub rows: usize,
+    /// The names of the bands, or a single empty name for a raster with one band.
+    pub bands: Vec<String>,
+    pub values: Vec<f32>,
+    /// The value of cells that have none.
+    pub nodata: Option<f32>,
+    pub geo_keys: Option<GeoKeys>,
+}
+
+impl Raster {
+    /// The smallest raster holding the cells of `cells`, keyed by column and row as returned by
+    /// [`cell_of`]. The other cells are set to `fill`. Fails if the raster would be too large for
+    /// a TIFF.
+    pub fn from_cells(
+        cell_size: f64,
+        cells: &HashMap<(i64, i64), f32>,
+        fill: f32,
+    ) -> Result<Self, MyError> {
+        let cells = cells
+            .iter()
+            .map(|(&cell, value)| (cell, std::slice::from_ref(value)));
+        Self::from_band_cells(cell_size, &[String::new()], cells, fill)
+    }
+
+    /// The smallest raster with the named `bands` holding `cells`, given with a value for each
+    /// band. The other cells are set to `fill` in every band.
+    pub fn from_band_cells<'a>(
+        cell_size: f64,
+        bands: &[String],
+        cells: impl Iterator<Item = ((i64, i64), &'a [f32])> + Clone,
+        fill: f32,
+    ) -> Result<Self, MyError> {
+        let mut raster = Self {
+            min_x: 0.0,
+            max_y: 0.0,
+            cell_size,
+            columns: 0,
+            rows: 0,
+            bands: bands.to_vec(),
+            values: Vec::new(),
+            nodata: None,
+            geo_keys: None,
+        };
+        let keys = cells.clone().map(|(cell, _)| cell);
+        let (Some(min_column), Some(max_column), Some(min_row), Some(max_row)) = (
+            keys.clone().map(|cell| cell.0).min(),
+            keys.clone().map(|cell| cell.0).max(),
+            keys.clone().map(|cell| cell.1).min(),
+            keys.map(|cell| cell.1).max(),
+        ) else {
+            return Ok(raster);
+        };
+        let columns = (max_column - min_column + 1) as u64;
+        let rows = (max_row - min_row + 1) as u64;
+        let bytes = columns
+            .saturating_mul(rows)
+            .saturating_mul(bands.len() as u64 * 4);
+        if bytes > u64::from(u32::MAX) / 2 {
+            return Err(MyError::RasterTooLarge(columns, rows));
+        }
+        let (columns, rows) = (columns as usize, rows as usize);
+        let mut values = vec![fill; columns * rows * bands.len()];
+        for ((column, row), cell_values) in cells {
+            let index = (max_row - row) as usize * columns + (column - min_column) as usize;
+            let start = index * bands.len();
+            values[start..start + bands.len()].copy_from_slice(cell_values);
+        }
+        raster.min_x = min_column as f64 * cell_size;
+        raster.max_y = (max_row + 1) as f64 * cell_size;
+        raster.columns = columns;
+        raster.rows = rows;
+        raster.values = values;
+        Ok(raster)
+    }
+
+    /// Marks the cells set to `nodata` as having no value.
+    pub fn with_nodata(mut self, nodata: f32) -> Self {
+        self.nodata = Some(nodata);
+        self
+    }
+
+    /// Places the raster in the coordinate system described by `geo_keys`.
+    pub fn with_geo_keys(mut self, geo_keys: Option<GeoKeys>) -> Self {
+        self.geo_keys = geo_keys;
+        self
+    }
+
+    /// The value of the first band of the cell holding `(x, y)`, if it is in the raster.
+    pub fn value_at(&self, x: f64, y: f64) -> Option<f32> {
+        self.values_at(x, y).map(|values| values[0])
+    }
+
+    /// The values of the bands of the cell holding `(x, y)`, if it is in the raster.
+    pub fn values_at(&self, x: f64, y: f64) -> Option<&[f32]> {
+        let column = ((x - self.min_x) / self.cell_size).floor();
+        let row = ((self.max_y - y) / self.cell_size).ceil() - 1.0;
+        if column < 0.0 || row < 0.0 {
+            return None;
+        }
+        let (column, row) = (column as usize, row as usize);
+        if column >= self.columns || row >= self.rows {
+            return None;
+        }
+        let start = (row * self.columns + column) * self.bands.len();
+        Some(&self.values[start..start + self.bands.len()])
+    }
+
+    /// Writes the raster to `path` as a GeoTIFF.
+    pub fn write_geotiff(&self, path: &Path) -> Result<(), MyError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_tiff(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the cells that have a value in some band to `path` as CSV, with the coordinates of
+    /// their centre and the value of each band, row by row from the top.
+    pub fn write_csv(&self, path: &Path) -> Result<(), MyError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "x,y,{}", self.bands.join(","))?;
+        for (index, values) in self.values.chunks_exact(self.bands.len()).enumerate() {
+            if values.iter().all(|&value| Some(value) == self.nodata) {
+                continue;
+            }
+            let (row, column) = (index / self.columns, index % self.columns);
+            let x = self.min_x + (column as f64 + 0.5) * self.cell_size;
+            let y = self.max_y - (row as f64 + 0.5) * self.cell_size;
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            writeln!(writer, "{},{},{}", x, y, values.join(","))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the raster as a little-endian TIFF: the header, the pixels, the tag values too
+    /// large to fit in their entries, then the image file directory.
+    fn write_tiff(&self, writer: &mut impl Write) -> Result<(), MyError> {
+        let image_bytes = (self.values.len() * 4) as u32;
+        let (columns, rows) = (self.columns as u32, self.rows as u32);
+        let bands = self.bands.len();
+        let mut entries = vec![
+            Entry::long(256, columns),
+            Entry::long(257, rows),
+            Entry::short(258, &vec![32; bands]),
+            // No compression, black is zero
+            Entry::short(259, &[1]),
+            Entry::short(262, &[1]),
+            Entry::long(273, HEADER_SIZE),
+            Entry::short(277, &[bands as u16]),
+            Entry::long(278, rows.max(1)),
+            Entry::long(279, image_bytes),
+            // The bands of a pixel next to each other
+            Entry::short(284, &[1]),
+        ];
+        if bands > 1 {
+            // The bands after the first are of no particular kind
+            entries.push(Entry::short(338, &vec![0; bands - 1]));
+        }
+        entries.extend([
+            // IEEE floating point samples
+            Entry::short(339, &vec![3; bands]),
+            Entry::double(33550, &[self.cell_size, self.cell_size, 0.0]),
+            Entry::double(33922, &[0.0, 0.0, 0.0, self.min_x, self.max_y, 0.0]),
+        ]);
+        match &self.geo_keys {
+            Some(geo_keys) => {
+                entries.push(Entry::short(34735, &geo_keys.directory));
+                if !geo_keys.doubles.is_empty() {
+                    entries.push(Entry::double(34736, &geo_keys.doubles));
+                }
+                if !geo_keys.ascii.is_empty() {
+                    entries.push(Entry::ascii(34737, &geo_keys.ascii));
+                }
+            }
+            // A projected model with pixels covering an area, in no coordinate system in
+            // particular
+            None => entries.push(Entry::short(
+                34735,
+                &[1, 1, 0, 2, 1024, 0, 1, 1, 1025, 0, 1, 1],
+            )),
+        }
+        if self.bands.iter().any(|name| !name.is_empty()) {
+            // The names of the bands, as GDAL stores them
+            let items: String = self
+                .bands
+                .iter()
+                .enumerate()
+                .map(|(band, name)| {
+                    format!(
+                        "<Item name=\"DESCRIPTION\" sample=\"{}\" role=\"description\">{}</Item>",
+                        band, name
+                    )
+                })
+                .collect();
+            entries.push(Entry::ascii(
+                42112,
+                &format!("<GDALMetadata>{}</GDALMetadata>", items),
+            ));
+        }
+        if let Some(nodata) = self.nodata {
+            // Read by GDAL and most GIS software
+            entries.push(Entry::ascii(42113, &nodata.to_string()));
+        }
+
+        let mut offset = HEADER_SIZE + image_bytes;
+        let mut extra = Vec::new();
+        for entry in &mut entries {
+            if entry.bytes.len() > 4 {
+                entry.offset = Some(offset);
+                offset += entry.bytes.len() as u32;
+                extra.extend_from_slice(&entry.bytes);
+                if extra.len() % 2 == 1 {
+                    extra.push(0);
+                    offset += 1;
+                }
+            }
+        }
+
+        writer.write_all(b"II")?;
+        writer.write_all(&42u16.to_le_bytes())?;
+        writer.write_all(&offset.to_le_bytes())?;
+        for value in &self.values {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&extra)?;
+        writer.write_all(&(entries.len() as u16).to_le_bytes())?;
+        for entry in &entries {
+            writer.write_all(&entry.tag.to_le_bytes())?;
+            writer.write_all(&entry.field_type.to_le_bytes())?;
+            writer.write_all(&entry.count.to_le_bytes())?;
+            match entry.offset {
+                Some(offset) => writer.write_all(&offset.to_le_bytes())?,
+                None => {
+                    let mut value = [0; 4];
+                    value[..entry.bytes.len()].copy_from_slice(&entry.bytes);
+                    writer.write_all(&value)?;
+                }
+            }
+        }
+        // No further image
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// The size of the TIFF header, after which the pixels start.
+const HEADER_SIZE: u32 = 8;
+
+/// An entry of a TIFF image file directory.
+struct Entry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    bytes: Vec<u8>,
+    /// Where the value is written, if it doesn't fit in the entry.
+    offset: Option<u32>,
+}
+
+impl Entry {
+    fn new(tag: u16, field_type: u16, count: usize, bytes: Vec<u8>) -> Self {
+        Self {
+            tag,
+            field_type,
+            count: count as u32,
+            bytes,
+            offset: None,
+        }
+    }
+
+    fn short(tag: u16, values: &[u16]) -> Self {
+        let bytes = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        Self::new(tag, 3, values.len(), bytes)
+    }
+
+    fn long(tag: u16, value: u32) -> Self {
+        Self::new(tag, 4, 1, value.to_le_bytes().to_vec())
+    }
+
+    fn double(tag: u16, values: &[f64]) -> Self {
+        let bytes = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        Self::new(tag, 12, values.len(), bytes)
+    }
+
+    fn ascii(tag: u16, value: &str) -> Self {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        Self::new(tag, 2, bytes.len(), bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_raster() {
+        let cells = HashMap::from([((2, 5), 1.0), ((4, 5), 2.0), ((2, 3), 3.0)]);
+        let raster = Raster::from_cells(10.0, &cells, -1.0).unwrap();
+        assert_eq!((raster.columns, raster.rows), (3, 3));
+        assert_eq!((raster.min_x, raster.max_y), (20.0, 60.0));
+        assert_eq!(
+            raster.values,
+            [1.0, -1.0, 2.0, -1.0, -1.0, -1.0, 3.0, -1.0, -1.0]
+        );
+        assert_eq!(cell_of(45.0, 59.9, 10.0), (4, 5));
+        assert_eq!(raster.value_at(45.0, 59.9), Some(2.0));
+        assert_eq!(raster.value_at(20.0, 30.0), Some(3.0));
+        assert_eq!(raster.value_at(20.0, 29.9), None);
+        assert_eq!(raster.value_at(19.9, 55.0), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("raster.tif");
+        raster.with_nodata(-1.0).write_geotiff(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..4], b"II*\0");
+        assert_eq!(f32::from_le_bytes(bytes[8..12].try_into().unwrap()), 1.0);
+        let directory = u32_at(&bytes, 4) as usize;
+        let entries = u16_at(&bytes, directory) as usize;
+        assert_eq!(bytes.len(), directory + 2 + entries * 12 + 4);
+        let tags: Vec<u16> = (0..entries)
+            .map(|entry| u16_at(&bytes, directory + 2 + entry * 12))
+            .collect();
+        assert!(tags.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(tags.last(), Some(&42113));
+        assert_eq!(u32_at(&bytes, directory + 2 + 8), 3);
+
+        let bands = ["low".to_string(), "high".to_string()];
+        let cells = HashMap::from([((0, 0), [1.0, 2.0]), ((1, 1), [3.0, 4.0])]);
+        let cells = cells.iter().map(|(&cell, values)| (cell, &values[..]));
+        let raster = Raster::from_band_cells(2.0, &bands, cells, NODATA)
+            .unwrap()
+            .with_nodata(NODATA);
+        assert_eq!(raster.values.len(), 8);
+        assert_eq!(raster.values_at(3.0, 3.0), Some(&[3.0, 4.0][..]));
+        assert_eq!(raster.values_at(1.0, 3.0), Some(&[NODATA, NODATA][..]));
+        raster.write_geotiff(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let directory = u32_at(&bytes, 4) as usize;
+        let entries = u16_at(&bytes, directory) as usize;
+        let tags: Vec<u16> = (0..entries)
+            .map(|entry| u16_at(&bytes, directory + 2 + entry * 12))
+            .collect();
+        assert!(tags.contains(&338) && tags.contains(&42112));
+        let metadata = String::from_utf8_lossy(&bytes);
+        assert!(metadata.contains(r#"sample="1" role="description">high<"#));
+
+        let csv_path = dir.path().join("raster.csv");
+        raster.write_csv(&csv_path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&csv_path).unwrap(),
+            "x,y,low,high\n3,3,3,4\n1,1,1,2\n"
+        );
+
+        assert!(is_raster_path("density.TIF"));
+        assert!(!is_raster_path("density.las"));
+        assert!(is_csv_path("metrics.CSV"));
+        let huge = HashMap::from([((0, 0), 1.0), ((1 << 20, 1 << 20), 1.0)]);
+        assert!(matches!(
+            Raster::from_cells(1.0, &huge, 0.0),
+            Err(MyError::RasterTooLarge(_, _))
+        ));
+    }
+}
@@ -0,0 +1,387 @@
+//! Reading the raw point records of uncompressed LAS files.
+//!
+//! Conditions that only look at a few fields of a point don't need the point decoded, so when
+//! every condition works on a [`PointView`] local LAS files are read record by record with
+//! [`RecordReader`] instead of through `las::Reader`. Everything else goes through `las::Reader`
+//! as before.
+//!
+//! With the `mmap` feature the files can also be memory-mapped, so the records are looked at
+//! where they are instead of being copied through a buffer, and seeking to a range of points costs
+//! nothing.
+//!
+//! Records are read a block at a time, so the built-in numeric filters can be evaluated for the
+//! whole block at once by [`crate::simd`].
+use crate::archive;
+use crate::errors::MyError;
+use crate::filter::{Condition, PointView};
+use crate::input::{is_stdin, open_reader};
+use crate::remote;
+use crate::simd::{filter_records, BLOCK_SIZE};
+use crate::stream::InputSource;
+use las::{Header, Reader};
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+
+/// Reads the raw point records of an uncompressed LAS file.
+pub struct RecordReader {
+    data: Data,
+    header: Header,
+    offset_to_point_data: u64,
+    record_length: usize,
+    /// The index of the point after the current block.
+    next: u64,
+    block: Block,
+}
+
+/// The records read last and what the numeric filters made of them.
+#[derive(Default)]
+struct Block {
+    /// The records, for buffered files.
+    records: Vec<u8>,
+    /// Where the records start, for mapped files.
+    #[cfg(feature = "mmap")]
+    start: usize,
+    len: usize,
+    /// The index in the block of the next record to hand out.
+    position: usize,
+    /// Whether each record matches each numeric condition, a column of `len` per condition.
+    matches: Vec<bool>,
+    matched: bool,
+}
+
+/// Where the records come from.
+enum Data {
+    Buffered(BufReader<File>),
+    /// The whole file mapped into memory, and the position of the next record in it.
+    #[cfg(feature = "mmap")]
+    Mapped {
+        map: memmap2::Mmap,
+        position: usize,
+    },
+}
+
+impl RecordReader {
+    /// Opens the LAS file at `path`, memory-mapping it if `mmap` is set. Returns `None` if the
+    /// file is LAZ compressed.
+    pub fn open(path: &str, mmap: bool) -> Result<Option<Self>, MyError> {
+        let header = open_reader(path)?.header().clone();
+        if header.point_format().is_compressed {
+            return Ok(None);
+        }
+        let mut read = BufReader::new(File::open(path)?);
+        let raw_header = las::raw::Header::read_from(&mut read)?;
+        let offset_to_point_data = u64::from(raw_header.offset_to_point_data);
+        let data = if mmap {
+            map(path, read.into_inner(), offset_to_point_data)?
+        } else {
+            read.seek(SeekFrom::Start(offset_to_point_data))?;
+            Data::Buffered(read)
+        };
+        Ok(Some(Self {
+            data,
+            header,
+            offset_to_point_data,
+            record_length: usize::from(raw_header.point_data_record_length),
+            next: 0,
+            block: Block::default(),
+        }))
+    }
+
+    /// The header of the file.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Moves to the record of point `index`.
+    pub fn seek(&mut self, index: u64) -> Result<(), MyError> {
+        let position = self.offset_to_point_data + index * self.record_length as u64;
+        match &mut self.data {
+            Data::Buffered(read) => {
+                read.seek(SeekFrom::Start(position))?;
+            }
+            #[cfg(feature = "mmap")]
+            Data::Mapped { position: next, .. } => *next = position as usize,
+        }
+        self.next = index;
+        self.block = Block {
+            records: std::mem::take(&mut self.block.records),
+            matches: std::mem::take(&mut self.block.matches),
+            ..Block::default()
+        };
+        Ok(())
+    }
+
+    /// Reads the next record, or returns `None` after the last point. Mapped files hand out the
+    /// record in place, without copying it.
+    pub fn read_record(&mut self) -> Option<Result<&[u8], las::Error>> {
+        match self.advance()? {
+            Ok(index) => Some(Ok(self.record(index))),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// Reads the next record as a view, or returns `None` after the last point.
+    pub fn read_view(&mut self) -> Option<Result<PointView<'_>, las::Error>> {
+        match self.advance()? {
+            Ok(index) => Some(Ok(PointView::from_record(self.record(index), &self.header))),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// Reads the next record as a view and writes whether it meets each of `conditions` to
+    /// `matches`. The numeric conditions are evaluated for the whole block of records when its
+    /// first record is read, and the others for each record. The same conditions must be passed
+    /// for every record of a block.
+    pub fn read_matching(
+        &mut self,
+        conditions: &[Condition],
+        matches: &mut [bool],
+    ) -> Option<Result<PointView<'_>, las::Error>> {
+        let index = match self.advance()? {
+            Ok(index) => index,
+            Err(err) => return Some(Err(err)),
+        };
+        if !self.block.matched {
+            self.match_block(conditions);
+        }
+        let len = self.block.len;
+        let mut view = PointView::from_record(self.record(index), &self.header);
+        for (j, (matched, condition)) in matches.iter_mut().zip(conditions).enumerate() {
+            *matched = match condition.numeric_filter() {
+                Some(_) => self.block.matches[j * len + index],
+                None => match condition.matches(&mut view) {
+                    Ok(matched) => matched,
+                    Err(err) => return Some(Err(err)),
+                },
+            };
+        }
+        Some(Ok(view))
+    }
+
+    /// Evaluates the numeric conditions for the records of the current block.
+    fn match_block(&mut self, conditions: &[Condition]) {
+        let len = self.block.len;
+        let mut matches = std::mem::take(&mut self.block.matches);
+        matches.clear();
+        matches.resize(conditions.len() * len, false);
+        let records = self.records();
+        for (condition, out) in conditions.iter().zip(matches.chunks_mut(len.max(1))) {
+            if let Some(filter) = condition.numeric_filter() {
+                filter_records(filter, records, &self.header, out);
+            }
+        }
+        self.block.matches = matches;
+        self.block.matched = true;
+    }
+
+    /// Moves on to the next record, reading the next block if the current one is used up, and
+    /// returns its index in the block. Returns `None` after the last point.
+    fn advance(&mut self) -> Option<Result<usize, las::Error>> {
+        if self.block.position == self.block.len {
+            let remaining = self.header.number_of_points().saturating_sub(self.next);
+            if remaining == 0 {
+                return None;
+            }
+            let len = remaining.min(BLOCK_SIZE as u64) as usize;
+            if let Err(err) = self.read_block(len) {
+                return Some(Err(err));
+            }
+        }
+        self.block.position += 1;
+        Some(Ok(self.block.position - 1))
+    }
+
+    /// Reads up to `len` records into the block. If the file ends early, the block holds the
+    /// records that could be read, and an error is only returned when there are none.
+    fn read_block(&mut self, len: usize) -> Result<(), las::Error> {
+        let bytes = len * self.record_length;
+        let read = match &mut self.data {
+            Data::Buffered(read) => {
+                self.block.records.resize(bytes, 0);
+                fill(read, &mut self.block.records)?
+            }
+            #[cfg(feature = "mmap")]
+            Data::Mapped { map, position } => {
+                self.block.start = *position;
+                bytes.min(map.len().saturating_sub(*position))
+            }
+        };
+        let len = read / self.record_length;
+        if len == 0 {
+            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        #[cfg(feature = "mmap")]
+        if let Data::Mapped { position, .. } = &mut self.data {
+            *position += len * self.record_length;
+        }
+        self.next += len as u64;
+        self.block.len = len;
+        self.block.position = 0;
+        self.block.matched = false;
+        Ok(())
+    }
+
+    /// The records of the current block.
+    fn records(&self) -> &[u8] {
+        let bytes = self.block.len * self.record_length;
+        match &self.data {
+            Data::Buffered(_) => &self.block.records[..bytes],
+            #[cfg(feature = "mmap")]
+            Data::Mapped { map, .. } => &map[self.block.start..self.block.start + bytes],
+        }
+    }
+
+    /// Record `index` of the current block.
+    fn record(&self, index: usize) -> &[u8] {
+        let start = index * self.record_length;
+        &self.records()[start..start + self.record_length]
+    }
+}
+
+/// Reads into `buffer` until it is full or the stream ends, and returns how many bytes were read.
+fn fill(read: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match read.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(feature = "mmap")]
+fn map(_path: &str, file: File, offset_to_point_data: u64) -> Result<Data, MyError> {
+    // SAFETY: the map is only read from. If another process truncates the file while it is
+    // mapped, reading the missing pages raises SIGBUS, which is the usual caveat of mapping
+    // files and the reason mapping is opt-in.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    Ok(Data::Mapped {
+        map,
+        position: offset_to_point_data as usize,
+    })
+}
+
+#[cfg(not(feature = "mmap"))]
+fn map(path: &str, _file: File, _offset_to_point_data: u64) -> Result<Data, MyError> {
+    Err(MyError::FeatureNotEnabled(path.to_string(), "mmap"))
+}
+
+/// The points of an input, decoded by `las` or read as raw records.
+pub(crate) enum PointSource {
+    Points(Reader),
+    Records(Box<RecordReader>),
+}
+
+impl PointSource {
+    /// Opens `source`, as raw records if `records` or `mmap` is set and the input is an
+    /// uncompressed local LAS file. With `mmap` the records are read from a memory map.
+    pub(crate) fn open(source: &InputSource, records: bool, mmap: bool) -> Result<Self, MyError> {
+        let path = match source {
+            InputSource::Path(path) => path,
+            InputSource::Reader(_) => return Ok(PointSource::Points(source.open_reader()?)),
+        };
+        let local = !(is_stdin(path)
+            || remote::is_remote(path)
+            || remote::is_http(path)
+            || archive::split_entry(path).is_some());
+        if (records || mmap) && local {
+            if let Some(reader) = RecordReader::open(path, mmap)? {
+                return Ok(PointSource::Records(Box::new(reader)));
+            }
+        }
+        Ok(PointSource::Points(open_reader(path)?))
+    }
+
+    pub(crate) fn header(&self) -> &Header {
+        match self {
+            PointSource::Points(reader) => reader.header(),
+            PointSource::Records(reader) => reader.header(),
+        }
+    }
+
+    pub(crate) fn seek(&mut self, index: u64) -> Result<(), MyError> {
+        match self {
+            PointSource::Points(reader) => Ok(reader.seek(index)?),
+            PointSource::Records(reader) => reader.seek(index),
+        }
+    }
+
+    /// Reads the next point and writes whether it meets each of `conditions` to `matches`, or
+    /// returns `None` after the last point.
+    pub(crate) fn read(
+        &mut self,
+        conditions: &[Condition],
+        matches: &mut [bool],
+    ) -> Option<Result<PointView<'_>, las::Error>> {
+        match self {
+            PointSource::Points(reader) => {
+                let point = match reader.read_point().transpose()? {
+                    Ok(point) => point,
+                    Err(err) => return Some(Err(err)),
+                };
+                let mut view = PointView::from_point(point);
+                for (matched, condition) in matches.iter_mut().zip(conditions) {
+                    *matched = match condition.matches(&mut view) {
+                        Ok(matched) => matched,
+                        Err(err) => return Some(Err(err)),
+                    };
+                }
+                Some(Ok(view))
+            }
+            PointSource::Records(reader) => reader.read_matching(conditions, matches),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_reader() {
+        let path = "tests/data/input1.las";
+        let mut expected = open_reader(path).unwrap();
+        let mut reader = RecordReader::open(path, false).unwrap().unwrap();
+        assert_eq!(
+            reader.header().number_of_points(),
+            expected.header().number_of_points()
+        );
+        for _ in 0..3 {
+            let point = reader.read_view().unwrap().unwrap().into_point().unwrap();
+            assert_eq!(point, expected.read_point().unwrap().unwrap());
+        }
+
+        let last = expected.header().number_of_points() - 1;
+        reader.seek(last).unwrap();
+        expected.seek(last).unwrap();
+        let point = reader.read_view().unwrap().unwrap().into_point().unwrap();
+        assert_eq!(point, expected.read_point().unwrap().unwrap());
+        assert!(reader.read_record().is_none());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_record_reader() {
+        let path = "tests/data/input1.las";
+        let mut buffered = RecordReader::open(path, false).unwrap().unwrap();
+        let mut mapped = RecordReader::open(path, true).unwrap().unwrap();
+        mapped.seek(10).unwrap();
+        buffered.seek(10).unwrap();
+        while let Some(record) = buffered.read_record() {
+            assert_eq!(record.unwrap(), mapped.read_record().unwrap().unwrap());
+        }
+        assert!(mapped.read_record().is_none());
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    #[test]
+    fn test_mmap_needs_feature() {
+        assert!(matches!(
+            RecordReader::open("tests/data/input1.las", true),
+            Err(MyError::FeatureNotEnabled(_, "mmap"))
+        ));
+    }
+}
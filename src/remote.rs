@@ -0,0 +1,219 @@
+//! Reading and writing LAS/LAZ data that lives in cloud object storage (S3, GCS, Azure) or on a
+//! plain HTTP(S) server.
+//!
+//! Remote inputs are read with ranged GETs, so only the parts of the object that the reader
+//! actually touches are downloaded. Remote outputs are written locally first and sent with a
+//! multipart upload once the header has been finalized. HTTP(S) URLs can only be used as inputs,
+//! and the server has to support range requests.
+//!
+//! Credentials and other store options are taken from the environment (`AWS_ACCESS_KEY_ID`,
+//! `GOOGLE_SERVICE_ACCOUNT`, `AZURE_STORAGE_ACCOUNT_NAME`, ...). Object stores need the
+//! `object-store` feature, HTTP(S) inputs need the `http` feature.
+use crate::errors::MyError;
+use las::Reader;
+use std::path::Path;
+
+/// The URL schemes that are treated as object-store locations.
+const REMOTE_SCHEMES: [&str; 6] = ["s3://", "s3a://", "gs://", "az://", "abfs://", "abfss://"];
+
+/// Returns `true` if `path` is an object-store URL such as `s3://bucket/key.laz`.
+pub fn is_remote(path: &str) -> bool {
+    REMOTE_SCHEMES.iter().any(|scheme| path.starts_with(scheme))
+}
+
+/// Returns `true` if `path` is an `http://` or `https://` URL.
+pub fn is_http(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Opens a `las::Reader` over an object-store or HTTP(S) URL.
+pub fn open_reader(path: &str) -> Result<Reader, MyError> {
+    if is_http(path) && !cfg!(feature = "http") {
+        return Err(MyError::FeatureNotEnabled(path.to_string(), "http"));
+    }
+    open_store_reader(path)
+}
+
+#[cfg(feature = "object-store")]
+fn open_store_reader(path: &str) -> Result<Reader, MyError> {
+    let (store, location) = parse_url(path)?;
+    Ok(Reader::new(RemoteReader::new(store, location)?)?)
+}
+
+#[cfg(not(feature = "object-store"))]
+fn open_store_reader(path: &str) -> Result<Reader, MyError> {
+    Err(MyError::FeatureNotEnabled(path.to_string(), "object-store"))
+}
+
+/// Uploads the local file `file` to the object-store URL `path`. If any part fails to be read or
+/// sent, the multipart upload is aborted so the store doesn't keep the parts already sent.
+#[cfg(feature = "object-store")]
+pub fn upload(file: &Path, path: &str) -> Result<(), MyError> {
+    use object_store::PutPayload;
+    use std::io::Read;
+
+    let (store, location) = parse_url(path)?;
+    let mut file = std::fs::File::open(file)?;
+    runtime()?.block_on(async {
+        let mut upload = store.put_multipart(&location).await?;
+        let result = async {
+            loop {
+                let mut part = Vec::with_capacity(UPLOAD_PART_SIZE);
+                (&mut file)
+                    .take(UPLOAD_PART_SIZE as u64)
+                    .read_to_end(&mut part)?;
+                if part.is_empty() {
+                    break;
+                }
+                upload.put_part(PutPayload::from(part)).await?;
+            }
+            upload.complete().await?;
+            Ok::<(), MyError>(())
+        }
+        .await;
+        if result.is_err() {
+            // The error that stopped the upload matters more than one aborting it
+            let _ = upload.abort().await;
+        }
+        result
+    })
+}
+
+/// Uploads the local file `file` to the object-store URL `path`.
+#[cfg(not(feature = "object-store"))]
+pub fn upload(_file: &Path, path: &str) -> Result<(), MyError> {
+    Err(MyError::FeatureNotEnabled(path.to_string(), "object-store"))
+}
+
+#[cfg(feature = "object-store")]
+pub use store::RemoteReader;
+#[cfg(feature = "object-store")]
+use store::{parse_url, runtime, UPLOAD_PART_SIZE};
+
+#[cfg(feature = "object-store")]
+mod store {
+    use crate::errors::MyError;
+    use object_store::{path::Path as ObjectPath, ObjectStore};
+    use std::io::{self, Read, Seek, SeekFrom};
+    use std::sync::{Arc, OnceLock};
+    use tokio::runtime::Runtime;
+    use url::Url;
+
+    /// How much data each ranged GET fetches. LAS readers mostly read sequentially, so reading
+    /// ahead keeps the number of requests down.
+    const READ_BLOCK_SIZE: u64 = 8 * 1024 * 1024;
+    /// The size of each part of a multipart upload. Must be at least 5 MiB for S3.
+    pub(super) const UPLOAD_PART_SIZE: usize = 16 * 1024 * 1024;
+
+    /// The runtime that drives the async object-store calls from the processor's worker threads.
+    pub(super) fn runtime() -> Result<&'static Runtime, MyError> {
+        static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+        if let Some(runtime) = RUNTIME.get() {
+            return Ok(runtime);
+        }
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        Ok(RUNTIME.get_or_init(|| runtime))
+    }
+
+    pub(super) fn parse_url(path: &str) -> Result<(Arc<dyn ObjectStore>, ObjectPath), MyError> {
+        let url = Url::parse(path).map_err(|_| MyError::InvalidRemoteUrl(path.to_string()))?;
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, location) = object_store::parse_url_opts(&url, options)?;
+        Ok((Arc::from(store), location))
+    }
+
+    /// A `Read + Seek` view of an object that fetches the bytes it needs with ranged GETs.
+    pub struct RemoteReader {
+        store: Arc<dyn ObjectStore>,
+        location: ObjectPath,
+        size: u64,
+        position: u64,
+        block: Vec<u8>,
+        block_start: u64,
+    }
+
+    impl RemoteReader {
+        /// Creates a reader for `location` in `store`.
+        pub fn new(store: Arc<dyn ObjectStore>, location: ObjectPath) -> Result<Self, MyError> {
+            let meta = runtime()?.block_on(store.head(&location))?;
+            Ok(Self {
+                store,
+                location,
+                size: meta.size as u64,
+                position: 0,
+                block: Vec::new(),
+                block_start: 0,
+            })
+        }
+
+        fn fetch_block(&mut self) -> io::Result<()> {
+            let end = (self.position + READ_BLOCK_SIZE).min(self.size);
+            let range = self.position as usize..end as usize;
+            let runtime = runtime().map_err(|err| io::Error::other(err.to_string()))?;
+            let bytes = runtime
+                .block_on(self.store.get_range(&self.location, range))
+                .map_err(io::Error::other)?;
+            self.block = bytes.to_vec();
+            self.block_start = self.position;
+            Ok(())
+        }
+    }
+
+    impl Read for RemoteReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if buf.is_empty() || self.position >= self.size {
+                return Ok(0);
+            }
+            let block_end = self.block_start + self.block.len() as u64;
+            if self.position < self.block_start || self.position >= block_end {
+                self.fetch_block()?;
+            }
+            let offset = (self.position - self.block_start) as usize;
+            let n = buf.len().min(self.block.len() - offset);
+            buf[..n].copy_from_slice(&self.block[offset..offset + n]);
+            self.position += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Seek for RemoteReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            let position = match pos {
+                SeekFrom::Start(position) => Some(position),
+                SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+                SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            };
+            self.position = position.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+            })?;
+            Ok(self.position)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use object_store::memory::InMemory;
+
+        #[test]
+        fn test_remote_reader_reads_las_from_store() {
+            let store = Arc::new(InMemory::new());
+            let location = ObjectPath::from("tiles/input1.las");
+            let data = std::fs::read("tests/data/input1.las").unwrap();
+            runtime()
+                .unwrap()
+                .block_on(store.put(&location, data.into()))
+                .unwrap();
+
+            let remote = RemoteReader::new(store, location).unwrap();
+            let mut reader = las::Reader::new(remote).unwrap();
+            let expected = las::Reader::from_path("tests/data/input1.las")
+                .unwrap()
+                .header()
+                .number_of_points();
+            assert_eq!(reader.points().count() as u64, expected);
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! Rewriting a file with a header that agrees with its points.
+//!
+//! The points are read once to count them and find their bounds and returns, then the file is
+//! copied with a fixed header. The point data is copied byte for byte, compressed or not, so the
+//! points come through exactly as they were. Optionally, VLRs identical to an earlier one are
+//! left out.
+//!
+//! The points of an uncompressed file are counted from the size of the point data, so a header
+//! counting too few or too many points is fixed, and a last record cut short is dropped. LAZ data
+//! doesn't say how many points it holds, so for LAZ files the points are read up to the count of
+//! the header or the first point that can't be decoded.
+use crate::errors::MyError;
+use crate::input::open_reader;
+use crate::output::check_output_paths;
+use crate::validate::{legacy_counts, trim_zeros, PointStats, VLR_HEADER_SIZE};
+use las::point::Format;
+use las::{Bounds, Point, Transform, Vector};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// How a file is repaired.
+#[derive(Clone, Debug, Default)]
+pub struct RepairOptions {
+    drop_duplicate_vlrs: bool,
+    overwrite: bool,
+}
+
+impl RepairOptions {
+    /// Leaves out VLRs with the same user id, record id and data as an earlier VLR.
+    pub fn with_drop_duplicate_vlrs(mut self, drop_duplicate_vlrs: bool) -> Self {
+        self.drop_duplicate_vlrs = drop_duplicate_vlrs;
+        self
+    }
+
+    /// Overwrites the output if it already exists.
+    pub fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+}
+
+/// Copies the local LAS/LAZ file at `input` to `output`, fixing the point counts, bounds and
+/// points by return of the header, and returns what was fixed.
+pub fn repair(input: &str, output: &str, options: &RepairOptions) -> Result<Vec<String>, MyError> {
+    let cannot = |reason: &str| MyError::CannotRepair(input.to_string(), reason.to_string());
+    if !Path::new(input).is_file() {
+        return Err(cannot("only local files can be repaired"));
+    }
+    check_output_paths(
+        &[output.to_string()],
+        &[input.to_string()],
+        options.overwrite,
+    )?;
+
+    let mut read = BufReader::new(File::open(input)?);
+    let file_size = read.get_ref().metadata()?.len();
+    let raw = las::raw::Header::read_from(&mut read)?;
+    read.seek(SeekFrom::Start(u64::from(raw.header_size)))?;
+    let mut vlrs = Vec::new();
+    for _ in 0..raw.number_of_variable_length_records {
+        vlrs.push(las::raw::Vlr::read_from(&mut read, false)?);
+    }
+    let vlrs_end = read.stream_position()?;
+    let point_data_start = u64::from(raw.offset_to_point_data);
+    if vlrs_end > point_data_start {
+        return Err(cannot("the VLRs run into the point data"));
+    }
+    // Whatever lies between the VLRs and the point data is kept
+    let mut vlr_padding = vec![0; (point_data_start - vlrs_end) as usize];
+    read.read_exact(&mut vlr_padding)?;
+    // The EVLRs, and in LAS 1.3 the waveform data, follow the point data
+    let point_data_end = [
+        raw.evlr
+            .filter(|evlr| evlr.number_of_evlrs > 0)
+            .map(|evlr| evlr.start_of_first_evlr),
+        raw.start_of_waveform_data_packet_record,
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|&start| start >= point_data_start)
+    .fold(file_size, u64::min);
+
+    let mut stats = PointStats::default();
+    let compressed = raw.point_data_record_format & 0x80 != 0;
+    let point_bytes = if compressed {
+        let mut reader = open_reader(input)?;
+        for point in reader.points() {
+            match point {
+                Ok(point) => stats.add(&point),
+                Err(_) => break,
+            }
+        }
+        point_data_end - point_data_start
+    } else {
+        let mut format = Format::new(raw.point_data_record_format)?;
+        format.extra_bytes = raw
+            .point_data_record_length
+            .checked_sub(format.len())
+            .ok_or_else(|| cannot("the point records are shorter than the point format"))?;
+        let transforms = Vector {
+            x: Transform {
+                scale: raw.x_scale_factor,
+                offset: raw.x_offset,
+            },
+            y: Transform {
+                scale: raw.y_scale_factor,
+                offset: raw.y_offset,
+            },
+            z: Transform {
+                scale: raw.z_scale_factor,
+                offset: raw.z_offset,
+            },
+        };
+        let record_length = u64::from(raw.point_data_record_length);
+        let count = (point_data_end - point_data_start) / record_length;
+        for _ in 0..count {
+            let point = las::raw::Point::read_from(&mut read, &format)?;
+            stats.add(&Point::new(point, &transforms));
+        }
+        count * record_length
+    };
+
+    let mut header = raw.clone();
+    let mut fixes = Vec::new();
+    if options.drop_duplicate_vlrs {
+        let count = vlrs.len();
+        let mut kept: Vec<las::raw::Vlr> = Vec::new();
+        for vlr in vlrs {
+            let duplicate = kept.iter().any(|kept| {
+                kept.user_id == vlr.user_id
+                    && kept.record_id == vlr.record_id
+                    && kept.data == vlr.data
+            });
+            if !duplicate {
+                kept.push(vlr);
+            }
+        }
+        if kept.len() < count {
+            fixes.push(format!("dropped {} duplicate VLRs", count - kept.len()));
+        }
+        vlrs = kept;
+    }
+    header.number_of_variable_length_records = vlrs.len() as u32;
+    let vlrs_size: u64 = vlrs
+        .iter()
+        .map(|vlr| VLR_HEADER_SIZE + vlr.data.len() as u64)
+        .sum();
+    let new_point_data_start = u64::from(raw.header_size) + vlrs_size + vlr_padding.len() as u64;
+    // Dropping VLRs only moves the point data closer to the start, so the offset still fits
+    header.offset_to_point_data = new_point_data_start as u32;
+
+    let format = raw.point_data_record_format & 0x3f;
+    let count = stats.number_of_points;
+    let (legacy_count, legacy_by_return) = legacy_counts(format, count, &stats.points_by_return);
+    match header.large_file.as_mut() {
+        Some(large_file) => {
+            update(
+                &mut fixes,
+                "the point count was",
+                &mut large_file.number_of_point_records,
+                count,
+            );
+            if large_file.number_of_points_by_return != stats.points_by_return {
+                fixes.push(format!(
+                    "the points by return were {:?}, now {:?}",
+                    trim_zeros(&large_file.number_of_points_by_return),
+                    trim_zeros(&stats.points_by_return)
+                ));
+                large_file.number_of_points_by_return = stats.points_by_return;
+            }
+            update(
+                &mut fixes,
+                "the legacy point count was",
+                &mut header.number_of_point_records,
+                legacy_count,
+            );
+            update(
+                &mut fixes,
+                "the legacy points by return were",
+                &mut header.number_of_points_by_return,
+                legacy_by_return,
+            );
+        }
+        None => {
+            if count > u64::from(u32::MAX) {
+                return Err(cannot("too many points for a file older than LAS 1.4"));
+            }
+            update(
+                &mut fixes,
+                "the point count was",
+                &mut header.number_of_point_records,
+                legacy_count,
+            );
+            update(
+                &mut fixes,
+                "the points by return were",
+                &mut header.number_of_points_by_return,
+                legacy_by_return,
+            );
+        }
+    }
+
+    // Files without points have all their bounds at 0
+    let origin = Vector {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let bounds = stats.bounds.unwrap_or(Bounds {
+        min: origin,
+        max: origin,
+    });
+    // Header bounds and coordinates may be rounded differently
+    let agree = |old: f64, new: f64, scale: f64| (old - new).abs() <= scale / 2.0;
+    let bounds_agree = agree(header.min_x, bounds.min.x, header.x_scale_factor)
+        && agree(header.max_x, bounds.max.x, header.x_scale_factor)
+        && agree(header.min_y, bounds.min.y, header.y_scale_factor)
+        && agree(header.max_y, bounds.max.y, header.y_scale_factor)
+        && agree(header.min_z, bounds.min.z, header.z_scale_factor)
+        && agree(header.max_z, bounds.max.z, header.z_scale_factor);
+    if !bounds_agree {
+        fixes.push(format!(
+            "the bounds were {} {} {} to {} {} {}, now {} {} {} to {} {} {}",
+            header.min_x,
+            header.min_y,
+            header.min_z,
+            header.max_x,
+            header.max_y,
+            header.max_z,
+            bounds.min.x,
+            bounds.min.y,
+            bounds.min.z,
+            bounds.max.x,
+            bounds.max.y,
+            bounds.max.z
+        ));
+        header.min_x = bounds.min.x;
+        header.max_x = bounds.max.x;
+        header.min_y = bounds.min.y;
+        header.max_y = bounds.max.y;
+        header.min_z = bounds.min.z;
+        header.max_z = bounds.max.z;
+    }
+    if point_data_start + point_bytes < point_data_end {
+        fixes.push(format!(
+            "dropped {} bytes after the last point record",
+            point_data_end - point_data_start - point_bytes
+        ));
+    }
+
+    // Everything after the point data is copied as it is, so what points into it moves with it
+    let tail_start = new_point_data_start + point_bytes;
+    let move_into_tail = |start: u64| start - point_data_end + tail_start;
+    if let Some(evlr) = header.evlr.as_mut() {
+        if evlr.number_of_evlrs > 0 && evlr.start_of_first_evlr >= point_data_end {
+            evlr.start_of_first_evlr = move_into_tail(evlr.start_of_first_evlr);
+        }
+    }
+    if let Some(start) = header.start_of_waveform_data_packet_record.as_mut() {
+        if *start >= point_data_end {
+            *start = move_into_tail(*start);
+        }
+    }
+
+    let mut write = BufWriter::new(File::create(output)?);
+    header.write_to(&mut write)?;
+    for vlr in &vlrs {
+        vlr.write_to(&mut write)?;
+    }
+    write.write_all(&vlr_padding)?;
+    read.seek(SeekFrom::Start(point_data_start))?;
+    let mut points = (&mut read).take(point_bytes);
+    if compressed && point_bytes >= 8 {
+        // LAZ point data starts with the offset of the chunk table, which moves with the points
+        let mut offset = [0; 8];
+        points.read_exact(&mut offset)?;
+        let mut offset = i64::from_le_bytes(offset);
+        if offset >= point_data_start as i64 {
+            offset = offset - point_data_start as i64 + new_point_data_start as i64;
+        }
+        write.write_all(&offset.to_le_bytes())?;
+    }
+    io::copy(&mut points, &mut write)?;
+    read.seek(SeekFrom::Start(point_data_end))?;
+    io::copy(&mut read, &mut write)?;
+    write.flush()?;
+    Ok(fixes)
+}
+
+/// Sets `field` to `value`, noting the fix in `fixes` if they differ. `name` ends with the verb
+/// the old value follows, e.g. "the point count was".
+fn update<T: PartialEq + Debug>(fixes: &mut Vec<String>, name: &str, field: &mut T, value: T) {
+    if *field != value {
+        fixes.push(format!("{} {:?}, now {:?}", name, field, value));
+        *field = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate::validate;
+    use las::{Builder, Reader, Vlr, Writer};
+
+    /// Writes four points with a VLR twice over, and returns the points.
+    fn write_file(path: &Path) -> Vec<Point> {
+        let mut builder = Builder::from((1, 4));
+        let vlr = Vlr {
+            user_id: "las_trimmer".to_string(),
+            record_id: 1,
+            description: "test".to_string(),
+            data: vec![1, 2, 3],
+        };
+        builder.vlrs.push(vlr.clone());
+        builder.vlrs.push(vlr);
+        let mut writer = Writer::from_path(path, builder.into_header().unwrap()).unwrap();
+        let points: Vec<Point> = (0..4)
+            .map(|i| Point {
+                x: f64::from(i),
+                return_number: 1,
+                number_of_returns: 1,
+                ..Default::default()
+            })
+            .collect();
+        for point in &points {
+            writer.write_point(point.clone()).unwrap();
+        }
+        writer.close().unwrap();
+        points
+    }
+
+    #[test]
+    fn test_repair() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.las");
+        let repaired = dir.path().join("repaired.las");
+        let (input, output) = (path.to_str().unwrap(), repaired.to_str().unwrap());
+        write_file(&path);
+        let data = std::fs::read(&path).unwrap();
+
+        // Break the legacy point count and first returns at byte 107, the max X at byte 179, and
+        // the point count of LAS 1.4 at byte 247
+        let mut broken = data.clone();
+        broken[107..111].copy_from_slice(&9u32.to_le_bytes());
+        broken[111..115].copy_from_slice(&3u32.to_le_bytes());
+        broken[179..187].copy_from_slice(&1.0f64.to_le_bytes());
+        broken[247..255].copy_from_slice(&9u64.to_le_bytes());
+        std::fs::write(&path, &broken).unwrap();
+        assert!(!validate(input).unwrap().is_valid());
+
+        let fixes = repair(input, output, &RepairOptions::default()).unwrap();
+        assert_eq!(
+            fixes,
+            [
+                "the point count was 9, now 4",
+                "the legacy point count was 9, now 4",
+                "the legacy points by return were [3, 0, 0, 0, 0], now [4, 0, 0, 0, 0]",
+                "the bounds were 0 0 0 to 1 0 0, now 0 0 0 to 3 0 0",
+            ]
+        );
+        assert!(validate(output).unwrap().issues.is_empty());
+        assert_eq!(std::fs::read(&repaired).unwrap(), data);
+
+        assert!(matches!(
+            repair(input, output, &RepairOptions::default()),
+            Err(MyError::OutputExists(_))
+        ));
+        assert!(matches!(
+            repair(input, input, &RepairOptions::default().with_overwrite(true)),
+            Err(MyError::OutputOverlapsInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_repair_laz() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.laz");
+        let repaired = dir.path().join("repaired.laz");
+        let (input, output) = (path.to_str().unwrap(), repaired.to_str().unwrap());
+        let points = write_file(&path);
+
+        let options = RepairOptions::default().with_drop_duplicate_vlrs(true);
+        let fixes = repair(input, output, &options).unwrap();
+        assert_eq!(fixes, ["dropped 1 duplicate VLRs"]);
+        let mut reader = Reader::from_path(&repaired).unwrap();
+        let vlrs = reader.header().vlrs();
+        assert_eq!(
+            vlrs.iter()
+                .filter(|vlr| vlr.user_id == "las_trimmer")
+                .count(),
+            1
+        );
+        let repaired_points: Vec<Point> = reader.points().map(Result::unwrap).collect();
+        assert_eq!(repaired_points, points);
+    }
+}
@@ -0,0 +1,116 @@
+//! The summary of a processing run returned by `LasProcessor::process_lidar_files`.
+use crate::crs::Crs;
+use crate::tuning::Tuning;
+use las::Bounds;
+use std::time::Duration;
+
+/// How far the processing of a single input file has got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileState {
+    /// Waiting for a free reader thread.
+    Queued,
+    /// Being read.
+    Reading,
+    /// All points were read.
+    Done,
+    /// The reader made no progress within the file timeout and was abandoned.
+    TimedOut,
+    /// The file could not be read.
+    Failed,
+}
+
+impl FileState {
+    /// A short lowercase name for the state, as used in machine-readable output.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileState::Queued => "queued",
+            FileState::Reading => "reading",
+            FileState::Done => "done",
+            FileState::TimedOut => "timed_out",
+            FileState::Failed => "failed",
+        }
+    }
+}
+
+/// What happened during a call to `LasProcessor::process_lidar_files`.
+#[derive(Clone, Debug)]
+pub struct ProcessingReport {
+    /// One entry per input, in the order the inputs were given.
+    pub files: Vec<FileReport>,
+    /// One entry per output, in the order the outputs were given.
+    pub outputs: Vec<OutputReport>,
+    /// The inputs that couldn't be read and were left out under `ErrorPolicy::Skip`.
+    pub skipped: Vec<String>,
+    /// Whether processing was stopped early through a `CancellationToken`.
+    pub cancelled: bool,
+    /// How long the whole run took.
+    pub duration: Duration,
+    /// The batch size and channel depth used, as picked by auto-tuning if it was enabled.
+    pub tuning: Tuning,
+    /// The coordinate system of the points, decoded from the VLRs the outputs are made from, if
+    /// there is one that can be read.
+    pub crs: Option<Crs>,
+}
+
+impl ProcessingReport {
+    /// The number of points read from all inputs.
+    pub fn points_read(&self) -> u64 {
+        self.files.iter().map(|file| file.points_read).sum()
+    }
+
+    /// The number of points written to all outputs.
+    pub fn points_written(&self) -> u64 {
+        self.outputs
+            .iter()
+            .map(|output| output.points_written)
+            .sum()
+    }
+
+    /// The number of undecodable points that were skipped.
+    pub fn corrupt_points(&self) -> u64 {
+        self.files.iter().map(|file| file.corrupt_points).sum()
+    }
+
+    /// The bounds of all points written, or `None` if nothing was written.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.outputs
+            .iter()
+            .filter_map(|output| output.bounds)
+            .reduce(|mut bounds, other| {
+                bounds.min.x = bounds.min.x.min(other.min.x);
+                bounds.min.y = bounds.min.y.min(other.min.y);
+                bounds.min.z = bounds.min.z.min(other.min.z);
+                bounds.max.x = bounds.max.x.max(other.max.x);
+                bounds.max.y = bounds.max.y.max(other.max.y);
+                bounds.max.z = bounds.max.z.max(other.max.z);
+                bounds
+            })
+    }
+}
+
+/// What happened to one input file.
+#[derive(Clone, Debug)]
+pub struct FileReport {
+    /// The input path.
+    pub path: String,
+    /// How far reading got. `Done` unless the file failed or timed out.
+    pub state: FileState,
+    /// The number of points read.
+    pub points_read: u64,
+    /// The number of undecodable points that were skipped.
+    pub corrupt_points: u64,
+    /// How long reading took.
+    pub duration: Duration,
+}
+
+/// What was written to one output.
+#[derive(Clone, Debug)]
+pub struct OutputReport {
+    /// The output path.
+    pub path: String,
+    /// The number of points written.
+    pub points_written: u64,
+    /// The bounds of the points written, and of the points of a file appended to, or `None` if
+    /// the output is empty.
+    pub bounds: Option<Bounds>,
+}
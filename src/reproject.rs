@@ -0,0 +1,226 @@
+//! Projecting filter geometry given in another coordinate system than the points.
+//!
+//! A [`Reprojection`] takes coordinates from one of the systems [`crate::crs`] knows to another,
+//! e.g. a bounding box in WGS 84 longitudes and latitudes to the UTM zone of a dataset. The
+//! projections are transverse Mercator, computed with the series of Krüger, which are good to
+//! well under a millimetre within a zone. Datums aren't shifted: the geographic systems are all
+//! taken to be the same, which is within a metre or two of the truth for the ones known, and
+//! plenty for picking the area to keep.
+use crate::crs::{inverse_flattening, name_of, projection, Crs, CrsKind};
+use crate::errors::MyError;
+
+/// The semi-major axis of the ellipsoids of the known systems, in metres.
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+
+/// Coordinates in a known system, geographic as longitude and latitude in degrees, or projected.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Plane {
+    Geographic,
+    TransverseMercator(TransverseMercator),
+}
+
+impl Plane {
+    /// The plane of the system with EPSG code `code`.
+    fn of(code: u32) -> Result<Self, MyError> {
+        if inverse_flattening(code).is_some() {
+            return Ok(Plane::Geographic);
+        }
+        let projection = projection(code).ok_or(MyError::UnsupportedCrs(code))?;
+        let flattening =
+            1.0 / inverse_flattening(projection.base).ok_or(MyError::UnsupportedCrs(code))?;
+        Ok(Plane::TransverseMercator(TransverseMercator::new(
+            flattening,
+            projection.central_meridian,
+            projection.scale_factor,
+            projection.false_easting,
+            projection.false_northing,
+        )))
+    }
+
+    /// The longitude and latitude of `(x, y)`.
+    fn unproject(&self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            Plane::Geographic => (x, y),
+            Plane::TransverseMercator(projection) => projection.inverse(x, y),
+        }
+    }
+
+    /// The coordinates of the longitude and latitude `(lon, lat)`.
+    fn project(&self, lon: f64, lat: f64) -> (f64, f64) {
+        match self {
+            Plane::Geographic => (lon, lat),
+            Plane::TransverseMercator(projection) => projection.forward(lon, lat),
+        }
+    }
+}
+
+/// Takes coordinates from one coordinate system to another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reprojection {
+    from: Plane,
+    to: Plane,
+}
+
+impl Reprojection {
+    /// A reprojection from the system with EPSG code `from` to `to`. Fails with
+    /// `MyError::UnsupportedCrs` if either isn't one of the systems [`crate::crs`] knows.
+    pub fn new(from: u32, to: &Crs) -> Result<Self, MyError> {
+        let to_code = to
+            .epsg
+            .ok_or_else(|| MyError::UnknownDataCrs(to.name.clone()))?;
+        let to_plane = Plane::of(to_code)?;
+        // A system named by the data but not known can't be trusted to be what its code says
+        if (to.kind == CrsKind::Geographic) != (to_plane == Plane::Geographic) {
+            return Err(MyError::UnsupportedCrs(to_code));
+        }
+        Ok(Self {
+            from: Plane::of(from)?,
+            to: to_plane,
+        })
+    }
+
+    /// A reprojection between the systems with EPSG codes `from` and `to`.
+    pub fn between(from: u32, to: u32) -> Result<Self, MyError> {
+        let name = name_of(to).ok_or(MyError::UnsupportedCrs(to))?;
+        let kind = match inverse_flattening(to) {
+            Some(_) => CrsKind::Geographic,
+            None => CrsKind::Projected,
+        };
+        Self::new(
+            from,
+            &Crs {
+                name,
+                kind,
+                epsg: Some(to),
+                vertical_epsg: None,
+            },
+        )
+    }
+
+    /// `(x, y)` in the target system.
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        if self.from == self.to {
+            return (x, y);
+        }
+        let (lon, lat) = self.from.unproject(x, y);
+        self.to.project(lon, lat)
+    }
+}
+
+/// A transverse Mercator projection, with the coefficients of its series.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TransverseMercator {
+    /// The eccentricity of the ellipsoid.
+    eccentricity: f64,
+    /// The radius of the rectifying sphere times the scale factor.
+    radius: f64,
+    central_meridian: f64,
+    false_easting: f64,
+    false_northing: f64,
+    alpha: [f64; 4],
+    beta: [f64; 4],
+    delta: [f64; 4],
+}
+
+impl TransverseMercator {
+    fn new(
+        flattening: f64,
+        central_meridian: f64,
+        scale_factor: f64,
+        false_easting: f64,
+        false_northing: f64,
+    ) -> Self {
+        let n = flattening / (2.0 - flattening);
+        let (n2, n3, n4) = (n * n, n * n * n, n * n * n * n);
+        Self {
+            eccentricity: (flattening * (2.0 - flattening)).sqrt(),
+            radius: scale_factor * SEMI_MAJOR_AXIS / (1.0 + n) * (1.0 + n2 / 4.0 + n4 / 64.0),
+            central_meridian,
+            false_easting,
+            false_northing,
+            alpha: [
+                n / 2.0 - 2.0 * n2 / 3.0 + 5.0 * n3 / 16.0 + 41.0 * n4 / 180.0,
+                13.0 * n2 / 48.0 - 3.0 * n3 / 5.0 + 557.0 * n4 / 1440.0,
+                61.0 * n3 / 240.0 - 103.0 * n4 / 140.0,
+                49561.0 * n4 / 161280.0,
+            ],
+            beta: [
+                n / 2.0 - 2.0 * n2 / 3.0 + 37.0 * n3 / 96.0 - n4 / 360.0,
+                n2 / 48.0 + n3 / 15.0 - 437.0 * n4 / 1440.0,
+                17.0 * n3 / 480.0 - 37.0 * n4 / 840.0,
+                4397.0 * n4 / 161280.0,
+            ],
+            delta: [
+                2.0 * n - 2.0 * n2 / 3.0 - 2.0 * n3 + 116.0 * n4 / 45.0,
+                7.0 * n2 / 3.0 - 8.0 * n3 / 5.0 - 227.0 * n4 / 45.0,
+                56.0 * n3 / 15.0 - 136.0 * n4 / 35.0,
+                4279.0 * n4 / 630.0,
+            ],
+        }
+    }
+
+    /// The easting and northing of the longitude and latitude `(lon, lat)`, in degrees.
+    fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let (lat, dlon) = (lat.to_radians(), (lon - self.central_meridian).to_radians());
+        let e = self.eccentricity;
+        let t = (lat.sin().atanh() - e * (e * lat.sin()).atanh()).sinh();
+        let xi = t.atan2(dlon.cos());
+        let eta = (dlon.sin() / (1.0 + t * t).sqrt()).atanh();
+        let (mut x, mut y) = (eta, xi);
+        for (j, alpha) in self.alpha.iter().enumerate() {
+            let k = 2.0 * (j + 1) as f64;
+            x += alpha * (k * xi).cos() * (k * eta).sinh();
+            y += alpha * (k * xi).sin() * (k * eta).cosh();
+        }
+        (
+            self.false_easting + self.radius * x,
+            self.false_northing + self.radius * y,
+        )
+    }
+
+    /// The longitude and latitude in degrees of the easting and northing `(x, y)`.
+    fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let xi = (y - self.false_northing) / self.radius;
+        let eta = (x - self.false_easting) / self.radius;
+        let (mut xi1, mut eta1) = (xi, eta);
+        for (j, beta) in self.beta.iter().enumerate() {
+            let k = 2.0 * (j + 1) as f64;
+            xi1 -= beta * (k * xi).sin() * (k * eta).cosh();
+            eta1 -= beta * (k * xi).cos() * (k * eta).sinh();
+        }
+        let chi = (xi1.sin() / eta1.cosh()).asin();
+        let mut lat = chi;
+        for (j, delta) in self.delta.iter().enumerate() {
+            lat += delta * (2.0 * (j + 1) as f64 * chi).sin();
+        }
+        let lon = self.central_meridian + eta1.sinh().atan2(xi1.cos()).to_degrees();
+        (lon, lat.to_degrees())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reprojection() {
+        // The origin of UTM zone 33N, and a point in the southern hemisphere of NZTM
+        let utm = Reprojection::between(4326, 32633).unwrap();
+        let (x, y) = utm.apply(15.0, 0.0);
+        assert!((x - 500_000.0).abs() < 1e-6 && y.abs() < 1e-6);
+        let (x, y) = utm.apply(16.0, 45.0);
+        assert!((x - 578_815.302).abs() < 0.01, "{}", x);
+        assert!((y - 4_983_436.768).abs() < 0.01, "{}", y);
+
+        let nztm = Reprojection::between(4167, 2193).unwrap();
+        let (x, y) = nztm.apply(174.7633, -36.8485);
+        let (lon, lat) = Reprojection::between(2193, 4167).unwrap().apply(x, y);
+        assert!((lon - 174.7633).abs() < 1e-9 && (lat + 36.8485).abs() < 1e-9);
+        assert!((x - 1_757_209.253).abs() < 0.01 && (y - 5_920_482.809).abs() < 0.01);
+
+        assert!(matches!(
+            Reprojection::between(4326, 3857),
+            Err(MyError::UnsupportedCrs(3857))
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+//! A tiny HTTP server for the monitoring endpoints of long-running modes.
+//!
+//! It only understands `GET` requests and answers each connection on a thread of its own, which
+//! is plenty for a Prometheus scraper or an operator's browser. A client gets a few seconds to
+//! send its request and take the response before it is dropped.
+use crate::errors::MyError;
+use log::warn;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How long a client may take to send its request or to take the response.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The body of a successful response.
+pub struct Response {
+    /// The value of the `Content-Type` header.
+    pub content_type: &'static str,
+    /// The response body.
+    pub body: String,
+}
+
+/// Starts serving on `addr` in a background thread. `handler` is called with the path of each
+/// `GET` request and returns the response, or `None` for a 404. Returns the address the server
+/// is bound to, which is useful when `addr` asks for port 0.
+pub fn serve<F>(addr: &str, handler: F) -> Result<SocketAddr, MyError>
+where
+    F: Fn(&str) -> Option<Response> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    thread::spawn(move || {
+        let handler = Arc::new(handler);
+        for stream in listener.incoming() {
+            let handler = Arc::clone(&handler);
+            let result = stream.and_then(|stream| {
+                thread::Builder::new().spawn(move || {
+                    if let Err(err) = respond(stream, &*handler) {
+                        warn!("Failed to answer a monitoring request: {}", err);
+                    }
+                })
+            });
+            if let Err(err) = result {
+                warn!("Failed to answer a monitoring request: {}", err);
+            }
+        }
+    });
+    Ok(local_addr)
+}
+
+fn respond<F>(mut stream: TcpStream, handler: &F) -> std::io::Result<()>
+where
+    F: Fn(&str) -> Option<Response>,
+{
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Read the headers too, closing the connection with unread data would reset it
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let (status, response) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => match handler(path) {
+            Some(response) => ("200 OK", response),
+            None => ("404 Not Found", text("Not found\n")),
+        },
+        _ => ("405 Method Not Allowed", text("Only GET is supported\n")),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn text(body: &str) -> Response {
+    Response {
+        content_type: "text/plain; charset=utf-8",
+        body: body.to_string(),
+    }
+}
@@ -0,0 +1,319 @@
+//! Evaluating the built-in numeric filters over blocks of raw point records.
+//!
+//! The fields a filter tests are gathered from a handful of records into lane arrays and compared
+//! without branches, which the compiler turns into vector instructions. On x86-64 the kernels are
+//! compiled a second time with AVX2 enabled, and that copy is picked at runtime when the CPU
+//! supports it. Bounds are converted to raw integer coordinates once per block, so testing a point
+//! takes no floating point arithmetic.
+use crate::filter::{scan_flags_offset, NumericFilter, PointView};
+use las::point::ScanDirection;
+use las::{Header, Transform};
+
+/// How many records are tested together.
+const LANES: usize = 8;
+
+/// How many records are read and filtered at a time.
+pub(crate) const BLOCK_SIZE: usize = 1024;
+
+/// Tests the `records` of the point format of `header`, stored back to back, against `filter`,
+/// and writes whether each one matches to `out`, which has one entry per record.
+pub(crate) fn filter_records(
+    filter: &NumericFilter,
+    records: &[u8],
+    header: &Header,
+    out: &mut [bool],
+) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: the CPU supports AVX2, which was just checked.
+        unsafe { filter_records_avx2(filter, records, header, out) };
+        return;
+    }
+    filter_records_portable(filter, records, header, out);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn filter_records_avx2(
+    filter: &NumericFilter,
+    records: &[u8],
+    header: &Header,
+    out: &mut [bool],
+) {
+    filter_records_portable(filter, records, header, out);
+}
+
+#[inline(always)]
+fn filter_records_portable(
+    filter: &NumericFilter,
+    records: &[u8],
+    header: &Header,
+    out: &mut [bool],
+) {
+    let record_length = usize::from(header.point_format().len());
+    match filter {
+        NumericFilter::Bounds(bounds) => {
+            let transforms = header.transforms();
+            let ranges = [
+                raw_range(&transforms.x, bounds.min.x, bounds.max.x),
+                raw_range(&transforms.y, bounds.min.y, bounds.max.y),
+                raw_range(&transforms.z, bounds.min.z, bounds.max.z),
+            ];
+            let [Some(x), Some(y), Some(z)] = ranges else {
+                // Negative scales don't map to a range of raw values
+                for (keep, record) in out.iter_mut().zip(records.chunks_exact(record_length)) {
+                    *keep = filter.matches(&PointView::from_record(record, header));
+                }
+                return;
+            };
+            evaluate(
+                records,
+                record_length,
+                out,
+                |record| {
+                    [
+                        i64::from(read_i32(record, 0)),
+                        i64::from(read_i32(record, 4)),
+                        i64::from(read_i32(record, 8)),
+                    ]
+                },
+                |[rx, ry, rz]| {
+                    (x.0 <= rx)
+                        & (rx <= x.1)
+                        & (y.0 <= ry)
+                        & (ry <= y.1)
+                        & (z.0 <= rz)
+                        & (rz <= z.1)
+                },
+            );
+        }
+        NumericFilter::Intensity(range) => {
+            let (low, high) = (*range.start(), *range.end());
+            evaluate(
+                records,
+                record_length,
+                out,
+                |record| u16::from_le_bytes([record[12], record[13]]),
+                |intensity| (low <= intensity) & (intensity <= high),
+            );
+        }
+        NumericFilter::Classes(mask) => {
+            if header.point_format().is_extended {
+                evaluate(
+                    records,
+                    record_length,
+                    out,
+                    |record| record[16],
+                    |class| mask.contains(class),
+                );
+            } else {
+                evaluate(
+                    records,
+                    record_length,
+                    out,
+                    |record| record[15] & 0x1f,
+                    |class| mask.contains(class),
+                );
+            }
+        }
+        NumericFilter::Returns(selection) => {
+            let extended = header.point_format().is_extended;
+            evaluate(
+                records,
+                record_length,
+                out,
+                |record| {
+                    if extended {
+                        (record[14] & 0x0f, record[14] >> 4)
+                    } else {
+                        (record[14] & 0x07, (record[14] >> 3) & 0x07)
+                    }
+                },
+                |(return_number, number_of_returns)| {
+                    selection.contains(return_number, number_of_returns)
+                },
+            );
+        }
+        NumericFilter::EdgeOfFlightLine(edge) => {
+            let offset = scan_flags_offset(header);
+            evaluate(
+                records,
+                record_length,
+                out,
+                |record| record[offset] & 0x80 != 0,
+                |flag| flag == *edge,
+            );
+        }
+        NumericFilter::ScanDirection(direction) => {
+            let offset = scan_flags_offset(header);
+            let left_to_right = *direction == ScanDirection::LeftToRight;
+            evaluate(
+                records,
+                record_length,
+                out,
+                |record| record[offset] & 0x40 != 0,
+                |flag| flag == left_to_right,
+            );
+        }
+        NumericFilter::ScannerChannel(channel) => {
+            if header.point_format().is_extended {
+                evaluate(
+                    records,
+                    record_length,
+                    out,
+                    |record| (record[15] >> 4) & 0x03,
+                    |record_channel| record_channel == *channel,
+                );
+            } else {
+                out.fill(*channel == 0);
+            }
+        }
+        // Testing against polygons and corridors takes branches anyway
+        NumericFilter::Polygons(..) | NumericFilter::Corridor(_) => {
+            for (keep, record) in out.iter_mut().zip(records.chunks_exact(record_length)) {
+                *keep = filter.matches(&PointView::from_record(record, header));
+            }
+        }
+    }
+}
+
+/// Gathers a field from `LANES` records at a time into an array and tests the whole array, with
+/// the records left over at the end tested one by one.
+#[inline(always)]
+fn evaluate<T: Copy + Default>(
+    records: &[u8],
+    record_length: usize,
+    out: &mut [bool],
+    field: impl Fn(&[u8]) -> T,
+    test: impl Fn(T) -> bool,
+) {
+    let blocks = records.chunks(record_length * LANES);
+    for (keep, block) in out.chunks_mut(LANES).zip(blocks) {
+        if keep.len() == LANES {
+            let mut values = [T::default(); LANES];
+            for (value, record) in values.iter_mut().zip(block.chunks_exact(record_length)) {
+                *value = field(record);
+            }
+            let mut lanes = [false; LANES];
+            for (lane, value) in lanes.iter_mut().zip(values) {
+                *lane = test(value);
+            }
+            keep.copy_from_slice(&lanes);
+        } else {
+            for (keep, record) in keep.iter_mut().zip(block.chunks_exact(record_length)) {
+                *keep = test(field(record));
+            }
+        }
+    }
+}
+
+/// The range of raw values whose coordinates are within `min..=max`, or `None` if the scale is
+/// negative. The ends are found by applying the transform, so they agree with testing the
+/// coordinates themselves. An empty range comes out with its start past its end.
+fn raw_range(transform: &Transform, min: f64, max: f64) -> Option<(i64, i64)> {
+    if transform.scale <= 0.0 {
+        return None;
+    }
+    if min.is_nan() || max.is_nan() || min > max {
+        return Some((1, 0));
+    }
+    let direct = |raw: i64| transform.direct(raw as i32);
+    let (lowest, highest) = (i64::from(i32::MIN), i64::from(i32::MAX));
+
+    let mut start =
+        (((min - transform.offset) / transform.scale).ceil() as i64).clamp(lowest, highest + 1);
+    while start > lowest && direct(start - 1) >= min {
+        start -= 1;
+    }
+    while start <= highest && direct(start) < min {
+        start += 1;
+    }
+
+    let mut end =
+        (((max - transform.offset) / transform.scale).floor() as i64).clamp(lowest - 1, highest);
+    while end < highest && direct(end + 1) <= max {
+        end += 1;
+    }
+    while end >= lowest && direct(end) > max {
+        end -= 1;
+    }
+    Some((start, end))
+}
+
+#[inline(always)]
+fn read_i32(record: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes([
+        record[offset],
+        record[offset + 1],
+        record[offset + 2],
+        record[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{ClassMask, ReturnSelection};
+    use las::{Reader, Vector};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_filter_records() {
+        let data = std::fs::read("tests/data/input1.las").unwrap();
+        let header = Reader::new(Cursor::new(data.clone()))
+            .unwrap()
+            .header()
+            .clone();
+        let start = las::raw::Header::read_from(&mut &data[..])
+            .unwrap()
+            .offset_to_point_data as usize;
+        // An odd number of records, so some are left over after the lanes
+        let records = &data[start..start + 1_001 * usize::from(header.point_format().len())];
+        let point = PointView::from_record(records, &header);
+        let (x, y, z) = (point.x(), point.y(), point.z());
+
+        let filters = [
+            // Bounds on the coordinates of the first point, which must keep it
+            NumericFilter::Bounds(las::Bounds {
+                min: Vector { x, y, z },
+                max: Vector {
+                    x: x + 50.0,
+                    y: y + 50.0,
+                    z,
+                },
+            }),
+            NumericFilter::Bounds(las::Bounds {
+                min: Vector { x, y, z: f64::NAN },
+                max: Vector { x, y, z },
+            }),
+            NumericFilter::Intensity(30_000..=50_000),
+            NumericFilter::Classes(ClassMask::new(&[2, 9])),
+            NumericFilter::Returns(ReturnSelection::First),
+            NumericFilter::Returns(ReturnSelection::Last),
+            NumericFilter::Returns(ReturnSelection::Single),
+            NumericFilter::ScannerChannel(0),
+            NumericFilter::EdgeOfFlightLine(false),
+            NumericFilter::ScanDirection(ScanDirection::LeftToRight),
+        ];
+        let record_length = usize::from(header.point_format().len());
+        for filter in &filters {
+            let mut out = vec![false; 1_001];
+            filter_records(filter, records, &header, &mut out);
+            let mut portable = vec![false; 1_001];
+            filter_records_portable(filter, records, &header, &mut portable);
+            assert_eq!(out, portable);
+            for (keep, record) in out.iter().zip(records.chunks_exact(record_length)) {
+                assert_eq!(
+                    *keep,
+                    filter.matches(&PointView::from_record(record, &header))
+                );
+            }
+        }
+
+        let mut out = vec![false; 1_001];
+        filter_records(&filters[0], records, &header, &mut out);
+        assert!(out[0]);
+        filter_records(&filters[1], records, &header, &mut out);
+        assert!(!out.contains(&true));
+    }
+}
@@ -0,0 +1,194 @@
+//! Outputs that hand the points to the caller instead of writing a file.
+//!
+//! [`LasProcessor::with_sink`](crate::LasProcessor::with_sink) adds a [`PointSink`] as an output
+//! reported under the name it is given. The processor keeps the sink and writes to it through an
+//! [`OutputTarget`], which is either a path or a sink. [`MemoryOutput`] is a sink collecting the
+//! points in memory, and [`LasProcessor::process_with`] hands every batch to a callback.
+use crate::errors::MyError;
+use crate::report::ProcessingReport;
+use crate::LasProcessor;
+use las::Point;
+use std::sync::{Arc, Mutex};
+
+/// Receives the points of an output, a batch at a time.
+pub trait PointSink: Send {
+    /// Receives a batch of points. The points should be taken out of `points`, which is reused
+    /// for later batches.
+    fn write_points(&mut self, points: &mut Vec<Point>) -> Result<(), MyError>;
+
+    /// Called once every point has been received, if the run succeeded.
+    fn finish(&mut self) -> Result<(), MyError> {
+        Ok(())
+    }
+}
+
+impl PointSink for Vec<Point> {
+    fn write_points(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        self.append(points);
+        Ok(())
+    }
+}
+
+/// Collects the points of an output in memory. Clones share the same points, so keep one to
+/// take the points out once processing is done.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryOutput {
+    points: Arc<Mutex<Vec<Point>>>,
+}
+
+impl MemoryOutput {
+    /// Creates an empty output.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the points collected so far, leaving the output empty.
+    pub fn take(&self) -> Vec<Point> {
+        self.points
+            .lock()
+            .map(|mut points| std::mem::take(&mut *points))
+            .unwrap_or_default()
+    }
+
+    /// The number of points collected so far.
+    pub fn len(&self) -> usize {
+        self.points.lock().map(|points| points.len()).unwrap_or(0)
+    }
+
+    /// Returns `true` if no point has been collected.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl PointSink for MemoryOutput {
+    fn write_points(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        self.points
+            .lock()
+            .map_err(|_| MyError::LockError)?
+            .append(points);
+        Ok(())
+    }
+}
+
+/// Calls a callback with the batches of one output.
+struct CallbackSink<F> {
+    index: usize,
+    callback: Arc<F>,
+}
+
+impl<F: Fn(usize, &[Point]) + Send + Sync> PointSink for CallbackSink<F> {
+    fn write_points(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        (self.callback)(self.index, points);
+        points.clear();
+        Ok(())
+    }
+}
+
+impl LasProcessor {
+    /// Processes the inputs like [`LasProcessor::process_lidar_files`], but instead of writing
+    /// the outputs calls `callback` with the index of the output and each batch of points meeting
+    /// its condition. The callback is called from several threads at once when there are several
+    /// outputs. The outputs are reported under their original paths.
+    pub fn process_with<F>(mut self, callback: F) -> Result<ProcessingReport, MyError>
+    where
+        F: Fn(usize, &[Point]) + Send + Sync + 'static,
+    {
+        let callback = Arc::new(callback);
+        let names = std::mem::take(&mut self.output_paths);
+        let conditions = std::mem::take(&mut self.conditions);
+        for (index, condition) in conditions.into_iter().enumerate() {
+            let name = names
+                .get(index)
+                .cloned()
+                .unwrap_or_else(|| index.to_string());
+            let sink = CallbackSink {
+                index,
+                callback: Arc::clone(&callback),
+            };
+            self = self.with_sink(&name, sink, condition);
+        }
+        self.process_lidar_files()
+    }
+}
+
+pub(crate) type SharedSink = Arc<Mutex<Box<dyn PointSink>>>;
+
+/// Where the points of an output go.
+#[derive(Clone)]
+pub(crate) enum OutputTarget {
+    /// A file, stdout or an object, written by its path (see [`crate::output`]).
+    Path(String),
+    /// A sink handed over by the caller.
+    Sink(SharedSink),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Condition, LasProcessor, NumericFilter};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn test_memory_output() {
+        let kept = MemoryOutput::new();
+        let processor = LasProcessor::builder()
+            .input("tests/data/input1.las")
+            .sink(
+                "kept",
+                kept.clone(),
+                Condition::numeric(NumericFilter::Intensity(0..=39_999)),
+            )
+            .batch_size(100)
+            .build();
+        let report = processor.process_lidar_files().unwrap();
+
+        let expected: Vec<Point> = las::Reader::from_path("tests/data/input1.las")
+            .unwrap()
+            .points()
+            .map(|point| point.unwrap())
+            .filter(|point| point.intensity < 40_000)
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(report.outputs[0].points_written, expected.len() as u64);
+        assert_eq!(report.outputs[0].path, "kept");
+        assert_eq!(kept.len(), expected.len());
+        assert_eq!(kept.take(), expected);
+        assert!(kept.is_empty());
+    }
+
+    #[test]
+    fn test_process_with() {
+        let counts = Arc::new([AtomicU64::new(0), AtomicU64::new(0)]);
+        let counted = Arc::clone(&counts);
+        let report = LasProcessor::new(
+            vec!["tests/data/input1.las".to_string()],
+            vec!["dim.las".to_string(), "bright.las".to_string()],
+            vec![
+                Arc::new(|point: &Point| point.intensity < 40_000),
+                Arc::new(|point: &Point| point.intensity >= 40_000),
+            ],
+            false,
+        )
+        .with_batch_size(100)
+        .process_with(move |index, batch| {
+            counted[index].fetch_add(batch.len() as u64, Ordering::Relaxed);
+        })
+        .unwrap();
+
+        let total = las::Reader::from_path("tests/data/input1.las")
+            .unwrap()
+            .header()
+            .number_of_points();
+        assert_eq!(report.outputs[0].path, "dim.las");
+        for (output, count) in report.outputs.iter().zip(counts.iter()) {
+            assert_eq!(output.points_written, count.load(Ordering::Relaxed));
+        }
+        assert_eq!(
+            report.outputs[0].points_written + report.outputs[1].points_written,
+            total
+        );
+        // Nothing was written to disk
+        assert!(!std::path::Path::new("dim.las").exists());
+    }
+}
@@ -0,0 +1,184 @@
+//! Recording which input each point of an output came from, to trace problems back to the tiles
+//! they came from after merging.
+//!
+//! The index of the input, in the order the inputs were given, is written to each point either in
+//! place of its point source ID or as a `source` attribute added to the extra bytes. Either way
+//! the outputs get a VLR listing the inputs, one path per line, so the index of a point leads to
+//! its file with [`source_paths`].
+//!
+//! [`SourceIds`] instead sets the point source ID of the points of chosen inputs to a given value,
+//! a common fix for strips delivered with all their source IDs left at 0.
+use crate::errors::MyError;
+use crate::extra_bytes::{append_attributes, Attribute, AttributeType};
+use las::{Builder, Header, Point, Vlr};
+use std::path::Path;
+
+/// The user id of the VLR listing the inputs.
+pub const LOOKUP_USER_ID: &str = "las_trimmer";
+
+/// The record id of the VLR listing the inputs.
+pub const LOOKUP_RECORD_ID: u16 = 1;
+
+/// Where the index of the input of each point is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceTag {
+    /// In place of the point source ID, which limits the inputs to 65,536.
+    PointSourceId,
+    /// In a `source` attribute of 4 bytes added after the other extra bytes.
+    ExtraByte,
+}
+
+impl SourceTag {
+    /// The header of outputs made from `header` with the points of `paths` tagged: with the
+    /// `source` attribute if it is added, and the VLR listing the inputs.
+    pub fn header(self, header: Header, paths: &[String]) -> Result<Header, MyError> {
+        if self == SourceTag::PointSourceId && paths.len() > usize::from(u16::MAX) + 1 {
+            return Err(MyError::TooManySources(paths.len()));
+        }
+        let mut builder = Builder::from(header);
+        builder
+            .vlrs
+            .retain(|vlr| vlr.user_id != LOOKUP_USER_ID || vlr.record_id != LOOKUP_RECORD_ID);
+        if self == SourceTag::ExtraByte {
+            append_attributes(
+                &mut builder,
+                &[Attribute {
+                    name: "source",
+                    attribute_type: AttributeType::UnsignedLong,
+                    description: "Index of the input file",
+                }],
+            );
+        }
+        let lookup = Vlr {
+            user_id: LOOKUP_USER_ID.to_string(),
+            record_id: LOOKUP_RECORD_ID,
+            description: "Source files".to_string(),
+            data: paths.join("\n").into_bytes(),
+        };
+        if lookup.data.len() > usize::from(u16::MAX) {
+            builder.evlrs.push(lookup);
+        } else {
+            builder.vlrs.push(lookup);
+        }
+        Ok(builder.into_header()?)
+    }
+
+    /// Tags `point` as coming from the input at `index`.
+    pub fn tag(self, point: &mut Point, index: usize) {
+        match self {
+            SourceTag::PointSourceId => point.point_source_id = index as u16,
+            SourceTag::ExtraByte => point
+                .extra_bytes
+                .extend_from_slice(&(index as u32).to_le_bytes()),
+        }
+    }
+}
+
+/// Point source IDs given to the points of inputs, by file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceIds(Vec<(String, u16)>);
+
+impl SourceIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gives the points of the inputs at `path` the point source ID `id`. The path matches inputs
+    /// ending with it, so a file name matches that file in any folder. Later assignments win.
+    pub fn with(mut self, path: impl Into<String>, id: u16) -> Self {
+        self.0.push((path.into(), id));
+        self
+    }
+
+    /// The point source ID given to the points of `input`, if any.
+    pub fn id_of(&self, input: &str) -> Option<u16> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(path, _)| matches_input(path, input))
+            .map(|&(_, id)| id)
+    }
+
+    /// The paths of the assignments matching none of `inputs`.
+    pub fn unmatched<'a>(&'a self, inputs: &[String]) -> Vec<&'a str> {
+        self.0
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .filter(|path| !inputs.iter().any(|input| matches_input(path, input)))
+            .collect()
+    }
+}
+
+pub(crate) fn matches_input(path: &str, input: &str) -> bool {
+    path == input || Path::new(input).ends_with(path)
+}
+
+/// The inputs listed in `header` by a run tagging the points with their source, by index.
+pub fn source_paths(header: &Header) -> Option<Vec<String>> {
+    let vlr = header
+        .vlrs()
+        .iter()
+        .chain(header.evlrs())
+        .find(|vlr| vlr.user_id == LOOKUP_USER_ID && vlr.record_id == LOOKUP_RECORD_ID)?;
+    let paths = String::from_utf8_lossy(&vlr.data);
+    Some(paths.split('\n').map(String::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LasProcessor;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_source_tag() {
+        let inputs = vec![
+            "tests/data/input1.las".to_string(),
+            "tests/data/input1.las".to_string(),
+        ];
+        let points = las::Reader::from_path(&inputs[0])
+            .unwrap()
+            .header()
+            .number_of_points();
+        let dir = tempfile::tempdir().unwrap();
+        for tag in [SourceTag::PointSourceId, SourceTag::ExtraByte] {
+            let output = dir.path().join(format!("{:?}.las", tag));
+            let output = output.to_str().unwrap().to_string();
+            LasProcessor::new(inputs.clone(), vec![output.clone()], Vec::new(), false)
+                .with_conditions(vec![crate::Condition::on_point(Arc::new(|_| true))])
+                .with_source_tag(tag)
+                .with_reader_threads(1)
+                .with_observer(Arc::new(crate::NoProgress))
+                .process_lidar_files()
+                .unwrap();
+
+            let mut reader = las::Reader::from_path(&output).unwrap();
+            assert_eq!(source_paths(reader.header()).unwrap(), inputs);
+            let mut counts = [0u64; 2];
+            for point in reader.points() {
+                let point = point.unwrap();
+                let index = match tag {
+                    SourceTag::PointSourceId => usize::from(point.point_source_id),
+                    SourceTag::ExtraByte => {
+                        let bytes = &point.extra_bytes[point.extra_bytes.len() - 4..];
+                        u32::from_le_bytes(bytes.try_into().unwrap()) as usize
+                    }
+                };
+                counts[index] += 1;
+            }
+            assert_eq!(counts, [points, points]);
+        }
+
+        let ids = SourceIds::new()
+            .with("strip.las", 17)
+            .with("b/strip.las", 18)
+            .with("other.las", 3);
+        assert_eq!(ids.id_of("a/strip.las"), Some(17));
+        assert_eq!(ids.id_of("b/strip.las"), Some(18));
+        assert_eq!(ids.id_of("a/mystrip.las"), None);
+        assert_eq!(
+            ids.unmatched(&["a/strip.las".to_string()]),
+            ["b/strip.las", "other.las"]
+        );
+    }
+}
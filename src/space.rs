@@ -0,0 +1,140 @@
+//! Checking that the outputs of a run fit on disk before any point is read.
+//!
+//! The outputs are sized by `LasProcessor::plan` as if every point went to each of them, so the
+//! check errs on the safe side: a run whose filters keep few points may be refused although it
+//! would fit, which is why it is only made when asked for. Outputs on the same filesystem are added up, and outputs written to stdout or object
+//! storage count against the temporary directory, where they are staged. The free space can only
+//! be found on Unix, elsewhere the check passes.
+use crate::errors::MyError;
+use crate::output::is_stdout;
+use crate::plan::PlannedOutput;
+use crate::remote;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Fails with `MyError::InsufficientSpace` if `outputs` may not fit in the free space of the
+/// filesystems they are written to. Outputs in folders that don't exist are left to fail when
+/// they are created.
+pub fn check_space(outputs: &[PlannedOutput]) -> Result<(), MyError> {
+    // The filesystem, a folder on it and the bytes the outputs on it may need
+    let mut needed: Vec<(u64, PathBuf, u64)> = Vec::new();
+    for output in outputs {
+        let dir = staging_dir(&output.path);
+        let Ok(device) = device(&dir) else {
+            continue;
+        };
+        match needed.iter_mut().find(|(other, _, _)| *other == device) {
+            Some((_, _, bytes)) => *bytes = bytes.saturating_add(output.estimated_bytes),
+            None => needed.push((device, dir, output.estimated_bytes)),
+        }
+    }
+    for (_, dir, bytes) in needed {
+        let Ok(available) = available_space(&dir) else {
+            continue;
+        };
+        if bytes > available {
+            return Err(MyError::InsufficientSpace {
+                path: dir.to_string_lossy().to_string(),
+                needed: bytes,
+                available,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The folder the output at `path` is written to before it is finished.
+fn staging_dir(path: &str) -> PathBuf {
+    if is_stdout(path) || remote::is_remote(path) {
+        env::temp_dir()
+    } else {
+        Path::new(path)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_path_buf()
+    }
+}
+
+/// The ID of the filesystem holding `dir`.
+#[cfg(unix)]
+fn device(dir: &Path) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(std::fs::metadata(dir)?.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_dir: &Path) -> io::Result<u64> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// The number of bytes that unprivileged users may still write to the filesystem holding `dir`.
+#[cfg(unix)]
+pub fn available_space(dir: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    // SAFETY: `path` is a valid C string and `stats` a valid buffer for `statvfs` to fill in.
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The types of the fields differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+/// The number of bytes that may still be written to the filesystem holding `dir`. Always fails,
+/// as the free space can only be found on Unix.
+#[cfg(not(unix))]
+pub fn available_space(_dir: &Path) -> io::Result<u64> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn output(path: &Path, estimated_bytes: u64) -> PlannedOutput {
+        PlannedOutput {
+            path: path.to_str().unwrap().to_string(),
+            compressed: false,
+            max_points: 0,
+            parts: 1,
+            estimated_bytes,
+            exists: false,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let available = available_space(dir.path()).unwrap();
+        assert!(available > 0);
+
+        let small = output(&dir.path().join("small.las"), 1024);
+        check_space(&[small.clone(), small]).unwrap();
+
+        // Two outputs that fit on their own but not together
+        let half = output(&dir.path().join("half.las"), available / 2 + 1);
+        let halves = [half.clone(), half];
+        check_space(&halves[..1]).unwrap();
+        assert!(matches!(
+            check_space(&halves),
+            Err(MyError::InsufficientSpace { .. })
+        ));
+
+        // Sizes that overflow when added up
+        let huge = output(&dir.path().join("huge.las"), u64::MAX);
+        assert!(matches!(
+            check_space(&[huge.clone(), huge]),
+            Err(MyError::InsufficientSpace { .. })
+        ));
+
+        let missing = output(&dir.path().join("missing/huge.las"), u64::MAX);
+        check_space(&[missing]).unwrap();
+    }
+}
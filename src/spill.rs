@@ -0,0 +1,91 @@
+//! Spilling the points held by stages that need every point to temporary files.
+//!
+//! Sorting and neighbourhood filtering can't pass a point on before they have seen every point,
+//! so they hold what they are given. They keep up to a [`SpillConfig`]'s buffer of points in
+//! memory and write the rest to temporary files, in its folder or the system's. The default buffer
+//! suits most machines; the low-memory one, with the smaller batches and shallower channel set by
+//! [`LasProcessor::with_low_memory`](crate::LasProcessor::with_low_memory), trades speed for
+//! a hard ceiling on the memory a run takes.
+//!
+//! Sorts spill through an [`ExternalSort`](crate::external_sort::ExternalSort), neighbourhood
+//! filters through a [`TileSpill`](crate::neighborhood::TileSpill).
+use crate::errors::MyError;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// The bytes of points a spilling stage holds in memory unless told otherwise.
+pub const DEFAULT_SPILL_BYTES: usize = 16 << 20;
+/// The bytes of points a spilling stage holds in memory in low-memory mode.
+pub const LOW_MEMORY_SPILL_BYTES: usize = 1 << 20;
+/// The batch size in low-memory mode.
+pub const LOW_MEMORY_BATCH_SIZE: u64 = 4_096;
+/// The channel depth in low-memory mode.
+pub const LOW_MEMORY_CHANNEL_DEPTH: usize = 1;
+
+/// Where spilled points go, and how many bytes of them are held before they are spilled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpillConfig {
+    dir: Option<PathBuf>,
+    buffer_bytes: usize,
+    compressed_runs: bool,
+}
+
+impl Default for SpillConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            buffer_bytes: DEFAULT_SPILL_BYTES,
+            compressed_runs: false,
+        }
+    }
+}
+
+impl SpillConfig {
+    /// Spills to the system's temporary folder, holding [`DEFAULT_SPILL_BYTES`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spills to a temporary folder made in `dir`, e.g. on a disk with room to spare.
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Holds up to `bytes` bytes of points before spilling them. A value of 0 is treated as 1.
+    pub fn with_buffer_bytes(mut self, bytes: usize) -> Self {
+        self.buffer_bytes = bytes.max(1);
+        self
+    }
+
+    /// Writes the runs of sorts as LAZ rather than as raw point records, taking several times
+    /// less disk for the time spent compressing. Neighbourhood filters spill raw records either
+    /// way, as they append to their tiles.
+    pub fn with_compressed_runs(mut self, compressed_runs: bool) -> Self {
+        self.compressed_runs = compressed_runs;
+        self
+    }
+
+    /// The folder the temporary folders are made in, or `None` for the system's.
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// The bytes of points held before they are spilled.
+    pub fn buffer_bytes(&self) -> usize {
+        self.buffer_bytes
+    }
+
+    /// Whether the runs of sorts are written as LAZ.
+    pub fn compressed_runs(&self) -> bool {
+        self.compressed_runs
+    }
+
+    /// Makes a temporary folder for a spill, removed when it is dropped.
+    pub fn tempdir(&self) -> Result<TempDir, MyError> {
+        Ok(match &self.dir {
+            Some(dir) => tempfile::tempdir_in(dir)?,
+            None => tempfile::tempdir()?,
+        })
+    }
+}
@@ -0,0 +1,79 @@
+//! Splitting large inputs into ranges of points that are read by separate threads.
+//!
+//! When there are fewer inputs than reader threads, each input is cut into ranges that are read
+//! concurrently, every thread seeking to the start of its range. For LAZ files with fixed-size
+//! chunks the range boundaries are put on chunk boundaries, so no chunk is decompressed twice.
+use crate::archive;
+use crate::remote;
+use las::Header;
+use std::ops::Range;
+
+/// The smallest number of points worth giving a thread of its own by default.
+pub const DEFAULT_SPLIT_SIZE: u64 = 1_000_000;
+
+/// Returns `true` if `path` can be opened again cheaply, which splitting it needs: one reader is
+/// opened per range. Remote inputs and ZIP entries would be downloaded or extracted once per range.
+pub fn can_split(path: &str) -> bool {
+    !(remote::is_remote(path) || remote::is_http(path) || archive::split_entry(path).is_some())
+}
+
+/// The number of points range boundaries should be a multiple of for the points of `header`:
+/// the chunk size for LAZ files with fixed-size chunks, and 1 for LAS files. Returns `None` for
+/// LAZ files with variable-size chunks, which can't be split because the parallel LAZ
+/// decompressor doesn't support seeking in them.
+pub fn chunk_alignment(header: &Header) -> Option<u64> {
+    match header.laz_vlr() {
+        Some(vlr) if vlr.uses_variable_size_chunks() => None,
+        Some(vlr) => Some(u64::from(vlr.chunk_size()).max(1)),
+        None => Some(1),
+    }
+}
+
+/// Splits `number_of_points` points into at most `parts` ranges of at least `min_size` points
+/// each, with boundaries at multiples of `alignment`. Returns a single range if the points
+/// aren't worth splitting, and none if there are no points.
+pub fn split_ranges(
+    number_of_points: u64,
+    parts: usize,
+    min_size: u64,
+    alignment: u64,
+) -> Vec<Range<u64>> {
+    let parts = (parts as u64)
+        .min(number_of_points / min_size.max(1))
+        .max(1);
+    let alignment = alignment.max(1);
+    let size = number_of_points.div_ceil(parts).div_ceil(alignment) * alignment;
+    if size == 0 {
+        return Vec::new();
+    }
+    (0..number_of_points)
+        .step_by(size as usize)
+        .map(|start| start..(start + size).min(number_of_points))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_ranges() {
+        assert_eq!(
+            split_ranges(100, 4, 10, 1),
+            [0..25, 25..50, 50..75, 75..100]
+        );
+        // Not enough points for four ranges of 40
+        assert_eq!(split_ranges(100, 4, 40, 1), [0..50, 50..100]);
+        // Boundaries on chunks of 30 points
+        assert_eq!(
+            split_ranges(100, 4, 10, 30),
+            [0..30, 30..60, 60..90, 90..100]
+        );
+        let whole = split_ranges(100, 1, 10, 1);
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0], 0..100);
+        assert!(split_ranges(0, 4, 10, 1).is_empty());
+        assert!(can_split("tile.laz"));
+        assert!(!can_split("s3://bucket/tile.laz"));
+    }
+}
@@ -0,0 +1,536 @@
+//! Chains of stages the points go through in turn, for work a condition per output can't express.
+//!
+//! A [`LasProcessor`] tests each output's condition against the points as they were read, so
+//! reclassifying the points and then keeping a class, say, takes two runs. A [`Pipeline`] hands
+//! the points to its [`Stage`]s in order instead: filters drop points, transforms change them,
+//! thinning keeps one point in so many, and sorting holds the points back until every point is
+//! in. Writes and sinks take a copy of the points reaching them and pass the points on, so a
+//! pipeline can write what it has at several points along the chain. Branches run a copy of the
+//! points through stages of their own, so several jobs can share the reading and decompression
+//! of the inputs, which usually take most of the time of a run.
+//!
+//! The stages run on the points of each batch in the order they come from the readers, one batch
+//! at a time, so thinning keeps a different selection of points from run to run unless the
+//! points are sorted first. Sorts spill sorted runs of the points they hold to disk as set by the
+//! processor's [`SpillConfig`], so they can sort more points than fit in memory.
+use crate::compression::LazChunking;
+use crate::errors::MyError;
+use crate::external_sort::{ExternalSort, PointOrder};
+use crate::filter::{Condition, PointView};
+use crate::input::open_reader;
+use crate::output::{check_output_paths, OutputWriter};
+use crate::report::{OutputReport, ProcessingReport};
+use crate::sink::PointSink;
+use crate::spill::SpillConfig;
+use crate::transform::ConditionalTransform;
+use crate::LasProcessor;
+use las::{Bounds, Header, Point};
+use std::cmp::Ordering;
+use std::sync::{Arc, Mutex};
+
+/// What the points are sorted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// The GPS time, the order the points were scanned in.
+    GpsTime,
+    /// The point source ID, then the GPS time, grouping the points by flight line.
+    PointSourceId,
+    /// The elevation, lowest first.
+    Z,
+    /// The position along a Z-order curve over X and Y within the bounds of the header, so that
+    /// points near each other are mostly near each other in the file too.
+    Morton,
+}
+
+impl SortKey {
+    /// The order of the key, for points within the bounds of `header`.
+    pub fn order(self, header: &Header) -> PointOrder {
+        let time = |point: &Point| point.gps_time.unwrap_or_default();
+        match self {
+            SortKey::GpsTime => Arc::new(move |a: &Point, b: &Point| time(a).total_cmp(&time(b))),
+            SortKey::PointSourceId => Arc::new(move |a: &Point, b: &Point| {
+                a.point_source_id
+                    .cmp(&b.point_source_id)
+                    .then_with(|| time(a).total_cmp(&time(b)))
+            }),
+            SortKey::Z => Arc::new(|a: &Point, b: &Point| a.z.total_cmp(&b.z)),
+            SortKey::Morton => {
+                let bounds = header.bounds();
+                Arc::new(move |a: &Point, b: &Point| {
+                    morton_code(&bounds, a).cmp(&morton_code(&bounds, b))
+                })
+            }
+        }
+    }
+}
+
+/// The position of `point` along a Z-order curve over X and Y, with `bounds` split into 2^32
+/// steps each way. Points outside the bounds are taken to their edge.
+fn morton_code(bounds: &Bounds, point: &Point) -> u64 {
+    let step = |value: f64, min: f64, max: f64| {
+        if max > min {
+            ((value - min) / (max - min) * f64::from(u32::MAX)).clamp(0.0, f64::from(u32::MAX))
+                as u32
+        } else {
+            0
+        }
+    };
+    let x = step(point.x, bounds.min.x, bounds.max.x);
+    let y = step(point.y, bounds.min.y, bounds.max.y);
+    spread_bits(x) | spread_bits(y) << 1
+}
+
+/// The bits of `value` spread out to the even bits of the result.
+fn spread_bits(value: u32) -> u64 {
+    let mut bits = u64::from(value);
+    bits = (bits | bits << 16) & 0x0000_ffff_0000_ffff;
+    bits = (bits | bits << 8) & 0x00ff_00ff_00ff_00ff;
+    bits = (bits | bits << 4) & 0x0f0f_0f0f_0f0f_0f0f;
+    bits = (bits | bits << 2) & 0x3333_3333_3333_3333;
+    (bits | bits << 1) & 0x5555_5555_5555_5555
+}
+
+/// A step of a [`Pipeline`].
+pub enum Stage {
+    /// Drops the points the condition doesn't pass.
+    Filter(Condition),
+    /// Changes the points.
+    Transform(ConditionalTransform),
+    /// Keeps the first of every so many points, so `Thin(1)` keeps every point.
+    Thin(u64),
+    /// Holds the points back until every point is in, and passes them on sorted, in an
+    /// [`ExternalSort`] spilling as the processor's [`SpillConfig`] says. Morton order is within
+    /// the bounds of the first input.
+    Sort(SortKey),
+    /// Like [`Stage::Sort`], in an order of the caller's own.
+    SortBy(PointOrder),
+    /// Writes the points to a LAS/LAZ file, with the header of the first input as the template.
+    Write(String),
+    /// Hands the points to a sink.
+    Sink(Box<dyn PointSink>),
+    /// Runs a copy of the points through stages of their own, leaving the points going on
+    /// unchanged.
+    Branch(Vec<Stage>),
+}
+
+/// A chain of stages the points of some inputs go through.
+///
+/// # Example
+///
+/// ```no_run
+/// use las_trimmer::{ConditionalTransform, Condition, Pipeline, PointFlag, Transform};
+/// use std::sync::Arc;
+///
+/// let low = Condition::on_point(Arc::new(|point| point.z < 2.0));
+/// let withheld = Condition::on_point(Arc::new(|point| point.is_withheld));
+/// Pipeline::new(vec!["tests/data/input1.las".to_string()])
+///     .transform(ConditionalTransform::new(Transform::SetFlag(PointFlag::Withheld, true)).when(low))
+///     .write("all.laz")
+///     .filter(!withheld)
+///     .write("kept.laz")
+///     .run()
+///     .unwrap();
+/// ```
+pub struct Pipeline {
+    inputs: Vec<String>,
+    stages: Vec<Stage>,
+    overwrite: bool,
+}
+
+impl Pipeline {
+    /// A pipeline over `inputs` with no stages.
+    pub fn new(inputs: Vec<String>) -> Self {
+        Self {
+            inputs,
+            stages: Vec::new(),
+            overwrite: false,
+        }
+    }
+
+    /// Adds `stage` at the end of the chain.
+    pub fn stage(mut self, stage: Stage) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Adds a [`Stage::Filter`].
+    pub fn filter(self, condition: Condition) -> Self {
+        self.stage(Stage::Filter(condition))
+    }
+
+    /// Adds a [`Stage::Transform`].
+    pub fn transform(self, transform: ConditionalTransform) -> Self {
+        self.stage(Stage::Transform(transform))
+    }
+
+    /// Adds a [`Stage::Thin`].
+    pub fn thin(self, every: u64) -> Self {
+        self.stage(Stage::Thin(every))
+    }
+
+    /// Adds a [`Stage::Sort`].
+    pub fn sort(self, key: SortKey) -> Self {
+        self.stage(Stage::Sort(key))
+    }
+
+    /// Adds a [`Stage::SortBy`].
+    pub fn sort_by(
+        self,
+        order: impl Fn(&Point, &Point) -> Ordering + Send + Sync + 'static,
+    ) -> Self {
+        self.stage(Stage::SortBy(Arc::new(order)))
+    }
+
+    /// Adds a [`Stage::Write`].
+    pub fn write(self, path: impl Into<String>) -> Self {
+        self.stage(Stage::Write(path.into()))
+    }
+
+    /// Adds a [`Stage::Sink`].
+    pub fn sink(self, sink: impl PointSink + 'static) -> Self {
+        self.stage(Stage::Sink(Box::new(sink)))
+    }
+
+    /// Adds a [`Stage::Branch`] with the stages `build` adds to an empty pipeline. Independent
+    /// jobs, each with its own filters, transforms and writes, can share a single read of the
+    /// inputs as branches of one pipeline.
+    pub fn branch(self, build: impl FnOnce(Pipeline) -> Pipeline) -> Self {
+        let branch = build(Pipeline::new(Vec::new()));
+        self.stage(Stage::Branch(branch.stages))
+    }
+
+    /// Replaces existing files with the written ones rather than failing.
+    pub fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Runs the pipeline with a processor left as [`LasProcessor::new`] makes it.
+    pub fn run(self) -> Result<ProcessingReport, MyError> {
+        self.run_with(|processor| processor)
+    }
+
+    /// Runs the pipeline, setting up the processor reading the inputs with `configure`, e.g. for
+    /// its threads or observer. The report has an output for each write stage, in order.
+    pub fn run_with(
+        self,
+        configure: impl FnOnce(LasProcessor) -> LasProcessor,
+    ) -> Result<ProcessingReport, MyError> {
+        let mut paths = Vec::new();
+        write_paths(&self.stages, &mut paths);
+        check_output_paths(&paths, &self.inputs, self.overwrite)?;
+        let header = match self.inputs.first() {
+            Some(input) => open_reader(input)?.header().clone(),
+            None => return Err(MyError::InvalidInputPath),
+        };
+        let processor = configure(LasProcessor::new(
+            self.inputs,
+            Vec::new(),
+            Vec::new(),
+            self.overwrite,
+        ));
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = StageSink {
+            chain: Chain::new(self.stages, &header, &processor.spill)?,
+            reports: Arc::clone(&reports),
+        };
+        let mut report = processor
+            .with_sink("pipeline", sink, Condition::on_point(Arc::new(|_| true)))
+            .process_lidar_files()?;
+        report.outputs = std::mem::take(&mut *reports.lock().map_err(|_| MyError::LockError)?);
+        Ok(report)
+    }
+}
+
+/// A stage as it runs.
+enum Step {
+    Filter(Condition),
+    Transform(ConditionalTransform),
+    Thin { every: u64, seen: u64 },
+    Sort(Option<ExternalSort>),
+    Write(Option<Box<OutputWriter>>),
+    Sink(Box<dyn PointSink>),
+    Branch(Chain),
+}
+
+/// The steps of a pipeline or of one of its branches.
+struct Chain {
+    steps: Vec<Step>,
+}
+
+impl Chain {
+    fn new(stages: Vec<Stage>, header: &Header, spill: &SpillConfig) -> Result<Self, MyError> {
+        let steps = stages
+            .into_iter()
+            .map(|stage| {
+                Ok(match stage {
+                    Stage::Filter(condition) => Step::Filter(condition),
+                    Stage::Transform(transform) => Step::Transform(transform),
+                    Stage::Thin(every) => Step::Thin {
+                        every: every.max(1),
+                        seen: 0,
+                    },
+                    Stage::Sort(key) => Step::Sort(Some(ExternalSort::new(
+                        key.order(header),
+                        header,
+                        spill.clone(),
+                    ))),
+                    Stage::SortBy(order) => {
+                        Step::Sort(Some(ExternalSort::new(order, header, spill.clone())))
+                    }
+                    Stage::Write(path) => Step::Write(Some(Box::new(OutputWriter::create(
+                        &path,
+                        header.clone(),
+                        LazChunking::default(),
+                    )?))),
+                    Stage::Sink(sink) => Step::Sink(sink),
+                    Stage::Branch(stages) => Step::Branch(Chain::new(stages, header, spill)?),
+                })
+            })
+            .collect::<Result<_, MyError>>()?;
+        Ok(Self { steps })
+    }
+
+    /// Runs `points` through the steps from `start` on.
+    fn run(&mut self, start: usize, mut points: Vec<Point>) -> Result<(), MyError> {
+        let last = self.steps.len().saturating_sub(1);
+        for (index, step) in self.steps.iter_mut().enumerate().skip(start) {
+            if points.is_empty() {
+                break;
+            }
+            // The steps taking a copy can have the points themselves at the end of the chain
+            let copy = |points: &mut Vec<Point>| {
+                if index == last {
+                    std::mem::take(points)
+                } else {
+                    points.clone()
+                }
+            };
+            match step {
+                Step::Filter(condition) => {
+                    let mut kept = Vec::with_capacity(points.len());
+                    for point in points {
+                        let mut view = PointView::from_point(point);
+                        if condition.matches(&mut view)? {
+                            kept.push(view.into_point()?);
+                        }
+                    }
+                    points = kept;
+                }
+                Step::Transform(transform) => {
+                    points = points
+                        .into_iter()
+                        .map(|point| transform.apply(point))
+                        .collect::<Result<_, _>>()?;
+                }
+                Step::Thin { every, seen } => {
+                    points.retain(|_| {
+                        let keep = (*seen).is_multiple_of(*every);
+                        *seen += 1;
+                        keep
+                    });
+                }
+                Step::Sort(spill) => {
+                    spill.as_mut().ok_or(MyError::LockError)?.add(&mut points)?;
+                    return Ok(());
+                }
+                Step::Write(writer) => {
+                    let writer = writer.as_mut().ok_or(MyError::LockError)?;
+                    writer.write_batch(&mut copy(&mut points))?;
+                }
+                Step::Sink(sink) => sink.write_points(&mut copy(&mut points))?,
+                Step::Branch(chain) => chain.run(0, copy(&mut points))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Passes on the points held by the sorts, in order, since the points a sort passes on can
+    /// reach the later ones.
+    fn release(&mut self) -> Result<(), MyError> {
+        for index in 0..self.steps.len() {
+            match &mut self.steps[index] {
+                Step::Sort(spill) => {
+                    if let Some(spill) = spill.take() {
+                        spill.finish(&mut |points| self.run(index + 1, points))?;
+                    }
+                }
+                Step::Branch(chain) => chain.release()?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes the writes and sinks, adding the reports of the writes to `reports`.
+    fn finish(&mut self, reports: &mut Vec<OutputReport>) -> Result<(), MyError> {
+        for step in &mut self.steps {
+            match step {
+                Step::Write(writer) => {
+                    if let Some(writer) = writer.take() {
+                        reports.extend(writer.finish()?);
+                    }
+                }
+                Step::Sink(sink) => sink.finish()?,
+                Step::Branch(chain) => chain.finish(reports)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The sink running the stages of a pipeline on the points of the processor.
+struct StageSink {
+    chain: Chain,
+    reports: Arc<Mutex<Vec<OutputReport>>>,
+}
+
+impl PointSink for StageSink {
+    fn write_points(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        self.chain.run(0, std::mem::take(points))
+    }
+
+    fn finish(&mut self) -> Result<(), MyError> {
+        self.chain.release()?;
+        let mut reports = Vec::new();
+        self.chain.finish(&mut reports)?;
+        *self.reports.lock().map_err(|_| MyError::LockError)? = reports;
+        Ok(())
+    }
+}
+
+/// Adds the paths of the write stages of `stages` to `paths`, in order.
+fn write_paths(stages: &[Stage], paths: &mut Vec<String>) {
+    for stage in stages {
+        match stage {
+            Stage::Write(path) => paths.push(path.clone()),
+            Stage::Branch(stages) => write_paths(stages, paths),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::MemoryOutput;
+    use crate::transform::{PointFlag, Transform};
+
+    #[test]
+    fn test_morton_order() {
+        let mut header = las::Builder::from((1, 4)).into_header().unwrap();
+        for (x, y) in [(0.0, 0.0), (4.0, 4.0)] {
+            header.add_point(&Point {
+                x,
+                y,
+                ..Default::default()
+            });
+        }
+        let order = SortKey::Morton.order(&header);
+        let mut points: Vec<Point> = [(3.0, 3.0), (1.0, 3.0), (3.0, 1.0), (1.0, 1.0), (9.0, -1.0)]
+            .iter()
+            .map(|&(x, y)| Point {
+                x,
+                y,
+                ..Default::default()
+            })
+            .collect();
+        points.sort_by(|a, b| order(a, b));
+        let positions: Vec<(f64, f64)> = points.iter().map(|point| (point.x, point.y)).collect();
+        // The quadrants in Z order, with the point outside the bounds taken to the corner
+        assert_eq!(
+            positions,
+            [(1.0, 1.0), (3.0, 1.0), (9.0, -1.0), (1.0, 3.0), (3.0, 3.0)]
+        );
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let input = "tests/data/input1.las";
+        let points: Vec<Point> = las::Reader::from_path(input)
+            .unwrap()
+            .points()
+            .map(|point| point.unwrap())
+            .collect();
+        let mut intensities: Vec<u16> = points.iter().map(|point| point.intensity).collect();
+        intensities.sort();
+        let median = intensities[intensities.len() / 2];
+
+        // Flag the bright points, then keep the flagged ones
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bright.las");
+        let path = path.to_str().unwrap();
+        let bright = Condition::on_point(Arc::new(move |point| point.intensity > median));
+        let synthetic = Condition::on_point(Arc::new(|point| point.is_synthetic));
+        let thinned = MemoryOutput::new();
+        let sorted = MemoryOutput::new();
+        let report = Pipeline::new(vec![input.to_string()])
+            .transform(
+                ConditionalTransform::new(Transform::SetFlag(PointFlag::Synthetic, true))
+                    .when(bright),
+            )
+            .filter(synthetic)
+            .write(path)
+            .thin(3)
+            .sink(thinned.clone())
+            .sort(SortKey::Z)
+            .sink(sorted.clone())
+            .run_with(|processor| processor.with_observer(Arc::new(crate::NoProgress)))
+            .unwrap();
+
+        let expected = points
+            .iter()
+            .filter(|point| point.intensity > median || point.is_synthetic)
+            .count() as u64;
+        assert!(expected > 0 && expected < points.len() as u64);
+        assert_eq!(report.outputs.len(), 1);
+        assert_eq!(report.outputs[0].points_written, expected);
+        let written = las::Reader::from_path(path).unwrap();
+        assert_eq!(written.header().number_of_points(), expected);
+
+        assert_eq!(thinned.len() as u64, expected.div_ceil(3));
+        let sorted = sorted.take();
+        assert_eq!(sorted.len(), thinned.len());
+        assert!(sorted.windows(2).all(|pair| pair[0].z <= pair[1].z));
+        assert!(sorted.iter().all(|point| point.is_synthetic));
+
+        // Two jobs sharing a read, where the transform of one doesn't reach the other
+        let bright_points = MemoryOutput::new();
+        let flagged_points = MemoryOutput::new();
+        let bright = Condition::on_point(Arc::new(move |point| point.intensity > median));
+        Pipeline::new(vec![input.to_string()])
+            .branch(|job| job.filter(bright).sink(bright_points.clone()))
+            .branch(|job| {
+                job.transform(ConditionalTransform::new(Transform::SetFlag(
+                    PointFlag::KeyPoint,
+                    true,
+                )))
+                .sink(flagged_points.clone())
+            })
+            .run_with(|processor| processor.with_observer(Arc::new(crate::NoProgress)))
+            .unwrap();
+        let bright_points = bright_points.take();
+        let expected: Vec<&Point> = points
+            .iter()
+            .filter(|point| point.intensity > median)
+            .collect();
+        assert_eq!(bright_points.len(), expected.len());
+        assert_eq!(
+            bright_points
+                .iter()
+                .filter(|point| point.is_key_point)
+                .count(),
+            expected.iter().filter(|point| point.is_key_point).count()
+        );
+        let flagged_points = flagged_points.take();
+        assert_eq!(flagged_points.len(), points.len());
+        assert!(flagged_points.iter().all(|point| point.is_key_point));
+
+        // Writes refuse to replace files
+        assert!(matches!(
+            Pipeline::new(vec![input.to_string()]).write(path).run(),
+            Err(MyError::OutputExists(_))
+        ));
+    }
+}
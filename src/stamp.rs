@@ -0,0 +1,158 @@
+//! Stamping the provenance of the outputs in their headers.
+//!
+//! A [`HeaderStamp`] sets or clears the system identifier, generating software, project GUID and
+//! creation date of the outputs, so that deliverables carry the same provenance whatever the
+//! inputs say. Without being told otherwise, the outputs name las-trimmer as their generating
+//! software, and keep the other fields of the inputs.
+use crate::errors::MyError;
+use chrono::NaiveDate;
+use las::{Builder, Header};
+use uuid::Uuid;
+
+/// The length of the system identifier and generating software fields of a LAS header.
+pub const FIELD_LENGTH: usize = 32;
+
+/// The generating software the outputs are stamped with by default, e.g. `las-trimmer v0.1`.
+pub fn default_generating_software() -> String {
+    format!(
+        "las-trimmer v{}.{}",
+        env!("CARGO_PKG_VERSION_MAJOR"),
+        env!("CARGO_PKG_VERSION_MINOR")
+    )
+}
+
+/// The header fields set on the outputs. The fields left `None` keep the value of the inputs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderStamp {
+    system_identifier: Option<String>,
+    generating_software: Option<String>,
+    guid: Option<Uuid>,
+    date: Option<Option<NaiveDate>>,
+}
+
+impl Default for HeaderStamp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeaderStamp {
+    /// A stamp naming las-trimmer as the generating software.
+    pub fn new() -> Self {
+        Self {
+            system_identifier: None,
+            generating_software: Some(default_generating_software()),
+            guid: None,
+            date: None,
+        }
+    }
+
+    /// Sets the system identifier, or clears it if `identifier` is empty.
+    pub fn with_system_identifier(mut self, identifier: &str) -> Self {
+        self.system_identifier = Some(identifier.to_string());
+        self
+    }
+
+    /// Sets the generating software, or clears it if `software` is empty.
+    pub fn with_generating_software(mut self, software: &str) -> Self {
+        self.generating_software = Some(software.to_string());
+        self
+    }
+
+    /// Keeps the generating software of the inputs rather than naming las-trimmer.
+    pub fn keeping_generating_software(mut self) -> Self {
+        self.generating_software = None;
+        self
+    }
+
+    /// Sets the project GUID. The nil GUID clears it.
+    pub fn with_guid(mut self, guid: Uuid) -> Self {
+        self.guid = Some(guid);
+        self
+    }
+
+    /// Sets the creation date, or clears it if `date` is `None`.
+    pub fn with_date(mut self, date: Option<NaiveDate>) -> Self {
+        self.date = Some(date);
+        self
+    }
+
+    /// The system identifier set, if it is.
+    pub fn system_identifier(&self) -> Option<&str> {
+        self.system_identifier.as_deref()
+    }
+
+    /// The generating software set, if it is.
+    pub fn generating_software(&self) -> Option<&str> {
+        self.generating_software.as_deref()
+    }
+
+    /// The header of the outputs, made from the header the points are read with. Identifiers
+    /// longer than the [`FIELD_LENGTH`] of the header fail with `MyError::HeaderFieldTooLong`.
+    pub fn header(&self, header: Header) -> Result<Header, MyError> {
+        let mut builder = Builder::from(header);
+        for (field, value) in [
+            ("system identifier", &self.system_identifier),
+            ("generating software", &self.generating_software),
+        ] {
+            if let Some(value) = value.as_ref().filter(|value| value.len() > FIELD_LENGTH) {
+                return Err(MyError::HeaderFieldTooLong(
+                    field.to_string(),
+                    value.clone(),
+                ));
+            }
+        }
+        if let Some(identifier) = &self.system_identifier {
+            builder.system_identifier = identifier.clone();
+        }
+        if let Some(software) = &self.generating_software {
+            builder.generating_software = software.clone();
+        }
+        if let Some(guid) = self.guid {
+            builder.guid = guid;
+        }
+        if let Some(date) = self.date {
+            builder.date = date;
+        }
+        Ok(builder.into_header()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_stamp() {
+        let mut builder = Builder::from((1, 2));
+        builder.system_identifier = "Survey rig 7".to_string();
+        builder.generating_software = "Vendor suite".to_string();
+        let header = builder.into_header().unwrap();
+
+        let stamped = HeaderStamp::new().header(header.clone()).unwrap();
+        assert_eq!(stamped.system_identifier(), "Survey rig 7");
+        assert_eq!(stamped.generating_software(), "las-trimmer v0.1");
+
+        let guid = Uuid::parse_str("6f1c2a9e-3b4d-4e5f-8a7b-9c0d1e2f3a4b").unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let stamped = HeaderStamp::new()
+            .with_system_identifier("")
+            .keeping_generating_software()
+            .with_guid(guid)
+            .with_date(Some(date))
+            .header(header.clone())
+            .unwrap();
+        assert_eq!(stamped.system_identifier(), "");
+        assert_eq!(stamped.generating_software(), "Vendor suite");
+        assert_eq!(stamped.guid(), guid);
+        assert_eq!(stamped.date(), Some(date));
+
+        let too_long = "x".repeat(FIELD_LENGTH + 1);
+        assert!(matches!(
+            HeaderStamp::new()
+                .with_system_identifier(&too_long)
+                .header(header),
+            Err(MyError::HeaderFieldTooLong(..))
+        ));
+    }
+}
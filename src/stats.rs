@@ -0,0 +1,81 @@
+//! Gathering statistics of the points of an output without writing them.
+//!
+//! A [`StatsOutput`] is a [`PointSink`] that counts the points it gets in a [`PointStats`] and
+//! drops them, so QA sweeps over an archive can look at what each output would hold without
+//! spending the bandwidth and disk space of writing it.
+use crate::errors::MyError;
+use crate::sink::PointSink;
+use crate::validate::PointStats;
+use las::Point;
+use std::sync::{Arc, Mutex};
+
+/// Gathers the statistics of the points of an output. Clones share the same statistics, so keep
+/// one to read them once processing is done.
+#[derive(Clone, Debug, Default)]
+pub struct StatsOutput {
+    stats: Arc<Mutex<PointStats>>,
+}
+
+impl StatsOutput {
+    /// Creates an output without points.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The statistics of the points gathered so far.
+    pub fn stats(&self) -> PointStats {
+        self.stats
+            .lock()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl PointSink for StatsOutput {
+    fn write_points(&mut self, points: &mut Vec<Point>) -> Result<(), MyError> {
+        let mut stats = self.stats.lock().map_err(|_| MyError::LockError)?;
+        for point in points.drain(..) {
+            stats.add(&point);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Condition, LasProcessor, NumericFilter};
+
+    #[test]
+    fn test_stats_output() {
+        let dim = StatsOutput::new();
+        let report = LasProcessor::builder()
+            .input("tests/data/input1.las")
+            .sink(
+                "dim",
+                dim.clone(),
+                Condition::numeric(NumericFilter::Intensity(0..=39_999)),
+            )
+            .build()
+            .process_lidar_files()
+            .unwrap();
+
+        let mut expected = PointStats::default();
+        for point in las::Reader::from_path("tests/data/input1.las")
+            .unwrap()
+            .points()
+        {
+            let point = point.unwrap();
+            if point.intensity < 40_000 {
+                expected.add(&point);
+            }
+        }
+        let stats = dim.stats();
+        assert!(stats.number_of_points > 0);
+        assert_eq!(stats.number_of_points, report.outputs[0].points_written);
+        assert_eq!(stats.bounds, expected.bounds);
+        assert_eq!(stats.points_by_class, expected.points_by_class);
+        assert_eq!(stats.points_by_return, expected.points_by_return);
+        assert!(stats.intensity.unwrap().1 < 40_000);
+    }
+}
@@ -0,0 +1,209 @@
+//! A job-status endpoint for checking on long runs remotely.
+//!
+//! [`JobStatus`] is a `ProgressObserver` that keeps track of the run in flight, and
+//! [`serve_status`] shows it as JSON on `/status` and as a small self-refreshing page on `/`.
+use crate::errors::MyError;
+use crate::progress::{Eta, EtaEstimator, Progress, ProgressObserver};
+use crate::report::{FileReport, FileState, ProcessingReport};
+use crate::server::{self, Response};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// The state of the current run, as seen by a `ProgressObserver`.
+#[derive(Debug, Default)]
+pub struct JobStatus {
+    state: Mutex<StatusState>,
+}
+
+#[derive(Debug, Default)]
+struct StatusState {
+    running: bool,
+    runs_finished: u64,
+    files: Vec<(String, FileState)>,
+    progress: Progress,
+    estimator: EtaEstimator,
+    eta: Option<Eta>,
+}
+
+impl JobStatus {
+    /// Creates a status that reports no run yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the status as a JSON object.
+    pub fn to_json(&self) -> Value {
+        let Ok(state) = self.state.lock() else {
+            return Value::Null;
+        };
+        let progress = &state.progress;
+        let percent = if progress.points_to_read == 0 {
+            0.0
+        } else {
+            progress.points_read as f64 / progress.points_to_read as f64 * 100.0
+        };
+        let current_files: Vec<_> = state
+            .files
+            .iter()
+            .filter(|(_, file_state)| *file_state == FileState::Reading)
+            .map(|(path, _)| path.clone())
+            .collect();
+        json!({
+            "running": state.running,
+            "runs_finished": state.runs_finished,
+            "current_files": current_files,
+            "files_total": progress.files_total,
+            "files_finished": progress.files_finished,
+            "percent": percent,
+            "points_to_read": progress.points_to_read,
+            "points_read": progress.points_read,
+            "points_written": progress.points_written,
+            "elapsed_secs": progress.elapsed.as_secs_f64(),
+            "eta_secs": state.eta.map(|eta| eta.remaining.as_secs_f64()),
+            "completion": state.eta.map(|eta| eta.completion.to_rfc3339()),
+        })
+    }
+
+    /// Returns the status as a small HTML page that reloads itself every five seconds.
+    pub fn to_html(&self) -> String {
+        let status = self.to_json();
+        let current_files = status["current_files"]
+            .as_array()
+            .map(|files| {
+                files
+                    .iter()
+                    .filter_map(|file| file.as_str())
+                    .map(|file| format!("<li>{}</li>", escape_html(file)))
+                    .collect::<String>()
+            })
+            .unwrap_or_default();
+        let eta = match (status["eta_secs"].as_f64(), status["completion"].as_str()) {
+            (Some(secs), Some(completion)) => format!("{:.0} s (done at {})", secs, completion),
+            _ => "unknown".to_string(),
+        };
+        format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"5\">\
+             <title>las_trimmer status</title></head><body>\n\
+             <h1>las_trimmer: {}</h1>\n\
+             <p>{:.1}% complete, {} of {} points read, {} written</p>\n\
+             <p>Files finished: {} of {}</p>\n\
+             <p>ETA: {}</p>\n\
+             <h2>Reading</h2><ul>{}</ul>\n</body></html>\n",
+            if status["running"].as_bool() == Some(true) { "running" } else { "idle" },
+            status["percent"].as_f64().unwrap_or(0.0),
+            status["points_read"],
+            status["points_to_read"],
+            status["points_written"],
+            status["files_finished"],
+            status["files_total"],
+            eta,
+            current_files
+        )
+    }
+
+    fn set_file_state(&self, index: usize, file_state: FileState) {
+        if let Ok(mut state) = self.state.lock() {
+            if let Some(file) = state.files.get_mut(index) {
+                file.1 = file_state;
+            }
+        }
+    }
+}
+
+impl ProgressObserver for JobStatus {
+    fn on_started(&self, paths: &[String], _output_paths: &[String]) {
+        if let Ok(mut state) = self.state.lock() {
+            state.running = true;
+            state.files = paths
+                .iter()
+                .map(|path| (path.clone(), FileState::Queued))
+                .collect();
+            state.progress = Progress::default();
+            state.estimator = EtaEstimator::new();
+            state.eta = None;
+        }
+    }
+
+    fn on_file_started(&self, index: usize, _path: &str, _number_of_points: u64) {
+        self.set_file_state(index, FileState::Reading);
+    }
+
+    fn on_file_finished(&self, index: usize, file: &FileReport) {
+        self.set_file_state(index, file.state);
+    }
+
+    fn on_progress(&self, progress: &Progress) {
+        if let Ok(mut state) = self.state.lock() {
+            state.progress = *progress;
+            state.eta = state.estimator.update(progress);
+        }
+    }
+
+    fn on_finished(&self, _report: &ProcessingReport) {
+        if let Ok(mut state) = self.state.lock() {
+            state.running = false;
+            state.runs_finished += 1;
+            state.eta = None;
+        }
+    }
+}
+
+/// Serves `status` on `http://<addr>/status` as JSON and on `http://<addr>/` as a page, from a
+/// background thread. Returns the bound address.
+pub fn serve_status(addr: &str, status: Arc<JobStatus>) -> Result<SocketAddr, MyError> {
+    server::serve(addr, move |path| match path {
+        "/status" => Some(Response {
+            content_type: "application/json",
+            body: status.to_json().to_string(),
+        }),
+        "/" => Some(Response {
+            content_type: "text/html; charset=utf-8",
+            body: status.to_html(),
+        }),
+        _ => None,
+    })
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    #[test]
+    fn test_status_endpoint() {
+        let status = Arc::new(JobStatus::new());
+        status.on_started(&["a.las".to_string(), "b.las".to_string()], &[]);
+        status.on_file_started(1, "b.las", 2_000);
+        status.on_progress(&Progress {
+            files_total: 2,
+            points_to_read: 2_000,
+            points_read: 500,
+            elapsed: Duration::from_secs(5),
+            ..Progress::default()
+        });
+
+        let addr = serve_status("127.0.0.1:0", Arc::clone(&status)).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let json: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["running"], true);
+        assert_eq!(json["current_files"][0], "b.las");
+        assert_eq!(json["percent"], 25.0);
+    }
+}
@@ -0,0 +1,114 @@
+//! Inputs read from streams handed over by the caller instead of from paths.
+//!
+//! [`LasProcessor::with_stream`](crate::LasProcessor::with_stream) adds a `Read + Seek` stream,
+//! like an in-memory buffer or a decrypting reader, as an input reported under the name it is
+//! given. The processor keeps the stream and opens it through an [`InputSource`], which is either
+//! a path or a stream. It opens each input more than once, for its header and then once per range
+//! when it is split, so every reader gets a [`SharedStream`] handle on the same stream. A handle
+//! keeps its own position and seeks the stream there before each read.
+use crate::errors::MyError;
+use crate::input;
+use las::Reader;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+/// A seekable stream of LAS or LAZ data.
+pub trait InputStream: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> InputStream for T {}
+
+pub(crate) type Stream = Arc<Mutex<Box<dyn InputStream>>>;
+
+/// Where the points of an input are read from.
+#[derive(Clone)]
+pub(crate) enum InputSource {
+    /// A file, or any other input opened by its path (see [`crate::input`]).
+    Path(String),
+    /// A stream handed over by the caller, shared by every reader of the input.
+    Reader(Stream),
+}
+
+impl InputSource {
+    /// Opens a `las::Reader` on the input.
+    pub(crate) fn open_reader(&self) -> Result<Reader, MyError> {
+        match self {
+            InputSource::Path(path) => input::open_reader(path),
+            InputSource::Reader(stream) => {
+                let shared = SharedStream {
+                    stream: Arc::clone(stream),
+                    position: 0,
+                };
+                Ok(Reader::new(BufReader::new(shared))?)
+            }
+        }
+    }
+}
+
+/// A handle on a stream with a position of its own.
+pub struct SharedStream {
+    stream: Stream,
+    position: u64,
+}
+
+impl Read for SharedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut stream = self
+            .stream
+            .lock()
+            .map_err(|_| io::Error::other("stream lock poisoned"))?;
+        stream.seek(SeekFrom::Start(self.position))?;
+        let read = stream.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for SharedStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(position) => position,
+            SeekFrom::Current(offset) => self
+                .position
+                .checked_add_signed(offset)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?,
+            SeekFrom::End(_) => self
+                .stream
+                .lock()
+                .map_err(|_| io::Error::other("stream lock poisoned"))?
+                .seek(pos)?,
+        };
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LasProcessor;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_process_stream() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("output.las");
+        let data = std::fs::read("tests/data/input1.las").unwrap();
+        let processor = LasProcessor::builder()
+            .stream("input1.las", Cursor::new(data))
+            .output(
+                output.to_str().unwrap(),
+                crate::Condition::on_point(std::sync::Arc::new(|_| true)),
+            )
+            // Split the stream so several readers share it
+            .reader_threads(3)
+            .split_size(1_000)
+            .build();
+        assert_eq!(processor.paths, ["input1.las"]);
+
+        let report = processor.process_lidar_files().unwrap();
+        let expected = las::Reader::from_path("tests/data/input1.las").unwrap();
+        assert_eq!(
+            report.outputs[0].points_written,
+            expected.header().number_of_points()
+        );
+    }
+}
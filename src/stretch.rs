@@ -0,0 +1,131 @@
+//! Stretching intensities over the whole range, so that viewers show them with contrast.
+//!
+//! Scanners rarely use the whole range of intensities, and a few very bright returns off
+//! retro-reflectors make a linear ramp look almost black. An [`IntensityStretch`] clips the
+//! intensities to the range between two percentiles, by default the 2nd and the 98th, and maps
+//! that range linearly onto 0 to 65535. The percentiles are read exactly off an
+//! [`IntensityHistogram`] of every intensity, filled in a pass over the inputs before the points
+//! are copied.
+use las::Point;
+
+/// The number of points of each intensity.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntensityHistogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl Default for IntensityHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; usize::from(u16::MAX) + 1],
+            total: 0,
+        }
+    }
+}
+
+impl IntensityHistogram {
+    /// An empty histogram.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts `intensity`.
+    pub fn add(&mut self, intensity: u16) {
+        self.counts[usize::from(intensity)] += 1;
+        self.total += 1;
+    }
+
+    /// The number of intensities counted.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The smallest intensity at least `percentile` percent of the intensities are at or below,
+    /// or `None` if the histogram is empty.
+    pub fn percentile(&self, percentile: f64) -> Option<u16> {
+        if self.total == 0 {
+            return None;
+        }
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0 * self.total as f64).ceil() as u64)
+            .clamp(1, self.total);
+        let mut seen = 0;
+        self.counts
+            .iter()
+            .position(|&count| {
+                seen += count;
+                seen >= rank
+            })
+            .map(|intensity| intensity as u16)
+    }
+}
+
+/// Maps the intensities from `low` to `high` onto the whole range, clipping the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IntensityStretch {
+    low: u16,
+    high: u16,
+}
+
+impl IntensityStretch {
+    /// A stretch of the intensities from `low` to `high`.
+    pub fn new(low: u16, high: u16) -> Self {
+        Self {
+            low: low.min(high),
+            high: high.max(low),
+        }
+    }
+
+    /// The stretch clipping to the `low` and `high` percentiles of `histogram`, or `None` if it
+    /// is empty.
+    pub fn from_percentiles(histogram: &IntensityHistogram, low: f64, high: f64) -> Option<Self> {
+        Some(Self::new(
+            histogram.percentile(low)?,
+            histogram.percentile(high)?,
+        ))
+    }
+
+    /// The intensity `intensity` is stretched to. If every intensity is the same, they are left
+    /// alone.
+    pub fn stretch(&self, intensity: u16) -> u16 {
+        if self.low == self.high {
+            return intensity;
+        }
+        let clipped = intensity.clamp(self.low, self.high) - self.low;
+        (u32::from(clipped) * u32::from(u16::MAX) / u32::from(self.high - self.low)) as u16
+    }
+
+    /// Stretches the intensity of `point`.
+    pub fn apply(&self, point: &mut Point) {
+        point.intensity = self.stretch(point.intensity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intensity_stretch() {
+        let mut histogram = IntensityHistogram::new();
+        assert_eq!(histogram.percentile(50.0), None);
+        // 100 points from 100 to 199, and a bright outlier
+        for intensity in 100..200 {
+            histogram.add(intensity);
+        }
+        histogram.add(60000);
+        assert_eq!(histogram.total(), 101);
+        assert_eq!(histogram.percentile(0.0), Some(100));
+        assert_eq!(histogram.percentile(50.0), Some(150));
+        assert_eq!(histogram.percentile(100.0), Some(60000));
+
+        let stretch = IntensityStretch::from_percentiles(&histogram, 2.0, 98.0).unwrap();
+        assert_eq!(stretch, IntensityStretch::new(102, 198));
+        assert_eq!(stretch.stretch(50), 0);
+        assert_eq!(stretch.stretch(102), 0);
+        assert_eq!(stretch.stretch(150), 32767);
+        assert_eq!(stretch.stretch(198), u16::MAX);
+        assert_eq!(stretch.stretch(60000), u16::MAX);
+        assert_eq!(IntensityStretch::new(7, 7).stretch(9), 9);
+    }
+}
@@ -0,0 +1,245 @@
+//! Elevation models gridded from the points while they stream past.
+//!
+//! A terrain model (DTM) is made from the points classified as ground, and a surface model (DSM)
+//! from the first returns. [`SurfaceGrid`] keeps, for each cell, the lowest and highest point and
+//! the sum of the heights, so the points themselves don't have to be held. The TIN method
+//! triangulates the lowest point of each cell for a DTM, or the highest for a DSM, and takes the
+//! height of the triangles at the centre of each cell, which also fills the cells without points
+//! between the ones with. Cells without a height are written as [`NODATA`].
+use crate::errors::MyError;
+use crate::filter::{Condition, Dimensions};
+use crate::grid::RasterGrid;
+use crate::raster::{cell_of, Raster, NODATA};
+use crate::tin::{interpolate, Triangulation};
+use las::Point;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The classification code of ground points.
+const GROUND: u8 = 2;
+
+/// The elevation model made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceKind {
+    /// A digital terrain model, from the ground points.
+    Terrain,
+    /// A digital surface model, from the first returns.
+    Surface,
+}
+
+impl SurfaceKind {
+    /// A condition keeping the points the model is made from. Points without a return number
+    /// count as first returns.
+    pub fn condition(self) -> Condition {
+        match self {
+            SurfaceKind::Terrain => Condition::on_view(
+                Dimensions::CLASSIFICATION,
+                Arc::new(|view| view.classification() == GROUND),
+            ),
+            SurfaceKind::Surface => Condition::on_view(
+                Dimensions::RETURNS,
+                Arc::new(|view| view.return_number() <= 1),
+            ),
+        }
+    }
+}
+
+/// How the height of a cell is found from its points.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridMethod {
+    /// The height of the lowest point.
+    Min,
+    /// The height of the highest point.
+    Max,
+    /// The mean height of the points.
+    Mean,
+    /// The height at the centre of the cell of a triangulation of the lowest point of each cell
+    /// for a terrain model, or the highest for a surface model.
+    Tin,
+}
+
+/// What is kept of the points of a cell.
+#[derive(Clone, Copy, Debug)]
+struct Cell {
+    lowest: [f64; 3],
+    highest: [f64; 3],
+    sum: f64,
+    count: u64,
+}
+
+/// The points of a grid, reduced to what the elevation model needs.
+#[derive(Clone, Debug)]
+pub struct SurfaceGrid {
+    kind: SurfaceKind,
+    method: GridMethod,
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Cell>,
+}
+
+impl SurfaceGrid {
+    pub fn new(kind: SurfaceKind, method: GridMethod, cell_size: f64) -> Self {
+        Self {
+            kind,
+            method,
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn heights(&self, height: impl Fn(&Cell) -> f64) -> HashMap<(i64, i64), f32> {
+        self.cells
+            .iter()
+            .map(|(&key, cell)| (key, height(cell) as f32))
+            .collect()
+    }
+
+    fn interpolated_heights(&self) -> HashMap<(i64, i64), f32> {
+        // Row by row, so that each point is inserted next to the previous one
+        let mut keys: Vec<&(i64, i64)> = self.cells.keys().collect();
+        keys.sort_unstable_by_key(|&&(column, row)| (row, column));
+        let points: Vec<[f64; 3]> = keys
+            .into_iter()
+            .map(|key| match self.kind {
+                SurfaceKind::Terrain => self.cells[key].lowest,
+                SurfaceKind::Surface => self.cells[key].highest,
+            })
+            .collect();
+        let mut heights = HashMap::new();
+        for triangle in Triangulation::new(&points).triangles() {
+            let (xs, ys) = (
+                triangle.map(|corner| corner[0]),
+                triangle.map(|corner| corner[1]),
+            );
+            let (min_column, min_row) = cell_of(
+                xs.into_iter().fold(f64::INFINITY, f64::min),
+                ys.into_iter().fold(f64::INFINITY, f64::min),
+                self.cell_size,
+            );
+            let (max_column, max_row) = cell_of(
+                xs.into_iter().fold(f64::NEG_INFINITY, f64::max),
+                ys.into_iter().fold(f64::NEG_INFINITY, f64::max),
+                self.cell_size,
+            );
+            for row in min_row..=max_row {
+                for column in min_column..=max_column {
+                    let x = (column as f64 + 0.5) * self.cell_size;
+                    let y = (row as f64 + 0.5) * self.cell_size;
+                    if let Some(height) = interpolate(&triangle, x, y) {
+                        heights.insert((column, row), height as f32);
+                    }
+                }
+            }
+        }
+        heights
+    }
+}
+
+impl RasterGrid for SurfaceGrid {
+    /// Adds `points` to the cells they fall in. The points should already be the ones the model
+    /// is made from, as kept by [`SurfaceKind::condition`].
+    fn add(&mut self, points: &[Point]) {
+        for point in points {
+            let xyz = [point.x, point.y, point.z];
+            let cell = self
+                .cells
+                .entry(cell_of(point.x, point.y, self.cell_size))
+                .or_insert(Cell {
+                    lowest: xyz,
+                    highest: xyz,
+                    sum: 0.0,
+                    count: 0,
+                });
+            if point.z < cell.lowest[2] {
+                cell.lowest = xyz;
+            }
+            if point.z > cell.highest[2] {
+                cell.highest = xyz;
+            }
+            cell.sum += point.z;
+            cell.count += 1;
+        }
+    }
+
+    /// The heights of the cells as a raster.
+    fn to_raster(&self) -> Result<Raster, MyError> {
+        let heights: HashMap<(i64, i64), f32> = match self.method {
+            GridMethod::Min => self.heights(|cell| cell.lowest[2]),
+            GridMethod::Max => self.heights(|cell| cell.highest[2]),
+            GridMethod::Mean => self.heights(|cell| cell.sum / cell.count as f64),
+            GridMethod::Tin => self.interpolated_heights(),
+        };
+        Ok(Raster::from_cells(self.cell_size, &heights, NODATA)?.with_nodata(NODATA))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::PointView;
+
+    #[test]
+    fn test_surface() {
+        // Points on the plane z = x + y in a 4 by 4 grid of cells, but for the cell at (1, 2)
+        let mut points = Vec::new();
+        for row in 0..4 {
+            for column in 0..4 {
+                if (column, row) == (1, 2) {
+                    continue;
+                }
+                for (dx, dy) in [(0.25, 0.25), (0.75, 0.5)] {
+                    let (x, y) = (column as f64 + dx, row as f64 + dy);
+                    points.push(Point {
+                        x,
+                        y,
+                        z: x + y,
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        let raster = |method| {
+            let mut grid = SurfaceGrid::new(SurfaceKind::Terrain, method, 1.0);
+            grid.add(&points);
+            grid.to_raster().unwrap()
+        };
+
+        let min = raster(GridMethod::Min);
+        assert_eq!((min.columns, min.rows), (4, 4));
+        assert_eq!(min.nodata, Some(NODATA));
+        assert_eq!(min.value_at(0.5, 0.5), Some(0.5));
+        assert_eq!(min.value_at(1.5, 2.5), Some(NODATA));
+        assert_eq!(raster(GridMethod::Max).value_at(0.5, 0.5), Some(1.25));
+        assert_eq!(raster(GridMethod::Mean).value_at(0.5, 0.5), Some(0.875));
+
+        // The triangulation of the lowest points fills the empty cell, on the plane
+        // but not the cells whose centre is outside the lowest points
+        let tin = raster(GridMethod::Tin);
+        assert!((tin.value_at(1.5, 2.5).unwrap() - 4.0).abs() < 1e-5);
+        assert_eq!((tin.columns, tin.rows), (3, 3));
+
+        let mut ground = PointView::from_point(Point {
+            classification: las::point::Classification::Ground,
+            ..Default::default()
+        });
+        assert!(SurfaceKind::Terrain
+            .condition()
+            .matches(&mut ground)
+            .unwrap());
+        assert!(SurfaceKind::Surface
+            .condition()
+            .matches(&mut ground)
+            .unwrap());
+        let mut last_return = PointView::from_point(Point {
+            return_number: 2,
+            ..Default::default()
+        });
+        assert!(!SurfaceKind::Terrain
+            .condition()
+            .matches(&mut last_return)
+            .unwrap());
+        assert!(!SurfaceKind::Surface
+            .condition()
+            .matches(&mut last_return)
+            .unwrap());
+    }
+}
@@ -0,0 +1,111 @@
+//! Cutting point clouds into square tiles on a grid aligned to multiples of the tile size.
+//!
+//! Each tile is an output whose condition keeps the points inside it. The lower edges of a tile
+//! belong to it and the upper edges to the next tile, so every point ends up in exactly one tile.
+use crate::filter::{Condition, Dimensions};
+use las::Bounds;
+use std::sync::Arc;
+
+/// The placeholder in an output path that stands for the lowest X coordinate of a tile.
+pub const X_PLACEHOLDER: &str = "{x}";
+
+/// The placeholder in an output path that stands for the lowest Y coordinate of a tile.
+pub const Y_PLACEHOLDER: &str = "{y}";
+
+/// Returns `true` if `template` can name tiles, i.e. contains both `{x}` and `{y}`.
+pub fn is_tile_template(template: &str) -> bool {
+    template.contains(X_PLACEHOLDER) && template.contains(Y_PLACEHOLDER)
+}
+
+/// A square of the grid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub size: f64,
+}
+
+impl Tile {
+    /// A condition keeping the points inside the tile.
+    pub fn condition(&self) -> Condition {
+        let Tile { min_x, min_y, size } = *self;
+        let (max_x, max_y) = (min_x + size, min_y + size);
+        Condition::on_view(
+            Dimensions::XYZ,
+            Arc::new(move |view| {
+                let (x, y) = (view.x(), view.y());
+                min_x <= x && x < max_x && min_y <= y && y < max_y
+            }),
+        )
+    }
+
+    /// Fills in the `{x}` and `{y}` placeholders of an output path template for the tile.
+    pub fn render_path(&self, template: &str) -> String {
+        template
+            .replace(X_PLACEHOLDER, &self.min_x.to_string())
+            .replace(Y_PLACEHOLDER, &self.min_y.to_string())
+    }
+}
+
+/// The tiles of side `size` covering `bounds`, row by row from the lowest Y.
+pub fn tiles(bounds: &Bounds, size: f64) -> Vec<Tile> {
+    let index = |coordinate: f64| (coordinate / size).floor() as i64;
+    let columns = index(bounds.min.x)..=index(bounds.max.x);
+    (index(bounds.min.y)..=index(bounds.max.y))
+        .flat_map(|row| {
+            columns.clone().map(move |column| Tile {
+                min_x: column as f64 * size,
+                min_y: row as f64 * size,
+                size,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::PointView;
+    use las::{Point, Vector};
+
+    #[test]
+    fn test_tiles() {
+        let bounds = Bounds {
+            min: Vector {
+                x: 120.0,
+                y: -30.0,
+                z: 0.0,
+            },
+            max: Vector {
+                x: 200.0,
+                y: 40.0,
+                z: 10.0,
+            },
+        };
+        let tiles = tiles(&bounds, 100.0);
+        let corners: Vec<_> = tiles.iter().map(|tile| (tile.min_x, tile.min_y)).collect();
+        assert_eq!(
+            corners,
+            [(100.0, -100.0), (200.0, -100.0), (100.0, 0.0), (200.0, 0.0)]
+        );
+        assert!(is_tile_template("out/{x}_{y}.laz"));
+        assert!(!is_tile_template("out/{x}.laz"));
+        assert_eq!(tiles[1].render_path("out/{x}_{y}.laz"), "out/200_-100.laz");
+
+        // A point on the edge between two tiles goes to the upper one only
+        let point = Point {
+            x: 200.0,
+            y: 0.0,
+            ..Default::default()
+        };
+        let matches: Vec<_> = tiles
+            .iter()
+            .map(|tile| {
+                tile.condition()
+                    .matches(&mut PointView::from_point(point.clone()))
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(matches, [false, false, false, true]);
+    }
+}
@@ -0,0 +1,246 @@
+//! Merging inputs that are each sorted by GPS time into points sorted by GPS time.
+//!
+//! Sorting by GPS time otherwise means holding every point, as a sort stage does. When each input
+//! is already in time order, as the strips of a survey usually are, a [`TimeMerge`] streams them
+//! instead: it keeps the next point of every input and always passes on the earliest, so only a
+//! point per input is held whatever the size of the inputs. The points come out in the order of
+//! the trajectory, for processing coupled to it.
+//!
+//! The inputs are read one point at a time, in step, so a merge doesn't use the threads of a
+//! [`LasProcessor`](crate::LasProcessor). Inputs without GPS times fail, and so do inputs with
+//! GPS times of another type than the first and inputs found out of order as they are read.
+use crate::compression::LazChunking;
+use crate::errors::MyError;
+use crate::input::open_reader;
+use crate::output::{check_output_paths, OutputWriter};
+use crate::report::OutputReport;
+use las::{Point, Reader};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// A merge of inputs sorted by GPS time.
+pub struct TimeMerge {
+    inputs: Vec<String>,
+    overwrite: bool,
+    chunking: LazChunking,
+    verify: bool,
+}
+
+impl TimeMerge {
+    /// A merge of `inputs`, each of which must be sorted by GPS time.
+    pub fn new(inputs: Vec<String>) -> Self {
+        Self {
+            inputs,
+            overwrite: false,
+            chunking: LazChunking::default(),
+            verify: false,
+        }
+    }
+
+    /// Replaces an existing output rather than failing.
+    pub fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Sets how a LAZ output is split into chunks.
+    pub fn with_laz_chunking(mut self, chunking: LazChunking) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Re-opens the output once it is written to check it, like
+    /// [`OutputWriter::with_verify`].
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// The points of all inputs, earliest first. Points with the same GPS time come in the order
+    /// of their inputs.
+    pub fn points(&self) -> Result<TimeMerged, MyError> {
+        let mut merged = TimeMerged {
+            inputs: Vec::with_capacity(self.inputs.len()),
+            heads: BinaryHeap::with_capacity(self.inputs.len()),
+        };
+        let mut gps_time_type = None;
+        for (index, path) in self.inputs.iter().enumerate() {
+            let reader = open_reader(path)?;
+            if !reader.header().point_format().has_gps_time {
+                return Err(MyError::NoGpsTime(path.clone()));
+            }
+            if *gps_time_type.get_or_insert(reader.header().gps_time_type())
+                != reader.header().gps_time_type()
+            {
+                return Err(MyError::GpsTimeTypeMismatch(path.clone()));
+            }
+            merged.inputs.push(SortedInput {
+                path: path.clone(),
+                reader,
+                points_read: 0,
+                last_time: f64::NEG_INFINITY,
+            });
+            merged.advance(index)?;
+        }
+        Ok(merged)
+    }
+
+    /// Writes the merged points to `output`, with the header of the first input as the template.
+    pub fn write(&self, output: &str) -> Result<Vec<OutputReport>, MyError> {
+        check_output_paths(&[output.to_string()], &self.inputs, self.overwrite)?;
+        let header = match self.inputs.first() {
+            Some(input) => open_reader(input)?.header().clone(),
+            None => return Err(MyError::InvalidInputPath),
+        };
+        let mut writer = OutputWriter::create(output, header, self.chunking)?
+            .with_verify(self.verify)
+            .with_fitted_points(true);
+        for point in self.points()? {
+            writer.write_point(point?)?;
+        }
+        writer.finish()
+    }
+}
+
+/// The points of a [`TimeMerge`], earliest first.
+pub struct TimeMerged {
+    inputs: Vec<SortedInput>,
+    /// The next point of each input that has points left.
+    heads: BinaryHeap<Head>,
+}
+
+impl TimeMerged {
+    /// Reads the next point of input `index` into the heads. Fails if it is earlier than the last.
+    fn advance(&mut self, index: usize) -> Result<(), MyError> {
+        let input = &mut self.inputs[index];
+        let Some(point) = input.reader.read_point()? else {
+            return Ok(());
+        };
+        let time = point
+            .gps_time
+            .ok_or_else(|| MyError::NoGpsTime(input.path.clone()))?;
+        if time < input.last_time {
+            return Err(MyError::UnsortedInput(
+                input.path.clone(),
+                input.points_read,
+            ));
+        }
+        input.points_read += 1;
+        input.last_time = time;
+        self.heads.push(Head { time, index, point });
+        Ok(())
+    }
+}
+
+impl Iterator for TimeMerged {
+    type Item = Result<Point, MyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let head = self.heads.pop()?;
+        Some(self.advance(head.index).map(|()| head.point))
+    }
+}
+
+/// An input being merged.
+struct SortedInput {
+    path: String,
+    reader: Reader,
+    points_read: u64,
+    last_time: f64,
+}
+
+/// The next point of an input, ordered so that the earliest is the greatest.
+struct Head {
+    time: f64,
+    index: usize,
+    point: Point,
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .time
+            .total_cmp(&self.time)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::{Builder, GpsTimeType, Writer};
+
+    fn write_times(path: &std::path::Path, times: &[f64], gps_time_type: GpsTimeType) {
+        let mut builder = Builder::from((1, 4));
+        builder.point_format = las::point::Format::new(1).unwrap();
+        builder.gps_time_type = gps_time_type;
+        let mut writer = Writer::from_path(path, builder.into_header().unwrap()).unwrap();
+        for &time in times {
+            writer
+                .write_point(Point {
+                    x: time,
+                    gps_time: Some(time),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn test_time_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs: Vec<String> = [vec![1.0, 4.0, 5.0, 9.0], vec![2.0, 3.0, 4.0], vec![]]
+            .iter()
+            .enumerate()
+            .map(|(index, times)| {
+                let path = dir.path().join(format!("strip_{}.las", index));
+                write_times(&path, times, GpsTimeType::Standard);
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+
+        let merge = TimeMerge::new(inputs.clone());
+        let times: Vec<f64> = merge
+            .points()
+            .unwrap()
+            .map(|point| point.unwrap().gps_time.unwrap())
+            .collect();
+        assert_eq!(times, [1.0, 2.0, 3.0, 4.0, 4.0, 5.0, 9.0]);
+
+        let output = dir.path().join("merged.laz");
+        let reports = merge.write(output.to_str().unwrap()).unwrap();
+        assert_eq!(reports[0].points_written, 7);
+
+        let unsorted = dir.path().join("unsorted.las");
+        write_times(&unsorted, &[1.0, 3.0, 2.0], GpsTimeType::Standard);
+        let merge = TimeMerge::new(vec![
+            inputs[0].clone(),
+            unsorted.to_string_lossy().to_string(),
+        ]);
+        let result: Result<Vec<Point>, MyError> = merge.points().unwrap().collect();
+        assert!(matches!(result, Err(MyError::UnsortedInput(_, 2))));
+
+        let week = dir.path().join("week.las");
+        write_times(&week, &[1.0, 2.0], GpsTimeType::Week);
+        let merge = TimeMerge::new(vec![inputs[0].clone(), week.to_string_lossy().to_string()]);
+        assert!(matches!(
+            merge.points(),
+            Err(MyError::GpsTimeTypeMismatch(path)) if path.ends_with("week.las")
+        ));
+    }
+}
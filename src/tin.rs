@@ -0,0 +1,333 @@
+//! Delaunay triangulations of points in the plane, to interpolate surfaces between them.
+//!
+//! Points are inserted one at a time with the Bowyer-Watson algorithm: the triangle holding the
+//! new point is found by walking from the last triangle made, and the triangles whose
+//! circumcircle holds the point are replaced by a fan around it. Inserting points in an order
+//! where neighbours follow each other, such as cell by cell, keeps the walks short. Coordinates
+//! are made relative to the first point, so that large projected coordinates keep their
+//! precision.
+
+/// A triangle, counter-clockwise, with the triangle across the edge facing each vertex.
+#[derive(Clone, Debug)]
+struct Triangle {
+    vertices: [usize; 3],
+    neighbours: [Option<usize>; 3],
+    alive: bool,
+}
+
+/// A Delaunay triangulation of points with a height.
+#[derive(Clone, Debug)]
+pub struct Triangulation {
+    origin: [f64; 2],
+    /// The points, relative to `origin`, followed by the three corners of a triangle enclosing
+    /// them all.
+    points: Vec<[f64; 3]>,
+    triangles: Vec<Triangle>,
+}
+
+impl Triangulation {
+    /// Triangulates `points`, given as X, Y and height. Points falling on an earlier point are
+    /// left out.
+    pub fn new(points: &[[f64; 3]]) -> Self {
+        let origin = points
+            .first()
+            .map_or([0.0, 0.0], |point| [point[0], point[1]]);
+        let mut relative: Vec<[f64; 3]> = points
+            .iter()
+            .map(|point| [point[0] - origin[0], point[1] - origin[1], point[2]])
+            .collect();
+        let (mut min, mut max) = ([0.0f64; 2], [0.0f64; 2]);
+        for point in &relative {
+            for axis in 0..2 {
+                min[axis] = min[axis].min(point[axis]);
+                max[axis] = max[axis].max(point[axis]);
+            }
+        }
+        let size = (max[0] - min[0]).max(max[1] - min[1]).max(1.0);
+        let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+        let count = relative.len();
+        relative.push([center[0] - 20.0 * size, center[1] - size, 0.0]);
+        relative.push([center[0] + 20.0 * size, center[1] - size, 0.0]);
+        relative.push([center[0], center[1] + 20.0 * size, 0.0]);
+        let mut triangulation = Self {
+            origin,
+            points: relative,
+            triangles: vec![Triangle {
+                vertices: [count, count + 1, count + 2],
+                neighbours: [None; 3],
+                alive: true,
+            }],
+        };
+        let mut last = 0;
+        let mut visited = Vec::new();
+        for index in 0..count {
+            last = triangulation.insert(index, last, &mut visited);
+        }
+        triangulation
+    }
+
+    /// The triangles between the points, as the X, Y and height of their corners.
+    pub fn triangles(&self) -> impl Iterator<Item = [[f64; 3]; 3]> + '_ {
+        let count = self.points.len() - 3;
+        self.triangles
+            .iter()
+            .filter(move |triangle| {
+                triangle.alive && triangle.vertices.iter().all(|&vertex| vertex < count)
+            })
+            .map(|triangle| {
+                triangle.vertices.map(|vertex| {
+                    let point = self.points[vertex];
+                    [
+                        point[0] + self.origin[0],
+                        point[1] + self.origin[1],
+                        point[2],
+                    ]
+                })
+            })
+    }
+
+    /// Inserts the point at `index`, starting the search for the triangle holding it at `start`.
+    /// Returns a triangle made for the point, or `start` if the point was left out.
+    fn insert(&mut self, index: usize, start: usize, visited: &mut Vec<usize>) -> usize {
+        let point = self.points[index];
+        let holding = self.locate(point, start);
+        let duplicate = self.triangles[holding].vertices.iter().any(|&vertex| {
+            let other = self.points[vertex];
+            (other[0] - point[0]).abs() < 1e-9 && (other[1] - point[1]).abs() < 1e-9
+        });
+        if duplicate {
+            return start;
+        }
+
+        // The triangles whose circumcircle holds the point, which all touch the one holding it
+        let mut cavity = vec![holding];
+        visited.resize(self.triangles.len(), usize::MAX);
+        visited[holding] = index;
+        let mut next = 0;
+        while next < cavity.len() {
+            let triangle = cavity[next];
+            next += 1;
+            for neighbour in self.triangles[triangle].neighbours.into_iter().flatten() {
+                if visited[neighbour] != index && self.in_circumcircle(neighbour, point) {
+                    visited[neighbour] = index;
+                    cavity.push(neighbour);
+                }
+            }
+        }
+
+        // Joins the point to the edges around the cavity
+        let first = self.triangles.len();
+        for &triangle in &cavity {
+            let Triangle {
+                vertices,
+                neighbours,
+                ..
+            } = self.triangles[triangle];
+            for corner in 0..3 {
+                let outside = neighbours[corner];
+                if outside.is_some_and(|outside| visited[outside] == index) {
+                    continue;
+                }
+                let made = self.triangles.len();
+                self.triangles.push(Triangle {
+                    vertices: [
+                        vertices[(corner + 1) % 3],
+                        vertices[(corner + 2) % 3],
+                        index,
+                    ],
+                    neighbours: [None, None, outside],
+                    alive: true,
+                });
+                if let Some(outside) = outside {
+                    for link in &mut self.triangles[outside].neighbours {
+                        if *link == Some(triangle) {
+                            *link = Some(made);
+                        }
+                    }
+                }
+            }
+        }
+        for &triangle in &cavity {
+            self.triangles[triangle].alive = false;
+        }
+        for made in first..self.triangles.len() {
+            let [from, to, _] = self.triangles[made].vertices;
+            for other in first..self.triangles.len() {
+                let [other_from, other_to, _] = self.triangles[other].vertices;
+                if other_from == to {
+                    self.triangles[made].neighbours[0] = Some(other);
+                }
+                if other_to == from {
+                    self.triangles[made].neighbours[1] = Some(other);
+                }
+            }
+        }
+        first
+    }
+
+    /// The triangle holding `point`, walking towards it from `start`.
+    fn locate(&self, point: [f64; 3], start: usize) -> usize {
+        let mut triangle = start;
+        for _ in 0..self.triangles.len() {
+            let Triangle {
+                vertices,
+                neighbours,
+                ..
+            } = &self.triangles[triangle];
+            let across = (0..3).find(|&corner| {
+                let (from, to) = (vertices[(corner + 1) % 3], vertices[(corner + 2) % 3]);
+                orientation(self.points[from], self.points[to], point) < 0.0
+            });
+            match across.and_then(|corner| neighbours[corner]) {
+                Some(neighbour) => triangle = neighbour,
+                None => return triangle,
+            }
+        }
+        // Rounding can make the walk go round in circles, so fall back to looking at every
+        // triangle
+        self.triangles
+            .iter()
+            .position(|candidate| {
+                candidate.alive
+                    && (0..3).all(|corner| {
+                        let from = candidate.vertices[(corner + 1) % 3];
+                        let to = candidate.vertices[(corner + 2) % 3];
+                        orientation(self.points[from], self.points[to], point) >= 0.0
+                    })
+            })
+            .unwrap_or(triangle)
+    }
+
+    /// Returns `true` if `point` is inside the circumcircle of `triangle`.
+    fn in_circumcircle(&self, triangle: usize, point: [f64; 3]) -> bool {
+        let [a, b, c] = self.triangles[triangle]
+            .vertices
+            .map(|vertex| self.points[vertex]);
+        let (ax, ay) = (a[0] - point[0], a[1] - point[1]);
+        let (bx, by) = (b[0] - point[0], b[1] - point[1]);
+        let (cx, cy) = (c[0] - point[0], c[1] - point[1]);
+        let determinant = (ax * ax + ay * ay) * (bx * cy - cx * by)
+            - (bx * bx + by * by) * (ax * cy - cx * ay)
+            + (cx * cx + cy * cy) * (ax * by - bx * ay);
+        determinant > 0.0
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `c`: positive if `c` is left of `a` to `b`.
+fn orientation(a: [f64; 3], b: [f64; 3], c: [f64; 3]) -> f64 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// The height at `(x, y)` of the plane through `triangle`, if the point is inside it.
+pub fn interpolate(triangle: &[[f64; 3]; 3], x: f64, y: f64) -> Option<f64> {
+    let [a, b, c] = *triangle;
+    let area = orientation(a, b, c);
+    if area.abs() < f64::EPSILON {
+        return None;
+    }
+    let point = [x, y, 0.0];
+    let weights = [
+        orientation(b, c, point) / area,
+        orientation(c, a, point) / area,
+        orientation(a, b, point) / area,
+    ];
+    if weights.iter().any(|&weight| weight < -1e-9) {
+        return None;
+    }
+    Some(weights[0] * a[2] + weights[1] * b[2] + weights[2] * c[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_triangulation() {
+        // Scattered points on the plane z = x + 2y, around large coordinates
+        let mut seed = 12345u64;
+        let mut random = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let mut points: Vec<[f64; 3]> = (0..300)
+            .map(|_| {
+                let (x, y) = (random() * 100.0, random() * 100.0);
+                [1_700_000.0 + x, 5_900_000.0 + y, x + 2.0 * y]
+            })
+            .collect();
+        // A grid, with points on common circles, and a duplicate
+        for row in 0..5 {
+            for column in 0..5 {
+                let (x, y) = (200.0 + column as f64, row as f64);
+                points.push([1_700_000.0 + x, 5_900_000.0 + y, x + 2.0 * y]);
+            }
+        }
+        points.push(points[0]);
+        let triangulation = Triangulation::new(&points);
+        let triangles: Vec<_> = triangulation.triangles().collect();
+
+        // Every triangle is counter-clockwise and no point is inside its circumcircle
+        for triangle in &triangles {
+            assert!(orientation(triangle[0], triangle[1], triangle[2]) > 0.0);
+        }
+        for (index, triangle) in triangulation.triangles.iter().enumerate() {
+            if !triangle.alive {
+                continue;
+            }
+            for point in &triangulation.points[..points.len()] {
+                assert!(
+                    !triangulation.in_circumcircle(index, *point)
+                        || triangle
+                            .vertices
+                            .iter()
+                            .any(|&vertex| vertex >= points.len())
+                        || {
+                            // Points on the circle, up to rounding
+                            let [a, b, c] =
+                                triangle.vertices.map(|vertex| triangulation.points[vertex]);
+                            let center = circumcenter(a, b, c);
+                            let radius = distance(center, a);
+                            distance(center, *point) > radius - 1e-6
+                        }
+                );
+            }
+        }
+
+        // A triangulation of n points with h on the hull has 2n - 2 - h triangles, so the
+        // triangles cover the hull exactly when the count is between these
+        let unique = points.len() - 1;
+        assert!(triangles.len() <= 2 * unique - 5);
+        assert!(triangles.len() >= unique);
+
+        // Heights on the plane are found exactly
+        let (x, y) = (1_700_050.5, 5_900_050.25);
+        let height = triangles
+            .iter()
+            .find_map(|triangle| interpolate(triangle, x, y))
+            .unwrap();
+        assert!((height - (50.5 + 100.5)).abs() < 1e-6);
+        assert!(triangles
+            .iter()
+            .all(|triangle| interpolate(triangle, 1_600_000.0, y).is_none()));
+
+        assert_eq!(Triangulation::new(&[]).triangles().count(), 0);
+    }
+
+    fn circumcenter(a: [f64; 3], b: [f64; 3], c: [f64; 3]) -> [f64; 3] {
+        let d = 2.0 * (a[0] * (b[1] - c[1]) + b[0] * (c[1] - a[1]) + c[0] * (a[1] - b[1]));
+        let (a2, b2, c2) = (
+            a[0] * a[0] + a[1] * a[1],
+            b[0] * b[0] + b[1] * b[1],
+            c[0] * c[0] + c[1] * c[1],
+        );
+        [
+            (a2 * (b[1] - c[1]) + b2 * (c[1] - a[1]) + c2 * (a[1] - b[1])) / d,
+            (a2 * (c[0] - b[0]) + b2 * (a[0] - c[0]) + c2 * (b[0] - a[0])) / d,
+            0.0,
+        ]
+    }
+
+    fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+        ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+    }
+}
@@ -0,0 +1,188 @@
+//! Changing the points that meet a condition as they are copied, for fixes that would otherwise
+//! mean dropping points.
+//!
+//! A [`ConditionalTransform`] applies a [`Transform`] to the points its condition passes, or to
+//! every point if it has none. Marking the points outside an area as withheld, for instance,
+//! keeps them in the file for software that wants them while others skip them. Other transforms
+//! adjust the colors of the points, e.g. to even out photogrammetric clouds captured under
+//! different exposures. The transforms see the points going to the outputs, once the output conditions have been tested, and are
+//! applied in the order they were added.
+use crate::filter::{Condition, PointView};
+use las::Point;
+
+/// The classification flags of a point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointFlag {
+    /// The point should be left out of processing.
+    Withheld,
+    /// The point was made by a technique other than the scan, e.g. digitized.
+    Synthetic,
+    /// The point is a model key-point that thinning should keep.
+    KeyPoint,
+    /// The point is in the overlap between flight lines. The formats before LAS 1.4 have no
+    /// flag for it and record it as class 12 instead, which replaces the class of the point.
+    Overlap,
+}
+
+impl PointFlag {
+    /// Whether the flag of `point` is set.
+    pub fn get(self, point: &Point) -> bool {
+        match self {
+            PointFlag::Withheld => point.is_withheld,
+            PointFlag::Synthetic => point.is_synthetic,
+            PointFlag::KeyPoint => point.is_key_point,
+            PointFlag::Overlap => point.is_overlap,
+        }
+    }
+
+    /// Sets the flag of `point` to `value`.
+    pub fn set(self, point: &mut Point, value: bool) {
+        let flag = match self {
+            PointFlag::Withheld => &mut point.is_withheld,
+            PointFlag::Synthetic => &mut point.is_synthetic,
+            PointFlag::KeyPoint => &mut point.is_key_point,
+            PointFlag::Overlap => &mut point.is_overlap,
+        };
+        *flag = value;
+    }
+}
+
+/// A change made to a point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    /// Sets the flag to the value, `false` clearing it.
+    SetFlag(PointFlag, bool),
+    /// Multiplies the red, green and blue channels by their gains, to balance the colors of
+    /// clouds captured under different light.
+    ColorGain([f64; 3]),
+    /// Raises each color channel, as a fraction of the full range, to the power of `1 / gamma`,
+    /// so a gamma above 1 brightens the mid-tones and one below 1 darkens them.
+    Gamma(f64),
+    /// Adds a fraction of the full range to each color channel, negative to darken.
+    Brightness(f64),
+}
+
+impl Transform {
+    /// Changes `point`. The color transforms leave points without color alone, and clip the
+    /// channels to the range.
+    pub fn apply(&self, point: &mut Point) {
+        match *self {
+            Transform::SetFlag(flag, value) => flag.set(point, value),
+            Transform::ColorGain(gains) => {
+                map_channels(point, |channel, value| value * gains[channel])
+            }
+            Transform::Gamma(gamma) => {
+                map_channels(point, |_, value| value.max(0.0).powf(1.0 / gamma))
+            }
+            Transform::Brightness(brightness) => map_channels(point, |_, value| value + brightness),
+        }
+    }
+}
+
+/// Replaces each color channel of `point` by what `map` makes of its index, from red to blue,
+/// and its value as a fraction of the full range.
+fn map_channels(point: &mut Point, map: impl Fn(usize, f64) -> f64) {
+    let Some(color) = &mut point.color else {
+        return;
+    };
+    let full = f64::from(u16::MAX);
+    for (index, channel) in [&mut color.red, &mut color.green, &mut color.blue]
+        .into_iter()
+        .enumerate()
+    {
+        let value = map(index, f64::from(*channel) / full);
+        *channel = (value.clamp(0.0, 1.0) * full).round() as u16;
+    }
+}
+
+/// A transform applied to the points meeting a condition.
+#[derive(Clone)]
+pub struct ConditionalTransform {
+    transform: Transform,
+    condition: Option<Condition>,
+}
+
+impl ConditionalTransform {
+    /// `transform` applied to every point.
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            transform,
+            condition: None,
+        }
+    }
+
+    /// Only applies the transform to the points `condition` passes.
+    pub fn when(mut self, condition: Condition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    /// The transform applied.
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Applies the transform to `point` if it meets the condition.
+    pub fn apply(&self, mut point: Point) -> Result<Point, las::Error> {
+        let matches = match &self.condition {
+            Some(condition) => {
+                let mut view = PointView::from_point(point);
+                let matches = condition.matches(&mut view)?;
+                point = view.into_point()?;
+                matches
+            }
+            None => true,
+        };
+        if matches {
+            self.transform.apply(&mut point);
+        }
+        Ok(point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::NumericFilter;
+    use crate::ClassMask;
+    use las::point::Classification;
+    use las::Color;
+
+    #[test]
+    fn test_conditional_transform() {
+        let ground = Point {
+            classification: Classification::Ground,
+            ..Default::default()
+        };
+        let building = Point {
+            classification: Classification::Building,
+            is_key_point: true,
+            ..Default::default()
+        };
+        let not_ground = !Condition::numeric(NumericFilter::Classes(ClassMask::new(&[2])));
+        let withhold = ConditionalTransform::new(Transform::SetFlag(PointFlag::Withheld, true))
+            .when(not_ground);
+        assert!(!withhold.apply(ground.clone()).unwrap().is_withheld);
+        assert!(withhold.apply(building.clone()).unwrap().is_withheld);
+
+        let clear = ConditionalTransform::new(Transform::SetFlag(PointFlag::KeyPoint, false));
+        let building = clear.apply(building).unwrap();
+        assert!(!PointFlag::KeyPoint.get(&building));
+        assert_eq!(building.classification, Classification::Building);
+
+        let mut point = Point {
+            color: Some(Color::new(16384, 32768, 65535)),
+            ..Default::default()
+        };
+        Transform::ColorGain([2.0, 1.0, 0.5]).apply(&mut point);
+        assert_eq!(point.color, Some(Color::new(32768, 32768, 32768)));
+        Transform::Gamma(0.5).apply(&mut point);
+        assert_eq!(point.color, Some(Color::new(16384, 16384, 16384)));
+        Transform::Brightness(-0.5).apply(&mut point);
+        assert_eq!(point.color, Some(Color::new(0, 0, 0)));
+        // Points without color are left alone
+        let mut point = Point::default();
+        Transform::Brightness(0.5).apply(&mut point);
+        assert_eq!(point.color, None);
+    }
+}
@@ -0,0 +1,188 @@
+//! Trimming LAS/LAZ data held in memory, on the calling thread.
+//!
+//! [`trim`] is the part of the pipeline that makes no assumption about threads or a file system,
+//! so it is what's left of the crate without the default `native` feature, e.g. when building for
+//! `wasm32-unknown-unknown` to crop small files in a browser before uploading them. Everything
+//! else, [`LasProcessor`](crate) included, needs `native`.
+use crate::compression::{LazChunking, LazWriter};
+use crate::errors::MyError;
+use crate::filter::{Condition, PointView};
+use crate::simd::{filter_records, BLOCK_SIZE};
+use las::{Builder, Header, Point, Reader, Writer};
+use std::io::{Cursor, ErrorKind};
+use std::sync::Arc;
+
+/// An output of [`trim`]: the points meeting `condition`, written as LAZ if `compressed` is set
+/// and as LAS otherwise.
+#[derive(Clone)]
+pub struct TrimOutput {
+    pub condition: Condition,
+    pub compressed: bool,
+}
+
+/// Reads the LAS or LAZ file in `data` and returns one file per output with the points meeting
+/// its condition. The records of LAS files are tested where they are, like raw records are read
+/// from local files by the processor.
+pub fn trim(data: impl Into<Arc<[u8]>>, outputs: &[TrimOutput]) -> Result<Vec<Vec<u8>>, MyError> {
+    let data: Arc<[u8]> = data.into();
+    let mut reader = Reader::new(Cursor::new(Arc::clone(&data)))?;
+    let header = reader.header().clone();
+    let mut writers = outputs
+        .iter()
+        .map(|output| MemoryWriter::new(header.clone(), output.compressed))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut matches = vec![false; outputs.len()];
+
+    if header.point_format().is_compressed {
+        for point in reader.points() {
+            let mut view = PointView::from_point(point?);
+            for (matched, output) in matches.iter_mut().zip(outputs) {
+                *matched = output.condition.matches(&mut view)?;
+            }
+            write_matches(view, &matches, &mut writers)?;
+        }
+        return writers.into_iter().map(MemoryWriter::finish).collect();
+    }
+
+    let raw_header = las::raw::Header::read_from(&mut &data[..])?;
+    let start = raw_header.offset_to_point_data as usize;
+    let record_length = usize::from(header.point_format().len());
+    let number_of_points = header.number_of_points();
+    let end = usize::try_from(number_of_points)
+        .ok()
+        .and_then(|points| points.checked_mul(record_length))
+        .and_then(|len| len.checked_add(start))
+        .ok_or(MyError::PointDataOutOfRange(number_of_points))?;
+    let records = data
+        .get(start..end)
+        .ok_or_else(|| std::io::Error::from(ErrorKind::UnexpectedEof))?;
+    // Whether each record of a block matches each numeric condition, a column per output
+    let mut numeric = Vec::new();
+    for block in records.chunks(BLOCK_SIZE * record_length) {
+        let len = block.len() / record_length;
+        numeric.clear();
+        numeric.resize(outputs.len() * len, false);
+        for (output, out) in outputs.iter().zip(numeric.chunks_mut(len)) {
+            if let Some(filter) = output.condition.numeric_filter() {
+                filter_records(filter, block, &header, out);
+            }
+        }
+        for (k, record) in block.chunks_exact(record_length).enumerate() {
+            let mut view = PointView::from_record(record, &header);
+            for (j, (matched, output)) in matches.iter_mut().zip(outputs).enumerate() {
+                *matched = match output.condition.numeric_filter() {
+                    Some(_) => numeric[j * len + k],
+                    None => output.condition.matches(&mut view)?,
+                };
+            }
+            write_matches(view, &matches, &mut writers)?;
+        }
+    }
+    writers.into_iter().map(MemoryWriter::finish).collect()
+}
+
+/// Writes the point seen through `view` to the writers it matches, decoding it only if it
+/// matches any.
+fn write_matches(
+    view: PointView,
+    matches: &[bool],
+    writers: &mut [MemoryWriter],
+) -> Result<(), MyError> {
+    if !matches.contains(&true) {
+        return Ok(());
+    }
+    let point = view.into_point()?;
+    // Only points matching more than one output are cloned, the last match takes it
+    let mut last_match = None;
+    for (j, _) in matches.iter().enumerate().filter(|(_, matched)| **matched) {
+        if let Some(previous) = last_match.replace(j) {
+            writers[previous].write_point(point.clone())?;
+        }
+    }
+    match last_match {
+        Some(last_match) => writers[last_match].write_point(point),
+        None => Ok(()),
+    }
+}
+
+/// Writes LAS or LAZ data to memory.
+enum MemoryWriter {
+    Las(Writer<Cursor<Vec<u8>>>),
+    Laz(Box<LazWriter<Cursor<Vec<u8>>>>),
+}
+
+impl MemoryWriter {
+    fn new(header: Header, compressed: bool) -> Result<Self, MyError> {
+        let write = Cursor::new(Vec::new());
+        if compressed {
+            let writer = LazWriter::new(write, header, LazChunking::default())?;
+            return Ok(MemoryWriter::Laz(Box::new(writer)));
+        }
+        let mut builder = Builder::from(header);
+        builder.point_format.is_compressed = false;
+        // A laszip VLR copied from a LAZ input would be stale
+        builder.vlrs.retain(|vlr| !las::laz::is_laszip_vlr(vlr));
+        builder.evlrs.retain(|vlr| !las::laz::is_laszip_vlr(vlr));
+        Ok(MemoryWriter::Las(Writer::new(
+            write,
+            builder.into_header()?,
+        )?))
+    }
+
+    fn write_point(&mut self, point: Point) -> Result<(), MyError> {
+        match self {
+            MemoryWriter::Las(writer) => Ok(writer.write_point(point)?),
+            MemoryWriter::Laz(writer) => writer.write_point(point),
+        }
+    }
+
+    fn finish(self) -> Result<Vec<u8>, MyError> {
+        let write = match self {
+            MemoryWriter::Las(writer) => writer.into_inner()?,
+            MemoryWriter::Laz(writer) => writer.into_inner()?,
+        };
+        Ok(write.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::NumericFilter;
+
+    #[test]
+    fn test_trim() {
+        let data = std::fs::read("tests/data/input1.las").unwrap();
+        // Scan angles don't survive writing exactly, so the points are compared on other fields
+        let key = |point: Point| (point.x, point.y, point.z, point.intensity, point.gps_time);
+        let expected: Vec<_> = Reader::new(Cursor::new(data.clone()))
+            .unwrap()
+            .points()
+            .map(|point| point.unwrap())
+            .filter(|point| point.intensity < 40_000)
+            .map(key)
+            .collect();
+        let condition = Condition::numeric(NumericFilter::Intensity(0..=39_999));
+        let outputs = [
+            TrimOutput {
+                condition: condition.clone(),
+                compressed: false,
+            },
+            TrimOutput {
+                condition,
+                compressed: true,
+            },
+        ];
+
+        let trimmed = trim(data, &outputs).unwrap();
+        assert_eq!(trimmed.len(), 2);
+        // Trimming the LAZ output again goes through the decompressor instead of the records
+        let laz = trim(trimmed[1].clone(), &outputs[..1]).unwrap();
+        for (file, compressed) in trimmed.into_iter().chain(laz).zip([false, true, false]) {
+            let mut reader = Reader::new(Cursor::new(file)).unwrap();
+            assert_eq!(reader.header().point_format().is_compressed, compressed);
+            let points: Vec<_> = reader.points().map(|point| key(point.unwrap())).collect();
+            assert_eq!(points, expected);
+        }
+    }
+}
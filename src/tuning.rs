@@ -0,0 +1,321 @@
+//! Backpressure between the reader threads and the writers, and the auto-tuning of its limits.
+//!
+//! Readers take a slot from a [`Throttle`] before sending a batch and the writers give it back
+//! once the batch is written, so the number of batches in flight never exceeds the channel depth.
+//! With auto-tuning enabled an `AutoTuner` looks at how often the readers had to wait and whether
+//! the writers ran dry, and adjusts the batch size and channel depth while the run is going.
+use crate::errors::MyError;
+use log::debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The batch size used unless set otherwise.
+pub const DEFAULT_BATCH_SIZE: u64 = 100_000;
+/// The channel depth used unless set otherwise.
+pub const DEFAULT_CHANNEL_DEPTH: usize = 20;
+
+/// How often the auto-tuner reconsiders the limits.
+const TUNE_INTERVAL: Duration = Duration::from_secs(1);
+const MIN_BATCH_SIZE: u64 = 10_000;
+const MAX_BATCH_SIZE: u64 = 1_000_000;
+const MIN_CHANNEL_DEPTH: usize = 2;
+const MAX_CHANNEL_DEPTH: usize = 256;
+/// Above this many batches per second the per-batch overhead starts to show.
+const MAX_BATCH_RATE: f64 = 100.0;
+/// Below this many batches per second a starved writer waits too long for each batch.
+const MIN_BATCH_RATE: f64 = 2.0;
+/// Auto-tuning never holds more points in flight than four times the defaults would.
+const MAX_POINTS_IN_FLIGHT: u64 = 4 * DEFAULT_BATCH_SIZE * DEFAULT_CHANNEL_DEPTH as u64;
+
+/// The batch size and channel depth a run ended up with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tuning {
+    /// The number of points per batch sent from a reader to a writer.
+    pub batch_size: u64,
+    /// The number of batches that may be waiting to be written.
+    pub channel_depth: usize,
+    /// Whether the values were picked by auto-tuning rather than set explicitly.
+    pub auto_tuned: bool,
+}
+
+/// Limits the number of batches between the readers and the writers.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    state: Mutex<ThrottleState>,
+    released: Condvar,
+    batch_size: AtomicU64,
+}
+
+#[derive(Debug)]
+struct ThrottleState {
+    in_flight: usize,
+    depth: usize,
+    stats: ThrottleStats,
+}
+
+/// What happened since the statistics were last taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ThrottleStats {
+    /// How often a reader had to wait for a free slot.
+    waits: u64,
+    /// The number of batches written.
+    batches: u64,
+    /// The fewest batches in flight after a batch was written. 0 means a writer ran dry.
+    min_in_flight: usize,
+}
+
+impl Throttle {
+    pub(crate) fn new(batch_size: u64, depth: usize) -> Self {
+        Self {
+            state: Mutex::new(ThrottleState {
+                in_flight: 0,
+                depth,
+                stats: ThrottleStats {
+                    waits: 0,
+                    batches: 0,
+                    min_in_flight: 0,
+                },
+            }),
+            released: Condvar::new(),
+            batch_size: AtomicU64::new(batch_size),
+        }
+    }
+
+    /// The number of points readers should put in a batch.
+    pub(crate) fn batch_size(&self) -> u64 {
+        self.batch_size.load(Ordering::Relaxed)
+    }
+
+    /// The number of batches sent but not yet written.
+    pub(crate) fn in_flight(&self) -> usize {
+        self.state.lock().map(|state| state.in_flight).unwrap_or(0)
+    }
+
+    /// Takes a slot for a batch, waiting while all of them are in use. `keep_waiting` is called
+    /// about every `poll` while waiting; when it returns `false` the wait is given up and `false`
+    /// is returned.
+    pub(crate) fn acquire(
+        &self,
+        poll: Duration,
+        keep_waiting: impl Fn() -> bool,
+    ) -> Result<bool, MyError> {
+        let mut state = self.state.lock().map_err(|_| MyError::LockError)?;
+        if state.in_flight >= state.depth {
+            state.stats.waits += 1;
+        }
+        while state.in_flight >= state.depth {
+            state = self
+                .released
+                .wait_timeout(state, poll)
+                .map_err(|_| MyError::LockError)?
+                .0;
+            if !keep_waiting() {
+                return Ok(false);
+            }
+        }
+        state.in_flight += 1;
+        Ok(true)
+    }
+
+    /// Gives back the slot of a batch that has been written.
+    pub(crate) fn release(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.in_flight = state.in_flight.saturating_sub(1);
+            state.stats.batches += 1;
+            state.stats.min_in_flight = state.stats.min_in_flight.min(state.in_flight);
+        }
+        self.released.notify_one();
+    }
+
+    /// The current limits.
+    pub(crate) fn tuning(&self, auto_tuned: bool) -> Tuning {
+        Tuning {
+            batch_size: self.batch_size(),
+            channel_depth: self.state.lock().map(|state| state.depth).unwrap_or(0),
+            auto_tuned,
+        }
+    }
+
+    fn take_stats(&self) -> Result<(ThrottleStats, usize), MyError> {
+        let mut state = self.state.lock().map_err(|_| MyError::LockError)?;
+        let stats = state.stats;
+        state.stats = ThrottleStats {
+            waits: 0,
+            batches: 0,
+            min_in_flight: state.in_flight,
+        };
+        Ok((stats, state.depth))
+    }
+
+    fn set(&self, batch_size: u64, depth: usize) -> Result<(), MyError> {
+        self.batch_size.store(batch_size, Ordering::Relaxed);
+        self.state.lock().map_err(|_| MyError::LockError)?.depth = depth;
+        self.released.notify_all();
+        Ok(())
+    }
+}
+
+/// The most points that may be held in batches at once, both in the ones the readers are filling
+/// and in the ones waiting to be written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PointBudget {
+    max_points: u64,
+    /// The number of batches being filled at any time, one per reader thread and output.
+    buffers: u64,
+}
+
+impl PointBudget {
+    /// A budget of `max_memory` bytes for points taking `point_size` bytes each.
+    pub(crate) fn from_memory(max_memory: u64, point_size: u64, buffers: u64) -> Self {
+        Self {
+            max_points: max_memory / point_size.max(1),
+            buffers,
+        }
+    }
+
+    /// Shrinks the channel depth, and then the batch size if a depth of 1 is still too much, until
+    /// they fit in the budget.
+    pub(crate) fn fit(&self, batch_size: u64, depth: usize) -> (u64, usize) {
+        let points = |batch_size: u64, depth: usize| (self.buffers + depth as u64) * batch_size;
+        if points(batch_size, depth) <= self.max_points {
+            return (batch_size, depth);
+        }
+        let depth = (self.max_points / batch_size.max(1))
+            .saturating_sub(self.buffers)
+            .max(1) as usize;
+        if points(batch_size, depth) <= self.max_points {
+            return (batch_size, depth);
+        }
+        ((self.max_points / (self.buffers + 1)).max(1), 1)
+    }
+}
+
+/// Adjusts the limits of a `Throttle` about once a second, from the writer loop.
+#[derive(Debug)]
+pub(crate) struct AutoTuner {
+    last: Instant,
+    budget: Option<PointBudget>,
+}
+
+impl AutoTuner {
+    /// Creates a tuner that keeps the limits within `budget`, if there is one.
+    pub(crate) fn new(budget: Option<PointBudget>) -> Self {
+        Self {
+            last: Instant::now(),
+            budget,
+        }
+    }
+
+    /// Adjusts the limits if the last adjustment was long enough ago.
+    pub(crate) fn tune(&mut self, throttle: &Throttle) -> Result<(), MyError> {
+        let elapsed = self.last.elapsed();
+        if elapsed < TUNE_INTERVAL {
+            return Ok(());
+        }
+        self.last = Instant::now();
+        let (stats, depth) = throttle.take_stats()?;
+        let batch_size = throttle.batch_size();
+        let (mut new_batch_size, mut new_depth) = next_limits(stats, elapsed, batch_size, depth);
+        if let Some(budget) = self.budget {
+            (new_batch_size, new_depth) = budget.fit(new_batch_size, new_depth);
+        }
+        if (new_batch_size, new_depth) != (batch_size, depth) {
+            debug!(
+                "Auto-tuning batch size {} -> {}, channel depth {} -> {}",
+                batch_size, new_batch_size, depth, new_depth
+            );
+            throttle.set(new_batch_size, new_depth)?;
+        }
+        Ok(())
+    }
+}
+
+/// Picks the batch size and channel depth for the next interval.
+fn next_limits(
+    stats: ThrottleStats,
+    elapsed: Duration,
+    batch_size: u64,
+    depth: usize,
+) -> (u64, usize) {
+    let starved = stats.min_in_flight == 0;
+    let mut depth = if starved && stats.waits > 0 {
+        // Batches arrive in bursts: readers were blocked while a writer sat idle at other times
+        depth * 2
+    } else if !starved && stats.min_in_flight >= depth / 2 {
+        // The writers never got through the older half of the queue, it only costs memory
+        depth / 2
+    } else {
+        depth
+    }
+    .clamp(MIN_CHANNEL_DEPTH, MAX_CHANNEL_DEPTH);
+
+    let rate = stats.batches as f64 / elapsed.as_secs_f64();
+    let batch_size = if rate > MAX_BATCH_RATE {
+        batch_size * 2
+    } else if rate < MIN_BATCH_RATE && starved {
+        batch_size / 2
+    } else {
+        batch_size
+    }
+    .clamp(MIN_BATCH_SIZE, MAX_BATCH_SIZE);
+
+    while depth as u64 * batch_size > MAX_POINTS_IN_FLIGHT && depth > MIN_CHANNEL_DEPTH {
+        depth /= 2;
+    }
+    (batch_size, depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_budget_fit() {
+        // 4 readers filling 2 batches each, and room for 1,000,000 points
+        let budget = PointBudget::from_memory(100_000_000, 100, 8);
+
+        // Fits already
+        assert_eq!(budget.fit(10_000, 20), (10_000, 20));
+        // The depth gives way first
+        assert_eq!(budget.fit(100_000, 20), (100_000, 2));
+        // Then the batch size
+        assert_eq!(budget.fit(500_000, 20), (111_111, 1));
+    }
+
+    #[test]
+    fn test_next_limits() {
+        let second = Duration::from_secs(1);
+        let stats = |waits, batches, min_in_flight| ThrottleStats {
+            waits,
+            batches,
+            min_in_flight,
+        };
+
+        // Bursty: readers waited and the writer also ran dry
+        assert_eq!(
+            next_limits(stats(5, 10, 0), second, 100_000, 20),
+            (100_000, 40)
+        );
+        // Writer bound: the queue never dropped below half full
+        assert_eq!(
+            next_limits(stats(5, 10, 15), second, 100_000, 20),
+            (100_000, 10)
+        );
+        // Many small batches are merged, and the depth shrinks to stay within the memory cap
+        assert_eq!(
+            next_limits(stats(0, 500, 3), second, 400_000, 20),
+            (800_000, 10)
+        );
+        // A starved writer with few batches gets smaller ones
+        assert_eq!(
+            next_limits(stats(0, 1, 0), second, 100_000, 20),
+            (50_000, 20)
+        );
+        // Balanced: nothing changes
+        assert_eq!(
+            next_limits(stats(0, 10, 3), second, 100_000, 20),
+            (100_000, 20)
+        );
+    }
+}
@@ -0,0 +1,466 @@
+//! Checking that a file conforms to the LAS specification and that its points agree with what its
+//! header says about them.
+//!
+//! The points are read once, and compared with the point count, bounds and return histogram of
+//! the header. For local files the raw header is read too, to check the legacy point counts of
+//! LAS 1.4 and that the VLRs, the point data and the EVLRs fit where the header puts them, which
+//! readers that trust the header trip over.
+use crate::errors::MyError;
+use crate::input::open_reader;
+use las::{Bounds, Header, Point, Vector};
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::path::Path;
+
+/// The size of the fixed part of a VLR.
+pub(crate) const VLR_HEADER_SIZE: u64 = 54;
+
+/// How serious a problem is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The file breaks the specification in a way most readers cope with, e.g. loose bounds.
+    Warning,
+    /// The file can't be read correctly, or its header is wrong about the points.
+    Error,
+}
+
+impl Severity {
+    /// A short lowercase name for the severity, as used in reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// A problem found in a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity.as_str(), self.message)
+    }
+}
+
+/// The problems found in a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub path: String,
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// Returns `true` if no problem is an error.
+    pub fn is_valid(&self) -> bool {
+        self.issues
+            .iter()
+            .all(|issue| issue.severity < Severity::Error)
+    }
+
+    /// The report as JSON, with the path, whether the file is valid and the issues.
+    pub fn to_json(&self) -> Value {
+        let issues: Vec<Value> = self
+            .issues
+            .iter()
+            .map(|issue| json!({"severity": issue.severity.as_str(), "message": issue.message.clone()}))
+            .collect();
+        json!({
+            "path": self.path.clone(),
+            "valid": self.is_valid(),
+            "issues": issues,
+        })
+    }
+
+    fn error(&mut self, message: String) {
+        self.issues.push(Issue {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.issues.push(Issue {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+}
+
+/// What the points of a file actually hold, gathered while streaming them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PointStats {
+    pub number_of_points: u64,
+    /// The extents of the points, `None` without points.
+    pub bounds: Option<Bounds>,
+    /// The number of points with each return number from 1 to 15.
+    pub points_by_return: [u64; 15],
+    /// The number of points with return number 0, which the specification doesn't allow.
+    pub points_without_return: u64,
+    /// The number of points with each classification code, up to the highest code seen.
+    pub points_by_class: Vec<u64>,
+    /// The lowest and highest intensity, `None` without points.
+    pub intensity: Option<(u16, u16)>,
+    /// The earliest and latest GPS time, `None` without points having one.
+    pub gps_time: Option<(f64, f64)>,
+}
+
+impl PointStats {
+    /// Counts `point`.
+    pub fn add(&mut self, point: &Point) {
+        self.number_of_points += 1;
+        match point.return_number {
+            0 => self.points_without_return += 1,
+            n => {
+                if let Some(count) = self.points_by_return.get_mut(usize::from(n) - 1) {
+                    *count += 1;
+                }
+            }
+        }
+        let class = usize::from(u8::from(point.classification));
+        if self.points_by_class.len() <= class {
+            self.points_by_class.resize(class + 1, 0);
+        }
+        self.points_by_class[class] += 1;
+        let intensity = self
+            .intensity
+            .get_or_insert((point.intensity, point.intensity));
+        *intensity = (
+            intensity.0.min(point.intensity),
+            intensity.1.max(point.intensity),
+        );
+        if let Some(time) = point.gps_time {
+            let gps_time = self.gps_time.get_or_insert((time, time));
+            *gps_time = (gps_time.0.min(time), gps_time.1.max(time));
+        }
+        let bounds = self.bounds.get_or_insert(Bounds {
+            min: Vector {
+                x: point.x,
+                y: point.y,
+                z: point.z,
+            },
+            max: Vector {
+                x: point.x,
+                y: point.y,
+                z: point.z,
+            },
+        });
+        bounds.min.x = bounds.min.x.min(point.x);
+        bounds.min.y = bounds.min.y.min(point.y);
+        bounds.min.z = bounds.min.z.min(point.z);
+        bounds.max.x = bounds.max.x.max(point.x);
+        bounds.max.y = bounds.max.y.max(point.y);
+        bounds.max.z = bounds.max.z.max(point.z);
+    }
+
+    /// The statistics as JSON, with the classes and returns as objects from the code or return
+    /// number to the number of points, leaving out the ones no point has.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "points": self.number_of_points,
+            "bounds": self.bounds.as_ref().map(|bounds| json!({
+                "min": [bounds.min.x, bounds.min.y, bounds.min.z],
+                "max": [bounds.max.x, bounds.max.y, bounds.max.z],
+            })),
+            "classes": counts_json((0..).zip(self.points_by_class.iter().copied())),
+            "returns": counts_json((1..).zip(self.points_by_return)),
+            "intensity": self.intensity.map(|(min, max)| json!({"min": min, "max": max})),
+            "gps_time": self.gps_time.map(|(min, max)| json!({"min": min, "max": max})),
+        })
+    }
+
+    /// The number of points by return that a header should hold, for return numbers 1 to 15.
+    pub fn header_points_by_return(header: &Header) -> [u64; 15] {
+        std::array::from_fn(|i| header.number_of_points_by_return(i as u8 + 1).unwrap_or(0))
+    }
+}
+
+/// Reads every point of the input at `path` and reports the ways the file breaks the
+/// specification or disagrees with its header. Reading stops at the first point that can't be
+/// decoded.
+pub fn validate(path: &str) -> Result<ValidationReport, MyError> {
+    let mut report = ValidationReport {
+        path: path.to_string(),
+        issues: Vec::new(),
+    };
+    if Path::new(path).is_file() {
+        check_layout(path, &mut report)?;
+    }
+
+    let mut reader = open_reader(path)?;
+    let header = reader.header().clone();
+    let mut stats = PointStats::default();
+    for point in reader.points() {
+        match point {
+            Ok(point) => stats.add(&point),
+            Err(err) => {
+                report.error(format!(
+                    "point {} can't be read: {}",
+                    stats.number_of_points, err
+                ));
+                break;
+            }
+        }
+    }
+    check_points(&header, &stats, &mut report);
+    Ok(report)
+}
+
+/// Compares the header with the points.
+fn check_points(header: &Header, stats: &PointStats, report: &mut ValidationReport) {
+    if stats.number_of_points != header.number_of_points() {
+        report.error(format!(
+            "the header counts {} points but {} were read",
+            header.number_of_points(),
+            stats.number_of_points
+        ));
+    }
+
+    if let Some(actual) = &stats.bounds {
+        let bounds = header.bounds();
+        let transforms = header.transforms();
+        // Header bounds and coordinates may be rounded differently
+        let tolerance = Vector {
+            x: transforms.x.scale / 2.0,
+            y: transforms.y.scale / 2.0,
+            z: transforms.z.scale / 2.0,
+        };
+        let outside = actual.min.x < bounds.min.x - tolerance.x
+            || actual.max.x > bounds.max.x + tolerance.x
+            || actual.min.y < bounds.min.y - tolerance.y
+            || actual.max.y > bounds.max.y + tolerance.y
+            || actual.min.z < bounds.min.z - tolerance.z
+            || actual.max.z > bounds.max.z + tolerance.z;
+        let loose = actual.min.x > bounds.min.x + tolerance.x
+            || actual.max.x < bounds.max.x - tolerance.x
+            || actual.min.y > bounds.min.y + tolerance.y
+            || actual.max.y < bounds.max.y - tolerance.y
+            || actual.min.z > bounds.min.z + tolerance.z
+            || actual.max.z < bounds.max.z - tolerance.z;
+        let extents = format!(
+            "{} {} {} to {} {} {}",
+            actual.min.x, actual.min.y, actual.min.z, actual.max.x, actual.max.y, actual.max.z
+        );
+        if outside {
+            report.error(format!(
+                "the points reach outside the header bounds, they span {}",
+                extents
+            ));
+        } else if loose {
+            report.warning(format!(
+                "the header bounds are wider than the points, which span {}",
+                extents
+            ));
+        }
+    }
+
+    let header_by_return = PointStats::header_points_by_return(header);
+    if header_by_return != stats.points_by_return {
+        report.warning(format!(
+            "the header counts {:?} points by return but the points give {:?}",
+            trim_zeros(&header_by_return),
+            trim_zeros(&stats.points_by_return)
+        ));
+    }
+    if stats.points_without_return > 0 {
+        report.warning(format!(
+            "{} points have return number 0",
+            stats.points_without_return
+        ));
+    }
+}
+
+/// The counts that aren't 0 as an object, by their key.
+fn counts_json(counts: impl Iterator<Item = (usize, u64)>) -> Value {
+    counts
+        .filter(|&(_, count)| count > 0)
+        .map(|(key, count)| (key.to_string(), Value::from(count)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// The counts up to the last one that isn't 0.
+pub(crate) fn trim_zeros(counts: &[u64]) -> &[u64] {
+    let len = counts
+        .iter()
+        .rposition(|&count| count != 0)
+        .map_or(0, |i| i + 1);
+    &counts[..len]
+}
+
+/// The legacy point count and points by return that go with `count` points of point format
+/// `format` and the counts by return `by_return`.
+pub(crate) fn legacy_counts(format: u8, count: u64, by_return: &[u64; 15]) -> (u32, [u32; 5]) {
+    // Formats 6 and up, and files too large for them, leave the legacy fields at 0
+    if format >= 6 || count > u64::from(u32::MAX) {
+        (0, [0; 5])
+    } else {
+        (
+            count as u32,
+            std::array::from_fn(|i| u32::try_from(by_return[i]).unwrap_or(0)),
+        )
+    }
+}
+
+/// Checks the raw header of the local file at `path`: the legacy point counts, and the offsets
+/// of the VLRs, point data and EVLRs.
+fn check_layout(path: &str, report: &mut ValidationReport) -> Result<(), MyError> {
+    let mut file = BufReader::new(File::open(path)?);
+    let file_size = file.get_ref().metadata()?.len();
+    let raw = match las::raw::Header::read_from(&mut file) {
+        Ok(raw) => raw,
+        Err(err) => {
+            report.error(format!("the header can't be read: {}", err));
+            return Ok(());
+        }
+    };
+
+    let format = raw.point_data_record_format & 0x3f;
+    let compressed = raw.point_data_record_format & 0x80 != 0;
+    if let Some(large_file) = &raw.large_file {
+        let (legacy_count, legacy_by_return) = legacy_counts(
+            format,
+            large_file.number_of_point_records,
+            &large_file.number_of_points_by_return,
+        );
+        if raw.number_of_point_records != legacy_count {
+            report.warning(format!(
+                "the legacy point count is {} but should be {}",
+                raw.number_of_point_records, legacy_count
+            ));
+        }
+        if raw.number_of_points_by_return != legacy_by_return {
+            report.warning(format!(
+                "the legacy points by return are {:?} but should be {:?}",
+                raw.number_of_points_by_return, legacy_by_return
+            ));
+        }
+    }
+
+    file.seek(SeekFrom::Start(u64::from(raw.header_size)))?;
+    let mut vlrs_end = u64::from(raw.header_size);
+    for index in 0..raw.number_of_variable_length_records {
+        match las::raw::Vlr::read_from(&mut file, false) {
+            Ok(vlr) => vlrs_end += VLR_HEADER_SIZE + vlr.data.len() as u64,
+            Err(_) => {
+                report.error(format!("VLR {} runs past the end of the file", index));
+                return Ok(());
+            }
+        }
+    }
+    let point_data_start = u64::from(raw.offset_to_point_data);
+    if vlrs_end > point_data_start {
+        report.error(format!(
+            "the VLRs end at byte {}, after the start of the point data at byte {}",
+            vlrs_end, point_data_start
+        ));
+    }
+
+    let count = raw
+        .large_file
+        .as_ref()
+        .map_or(u64::from(raw.number_of_point_records), |large_file| {
+            large_file.number_of_point_records
+        });
+    let point_data_end = point_data_start + count * u64::from(raw.point_data_record_length);
+    if !compressed && point_data_end > file_size {
+        report.error(format!(
+            "the point data should end at byte {} but the file is {} bytes long",
+            point_data_end, file_size
+        ));
+    }
+    if let Some(evlr) = raw.evlr.filter(|evlr| evlr.number_of_evlrs > 0) {
+        let start = evlr.start_of_first_evlr;
+        if start > file_size {
+            report.error(format!(
+                "the EVLRs start at byte {}, past the end of the file at byte {}",
+                start, file_size
+            ));
+        } else if start < point_data_start || (!compressed && start < point_data_end) {
+            report.error(format!(
+                "the EVLRs start at byte {}, inside the point data",
+                start
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::{Builder, Writer};
+
+    fn messages(report: &ValidationReport) -> Vec<String> {
+        report.issues.iter().map(Issue::to_string).collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        let report = validate("tests/data/input1.las").unwrap();
+        assert!(report.is_valid(), "{:?}", report.issues);
+
+        // Write a file, then break its header
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.las");
+        let header = Builder::from((1, 4)).into_header().unwrap();
+        let mut writer = Writer::from_path(&path, header).unwrap();
+        for i in 0..4 {
+            let point = Point {
+                x: f64::from(i),
+                return_number: 1,
+                number_of_returns: 1,
+                ..Default::default()
+            };
+            writer.write_point(point).unwrap();
+        }
+        writer.close().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        assert!(validate(path.to_str().unwrap()).unwrap().issues.is_empty());
+
+        // The max X of the header is a little-endian f64 at byte 179
+        let mut broken = data.clone();
+        broken[179..187].copy_from_slice(&1.0f64.to_le_bytes());
+        std::fs::write(&path, &broken).unwrap();
+        let report = validate(path.to_str().unwrap()).unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(
+            messages(&report),
+            ["error: the points reach outside the header bounds, they span 0 0 0 to 3 0 0"]
+        );
+
+        // The legacy point count is a little-endian u32 at byte 107, and the legacy count of
+        // first returns follows it
+        let mut broken = data.clone();
+        broken[107..111].copy_from_slice(&0u32.to_le_bytes());
+        broken[111..115].copy_from_slice(&3u32.to_le_bytes());
+        broken[179..187].copy_from_slice(&10.0f64.to_le_bytes());
+        std::fs::write(&path, &broken).unwrap();
+        let report = validate(path.to_str().unwrap()).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(
+            messages(&report),
+            [
+                "warning: the legacy point count is 0 but should be 4",
+                "warning: the legacy points by return are [3, 0, 0, 0, 0] but should be [4, 0, 0, 0, 0]",
+                "warning: the header bounds are wider than the points, which span 0 0 0 to 3 0 0",
+                "warning: the header counts [3] points by return but the points give [4]",
+            ]
+        );
+        let json = report.to_json();
+        assert_eq!(json["valid"], true);
+        assert_eq!(json["issues"][0]["severity"], "warning");
+
+        // Cut the file short
+        std::fs::write(&path, &data[..data.len() - 10]).unwrap();
+        let report = validate(path.to_str().unwrap()).unwrap();
+        assert!(!report.is_valid());
+        assert!(messages(&report)[0].starts_with("error: the point data should end at byte"));
+    }
+}
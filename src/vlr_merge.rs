@@ -0,0 +1,171 @@
+//! Merging the VLRs and EVLRs of every input into the outputs.
+//!
+//! By default the outputs get the records of the first input only. A [`VlrMerge`] copies the
+//! records of the other inputs as well, verbatim. Records with the same user id and record id are
+//! the same record: when their data is identical, as with tiles sharing a CRS, only one is kept,
+//! and when it differs the merge fails or keeps the record of the first or last input having it,
+//! following its [`VlrPrecedence`].
+//!
+//! The points are written with the extra bytes of the first input, so inputs describing their
+//! extra bytes differently always fail, whatever the precedence. The laszip record describes how
+//! an input is compressed and is written anew with the outputs, so it is never merged.
+use crate::errors::MyError;
+use crate::extra_bytes::is_extra_bytes_vlr;
+use las::{Builder, Header, Vlr};
+
+/// What is done with records that differ between inputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VlrPrecedence {
+    /// The merge fails with `MyError::VlrConflict`.
+    #[default]
+    Fail,
+    /// The record of the first input having it is kept.
+    First,
+    /// The record of the last input having it is kept.
+    Last,
+}
+
+/// Merges the records of the inputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VlrMerge {
+    precedence: VlrPrecedence,
+}
+
+impl VlrMerge {
+    /// A merge settling the records that differ with `precedence`.
+    pub fn new(precedence: VlrPrecedence) -> Self {
+        Self { precedence }
+    }
+
+    /// What is done with records that differ between inputs.
+    pub fn precedence(&self) -> VlrPrecedence {
+        self.precedence
+    }
+
+    /// The header of the first input with the records of the `others` merged into it, in the
+    /// order of the inputs. Each other header comes with the path of its input, to report
+    /// conflicts.
+    pub fn header(&self, header: Header, others: &[(String, Header)]) -> Result<Header, MyError> {
+        let mut builder = Builder::from(header);
+        for (path, other) in others {
+            self.merge(&mut builder.vlrs, other.vlrs(), path)?;
+            self.merge(&mut builder.evlrs, other.evlrs(), path)?;
+        }
+        Ok(builder.into_header()?)
+    }
+
+    /// Merges the `others` records of the input at `path` into `records`.
+    fn merge(&self, records: &mut Vec<Vlr>, others: &[Vlr], path: &str) -> Result<(), MyError> {
+        for other in others {
+            if las::laz::is_laszip_vlr(other) {
+                continue;
+            }
+            let same = records
+                .iter_mut()
+                .find(|vlr| vlr.user_id == other.user_id && vlr.record_id == other.record_id);
+            let Some(vlr) = same else {
+                records.push(other.clone());
+                continue;
+            };
+            if vlr.data == other.data {
+                continue;
+            }
+            if is_extra_bytes_vlr(other) {
+                return Err(MyError::ExtraBytesConflict(path.to_string()));
+            }
+            match self.precedence {
+                VlrPrecedence::Fail => {
+                    return Err(MyError::VlrConflict(
+                        path.to_string(),
+                        other.user_id.clone(),
+                        other.record_id,
+                    ))
+                }
+                VlrPrecedence::First => {}
+                VlrPrecedence::Last => *vlr = other.clone(),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_with(vlrs: Vec<Vlr>) -> Header {
+        let mut builder = Builder::from((1, 4));
+        builder.vlrs = vlrs;
+        builder.into_header().unwrap()
+    }
+
+    fn vlr(user_id: &str, record_id: u16, data: u8) -> Vlr {
+        Vlr {
+            user_id: user_id.to_string(),
+            record_id,
+            description: String::new(),
+            data: vec![data; 8],
+        }
+    }
+
+    /// The laszip record of LAZ written in chunks of `chunk_size` points.
+    fn laszip_vlr(chunk_size: u32) -> Vlr {
+        let items = laz::LazItemRecordBuilder::default_for_point_format_id(1, 0).unwrap();
+        let mut data = Vec::new();
+        laz::LazVlrBuilder::new(items)
+            .with_fixed_chunk_size(chunk_size)
+            .build()
+            .write_to(&mut data)
+            .unwrap();
+        Vlr {
+            user_id: "laszip encoded".to_string(),
+            record_id: 22204,
+            description: String::new(),
+            data,
+        }
+    }
+
+    #[test]
+    fn test_vlr_merge_skips_laszip() {
+        let first = header_with(vec![laszip_vlr(50_000), vlr("LASF_Projection", 2112, 1)]);
+        let others = vec![(
+            "tile_2.laz".to_string(),
+            header_with(vec![laszip_vlr(1_000), vlr("LASF_Projection", 2112, 1)]),
+        )];
+        let merged = VlrMerge::new(VlrPrecedence::Fail)
+            .header(first.clone(), &others)
+            .unwrap();
+        assert_eq!(merged.vlrs(), first.vlrs());
+    }
+
+    #[test]
+    fn test_vlr_merge() {
+        let first = header_with(vec![vlr("LASF_Projection", 2112, 1)]);
+        let others = vec![
+            (
+                "tile_2.las".to_string(),
+                header_with(vec![vlr("LASF_Projection", 2112, 1), vlr("Vendor", 1, 5)]),
+            ),
+            (
+                "tile_3.las".to_string(),
+                header_with(vec![vlr("LASF_Projection", 2112, 2)]),
+            ),
+        ];
+
+        let merged = VlrMerge::new(VlrPrecedence::First)
+            .header(first.clone(), &others)
+            .unwrap();
+        assert_eq!(
+            merged.vlrs(),
+            &vec![vlr("LASF_Projection", 2112, 1), vlr("Vendor", 1, 5)]
+        );
+        let merged = VlrMerge::new(VlrPrecedence::Last)
+            .header(first.clone(), &others)
+            .unwrap();
+        assert_eq!(merged.vlrs()[0], vlr("LASF_Projection", 2112, 2));
+        assert!(matches!(
+            VlrMerge::new(VlrPrecedence::Fail).header(first, &others),
+            Err(MyError::VlrConflict(path, _, 2112)) if path == "tile_3.las"
+        ));
+    }
+}
@@ -0,0 +1,98 @@
+//! Watching a drop folder and handing over new LAS/LAZ files as they arrive.
+//!
+//! This needs the `watch` feature.
+use crate::errors::MyError;
+use std::path::Path;
+
+/// Watches `dir` for new files with one of `extensions` and calls `on_new_file` with the path of
+/// each one, once its size has stopped changing so that files still being copied in aren't
+/// picked up half-written. Each file is handed over once.
+///
+/// This only returns if the watcher fails or shuts down.
+#[cfg(feature = "watch")]
+pub fn watch_directory<F>(
+    dir: &Path,
+    extensions: &[String],
+    mut on_new_file: F,
+) -> Result<(), MyError>
+where
+    F: FnMut(&str),
+{
+    use crate::input::has_extension;
+    use notify::{RecursiveMode, Watcher};
+    use std::collections::{HashMap, HashSet};
+    use std::path::PathBuf;
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use std::time::{Duration, Instant};
+
+    /// How long a file's size has to stay the same before it is considered complete.
+    const SETTLE_INTERVAL: Duration = Duration::from_secs(1);
+
+    let (tx, rx) = mpsc::channel::<notify::Result<notify::Event>>();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    // Files that have shown up, with the size they had when last checked.
+    let mut pending: HashMap<PathBuf, Option<u64>> = HashMap::new();
+    let mut handed_over: HashSet<PathBuf> = HashSet::new();
+    let mut last_check = Instant::now();
+    loop {
+        match rx.recv_timeout(SETTLE_INTERVAL) {
+            Ok(event) => {
+                let event = event?;
+                if event.kind.is_remove() {
+                    continue;
+                }
+                for path in event.paths {
+                    if has_extension(&path, extensions) && !handed_over.contains(&path) {
+                        pending.entry(path).or_insert(None);
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        if last_check.elapsed() < SETTLE_INTERVAL {
+            continue;
+        }
+        last_check = Instant::now();
+
+        let mut ready = Vec::new();
+        pending.retain(|path, last_size| {
+            let Ok(metadata) = std::fs::metadata(path) else {
+                // The file went away again before it settled.
+                return false;
+            };
+            let size = metadata.len();
+            if size > 0 && *last_size == Some(size) {
+                ready.push(path.clone());
+                false
+            } else {
+                *last_size = Some(size);
+                true
+            }
+        });
+        ready.sort();
+        for path in ready {
+            on_new_file(&path.to_string_lossy());
+            handed_over.insert(path);
+        }
+    }
+}
+
+/// Watches `dir` for new files with one of `extensions`.
+#[cfg(not(feature = "watch"))]
+pub fn watch_directory<F>(
+    dir: &Path,
+    _extensions: &[String],
+    _on_new_file: F,
+) -> Result<(), MyError>
+where
+    F: FnMut(&str),
+{
+    Err(MyError::FeatureNotEnabled(
+        dir.to_string_lossy().to_string(),
+        "watch",
+    ))
+}
@@ -0,0 +1,90 @@
+//! Dropping the full-waveform data of point formats 4, 5, 9 and 10.
+//!
+//! Each point of these formats refers to its waveform packet by a byte offset into waveform data
+//! held after the points, in an EVLR or in a `.wdp` file next to the input. The outputs are
+//! written without that data, and merged inputs would need their offsets rebased, so the
+//! references can't be carried over. Instead the points are written in the same format without
+//! the waveform fields, and the waveform packet descriptors and data are left out.
+use crate::errors::MyError;
+use las::point::Format;
+use las::{Builder, Header, Vlr};
+
+/// The user id of the waveform VLRs and EVLRs.
+const USER_ID: &str = "LASF_Spec";
+
+/// The record ids of the waveform packet descriptors.
+const DESCRIPTOR_RECORD_IDS: std::ops::RangeInclusive<u16> = 100..=354;
+
+/// The record id of the EVLR holding the waveform data.
+const DATA_RECORD_ID: u16 = 65535;
+
+/// The point format with the fields of `format` but the waveform, keeping its extra bytes.
+pub fn without_waveform(format: Format) -> Result<Format, MyError> {
+    if !format.has_waveform {
+        return Ok(format);
+    }
+    let number = match format.to_u8()? {
+        4 => 1,
+        5 => 3,
+        9 => 6,
+        _ => 7,
+    };
+    let mut without = Format::new(number)?;
+    without.extra_bytes = format.extra_bytes;
+    without.is_compressed = format.is_compressed;
+    Ok(without)
+}
+
+/// Returns `true` if `vlr` is a waveform packet descriptor or holds waveform data.
+pub fn is_waveform_vlr(vlr: &Vlr) -> bool {
+    vlr.user_id == USER_ID
+        && (DESCRIPTOR_RECORD_IDS.contains(&vlr.record_id) || vlr.record_id == DATA_RECORD_ID)
+}
+
+/// The header of outputs made from `header` with the waveforms dropped.
+pub fn drop_waveforms(header: Header) -> Result<Header, MyError> {
+    let mut builder = Builder::from(header);
+    builder.point_format = without_waveform(builder.point_format)?;
+    builder.vlrs.retain(|vlr| !is_waveform_vlr(vlr));
+    builder.evlrs.retain(|vlr| !is_waveform_vlr(vlr));
+    Ok(builder.into_header()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_waveforms() {
+        for (number, expected) in [(4, 1), (5, 3), (9, 6), (10, 7), (6, 6)] {
+            let mut format = Format::new(number).unwrap();
+            format.extra_bytes = 2;
+            let without = without_waveform(format).unwrap();
+            assert_eq!(without.to_u8().unwrap(), expected);
+            assert_eq!(without.extra_bytes, 2);
+            assert!(!without.has_waveform);
+        }
+
+        let mut builder = Builder::from((1, 4));
+        builder.point_format = Format::new(9).unwrap();
+        for record_id in [2112, 100, 354] {
+            builder.vlrs.push(Vlr {
+                user_id: USER_ID.to_string(),
+                record_id,
+                description: String::new(),
+                data: vec![0; 26],
+            });
+        }
+        builder.evlrs.push(Vlr {
+            user_id: USER_ID.to_string(),
+            record_id: DATA_RECORD_ID,
+            description: String::new(),
+            data: vec![0; 100],
+        });
+        let header = drop_waveforms(builder.into_header().unwrap()).unwrap();
+        assert_eq!(header.point_format().to_u8().unwrap(), 6);
+        let record_ids: Vec<u16> = header.vlrs().iter().map(|vlr| vlr.record_id).collect();
+        assert_eq!(record_ids, [2112]);
+        assert!(header.evlrs().is_empty());
+    }
+}
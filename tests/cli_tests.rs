@@ -198,6 +198,3096 @@ fn test_cli_mismatched_filters_and_outputs() {
     ));
 }
 
+#[test]
+fn test_cli_stdin_input() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+
+    // Create a test .las file with some dummy data and pipe it in on stdin
+    create_test_las_file(input_file_path.to_str().unwrap());
+    let input_bytes = fs::read(&input_file_path).unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg("-")
+        .arg("--output")
+        .arg(output_file_path.clone())
+        .arg("--filter")
+        .arg("always-true")
+        .write_stdin(input_bytes);
+
+    cmd.assert().success();
+
+    let output_file = fs::File::open(output_file_path).unwrap();
+    let reader = las::Reader::new(output_file).unwrap();
+    assert_eq!(reader.header().number_of_points(), 10);
+}
+
+#[test]
+fn test_cli_stdout_output() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+
+    // Create a test .las file with some dummy data
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(input_file_path)
+        .arg("--output")
+        .arg("-")
+        .arg("--filter")
+        .arg("always-true");
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+
+    // Verify that stdout carries a complete LAS file and nothing else
+    let reader = las::Reader::new(std::io::Cursor::new(output.stdout)).unwrap();
+    assert_eq!(reader.header().number_of_points(), 10);
+}
+
+#[test]
+#[cfg(not(feature = "http"))]
+fn test_cli_http_input_needs_feature() {
+    let dir = tempdir().unwrap();
+    let output_file_path = dir.path().join("output.las");
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg("https://example.com/tile.laz")
+        .arg("--output")
+        .arg(output_file_path)
+        .arg("--filter")
+        .arg("always-true");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("needs the `http` feature"));
+}
+
+#[test]
+fn test_cli_recursive_directory_input() {
+    let dir = tempdir().unwrap();
+    let input_dir = dir.path().join("tiles");
+    let nested_dir = input_dir.join("nested");
+    fs::create_dir_all(&nested_dir).unwrap();
+    let output_file_path = dir.path().join("output.las");
+
+    // One .las at the top level and one .laz in a subdirectory
+    create_test_las_file(input_dir.join("a.las").to_str().unwrap());
+    create_test_las_file(nested_dir.join("b.laz").to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_dir)
+        .arg("--recursive")
+        .arg("--output")
+        .arg(output_file_path.clone())
+        .arg("--filter")
+        .arg("always-true");
+
+    cmd.assert().success();
+
+    let output_file = fs::File::open(output_file_path).unwrap();
+    let reader = las::Reader::new(output_file).unwrap();
+    assert_eq!(reader.header().number_of_points(), 20);
+}
+
+#[test]
+fn test_cli_input_list() {
+    let dir = tempdir().unwrap();
+    let input_file_path1 = dir.path().join("test1.las");
+    let input_file_path2 = dir.path().join("test2.las");
+    let list_path = dir.path().join("files.txt");
+    let output_file_path = dir.path().join("output.las");
+
+    create_test_las_file(input_file_path1.to_str().unwrap());
+    create_test_las_file(input_file_path2.to_str().unwrap());
+    fs::write(
+        &list_path,
+        format!(
+            "# inputs\n{}\n\n{}\n",
+            input_file_path1.display(),
+            input_file_path2.display()
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input-list")
+        .arg(&list_path)
+        .arg("--output")
+        .arg(output_file_path.clone())
+        .arg("--filter")
+        .arg("always-true");
+
+    cmd.assert().success();
+
+    let output_file = fs::File::open(output_file_path).unwrap();
+    let reader = las::Reader::new(output_file).unwrap();
+    assert_eq!(reader.header().number_of_points(), 20);
+}
+
+#[test]
+fn test_cli_skip_existing() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+
+    create_test_las_file(input_file_path.to_str().unwrap());
+    fs::write(&output_file_path, b"placeholder").unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--skip-existing");
+
+    cmd.assert().success();
+
+    // The existing output was left alone
+    assert_eq!(fs::read(&output_file_path).unwrap(), b"placeholder");
+}
+
+#[test]
+fn test_cli_existing_output_needs_force() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+
+    create_test_las_file(input_file_path.to_str().unwrap());
+    fs::write(&output_file_path, b"placeholder").unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("already exists"));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--force");
+    cmd.assert().success();
+
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().number_of_points(), 10);
+}
+
+#[test]
+fn test_cli_output_same_as_input() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&input_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--force");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("is also one of the inputs"));
+
+    // The input is untouched
+    let reader = las::Reader::from_path(&input_file_path).unwrap();
+    assert_eq!(reader.header().number_of_points(), 10);
+}
+
+#[test]
+fn test_cli_journal_resumes_per_input_outputs() {
+    let dir = tempdir().unwrap();
+    let input_file_path1 = dir.path().join("a.las");
+    let input_file_path2 = dir.path().join("b.las");
+    let journal_path = dir.path().join("run.journal");
+    let output_template = dir.path().join("{stem}_out.las");
+
+    create_test_las_file(input_file_path1.to_str().unwrap());
+    create_test_las_file(input_file_path2.to_str().unwrap());
+    // Pretend an earlier run already finished the first input
+    fs::write(
+        &journal_path,
+        format!("{}\n", input_file_path1.to_str().unwrap()),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path1)
+        .arg("--input")
+        .arg(&input_file_path2)
+        .arg("--output")
+        .arg(&output_template)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--journal")
+        .arg(&journal_path);
+
+    cmd.assert().success();
+
+    assert!(!dir.path().join("a_out.las").exists());
+    let reader = las::Reader::from_path(dir.path().join("b_out.las")).unwrap();
+    assert_eq!(reader.header().number_of_points(), 10);
+    let journal = fs::read_to_string(&journal_path).unwrap();
+    assert_eq!(journal.lines().count(), 2);
+}
+
+#[test]
+fn test_cli_on_error_skip() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("good.las");
+    let broken_file_path = dir.path().join("broken.las");
+    let output_file_path = dir.path().join("output.las");
+
+    create_test_las_file(input_file_path.to_str().unwrap());
+    fs::write(&broken_file_path, b"not a las file").unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(dir.path())
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--on-error")
+        .arg("skip");
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("broken.las"));
+
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().number_of_points(), 10);
+}
+
+#[test]
+fn test_cli_progress_modes() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    for mode in ["plain", "bars"] {
+        let output_file_path = dir.path().join(format!("{}.las", mode));
+        let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+        cmd.arg("--input")
+            .arg(&input_file_path)
+            .arg("--output")
+            .arg(&output_file_path)
+            .arg("--filter")
+            .arg("always-true")
+            .arg("--progress")
+            .arg(mode);
+        cmd.assert().success();
+        assert!(output_file_path.exists());
+    }
+}
+
+#[test]
+fn test_cli_json_progress() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--progress")
+        .arg("json");
+    let output = cmd.assert().success().get_output().clone();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    // Every line is an event, logs included
+    let events: Vec<serde_json::Value> = stderr
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|_| panic!("not JSON: {}", line)))
+        .collect();
+    assert!(events.iter().any(|event| event["event"] == "log"));
+    let finished = events
+        .iter()
+        .find(|event| event["event"] == "finished")
+        .expect("no finished event");
+    assert_eq!(finished["points_written"], 10);
+    assert_eq!(finished["files"][0]["state"], "done");
+}
+
+#[test]
+fn test_cli_log_level() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let run = |log_level: &str, output: &str| {
+        let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+        cmd.arg("--input")
+            .arg(&input_file_path)
+            .arg("--output")
+            .arg(dir.path().join(output))
+            .arg("--filter")
+            .arg("always-true")
+            .arg("--progress")
+            .arg("plain")
+            .arg("--log-level")
+            .arg(log_level);
+        cmd.assert().success()
+    };
+
+    run("info", "info.las").stderr(predicates::str::contains("Total points read/written"));
+    run("warn", "warn.las").stderr(predicates::str::is_empty());
+    run("debug", "debug.las").stderr(predicates::str::contains("Starting read thread"));
+}
+
+#[test]
+fn test_cli_quiet_and_verbose() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let run = |flag: &str, output: &str| {
+        let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+        cmd.arg("--input")
+            .arg(&input_file_path)
+            .arg("--output")
+            .arg(dir.path().join(output))
+            .arg("--filter")
+            .arg("always-true")
+            .arg(flag);
+        cmd.assert().success()
+    };
+
+    run("-q", "quiet.las").stderr(predicates::str::is_empty());
+    run("-v", "verbose.las").stderr(predicates::str::contains("Starting read thread"));
+
+    // Progress asked for is still printed, but nothing else
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("progress.las"))
+        .arg("--filter")
+        .arg("always-true")
+        .arg("-q")
+        .arg("--progress")
+        .arg("plain");
+    cmd.assert()
+        .success()
+        .stderr(predicates::str::contains("Total points read/written"));
+}
+
+#[test]
+fn test_cli_log_file() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+    let log_file_path = dir.path().join("run.log");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--quiet")
+        .arg("--log-file")
+        .arg(&log_file_path);
+    cmd.assert().success().stderr(predicates::str::is_empty());
+
+    // The log file gets the per-file statistics even though the console is quiet
+    let log = fs::read_to_string(&log_file_path).unwrap();
+    assert!(log.contains("Read 10 points from"));
+    assert!(log.contains("Wrote 10 points to"));
+}
+
+#[test]
+fn test_cli_thread_counts() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path1 = dir.path().join("output1.las");
+    let output_file_path2 = dir.path().join("output2.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path1)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--output")
+        .arg(&output_file_path2)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--threads")
+        .arg("1")
+        .arg("--writer-threads")
+        .arg("2");
+    cmd.assert().success();
+
+    for output_file_path in [output_file_path1, output_file_path2] {
+        let reader = las::Reader::from_path(output_file_path).unwrap();
+        assert_eq!(reader.header().number_of_points(), 10);
+    }
+}
+
+#[test]
+fn test_cli_batch_size_and_channel_depth() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--batch-size")
+        .arg("3")
+        .arg("--channel-depth")
+        .arg("1")
+        .arg("--progress")
+        .arg("json");
+    let output = cmd.assert().success().get_output().clone();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let finished = stderr
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|event| event["event"] == "finished")
+        .unwrap();
+    assert_eq!(finished["points_written"], 10);
+    assert_eq!(finished["batch_size"], 3);
+    assert_eq!(finished["channel_depth"], 1);
+    assert_eq!(finished["auto_tuned"], false);
+}
+
+#[test]
+fn test_cli_max_memory() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--max-memory")
+        .arg("1K")
+        .arg("--progress")
+        .arg("json");
+    let output = cmd.assert().success().get_output().clone();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let finished = stderr
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|event| event["event"] == "finished")
+        .unwrap();
+    assert_eq!(finished["points_written"], 10);
+    assert_eq!(finished["channel_depth"], 1);
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--max-memory")
+        .arg("lots");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("invalid size"));
+}
+
+#[test]
+fn test_cli_laz_chunk_size() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.laz");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--laz-chunk-size")
+        .arg("4");
+    cmd.assert().success();
+
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().laz_vlr().unwrap().chunk_size(), 4);
+    assert_eq!(reader.header().number_of_points(), 10);
+}
+
+#[test]
+fn test_cli_parallel_compression() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.laz");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--laz-chunk-size")
+        .arg("3")
+        .arg("--parallel-compression");
+    cmd.assert().success();
+
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().laz_vlr().unwrap().chunk_size(), 3);
+    let xs: Vec<f64> = reader.points().map(|point| point.unwrap().x).collect();
+    assert_eq!(xs, (0..10).map(f64::from).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_cli_rayon_backend() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--backend")
+        .arg("rayon");
+    cmd.assert().success();
+
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().number_of_points(), 10);
+
+    // Options of the channel between readers and writers are refused
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("tuned.las"))
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--backend")
+        .arg("rayon")
+        .arg("--channel-depth")
+        .arg("4");
+    cmd.assert().failure().stderr(predicates::str::contains(
+        "--channel-depth can't be used with the rayon backend",
+    ));
+}
+
+#[test]
+fn test_cli_unknown_option_of_command() {
+    // A mistake in a command is reported for that command, not taken as an option of trim
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("--log-level")
+        .arg("debug")
+        .arg("merge")
+        .arg("--bogus");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("'--bogus'"))
+        .stderr(predicates::str::contains("Usage: las_trimmer merge"));
+}
+
+#[test]
+fn test_cli_merge_and_diff() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let input_file_path2 = dir.path().join("test2.las");
+    let output_file_path = dir.path().join("merged.laz");
+    create_test_las_file(input_file_path.to_str().unwrap());
+    create_test_las_file(input_file_path2.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--input")
+        .arg(&input_file_path2)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--verify");
+    cmd.assert().success();
+
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().number_of_points(), 20);
+
+    let copy_file_path = dir.path().join("copy.laz");
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&copy_file_path);
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("diff").arg(&input_file_path).arg(&copy_file_path);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("hold the same points"));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("diff")
+        .arg("tests/data/input1.las")
+        .arg("tests/data/input2.las");
+    cmd.assert()
+        .failure()
+        .stdout(predicates::str::contains("differ"));
+
+    // Rewriting input1.las rounds its scan angles and recomputes its bounds
+    let round_trip_path = dir.path().join("round_trip.las");
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg("tests/data/input1.las")
+        .arg("--output")
+        .arg(&round_trip_path);
+    cmd.assert().success();
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("diff")
+        .arg("tests/data/input1.las")
+        .arg(&round_trip_path)
+        .arg("--points")
+        .arg("2");
+    cmd.assert()
+        .failure()
+        .stdout(predicates::str::contains("scan_angle: 8137 points differ"));
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("diff")
+        .arg("tests/data/input1.las")
+        .arg(&round_trip_path)
+        .arg("--tolerance")
+        .arg("x=0.002")
+        .arg("--tolerance")
+        .arg("scan_angle=0.01");
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("hold the same points"));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("diff")
+        .arg(&input_file_path)
+        .arg(&copy_file_path)
+        .arg("--tolerance")
+        .arg("height=1");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("invalid tolerance"));
+}
+
+#[test]
+fn test_cli_split() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("split")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("{stem}_{part}.las"))
+        .arg("--points")
+        .arg("4");
+    cmd.assert().success();
+
+    for (part, points) in [(1, 4), (2, 4), (3, 2)] {
+        let path = dir.path().join(format!("test_{}.las", part));
+        let reader = las::Reader::from_path(&path).unwrap();
+        assert_eq!(reader.header().number_of_points(), points);
+    }
+    assert!(!dir.path().join("test_4.las").exists());
+}
+
+#[test]
+fn test_cli_tile() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    // The points run from (0, 0) to (9, 9) along the diagonal
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("tile")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("tile_{x}_{y}.las"))
+        .arg("--size")
+        .arg("5");
+    cmd.assert().success();
+
+    for corner in ["0_0", "5_5"] {
+        let path = dir.path().join(format!("tile_{}.las", corner));
+        let reader = las::Reader::from_path(&path).unwrap();
+        assert_eq!(reader.header().number_of_points(), 5);
+    }
+    // The off-diagonal tiles are empty and not written
+    assert!(!dir.path().join("tile_5_0.las").exists());
+    assert!(!dir.path().join("tile_0_5.las").exists());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("tile")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("tile.las"))
+        .arg("--size")
+        .arg("5");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("{x} and {y}"));
+}
+
+#[test]
+fn test_cli_info_validate_and_index() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let index_path = dir.path().join("index.csv");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("info").arg("--input").arg(&input_file_path);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("points:              10"));
+
+    // The points of the test file have no return number, which is only a warning
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("validate").arg("--input").arg(&input_file_path);
+    cmd.assert().success().stdout(predicates::str::contains(
+        "warning: 10 points have return number 0",
+    ));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("validate")
+        .arg("--input")
+        .arg("tests/data/input1.las")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--json");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let reports: serde_json::Value =
+        serde_json::from_str(&String::from_utf8(output).unwrap()).unwrap();
+    assert_eq!(reports[0]["path"], "tests/data/input1.las");
+    assert_eq!(reports[0]["issues"].as_array().unwrap().len(), 0);
+    assert_eq!(reports[1]["valid"], true);
+    assert_eq!(reports[1]["issues"][0]["severity"], "warning");
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("index")
+        .arg("--input")
+        .arg(dir.path())
+        .arg("--output")
+        .arg(&index_path);
+    cmd.assert().success();
+    let index = std::fs::read_to_string(&index_path).unwrap();
+    let lines: Vec<_> = index.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].ends_with(",10,0,0,0,9,9,9"));
+}
+
+#[test]
+fn test_cli_pipeline_file() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let pipeline_path = dir.path().join("job.json");
+    create_test_las_file(input_file_path.to_str().unwrap());
+    let pipeline = format!(
+        r#"{{
+            "inputs": [{:?}],
+            "batch_size": 4,
+            "force": true,
+            "outputs": [{{"path": {:?}, "filter": "always-true"}}]
+        }}"#,
+        input_file_path.to_str().unwrap(),
+        dir.path().join("kept.las").to_str().unwrap()
+    );
+    std::fs::write(&pipeline_path, pipeline).unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim").arg("--pipeline").arg(&pipeline_path);
+    cmd.assert().success();
+    let reader = las::Reader::from_path(dir.path().join("kept.las")).unwrap();
+    assert_eq!(reader.header().number_of_points(), 10);
+
+    // Outputs on the command line replace the ones in the file
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--pipeline")
+        .arg(&pipeline_path)
+        .arg("--output")
+        .arg(dir.path().join("override.las"))
+        .arg("--filter")
+        .arg("always-false");
+    cmd.assert().success();
+    let reader = las::Reader::from_path(dir.path().join("override.las")).unwrap();
+    assert_eq!(reader.header().number_of_points(), 0);
+
+    std::fs::write(&pipeline_path, r#"{"no_such_option": 1}"#).unwrap();
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim").arg("--pipeline").arg(&pipeline_path);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("unknown option `no-such-option`"));
+}
+
+#[test]
+fn test_cli_pdal_pipeline() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("cropped.las");
+    let pipeline_path = dir.path().join("pdal.json");
+    create_test_las_file(input_file_path.to_str().unwrap());
+    let pipeline = format!(
+        r#"{{"pipeline": [
+            {:?},
+            {{"type": "filters.crop", "bounds": "([2, 5], [0, 10])"}},
+            {{"type": "filters.range", "limits": "Z[:4]"}},
+            {{"type": "writers.las", "filename": {:?}}}
+        ]}}"#,
+        input_file_path.to_str().unwrap(),
+        output_file_path.to_str().unwrap()
+    );
+    std::fs::write(&pipeline_path, pipeline).unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim").arg("--pipeline").arg(&pipeline_path);
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    let xs: Vec<f64> = reader.points().map(|point| point.unwrap().x).collect();
+    assert_eq!(xs, [2.0, 3.0, 4.0]);
+}
+
+#[test]
+fn test_cli_presets() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("west.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+    std::fs::create_dir(dir.path().join("presets")).unwrap();
+    std::fs::write(
+        dir.path().join("presets/west.json"),
+        r#"{"description": "The west of the site", "stages": [
+            {"type": "filters.crop", "bounds": "([0, 3], [0, 10])"}
+        ]}"#,
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.env("LAS_TRIMMER_CONFIG_DIR", dir.path())
+        .arg("trim")
+        .arg("--list-presets");
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("ground-only"))
+        .stdout(predicates::str::contains("The west of the site"));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.env("LAS_TRIMMER_CONFIG_DIR", dir.path())
+        .arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--preset")
+        .arg("west");
+    cmd.assert().success();
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().number_of_points(), 4);
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.env("LAS_TRIMMER_CONFIG_DIR", dir.path())
+        .arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--preset")
+        .arg("roads");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("Unknown preset roads"));
+}
+
+#[test]
+fn test_cli_dry_run() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--dry-run");
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("1 input(s), 10 points"))
+        .stdout(predicates::str::contains(format!(
+            "{}: LAS, at most 10 points, about 575 B",
+            output_file_path.to_str().unwrap()
+        )));
+    assert!(!output_file_path.exists());
+
+    // The checks of a real run still apply
+    create_test_las_file(output_file_path.to_str().unwrap());
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--dry-run");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("already exists"));
+}
+
+#[test]
+fn test_cli_bench() {
+    let dir = tempdir().unwrap();
+    let recommendation_path = dir.path().join("tuning.json");
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("bench")
+        .arg("tests/data/input1.las")
+        .arg("--threads")
+        .arg("1,2")
+        .arg("--batch-sizes")
+        .arg("1000")
+        .arg("--channel-depths")
+        .arg("2")
+        .arg("--output")
+        .arg(&recommendation_path);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("points/s"))
+        .stdout(predicates::str::contains(
+            "--batch-size 1000 --channel-depth 2",
+        ));
+    let recommendation = std::fs::read_to_string(&recommendation_path).unwrap();
+    assert!(recommendation.contains("\"channel_depth\": 2"));
+}
+
+#[test]
+fn test_cli_repair() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("repaired.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    // The max X of the header is a little-endian f64 at byte 179
+    let mut data = std::fs::read(&input_file_path).unwrap();
+    data[179..187].copy_from_slice(&5.0f64.to_le_bytes());
+    std::fs::write(&input_file_path, &data).unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("repair")
+        .arg(&input_file_path)
+        .arg(&output_file_path);
+    cmd.assert().success().stdout(predicates::str::contains(
+        "the bounds were 0 0 0 to 5 9 9, now 0 0 0 to 9 9 9",
+    ));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("validate").arg("--input").arg(&output_file_path);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    assert!(!String::from_utf8(output).unwrap().contains("header bounds"));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("repair")
+        .arg(&output_file_path)
+        .arg(&output_file_path);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("is also one of the inputs"));
+}
+
+#[test]
+fn test_cli_boundary() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("boundary.geojson");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("boundary").arg("--input").arg(&input_file_path);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("\"FeatureCollection\""))
+        .stdout(predicates::str::contains("\"points\":10"));
+
+    // The points lie on a diagonal, so the cells holding them only touch at their corners
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("boundary")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--concave")
+        .arg("1")
+        .arg("--output")
+        .arg(&output_file_path);
+    cmd.assert().success();
+    let geojson = fs::read_to_string(&output_file_path).unwrap();
+    assert!(geojson.contains("\"MultiPolygon\""));
+    assert!(geojson.contains("[[[9,9],[10,9],[10,10],[9,10],[9,9]]]"));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("boundary")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--concave")
+        .arg("0");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("invalid cell size"));
+}
+
+#[test]
+fn test_cli_density() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+    let density_path = dir.path().join("density.tif");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("density")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&density_path)
+        .arg("--cell-size")
+        .arg("5");
+    cmd.assert().success();
+    // Two cells of 5 points each, top left holding the points from 5 to 9
+    let tiff = fs::read(&density_path).unwrap();
+    assert_eq!(&tiff[..4], b"II*\0");
+    let pixel =
+        |index: usize| f32::from_le_bytes(tiff[8 + index * 4..12 + index * 4].try_into().unwrap());
+    assert_eq!(
+        [pixel(0), pixel(1), pixel(2), pixel(3)],
+        [0.0, 5.0, 5.0, 0.0]
+    );
+
+    // Alongside the LAS output, counting every point read
+    fs::remove_file(&density_path).unwrap();
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-false")
+        .arg("--density")
+        .arg(&density_path)
+        .arg("--density-cell-size")
+        .arg("5");
+    cmd.assert().success();
+    assert!(output_file_path.exists());
+    assert_eq!(fs::read(&density_path).unwrap(), tiff);
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("density")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("density.png"));
+    cmd.assert().failure().stderr(predicates::str::contains(
+        "must have a .tif or .tiff extension",
+    ));
+}
+
+#[test]
+fn test_cli_surface() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+    let dsm_path = dir.path().join("dsm.tif");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("surface")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&dsm_path)
+        .arg("--kind")
+        .arg("dsm")
+        .arg("--method")
+        .arg("max")
+        .arg("--cell-size")
+        .arg("5");
+    cmd.assert().success();
+    // The highest points of the two cells holding points, the others without a height
+    let tiff = fs::read(&dsm_path).unwrap();
+    let pixel =
+        |index: usize| f32::from_le_bytes(tiff[8 + index * 4..12 + index * 4].try_into().unwrap());
+    assert_eq!(
+        [pixel(0), pixel(1), pixel(2), pixel(3)],
+        [-9999.0, 9.0, 4.0, -9999.0]
+    );
+
+    // Alongside the LAS output
+    fs::remove_file(&dsm_path).unwrap();
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--dsm")
+        .arg(&dsm_path)
+        .arg("--surface-method")
+        .arg("max")
+        .arg("--surface-cell-size")
+        .arg("5");
+    cmd.assert().success();
+    assert!(output_file_path.exists());
+    assert_eq!(fs::read(&dsm_path).unwrap(), tiff);
+}
+
+#[test]
+fn test_cli_intensity() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("merged.las");
+    let intensity_path = dir.path().join("intensity.tiff");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--intensity")
+        .arg(&intensity_path)
+        .arg("--intensity-cell-size")
+        .arg("5");
+    cmd.assert().success();
+    // The points have no intensity, and the cells without points no value
+    let tiff = fs::read(&intensity_path).unwrap();
+    let pixel =
+        |index: usize| f32::from_le_bytes(tiff[8 + index * 4..12 + index * 4].try_into().unwrap());
+    assert_eq!(
+        [pixel(0), pixel(1), pixel(2), pixel(3)],
+        [-9999.0, 0.0, 0.0, -9999.0]
+    );
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--intensity")
+        .arg(dir.path().join("intensity.jpg"));
+    cmd.assert().failure().stderr(predicates::str::contains(
+        "must have a .tif or .tiff extension",
+    ));
+}
+
+#[test]
+fn test_cli_canopy() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("output.las");
+    let canopy_path = dir.path().join("canopy.csv");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--canopy")
+        .arg(&canopy_path)
+        .arg("--canopy-cell-size")
+        .arg("5")
+        .arg("--canopy-percentiles")
+        .arg("50");
+    cmd.assert().success();
+    // Without ground points, the heights are above the lowest point of each cell
+    assert_eq!(
+        fs::read_to_string(&canopy_path).unwrap(),
+        "x,y,cover,max_height,p50\n7.5,7.5,1,4,2\n2.5,2.5,1,4,2\n"
+    );
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--force")
+        .arg("--canopy")
+        .arg(&canopy_path)
+        .arg("--canopy-percentiles")
+        .arg("101");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("invalid percentile `101`"));
+}
+
+#[test]
+fn test_cli_profile() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let csv_path = dir.path().join("profile.csv");
+    let las_path = dir.path().join("profile.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    // A line east along y = 2, crossing the points on the diagonal
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("profile")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&csv_path)
+        .arg("--line")
+        .arg("0,2,8,2")
+        .arg("--width")
+        .arg("3");
+    cmd.assert().success();
+    assert_eq!(
+        fs::read_to_string(&csv_path).unwrap(),
+        "station,offset,x,y,z,intensity,classification,return_number\n\
+         1,-1,1,1,1,0,0,0\n2,0,2,2,2,0,0,0\n3,1,3,3,3,0,0,0\n"
+    );
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("profile")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&las_path)
+        .arg("--line")
+        .arg("0,2,8,2")
+        .arg("--width")
+        .arg("3");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&las_path).unwrap();
+    assert_eq!(reader.header().point_format().extra_bytes, 16);
+    let stations: Vec<f64> = reader
+        .points()
+        .map(|point| f64::from_le_bytes(point.unwrap().extra_bytes[..8].try_into().unwrap()))
+        .collect();
+    assert_eq!(stations, [1.0, 2.0, 3.0]);
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("profile")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&csv_path)
+        .arg("--force")
+        .arg("--line")
+        .arg("0,2,8")
+        .arg("--width")
+        .arg("3");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid profile"));
+}
+
+#[test]
+fn test_cli_keep_returns() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("returns.las");
+    let output_file_path = dir.path().join("output.las");
+    // Pulses of one, two and three returns, the later returns on scanner channel 1
+    let mut builder = las::Builder::from((1, 4));
+    builder.point_format = las::point::Format::new(6).unwrap();
+    let mut writer =
+        las::Writer::from_path(&input_file_path, builder.into_header().unwrap()).unwrap();
+    for (return_number, number_of_returns) in [(1, 1), (1, 2), (2, 2), (1, 3), (2, 3), (3, 3)] {
+        writer
+            .write_point(las::Point {
+                return_number,
+                number_of_returns,
+                scanner_channel: u8::from(return_number > 1),
+                gps_time: Some(0.0),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let returns_kept = |flags: &[&str]| {
+        let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+        cmd.arg("trim")
+            .arg("--input")
+            .arg(&input_file_path)
+            .arg("--output")
+            .arg(&output_file_path)
+            .arg("--force")
+            .args(flags);
+        cmd.assert().success();
+        let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+        reader
+            .points()
+            .map(|point| {
+                let point = point.unwrap();
+                (point.return_number, point.number_of_returns)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(returns_kept(&["--keep-first"]), [(1, 1), (1, 2), (1, 3)]);
+    assert_eq!(returns_kept(&["--keep-last"]), [(1, 1), (2, 2), (3, 3)]);
+    assert_eq!(returns_kept(&["--keep-single"]), [(1, 1)]);
+    assert_eq!(
+        returns_kept(&["--keep-last", "--keep-scanner-channel", "1"]),
+        [(2, 2), (3, 3)]
+    );
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--keep-first")
+        .arg("--keep-last");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_cli_scan_flags() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("flags.las");
+    let output_file_path = dir.path().join("output.las");
+    // Scan lines of three points in alternating directions, the last point of each on the edge
+    let mut writer = las::Writer::from_path(
+        &input_file_path,
+        las::Builder::from((1, 2)).into_header().unwrap(),
+    )
+    .unwrap();
+    for i in 0..6 {
+        writer
+            .write_point(las::Point {
+                x: f64::from(i),
+                scan_direction: if i < 3 {
+                    las::point::ScanDirection::LeftToRight
+                } else {
+                    las::point::ScanDirection::RightToLeft
+                },
+                is_edge_of_flight_line: i % 3 == 2,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let kept = |flags: &[&str]| {
+        let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+        cmd.arg("trim")
+            .arg("--input")
+            .arg(&input_file_path)
+            .arg("--output")
+            .arg(&output_file_path)
+            .arg("--force")
+            .args(flags);
+        cmd.assert().success();
+        let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+        reader
+            .points()
+            .map(|point| point.unwrap().x)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(kept(&["--drop-edge-of-flight-line"]), [0.0, 1.0, 3.0, 4.0]);
+    assert_eq!(
+        kept(&["--keep-scan-direction", "right-to-left"]),
+        [3.0, 4.0, 5.0]
+    );
+    assert_eq!(
+        kept(&[
+            "--drop-edge-of-flight-line",
+            "--keep-scan-direction",
+            "left-to-right"
+        ]),
+        [0.0, 1.0]
+    );
+}
+
+#[test]
+fn test_cli_gps_time() {
+    let dir = tempdir().unwrap();
+    let week_path = dir.path().join("week.las");
+    let standard_path = dir.path().join("standard.las");
+    let output_file_path = dir.path().join("merged.las");
+    // The same moment, noon on Tuesday of GPS week 2000, in both representations
+    let week_time = 2.0 * 86_400.0 + 43_200.0;
+    let standard_time = 2000.0 * 604_800.0 + week_time - 1e9;
+    for (path, gps_time_type, gps_time) in [
+        (&week_path, las::GpsTimeType::Week, week_time),
+        (&standard_path, las::GpsTimeType::Standard, standard_time),
+    ] {
+        let mut builder = las::Builder::from((1, 2));
+        builder.point_format = las::point::Format::new(1).unwrap();
+        builder.gps_time_type = gps_time_type;
+        let mut writer = las::Writer::from_path(path, builder.into_header().unwrap()).unwrap();
+        writer
+            .write_point(las::Point {
+                gps_time: Some(gps_time),
+                ..Default::default()
+            })
+            .unwrap();
+        writer.close().unwrap();
+    }
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&week_path)
+        .arg("--input")
+        .arg(&standard_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--gps-time")
+        .arg("standard")
+        .arg("--gps-week")
+        .arg("2000")
+        .arg("--gps-time-offset")
+        .arg("-1.5");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().gps_time_type(), las::GpsTimeType::Standard);
+    for point in reader.points() {
+        assert_eq!(point.unwrap().gps_time, Some(standard_time - 1.5));
+    }
+
+    // Week time can't be placed in standard time without the week
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&week_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--gps-time")
+        .arg("standard");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("needs the GPS week"));
+}
+
+#[test]
+fn test_cli_tag_source() {
+    let dir = tempdir().unwrap();
+    let first_path = dir.path().join("first.las");
+    let second_path = dir.path().join("second.las");
+    let output_file_path = dir.path().join("merged.las");
+    create_test_las_file(first_path.to_str().unwrap());
+    create_test_las_file(second_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first_path)
+        .arg("--input")
+        .arg(&second_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--tag-source")
+        .arg("extra-byte");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().point_format().extra_bytes, 4);
+    let lookup = reader
+        .header()
+        .vlrs()
+        .iter()
+        .find(|vlr| vlr.user_id == "las_trimmer")
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&lookup.data),
+        format!("{}\n{}", first_path.display(), second_path.display())
+    );
+    let mut sources: Vec<u32> = reader
+        .points()
+        .map(|point| u32::from_le_bytes(point.unwrap().extra_bytes[..].try_into().unwrap()))
+        .collect();
+    sources.sort();
+    assert_eq!(sources, [[0; 10], [1; 10]].concat());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first_path)
+        .arg("--input")
+        .arg(&second_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--tag-source")
+        .arg("point-source-id");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().point_format().extra_bytes, 0);
+    let ones = reader
+        .points()
+        .filter(|point| point.as_ref().unwrap().point_source_id == 1)
+        .count();
+    assert_eq!(ones, 10);
+}
+
+#[test]
+fn test_cli_assign_source_id() {
+    let dir = tempdir().unwrap();
+    let first_path = dir.path().join("strip_01.las");
+    let second_path = dir.path().join("strip_02.las");
+    let output_file_path = dir.path().join("merged.las");
+    create_test_las_file(first_path.to_str().unwrap());
+    create_test_las_file(second_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first_path)
+        .arg("--input")
+        .arg(&second_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--assign-source-id")
+        .arg("strip_01.las=17")
+        .arg("--assign-source-id")
+        .arg("strip_02.las=18");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    let mut ids: Vec<u16> = reader
+        .points()
+        .map(|point| point.unwrap().point_source_id)
+        .collect();
+    ids.sort();
+    assert_eq!(ids, [[17; 10], [18; 10]].concat());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--assign-source-id")
+        .arg("strip_03.las=19");
+    cmd.assert().failure().stderr(predicates::str::contains(
+        "strip_03.las, which isn't one of the inputs",
+    ));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--assign-source-id")
+        .arg("strip_01.las=70000");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("invalid source ID assignment"));
+}
+
+#[test]
+fn test_cli_set_flag() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("classified.las");
+    let output_file_path = dir.path().join("flagged.las");
+    {
+        let header = las::Builder::from((1, 4)).into_header().unwrap();
+        let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+        for i in 0..10 {
+            let classification = if i % 2 == 0 { 2 } else { 1 };
+            writer
+                .write_point(las::Point {
+                    x: i as f64,
+                    classification: las::point::Classification::new(classification).unwrap(),
+                    is_key_point: true,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+    }
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--set-flag")
+        .arg("withheld=!ground-only")
+        .arg("--set-flag")
+        .arg("synthetic")
+        .arg("--clear-flag")
+        .arg("key-point=ground-only");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    let points: Vec<las::Point> = reader.points().map(|point| point.unwrap()).collect();
+    assert_eq!(points.len(), 10);
+    for point in points {
+        let ground = point.classification == las::point::Classification::Ground;
+        assert_eq!(point.is_withheld, !ground);
+        assert!(point.is_synthetic);
+        assert_eq!(point.is_key_point, !ground);
+    }
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--set-flag")
+        .arg("hidden");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("unknown flag `hidden`"));
+}
+
+#[test]
+fn test_cli_drop_waveforms() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("waveforms.las");
+    let output_file_path = dir.path().join("no_waveforms.las");
+    {
+        let mut builder = las::Builder::from((1, 4));
+        builder.point_format = las::point::Format::new(4).unwrap();
+        builder.vlrs.push(las::Vlr {
+            user_id: "LASF_Spec".to_string(),
+            record_id: 100,
+            description: "Waveform packet".to_string(),
+            data: vec![0; 26],
+        });
+        let header = builder.into_header().unwrap();
+        let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+        for i in 0..10 {
+            writer
+                .write_point(las::Point {
+                    x: i as f64,
+                    gps_time: Some(i as f64),
+                    waveform: Some(las::raw::point::Waveform {
+                        wave_packet_descriptor_index: 1,
+                        byte_offset_to_waveform_data: 60 * i,
+                        waveform_packet_size_in_bytes: 60,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+    }
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("--drop-waveforms"));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--drop-waveforms");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().point_format().to_u8().unwrap(), 1);
+    assert!(reader
+        .header()
+        .vlrs()
+        .iter()
+        .all(|vlr| vlr.record_id != 100));
+    let points: Vec<las::Point> = reader.points().map(|point| point.unwrap()).collect();
+    assert_eq!(points.len(), 10);
+    assert!(points.iter().all(|point| point.waveform.is_none()));
+}
+
+#[test]
+fn test_cli_min_neighbors() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("noisy.las");
+    let output_file_path = dir.path().join("clean.las");
+    {
+        let header = las::Builder::from((1, 4)).into_header().unwrap();
+        let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+        // A dense patch of 100 points 0.1 apart, and 5 isolated points far above it
+        for i in 0..100 {
+            writer
+                .write_point(las::Point {
+                    x: (i % 10) as f64 * 0.1,
+                    y: (i / 10) as f64 * 0.1,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        for i in 0..5 {
+            writer
+                .write_point(las::Point {
+                    x: i as f64 * 10.0,
+                    z: 50.0,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+    }
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--min-neighbors")
+        .arg("2")
+        .arg("--neighbor-radius")
+        .arg("0.5");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().number_of_points(), 100);
+    assert!(reader.points().all(|point| point.unwrap().z == 0.0));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--min-neighbors")
+        .arg("2");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("--neighbor-radius"));
+}
+
+#[test]
+fn test_cli_pipeline_output_options() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let pipeline_path = dir.path().join("job.json");
+    create_test_las_file(input_file_path.to_str().unwrap());
+    let pipeline = format!(
+        r#"{{
+            "inputs": [{:?}],
+            "outputs": [
+                {{"path": {:?}, "filter": "always-true"}},
+                {{"path": {:?}, "filter": "always-true", "point_format": 7, "drop-crs": true}}
+            ]
+        }}"#,
+        input_file_path.to_str().unwrap(),
+        dir.path().join("archive.las").to_str().unwrap(),
+        dir.path().join("web.laz").to_str().unwrap()
+    );
+    std::fs::write(&pipeline_path, pipeline).unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim").arg("--pipeline").arg(&pipeline_path);
+    cmd.assert().success();
+    let reader = las::Reader::from_path(dir.path().join("archive.las")).unwrap();
+    assert_eq!(reader.header().point_format().to_u8().unwrap(), 0);
+    let mut reader = las::Reader::from_path(dir.path().join("web.laz")).unwrap();
+    assert_eq!(reader.header().point_format().to_u8().unwrap(), 7);
+    assert_eq!(reader.header().number_of_points(), 10);
+    let point = reader.points().next().unwrap().unwrap();
+    assert_eq!(point.color, Some(las::Color::new(0, 0, 0)));
+}
+
+#[test]
+fn test_cli_minimize_format() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("minimal.las");
+    let mut builder = las::Builder::from((1, 2));
+    builder.point_format = las::point::Format::new(3).unwrap();
+    builder.point_format.extra_bytes = 2;
+    let header = builder.into_header().unwrap();
+    let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+    for i in 0..10 {
+        writer
+            .write_point(las::Point {
+                x: i as f64,
+                gps_time: Some(if i < 5 { 1.0 } else { 2.0 }),
+                color: Some(las::Color::new(0, 0, 0)),
+                extra_bytes: vec![0, 0],
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--minimize-format");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    let format = *reader.header().point_format();
+    assert_eq!(format.to_u8().unwrap(), 1);
+    assert_eq!(format.extra_bytes, 0);
+    let times: Vec<f64> = reader
+        .points()
+        .map(|point| point.unwrap().gps_time.unwrap())
+        .collect();
+    assert_eq!(times.len(), 10);
+    assert_eq!(times[9], 2.0);
+}
+
+#[test]
+fn test_cli_stretch_intensity() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("stretched.las");
+    let header = las::Builder::from((1, 4)).into_header().unwrap();
+    let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+    for i in 0..10 {
+        writer
+            .write_point(las::Point {
+                x: i as f64,
+                intensity: 100 + i * 10,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--stretch-intensity=0,100");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    let intensities: Vec<u16> = reader
+        .points()
+        .map(|point| point.unwrap().intensity)
+        .collect();
+    assert_eq!(intensities[0], 0);
+    assert_eq!(intensities[3], 21845);
+    assert_eq!(intensities[9], u16::MAX);
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--stretch-intensity=98,2");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("invalid range"));
+}
+
+#[test]
+fn test_cli_color_adjustments() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("adjusted.las");
+    let mut builder = las::Builder::from((1, 2));
+    builder.point_format = las::point::Format::new(2).unwrap();
+    let header = builder.into_header().unwrap();
+    let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+    writer
+        .write_point(las::Point {
+            color: Some(las::Color::new(16384, 32768, 65535)),
+            ..Default::default()
+        })
+        .unwrap();
+    writer.close().unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--color-gain")
+        .arg("2,1,0.5")
+        .arg("--color-gamma")
+        .arg("0.5")
+        .arg("--color-brightness")
+        .arg("-0.1");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    let color = reader.points().next().unwrap().unwrap().color.unwrap();
+    // Evened out to a half by the gains, a quarter after the gamma, then darkened by a tenth
+    assert_eq!(color, las::Color::new(9831, 9831, 9831));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--color-gain")
+        .arg("1,1");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("invalid gains"));
+}
+
+#[test]
+fn test_cli_color_from_elevation() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("colored.las");
+    let mut builder = las::Builder::from((1, 2));
+    builder.point_format = las::point::Format::new(1).unwrap();
+    let header = builder.into_header().unwrap();
+    let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+    for z in [0.0, 5.0, 10.0] {
+        writer
+            .write_point(las::Point {
+                z,
+                gps_time: Some(0.0),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--color-from")
+        .arg("elevation")
+        .arg("--colormap")
+        .arg("gray");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().point_format().to_u8().unwrap(), 3);
+    let colors: Vec<_> = reader
+        .points()
+        .map(|point| point.unwrap().color.unwrap().red)
+        .collect();
+    assert_eq!(colors, vec![0, 32768, 65535]);
+}
+
+#[test]
+fn test_cli_color_by_class() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("classes.las");
+    let palette_path = dir.path().join("palette.json");
+    fs::write(&palette_path, r##"{"6": "#ff8000"}"##).unwrap();
+    let mut writer = las::Writer::from_path(
+        &input_file_path,
+        las::Builder::from((1, 2)).into_header().unwrap(),
+    )
+    .unwrap();
+    for classification in [
+        las::point::Classification::Ground,
+        las::point::Classification::Building,
+    ] {
+        writer
+            .write_point(las::Point {
+                classification,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--color-by-class")
+        .arg(&palette_path);
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().point_format().to_u8().unwrap(), 2);
+    let colors: Vec<_> = reader
+        .points()
+        .map(|point| point.unwrap().color.unwrap())
+        .collect();
+    // Ground keeps its standard brown, buildings take the color of the palette
+    assert_eq!(
+        colors,
+        vec![
+            las::Color::new(43690, 21845, 0),
+            las::Color::new(65535, 32896, 0)
+        ]
+    );
+
+    fs::write(&palette_path, r#"{"6": "orange"}"#).unwrap();
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--force")
+        .arg("--color-by-class")
+        .arg(&palette_path);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid class palette"));
+}
+
+#[test]
+fn test_cli_crop_to_overlap() {
+    let dir = tempdir().unwrap();
+    let first_path = dir.path().join("first.las");
+    let second_path = dir.path().join("second.las");
+    let output_file_path = dir.path().join("overlap.las");
+    // Two strips along X, the second starting halfway along the first
+    for (path, start) in [(&first_path, 0), (&second_path, 5)] {
+        let mut writer =
+            las::Writer::from_path(path, las::Builder::from((1, 2)).into_header().unwrap())
+                .unwrap();
+        for x in start..start + 10 {
+            writer
+                .write_point(las::Point {
+                    x: f64::from(x),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        writer.close().unwrap();
+    }
+
+    for cell_size in [None, Some("2")] {
+        let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+        cmd.arg("merge")
+            .arg("--input")
+            .arg(&first_path)
+            .arg("--input")
+            .arg(&second_path)
+            .arg("--output")
+            .arg(&output_file_path)
+            .arg("--force")
+            .arg("--crop-to-overlap");
+        if let Some(cell_size) = cell_size {
+            cmd.arg(cell_size);
+        }
+        cmd.assert().success();
+        let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+        let mut xs: Vec<f64> = reader.points().map(|point| point.unwrap().x).collect();
+        xs.sort_by(f64::total_cmp);
+        // The bounds overlap from 5 to 9, the cells of size 2 from 4 to 10
+        let expected: Vec<f64> = match cell_size {
+            None => vec![5.0, 5.0, 6.0, 6.0, 7.0, 7.0, 8.0, 8.0, 9.0, 9.0],
+            Some(_) => vec![4.0, 5.0, 5.0, 6.0, 6.0, 7.0, 7.0, 8.0, 8.0, 9.0, 9.0],
+        };
+        assert_eq!(xs, expected);
+    }
+}
+
+#[test]
+fn test_cli_clip_polygons() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let polygon_path = dir.path().join("area.geojson");
+    create_test_las_file(input_file_path.to_str().unwrap());
+    // A square around the points from 2 to 5
+    fs::write(
+        &polygon_path,
+        r#"{"type": "Feature", "properties": {}, "geometry": {"type": "Polygon",
+            "coordinates": [[[1.5, 1.5], [5.5, 1.5], [5.5, 5.5], [1.5, 5.5], [1.5, 1.5]]]}}"#,
+    )
+    .unwrap();
+
+    for (flag, expected) in [
+        ("--clip", vec![2.0, 3.0, 4.0, 5.0]),
+        ("--clip-outside", vec![0.0, 1.0, 6.0, 7.0, 8.0, 9.0]),
+    ] {
+        let output_file_path = dir
+            .path()
+            .join(format!("{}.las", flag.trim_start_matches('-')));
+        let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+        cmd.arg("trim")
+            .arg("--input")
+            .arg(&input_file_path)
+            .arg("--output")
+            .arg(&output_file_path)
+            .arg(flag)
+            .arg(&polygon_path);
+        cmd.assert().success();
+        let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+        let xs: Vec<f64> = reader.points().map(|point| point.unwrap().x).collect();
+        assert_eq!(xs, expected);
+    }
+
+    fs::write(&polygon_path, r#"{"type": "Point", "coordinates": [0, 0]}"#).unwrap();
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("none.las"))
+        .arg("--clip")
+        .arg(&polygon_path);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid clip polygons"));
+}
+
+#[test]
+fn test_cli_clip_features() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let polygon_path = dir.path().join("parcels.geojson");
+    create_test_las_file(input_file_path.to_str().unwrap());
+    let square = |min: f64, max: f64| {
+        format!(
+            r#"{{"type": "Polygon", "coordinates": [[[{min}, {min}], [{max}, {min}], [{max}, {max}], [{min}, {max}]]]}}"#
+        )
+    };
+    fs::write(
+        &polygon_path,
+        format!(
+            r#"{{"type": "FeatureCollection", "features": [
+                {{"type": "Feature", "properties": {{"parcel": "north"}}, "geometry": {}}},
+                {{"type": "Feature", "properties": {{"parcel": "south"}}, "geometry": {}}}
+            ]}}"#,
+            square(0.5, 2.5),
+            square(6.5, 9.5)
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("clip")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("{name}.las"))
+        .arg("--polygons")
+        .arg(&polygon_path)
+        .arg("--name-field")
+        .arg("parcel");
+    cmd.assert().success();
+    for (name, expected) in [("north", vec![1.0, 2.0]), ("south", vec![7.0, 8.0, 9.0])] {
+        let mut reader = las::Reader::from_path(dir.path().join(format!("{}.las", name))).unwrap();
+        let xs: Vec<f64> = reader.points().map(|point| point.unwrap().x).collect();
+        assert_eq!(xs, expected);
+    }
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("clip")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("parcel.las"))
+        .arg("--polygons")
+        .arg(&polygon_path);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("{name} placeholder"));
+
+    // Names that end up as the same file
+    fs::write(
+        &polygon_path,
+        format!(
+            r#"{{"type": "FeatureCollection", "features": [
+                {{"type": "Feature", "properties": {{"parcel": "a/b"}}, "geometry": {}}},
+                {{"type": "Feature", "properties": {{"parcel": "a_b"}}, "geometry": {}}}
+            ]}}"#,
+            square(0.5, 2.5),
+            square(6.5, 9.5)
+        ),
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("clip")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("{name}.las"))
+        .arg("--polygons")
+        .arg(&polygon_path)
+        .arg("--name-field")
+        .arg("parcel");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("more than one output"));
+    assert!(!dir.path().join("a_b.las").exists());
+}
+
+#[test]
+fn test_cli_flight_lines() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let mut builder = las::Builder::from((1, 2));
+    builder.point_format = las::point::Format::new(1).unwrap();
+    let header = builder.into_header().unwrap();
+    let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+    // Two lines a minute apart, with no source IDs
+    for i in 0..10 {
+        writer
+            .write_point(las::Point {
+                x: i as f64,
+                gps_time: Some(if i < 4 { i as f64 } else { 60.0 + i as f64 }),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("lines")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("line_{line}.las"))
+        .arg("--gap")
+        .arg("10");
+    cmd.assert().success();
+    for (line, expected) in [(1, 4), (2, 6)] {
+        let reader = las::Reader::from_path(dir.path().join(format!("line_{}.las", line))).unwrap();
+        assert_eq!(reader.header().number_of_points(), expected);
+    }
+
+    let output_file_path = dir.path().join("ids.las");
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--flight-line-ids")
+        .arg("10");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    let ids: Vec<u16> = reader
+        .points()
+        .map(|point| point.unwrap().point_source_id)
+        .collect();
+    assert_eq!(ids, [1, 1, 1, 1, 2, 2, 2, 2, 2, 2]);
+}
+
+#[test]
+fn test_cli_adjust() {
+    let dir = tempdir().unwrap();
+    let first_path = dir.path().join("strip_01.las");
+    let second_path = dir.path().join("strip_02.las");
+    let adjustments_path = dir.path().join("adjustments.csv");
+    let output_file_path = dir.path().join("merged.las");
+    create_test_las_file(first_path.to_str().unwrap());
+    create_test_las_file(second_path.to_str().unwrap());
+    // A quarter turn of the first strip about its middle, and a lift of the second
+    fs::write(
+        &adjustments_path,
+        "file,dz,rz\nstrip_01.las,0,90\nstrip_02.las,100,0\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first_path)
+        .arg("--input")
+        .arg(&second_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--adjust")
+        .arg(&adjustments_path);
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    let points: Vec<(f64, f64, f64)> = reader
+        .points()
+        .map(|point| {
+            let point = point.unwrap();
+            (point.x, point.y, point.z)
+        })
+        .collect();
+    assert_eq!(points.len(), 20);
+    assert!(points.contains(&(9.0, 0.0, 0.0)));
+    assert!(points.contains(&(0.0, 9.0, 9.0)));
+    assert!(points.contains(&(3.0, 3.0, 103.0)));
+    assert_eq!(reader.header().bounds().max.z, 109.0);
+
+    fs::write(&adjustments_path, "file,dz\nstrip_03.las,1\n").unwrap();
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--adjust")
+        .arg(&adjustments_path);
+    cmd.assert().failure().stderr(predicates::str::contains(
+        "strip_03.las, which isn't one of the inputs",
+    ));
+}
+
+#[test]
+fn test_cli_anonymize() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("survey.las");
+    let output_file_path = dir.path().join("public.las");
+    let mut builder = las::Builder::from((1, 2));
+    builder.point_format = las::point::Format::new(1).unwrap();
+    builder.system_identifier = "Survey rig 7".to_string();
+    builder.transforms.x.scale = 0.01;
+    builder.transforms.y.scale = 0.01;
+    let header = builder.into_header().unwrap();
+    let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+    for i in 0..10 {
+        writer
+            .write_point(las::Point {
+                x: i as f64 + 0.75,
+                y: 2.5,
+                gps_time: Some(1000.0 + i as f64),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--anonymize");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().point_format().to_u8().unwrap(), 0);
+    assert_eq!(reader.header().system_identifier(), "");
+    assert_eq!(reader.header().transforms().x.scale, 1.0);
+    let points: Vec<(f64, f64)> = reader
+        .points()
+        .map(|point| {
+            let point = point.unwrap();
+            assert_eq!(point.gps_time, None);
+            (point.x, point.y)
+        })
+        .collect();
+    assert_eq!(points[0], (0.0, 2.0));
+    assert_eq!(points[9], (9.0, 2.0));
+}
+
+#[test]
+fn test_cli_header_stamp() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("input.las");
+    let output_file_path = dir.path().join("output.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true");
+    cmd.assert().success();
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().generating_software(), "las-trimmer v0.1");
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--system-identifier")
+        .arg("Acme Surveys")
+        .arg("--generating-software")
+        .arg("")
+        .arg("--guid")
+        .arg("6f1c2a9e-3b4d-4e5f-8a7b-9c0d1e2f3a4b")
+        .arg("--creation-date")
+        .arg("2024-03-01");
+    cmd.assert().success();
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    let header = reader.header();
+    assert_eq!(header.system_identifier(), "Acme Surveys");
+    assert_eq!(header.generating_software(), "");
+    assert_eq!(
+        header.guid().to_string(),
+        "6f1c2a9e-3b4d-4e5f-8a7b-9c0d1e2f3a4b"
+    );
+    assert_eq!(header.date(), chrono::NaiveDate::from_ymd_opt(2024, 3, 1));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--creation-date")
+        .arg("March");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("expected YYYY-MM-DD or today"));
+}
+
+#[test]
+fn test_cli_merge_vlrs() {
+    let dir = tempdir().unwrap();
+    let output_file_path = dir.path().join("merged.las");
+    let write_tile = |name: &str, vlrs: Vec<(&str, u16, u8)>| {
+        let path = dir.path().join(name);
+        let mut builder = las::Builder::from((1, 4));
+        builder.vlrs = vlrs
+            .into_iter()
+            .map(|(user_id, record_id, data)| las::Vlr {
+                user_id: user_id.to_string(),
+                record_id,
+                description: String::new(),
+                data: vec![data; 8],
+            })
+            .collect();
+        let mut writer = las::Writer::from_path(&path, builder.into_header().unwrap()).unwrap();
+        writer.write_point(las::Point::default()).unwrap();
+        writer.close().unwrap();
+        path
+    };
+    let first = write_tile("tile_1.las", vec![("LASF_Projection", 2112, 1)]);
+    let second = write_tile(
+        "tile_2.las",
+        vec![("LASF_Projection", 2112, 1), ("Vendor", 1, 5)],
+    );
+    let third = write_tile("tile_3.las", vec![("LASF_Projection", 2112, 2)]);
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first)
+        .arg("--input")
+        .arg(&second)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--merge-vlrs");
+    cmd.assert().success();
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    let records: Vec<(String, u16)> = reader
+        .header()
+        .vlrs()
+        .iter()
+        .map(|vlr| (vlr.user_id.clone(), vlr.record_id))
+        .collect();
+    assert_eq!(
+        records,
+        [
+            ("LASF_Projection".to_string(), 2112),
+            ("Vendor".to_string(), 1)
+        ]
+    );
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first)
+        .arg("--input")
+        .arg(&third)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--merge-vlrs");
+    cmd.assert().failure().stderr(predicates::str::contains(
+        "has a LASF_Projection record 2112 that differs",
+    ));
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first)
+        .arg("--input")
+        .arg(&third)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--force")
+        .arg("--merge-vlrs")
+        .arg("last");
+    cmd.assert().success();
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().vlrs()[0].data, vec![2; 8]);
+}
+
+#[test]
+fn test_cli_info_crs() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("utm.las");
+    let mut builder = las::Builder::from((1, 4));
+    builder.point_format = las::point::Format::new(6).unwrap();
+    builder.has_wkt_crs = true;
+    builder.vlrs.push(las::Vlr {
+        user_id: "LASF_Projection".to_string(),
+        record_id: 2112,
+        description: String::new(),
+        data: b"PROJCS[\"WGS 84 / UTM zone 33N\",GEOGCS[\"WGS 84\",AUTHORITY[\"EPSG\",\"4326\"]],\
+            AUTHORITY[\"EPSG\",\"32633\"]]\0"
+            .to_vec(),
+    });
+    let mut writer =
+        las::Writer::from_path(&input_file_path, builder.into_header().unwrap()).unwrap();
+    writer.write_point(las::Point::default()).unwrap();
+    writer.close().unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("info").arg("--input").arg(&input_file_path);
+    cmd.assert().success().stdout(predicates::str::contains(
+        "CRS:                 WGS 84 / UTM zone 33N (EPSG:32633)",
+    ));
+
+    let input_file_path = dir.path().join("plain.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("info").arg("--input").arg(&input_file_path);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("CRS:                 none"));
+}
+
+#[test]
+fn test_cli_near() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    // The neighbours of (5, 5, 5) are 1.41 away horizontally and 1.73 away in 3D
+    for (near, expected) in [
+        ("5,5,5", vec![5.0]),
+        ("5,5", vec![4.0, 5.0, 6.0]),
+        ("0,0;9,0", vec![0.0, 1.0]),
+    ] {
+        let output_file_path = dir.path().join("near.las");
+        let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+        cmd.arg("trim")
+            .arg("--input")
+            .arg(&input_file_path)
+            .arg("--output")
+            .arg(&output_file_path)
+            .arg("--near")
+            .arg(near)
+            .arg("--within")
+            .arg("1.6");
+        cmd.assert().success();
+        let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+        let xs: Vec<f64> = reader.points().map(|point| point.unwrap().x).collect();
+        assert_eq!(xs, expected, "{}", near);
+    }
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("none.las"))
+        .arg("--near")
+        .arg("0,0,0;9,0")
+        .arg("--within")
+        .arg("1");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("all with a Z or none"));
+}
+
+#[test]
+fn test_cli_filter_crs() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("utm.las");
+    let mut builder = las::Builder::from((1, 4));
+    builder.point_format = las::point::Format::new(6).unwrap();
+    builder.has_wkt_crs = true;
+    builder.vlrs.push(las::Vlr {
+        user_id: "LASF_Projection".to_string(),
+        record_id: 2112,
+        description: String::new(),
+        data: b"PROJCS[\"WGS 84 / UTM zone 33N\",GEOGCS[\"WGS 84\",AUTHORITY[\"EPSG\",\"4326\"]],\
+            AUTHORITY[\"EPSG\",\"32633\"]]\0"
+            .to_vec(),
+    });
+    let mut writer =
+        las::Writer::from_path(&input_file_path, builder.into_header().unwrap()).unwrap();
+    // A point every kilometre east of the origin of the zone, at 15E on the equator
+    for i in 0..10 {
+        writer
+            .write_point(las::Point {
+                x: 500_000.0 + 1000.0 * i as f64,
+                y: 10.0,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    // 15.05E is about 5.6 km east of the origin
+    let output_file_path = dir.path().join("box.las");
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--bbox")
+        .arg("14.99,-0.01,15.05,0.01")
+        .arg("--filter-crs")
+        .arg("EPSG:4326");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.points().count(), 6);
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("none.las"))
+        .arg("--bbox")
+        .arg("14.99,-0.01,15.05,0.01")
+        .arg("--filter-crs")
+        .arg("EPSG:3857");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("EPSG:3857"));
+}
+
+#[test]
+fn test_cli_stats_only() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--stats-only");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stats: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(stats[0]["output"], "all");
+    assert_eq!(stats[0]["points"], 10);
+    assert_eq!(
+        stats[0]["bounds"]["max"],
+        serde_json::json!([9.0, 9.0, 9.0])
+    );
+    assert_eq!(stats[0]["classes"], serde_json::json!({"0": 10}));
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    // The outputs only name the statistics, and aren't written
+    let stats_path = dir.path().join("stats.json");
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("kept.las"))
+        .arg("--output")
+        .arg(dir.path().join("dropped.las"))
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--filter")
+        .arg("always-false")
+        .arg("--stats")
+        .arg(&stats_path)
+        .arg("--stats-only");
+    cmd.assert().success();
+    let stats: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&stats_path).unwrap()).unwrap();
+    assert_eq!(stats[0]["points"], 10);
+    assert_eq!(stats[1]["points"], 0);
+    assert!(stats[1]["bounds"].is_null());
+    assert!(!dir.path().join("kept.las").exists());
+    assert!(!dir.path().join("dropped.las").exists());
+}
+
+#[test]
+fn test_cli_merge_by_gps_time() {
+    let dir = tempdir().unwrap();
+    let write_strip = |name: &str, times: &[f64]| {
+        let path = dir.path().join(name);
+        let mut builder = las::Builder::from((1, 2));
+        builder.point_format = las::point::Format::new(1).unwrap();
+        let header = builder.into_header().unwrap();
+        let mut writer = las::Writer::from_path(&path, header).unwrap();
+        for &time in times {
+            writer
+                .write_point(las::Point {
+                    x: time,
+                    gps_time: Some(time),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        writer.close().unwrap();
+        path
+    };
+    let first = write_strip("first.las", &[1.0, 3.0, 5.0, 7.0]);
+    let second = write_strip("second.las", &[2.0, 4.0, 6.0]);
+
+    let output_file_path = dir.path().join("merged.laz");
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first)
+        .arg("--input")
+        .arg(&second)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--by-gps-time");
+    cmd.assert().success();
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    let times: Vec<f64> = reader
+        .points()
+        .map(|point| point.unwrap().gps_time.unwrap())
+        .collect();
+    assert_eq!(times, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+
+    let unsorted = write_strip("unsorted.las", &[2.0, 1.0]);
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first)
+        .arg("--input")
+        .arg(&unsorted)
+        .arg("--output")
+        .arg(dir.path().join("unsorted_merge.las"))
+        .arg("--by-gps-time");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("isn't sorted by GPS time"));
+
+    // Processing options a merge by GPS time would ignore are refused
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&first)
+        .arg("--output")
+        .arg(dir.path().join("threads_merge.las"))
+        .arg("--by-gps-time")
+        .arg("--threads")
+        .arg("2");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_cli_append() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("merged.laz");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    for _ in 0..2 {
+        let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+        cmd.arg("merge")
+            .arg("--input")
+            .arg(&input_file_path)
+            .arg("--output")
+            .arg(&output_file_path)
+            .arg("--append")
+            .arg("--verify");
+        cmd.assert().success();
+    }
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().number_of_points(), 20);
+    assert_eq!(reader.header().bounds().max.x, 9.0);
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+    // An existing file of another point format is left alone
+    let other_file_path = dir.path().join("other.las");
+    let mut builder = las::Builder::from((1, 2));
+    builder.point_format = las::point::Format::new(1).unwrap();
+    let header = builder.into_header().unwrap();
+    las::Writer::from_path(&other_file_path, header)
+        .unwrap()
+        .close()
+        .unwrap();
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("merge")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&other_file_path)
+        .arg("--append");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("Can't append to"));
+    let reader = las::Reader::from_path(&other_file_path).unwrap();
+    assert_eq!(reader.header().number_of_points(), 0);
+}
+
+#[test]
+fn test_cli_legacy_compatible() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("legacy.las");
+    let mut builder = las::Builder::from((1, 4));
+    builder.point_format = las::point::Format::new(6).unwrap();
+    let header = builder.into_header().unwrap();
+    let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+    for i in 0..4 {
+        writer
+            .write_point(las::Point {
+                x: i as f64,
+                return_number: 1 + i as u8 * 3,
+                number_of_returns: 10,
+                classification: las::point::Classification::new(40).unwrap(),
+                gps_time: Some(i as f64),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--legacy-compatible");
+    cmd.assert().success();
+
+    let mut file = fs::File::open(&output_file_path).unwrap();
+    let raw = las::raw::Header::read_from(&mut file).unwrap();
+    assert_eq!(raw.version, las::Version::new(1, 4));
+    assert_eq!(raw.point_data_record_format, 1);
+    assert_eq!(raw.number_of_point_records, 4);
+    assert_eq!(raw.number_of_points_by_return, [1, 0, 0, 1, 0]);
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    for point in reader.points() {
+        let point = point.unwrap();
+        assert_eq!(u8::from(point.classification), 1);
+        assert!(point.return_number <= 7 && point.number_of_returns == 7);
+    }
+}
+
+#[test]
+fn test_cli_low_memory_spill_dir() {
+    let dir = tempdir().unwrap();
+    let spill_dir = dir.path().join("spill");
+    fs::create_dir(&spill_dir).unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("kept.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--min-neighbors")
+        .arg("1")
+        .arg("--neighbor-radius")
+        .arg("2")
+        .arg("--low-memory")
+        .arg("--spill-dir")
+        .arg(&spill_dir);
+    cmd.assert().success();
+
+    let reader = las::Reader::from_path(&output_file_path).unwrap();
+    assert_eq!(reader.header().number_of_points(), 10);
+    assert_eq!(fs::read_dir(&spill_dir).unwrap().count(), 0);
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(dir.path().join("tuned.las"))
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--low-memory")
+        .arg("--auto-tune");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_cli_sort_by() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    let output_file_path = dir.path().join("sorted.laz");
+    let header = las::Builder::from((1, 4)).into_header().unwrap();
+    let mut writer = las::Writer::from_path(&input_file_path, header).unwrap();
+    for i in 0..10 {
+        writer
+            .write_point(las::Point {
+                x: i as f64,
+                z: ((i * 7) % 10) as f64,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("trim")
+        .arg("--input")
+        .arg(&input_file_path)
+        .arg("--output")
+        .arg(&output_file_path)
+        .arg("--filter")
+        .arg("always-true")
+        .arg("--sort-by")
+        .arg("z")
+        .arg("--compress-spill");
+    cmd.assert().success();
+
+    let mut reader = las::Reader::from_path(&output_file_path).unwrap();
+    let heights: Vec<f64> = reader.points().map(|point| point.unwrap().z).collect();
+    assert_eq!(heights, (0..10).map(f64::from).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_cli_queue() {
+    let dir = tempdir().unwrap();
+    let input_file_path = dir.path().join("test.las");
+    create_test_las_file(input_file_path.to_str().unwrap());
+    let job = |output: &str| {
+        format!(
+            "trim --input \"{}\" --output \"{}\" --filter always-true",
+            input_file_path.display(),
+            dir.path().join(output).display()
+        )
+    };
+
+    let queue_dir = dir.path().join("queue");
+    fs::create_dir(&queue_dir).unwrap();
+    fs::write(queue_dir.join("a.job"), job("a.las")).unwrap();
+    fs::write(queue_dir.join("b.job"), "trim --input missing.las").unwrap();
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("queue").arg(&queue_dir);
+    cmd.assert()
+        .failure()
+        .stdout(predicates::str::contains("a.job: done in"))
+        .stdout(predicates::str::contains("b.job: failed after"))
+        .stderr(predicates::str::contains("1 of 2 job(s) failed: b.job"));
+    assert!(dir.path().join("a.las").exists());
+
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("queue")
+        .arg("-")
+        .arg("--jobs")
+        .arg("2")
+        .write_stdin(format!("{}\n# a comment\n{}\n", job("c.las"), job("d.laz")));
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("line 1: done in"))
+        .stdout(predicates::str::contains("line 3: done in"));
+    for output in ["c.las", "d.laz"] {
+        let reader = las::Reader::from_path(dir.path().join(output)).unwrap();
+        assert_eq!(reader.header().number_of_points(), 10);
+    }
+
+    // Logging is set up once for the whole queue
+    let mut cmd = Command::cargo_bin("las_trimmer").unwrap();
+    cmd.arg("queue")
+        .arg("-")
+        .write_stdin(format!("{} --log-level debug\n", job("e.las")));
+    cmd.assert().failure().stdout(predicates::str::contains(
+        "logging options apply to the whole queue",
+    ));
+    assert!(!dir.path().join("e.las").exists());
+}
+
 fn create_test_las_file(file_path: &str) {
     let builder = las::Builder::from((1, 4)); // LAS version 1.4
     let header = builder.into_header().unwrap();